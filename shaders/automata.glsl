@@ -0,0 +1,55 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// Bit n set when n neighbors cause a birth / let a live cell survive
+layout(push_constant) uniform Params {
+	uint birth;
+	uint survival;
+	uint seed;
+} params;
+
+// Alive in red, age in green
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D src;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D dst;
+
+uint hash(uint x) {
+	x ^= x >> 16;
+	x *= 0x7feb352du;
+	x ^= x >> 15;
+	x *= 0x846ca68bu;
+	x ^= x >> 16;
+	return x;
+}
+
+void main() {
+	ivec2 size = imageSize(dst);
+	ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+	if (any(greaterThanEqual(coord, size))) {
+		return;
+	}
+
+	if (params.seed != 0) {
+		uint h = hash(uint(coord.y * size.x + coord.x) ^ hash(params.seed));
+		imageStore(dst, coord, vec4(h % 3 == 0 ? 1.0 : 0.0, 0.0, 0.0, 1.0));
+		return;
+	}
+
+	// The grid wraps around at the edges
+	uint neighbors = 0;
+	for (int y = -1; y <= 1; y++) {
+		for (int x = -1; x <= 1; x++) {
+			if (x != 0 || y != 0) {
+				ivec2 n = (coord + ivec2(x, y) + size) % size;
+				neighbors += imageLoad(src, n).r > 0.5 ? 1 : 0;
+			}
+		}
+	}
+	vec4 cell = imageLoad(src, coord);
+	bool alive = cell.r > 0.5;
+	uint mask = alive ? params.survival : params.birth;
+	bool next = (mask & (1u << neighbors)) != 0;
+	float age = next && alive ? min(cell.g + 1.0 / 32.0, 1.0) : 0.0;
+	imageStore(dst, coord, vec4(next ? 1.0 : 0.0, age, 0.0, 1.0));
+}
@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D cells;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec4 cell = texture(cells, fragTexCoord);
+	// Newborn cells are bright, long-lived ones cool off
+	vec3 color = mix(vec3(1.0, 0.95, 0.6), vec3(0.2, 0.4, 1.0), cell.g);
+	outColor = vec4(color * cell.r, 1.0);
+}
@@ -0,0 +1,50 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D fullDepth;
+layout(set = 0, binding = 1) uniform sampler2D reducedDepth;
+layout(set = 0, binding = 2) uniform sampler2D effect;
+
+layout(push_constant) uniform Upsample {
+	// Size of one reduced texel in texture coordinates
+	vec2 reducedTexel;
+	// Depth weight falloff, relative to this pixel's depth
+	float depthSigma;
+} params;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Bilinear weights for the four nearest reduced texels, scaled down by how far each one's depth
+// is from this pixel's. Texels across a silhouette get almost no weight, so the effect stays on
+// its own side of the edge.
+void main() {
+	float depth = texture(fullDepth, fragTexCoord).r;
+	ivec2 size = textureSize(effect, 0);
+	vec2 position = fragTexCoord / params.reducedTexel - 0.5;
+	ivec2 base = ivec2(floor(position));
+	vec2 f = fract(position);
+	float falloff = max(params.depthSigma * depth, 1e-6);
+
+	vec4 sum = vec4(0.0);
+	float total = 0.0;
+	vec4 closest = vec4(0.0);
+	float closestDistance = 1e30;
+	for (int i = 0; i < 4; i++) {
+		ivec2 offset = ivec2(i & 1, i >> 1);
+		ivec2 coord = clamp(base + offset, ivec2(0), size - 1);
+		vec4 value = texelFetch(effect, coord, 0);
+		float distance = abs(texelFetch(reducedDepth, coord, 0).r - depth);
+		float bilinear = (offset.x == 1 ? f.x : 1.0 - f.x) * (offset.y == 1 ? f.y : 1.0 - f.y);
+		float weight = bilinear * exp(-distance / falloff);
+		sum += value * weight;
+		total += weight;
+		if (distance < closestDistance) {
+			closestDistance = distance;
+			closest = value;
+		}
+	}
+	// None of the four are on this pixel's surface, so take the one that's closest to it
+	outColor = total > 1e-4 ? sum / total : closest;
+}
@@ -0,0 +1,50 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(push_constant) uniform Params {
+	// 0 is the mildest, 1 the strongest
+	float sharpness;
+} params;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D sharpened;
+
+vec3 fetch(ivec2 coord, ivec2 size) {
+	return texelFetch(source, clamp(coord, ivec2(0), size - 1), 0).rgb;
+}
+
+// AMD FidelityFX CAS without the scaling path. The 3x3 neighborhood's headroom from black and
+// white decides how much to sharpen, so low contrast areas get the most and edges that are
+// already strong get almost none, which keeps the usual halos away.
+void main() {
+	ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+	ivec2 size = textureSize(source, 0);
+	if (any(greaterThanEqual(coord, size))) {
+		return;
+	}
+	// a b c
+	// d e f
+	// g h i
+	vec3 a = fetch(coord + ivec2(-1, -1), size);
+	vec3 b = fetch(coord + ivec2(0, -1), size);
+	vec3 c = fetch(coord + ivec2(1, -1), size);
+	vec3 d = fetch(coord + ivec2(-1, 0), size);
+	vec3 e = fetch(coord, size);
+	vec3 f = fetch(coord + ivec2(1, 0), size);
+	vec3 g = fetch(coord + ivec2(-1, 1), size);
+	vec3 h = fetch(coord + ivec2(0, 1), size);
+	vec3 i = fetch(coord + ivec2(1, 1), size);
+
+	// Soft min and max: the cross plus the whole 3x3, so diagonals count half
+	vec3 mn = min(min(min(d, e), min(f, b)), h);
+	mn += min(mn, min(min(a, c), min(g, i)));
+	vec3 mx = max(max(max(d, e), max(f, b)), h);
+	mx += max(mx, max(max(a, c), max(g, i)));
+
+	vec3 amp = sqrt(clamp(min(mn, 2.0 - mx) / max(mx, vec3(1e-5)), 0.0, 1.0));
+	vec3 w = amp * (-1.0 / mix(8.0, 5.0, clamp(params.sharpness, 0.0, 1.0)));
+	vec3 color = ((b + d + f + h) * w + e) / (1.0 + 4.0 * w);
+	imageStore(sharpened, coord, vec4(clamp(color, 0.0, 1.0), 1.0));
+}
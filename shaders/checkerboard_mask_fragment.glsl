@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Checkerboard {
+	// Which color of the checkerboard is shaded this frame
+	uint parity;
+	uint reconstruction;
+} params;
+
+// Writes no color; the pipeline's stencil op marks every fragment that survives
+void main() {
+	uvec2 pixel = uvec2(gl_FragCoord.xy);
+	if (((pixel.x + pixel.y) & 1u) != params.parity) {
+		discard;
+	}
+}
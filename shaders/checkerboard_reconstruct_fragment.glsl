@@ -0,0 +1,45 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Must match Reconstruction in src/checkerboard.rs
+const uint SPATIAL = 0u;
+const uint TEMPORAL = 1u;
+
+layout(set = 0, binding = 0) uniform sampler2D current;
+layout(set = 0, binding = 1) uniform sampler2D previous;
+
+layout(push_constant) uniform Checkerboard {
+	// Which color of the checkerboard was shaded this frame
+	uint parity;
+	uint reconstruction;
+} params;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+vec4 fetch(ivec2 coord, ivec2 size) {
+	return texelFetch(current, clamp(coord, ivec2(0), size - 1), 0);
+}
+
+void main() {
+	ivec2 coord = ivec2(gl_FragCoord.xy);
+	if (((coord.x + coord.y) & 1) == int(params.parity)) {
+		outColor = texelFetch(current, coord, 0);
+		return;
+	}
+	// The four edge neighbors are all the other color, so they were shaded this frame
+	ivec2 size = textureSize(current, 0);
+	vec4 left = fetch(coord + ivec2(-1, 0), size);
+	vec4 right = fetch(coord + ivec2(1, 0), size);
+	vec4 up = fetch(coord + ivec2(0, -1), size);
+	vec4 down = fetch(coord + ivec2(0, 1), size);
+	vec4 average = (left + right + up + down) * 0.25;
+	if (params.reconstruction == TEMPORAL) {
+		vec4 lo = min(min(left, right), min(up, down));
+		vec4 hi = max(max(left, right), max(up, down));
+		outColor = clamp(texelFetch(previous, coord, 0), lo, hi);
+	} else {
+		outColor = average;
+	}
+}
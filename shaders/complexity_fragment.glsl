@@ -0,0 +1,18 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// After the mesh vertex shader's push constants
+layout(push_constant) uniform Complexity {
+	layout(offset = 128) float cost;
+} complexity;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec3 cheap = vec3(0.0, 0.8, 0.0);
+	vec3 medium = vec3(1.0, 0.8, 0.0);
+	vec3 expensive = vec3(1.0, 0.0, 0.0);
+	float t = clamp(complexity.cost, 0.0, 1.0);
+	vec3 color = t < 0.5 ? mix(cheap, medium, t * 2.0) : mix(medium, expensive, t * 2.0 - 1.0);
+	outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,60 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 4, local_size_y = 4, local_size_z = 4) in;
+
+layout(push_constant) uniform Params {
+	float time;
+	float frequency;
+} params;
+
+layout(set = 0, binding = 0, rgba16f) uniform writeonly image3D field;
+
+vec3 hash3(vec3 p) {
+	p = vec3(dot(p, vec3(127.1, 311.7, 74.7)),
+	         dot(p, vec3(269.5, 183.3, 246.1)),
+	         dot(p, vec3(113.5, 271.9, 124.6)));
+	return -1.0 + 2.0 * fract(sin(p) * 43758.5453123);
+}
+
+float gradientNoise(vec3 p) {
+	vec3 i = floor(p);
+	vec3 f = fract(p);
+	vec3 u = f * f * (3.0 - 2.0 * f);
+	return mix(mix(mix(dot(hash3(i + vec3(0, 0, 0)), f - vec3(0, 0, 0)),
+	                   dot(hash3(i + vec3(1, 0, 0)), f - vec3(1, 0, 0)), u.x),
+	               mix(dot(hash3(i + vec3(0, 1, 0)), f - vec3(0, 1, 0)),
+	                   dot(hash3(i + vec3(1, 1, 0)), f - vec3(1, 1, 0)), u.x), u.y),
+	           mix(mix(dot(hash3(i + vec3(0, 0, 1)), f - vec3(0, 0, 1)),
+	                   dot(hash3(i + vec3(1, 0, 1)), f - vec3(1, 0, 1)), u.x),
+	               mix(dot(hash3(i + vec3(0, 1, 1)), f - vec3(0, 1, 1)),
+	                   dot(hash3(i + vec3(1, 1, 1)), f - vec3(1, 1, 1)), u.x), u.y), u.z);
+}
+
+// Three decorrelated noise fields as a vector potential
+vec3 potential(vec3 p) {
+	return vec3(gradientNoise(p),
+	            gradientNoise(p + vec3(31.416, -47.853, 12.793)),
+	            gradientNoise(p + vec3(-233.145, -113.408, -185.31)));
+}
+
+vec3 curl(vec3 p) {
+	const float e = 1e-2;
+	vec3 dx = vec3(e, 0, 0);
+	vec3 dy = vec3(0, e, 0);
+	vec3 dz = vec3(0, 0, e);
+	vec3 px = potential(p + dx) - potential(p - dx);
+	vec3 py = potential(p + dy) - potential(p - dy);
+	vec3 pz = potential(p + dz) - potential(p - dz);
+	return vec3(py.z - pz.y, pz.x - px.z, px.y - py.x) / (2.0 * e);
+}
+
+void main() {
+	ivec3 size = imageSize(field);
+	ivec3 coord = ivec3(gl_GlobalInvocationID);
+	if (any(greaterThanEqual(coord, size))) {
+		return;
+	}
+	vec3 p = (vec3(coord) + 0.5) / vec3(size) * params.frequency + vec3(0.0, 0.0, params.time * 0.1);
+	imageStore(field, coord, vec4(curl(p), 0.0));
+}
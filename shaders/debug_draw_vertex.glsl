@@ -0,0 +1,20 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(push_constant) uniform DebugDraw {
+	mat4 viewProjection;
+} debugDraw;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+	gl_Position = debugDraw.viewProjection * vec4(inPosition, 1.0);
+	fragColor = inColor;
+}
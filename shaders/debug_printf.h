@@ -0,0 +1,30 @@
+// Instrumentation through debugPrintfEXT, see src/shader_printf.rs. Include with
+//
+//	#extension GL_GOOGLE_include_directive : require
+//	#include "debug_printf.h"
+//
+// The PRINTF macros compile to nothing unless the shaders are built with `make PRINTF=1`, so
+// they can stay in a shader while it's being debugged. GLSL has no variadic macros, hence one
+// per argument count. Output from every invocation is a lot of output; guard calls with e.g.
+// PRINTF_AT(gl_GlobalInvocationID.xy == uvec2(0), ...) or a pixel coordinate.
+#ifndef DEBUG_PRINTF_H
+#define DEBUG_PRINTF_H
+
+#ifdef DEBUG_PRINTF
+#extension GL_EXT_debug_printf : require
+#define PRINTF0(fmt) debugPrintfEXT(fmt)
+#define PRINTF1(fmt, a) debugPrintfEXT(fmt, a)
+#define PRINTF2(fmt, a, b) debugPrintfEXT(fmt, a, b)
+#define PRINTF3(fmt, a, b, c) debugPrintfEXT(fmt, a, b, c)
+#define PRINTF4(fmt, a, b, c, d) debugPrintfEXT(fmt, a, b, c, d)
+#define PRINTF_AT(cond, fmt, a) if (all(cond)) { debugPrintfEXT(fmt, a); }
+#else
+#define PRINTF0(fmt)
+#define PRINTF1(fmt, a)
+#define PRINTF2(fmt, a, b)
+#define PRINTF3(fmt, a, b, c)
+#define PRINTF4(fmt, a, b, c, d)
+#define PRINTF_AT(cond, fmt, a)
+#endif
+
+#endif
@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Zoom {
+	dvec2 center;
+	// Of the complex plane, per pixel
+	double scale;
+	uint maxIterations;
+	// Escaping at this iteration or later counts as late
+	uint lateIteration;
+	vec2 halfExtent;
+} zoom;
+
+layout(std430, set = 0, binding = 0) buffer Counter {
+	uint lateEscapes;
+};
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	dvec2 c = zoom.center + (dvec2(gl_FragCoord.xy) - dvec2(zoom.halfExtent)) * zoom.scale;
+	dvec2 z = dvec2(0.0);
+	uint i = 0;
+	for (; i < zoom.maxIterations; i++) {
+		z = dvec2(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+		if (dot(z, z) > 256.0) {
+			break;
+		}
+	}
+	if (i == zoom.maxIterations) {
+		outColor = vec4(0.0, 0.0, 0.0, 1.0);
+		return;
+	}
+	if (i >= zoom.lateIteration) {
+		atomicAdd(lateEscapes, 1);
+	}
+	// Smooth iteration count, so the bands don't step
+	float smoothed = float(i) + 1.0 - log2(log2(float(dot(z, z))) * 0.5);
+	float t = smoothed * 0.05;
+	outColor = vec4(0.5 + 0.5 * cos(6.2832 * (t + vec3(0.0, 0.15, 0.3))), 1.0);
+}
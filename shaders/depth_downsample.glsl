@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(push_constant) uniform Params {
+	// Full resolution pixels per reduced texel along each axis
+	uint divisor;
+} params;
+
+layout(set = 0, binding = 0) uniform sampler2D fullDepth;
+layout(set = 0, binding = 1, r32f) uniform writeonly image2D reducedDepth;
+
+// Keeps the nearest depth of the footprint on one checkerboard color and the farthest on the
+// other, so thin foreground and the background behind it both stay represented for the
+// bilateral upsample to pick from.
+void main() {
+	ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+	if (any(greaterThanEqual(coord, imageSize(reducedDepth)))) {
+		return;
+	}
+	ivec2 fullSize = textureSize(fullDepth, 0);
+	ivec2 base = coord * int(params.divisor);
+	float nearest = 1.0;
+	float farthest = 0.0;
+	for (int y = 0; y < int(params.divisor); y++) {
+		for (int x = 0; x < int(params.divisor); x++) {
+			float depth = texelFetch(fullDepth, min(base + ivec2(x, y), fullSize - 1), 0).r;
+			nearest = min(nearest, depth);
+			farthest = max(farthest, depth);
+		}
+	}
+	bool keepNearest = ((coord.x + coord.y) & 1) == 0;
+	imageStore(reducedDepth, coord, vec4(keepNearest ? nearest : farthest));
+}
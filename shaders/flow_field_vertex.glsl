@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform PerDraw {
+	mat4 viewProjection;
+	float extent;
+	float lineLength;
+	uint resolution;
+} perDraw;
+
+layout(set = 0, binding = 0) uniform sampler3D field;
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(location = 0) out vec3 fragColor;
+
+// One instance per voxel; vertex 0 is the voxel center and vertex 1 the head of the line
+void main() {
+	uint r = perDraw.resolution;
+	uint instance = uint(gl_InstanceIndex);
+	uvec3 cell = uvec3(instance % r, (instance / r) % r, instance / (r * r));
+	vec3 uvw = (vec3(cell) + 0.5) / float(r);
+	vec3 velocity = textureLod(field, uvw, 0.0).xyz;
+	float speed = length(velocity);
+	vec3 direction = speed > 0.0 ? velocity / speed : vec3(0.0);
+
+	vec3 position = (uvw * 2.0 - 1.0) * perDraw.extent;
+	position += direction * perDraw.lineLength * min(speed, 1.0) * float(gl_VertexIndex);
+	gl_Position = perDraw.viewProjection * vec4(position, 1.0);
+
+	vec3 color = mix(vec3(0.1, 0.3, 1.0), vec3(1.0, 0.4, 0.1), clamp(speed * 0.5, 0.0, 1.0));
+	// Fade the tail so the direction reads
+	fragColor = color * (gl_VertexIndex == 0 ? 0.2 : 1.0);
+}
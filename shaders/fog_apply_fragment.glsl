@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Params {
+	mat4 inverseViewProjection;
+	vec4 eye;
+	// x: near, y: far
+	vec4 range;
+} params;
+
+layout(set = 0, binding = 0) uniform sampler3D integrated;
+layout(set = 0, binding = 1) uniform sampler2D depth;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+// Blended as color * alpha + destination, with the pipeline's blend state
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	float ndcDepth = texture(depth, fragTexCoord).r;
+	vec4 world = params.inverseViewProjection * vec4(fragTexCoord * 2.0 - 1.0, ndcDepth, 1.0);
+	float distance = length(world.xyz / world.w - params.eye.xyz);
+
+	// Inverse of the exponential slice spacing
+	float slice = log(max(distance, params.range.x) / params.range.x) / log(params.range.y / params.range.x);
+	vec4 fog = texture(integrated, vec3(fragTexCoord, clamp(slice, 0.0, 1.0)));
+	outColor = fog;
+}
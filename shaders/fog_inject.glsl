@@ -0,0 +1,63 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(push_constant) uniform Params {
+	mat4 inverseViewProjection;
+	// xyz: camera position, w: frame number for the jitter
+	vec4 eye;
+	// xyz: direction towards the light, w: phase anisotropy
+	vec4 light;
+	// x: near, y: far, z: density, w: weight of the history
+	vec4 params;
+} params;
+
+layout(set = 0, binding = 0) uniform sampler3D history;
+// rgb: in-scattered light, a: extinction
+layout(set = 0, binding = 1, rgba16f) uniform writeonly image3D froxels;
+
+const float PI = 3.14159265;
+const vec3 LIGHT_COLOR = vec3(1.0, 0.95, 0.85);
+const vec3 AMBIENT_COLOR = vec3(0.15, 0.2, 0.3);
+
+// Slices are spaced exponentially, so near froxels are thin and far ones deep
+float sliceDistance(float slice, float sliceCount) {
+	return params.params.x * pow(params.params.y / params.params.x, slice / sliceCount);
+}
+
+float henyeyGreenstein(float cosTheta, float g) {
+	float g2 = g * g;
+	return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cosTheta, 1.5));
+}
+
+float hash(vec3 p) {
+	p = fract(p * 0.3183099 + 0.1);
+	p *= 17.0;
+	return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+void main() {
+	ivec3 size = imageSize(froxels);
+	ivec3 coord = ivec3(gl_GlobalInvocationID);
+	if (any(greaterThanEqual(coord, size))) {
+		return;
+	}
+
+	// A different depth within the froxel every frame, which the history averages out
+	float jitter = hash(vec3(coord) + params.eye.w);
+	vec2 uv = (vec2(coord.xy) + 0.5) / vec2(size.xy);
+	vec4 near = params.inverseViewProjection * vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+	vec4 far = params.inverseViewProjection * vec4(uv * 2.0 - 1.0, 1.0, 1.0);
+	vec3 dir = normalize(far.xyz / far.w - near.xyz / near.w);
+	vec3 pos = params.eye.xyz + dir * sliceDistance(float(coord.z) + jitter, float(size.z));
+
+	// Height fog, thinning out upwards
+	float density = params.params.z * exp(-max(pos.y, 0.0) * 0.5);
+	float phase = henyeyGreenstein(dot(dir, normalize(params.light.xyz)), params.light.w);
+	vec3 scattering = density * (LIGHT_COLOR * phase + AMBIENT_COLOR);
+	vec4 current = vec4(scattering, density);
+
+	vec4 previous = texture(history, (vec3(coord) + 0.5) / vec3(size));
+	imageStore(froxels, coord, mix(current, previous, params.params.w));
+}
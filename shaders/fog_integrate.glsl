@@ -0,0 +1,39 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(push_constant) uniform Params {
+	float near;
+	float far;
+} params;
+
+layout(set = 0, binding = 0, rgba16f) uniform readonly image3D froxels;
+// rgb: light scattered towards the camera up to the slice, a: transmittance
+layout(set = 0, binding = 1, rgba16f) uniform writeonly image3D integrated;
+
+float sliceDistance(float slice, float sliceCount) {
+	return params.near * pow(params.far / params.near, slice / sliceCount);
+}
+
+void main() {
+	ivec3 size = imageSize(froxels);
+	ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+	if (any(greaterThanEqual(coord, size.xy))) {
+		return;
+	}
+
+	// March front to back along the column of froxels
+	vec3 scattered = vec3(0.0);
+	float transmittance = 1.0;
+	for (int z = 0; z < size.z; z++) {
+		vec4 froxel = imageLoad(froxels, ivec3(coord, z));
+		float thickness = sliceDistance(float(z + 1), float(size.z)) - sliceDistance(float(z), float(size.z));
+		float extinction = max(froxel.a, 1e-6);
+		float sliceTransmittance = exp(-extinction * thickness);
+		// Scattering integrated analytically over the slice
+		scattered += transmittance * froxel.rgb * (1.0 - sliceTransmittance) / extinction;
+		transmittance *= sliceTransmittance;
+		imageStore(integrated, ivec3(coord, z), vec4(scattered, transmittance));
+	}
+}
@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Per-pixel difference of two RGBA8 images packed one texel per uint. The difference of each
+// color channel is multiplied by `amplify` so small ones show up, and alpha is left opaque.
+
+layout(local_size_x = 64) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+	float amplify;
+} params;
+
+layout(std430, set = 0, binding = 0) readonly buffer First {
+	uint texels[];
+} first;
+
+layout(std430, set = 0, binding = 1) readonly buffer Second {
+	uint texels[];
+} second;
+
+layout(std430, set = 0, binding = 2) writeonly buffer Difference {
+	uint texels[];
+} difference;
+
+layout(std430, set = 0, binding = 3) buffer Stats {
+	uint differing;
+	uint maxDifference;
+} stats;
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	if (idx >= params.count) {
+		return;
+	}
+	vec3 delta = abs(unpackUnorm4x8(first.texels[idx]).rgb - unpackUnorm4x8(second.texels[idx]).rgb);
+	uint largest = uint(round(max(delta.r, max(delta.g, delta.b)) * 255.0));
+	if (largest > 0) {
+		atomicAdd(stats.differing, 1);
+		atomicMax(stats.maxDifference, largest);
+	}
+	difference.texels[idx] = packUnorm4x8(vec4(min(delta * params.amplify, vec3(1.0)), 1.0));
+}
@@ -0,0 +1,39 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D sceneColor;
+
+layout(push_constant) uniform Gamma {
+	uint manualEncode;
+	float split;
+} gamma;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 linearToSrgb(vec3 linear) {
+	vec3 low = linear * 12.92;
+	vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+	return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+vec3 srgbToLinear(vec3 srgb) {
+	vec3 low = srgb / 12.92;
+	vec3 high = pow((srgb + 0.055) / 1.055, vec3(2.4));
+	return mix(high, low, lessThanEqual(srgb, vec3(0.04045)));
+}
+
+void main() {
+	vec3 color = texture(sceneColor, fragTexCoord).rgb;
+	bool uncorrected = fragTexCoord.x < gamma.split;
+	if (gamma.manualEncode != 0) {
+		if (!uncorrected) {
+			color = linearToSrgb(color);
+		}
+	} else if (uncorrected) {
+		// The hardware will encode on write, so pre-decode to show the linear values unencoded
+		color = srgbToLinear(color);
+	}
+	outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,42 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D target;
+
+layout(push_constant) uniform View {
+	uint mode;
+	float near;
+	float far;
+} view;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+const uint LINEAR_DEPTH = 1;
+const uint NORMALS = 2;
+const uint ALBEDO = 3;
+const uint ROUGHNESS_METALNESS = 4;
+const uint VELOCITY = 5;
+
+void main() {
+	vec4 texel = texture(target, fragTexCoord);
+	vec3 color;
+	if (view.mode == LINEAR_DEPTH) {
+		// Inverse of the [0, 1] perspective projection in math.rs
+		float linear = view.near * view.far / (view.far - texel.r * (view.far - view.near));
+		color = vec3((linear - view.near) / (view.far - view.near));
+	} else if (view.mode == NORMALS) {
+		color = normalize(texel.xyz) * 0.5 + 0.5;
+	} else if (view.mode == ALBEDO) {
+		color = texel.rgb;
+	} else if (view.mode == ROUGHNESS_METALNESS) {
+		color = vec3(texel.r, texel.g, 0.0);
+	} else if (view.mode == VELOCITY) {
+		// Scaled up since per-frame motion is usually a tiny fraction of the screen
+		color = vec3(abs(texel.xy) * 20.0, 0.0);
+	} else {
+		color = texel.rgb;
+	}
+	outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Specialized to LOCAL_SIZE in src/gradient.rs
+layout(local_size_x_id = 0, local_size_y_id = 1) in;
+
+layout(push_constant) uniform Params {
+	float time;
+} params;
+
+layout(set = 0, binding = 0, rgba8) uniform writeonly image2D dst;
+
+void main() {
+	ivec2 size = imageSize(dst);
+	ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+	if (pixel.x >= size.x || pixel.y >= size.y) {
+		return;
+	}
+	vec2 uv = vec2(pixel) / vec2(size);
+	// Horizontal and vertical ramps, with blue slowly cycling over time
+	vec3 color = vec3(uv.x, uv.y, 0.5 + 0.5 * sin(params.time));
+	imageStore(dst, pixel, vec4(color, 1.0));
+}
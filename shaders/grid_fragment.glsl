@@ -0,0 +1,44 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Grid {
+	mat4 viewProjection;
+	mat4 inverseViewProjection;
+} grid;
+
+layout(location = 0) in vec3 nearPoint;
+layout(location = 1) in vec3 farPoint;
+
+layout(location = 0) out vec4 outColor;
+
+const float FADE_DISTANCE = 100.0;
+
+vec4 gridLines(vec3 position, float scale) {
+	vec2 coord = position.xz / scale;
+	vec2 derivative = fwidth(coord);
+	vec2 lines = abs(fract(coord - 0.5) - 0.5) / derivative;
+	float line = min(lines.x, lines.y);
+	vec4 color = vec4(0.35, 0.35, 0.35, 1.0 - min(line, 1.0));
+	// Highlight the world X (red) and Z (blue) axes where they cross the plane
+	if (abs(position.z) < derivative.y * scale) {
+		color.rgb = vec3(1.0, 0.0, 0.0);
+	}
+	if (abs(position.x) < derivative.x * scale) {
+		color.rgb = vec3(0.0, 0.0, 1.0);
+	}
+	return color;
+}
+
+void main() {
+	float t = -nearPoint.y / (farPoint.y - nearPoint.y);
+	if (t <= 0.0) {
+		discard;
+	}
+	vec3 position = nearPoint + t * (farPoint - nearPoint);
+	vec4 clip = grid.viewProjection * vec4(position, 1.0);
+	gl_FragDepth = clip.z / clip.w;
+
+	float fade = max(0.0, 1.0 - length(position.xz) / FADE_DISTANCE);
+	vec4 color = gridLines(position, 1.0) + gridLines(position, 10.0) * 0.5;
+	outColor = vec4(color.rgb, min(color.a, 1.0) * fade);
+}
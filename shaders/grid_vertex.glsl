@@ -0,0 +1,32 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(push_constant) uniform Grid {
+	mat4 viewProjection;
+	mat4 inverseViewProjection;
+} grid;
+
+layout(location = 0) out vec3 nearPoint;
+layout(location = 1) out vec3 farPoint;
+
+vec2 positions[3] = vec2[](
+	vec2(-1.0, -1.0),
+	vec2(3.0, -1.0),
+	vec2(-1.0, 3.0)
+);
+
+vec3 unproject(vec2 ndc, float depth) {
+	vec4 p = grid.inverseViewProjection * vec4(ndc, depth, 1.0);
+	return p.xyz / p.w;
+}
+
+void main() {
+	vec2 ndc = positions[gl_VertexIndex];
+	nearPoint = unproject(ndc, 0.0);
+	farPoint = unproject(ndc, 1.0);
+	gl_Position = vec4(ndc, 0.0, 1.0);
+}
@@ -0,0 +1,31 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Camera {
+	mat4 inverseViewProjection;
+	// xyz: camera position, w: texture repeats per world unit
+	vec4 eye;
+} camera;
+
+layout(set = 0, binding = 0) uniform sampler2D checker;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec2 ndc = fragTexCoord * 2.0 - 1.0;
+	vec4 near = camera.inverseViewProjection * vec4(ndc, 0.0, 1.0);
+	vec4 far = camera.inverseViewProjection * vec4(ndc, 1.0, 1.0);
+	vec3 origin = camera.eye.xyz;
+	vec3 dir = normalize(far.xyz / far.w - near.xyz / near.w);
+
+	vec3 sky = mix(vec3(0.7, 0.8, 0.95), vec3(0.3, 0.45, 0.7), clamp(dir.y, 0.0, 1.0));
+	// Only rays heading down, from above the plane, hit it
+	float t = dir.y < 0.0 && origin.y > 0.0 ? -origin.y / dir.y : -1.0;
+	// The texture coordinates are computed for every pixel, sky included, so the derivatives
+	// the sampler picks mips and anisotropy from stay defined along the horizon
+	vec3 hit = origin + dir * max(t, 0.0);
+	vec3 ground = texture(checker, hit.xz * camera.eye.w).rgb;
+	outColor = vec4(t > 0.0 ? ground : sky, 1.0);
+}
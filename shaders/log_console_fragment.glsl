@@ -0,0 +1,56 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Must match src/bitmap_font.rs
+const uint FIRST_CHAR = 32u;
+const uint GLYPH_COUNT = 95u;
+const uint GLYPH_WORDS = 190u;
+
+// Indexed by the color in each cell, see `CellColor` in src/log_console.rs
+const vec3 COLORS[6] = vec3[](
+	vec3(0.6, 0.9, 1.0),  // header
+	vec3(1.0, 0.3, 0.3),  // error
+	vec3(1.0, 0.8, 0.2),  // warn
+	vec3(0.9, 0.9, 0.9),  // info
+	vec3(0.55, 0.65, 0.8),  // debug
+	vec3(0.5, 0.5, 0.5)  // trace
+);
+
+layout(push_constant) uniform Console {
+	// Top-left corner of the console, in pixels
+	vec2 origin;
+	// Size of one character cell, in pixels
+	vec2 cellSize;
+	uint columns;
+	uint rows;
+	float opacity;
+	// Where the grid starts in `cells`
+	uint firstCell;
+} console;
+
+// Each cell is a character in the low byte and a color index in the next
+layout(std430, set = 0, binding = 0) readonly buffer Text {
+	uint glyphs[GLYPH_WORDS];
+	uint cells[];
+} text;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec2 local = (gl_FragCoord.xy - console.origin) / console.cellSize;
+	if (any(lessThan(local, vec2(0.0))) || local.x >= float(console.columns) || local.y >= float(console.rows)) {
+		discard;
+	}
+	uvec2 cell = uvec2(local);
+	uvec2 pixel = min(uvec2(fract(local) * 8.0), uvec2(7u));
+	uint packed = text.cells[console.firstCell + cell.y * console.columns + cell.x];
+	uint code = packed & 0xffu;
+	uint color = min((packed >> 8u) & 0xffu, 5u);
+	bool lit = false;
+	if (code >= FIRST_CHAR && code < FIRST_CHAR + GLYPH_COUNT) {
+		uint word = text.glyphs[(code - FIRST_CHAR) * 2u + pixel.y / 4u];
+		uint row = (word >> ((pixel.y % 4u) * 8u)) & 0xffu;
+		lit = ((row >> pixel.x) & 1u) != 0u;
+	}
+	outColor = lit ? vec4(COLORS[color], 1.0) : vec4(0.0, 0.0, 0.0, console.opacity);
+}
@@ -0,0 +1,14 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 fragTexCoord;
+layout(location = 1) in vec3 fragNormal;
+
+layout(location = 0) out vec4 outColor;
+
+const vec3 LIGHT_DIRECTION = vec3(0.4, 0.8, 0.45);
+
+void main() {
+	float diffuse = max(dot(normalize(fragNormal), normalize(LIGHT_DIRECTION)), 0.0);
+	outColor = vec4(vec3(0.1 + 0.9 * diffuse), 1.0);
+}
@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Same layout as MeshVertex in src/mesh.rs. Plain floats, since a vec3 member would be padded
+// to 16 bytes.
+struct Vertex {
+	float position[3];
+	float normal[3];
+	float texCoord[2];
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer Vertices {
+	Vertex vertices[];
+};
+
+layout(push_constant) uniform PerDraw {
+	mat4 modelViewProjection;
+	mat4 model;
+} perDraw;
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(location = 0) out vec2 fragTexCoord;
+layout(location = 1) out vec3 fragNormal;
+
+void main() {
+	// With an index buffer bound, gl_VertexIndex is the index value
+	Vertex v = vertices[gl_VertexIndex];
+	vec3 position = vec3(v.position[0], v.position[1], v.position[2]);
+	vec3 normal = vec3(v.normal[0], v.normal[1], v.normal[2]);
+	gl_Position = perDraw.modelViewProjection * vec4(position, 1.0);
+	fragTexCoord = vec2(v.texCoord[0], v.texCoord[1]);
+	fragNormal = mat3(perDraw.model) * normal;
+}
@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// The dequantization matrix is already folded into modelViewProjection
+layout(push_constant) uniform PerDraw {
+	mat4 modelViewProjection;
+	mat4 model;
+} perDraw;
+
+// R16G16B16A16_SNORM and R16G16_SNORM, so these arrive already scaled to [-1, 1]
+layout(location = 0) in vec4 inPosition;
+layout(location = 1) in vec2 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(location = 0) out vec2 fragTexCoord;
+layout(location = 1) out vec3 fragNormal;
+
+vec3 octahedralDecode(vec2 e) {
+	vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+	if (n.z < 0.0) {
+		vec2 signs = vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+		n.xy = (1.0 - abs(n.yx)) * signs;
+	}
+	return normalize(n);
+}
+
+void main() {
+	gl_Position = perDraw.modelViewProjection * vec4(inPosition.xyz, 1.0);
+	fragTexCoord = inTexCoord;
+	fragNormal = mat3(perDraw.model) * octahedralDecode(inNormal);
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform PerDraw {
+	mat4 modelViewProjection;
+	mat4 model;
+} perDraw;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(location = 0) out vec2 fragTexCoord;
+layout(location = 1) out vec3 fragNormal;
+
+void main() {
+	gl_Position = perDraw.modelViewProjection * vec4(inPosition, 1.0);
+	fragTexCoord = inTexCoord;
+	fragNormal = mat3(perDraw.model) * inNormal;
+}
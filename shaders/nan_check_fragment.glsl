@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Params {
+	float maxValue;
+} params;
+
+layout(set = 0, binding = 0) uniform sampler2D hdr;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec4 color = texelFetch(hdr, ivec2(fragTexCoord * vec2(textureSize(hdr, 0))), 0);
+	if (any(isnan(color)) || any(isinf(color))) {
+		outColor = vec4(1.0, 0.0, 1.0, 1.0);
+	} else if (any(lessThan(color.rgb, vec3(0.0))) || any(greaterThan(color.rgb, vec3(params.maxValue)))) {
+		// Out of range, but at least a number
+		outColor = vec4(0.0, 0.6, 0.6, 0.6);
+	} else {
+		outColor = vec4(0.0);
+	}
+}
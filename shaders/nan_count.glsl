@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(push_constant) uniform Params {
+	float maxValue;
+} params;
+
+layout(set = 0, binding = 0) uniform sampler2D hdr;
+
+layout(std430, set = 0, binding = 1) buffer Counts {
+	uint nanCount;
+	uint infCount;
+	uint negativeCount;
+	uint overRangeCount;
+};
+
+void main() {
+	ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+	if (any(greaterThanEqual(coord, textureSize(hdr, 0)))) {
+		return;
+	}
+	vec4 color = texelFetch(hdr, coord, 0);
+	// Only the first problem counts, so every bad pixel is counted once
+	if (any(isnan(color))) {
+		atomicAdd(nanCount, 1u);
+	} else if (any(isinf(color))) {
+		atomicAdd(infCount, 1u);
+	} else if (any(lessThan(color.rgb, vec3(0.0)))) {
+		atomicAdd(negativeCount, 1u);
+	} else if (any(greaterThan(color.rgb, vec3(params.maxValue)))) {
+		atomicAdd(overRangeCount, 1u);
+	}
+}
@@ -0,0 +1,60 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 256) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+	float dt;
+	float softening;
+	float gravity;
+} params;
+
+// xyz position, w mass
+layout(std430, set = 0, binding = 0) readonly buffer PositionsIn {
+	vec4 positionsIn[];
+};
+
+layout(std430, set = 0, binding = 1) readonly buffer VelocitiesIn {
+	vec4 velocitiesIn[];
+};
+
+layout(std430, set = 0, binding = 2) writeonly buffer PositionsOut {
+	vec4 positionsOut[];
+};
+
+layout(std430, set = 0, binding = 3) writeonly buffer VelocitiesOut {
+	vec4 velocitiesOut[];
+};
+
+shared vec4 tile[256];
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	uint local = gl_LocalInvocationID.x;
+	bool active = idx < params.count;
+	vec4 body = active ? positionsIn[idx] : vec4(0.0);
+
+	vec3 acceleration = vec3(0.0);
+	for (uint base = 0; base < params.count; base += 256) {
+		// Bodies past the end get zero mass, so they don't pull on anything
+		uint other = base + local;
+		tile[local] = other < params.count ? positionsIn[other] : vec4(0.0);
+		barrier();
+		for (uint i = 0; i < 256; i++) {
+			vec3 d = tile[i].xyz - body.xyz;
+			float distSq = dot(d, d) + params.softening * params.softening;
+			float invDist = inversesqrt(distSq);
+			acceleration += d * (tile[i].w * invDist * invDist * invDist);
+		}
+		barrier();
+	}
+
+	if (!active) {
+		return;
+	}
+	// Semi-implicit Euler: velocity first, then position with the new velocity
+	vec3 velocity = velocitiesIn[idx].xyz + acceleration * params.gravity * params.dt;
+	velocitiesOut[idx] = vec4(velocity, 0.0);
+	positionsOut[idx] = vec4(body.xyz + velocity * params.dt, body.w);
+}
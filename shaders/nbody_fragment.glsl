@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// Round sprites with a soft falloff
+	vec2 offset = gl_PointCoord * 2.0 - 1.0;
+	float falloff = 1.0 - dot(offset, offset);
+	if (falloff <= 0.0) {
+		discard;
+	}
+	outColor = vec4(fragColor * falloff, 1.0);
+}
@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform PerDraw {
+	mat4 viewProjection;
+	float pointSize;
+} perDraw;
+
+layout(std430, set = 0, binding = 0) readonly buffer Positions {
+	vec4 positions[];
+};
+
+out gl_PerVertex {
+	vec4 gl_Position;
+	float gl_PointSize;
+};
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+	vec4 body = positions[gl_VertexIndex];
+	gl_Position = perDraw.viewProjection * vec4(body.xyz, 1.0);
+	gl_PointSize = perDraw.pointSize;
+	// Warmer toward the core
+	fragColor = mix(vec3(1.0, 0.8, 0.5), vec3(0.3, 0.5, 1.0), clamp(length(body.xz), 0.0, 1.0)) * 0.25;
+}
@@ -0,0 +1,114 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// Must match NoisePushConstants and the CPU generators in src/noise.rs
+layout(push_constant) uniform Params {
+	uint kind;
+	uint frequency;
+	uint octaves;
+	uint seed;
+	float gain;
+} params;
+
+layout(set = 0, binding = 0, r32f) uniform writeonly image2D noise;
+
+const uint KIND_VALUE = 0;
+const uint KIND_PERLIN = 1;
+const uint KIND_SIMPLEX = 2;
+
+const float PI = 3.14159265359;
+
+uint hash(uvec2 p, uint seed) {
+	uint h = seed ^ (p.x * 0x27d4eb2du) ^ (p.y * 0x165667b1u);
+	h ^= h >> 15;
+	h *= 0x2c1b3c6du;
+	h ^= h >> 12;
+	h *= 0x297a2d39u;
+	h ^= h >> 15;
+	return h;
+}
+
+uvec2 wrap(ivec2 lattice, int period) {
+	return uvec2(((lattice % period) + period) % period);
+}
+
+vec2 gradient(uint h) {
+	float angle = float(h & 7u) * (PI * 0.25);
+	return vec2(cos(angle), sin(angle));
+}
+
+vec2 fade(vec2 t) {
+	return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+// Value and Perlin noise repeat every `period` lattice cells, so the texture tiles; all three
+// return values centered on 0 in roughly [-0.5, 0.5]
+float valueNoise(vec2 p, int period, uint seed) {
+	ivec2 i = ivec2(floor(p));
+	vec2 u = fade(fract(p));
+	float a = float(hash(wrap(i, period), seed));
+	float b = float(hash(wrap(i + ivec2(1, 0), period), seed));
+	float c = float(hash(wrap(i + ivec2(0, 1), period), seed));
+	float d = float(hash(wrap(i + ivec2(1, 1), period), seed));
+	return mix(mix(a, b, u.x), mix(c, d, u.x), u.y) / 4294967295.0 - 0.5;
+}
+
+float perlinNoise(vec2 p, int period, uint seed) {
+	ivec2 i = ivec2(floor(p));
+	vec2 f = fract(p);
+	vec2 u = fade(f);
+	float a = dot(gradient(hash(wrap(i, period), seed)), f);
+	float b = dot(gradient(hash(wrap(i + ivec2(1, 0), period), seed)), f - vec2(1, 0));
+	float c = dot(gradient(hash(wrap(i + ivec2(0, 1), period), seed)), f - vec2(0, 1));
+	float d = dot(gradient(hash(wrap(i + ivec2(1, 1), period), seed)), f - vec2(1, 1));
+	return mix(mix(a, b, u.x), mix(c, d, u.x), u.y) * 0.70710678;
+}
+
+float simplexNoise(vec2 p, uint seed) {
+	const float F2 = 0.36602540378;
+	const float G2 = 0.2113248654;
+	ivec2 i = ivec2(floor(p + (p.x + p.y) * F2));
+	vec2 x0 = p - (vec2(i) - float(i.x + i.y) * G2);
+	ivec2 i1 = x0.x > x0.y ? ivec2(1, 0) : ivec2(0, 1);
+	vec2 x1 = x0 - vec2(i1) + G2;
+	vec2 x2 = x0 - 1.0 + 2.0 * G2;
+	float n = 0.0;
+	vec3 t = max(0.5 - vec3(dot(x0, x0), dot(x1, x1), dot(x2, x2)), 0.0);
+	t = t * t * t * t;
+	n += t.x * dot(gradient(hash(uvec2(i), seed)), x0);
+	n += t.y * dot(gradient(hash(uvec2(i + i1), seed)), x1);
+	n += t.z * dot(gradient(hash(uvec2(i + 1), seed)), x2);
+	return n * 35.0;
+}
+
+void main() {
+	ivec2 size = imageSize(noise);
+	ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+	if (any(greaterThanEqual(coord, size))) {
+		return;
+	}
+	vec2 uv = (vec2(coord) + 0.5) / vec2(size);
+	float sum = 0.0;
+	float amplitude = 1.0;
+	float total = 0.0;
+	int period = int(params.frequency);
+	for (uint octave = 0u; octave < max(params.octaves, 1u); octave++) {
+		uint seed = params.seed + octave * 0x9e3779b9u;
+		vec2 p = uv * float(period);
+		float n;
+		if (params.kind == KIND_VALUE) {
+			n = valueNoise(p, period, seed);
+		} else if (params.kind == KIND_PERLIN) {
+			n = perlinNoise(p, period, seed);
+		} else {
+			n = simplexNoise(p, seed);
+		}
+		sum += n * amplitude;
+		total += amplitude;
+		amplitude *= params.gain;
+		period *= 2;
+	}
+	imageStore(noise, coord, vec4(clamp(sum / total + 0.5, 0.0, 1.0)));
+}
@@ -0,0 +1,14 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D noise;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// Two tiles across, so any seam at the texture's edges shows
+	float value = texture(noise, fragTexCoord * 2.0).r;
+	outColor = vec4(vec3(value), 1.0);
+}
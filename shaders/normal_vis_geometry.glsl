@@ -0,0 +1,48 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(triangles) in;
+layout(line_strip, max_vertices = 8) out;
+
+layout(push_constant) uniform Params {
+	mat4 modelViewProjection;
+	float normalLength;
+} params;
+
+in gl_PerVertex {
+	vec4 gl_Position;
+} gl_in[];
+
+layout(location = 0) in vec3 geomNormal[];
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(location = 0) out vec3 fragColor;
+
+void emitLine(vec3 from, vec3 to, vec3 color) {
+	gl_Position = params.modelViewProjection * vec4(from, 1.0);
+	fragColor = color;
+	EmitVertex();
+	gl_Position = params.modelViewProjection * vec4(to, 1.0);
+	fragColor = color;
+	EmitVertex();
+	EndPrimitive();
+}
+
+void main() {
+	// Vertex normals in yellow
+	for (int i = 0; i < 3; i++) {
+		vec3 p = gl_in[i].gl_Position.xyz;
+		emitLine(p, p + normalize(geomNormal[i]) * params.normalLength, vec3(1.0, 1.0, 0.0));
+	}
+
+	// Face normal from the centroid in magenta, to spot winding and smoothing errors
+	vec3 a = gl_in[0].gl_Position.xyz;
+	vec3 b = gl_in[1].gl_Position.xyz;
+	vec3 c = gl_in[2].gl_Position.xyz;
+	vec3 centroid = (a + b + c) / 3.0;
+	vec3 faceNormal = normalize(cross(b - a, c - a));
+	emitLine(centroid, centroid + faceNormal * params.normalLength, vec3(1.0, 0.0, 1.0));
+}
@@ -0,0 +1,47 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Must match OutputFilter in src/output_filter.rs
+const uint RED = 1u;
+const uint GREEN = 2u;
+const uint BLUE = 3u;
+const uint ALPHA = 4u;
+const uint LUMINANCE = 5u;
+const uint PROTANOPIA = 6u;
+const uint DEUTERANOPIA = 7u;
+
+layout(set = 0, binding = 0) uniform sampler2D image;
+
+layout(push_constant) uniform OutputFilter {
+	uint mode;
+} params;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Machado, Oliveira and Fernandes 2009, at full severity. Rows apply to linear RGB.
+vec3 simulate(vec3 c, vec3 r, vec3 g, vec3 b) {
+	return clamp(vec3(dot(r, c), dot(g, c), dot(b, c)), 0.0, 1.0);
+}
+
+void main() {
+	vec4 color = texture(image, fragTexCoord);
+	vec3 rgb = color.rgb;
+	if (params.mode == RED) {
+		rgb = vec3(color.r);
+	} else if (params.mode == GREEN) {
+		rgb = vec3(color.g);
+	} else if (params.mode == BLUE) {
+		rgb = vec3(color.b);
+	} else if (params.mode == ALPHA) {
+		rgb = vec3(color.a);
+	} else if (params.mode == LUMINANCE) {
+		rgb = vec3(dot(color.rgb, vec3(0.2126, 0.7152, 0.0722)));
+	} else if (params.mode == PROTANOPIA) {
+		rgb = simulate(color.rgb, vec3(0.152286, 1.052583, -0.204868), vec3(0.114503, 0.786281, 0.099216), vec3(-0.003882, -0.048116, 1.051998));
+	} else if (params.mode == DEUTERANOPIA) {
+		rgb = simulate(color.rgb, vec3(0.367322, 0.860646, -0.227968), vec3(0.280085, 0.672501, 0.047413), vec3(-0.011820, 0.042940, 0.953070));
+	}
+	outColor = vec4(rgb, 1.0);
+}
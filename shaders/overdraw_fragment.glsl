@@ -0,0 +1,10 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// Blended additively, so a pixel goes from black through red and yellow to white as it is
+	// shaded more times
+	outColor = vec4(0.12, 0.05, 0.02, 1.0);
+}
@@ -0,0 +1,91 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Must match the flags in src/overlay.rs
+const uint RULERS = 1u;
+const uint CENTER = 2u;
+const uint THIRDS = 4u;
+const uint SAFE_AREAS = 8u;
+const uint VIEWPORT_BOUNDS = 16u;
+
+layout(push_constant) uniform Overlay {
+	uint flags;
+	float actionSafe;
+	float titleSafe;
+	float viewportX;
+	float viewportY;
+	float viewportWidth;
+	float viewportHeight;
+} overlay;
+
+layout(location = 0) out vec4 outColor;
+
+vec4 result = vec4(0.0);
+
+void layer(bool covered, vec4 color) {
+	if (covered) {
+		result = mix(result, vec4(color.rgb, 1.0), color.a);
+	}
+}
+
+// One pixel wide line at `at`, measured the same way as `p`
+bool onLine(float p, float at) {
+	return abs(p - at) < 0.5;
+}
+
+bool onRect(vec2 p, vec2 lo, vec2 hi) {
+	bool inside = all(greaterThanEqual(p, lo - 0.5)) && all(lessThanEqual(p, hi + 0.5));
+	return inside && (onLine(p.x, lo.x) || onLine(p.x, hi.x) || onLine(p.y, lo.y) || onLine(p.y, hi.y));
+}
+
+// Tick length at pixel `p` along a ruler: longer every 50 and 100 pixels
+float tickLength(float p) {
+	int i = int(floor(p));
+	if (i % 100 == 0) {
+		return 16.0;
+	} else if (i % 50 == 0) {
+		return 10.0;
+	} else if (i % 10 == 0) {
+		return 5.0;
+	}
+	return 0.0;
+}
+
+void main() {
+	vec2 size = vec2(overlay.viewportWidth, overlay.viewportHeight);
+	// Pixel centers are at half-integers; the viewport's first pixel is at (0.5, 0.5)
+	vec2 p = gl_FragCoord.xy - vec2(overlay.viewportX, overlay.viewportY);
+	vec2 center = size * 0.5;
+
+	if ((overlay.flags & THIRDS) != 0u) {
+		vec2 third = size / 3.0;
+		layer(onLine(p.x, third.x) || onLine(p.x, 2.0 * third.x) || onLine(p.y, third.y) || onLine(p.y, 2.0 * third.y), vec4(0.3, 0.9, 1.0, 0.5));
+	}
+	if ((overlay.flags & SAFE_AREAS) != 0u) {
+		vec2 action = size * (1.0 - overlay.actionSafe) * 0.5;
+		vec2 title = size * (1.0 - overlay.titleSafe) * 0.5;
+		layer(onRect(p, action, size - action), vec4(0.2, 1.0, 0.2, 0.7));
+		layer(onRect(p, title, size - title), vec4(1.0, 0.6, 0.1, 0.7));
+	}
+	if ((overlay.flags & CENTER) != 0u) {
+		vec2 d = abs(p - center);
+		layer((d.x < 0.5 && d.y < 12.0) || (d.y < 0.5 && d.x < 12.0), vec4(1.0, 1.0, 0.0, 1.0));
+	}
+	if ((overlay.flags & VIEWPORT_BOUNDS) != 0u) {
+		layer(onRect(p, vec2(0.5), size - 0.5), vec4(1.0, 0.0, 1.0, 1.0));
+	}
+	if ((overlay.flags & RULERS) != 0u) {
+		// Backing strips so the ticks read over any image
+		layer(p.y < 18.0 || p.x < 18.0, vec4(0.0, 0.0, 0.0, 0.4));
+		// X ticks red along the top, Y ticks green down the left, so flips show at a glance
+		layer(p.y < tickLength(p.x), vec4(1.0, 0.3, 0.3, 1.0));
+		layer(p.x < tickLength(p.y), vec4(0.3, 1.0, 0.3, 1.0));
+		// The viewport's origin
+		layer(all(lessThan(p, vec2(6.0))), vec4(1.0, 1.0, 1.0, 1.0));
+	}
+	if (all(greaterThanEqual(p, vec2(0.0))) && all(lessThan(p, size))) {
+		outColor = result;
+	} else {
+		outColor = vec4(0.0);
+	}
+}
@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 fragOffset;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// Round sprites with a soft edge
+	float falloff = 1.0 - dot(fragOffset, fragOffset);
+	if (falloff <= 0.0) {
+		discard;
+	}
+	outColor = vec4(fragColor.rgb, fragColor.a * falloff);
+}
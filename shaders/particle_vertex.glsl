@@ -0,0 +1,45 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform PerDraw {
+	mat4 viewProjection;
+	// xyz: camera right axis, w: billboard half size
+	vec4 right;
+	// xyz: camera up axis
+	vec4 up;
+} perDraw;
+
+// xyz: position, w: how much of the particle's lifetime is left, eased from 1 to 0
+layout(std430, set = 0, binding = 0) readonly buffer Particles {
+	vec4 particles[];
+};
+
+// Particle indices, farthest from the camera first
+layout(std430, set = 0, binding = 1) readonly buffer Order {
+	uint order[];
+};
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(location = 0) out vec2 fragOffset;
+layout(location = 1) out vec4 fragColor;
+
+const vec2 CORNERS[6] = vec2[](
+	vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+	vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+// One instance per particle, in sorted order; six vertices make its billboard
+void main() {
+	vec4 particle = particles[order[gl_InstanceIndex]];
+	vec2 corner = CORNERS[gl_VertexIndex];
+	float halfSize = perDraw.right.w;
+	vec3 position = particle.xyz + (perDraw.right.xyz * corner.x + perDraw.up.xyz * corner.y) * halfSize;
+	gl_Position = perDraw.viewProjection * vec4(position, 1.0);
+	fragOffset = corner;
+	// Cools from yellow to red, and fades out, as the particle ages
+	float life = clamp(particle.w, 0.0, 1.0);
+	fragColor = vec4(mix(vec3(0.8, 0.1, 0.05), vec3(1.0, 0.9, 0.3), life), life * 0.6);
+}
@@ -0,0 +1,25 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform PerDraw {
+	mat4 viewProjection;
+	float pointSize;
+	float inverseWidth;
+	float inverseHeight;
+} perDraw;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec4 inColor;
+
+out gl_PerVertex {
+	vec4 gl_Position;
+	float gl_PointSize;
+};
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+	gl_Position = perDraw.viewProjection * vec4(inPosition, 1.0);
+	gl_PointSize = perDraw.pointSize;
+	fragColor = inColor.rgb;
+}
@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragCorner;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// Round splats
+	if (dot(fragCorner, fragCorner) > 1.0) {
+		discard;
+	}
+	outColor = vec4(fragColor, 1.0);
+}
@@ -0,0 +1,31 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform PerDraw {
+	mat4 viewProjection;
+	float pointSize;
+	float inverseWidth;
+	float inverseHeight;
+} perDraw;
+
+// Per instance: one point per quad
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec4 inColor;
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragCorner;
+
+void main() {
+	// Triangle strip over the quad's corners
+	vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1) * 2.0 - 1.0;
+	vec4 center = perDraw.viewProjection * vec4(inPosition, 1.0);
+	// Offset in pixels, scaled by w so the size stays constant on screen
+	vec2 offset = corner * perDraw.pointSize * vec2(perDraw.inverseWidth, perDraw.inverseHeight);
+	gl_Position = center + vec4(offset * center.w, 0.0, 0.0);
+	fragColor = inColor.rgb;
+	fragCorner = corner;
+}
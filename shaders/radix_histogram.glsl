@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Counts each workgroup's digits for one radix sort pass
+layout(local_size_x = 256) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+	uint shift;
+	uint groupCount;
+} params;
+
+layout(std430, set = 0, binding = 0) readonly buffer KeysIn {
+	uint keysIn[];
+};
+
+// Digit-major, so an exclusive scan over the whole buffer yields each workgroup's offsets
+layout(std430, set = 0, binding = 4) writeonly buffer Histograms {
+	uint histograms[];
+};
+
+shared uint counts[256];
+
+void main() {
+	uint local = gl_LocalInvocationID.x;
+	counts[local] = 0;
+	barrier();
+
+	uint idx = gl_GlobalInvocationID.x;
+	if (idx < params.count) {
+		atomicAdd(counts[(keysIn[idx] >> params.shift) & 0xff], 1);
+	}
+	barrier();
+
+	histograms[local * params.groupCount + gl_WorkGroupID.x] = counts[local];
+}
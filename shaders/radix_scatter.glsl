@@ -0,0 +1,57 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Moves each key/value pair to its sorted position for one radix sort pass
+layout(local_size_x = 256) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+	uint shift;
+	uint groupCount;
+} params;
+
+layout(std430, set = 0, binding = 0) readonly buffer KeysIn {
+	uint keysIn[];
+};
+
+layout(std430, set = 0, binding = 1) readonly buffer ValuesIn {
+	uint valuesIn[];
+};
+
+layout(std430, set = 0, binding = 2) writeonly buffer KeysOut {
+	uint keysOut[];
+};
+
+layout(std430, set = 0, binding = 3) writeonly buffer ValuesOut {
+	uint valuesOut[];
+};
+
+layout(std430, set = 0, binding = 4) readonly buffer Histograms {
+	uint histograms[];
+};
+
+shared uint digits[256];
+
+void main() {
+	uint local = gl_LocalInvocationID.x;
+	uint idx = gl_GlobalInvocationID.x;
+	bool active = idx < params.count;
+	uint key = active ? keysIn[idx] : 0;
+	uint digit = active ? (key >> params.shift) & 0xff : 0xffffffffu;
+	digits[local] = digit;
+	barrier();
+
+	if (!active) {
+		return;
+	}
+	// Ranking by position within the workgroup keeps the sort stable
+	uint rank = 0;
+	for (uint i = 0; i < local; i++) {
+		if (digits[i] == digit) {
+			rank++;
+		}
+	}
+	uint dst = histograms[digit * params.groupCount + gl_WorkGroupID.x] + rank;
+	keysOut[dst] = key;
+	valuesOut[dst] = valuesIn[idx];
+}
@@ -0,0 +1,41 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 64) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+} params;
+
+layout(std430, set = 0, binding = 0) readonly buffer Input {
+	uint values[];
+} inputs;
+
+// One (sum, min, max, unused) per workgroup
+layout(std430, set = 0, binding = 1) writeonly buffer Output {
+	uvec4 partials[];
+} outputs;
+
+shared uvec3 scratch[64];
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	uint local = gl_LocalInvocationID.x;
+	scratch[local] = idx < params.count
+		? uvec3(inputs.values[idx])
+		: uvec3(0, 0xffffffffu, 0);
+	barrier();
+
+	for (uint stride = 32; stride > 0; stride >>= 1) {
+		if (local < stride) {
+			uvec3 a = scratch[local];
+			uvec3 b = scratch[local + stride];
+			scratch[local] = uvec3(a.x + b.x, min(a.y, b.y), max(a.z, b.z));
+		}
+		barrier();
+	}
+
+	if (local == 0) {
+		outputs.partials[gl_WorkGroupID.x] = uvec4(scratch[0], 0);
+	}
+}
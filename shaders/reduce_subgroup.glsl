@@ -0,0 +1,50 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_KHR_shader_subgroup_basic : enable
+#extension GL_KHR_shader_subgroup_arithmetic : enable
+
+layout(local_size_x = 64) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+} params;
+
+layout(std430, set = 0, binding = 0) readonly buffer Input {
+	uint values[];
+} inputs;
+
+// One (sum, min, max, unused) per workgroup
+layout(std430, set = 0, binding = 1) writeonly buffer Output {
+	uvec4 partials[];
+} outputs;
+
+// Enough for subgroups as small as 4 invocations
+shared uvec3 subgroupResults[16];
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	bool active = idx < params.count;
+	uint value = active ? inputs.values[idx] : 0;
+
+	uint sum = subgroupAdd(value);
+	uint minimum = subgroupMin(active ? value : 0xffffffffu);
+	uint maximum = subgroupMax(value);
+	if (subgroupElect()) {
+		subgroupResults[gl_SubgroupID] = uvec3(sum, minimum, maximum);
+	}
+	barrier();
+
+	if (gl_SubgroupID == 0) {
+		uvec3 partial = uvec3(0, 0xffffffffu, 0);
+		for (uint i = gl_SubgroupInvocationID; i < gl_NumSubgroups; i += gl_SubgroupSize) {
+			uvec3 r = subgroupResults[i];
+			partial = uvec3(partial.x + r.x, min(partial.y, r.y), max(partial.z, r.z));
+		}
+		sum = subgroupAdd(partial.x);
+		minimum = subgroupMin(partial.y);
+		maximum = subgroupMax(partial.z);
+		if (subgroupElect()) {
+			outputs.partials[gl_WorkGroupID.x] = uvec4(sum, minimum, maximum, 0);
+		}
+	}
+}
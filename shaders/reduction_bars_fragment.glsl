@@ -0,0 +1,41 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Bars {
+	// Fractions of the values' range
+	float minimum;
+	float mean;
+	float maximum;
+	// Nonzero when the GPU's reduction differed from the CPU's
+	uint mismatch;
+} bars;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+const vec3 BACKGROUND = vec3(0.08, 0.08, 0.1);
+const vec3 TRACK = vec3(0.16, 0.16, 0.2);
+const vec3 MISMATCH = vec3(0.9, 0.15, 0.1);
+const float MARGIN = 0.1;
+
+void main() {
+	// Three bars between the margins, each with a gap below it
+	vec2 p = (fragTexCoord - MARGIN) / (1.0 - 2.0 * MARGIN);
+	if (any(lessThan(p, vec2(0.0))) || any(greaterThan(p, vec2(1.0)))) {
+		outColor = vec4(BACKGROUND, 1.0);
+		return;
+	}
+	float row = p.y * 3.0;
+	int bar = int(min(row, 2.0));
+	if (fract(row) > 0.75) {
+		outColor = vec4(BACKGROUND, 1.0);
+		return;
+	}
+	float value = bar == 0 ? bars.minimum : (bar == 1 ? bars.mean : bars.maximum);
+	vec3 fill = bar == 0 ? vec3(0.2, 0.5, 1.0) : (bar == 1 ? vec3(0.3, 0.85, 0.4) : vec3(1.0, 0.7, 0.2));
+	if (bars.mismatch != 0) {
+		fill = MISMATCH;
+	}
+	outColor = vec4(p.x <= value ? fill : TRACK, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Adds the scanned block totals to every element of their blocks
+layout(local_size_x = 256) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+} params;
+
+layout(std430, set = 0, binding = 1) buffer Output {
+	uint outputs[];
+};
+
+layout(std430, set = 0, binding = 2) readonly buffer BlockSums {
+	uint blockSums[];
+};
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	if (idx < params.count) {
+		outputs[idx] += blockSums[gl_WorkGroupID.x];
+	}
+}
@@ -0,0 +1,46 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Exclusive scan of each workgroup's block, plus the block totals for the next level
+layout(local_size_x = 256) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+} params;
+
+// May alias outputs; each invocation reads its element before writing it
+layout(std430, set = 0, binding = 0) buffer Input {
+	uint inputs[];
+};
+
+layout(std430, set = 0, binding = 1) buffer Output {
+	uint outputs[];
+};
+
+layout(std430, set = 0, binding = 2) writeonly buffer BlockSums {
+	uint blockSums[];
+};
+
+shared uint scratch[256];
+
+void main() {
+	uint local = gl_LocalInvocationID.x;
+	uint idx = gl_GlobalInvocationID.x;
+	uint value = idx < params.count ? inputs[idx] : 0;
+	scratch[local] = value;
+	barrier();
+
+	for (uint offset = 1; offset < 256; offset <<= 1) {
+		uint add = local >= offset ? scratch[local - offset] : 0;
+		barrier();
+		scratch[local] += add;
+		barrier();
+	}
+
+	if (idx < params.count) {
+		outputs[idx] = scratch[local] - value;
+	}
+	if (local == 255) {
+		blockSums[gl_WorkGroupID.x] = scratch[255];
+	}
+}
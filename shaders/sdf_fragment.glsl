@@ -0,0 +1,134 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform Camera {
+	mat4 inverseViewProjection;
+	// xyz: camera position, w: animation time
+	vec4 eye;
+	// xyz: direction towards the light, w: shadow sharpness
+	vec4 light;
+} camera;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+const int MAX_STEPS = 128;
+const float MAX_DISTANCE = 100.0;
+const float SURFACE_EPSILON = 0.001;
+
+float sdSphere(vec3 p, float r) {
+	return length(p) - r;
+}
+
+float sdRoundBox(vec3 p, vec3 b, float r) {
+	vec3 q = abs(p) - b;
+	return length(max(q, 0.0)) + min(max(q.x, max(q.y, q.z)), 0.0) - r;
+}
+
+float sdTorus(vec3 p, vec2 t) {
+	vec2 q = vec2(length(p.xz) - t.x, p.y);
+	return length(q) - t.y;
+}
+
+float smoothUnion(float a, float b, float k) {
+	float h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+	return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+// Distance to the scene in x, material id in y
+vec2 scene(vec3 p) {
+	float t = camera.eye.w;
+	float ground = p.y;
+	float blob = smoothUnion(
+		sdSphere(p - vec3(sin(t) * 0.8, 1.0, 0.0), 0.6),
+		sdRoundBox(p - vec3(0.0, 0.7, 0.0), vec3(0.5, 0.4, 0.5), 0.1),
+		0.3);
+	float ring = sdTorus(p - vec3(2.0, 0.4, 0.0), vec2(0.6, 0.2));
+	vec2 ret = vec2(ground, 0.0);
+	if (blob < ret.x) {
+		ret = vec2(blob, 1.0);
+	}
+	if (ring < ret.x) {
+		ret = vec2(ring, 2.0);
+	}
+	return ret;
+}
+
+vec3 normalAt(vec3 p) {
+	const vec2 e = vec2(0.001, 0.0);
+	return normalize(vec3(
+		scene(p + e.xyy).x - scene(p - e.xyy).x,
+		scene(p + e.yxy).x - scene(p - e.yxy).x,
+		scene(p + e.yyx).x - scene(p - e.yyx).x));
+}
+
+// Penumbra from how closely the shadow ray passes occluders
+float softShadow(vec3 origin, vec3 dir, float k) {
+	float shade = 1.0;
+	float t = 0.02;
+	for (int i = 0; i < 64 && t < 20.0; i++) {
+		float h = scene(origin + dir * t).x;
+		if (h < SURFACE_EPSILON) {
+			return 0.0;
+		}
+		shade = min(shade, k * h / t);
+		t += clamp(h, 0.01, 0.5);
+	}
+	return clamp(shade, 0.0, 1.0);
+}
+
+float ambientOcclusion(vec3 p, vec3 n) {
+	float occlusion = 0.0;
+	float weight = 1.0;
+	for (int i = 1; i <= 5; i++) {
+		float d = 0.05 * float(i);
+		occlusion += weight * (d - scene(p + n * d).x);
+		weight *= 0.6;
+	}
+	return clamp(1.0 - 2.0 * occlusion, 0.0, 1.0);
+}
+
+vec3 albedo(float material, vec3 p) {
+	if (material < 0.5) {
+		float checker = mod(floor(p.x) + floor(p.z), 2.0);
+		return mix(vec3(0.25), vec3(0.45), checker);
+	} else if (material < 1.5) {
+		return vec3(0.9, 0.35, 0.2);
+	}
+	return vec3(0.2, 0.5, 0.9);
+}
+
+void main() {
+	vec2 ndc = fragTexCoord * 2.0 - 1.0;
+	vec4 near = camera.inverseViewProjection * vec4(ndc, 0.0, 1.0);
+	vec4 far = camera.inverseViewProjection * vec4(ndc, 1.0, 1.0);
+	vec3 origin = camera.eye.xyz;
+	vec3 dir = normalize(far.xyz / far.w - near.xyz / near.w);
+
+	vec3 sky = mix(vec3(0.7, 0.8, 0.95), vec3(0.3, 0.45, 0.7), clamp(dir.y, 0.0, 1.0));
+	float t = 0.0;
+	vec2 hit = vec2(MAX_DISTANCE, -1.0);
+	for (int i = 0; i < MAX_STEPS && t < MAX_DISTANCE; i++) {
+		vec2 d = scene(origin + dir * t);
+		if (d.x < SURFACE_EPSILON * t) {
+			hit = vec2(t, d.y);
+			break;
+		}
+		t += d.x;
+	}
+	if (hit.y < 0.0) {
+		outColor = vec4(sky, 1.0);
+		return;
+	}
+
+	vec3 p = origin + dir * hit.x;
+	vec3 n = normalAt(p);
+	vec3 l = normalize(camera.light.xyz);
+	float diffuse = max(dot(n, l), 0.0) * softShadow(p + n * 0.01, l, camera.light.w);
+	float ambient = 0.25 * ambientOcclusion(p, n);
+	vec3 color = albedo(hit.y, p) * (diffuse + ambient * sky);
+	// Distance fog into the sky color
+	color = mix(color, sky, 1.0 - exp(-0.002 * hit.x * hit.x));
+	outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D atlas;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// Perspective depth bunches up near 1, so spread it out: the far plane is black, and anything
+	// a light saw gets brighter the closer it was
+	float depth = texture(atlas, fragTexCoord).r;
+	outColor = vec4(vec3(1.0 - pow(depth, 32.0)), 1.0);
+}
@@ -0,0 +1,58 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(push_constant) uniform Params {
+	// x: near, y: far, z: radius in world units, w: intensity
+	vec4 params;
+	// Reduced texels per world unit at a distance of one
+	float focal;
+} params;
+
+layout(set = 0, binding = 1) uniform sampler2D reducedDepth;
+// rgb: black, a: occlusion, blended premultiplied over the lit image
+layout(set = 0, binding = 2, rgba16f) uniform writeonly image2D occlusion;
+
+const int SAMPLES = 12;
+const float GOLDEN_ANGLE = 2.39996323;
+
+float linearDepth(float depth) {
+	float near = params.params.x;
+	float far = params.params.y;
+	return near * far / (far - depth * (far - near));
+}
+
+// Counts the neighbors within `radius` that are in front of this texel by less than `radius`,
+// on a golden angle spiral so the samples spread evenly over the disk.
+void main() {
+	ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+	ivec2 size = imageSize(occlusion);
+	if (any(greaterThanEqual(coord, size))) {
+		return;
+	}
+	float depth = texelFetch(reducedDepth, coord, 0).r;
+	if (depth >= 1.0) {
+		imageStore(occlusion, coord, vec4(0.0));
+		return;
+	}
+	float z = linearDepth(depth);
+	float radius = params.params.z;
+	float pixels = max(radius * params.focal / z, 1.0);
+	float bias = 0.02 * z;
+	// Rotate the spiral per texel, which the upsample's blur mostly hides
+	float rotation = fract(sin(dot(vec2(coord), vec2(12.9898, 78.233))) * 43758.5453) * 6.2831853;
+
+	float occluded = 0.0;
+	for (int i = 0; i < SAMPLES; i++) {
+		float t = (float(i) + 0.5) / float(SAMPLES);
+		float angle = float(i) * GOLDEN_ANGLE + rotation;
+		ivec2 offset = ivec2(vec2(cos(angle), sin(angle)) * sqrt(t) * pixels);
+		ivec2 sampleCoord = clamp(coord + offset, ivec2(0), size - 1);
+		float difference = z - linearDepth(texelFetch(reducedDepth, sampleCoord, 0).r);
+		// Fade out occluders much nearer than the radius, which are likely in front of a gap
+		occluded += difference > bias ? smoothstep(0.0, 1.0, radius / difference) : 0.0;
+	}
+	float ao = clamp(occluded / float(SAMPLES) * params.params.w, 0.0, 1.0);
+	imageStore(occlusion, coord, vec4(0.0, 0.0, 0.0, ao));
+}
@@ -0,0 +1,27 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 64) in;
+
+// Laid out like ChunkPushConstants<u32> in src/chunked.rs
+layout(push_constant) uniform Params {
+	uvec3 groupOffset;
+	uint count;
+} params;
+
+layout(std430, set = 0, binding = 0) readonly buffer Input {
+	uint values[];
+} inputs;
+
+layout(std430, set = 0, binding = 1) writeonly buffer Output {
+	uint values[];
+} outputs;
+
+void main() {
+	uvec3 group = gl_WorkGroupID + params.groupOffset;
+	uint idx = (group.y * gl_NumWorkGroups.x + group.x) * gl_WorkGroupSize.x + gl_LocalInvocationID.x;
+	if (idx >= params.count) {
+		return;
+	}
+	outputs.values[idx] = inputs.values[idx];
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 64) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+} params;
+
+layout(std430, set = 0, binding = 0) readonly buffer Input {
+	uint values[];
+} inputs;
+
+layout(std430, set = 0, binding = 1) writeonly buffer Output {
+	uint values[];
+} outputs;
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	if (idx >= params.count) {
+		return;
+	}
+	outputs.values[idx] = inputs.values[idx];
+}
@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 64) in;
+
+layout(push_constant) uniform Params {
+	uint count;
+} params;
+
+// Two SNORM16 components per element, x in the low half
+layout(std430, set = 0, binding = 0) readonly buffer Input {
+	uint encoded[];
+} inputs;
+
+layout(std430, set = 0, binding = 1) writeonly buffer Output {
+	vec4 normals[];
+} outputs;
+
+// Keep in sync with shaders/mesh_quantized_vertex.glsl
+vec3 octahedralDecode(vec2 e) {
+	vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+	if (n.z < 0.0) {
+		vec2 signs = vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+		n.xy = (1.0 - abs(n.yx)) * signs;
+	}
+	return normalize(n);
+}
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	if (idx >= params.count) {
+		return;
+	}
+	outputs.normals[idx] = vec4(octahedralDecode(unpackSnorm2x16(inputs.encoded[idx])), 0.0);
+}
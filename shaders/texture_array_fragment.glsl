@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2DArray materialTextures;
+
+layout(push_constant) uniform PerDraw {
+	uint layer;
+} perDraw;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	outColor = texture(materialTextures, vec3(fragTexCoord, float(perDraw.layer)));
+}
@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(push_constant) uniform ThickLine {
+	mat4 viewProjection;
+	float width;
+	float viewportWidth;
+	float viewportHeight;
+} line;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) noperspective in vec2 fragLineCoord;
+layout(location = 2) flat in float fragLength;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// Distance to the segment gives round caps, and round joins where segments meet
+	vec2 nearest = vec2(clamp(fragLineCoord.x, 0.0, fragLength), 0.0);
+	float distance = length(fragLineCoord - nearest);
+	float coverage = clamp(line.width * 0.5 + 0.5 - distance, 0.0, 1.0);
+	if (coverage <= 0.0) {
+		discard;
+	}
+	outColor = vec4(fragColor, coverage);
+}
@@ -0,0 +1,59 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+out gl_PerVertex {
+	vec4 gl_Position;
+};
+
+layout(push_constant) uniform ThickLine {
+	mat4 viewProjection;
+	float width;
+	float viewportWidth;
+	float viewportHeight;
+} line;
+
+// Per instance: both ends of one segment
+layout(location = 0) in vec3 inPositionA;
+layout(location = 1) in vec3 inColorA;
+layout(location = 2) in vec3 inPositionB;
+layout(location = 3) in vec3 inColorB;
+
+layout(location = 0) out vec3 fragColor;
+// Pixel position relative to the first end, along and across the segment
+layout(location = 1) noperspective out vec2 fragLineCoord;
+layout(location = 2) flat out float fragLength;
+
+const float NEAR_W = 1e-4;
+
+void main() {
+	vec4 a = line.viewProjection * vec4(inPositionA, 1.0);
+	vec4 b = line.viewProjection * vec4(inPositionB, 1.0);
+	// Clip against the plane through the eye, so ends behind the camera don't flip
+	if (a.w < NEAR_W) {
+		a = mix(a, b, (NEAR_W - a.w) / (b.w - a.w));
+	} else if (b.w < NEAR_W) {
+		b = mix(b, a, (NEAR_W - b.w) / (a.w - b.w));
+	}
+
+	vec2 viewport = vec2(line.viewportWidth, line.viewportHeight);
+	vec2 pixelA = (a.xy / a.w * 0.5 + 0.5) * viewport;
+	vec2 pixelB = (b.xy / b.w * 0.5 + 0.5) * viewport;
+	vec2 delta = pixelB - pixelA;
+	float len = length(delta);
+	vec2 along = len > 1e-4 ? delta / len : vec2(1.0, 0.0);
+	vec2 across = vec2(-along.y, along.x);
+
+	// Triangle strip over a quad covering the segment and its round caps, with a pixel to spare
+	// for antialiasing
+	bool endB = (gl_VertexIndex & 1) != 0;
+	float side = (gl_VertexIndex & 2) != 0 ? 1.0 : -1.0;
+	float extent = line.width * 0.5 + 1.0;
+	vec2 offset = along * (endB ? extent : -extent) + across * side * extent;
+	vec4 clip = endB ? b : a;
+	vec2 pixel = (endB ? pixelB : pixelA) + offset;
+	gl_Position = vec4((pixel / viewport * 2.0 - 1.0) * clip.w, clip.z, clip.w);
+
+	fragColor = endB ? inColorB : inColorA;
+	fragLineCoord = vec2(dot(pixel - pixelA, along), dot(pixel - pixelA, across));
+	fragLength = len;
+}
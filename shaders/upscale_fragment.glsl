@@ -0,0 +1,61 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Must match UpscaleFilter in src/upscale.rs
+const uint NEAREST = 0u;
+const uint BILINEAR = 1u;
+const uint SHARPENED = 2u;
+
+// RCAS never lets the negative lobe go past this, or flat areas would start to ring
+const float MAX_LOBE = 0.1875;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Upscale {
+	uint mode;
+	// 0 is the strongest, each step of 1 halves it
+	float sharpness;
+	// Size of one output pixel in texture coordinates
+	vec2 outputTexel;
+} params;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 bilinear(vec2 uv) {
+	return texture(source, uv).rgb;
+}
+
+// Robust contrast-adaptive sharpening, as in the second pass of FSR 1. The four neighbors are
+// bilinear samples one output pixel away, so this sharpens the upscaled image without having to
+// write it out first. The lobe is limited by how far the neighborhood is from clipping at either
+// end, which keeps it from overshooting on edges.
+vec3 sharpened(vec2 uv) {
+	vec3 c = bilinear(uv);
+	vec3 n = bilinear(uv - vec2(0.0, params.outputTexel.y));
+	vec3 s = bilinear(uv + vec2(0.0, params.outputTexel.y));
+	vec3 w = bilinear(uv - vec2(params.outputTexel.x, 0.0));
+	vec3 e = bilinear(uv + vec2(params.outputTexel.x, 0.0));
+	vec3 lo = min(min(n, s), min(w, e));
+	vec3 hi = max(max(n, s), max(w, e));
+	vec3 hitMin = lo / max(4.0 * hi, vec3(1e-5));
+	vec3 hitMax = (1.0 - hi) / min(4.0 * lo - 4.0, vec3(-1e-5));
+	vec3 lobeRgb = max(-hitMin, hitMax);
+	float lobe = max(-MAX_LOBE, min(max(lobeRgb.r, max(lobeRgb.g, lobeRgb.b)), 0.0)) * exp2(-params.sharpness);
+	return (lobe * (n + s + w + e) + c) / (4.0 * lobe + 1.0);
+}
+
+void main() {
+	vec3 color;
+	if (params.mode == NEAREST) {
+		ivec2 size = textureSize(source, 0);
+		ivec2 texel = clamp(ivec2(fragTexCoord * vec2(size)), ivec2(0), size - 1);
+		color = texelFetch(source, texel, 0).rgb;
+	} else if (params.mode == SHARPENED) {
+		color = sharpened(fragTexCoord);
+	} else {
+		color = bilinear(fragTexCoord);
+	}
+	outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// After the mesh vertex shader's push constants
+layout(push_constant) uniform Checker {
+	layout(offset = 128) float scale;
+} checker;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec2 cell = floor(fragTexCoord * checker.scale);
+	float parity = mod(cell.x + cell.y, 2.0);
+	// Tint by the UV itself so that mirrored or rotated islands are easy to spot
+	vec3 tint = vec3(fract(fragTexCoord), 0.5);
+	outColor = vec4(mix(vec3(0.15), tint, parity), 1.0);
+}
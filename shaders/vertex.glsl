@@ -1,25 +1,29 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
+layout(set = 0, binding = 0) uniform SceneUniforms {
+	mat4 modelViewProjection;
+	mat4 model;
+} uniforms;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
 out gl_PerVertex {
 	vec4 gl_Position;
 };
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
 
-vec2 positions[3] = vec2[](
-	vec2(0.0, -0.5),
-	vec2(0.5, 0.5),
-	vec2(-0.5, 0.5)
-);
-
-vec3 colors[3] = vec3[](
-	vec3(1.0, 0.0, 0.0),
-	vec3(0.0, 1.0, 0.0),
-	vec3(0.0, 0.0, 1.0)
-);
+const vec3 lightDirection = normalize(vec3(0.5, 1.0, 1.0));
+const float ambient = 0.2;
 
 void main() {
-	gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
-	fragColor = colors[gl_VertexIndex];
+	gl_Position = uniforms.modelViewProjection * vec4(inPosition, 1.0);
+	// Lit from both sides, since back faces are drawn too
+	vec3 normal = normalize(mat3(uniforms.model) * inNormal);
+	fragColor = vec3(ambient + (1.0 - ambient) * abs(dot(normal, lightDirection)));
+	fragTexCoord = inTexCoord;
 }
@@ -0,0 +1,134 @@
+//! Importing assets into the running scene by dropping files onto the window, or pasting their
+//! paths with Ctrl+V. What a file is used for goes by its extension:
+//!
+//! - `.png`, `.jpg` and `.jpeg` replace the scene's texture.
+//! - `.spv` replaces the scene's fragment shader. It has to take the same inputs as
+//!   `shaders/fragment.glsl`.
+//! - `.obj`, `.gltf` and `.glb` replace the scene's mesh, and a glTF model's base color texture
+//!   replaces the scene's texture.
+use glfw;
+use std;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+
+/// File managers put paths on the clipboard as URLs.
+const FILE_URL_PREFIX: &'static str = "file://";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetKind {
+    Model,
+    Texture,
+    FragmentShader,
+}
+
+impl AssetKind {
+    /// `None` for files that can't be imported.
+    pub fn for_path(path: &Path) -> Option<AssetKind> {
+        let extension = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => extension.to_lowercase(),
+            None => return None,
+        };
+        match extension.as_str() {
+            "obj" | "gltf" | "glb" => Some(AssetKind::Model),
+            "png" | "jpg" | "jpeg" => Some(AssetKind::Texture),
+            "spv" => Some(AssetKind::FragmentShader),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AssetKind::Model => "model",
+            AssetKind::Texture => "texture",
+            AssetKind::FragmentShader => "fragment shader",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub path: PathBuf,
+    pub kind: AssetKind,
+}
+
+/// The imports for `paths`, skipping (and warning about) any that can't be imported.
+pub fn imports<I: Iterator<Item = PathBuf>>(paths: I) -> Vec<Import> {
+    paths.filter_map(|path| match AssetKind::for_path(&path) {
+        Some(kind) => Some(Import {
+            path: path,
+            kind: kind,
+        }),
+        None => {
+            warn!("Don't know how to import {:?}", path);
+            None
+        },
+    }).collect()
+}
+
+/// The path on one pasted line, or `None` for blank lines. URLs are percent-decoded, since file
+/// managers escape spaces and other characters in them.
+fn pasted_path(line: &str) -> Option<PathBuf> {
+    let line = line.trim();
+    let path = match line.strip_prefix(FILE_URL_PREFIX) {
+        Some(url_path) => percent_decode(url_path),
+        None => line.to_string(),
+    };
+    if path.is_empty() { None } else { Some(PathBuf::from(path)) }
+}
+
+/// `text` with each `%XX` escape replaced by the byte it stands for. Escapes that aren't two hex
+/// digits are left as they are, and bytes that don't make valid UTF-8 are replaced.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 3 <= bytes.len() && bytes[i + 1..i + 3].iter().all(u8::is_ascii_hexdigit) {
+            std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The imports `event` asks for: files dropped onto the window, or paths pasted one per line with
+/// Ctrl+V.
+pub fn imports_for_event(window: &glfw::Window, event: &glfw::WindowEvent) -> Vec<Import> {
+    match *event {
+        glfw::WindowEvent::FileDrop(ref paths) => imports(paths.iter().cloned()),
+        glfw::WindowEvent::Key(glfw::Key::V, _, glfw::Action::Press, modifiers) if modifiers.contains(glfw::modifiers::Control) => {
+            let clipboard = window.get_clipboard_string();
+            imports(clipboard.lines()
+                .filter_map(pasted_path))
+        },
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pasted_urls_are_percent_decoded() {
+        assert_eq!(pasted_path("file:///home/me/My%20Models/teapot%2B.obj"), Some(PathBuf::from("/home/me/My Models/teapot+.obj")));
+        assert_eq!(pasted_path("  file:///tmp/caf%C3%A9.png "), Some(PathBuf::from("/tmp/café.png")));
+        assert_eq!(pasted_path("file:///tmp/%+1.png"), Some(PathBuf::from("/tmp/%+1.png")));
+        assert_eq!(pasted_path("file:///tmp/100%.png"), Some(PathBuf::from("/tmp/100%.png")));
+        assert_eq!(pasted_path("/tmp/50%20off.png"), Some(PathBuf::from("/tmp/50%20off.png")));
+        assert_eq!(pasted_path("   "), None);
+    }
+}
@@ -0,0 +1,346 @@
+//! Life-like cellular automata on the GPU, and the smallest example of storage images in the
+//! playground. Two images take turns as the current generation: `shaders/automata.glsl` reads one
+//! and writes the other, and a full-screen pass (`shaders/automata_fragment.glsl`) samples
+//! whichever was written last. Both stay in `General` layout; the barriers around each step
+//! order the compute writes against the fragment shader reads.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::ComputeKernel;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::pipeline::GraphicsPipelineBuilder;
+use ::rect::ExtentExt;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const STEP_SHADER: &'static str = "shaders/automata.comp.spv";
+pub const FRAGMENT_SHADER: &'static str = "shaders/automata_fragment.frag.spv";
+
+/// Invocations per axis of a `shaders/automata.glsl` workgroup.
+const LOCAL_SIZE: u32 = 8;
+
+const SRC_BINDING: u32 = 0;
+const DST_BINDING: u32 = 1;
+/// Binding of the generation to show in the full-screen pass.
+pub const DRAW_BINDING: u32 = 0;
+
+/// Cells store alive in red and age in green; RGBA8 is always usable as a storage image.
+const CELL_FORMAT: Format = Format::R8g8b8a8Unorm;
+
+/// Birth and survival rule in B/S notation, as bit masks of neighbor counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub birth: u32,
+    pub survival: u32,
+}
+
+impl Rule {
+    /// Conway's Game of Life, B3/S23.
+    pub fn conway() -> Rule {
+        Rule::parse("B3/S23").unwrap()
+    }
+
+    /// Parses rules like `"B36/S23"` (HighLife).
+    pub fn parse(rule: &str) -> Option<Rule> {
+        let mut parts = rule.split('/');
+        let (birth, survival) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(b), Some(s), None) => (b, s),
+            _ => return None,
+        };
+        let mask = |part: &str, prefix: char| -> Option<u32> {
+            let mut chars = part.chars();
+            if chars.next().map(|c| c.to_ascii_uppercase()) != Some(prefix) {
+                return None;
+            }
+            chars.try_fold(0, |mask, c| match c.to_digit(10) {
+                Some(n) if n <= 8 => Some(mask | (1 << n)),
+                _ => None,
+            })
+        };
+        match (mask(birth, 'B'), mask(survival, 'S')) {
+            (Some(birth), Some(survival)) => Some(Rule { birth: birth, survival: survival }),
+            _ => None,
+        }
+    }
+}
+
+/// Push constant block read by `shaders/automata.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct AutomataPushConstants {
+    birth: u32,
+    survival: u32,
+    /// Non-zero to fill the destination with random cells instead of stepping, seeded by this.
+    seed: u32,
+}
+
+pub struct CellularAutomaton<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    images: [Image; 2],
+    memory: [VkAllocation<'d, D>; 2],
+    views: [ImageView; 2],
+    sampler: Sampler,
+    extent: Extent2D,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    /// `step_sets[i]` reads image `i` and writes the other.
+    step_sets: [DescriptorSet; 2],
+    /// `draw_sets[i]` samples image `i`.
+    draw_sets: [DescriptorSet; 2],
+    current: usize,
+    initialized: bool,
+    pub rule: Rule,
+}
+
+impl<'d, D: DeviceV1_0> CellularAutomaton<'d, D> {
+    /// `kernel` must be built from `STEP_SHADER` and `compute_bindings`, and `draw_set_layout`
+    /// from `descriptor::texture_view_bindings`. The grid is empty until `cmd_seed`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, kernel: &ComputeKernel<D>, draw_set_layout: DescriptorSetLayout, extent: Extent2D) -> VkResult<CellularAutomaton<'d, D>> {
+        let pool_sizes = [
+            DescriptorPoolSize {
+                typ: DescriptorType::StorageImage,
+                descriptor_count: 4,
+            },
+            DescriptorPoolSize {
+                typ: DescriptorType::CombinedImageSampler,
+                descriptor_count: 2,
+            },
+        ];
+        let mut ret = CellularAutomaton {
+            device: device,
+            images: [Image::null(); 2],
+            memory: [VkAllocation::null(), VkAllocation::null()],
+            views: [ImageView::null(); 2],
+            sampler: Sampler::null(),
+            extent: extent,
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, 4),
+            step_sets: [DescriptorSet::null(); 2],
+            draw_sets: [DescriptorSet::null(); 2],
+            current: 0,
+            initialized: false,
+            rule: Rule::conway(),
+        };
+        for i in 0..2 {
+            let image_create_info = ImageCreateInfo {
+                s_type: StructureType::ImageCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                image_type: ImageType::Type2d,
+                format: CELL_FORMAT,
+                extent: Extent3D {
+                    width: ret.extent.width,
+                    height: ret.extent.height,
+                    depth: 1,
+                },
+                mip_levels: 1,
+                array_layers: 1,
+                samples: SAMPLE_COUNT_1_BIT,
+                tiling: ImageTiling::Optimal,
+                usage: IMAGE_USAGE_STORAGE_BIT | IMAGE_USAGE_SAMPLED_BIT,
+                sharing_mode: SharingMode::Exclusive,
+                queue_family_index_count: 0,
+                p_queue_family_indices: ptr::null(),
+                initial_layout: ImageLayout::Undefined,
+            };
+            ret.images[i] = unsafe { device.create_image(&image_create_info, None) }?;
+            ret.memory[i] = allocator.allocate_image_memory(ret.images[i], MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+            let view_create_info = ImageViewCreateInfo {
+                s_type: StructureType::ImageViewCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                image: ret.images[i],
+                view_type: ImageViewType::Type2d,
+                format: CELL_FORMAT,
+                components: ComponentMapping {
+                    r: ComponentSwizzle::Identity,
+                    g: ComponentSwizzle::Identity,
+                    b: ComponentSwizzle::Identity,
+                    a: ComponentSwizzle::Identity,
+                },
+                subresource_range: CellularAutomaton::<D>::subresource_range(),
+            };
+            ret.views[i] = unsafe { device.create_image_view(&view_create_info, None) }?;
+        }
+
+        // Nearest filtering keeps cells crisp when the grid is stretched over the screen
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::Repeat,
+            address_mode_v: SamplerAddressMode::Repeat,
+            address_mode_w: SamplerAddressMode::Repeat,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+
+        let views = ret.views;
+        for i in 0..2 {
+            let storage = |binding: u32, idx: usize| DescriptorWrite::StorageImage {
+                binding: binding,
+                image_view: views[idx],
+                image_layout: ImageLayout::General,
+            };
+            let step_set = ret.descriptor_allocator.allocate(kernel.set_layout())?;
+            descriptor::update_descriptor_set(device, step_set, &[storage(SRC_BINDING, i), storage(DST_BINDING, 1 - i)]);
+            let draw_set = ret.descriptor_allocator.allocate(draw_set_layout)?;
+            descriptor::update_descriptor_set(device, draw_set, &[DescriptorWrite::CombinedImageSampler {
+                binding: DRAW_BINDING,
+                sampler: ret.sampler,
+                image_view: ret.views[i],
+                image_layout: ImageLayout::General,
+            }]);
+            ret.step_sets[i] = step_set;
+            ret.draw_sets[i] = draw_set;
+        }
+        debug!("Created {}x{} cellular automaton", ret.extent.width, ret.extent.height);
+        Ok(ret)
+    }
+
+    fn subresource_range() -> ImageSubresourceRange {
+        ImageSubresourceRange {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    pub fn compute_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        [SRC_BINDING, DST_BINDING].iter().map(|&binding| DescriptorSetLayoutBinding {
+            binding: binding,
+            descriptor_type: DescriptorType::StorageImage,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            p_immutable_samplers: ptr::null(),
+        }).collect()
+    }
+
+    /// Push constant size for a kernel built from `STEP_SHADER`.
+    pub fn push_constant_size() -> u32 {
+        std::mem::size_of::<AutomataPushConstants>() as u32
+    }
+
+    pub fn extent(&self) -> &Extent2D {
+        &self.extent
+    }
+
+    /// Records the barriers that move both images into `General` the first time around.
+    unsafe fn cmd_initialize(&mut self, command_buffer: CommandBuffer) {
+        if self.initialized {
+            return;
+        }
+        let barriers: Vec<ImageMemoryBarrier> = self.images.iter().map(|&image| ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: Default::default(),
+            dst_access_mask: ACCESS_SHADER_READ_BIT | ACCESS_SHADER_WRITE_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: image,
+            subresource_range: CellularAutomaton::<D>::subresource_range(),
+        }).collect();
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &barriers);
+        self.initialized = true;
+    }
+
+    unsafe fn cmd_dispatch(&mut self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>, seed: u32) {
+        self.cmd_initialize(command_buffer);
+        let dst = self.images[1 - self.current];
+        // The destination was last sampled by the full-screen pass
+        let before = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_READ_BIT,
+            dst_access_mask: ACCESS_SHADER_WRITE_BIT,
+            old_layout: ImageLayout::General,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: dst,
+            subresource_range: CellularAutomaton::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_FRAGMENT_SHADER_BIT | PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[before]);
+
+        let constants = AutomataPushConstants {
+            birth: self.rule.birth,
+            survival: self.rule.survival,
+            seed: seed,
+        };
+        let groups = self.extent.div_ceil(LOCAL_SIZE);
+        kernel.cmd_dispatch(command_buffer, self.step_sets[self.current], &constants, (groups.width, groups.height, 1));
+        self.current = 1 - self.current;
+
+        // The next step and the full-screen pass both read what was just written
+        let after = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::General,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: dst,
+            subresource_range: CellularAutomaton::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT | PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &[after]);
+    }
+
+    /// Records filling the grid with random cells, about a third of them alive. `seed` must not
+    /// be zero. Must be outside a render pass, like `cmd_step`.
+    pub unsafe fn cmd_seed(&mut self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>, seed: u32) {
+        assert!(seed != 0);
+        self.cmd_dispatch(command_buffer, kernel, seed);
+    }
+
+    /// Records one generation.
+    pub unsafe fn cmd_step(&mut self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>) {
+        self.cmd_dispatch(command_buffer, kernel, 0);
+    }
+
+    /// The draw descriptor set for the latest generation.
+    pub fn draw_descriptor_set(&self) -> DescriptorSet {
+        self.draw_sets[self.current]
+    }
+
+    /// Records the full-screen pass, with the automata pipeline and `draw_descriptor_set` bound.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer) {
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for CellularAutomaton<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.sampler, None);
+            for i in 0..2 {
+                trace!("Destroying automaton image: {:?}", self.images[i]);
+                self.device.destroy_image_view(self.views[i], None);
+                self.device.destroy_image(self.images[i], None);
+            }
+        }
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl` showing the cells.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
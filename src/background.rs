@@ -0,0 +1,128 @@
+//! What to do while the window isn't being looked at, i.e. when it's lost focus or been
+//! minimized, so the playground can be left running without keeping the GPU busy. Picked with
+//! `--background=<policy>`:
+//!
+//! - `full` keeps rendering as usual, and is the default.
+//! - `throttle[:<fps>]` renders at most `fps` frames a second, 10 if not given.
+//! - `pause` stops rendering until the window comes back.
+//!
+//! Waiting is done on window events, so rendering picks up again as soon as the window does.
+use glfw;
+use std::time::Instant;
+use ::cvar::Flag;
+
+pub const ARG_PREFIX: &'static str = "--background=";
+pub const CVAR: &'static str = "window.background";
+pub const FLAGS: &'static [Flag] = &[Flag::Value { prefix: ARG_PREFIX, name: CVAR }];
+pub const DEFAULT_THROTTLE_FPS: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Default)]
+pub enum BackgroundPolicy {
+    #[default]
+    Full,
+    /// At most this many frames a second.
+    Throttle(f32),
+    Pause,
+}
+
+
+impl BackgroundPolicy {
+    pub fn parse(text: &str) -> Option<BackgroundPolicy> {
+        let mut parts = text.splitn(2, ':');
+        match (parts.next().unwrap(), parts.next()) {
+            ("full", None) => Some(BackgroundPolicy::Full),
+            ("pause", None) => Some(BackgroundPolicy::Pause),
+            ("throttle", None) => Some(BackgroundPolicy::Throttle(DEFAULT_THROTTLE_FPS)),
+            ("throttle", Some(fps)) => match fps.parse::<f32>() {
+                Ok(fps) if fps > 0.0 => Some(BackgroundPolicy::Throttle(fps)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// From `window.background`, which `ARG_PREFIX` sets.
+    pub fn from_cvars() -> BackgroundPolicy {
+        let text: String = cvar!(CVAR, "full".to_string()).get();
+        BackgroundPolicy::parse(&text).unwrap_or_else(|| {
+            warn!("Invalid background policy {:?}, expected full, throttle[:<fps>] or pause", text);
+            BackgroundPolicy::default()
+        })
+    }
+}
+
+/// What the main loop should do before its next frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    Render,
+    /// Wait for up to this many seconds, or until an event comes in.
+    WaitFor(f64),
+    /// Wait for an event.
+    WaitForEvents,
+}
+
+/// Tracks whether the window is in the background from its focus and iconify events.
+#[derive(Debug)]
+pub struct WindowActivity {
+    policy: BackgroundPolicy,
+    focused: bool,
+    iconified: bool,
+    last_frame: Option<Instant>,
+}
+
+impl WindowActivity {
+    /// The window needs focus and iconify polling on for this to see changes.
+    pub fn new(window: &glfw::Window, policy: BackgroundPolicy) -> WindowActivity {
+        WindowActivity {
+            policy: policy,
+            focused: window.is_focused(),
+            iconified: window.is_iconified(),
+            last_frame: None,
+        }
+    }
+
+    pub fn is_background(&self) -> bool {
+        !self.focused || self.iconified
+    }
+
+    /// Updates the state from `event`, returning whether it was a focus or iconify event.
+    pub fn handle_event(&mut self, event: &glfw::WindowEvent) -> bool {
+        let was_background = self.is_background();
+        match *event {
+            glfw::WindowEvent::Focus(focused) => self.focused = focused,
+            glfw::WindowEvent::Iconify(iconified) => self.iconified = iconified,
+            _ => return false,
+        }
+        if self.policy != BackgroundPolicy::Full && was_background != self.is_background() {
+            if self.is_background() {
+                info!("Window went to the background, {}", match self.policy {
+                    BackgroundPolicy::Throttle(fps) => format!("throttling to {} fps", fps),
+                    _ => "pausing rendering".to_string(),
+                });
+            } else {
+                info!("Window is back, resuming rendering");
+            }
+        }
+        true
+    }
+
+    pub fn pacing(&self) -> Pacing {
+        if !self.is_background() {
+            return Pacing::Render;
+        }
+        match (self.policy, self.last_frame) {
+            (BackgroundPolicy::Full, _) | (BackgroundPolicy::Throttle(_), None) => Pacing::Render,
+            (BackgroundPolicy::Throttle(fps), Some(last_frame)) => {
+                let remaining = 1.0 / fps as f64 - ::time::seconds(last_frame.elapsed()) as f64;
+                if remaining > 0.0 { Pacing::WaitFor(remaining) } else { Pacing::Render }
+            },
+            (BackgroundPolicy::Pause, _) => Pacing::WaitForEvents,
+        }
+    }
+
+    /// Call when starting a frame, to throttle from.
+    pub fn frame_started(&mut self) {
+        self.last_frame = Some(Instant::now());
+    }
+}
@@ -0,0 +1,209 @@
+//! Buffers and their memory. A `DeviceBuffer` owns a buffer bound to memory from the
+//! `MemoryAllocator`, either host-visible for data the CPU rewrites, or device-local with its
+//! contents uploaded once through a host-visible staging buffer and a one-shot copy on an
+//! `ImmediateContext`.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::command::Drawable;
+use ::immediate::ImmediateContext;
+use ::logging;
+use ::safe_create::{ self, Owned };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub struct DeviceBuffer<'d, D: DeviceV1_0 + 'd> {
+    buffer: Owned<'d, D, Buffer>,
+    memory: VkAllocation<'d, D>,
+    size: DeviceSize,
+}
+
+impl<'d, D: DeviceV1_0> DeviceBuffer<'d, D> {
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, size: DeviceSize, usage: BufferUsageFlags, memory_flags: MemoryPropertyFlags) -> VkResult<DeviceBuffer<'d, D>> {
+        let create_info = BufferCreateInfo {
+            s_type: StructureType::BufferCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            size: size,
+            usage: usage,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+        };
+        let buffer = safe_create::create_buffer_safe(device, &create_info, None)?;
+        let memory = allocator.allocate_buffer_memory(*buffer, memory_flags)?;
+        Ok(DeviceBuffer {
+            buffer: buffer,
+            memory: memory,
+            size: size,
+        })
+    }
+
+    /// A buffer the CPU can `write` to directly.
+    pub fn host_visible(device: &'d D, allocator: &'d MemoryAllocator<D>, size: DeviceSize, usage: BufferUsageFlags) -> VkResult<DeviceBuffer<'d, D>> {
+        DeviceBuffer::new(device, allocator, size, usage, MEMORY_PROPERTY_HOST_VISIBLE_BIT | MEMORY_PROPERTY_HOST_COHERENT_BIT)
+    }
+
+    /// A device-local buffer holding `data`, copied in from a staging buffer on `immediate`,
+    /// which blocks until the copy is done. `usage` doesn't need to include transfers.
+    pub fn device_local_with_data<T: Copy>(device: &'d D, allocator: &'d MemoryAllocator<D>, immediate: &ImmediateContext<D>, usage: BufferUsageFlags, data: &[T]) -> VkResult<DeviceBuffer<'d, D>> {
+        // Zero-sized buffers aren't allowed
+        let size = std::cmp::max(std::mem::size_of_val(data), 4) as DeviceSize;
+        let staging = DeviceBuffer::host_visible(device, allocator, size, BUFFER_USAGE_TRANSFER_SRC_BIT)?;
+        staging.write(0, data)?;
+        let ret = DeviceBuffer::new(device, allocator, size, usage | BUFFER_USAGE_TRANSFER_DST_BIT, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+        let region = BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size: size,
+        };
+        immediate.immediate_submit(|command_buffer| unsafe {
+            device.cmd_copy_buffer(command_buffer, *staging.buffer, *ret.buffer, &[region]);
+        })?;
+        trace!(target: logging::UPLOAD, "Uploaded {} bytes to {:?} through a staging buffer", size, *ret.buffer);
+        Ok(ret)
+    }
+
+    /// Copies `data` into the buffer at `offset`. The buffer must be host-visible, and the GPU
+    /// must not be using that part of it.
+    pub fn write<T: Copy>(&self, offset: DeviceSize, data: &[T]) -> VkResult<()> {
+        let size = std::mem::size_of_val(data) as DeviceSize;
+        assert!(offset + size <= self.size);
+        if size == 0 {
+            return Ok(());
+        }
+        self.memory.write(offset, data);
+        Ok(())
+    }
+
+    /// Reads `count` values of `T` at `offset`, with the same requirements as `write`. The GPU's
+    /// writes have to have been made visible to the host.
+    pub fn read<T: Copy>(&self, offset: DeviceSize, count: usize) -> Vec<T> {
+        self.memory.read(offset, count)
+    }
+
+    pub fn buffer(&self) -> Buffer {
+        *self.buffer
+    }
+
+    pub fn size(&self) -> DeviceSize {
+        self.size
+    }
+}
+
+/// Vertices in device-local memory, for data that doesn't change after upload. The pipeline's
+/// vertex input state has to match the vertex type it was created from, with the vertices in
+/// binding 0. Drawing it on its own draws the vertices in order; see `IndexedVertexBuffer` for
+/// drawing them through an `IndexBuffer`.
+pub struct VertexBuffer<'d, D: DeviceV1_0 + 'd> {
+    buffer: DeviceBuffer<'d, D>,
+    vertex_count: u32,
+}
+
+impl<'d, D: DeviceV1_0> VertexBuffer<'d, D> {
+    pub fn new<V: Copy>(device: &'d D, allocator: &'d MemoryAllocator<D>, immediate: &ImmediateContext<D>, vertices: &[V]) -> VkResult<VertexBuffer<'d, D>> {
+        let buffer = DeviceBuffer::device_local_with_data(device, allocator, immediate, BUFFER_USAGE_VERTEX_BUFFER_BIT, vertices)?;
+        Ok(VertexBuffer {
+            buffer: buffer,
+            vertex_count: vertices.len() as u32,
+        })
+    }
+
+    pub fn buffer(&self) -> Buffer {
+        self.buffer.buffer()
+    }
+
+    /// Binds the vertices to binding 0.
+    pub unsafe fn cmd_bind<E: DeviceV1_0>(&self, device: &E, command_buffer: CommandBuffer) {
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.buffer.buffer()], &[0]);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drawable for VertexBuffer<'d, D> {
+    unsafe fn cmd_draw<E: DeviceV1_0>(&self, device: &E, command_buffer: CommandBuffer) {
+        self.cmd_bind(device, command_buffer);
+        device.cmd_draw(command_buffer, self.vertex_count, 1, 0, 0);
+    }
+}
+
+/// The integer types Vulkan can take indices as.
+pub trait Index: Copy {
+    fn index_type() -> IndexType;
+}
+
+impl Index for u16 {
+    fn index_type() -> IndexType {
+        IndexType::Uint16
+    }
+}
+
+impl Index for u32 {
+    fn index_type() -> IndexType {
+        IndexType::Uint32
+    }
+}
+
+pub fn index_type_size(index_type: IndexType) -> usize {
+    match index_type {
+        IndexType::Uint16 => 2,
+        IndexType::Uint32 => 4,
+    }
+}
+
+/// Indices in device-local memory, as `u16` or `u32`, whichever they were uploaded as.
+pub struct IndexBuffer<'d, D: DeviceV1_0 + 'd> {
+    buffer: DeviceBuffer<'d, D>,
+    index_count: u32,
+    index_type: IndexType,
+}
+
+impl<'d, D: DeviceV1_0> IndexBuffer<'d, D> {
+    pub fn new<I: Index>(device: &'d D, allocator: &'d MemoryAllocator<D>, immediate: &ImmediateContext<D>, indices: &[I]) -> VkResult<IndexBuffer<'d, D>> {
+        let buffer = DeviceBuffer::device_local_with_data(device, allocator, immediate, BUFFER_USAGE_INDEX_BUFFER_BIT, indices)?;
+        Ok(IndexBuffer {
+            buffer: buffer,
+            index_count: indices.len() as u32,
+            index_type: I::index_type(),
+        })
+    }
+
+    pub fn buffer(&self) -> Buffer {
+        self.buffer.buffer()
+    }
+
+    pub unsafe fn cmd_bind<E: DeviceV1_0>(&self, device: &E, command_buffer: CommandBuffer) {
+        device.cmd_bind_index_buffer(command_buffer, self.buffer.buffer(), 0, self.index_type);
+    }
+}
+
+/// Vertices drawn through indices, so triangles can share vertices.
+pub struct IndexedVertexBuffer<'d, D: DeviceV1_0 + 'd> {
+    vertices: VertexBuffer<'d, D>,
+    indices: IndexBuffer<'d, D>,
+}
+
+impl<'d, D: DeviceV1_0> IndexedVertexBuffer<'d, D> {
+    pub fn new<V: Copy, I: Index>(device: &'d D, allocator: &'d MemoryAllocator<D>, immediate: &ImmediateContext<D>, vertices: &[V], indices: &[I]) -> VkResult<IndexedVertexBuffer<'d, D>> {
+        Ok(IndexedVertexBuffer {
+            vertices: VertexBuffer::new(device, allocator, immediate, vertices)?,
+            indices: IndexBuffer::new(device, allocator, immediate, indices)?,
+        })
+    }
+
+    pub fn vertices(&self) -> &VertexBuffer<'d, D> {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &IndexBuffer<'d, D> {
+        &self.indices
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drawable for IndexedVertexBuffer<'d, D> {
+    unsafe fn cmd_draw<E: DeviceV1_0>(&self, device: &E, command_buffer: CommandBuffer) {
+        self.vertices.cmd_bind(device, command_buffer);
+        self.indices.cmd_bind(device, command_buffer);
+        device.cmd_draw_indexed(command_buffer, self.indices.index_count, 1, 0, 0, 0);
+    }
+}
@@ -0,0 +1,180 @@
+//! The scene camera, and fly controls for moving it around: WASD to move, E and Q to go up and
+//! down, shift to move faster, and dragging with the right mouse button held to look around.
+//! Input comes in as GLFW window events, so the window needs key, cursor position and mouse
+//! button polling on.
+use glfw;
+use std;
+use ::camera_path::CameraPose;
+use ::math::{ Mat4, Vec3 };
+use ::math::prelude::clamp;
+
+/// Just short of straight up or down, where looking along `up` breaks the view matrix.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 * 0.99;
+/// How much faster the camera moves with shift held.
+const FAST_MULTIPLIER: f32 = 4.0;
+
+/// Units per second.
+pub fn speed() -> f32 {
+    cvar!("camera.speed", 2.0).get()
+}
+
+/// Radians per pixel of mouse movement.
+pub fn sensitivity() -> f32 {
+    cvar!("camera.sensitivity", 0.003).get()
+}
+
+/// A perspective camera. With no yaw or pitch it looks down -Z, with +Y up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    /// Radians to the right, around +Y.
+    pub yaw: f32,
+    /// Radians up, within `MAX_PITCH` of level.
+    pub pitch: f32,
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    /// Two units back from the origin, looking at it.
+    fn default() -> Camera {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 2.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_y: std::f32::consts::PI / 3.0,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+}
+
+impl Camera {
+    /// Moves to `eye` and turns to face `target`.
+    pub fn look_at(&mut self, eye: Vec3, target: Vec3) {
+        self.position = eye;
+        let direction = (target - eye).normalize();
+        self.yaw = direction.x.atan2(-direction.z);
+        self.pitch = clamp(clamp(direction.y, -1.0, 1.0).asin(), -MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.look_at(pose.eye, pose.target);
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            eye: self.position,
+            target: self.position + self.forward(),
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(self.pitch.cos() * self.yaw.sin(), self.pitch.sin(), -self.pitch.cos() * self.yaw.cos())
+    }
+
+    /// Level with the ground, whatever the pitch.
+    pub fn right(&self) -> Vec3 {
+        Vec3::new(self.yaw.cos(), 0.0, self.yaw.sin())
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at(self.position, self.position + self.forward(), Vec3::unit_y())
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Mat4 {
+        Mat4::perspective(self.fov_y, aspect, self.near, self.far)
+    }
+
+    /// Turns by the given radians, keeping the pitch short of straight up or down.
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = (self.yaw + yaw) % (2.0 * std::f32::consts::PI);
+        self.pitch = clamp(self.pitch + pitch, -MAX_PITCH, MAX_PITCH);
+    }
+}
+
+/// Keyboard and mouse state for flying a `Camera` around.
+#[derive(Debug, Clone, Default)]
+pub struct FlyControls {
+    forward: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    fast: bool,
+    /// Whether the look button is held.
+    looking: bool,
+    last_cursor: Option<(f64, f64)>,
+    /// Cursor movement since the last `update`, in pixels.
+    look_delta: (f64, f64),
+}
+
+impl FlyControls {
+    /// Updates the controls from `event`, returning whether it was one they use.
+    pub fn handle_event(&mut self, event: &glfw::WindowEvent) -> bool {
+        match *event {
+            glfw::WindowEvent::Key(key, _, action, _) => {
+                let held = match action {
+                    glfw::Action::Press => true,
+                    glfw::Action::Release => false,
+                    glfw::Action::Repeat => return self.key_flag(key).is_some(),
+                };
+                match self.key_flag(key) {
+                    Some(flag) => {
+                        *flag = held;
+                        true
+                    },
+                    None => false,
+                }
+            },
+            glfw::WindowEvent::MouseButton(glfw::MouseButtonRight, action, _) => {
+                self.looking = action != glfw::Action::Release;
+                self.last_cursor = None;
+                true
+            },
+            glfw::WindowEvent::CursorPos(x, y) if self.looking => {
+                if let Some((last_x, last_y)) = self.last_cursor {
+                    self.look_delta.0 += x - last_x;
+                    self.look_delta.1 += y - last_y;
+                }
+                self.last_cursor = Some((x, y));
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn key_flag(&mut self, key: glfw::Key) -> Option<&mut bool> {
+        match key {
+            glfw::Key::W => Some(&mut self.forward),
+            glfw::Key::S => Some(&mut self.back),
+            glfw::Key::A => Some(&mut self.left),
+            glfw::Key::D => Some(&mut self.right),
+            glfw::Key::E => Some(&mut self.up),
+            glfw::Key::Q => Some(&mut self.down),
+            glfw::Key::LeftShift | glfw::Key::RightShift => Some(&mut self.fast),
+            _ => None,
+        }
+    }
+
+    /// Lets go of every key and button, e.g. when input goes somewhere else.
+    pub fn release_all(&mut self) {
+        *self = FlyControls::default();
+    }
+
+    /// Moves and turns `camera` for `delta` seconds of the current input.
+    pub fn update(&mut self, camera: &mut Camera, delta: f32) {
+        let sensitivity = sensitivity();
+        camera.rotate(self.look_delta.0 as f32 * sensitivity, -self.look_delta.1 as f32 * sensitivity);
+        self.look_delta = (0.0, 0.0);
+
+        let axis = |positive: bool, negative: bool| (positive as i32 - negative as i32) as f32;
+        let direction = camera.forward() * axis(self.forward, self.back) + camera.right() * axis(self.right, self.left) + Vec3::unit_y() * axis(self.up, self.down);
+        if direction.length() > 0.0 {
+            let speed = speed() * if self.fast { FAST_MULTIPLIER } else { 1.0 };
+            camera.position = camera.position + direction.normalize() * (speed * delta);
+        }
+    }
+}
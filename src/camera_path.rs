@@ -0,0 +1,411 @@
+//! Scripted camera paths, so runs that measure or capture something see the same views every
+//! time. A path is a list of keyframes, each a time, an eye position and a point to look at, and
+//! the camera follows a Catmull-Rom spline through them.
+//!
+//! Paths are stored as text, one keyframe per line as `<seconds> <eye x y z> <target x y z>`, in
+//! increasing time order. `loop` on a line of its own makes playback wrap around, and `#` starts a
+//! comment.
+//!
+//! Playback is started with `--camera-path=<file>`. It follows scaled frame time by default;
+//! `--camera-path-step=<seconds>` advances it by a fixed step every frame instead, so each frame
+//! shows the same view no matter how long it took to render.
+//!
+//! Paths are recorded by flying the camera around and pressing `RECORD_KEY` at each keyframe,
+//! which rewrites `RECORDING_FILE` with the path so far.
+use glfw;
+use std;
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+use ::cvar::Flag;
+use ::math::Vec3;
+use ::math::prelude::clamp;
+use ::time::Time;
+
+pub const PATH_ARG_PREFIX: &'static str = "--camera-path=";
+pub const STEP_ARG_PREFIX: &'static str = "--camera-path-step=";
+pub const PATH_CVAR: &'static str = "camera_path.file";
+pub const STEP_CVAR: &'static str = "camera_path.step";
+pub const FLAGS: &'static [Flag] = &[
+    Flag::Value { prefix: PATH_ARG_PREFIX, name: PATH_CVAR },
+    Flag::Value { prefix: STEP_ARG_PREFIX, name: STEP_CVAR },
+];
+
+/// Adds the camera's pose to the path being recorded.
+pub const RECORD_KEY: glfw::Key = glfw::Key::Insert;
+/// Where the path being recorded is saved, relative to the working directory.
+pub const RECORDING_FILE: &'static str = "camera_path.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraPose {
+    pub eye: Vec3,
+    pub target: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub pose: CameraPose,
+}
+
+#[derive(Debug)]
+pub enum PathError {
+    Io(io::Error),
+    /// A line that couldn't be parsed, numbered from 1.
+    Parse(usize, String),
+    Empty,
+}
+
+impl From<io::Error> for PathError {
+    fn from(e: io::Error) -> PathError {
+        PathError::Io(e)
+    }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathError::Io(ref e) => write!(f, "{}", e),
+            PathError::Parse(line, ref what) => write!(f, "line {}: {}", line, what),
+            PathError::Empty => write!(f, "no keyframes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    looping: bool,
+}
+
+/// Uniform Catmull-Rom between `p1` and `p2` at `t` in `[0, 1]`.
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+impl CameraPath {
+    pub fn new(looping: bool) -> CameraPath {
+        CameraPath {
+            keyframes: Vec::new(),
+            looping: looping,
+        }
+    }
+
+    /// Adds a keyframe, keeping them in time order.
+    pub fn add_keyframe(&mut self, keyframe: Keyframe) {
+        let idx = self.keyframes.iter().position(|k| k.time > keyframe.time).unwrap_or(self.keyframes.len());
+        self.keyframes.insert(idx, keyframe);
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|k| k.time).unwrap_or(0.0)
+    }
+
+    /// The pose `time` seconds in. Outside the keyframes' times it holds the first or last pose,
+    /// unless the path loops.
+    pub fn sample(&self, time: f32) -> Option<CameraPose> {
+        let count = self.keyframes.len();
+        if count == 0 {
+            return None;
+        }
+        let duration = self.duration();
+        let time = if self.looping && duration > 0.0 {
+            let wrapped = time % duration;
+            if wrapped < 0.0 { wrapped + duration } else { wrapped }
+        } else {
+            time
+        };
+        let next = match self.keyframes.iter().position(|k| k.time > time) {
+            Some(0) => return Some(self.keyframes[0].pose),
+            Some(idx) => idx,
+            None => return Some(self.keyframes[count - 1].pose),
+        };
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        // The ends repeat themselves as outer control points, unless the path wraps around
+        let control = |idx: isize| -> CameraPose {
+            let idx = if self.looping {
+                ((idx + count as isize) % count as isize) as usize
+            } else {
+                clamp(idx, 0, count as isize - 1) as usize
+            };
+            self.keyframes[idx].pose
+        };
+        let (p0, p1, p2, p3) = (control(next as isize - 2), a.pose, b.pose, control(next as isize + 1));
+        Some(CameraPose {
+            eye: catmull_rom(p0.eye, p1.eye, p2.eye, p3.eye, t),
+            target: catmull_rom(p0.target, p1.target, p2.target, p3.target, t),
+        })
+    }
+
+    pub fn parse(text: &str) -> std::result::Result<CameraPath, PathError> {
+        let mut ret = CameraPath::new(false);
+        let mut last_time = None;
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line == "loop" {
+                ret.looping = true;
+                continue;
+            }
+            let values: Vec<f32> = (line.split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<std::result::Result<Vec<f32>, _>>()
+                .map_err(|e| PathError::Parse(idx + 1, format!("{}", e))))?;
+            if values.len() != 7 {
+                return Err(PathError::Parse(idx + 1, format!("expected 7 numbers, found {}", values.len())));
+            }
+            if last_time.is_some_and(|last| values[0] <= last) {
+                return Err(PathError::Parse(idx + 1, "keyframe times must increase".to_string()));
+            }
+            last_time = Some(values[0]);
+            ret.keyframes.push(Keyframe {
+                time: values[0],
+                pose: CameraPose {
+                    eye: Vec3::new(values[1], values[2], values[3]),
+                    target: Vec3::new(values[4], values[5], values[6]),
+                },
+            });
+        }
+        if ret.keyframes.is_empty() {
+            return Err(PathError::Empty);
+        }
+        Ok(ret)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::result::Result<CameraPath, PathError> {
+        let data = ::read_full_file(&path.as_ref().to_string_lossy())?;
+        CameraPath::parse(&String::from_utf8_lossy(&data))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(self.to_string().as_bytes())
+    }
+}
+
+impl fmt::Display for CameraPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# time eye.x eye.y eye.z target.x target.y target.z")?;
+        if self.looping {
+            writeln!(f, "loop")?;
+        }
+        for k in self.keyframes.iter() {
+            let (eye, target) = (k.pose.eye, k.pose.target);
+            writeln!(f, "{} {} {} {} {} {} {}", k.time, eye.x, eye.y, eye.z, target.x, target.y, target.z)?;
+        }
+        Ok(())
+    }
+}
+
+/// What to play back, from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackConfig {
+    pub path: PathBuf,
+    /// Seconds to advance every frame, or `None` to follow frame time.
+    pub fixed_step: Option<f32>,
+}
+
+impl PlaybackConfig {
+    /// `None` without a `camera_path.file`, which `--camera-path=<file>` sets. A
+    /// `camera_path.step` of 0 follows frame time.
+    pub fn from_cvars() -> Option<PlaybackConfig> {
+        let path: String = cvar!(PATH_CVAR, String::new()).get();
+        let step: f32 = cvar!(STEP_CVAR, 0.0).get();
+        if step < 0.0 {
+            warn!("Camera path step has to be positive, not {}", step);
+        }
+        if path.is_empty() {
+            return None;
+        }
+        Some(PlaybackConfig {
+            path: PathBuf::from(path),
+            fixed_step: if step > 0.0 { Some(step) } else { None },
+        })
+    }
+}
+
+/// A path being played back.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    path: CameraPath,
+    fixed_step: Option<f32>,
+    time: f32,
+    frames: u64,
+    finished: bool,
+}
+
+impl Playback {
+    pub fn new(path: CameraPath, fixed_step: Option<f32>) -> Playback {
+        Playback {
+            path: path,
+            fixed_step: fixed_step,
+            time: 0.0,
+            frames: 0,
+            finished: false,
+        }
+    }
+
+    pub fn load(config: &PlaybackConfig) -> std::result::Result<Playback, PathError> {
+        let path = CameraPath::load(&config.path)?;
+        info!("Playing camera path {:?}: {} keyframes over {:.2} s{}", config.path, path.keyframes().len(), path.duration(), if path.is_looping() { ", looping" } else { "" });
+        Ok(Playback::new(path, config.fixed_step))
+    }
+
+    /// The pose for this frame, then moves on by the fixed step or `time`'s delta. Once a
+    /// non-looping path is over, it stays on its last pose.
+    pub fn advance(&mut self, time: &Time) -> CameraPose {
+        let pose = self.pose();
+        self.time += self.fixed_step.unwrap_or(time.delta());
+        self.frames += 1;
+        if !self.finished && !self.path.is_looping() && self.time > self.path.duration() {
+            self.finished = true;
+            info!("Camera path finished after {} frames", self.frames);
+        }
+        pose
+    }
+
+    /// The pose for this frame, without moving on.
+    pub fn pose(&self) -> CameraPose {
+        self.path.sample(self.time).expect("Camera paths have keyframes")
+    }
+
+}
+
+/// A path being recorded, a keyframe at a time.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    path: CameraPath,
+    /// Scene time of the first keyframe, which the path starts at.
+    start: f64,
+}
+
+impl Recording {
+    /// Adds `pose` at `time`'s scene time and saves the path to `RECORDING_FILE`, returning how
+    /// many keyframes it has. Keyframes added with the scene paused are a second apart, since
+    /// their times have to increase.
+    pub fn add(&mut self, pose: CameraPose, time: &Time) -> io::Result<usize> {
+        let seconds = match self.path.keyframes().last() {
+            Some(last) => ((time.total() - self.start) as f32).max(last.time + 1.0),
+            None => {
+                self.start = time.total();
+                0.0
+            },
+        };
+        self.path.add_keyframe(Keyframe {
+            time: seconds,
+            pose: pose,
+        });
+        self.path.save(RECORDING_FILE)?;
+        Ok(self.path.keyframes().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &'static str = "# A square around the origin\n\
+                                0 1 0 0  0 0 0\n\
+                                1 0 0 1  0 0 0 # halfway\n\
+                                2 -1 0 0  0 0 0\n";
+
+    fn eye(x: f32, y: f32, z: f32) -> CameraPose {
+        CameraPose {
+            eye: Vec3::new(x, y, z),
+            target: Vec3::zero(),
+        }
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        let path = CameraPath::parse(PATH).unwrap();
+        assert!(!path.is_looping());
+        assert_eq!(path.keyframes().len(), 3);
+        assert_eq!(path.keyframes()[1], Keyframe { time: 1.0, pose: eye(0.0, 0.0, 1.0) });
+        assert_eq!(path.duration(), 2.0);
+
+        let looping = CameraPath::parse(&format!("loop\n{}", PATH)).unwrap();
+        assert!(looping.is_looping());
+        let reparsed = CameraPath::parse(&looping.to_string()).unwrap();
+        assert!(reparsed.is_looping());
+        assert_eq!(reparsed.keyframes(), looping.keyframes());
+    }
+
+    #[test]
+    fn invalid_paths_say_which_line() {
+        match CameraPath::parse("0 0 0 0 0 0 0\n\n0.5 1 2 3") {
+            Err(PathError::Parse(3, _)) => {},
+            other => panic!("{:?}", other),
+        }
+        match CameraPath::parse("1 0 0 0 0 0 0\n1 0 0 0 0 0 0") {
+            Err(PathError::Parse(2, _)) => {},
+            other => panic!("{:?}", other),
+        }
+        match CameraPath::parse("0 0 0 0 x 0 0") {
+            Err(PathError::Parse(1, _)) => {},
+            other => panic!("{:?}", other),
+        }
+        match CameraPath::parse("# nothing\nloop\n") {
+            Err(PathError::Empty) => {},
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn samples_pass_through_keyframes_and_hold_at_the_ends() {
+        let path = CameraPath::parse(PATH).unwrap();
+        assert_eq!(CameraPath::new(false).sample(0.0), None);
+        assert_eq!(path.sample(-1.0), Some(eye(1.0, 0.0, 0.0)));
+        assert_eq!(path.sample(1.0), Some(eye(0.0, 0.0, 1.0)));
+        assert_eq!(path.sample(5.0), Some(eye(-1.0, 0.0, 0.0)));
+        // The spline bulges out past the straight line between keyframes
+        let between = path.sample(0.5).unwrap().eye;
+        assert!(between.x > 0.0 && between.z > 0.5 && between.y == 0.0, "{:?}", between);
+    }
+
+    #[test]
+    fn looping_paths_wrap_around() {
+        let path = CameraPath::parse(&format!("loop\n{}", PATH)).unwrap();
+        let (a, b) = (path.sample(0.5).unwrap().eye, path.sample(2.5).unwrap().eye);
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+        let (a, b) = (path.sample(1.5).unwrap().eye, path.sample(-0.5).unwrap().eye);
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn add_keyframe_keeps_time_order() {
+        let mut path = CameraPath::new(false);
+        for &time in [2.0, 0.0, 1.0].iter() {
+            path.add_keyframe(Keyframe { time: time, pose: eye(time, 0.0, 0.0) });
+        }
+        assert_eq!(path.keyframes().iter().map(|k| k.time).collect::<Vec<f32>>(), vec![0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn fixed_step_playback_holds_the_last_pose() {
+        let time = Time::new();
+        let path = CameraPath::parse(PATH).unwrap();
+        let mut playback = Playback::new(path.clone(), Some(0.75));
+        assert_eq!(playback.advance(&time), eye(1.0, 0.0, 0.0));
+        assert_eq!(Some(playback.pose()), path.sample(0.75));
+        for _ in 0..3 {
+            playback.advance(&time);
+        }
+        assert_eq!(playback.pose(), eye(-1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,375 @@
+//! What each demo needs from the device, checked once against the physical device so demos it
+//! can't run are listed as unavailable, along with what's missing, instead of failing at
+//! pipeline creation. Requirements are features, device extensions, limits, format support, and
+//! subgroup operations; the features of every available demo get enabled on the device.
+use ash::version::{ EntryV1_0, InstanceV1_0 };
+use glfw;
+use std::ffi::CStr;
+use vk::types::*;
+use ::debug_view;
+use ::format_support::{ FormatSupport, FormatUsage };
+use ::subgroup::SubgroupProperties;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    GeometryShader,
+    LargePoints,
+    WideLines,
+    FillModeNonSolid,
+    SamplerAnisotropy,
+    ShaderFloat64,
+    FragmentStoresAndAtomics,
+}
+
+impl Feature {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Feature::GeometryShader => "geometryShader",
+            Feature::LargePoints => "largePoints",
+            Feature::WideLines => "wideLines",
+            Feature::FillModeNonSolid => "fillModeNonSolid",
+            Feature::SamplerAnisotropy => "samplerAnisotropy",
+            Feature::ShaderFloat64 => "shaderFloat64",
+            Feature::FragmentStoresAndAtomics => "fragmentStoresAndAtomics",
+        }
+    }
+
+    fn field<'a>(&self, features: &'a mut PhysicalDeviceFeatures) -> &'a mut Bool32 {
+        match *self {
+            Feature::GeometryShader => &mut features.geometry_shader,
+            Feature::LargePoints => &mut features.large_points,
+            Feature::WideLines => &mut features.wide_lines,
+            Feature::FillModeNonSolid => &mut features.fill_mode_non_solid,
+            Feature::SamplerAnisotropy => &mut features.sampler_anisotropy,
+            Feature::ShaderFloat64 => &mut features.shader_float64,
+            Feature::FragmentStoresAndAtomics => &mut features.fragment_stores_and_atomics,
+        }
+    }
+
+    pub fn is_supported(&self, features: &PhysicalDeviceFeatures) -> bool {
+        let mut features = features.clone();
+        *self.field(&mut features) != 0
+    }
+
+    pub fn enable(&self, features: &mut PhysicalDeviceFeatures) {
+        *self.field(features) = true as Bool32;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    MaxImageDimension2D,
+    MaxImageDimension3D,
+    MaxImageArrayLayers,
+    MaxComputeSharedMemorySize,
+    MaxComputeWorkGroupInvocations,
+    MaxPushConstantsSize,
+}
+
+impl Limit {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Limit::MaxImageDimension2D => "maxImageDimension2D",
+            Limit::MaxImageDimension3D => "maxImageDimension3D",
+            Limit::MaxImageArrayLayers => "maxImageArrayLayers",
+            Limit::MaxComputeSharedMemorySize => "maxComputeSharedMemorySize",
+            Limit::MaxComputeWorkGroupInvocations => "maxComputeWorkGroupInvocations",
+            Limit::MaxPushConstantsSize => "maxPushConstantsSize",
+        }
+    }
+
+    pub fn value(&self, limits: &PhysicalDeviceLimits) -> u32 {
+        match *self {
+            Limit::MaxImageDimension2D => limits.max_image_dimension2d,
+            Limit::MaxImageDimension3D => limits.max_image_dimension3d,
+            Limit::MaxImageArrayLayers => limits.max_image_array_layers,
+            Limit::MaxComputeSharedMemorySize => limits.max_compute_shared_memory_size,
+            Limit::MaxComputeWorkGroupInvocations => limits.max_compute_work_group_invocations,
+            Limit::MaxPushConstantsSize => limits.max_push_constants_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requirement {
+    Feature(Feature),
+    Extension(&'static str),
+    /// The limit must be at least this.
+    Limit(Limit, u32),
+    /// The format supports the usage with optimal tiling.
+    Format(Format, FormatUsage),
+    /// `subgroupAdd` and friends in compute shaders.
+    SubgroupArithmetic,
+    /// Built with this cargo feature, see `is_built_with`.
+    CargoFeature(&'static str),
+}
+
+impl Requirement {
+    /// What's missing, or `None` if `capabilities` meet the requirement.
+    pub fn missing(&self, capabilities: &DeviceCapabilities) -> Option<String> {
+        match *self {
+            Requirement::Feature(feature) => {
+                if feature.is_supported(&capabilities.features) {
+                    None
+                } else {
+                    Some(format!("feature {}", feature.name()))
+                }
+            },
+            Requirement::Extension(name) => {
+                if capabilities.extensions.iter().any(|e| e == name) {
+                    None
+                } else {
+                    Some(format!("extension {}", name))
+                }
+            },
+            Requirement::Limit(limit, min) => {
+                let value = limit.value(&capabilities.properties.limits);
+                if value >= min {
+                    None
+                } else {
+                    Some(format!("{} of at least {} (device has {})", limit.name(), min, value))
+                }
+            },
+            Requirement::Format(format, usage) => {
+                match capabilities.formats.iter().find(|support| support.format == format) {
+                    Some(support) if support.supports(ImageTiling::Optimal, usage) => None,
+                    Some(support) => Some(format!("{:?} support for {:?} (only {:?})", usage, format, support.usages(ImageTiling::Optimal))),
+                    None => Some(format!("{:?} support for {:?}", usage, format)),
+                }
+            },
+            Requirement::SubgroupArithmetic => {
+                if capabilities.subgroup.map(|s| s.supports_compute_arithmetic()).unwrap_or(false) {
+                    None
+                } else {
+                    Some("subgroup arithmetic in compute shaders".to_string())
+                }
+            },
+            Requirement::CargoFeature(name) => {
+                if is_built_with(name) {
+                    None
+                } else {
+                    Some(format!("a build with the {} feature", name))
+                }
+            },
+        }
+    }
+}
+
+/// Whether the cargo feature called `name` was on. Only knows the features in `Cargo.toml`.
+fn is_built_with(name: &str) -> bool {
+    match name {
+        "physics" => cfg!(feature = "physics"),
+        _ => false,
+    }
+}
+
+/// Everything requirements are checked against, queried once per physical device.
+pub struct DeviceCapabilities {
+    pub properties: PhysicalDeviceProperties,
+    pub features: PhysicalDeviceFeatures,
+    pub extensions: Vec<String>,
+    /// Support for every format named by a `Requirement::Format` of `demos`.
+    pub formats: Vec<FormatSupport>,
+    pub subgroup: Option<SubgroupProperties>,
+}
+
+impl DeviceCapabilities {
+    pub fn query<E: EntryV1_0, I: InstanceV1_0>(entry: &E, instance: &I, physical_device: PhysicalDevice, demos: &[Demo]) -> DeviceCapabilities {
+        let extensions = instance.enumerate_device_extension_properties(physical_device)
+            .unwrap_or(vec![])
+            .iter()
+            .map(|properties| unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) }.to_string_lossy().into_owned())
+            .collect();
+        let mut formats: Vec<Format> = vec![];
+        for requirement in demos.iter().flat_map(|demo| demo.requirements.iter()) {
+            if let Requirement::Format(format, _) = *requirement {
+                if !formats.contains(&format) {
+                    formats.push(format);
+                }
+            }
+        }
+        DeviceCapabilities {
+            properties: instance.get_physical_device_properties(physical_device),
+            features: instance.get_physical_device_features(physical_device),
+            extensions: extensions,
+            formats: formats.into_iter().map(|format| FormatSupport::query(instance, physical_device, format)).collect(),
+            subgroup: SubgroupProperties::query(entry, instance, physical_device),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Demo {
+    pub name: &'static str,
+    pub requirements: &'static [Requirement],
+}
+
+/// Every demo, in selector order.
+pub const DEMOS: &'static [Demo] = &[
+    Demo { name: "Mesh", requirements: &[] },
+    Demo { name: "Primitives", requirements: &[] },
+    Demo { name: "Wireframe", requirements: &[Requirement::Feature(Feature::FillModeNonSolid), Requirement::Feature(Feature::WideLines)] },
+    Demo { name: "Vertex pulling", requirements: &[] },
+    Demo { name: "Vertex quantization", requirements: &[Requirement::Format(Format::R16g16b16a16Snorm, FormatUsage::VertexBuffer), Requirement::Format(Format::R16g16Snorm, FormatUsage::VertexBuffer)] },
+    Demo { name: "Checkerboard", requirements: &[] },
+    Demo { name: "Debug views", requirements: &[Requirement::Limit(Limit::MaxPushConstantsSize, debug_view::PUSH_CONSTANTS_SIZE)] },
+    Demo { name: "Signed distance fields", requirements: &[] },
+    Demo { name: "Deep zoom", requirements: &[Requirement::Feature(Feature::ShaderFloat64), Requirement::Feature(Feature::FragmentStoresAndAtomics)] },
+    Demo { name: "Cellular automata", requirements: &[Requirement::Format(Format::R8g8b8a8Unorm, FormatUsage::Storage)] },
+    Demo { name: "Compute gradient", requirements: &[Requirement::Format(Format::R8g8b8a8Unorm, FormatUsage::Storage), Requirement::Format(Format::R8g8b8a8Unorm, FormatUsage::BlitSrc)] },
+    Demo { name: "Normal visualization", requirements: &[Requirement::Feature(Feature::GeometryShader)] },
+    Demo { name: "Point cloud", requirements: &[Requirement::Feature(Feature::LargePoints), Requirement::Format(Format::R8g8b8a8Unorm, FormatUsage::VertexBuffer)] },
+    Demo { name: "Flow field", requirements: &[Requirement::Limit(Limit::MaxImageDimension3D, 64), Requirement::Format(Format::R16g16b16a16Sfloat, FormatUsage::Storage)] },
+    Demo { name: "Volumetric fog", requirements: &[Requirement::Limit(Limit::MaxImageDimension3D, 128), Requirement::Format(Format::R16g16b16a16Sfloat, FormatUsage::Storage), Requirement::Format(Format::R16g16b16a16Sfloat, FormatUsage::SampledLinear)] },
+    Demo { name: "Ambient occlusion", requirements: &[Requirement::Format(Format::R32Sfloat, FormatUsage::Storage), Requirement::Format(Format::R16g16b16a16Sfloat, FormatUsage::Storage)] },
+    Demo { name: "Noise textures", requirements: &[Requirement::Format(Format::R32Sfloat, FormatUsage::Storage)] },
+    Demo { name: "N-body", requirements: &[Requirement::Limit(Limit::MaxComputeWorkGroupInvocations, 256), Requirement::Limit(Limit::MaxComputeSharedMemorySize, 4096)] },
+    Demo { name: "Radix sort", requirements: &[Requirement::Limit(Limit::MaxComputeWorkGroupInvocations, 256)] },
+    Demo { name: "Subgroup reduction", requirements: &[Requirement::SubgroupArithmetic] },
+    Demo { name: "Shadow atlas", requirements: &[Requirement::Limit(Limit::MaxImageDimension2D, 8192)] },
+    Demo { name: "Texture array", requirements: &[Requirement::Limit(Limit::MaxImageArrayLayers, 256)] },
+    Demo { name: "Anisotropic filtering", requirements: &[Requirement::Feature(Feature::SamplerAnisotropy)] },
+    Demo { name: "Pipeline library", requirements: &[Requirement::Extension("VK_KHR_pipeline_library"), Requirement::Extension("VK_EXT_graphics_pipeline_library")] },
+    Demo { name: "Box drop", requirements: &[Requirement::CargoFeature("physics")] },
+];
+
+struct Entry {
+    demo: Demo,
+    missing: Vec<String>,
+}
+
+impl Entry {
+    fn is_available(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// The demo list with each demo's availability on one device. Unavailable demos stay in the list,
+/// but can't be selected.
+pub struct DemoSelector {
+    entries: Vec<Entry>,
+    selected: Option<usize>,
+}
+
+impl DemoSelector {
+    pub fn new(demos: &[Demo], capabilities: &DeviceCapabilities) -> DemoSelector {
+        let entries: Vec<Entry> = demos.iter()
+            .map(|demo| Entry {
+                demo: *demo,
+                missing: demo.requirements.iter().filter_map(|r| r.missing(capabilities)).collect(),
+            })
+            .collect();
+        let selected = entries.iter().position(|e| e.is_available());
+        DemoSelector {
+            entries: entries,
+            selected: selected,
+        }
+    }
+
+    /// The selected demo, or `None` if the device can't run any of them.
+    pub fn selected(&self) -> Option<&Demo> {
+        self.selected.map(|i| &self.entries[i].demo)
+    }
+
+    pub fn is_available(&self, name: &str) -> bool {
+        self.entries.iter().any(|e| e.demo.name == name && e.is_available())
+    }
+
+    /// What keeps `name` from running. Empty for available or unknown demos.
+    pub fn missing(&self, name: &str) -> &[String] {
+        self.entries.iter()
+            .find(|e| e.demo.name == name)
+            .map(|e| &e.missing[..])
+            .unwrap_or(&[])
+    }
+
+    /// Features every available demo needs enabled at device creation.
+    pub fn enable_features(&self, features: &mut PhysicalDeviceFeatures) {
+        for entry in self.entries.iter().filter(|e| e.is_available()) {
+            for requirement in entry.demo.requirements {
+                if let Requirement::Feature(feature) = *requirement {
+                    feature.enable(features);
+                }
+            }
+        }
+    }
+
+    /// The extensions the available demos need, to enable on the device.
+    pub fn extensions(&self) -> Vec<&'static str> {
+        let mut ret: Vec<&'static str> = Vec::new();
+        for entry in self.entries.iter().filter(|e| e.is_available()) {
+            for requirement in entry.demo.requirements {
+                if let Requirement::Extension(name) = *requirement {
+                    if !ret.contains(&name) {
+                        ret.push(name);
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// Selects `name`, if it's available.
+    pub fn select(&mut self, name: &str) {
+        if let Some(index) = self.entries.iter().position(|e| e.demo.name == name && e.is_available()) {
+            self.selected = Some(index);
+        }
+    }
+
+    /// Moves to the next available demo, wrapping around.
+    pub fn select_next(&mut self) {
+        let start = match self.selected {
+            Some(i) => i,
+            None => return,
+        };
+        let count = self.entries.len();
+        self.selected = (1..count + 1)
+            .map(|offset| (start + offset) % count)
+            .find(|&i| self.entries[i].is_available());
+        if let Some(demo) = self.selected() {
+            info!("Selected demo: {}", demo.name);
+        }
+    }
+
+    /// Marks `name` unavailable after the fact, e.g. when its shaders fail to load, and moves on
+    /// to the next available demo if it was selected.
+    pub fn mark_unavailable(&mut self, name: &str, missing: String) {
+        let index = match self.entries.iter().position(|e| e.demo.name == name) {
+            Some(index) => index,
+            None => return,
+        };
+        self.entries[index].missing.push(missing);
+        if self.selected == Some(index) {
+            self.select_next();
+        }
+    }
+
+    /// Cycles the demo on Tab. Returns whether the key was handled.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != glfw::Key::Tab {
+            return false;
+        }
+        self.select_next();
+        true
+    }
+
+    /// The whole list as text, one line per demo with whether it's available. The selected demo
+    /// is marked, and unavailable ones list what they're missing.
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        self.entries.iter().enumerate().map(|(i, entry)| {
+            let marker = if Some(i) == self.selected { ">" } else { " " };
+            if entry.is_available() {
+                (format!("{} {}", marker, entry.demo.name), true)
+            } else {
+                (format!("  ({}: unavailable, needs {})", entry.demo.name, entry.missing.join(", ")), false)
+            }
+        }).collect()
+    }
+
+    /// Logs the whole list, unavailable demos with what they're missing.
+    pub fn log(&self) {
+        for (line, _) in self.lines() {
+            info!("{}", line);
+        }
+    }
+}
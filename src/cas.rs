@@ -0,0 +1,313 @@
+//! Contrast-adaptive sharpening, after AMD's FidelityFX CAS, as a post pass to win back detail
+//! lost to upscaling or TAA. A compute pass (`shaders/cas.glsl`) reads the image through a
+//! sampler and writes the sharpened result into a storage image of the same size, which later
+//! passes sample instead. Its strength comes from the overlay, see `Overlay::handle_key`.
+//!
+//! In the frame, a `CasPass` sharpens the output filter's target after the final image is drawn
+//! into it, and the filter pass samples the sharpened image instead (see `output_filter`).
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::ComputeKernel;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::math::prelude::clamp;
+use ::rect::ExtentExt;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const SHADER: &'static str = "shaders/cas.comp.spv";
+
+/// Binding of the image to sharpen, read through a sampler.
+pub const SOURCE_BINDING: u32 = 0;
+/// Binding of the sharpened output, a storage image.
+pub const OUTPUT_BINDING: u32 = 1;
+
+/// Invocations per axis of a `shaders/cas.glsl` workgroup.
+const LOCAL_SIZE: u32 = 8;
+
+/// Matches the `rgba16f` qualifier in `shaders/cas.glsl`.
+const OUTPUT_FORMAT: Format = Format::R16g16b16a16Sfloat;
+
+/// Push constant block read by `shaders/cas.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CasPushConstants {
+    /// From 0, the mildest, to 1, the strongest.
+    pub sharpness: f32,
+}
+
+impl CasPushConstants {
+    pub fn new(sharpness: f32) -> CasPushConstants {
+        CasPushConstants {
+            sharpness: clamp(sharpness, 0.0, 1.0),
+        }
+    }
+}
+
+pub struct Cas<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+    sampler: Sampler,
+    extent: Extent2D,
+}
+
+impl<'d, D: DeviceV1_0> Cas<'d, D> {
+    /// Creates the output for sharpening `extent` sized images. Sized to the image it sharpens,
+    /// so it's recreated along with it.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, extent: Extent2D) -> VkResult<Cas<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: OUTPUT_FORMAT,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_STORAGE_BIT | IMAGE_USAGE_SAMPLED_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+        let mut ret = Cas {
+            device: device,
+            image: image,
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+            sampler: Sampler::null(),
+            extent: extent,
+        };
+        ret.memory = allocator.allocate_image_memory(image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: image,
+            view_type: ImageViewType::Type2d,
+            format: OUTPUT_FORMAT,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: Cas::<D>::subresource_range(),
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+
+        // The shader fetches texels directly; this is for reading the source and for whoever
+        // samples the output
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+        debug!("Created {}x{} CAS output: {:?}", ret.extent.width, ret.extent.height, image);
+        Ok(ret)
+    }
+
+    fn subresource_range() -> ImageSubresourceRange {
+        ImageSubresourceRange {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    /// Descriptor set layout bindings for the sharpening kernel.
+    pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![
+            DescriptorSetLayoutBinding {
+                binding: SOURCE_BINDING,
+                descriptor_type: DescriptorType::CombinedImageSampler,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+            DescriptorSetLayoutBinding {
+                binding: OUTPUT_BINDING,
+                descriptor_type: DescriptorType::StorageImage,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+        ]
+    }
+
+    /// The image to sharpen, for the kernel's descriptor set alongside `output_write`.
+    pub fn source_write(&self, source: ImageView, layout: ImageLayout) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: SOURCE_BINDING,
+            sampler: self.sampler,
+            image_view: source,
+            image_layout: layout,
+        }
+    }
+
+    pub fn output_write(&self) -> DescriptorWrite {
+        DescriptorWrite::StorageImage {
+            binding: OUTPUT_BINDING,
+            image_view: self.view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    /// The sharpened image for a later pass's descriptor set. It stays in `General`, so that's
+    /// what it's sampled in.
+    pub fn sampled_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: binding,
+            sampler: self.sampler,
+            image_view: self.view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    /// Records sharpening with `kernel` (built from `SHADER` and `bindings`) and `descriptor_set`
+    /// (holding `source_write` and `output_write`), followed by a barrier for fragment shader
+    /// reads of the output. The source must already be readable by compute shaders. Must be
+    /// outside a render pass.
+    pub unsafe fn cmd_sharpen(&self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>, descriptor_set: DescriptorSet, constants: &CasPushConstants) {
+        // All of the output is written again, so its old contents can go, but the previous
+        // frame's passes may still be sampling it
+        let to_compute = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_READ_BIT,
+            dst_access_mask: ACCESS_SHADER_WRITE_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: Cas::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_FRAGMENT_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[to_compute]);
+
+        let groups = self.extent.div_ceil(LOCAL_SIZE);
+        kernel.cmd_dispatch(command_buffer, descriptor_set, constants, (groups.width, groups.height, 1));
+
+        let to_fragment = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::General,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: Cas::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &[to_fragment]);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for Cas<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying CAS output: {:?}", self.image);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+
+/// A `Cas` output with the kernel and descriptor set that sharpen one source image into it.
+/// Sized to the source, so it's recreated with it.
+pub struct CasPass<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    cas: Cas<'d, D>,
+    kernel: ComputeKernel<'d, D>,
+    /// Owns `descriptor_set`.
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_set: DescriptorSet,
+    source: Image,
+}
+
+impl<'d, D: DeviceV1_0> CasPass<'d, D> {
+    /// Sharpens `source`, an `extent` color image that's sampled through `source_view` in
+    /// `ShaderReadOnlyOptimal` after a render pass writes it. `spirv` is `SHADER`'s.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, spirv: &[u8], source: Image, source_view: ImageView, extent: Extent2D) -> VkResult<CasPass<'d, D>> {
+        let cas = Cas::new(device, allocator, extent)?;
+        let kernel = ComputeKernel::new(device, spirv, &Cas::<D>::bindings(), std::mem::size_of::<CasPushConstants>() as u32)?;
+        let pool_sizes = [
+            DescriptorPoolSize {
+                typ: DescriptorType::CombinedImageSampler,
+                descriptor_count: 1,
+            },
+            DescriptorPoolSize {
+                typ: DescriptorType::StorageImage,
+                descriptor_count: 1,
+            },
+        ];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, 1);
+        let descriptor_set = descriptor_allocator.allocate(kernel.set_layout())?;
+        descriptor::update_descriptor_set(device, descriptor_set, &[cas.source_write(source_view, ImageLayout::ShaderReadOnlyOptimal), cas.output_write()]);
+        Ok(CasPass {
+            device: device,
+            cas: cas,
+            kernel: kernel,
+            _descriptor_allocator: descriptor_allocator,
+            descriptor_set: descriptor_set,
+            source: source,
+        })
+    }
+
+    /// The sharpened image, see `Cas::sampled_write`.
+    pub fn sampled_write(&self, binding: u32) -> DescriptorWrite {
+        self.cas.sampled_write(binding)
+    }
+
+    /// Records sharpening the source, after the color attachment writes of the render pass that
+    /// drew it. Must be outside a render pass.
+    pub unsafe fn cmd_sharpen(&self, command_buffer: CommandBuffer, constants: &CasPushConstants) {
+        // The render pass only makes its writes visible to fragment shaders
+        let to_compute = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::ShaderReadOnlyOptimal,
+            new_layout: ImageLayout::ShaderReadOnlyOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.source,
+            subresource_range: Cas::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[to_compute]);
+        self.cas.cmd_sharpen(command_buffer, &self.kernel, self.descriptor_set, constants);
+    }
+}
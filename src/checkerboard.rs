@@ -0,0 +1,501 @@
+//! Experimental checkerboard rendering: each frame shades only half of the pixels, alternating
+//! between the two colors of a checkerboard, and a reconstruction pass fills in the other half.
+//! A full-screen mask draw (`shaders/checkerboard_mask_fragment.glsl`) writes the frame's cells
+//! into the stencil buffer, and scene pipelines built with `SHADED_STENCIL` fail the stencil
+//! test everywhere else, so the fragment shader only runs for half of the pixels. The target
+//! alternates between two color images, so the reconstruction
+//! (`shaders/checkerboard_reconstruct_fragment.glsl`) still has last frame's shading for the cells
+//! skipped this frame. The Checkerboard demo uses it with `--checkerboard`; run with and without
+//! to compare against full rate in the frame stats.
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, InstanceV1_0 };
+use std;
+use std::ptr;
+use vk::types::*;
+use ::command::RenderTarget;
+use ::cvar::Flag;
+use ::descriptor::DescriptorWrite;
+use ::format_support;
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::safe_create::{ self, FramebufferCreateInfoSafe, Owned, OwnedFramebuffer };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const ARG: &'static str = "--checkerboard";
+pub const CVAR: &'static str = "r.checkerboard";
+pub const FLAGS: &'static [Flag] = &[Flag::Switch { arg: ARG, name: CVAR }];
+
+pub const MASK_FRAGMENT_SHADER: &'static str = "shaders/checkerboard_mask_fragment.frag.spv";
+pub const RECONSTRUCT_FRAGMENT_SHADER: &'static str = "shaders/checkerboard_reconstruct_fragment.frag.spv";
+
+/// Binding of this frame's half-shaded color in the reconstruction set.
+pub const CURRENT_BINDING: u32 = 0;
+/// Binding of the previous frame's, shaded on the other half.
+pub const PREVIOUS_BINDING: u32 = 1;
+
+/// Stencil value of the cells shaded this frame.
+const SHADED: u32 = 1;
+
+/// Stencil state for scene pipelines drawing into a `CheckerboardTarget`: only the cells the mask
+/// marked pass.
+pub const SHADED_STENCIL: StencilOpState = StencilOpState {
+    fail_op: StencilOp::Keep,
+    pass_op: StencilOp::Keep,
+    depth_fail_op: StencilOp::Keep,
+    compare_op: CompareOp::Equal,
+    compare_mask: 0xff,
+    write_mask: 0,
+    reference: SHADED,
+};
+
+/// Stencil state of the mask pipeline, marking every fragment it doesn't discard.
+const MASK_STENCIL: StencilOpState = StencilOpState {
+    fail_op: StencilOp::Keep,
+    pass_op: StencilOp::Replace,
+    depth_fail_op: StencilOp::Keep,
+    compare_op: CompareOp::Always,
+    compare_mask: 0xff,
+    write_mask: 0xff,
+    reference: SHADED,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Every pixel shaded every frame, for comparison.
+    FullRate,
+    Checkerboard,
+}
+
+impl RenderMode {
+    /// From `r.checkerboard`, which `ARG` sets.
+    pub fn from_cvars() -> RenderMode {
+        if cvar!(CVAR, false).get() {
+            RenderMode::Checkerboard
+        } else {
+            RenderMode::FullRate
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconstruction {
+    /// Skipped cells average their four neighbors, which were all shaded this frame. Stable, but
+    /// loses half of the detail.
+    Spatial,
+    /// Skipped cells take last frame's shading, clamped to the range of their neighbors so
+    /// anything that moved doesn't leave a trail behind.
+    Temporal,
+}
+
+impl Reconstruction {
+    pub fn next(&self) -> Reconstruction {
+        match *self {
+            Reconstruction::Spatial => Reconstruction::Temporal,
+            Reconstruction::Temporal => Reconstruction::Spatial,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        match *self {
+            Reconstruction::Spatial => 0,
+            Reconstruction::Temporal => 1,
+        }
+    }
+}
+
+/// Push constant block read by `shaders/checkerboard_mask_fragment.glsl` and
+/// `shaders/checkerboard_reconstruct_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CheckerboardPushConstants {
+    /// Which color of the checkerboard is shaded this frame, 0 or 1.
+    pub parity: u32,
+    /// The `Reconstruction`; ignored by the mask.
+    pub reconstruction: u32,
+}
+
+impl CheckerboardPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<CheckerboardPushConstants>() as u32,
+        }
+    }
+}
+
+/// The first stencil format usable as an attachment. A pure `S8Uint` is preferred, since the depth
+/// of the others goes unused.
+pub fn stencil_format<I: InstanceV1_0>(instance: &I, physical_device: PhysicalDevice) -> Option<Format> {
+    let candidates = [Format::S8Uint, Format::D24UnormS8Uint, Format::D32SfloatS8Uint];
+    format_support::first_supported(instance, physical_device, &candidates, &[format_support::FormatUsage::DepthStencilAttachment])
+}
+
+/// Two color images shaded on alternate halves of the checkerboard, a stencil buffer holding the
+/// current half, and the render pass and framebuffers to draw into them. The render pass leaves
+/// the color in `ShaderReadOnlyOptimal` for the reconstruction. Sized to the swapchain, so it's
+/// recreated with it.
+pub struct CheckerboardTarget<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    // In the order they're destroyed
+    framebuffers: Vec<OwnedFramebuffer<'d, D>>,
+    render_pass: Owned<'d, D, RenderPass>,
+    /// Nearest, since the reconstruction works on whole pixels.
+    sampler: Owned<'d, D, Sampler>,
+    colors: [TargetImage<'d, D>; 2],
+    _stencil: TargetImage<'d, D>,
+    extent: Extent2D,
+    pub reconstruction: Reconstruction,
+    /// Frames begun so far; the low bit is the parity.
+    frame: u64,
+    /// Whether the image that hasn't been rendered yet has been moved to
+    /// `ShaderReadOnlyOptimal`, so the first reconstruction can bind it.
+    initialized: bool,
+}
+
+impl<'d, D: DeviceV1_0> CheckerboardTarget<'d, D> {
+    /// Creates the target with `format` color, like the swapchain's, and a `stencil_format` from
+    /// `stencil_format`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, stencil_format: Format, extent: Extent2D) -> VkResult<CheckerboardTarget<'d, D>> {
+        let color_usage = IMAGE_USAGE_COLOR_ATTACHMENT_BIT | IMAGE_USAGE_SAMPLED_BIT;
+        let colors = [
+            TargetImage::new(device, allocator, format, color_usage, IMAGE_ASPECT_COLOR_BIT, &extent)?,
+            TargetImage::new(device, allocator, format, color_usage, IMAGE_ASPECT_COLOR_BIT, &extent)?,
+        ];
+        let stencil_aspect = if stencil_format == Format::S8Uint {
+            IMAGE_ASPECT_STENCIL_BIT
+        } else {
+            IMAGE_ASPECT_DEPTH_BIT | IMAGE_ASPECT_STENCIL_BIT
+        };
+        let stencil = TargetImage::new(device, allocator, stencil_format, IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT, stencil_aspect, &extent)?;
+
+        let attachments = [
+            AttachmentDescription {
+                flags: Default::default(),
+                format: format,
+                samples: SAMPLE_COUNT_1_BIT,
+                load_op: AttachmentLoadOp::Clear,
+                store_op: AttachmentStoreOp::Store,
+                stencil_load_op: AttachmentLoadOp::DontCare,
+                stencil_store_op: AttachmentStoreOp::DontCare,
+                initial_layout: ImageLayout::Undefined,
+                final_layout: ImageLayout::ShaderReadOnlyOptimal,
+            },
+            AttachmentDescription {
+                flags: Default::default(),
+                format: stencil_format,
+                samples: SAMPLE_COUNT_1_BIT,
+                load_op: AttachmentLoadOp::DontCare,
+                store_op: AttachmentStoreOp::DontCare,
+                stencil_load_op: AttachmentLoadOp::Clear,
+                stencil_store_op: AttachmentStoreOp::DontCare,
+                initial_layout: ImageLayout::Undefined,
+                final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            },
+        ];
+        let color_attachment_ref = AttachmentReference {
+            attachment: 0,
+            layout: ImageLayout::ColorAttachmentOptimal,
+        };
+        let stencil_attachment_ref = AttachmentReference {
+            attachment: 1,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+        };
+        let subpass = SubpassDescription {
+            flags: Default::default(),
+            pipeline_bind_point: PipelineBindPoint::Graphics,
+            input_attachment_count: 0,
+            p_input_attachments: ptr::null(),
+            color_attachment_count: 1,
+            p_color_attachments: &color_attachment_ref,
+            p_resolve_attachments: ptr::null(),
+            p_depth_stencil_attachment: &stencil_attachment_ref,
+            preserve_attachment_count: 0,
+            p_preserve_attachments: ptr::null(),
+        };
+        let dependencies = [
+            // The image was last sampled by the reconstruction two frames ago, and the stencil
+            // written by last frame's mask
+            SubpassDependency {
+                src_subpass: VK_SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PIPELINE_STAGE_FRAGMENT_SHADER_BIT | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+                src_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dst_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+                dst_access_mask: ACCESS_COLOR_ATTACHMENT_READ_BIT | ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dependency_flags: Default::default(),
+            },
+            SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: VK_SUBPASS_EXTERNAL,
+                src_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+                src_access_mask: ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+                dst_stage_mask: PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+                dst_access_mask: ACCESS_SHADER_READ_BIT,
+                dependency_flags: Default::default(),
+            },
+        ];
+        let render_pass_create_info = RenderPassCreateInfo {
+            s_type: StructureType::RenderPassCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+        };
+        let render_pass = safe_create::create_render_pass_safe(device, &render_pass_create_info, None)?;
+
+        let mut framebuffers = Vec::with_capacity(colors.len());
+        for color in colors.iter() {
+            let framebuffer_create_info = FramebufferCreateInfo {
+                s_type: StructureType::FramebufferCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                render_pass: RenderPass::null(),
+                attachment_count: 0,
+                p_attachments: ptr::null(),
+                width: extent.width,
+                height: extent.height,
+                layers: 1,
+            };
+            let framebuffer_create_info = unsafe { FramebufferCreateInfoSafe::from_handles(framebuffer_create_info, *render_pass, &[*color.view, *stencil.view]) };
+            framebuffers.push(safe_create::create_framebuffer_safe(device, framebuffer_create_info, None)?);
+        }
+
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        let sampler = safe_create::create_sampler_safe(device, &sampler_create_info, None)?;
+        debug!("Created {}x{} checkerboard target with {:?} stencil", extent.width, extent.height, stencil_format);
+        Ok(CheckerboardTarget {
+            device: device,
+            framebuffers: framebuffers,
+            render_pass: render_pass,
+            sampler: sampler,
+            colors: colors,
+            _stencil: stencil,
+            extent: extent,
+            reconstruction: Reconstruction::Temporal,
+            frame: 0,
+            initialized: false,
+        })
+    }
+
+    fn current(&self) -> usize {
+        (self.frame & 1) as usize
+    }
+
+    pub fn extent(&self) -> &Extent2D {
+        &self.extent
+    }
+
+    pub fn render_pass(&self) -> RenderPass {
+        *self.render_pass
+    }
+
+    /// Moves on to the other half of the checkerboard and the other color image. Call once per
+    /// frame, before rendering into `target`.
+    pub fn advance(&mut self) {
+        self.frame += 1;
+    }
+
+    /// This frame's framebuffer. Begin its render pass with `clear_values`.
+    pub fn target<'a>(&'a self) -> RenderTarget<'a> {
+        RenderTarget {
+            render_pass: &self.render_pass,
+            framebuffer: &self.framebuffers[self.current()],
+            extent: self.extent.clone(),
+        }
+    }
+
+    /// Clear values for `target`: `color` and a stencil of 0, so only the mask's cells pass.
+    pub fn clear_values(color: ClearColorValue) -> [ClearValue; 2] {
+        [
+            ClearValue::new_color(color),
+            ClearValue::new_depth_stencil(ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            }),
+        ]
+    }
+
+    pub fn push_constants(&self) -> CheckerboardPushConstants {
+        CheckerboardPushConstants {
+            parity: self.current() as u32,
+            reconstruction: self.reconstruction.mode(),
+        }
+    }
+
+    /// Descriptor set layout bindings for the reconstruction.
+    pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+        [CURRENT_BINDING, PREVIOUS_BINDING].iter()
+            .map(|&binding| DescriptorSetLayoutBinding {
+                binding: binding,
+                descriptor_type: DescriptorType::CombinedImageSampler,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+                p_immutable_samplers: ptr::null(),
+            })
+            .collect()
+    }
+
+    /// The reconstruction's writes for this frame. They swap every frame, so they belong in a set
+    /// from the frame's transient allocator.
+    pub fn reconstruct_writes(&self) -> [DescriptorWrite; 2] {
+        let current = self.current();
+        [
+            DescriptorWrite::CombinedImageSampler {
+                binding: CURRENT_BINDING,
+                sampler: *self.sampler,
+                image_view: *self.colors[current].view,
+                image_layout: ImageLayout::ShaderReadOnlyOptimal,
+            },
+            DescriptorWrite::CombinedImageSampler {
+                binding: PREVIOUS_BINDING,
+                sampler: *self.sampler,
+                image_view: *self.colors[1 - current].view,
+                image_layout: ImageLayout::ShaderReadOnlyOptimal,
+            },
+        ]
+    }
+
+    /// Records moving the image that hasn't been rendered yet into `ShaderReadOnlyOptimal`, so the
+    /// first reconstruction can bind it; until a frame has been rendered into it, it only holds
+    /// garbage. Does nothing after the first call. Must be outside a render pass.
+    pub unsafe fn cmd_prepare(&mut self, command_buffer: CommandBuffer) {
+        if self.initialized {
+            return;
+        }
+        let barrier = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: Default::default(),
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::ShaderReadOnlyOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: *self.colors[1 - self.current()].image,
+            subresource_range: subresource_range(IMAGE_ASPECT_COLOR_BIT),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &[barrier]);
+        self.initialized = true;
+    }
+
+    /// Records the mask draw with `pipeline` (from `mask_pipeline_builder`) and its `layout`. Must
+    /// be the first draw in `target`'s render pass.
+    pub unsafe fn cmd_draw_mask(&self, command_buffer: CommandBuffer, pipeline: Pipeline, layout: PipelineLayout) {
+        let constants = self.push_constants();
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, pipeline);
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, SHADER_STAGE_FRAGMENT_BIT, 0, std::mem::size_of::<CheckerboardPushConstants>() as u32, &constants as *const CheckerboardPushConstants as *const c_void);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+fn subresource_range(aspect_mask: ImageAspectFlags) -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: aspect_mask,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// A 2D image with its memory and a view of the whole thing.
+struct TargetImage<'d, D: DeviceV1_0 + 'd> {
+    // In the order they're destroyed
+    view: Owned<'d, D, ImageView>,
+    image: Owned<'d, D, Image>,
+    _memory: VkAllocation<'d, D>,
+}
+
+impl<'d, D: DeviceV1_0> TargetImage<'d, D> {
+    fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, usage: ImageUsageFlags, aspect_mask: ImageAspectFlags, extent: &Extent2D) -> VkResult<TargetImage<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: format,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: usage,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = safe_create::create_image_safe(device, &image_create_info, None)?;
+        let memory = allocator.allocate_image_memory(*image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: *image,
+            view_type: ImageViewType::Type2d,
+            format: format,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: subresource_range(aspect_mask),
+        };
+        let view = safe_create::create_image_view_safe(device, &view_create_info, None)?;
+        Ok(TargetImage {
+            view: view,
+            image: image,
+            _memory: memory,
+        })
+    }
+}
+
+/// A builder for the mask pipeline, drawn with `shaders/fullscreen_vertex.glsl` and
+/// `MASK_FRAGMENT_SHADER` into a `CheckerboardTarget`'s render pass. It writes no color.
+pub fn mask_pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    let mut no_color = BlendPreset::Opaque.attachment_state();
+    no_color.color_write_mask = ColorComponentFlags::empty();
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .stencil_test(MASK_STENCIL)
+        .blend_attachment(no_color)
+}
+
+/// A builder for the reconstruction, drawn with `shaders/fullscreen_vertex.glsl` and
+/// `RECONSTRUCT_FRAGMENT_SHADER` into wherever the full image goes, e.g. the swapchain.
+pub fn reconstruct_pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
@@ -0,0 +1,165 @@
+//! Splitting compute work too big for one submission, like prefiltering an IBL cubemap or baking
+//! terrain, into a series of smaller ones. Operating systems reset the GPU when a single
+//! submission runs for too long (about two seconds on Windows), which takes the whole session
+//! down with it. `ChunkedDispatch` submits a dispatch a few rows of workgroups at a time through
+//! an `ImmediateContext`, waiting for each chunk before the next, and resizes the chunks to stay
+//! around a target time. Kernels read where their chunk starts from the front of their push
+//! constants, see `ChunkPushConstants`.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use std::time::{ Duration, Instant };
+use vk::types::*;
+use ::compute::ComputeKernel;
+use ::immediate::ImmediateContext;
+use ::time;
+
+/// Well under every OS watchdog, while still keeping per-submission overhead small.
+const DEFAULT_TARGET_CHUNK_TIME_MS: u64 = 100;
+const DEFAULT_GROUPS_PER_CHUNK: u32 = 1024;
+
+/// Push constants of a chunked kernel: the chunk's first workgroup, followed by the kernel's own
+/// constants. In GLSL, that's a `uvec3 groupOffset` at the start of the push constant block, with
+/// the kernel's members from offset 16, and the kernel uses `gl_WorkGroupID + groupOffset` in
+/// place of `gl_WorkGroupID`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkPushConstants<T: Copy> {
+    pub group_offset: [u32; 3],
+    _padding: u32,
+    pub constants: T,
+}
+
+impl<T: Copy> ChunkPushConstants<T> {
+    pub fn new(group_offset: [u32; 3], constants: T) -> ChunkPushConstants<T> {
+        ChunkPushConstants {
+            group_offset: group_offset,
+            _padding: 0,
+            constants: constants,
+        }
+    }
+
+    /// The push constant size to create the kernel with.
+    pub fn size() -> u32 {
+        std::mem::size_of::<ChunkPushConstants<T>>() as u32
+    }
+}
+
+/// How far along a chunked dispatch is, passed to the progress callback after every chunk.
+#[derive(Debug, Clone)]
+pub struct ChunkProgress {
+    /// Chunks submitted so far, including the one just finished.
+    pub chunks: u32,
+    pub groups_done: u64,
+    pub groups_total: u64,
+    pub elapsed: Duration,
+    /// How long the last chunk took, from submission to its fence.
+    pub last_chunk: Duration,
+}
+
+impl ChunkProgress {
+    pub fn fraction(&self) -> f32 {
+        self.groups_done as f32 / self.groups_total as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.groups_done == self.groups_total
+    }
+
+    pub fn log(&self, name: &str) {
+        debug!("{}: {:.0}% after {} chunks, {:.1} s ({:.1} ms last chunk)", name, self.fraction() * 100.0, self.chunks, time::seconds(self.elapsed), time::seconds(self.last_chunk) * 1000.0);
+    }
+}
+
+/// Chunk sizing for chunked dispatches. Sizes carry over between dispatches, so a series of
+/// similar dispatches (e.g. every mip of a prefilter) starts out at the size the last one ended
+/// with.
+#[derive(Debug, Clone)]
+pub struct ChunkedDispatch {
+    target_chunk_time: Duration,
+    groups_per_chunk: u32,
+}
+
+impl Default for ChunkedDispatch {
+    fn default() -> ChunkedDispatch {
+        ChunkedDispatch::new(Duration::from_millis(DEFAULT_TARGET_CHUNK_TIME_MS))
+    }
+}
+
+impl ChunkedDispatch {
+    pub fn new(target_chunk_time: Duration) -> ChunkedDispatch {
+        ChunkedDispatch {
+            target_chunk_time: target_chunk_time,
+            groups_per_chunk: DEFAULT_GROUPS_PER_CHUNK,
+        }
+    }
+
+    /// Sets the size the next dispatch starts out with, e.g. small for a kernel known to be slow.
+    pub fn set_groups_per_chunk(&mut self, groups_per_chunk: u32) {
+        self.groups_per_chunk = std::cmp::max(1, groups_per_chunk);
+    }
+
+    /// Halves the chunk size when a chunk ran over the target, and doubles it when one took less
+    /// than a quarter of it.
+    fn adapt(&mut self, chunk_time: Duration) {
+        if chunk_time > self.target_chunk_time {
+            self.groups_per_chunk = std::cmp::max(1, self.groups_per_chunk / 2);
+        } else if chunk_time * 4 < self.target_chunk_time {
+            self.groups_per_chunk = self.groups_per_chunk.saturating_mul(2);
+        }
+    }
+
+    /// Dispatches `group_count` workgroups of `kernel` in chunks of whole rows (a row being
+    /// `group_count.0` groups, the smallest piece a chunk can have), waiting for each chunk before
+    /// submitting the next and calling `progress` after it. Chunks after the first begin with a
+    /// compute to compute barrier, so a chunk may read what earlier ones wrote. The kernel must
+    /// have been created with `ChunkPushConstants::<T>::size()`. Blocks until the whole dispatch
+    /// is done.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run<D: DeviceV1_0, T: Copy, P: FnMut(&ChunkProgress)>(&mut self, device: &D, immediate: &ImmediateContext<D>, kernel: &ComputeKernel<D>, descriptor_set: DescriptorSet, constants: &T, group_count: (u32, u32, u32), mut progress: P) -> VkResult<ChunkProgress> {
+        let (groups_x, groups_y, groups_z) = group_count;
+        let start = Instant::now();
+        let mut ret = ChunkProgress {
+            chunks: 0,
+            groups_done: 0,
+            groups_total: groups_x as u64 * groups_y as u64 * groups_z as u64,
+            elapsed: Duration::new(0, 0),
+            last_chunk: Duration::new(0, 0),
+        };
+        if ret.groups_total == 0 {
+            return Ok(ret);
+        }
+        let (mut y, mut z) = (0, 0);
+        while z < groups_z {
+            let rows = std::cmp::min(std::cmp::max(1, self.groups_per_chunk / groups_x), groups_y - y);
+            let chunk_constants = ChunkPushConstants::new([0, y, z], *constants);
+            let first = ret.chunks == 0;
+            let chunk_start = Instant::now();
+            immediate.immediate_submit(|command_buffer| unsafe {
+                if !first {
+                    let barrier = MemoryBarrier {
+                        s_type: StructureType::MemoryBarrier,
+                        p_next: ptr::null(),
+                        src_access_mask: ACCESS_SHADER_WRITE_BIT,
+                        dst_access_mask: ACCESS_SHADER_READ_BIT | ACCESS_SHADER_WRITE_BIT,
+                    };
+                    device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[barrier], &[], &[]);
+                }
+                kernel.cmd_dispatch(command_buffer, descriptor_set, &chunk_constants, (groups_x, rows, 1));
+            })?;
+            ret.last_chunk = chunk_start.elapsed();
+            self.adapt(ret.last_chunk);
+            ret.chunks += 1;
+            ret.groups_done += groups_x as u64 * rows as u64;
+            ret.elapsed = start.elapsed();
+            progress(&ret);
+            y += rows;
+            if y == groups_y {
+                y = 0;
+                z += 1;
+            }
+        }
+        Ok(ret)
+    }
+}
@@ -0,0 +1,187 @@
+//! Window and device options from the command line. Each takes its value either after `=` or as
+//! the next argument:
+//!
+//! - `--width <pixels>` and `--height <pixels>` size the window, 1280x720 by default.
+//! - `--device <device>` picks the physical device, by its index in enumeration order or part of
+//!   its name. If several match, the best scoring one that can run the renderer is used.
+//! - `--present-mode <immediate|mailbox|fifo|fifo_relaxed>` is used if the surface supports it.
+//! - `--validation <on|off>` turns the validation layer on or off. Without it, `SMOLDER_VALIDATION`
+//!   in the environment decides, and validation is on if neither says. It's skipped anyway if no
+//!   validation layer is installed.
+//!
+//! `--fullscreen` and `--monitor` are in `monitor`. Each flag sets a console variable, which is
+//! what `LaunchConfig` is read from.
+use std::env;
+use vk::types::*;
+use ::cvar::Flag;
+
+pub const WIDTH_ARG: &'static str = "--width";
+pub const HEIGHT_ARG: &'static str = "--height";
+pub const DEVICE_ARG: &'static str = "--device";
+pub const PRESENT_MODE_ARG: &'static str = "--present-mode";
+pub const VALIDATION_ARG: &'static str = "--validation";
+pub const VALIDATION_ENV: &'static str = "SMOLDER_VALIDATION";
+
+pub const WIDTH_CVAR: &'static str = "window.width";
+pub const HEIGHT_CVAR: &'static str = "window.height";
+/// The rest are empty when not given.
+pub const DEVICE_CVAR: &'static str = "device.select";
+pub const PRESENT_MODE_CVAR: &'static str = "r.present_mode";
+pub const VALIDATION_CVAR: &'static str = "device.validation";
+pub const FLAGS: &'static [Flag] = &[
+    Flag::Separable { arg: WIDTH_ARG, name: WIDTH_CVAR },
+    Flag::Separable { arg: HEIGHT_ARG, name: HEIGHT_CVAR },
+    Flag::Separable { arg: DEVICE_ARG, name: DEVICE_CVAR },
+    Flag::Separable { arg: PRESENT_MODE_ARG, name: PRESENT_MODE_CVAR },
+    Flag::Separable { arg: VALIDATION_ARG, name: VALIDATION_CVAR },
+];
+
+const DEFAULT_WIDTH: u32 = 1280;
+const DEFAULT_HEIGHT: u32 = 720;
+
+pub fn parse_present_mode(name: &str) -> Option<PresentModeKHR> {
+    match name {
+        "immediate" => Some(PresentModeKHR::Immediate),
+        "mailbox" => Some(PresentModeKHR::Mailbox),
+        "fifo" => Some(PresentModeKHR::Fifo),
+        "fifo_relaxed" => Some(PresentModeKHR::FifoRelaxed),
+        _ => None,
+    }
+}
+
+/// Which physical device to use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Index(usize),
+    /// Part of the name, ignoring case.
+    Name(String),
+}
+
+impl DeviceSelector {
+    pub fn parse(text: &str) -> DeviceSelector {
+        match text.parse::<usize>() {
+            Ok(index) => DeviceSelector::Index(index),
+            Err(_) => DeviceSelector::Name(text.to_lowercase()),
+        }
+    }
+
+    /// Whether this picks the device at `index` in enumeration order, named `name`.
+    pub fn matches(&self, index: usize, name: &str) -> bool {
+        match *self {
+            DeviceSelector::Index(selected) => selected == index,
+            DeviceSelector::Name(ref selected) => name.to_lowercase().contains(selected.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchConfig {
+    pub width: u32,
+    pub height: u32,
+    /// The best scoring suitable device if `None`.
+    pub device: Option<DeviceSelector>,
+    /// The surface's preferred mode if `None`.
+    pub present_mode: Option<PresentModeKHR>,
+    pub validation: bool,
+}
+
+impl Default for LaunchConfig {
+    fn default() -> LaunchConfig {
+        LaunchConfig {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            device: None,
+            present_mode: None,
+            validation: true,
+        }
+    }
+}
+
+impl LaunchConfig {
+    /// From the variables `FLAGS` set.
+    pub fn from_cvars() -> LaunchConfig {
+        let text = |name: &'static str| {
+            let value: String = cvar!(name, String::new()).get();
+            if value.is_empty() { None } else { Some(value) }
+        };
+        LaunchConfig::new(cvar!(WIDTH_CVAR, DEFAULT_WIDTH).get(), cvar!(HEIGHT_CVAR, DEFAULT_HEIGHT).get(),
+                          text(DEVICE_CVAR), text(PRESENT_MODE_CVAR), text(VALIDATION_CVAR))
+    }
+
+    /// `validation` falls back to `VALIDATION_ENV`.
+    fn new(width: u32, height: u32, device: Option<String>, present_mode: Option<String>, validation: Option<String>) -> LaunchConfig {
+        let mut ret = LaunchConfig::default();
+        let size = |name: &str, size: u32, default: u32| if size > 0 {
+            size
+        } else {
+            warn!("Invalid {} 0, expected a positive number of pixels, using {}", name, default);
+            default
+        };
+        ret.width = size(WIDTH_ARG, width, DEFAULT_WIDTH);
+        ret.height = size(HEIGHT_ARG, height, DEFAULT_HEIGHT);
+        ret.device = device.map(|value| DeviceSelector::parse(&value));
+        ret.present_mode = present_mode.and_then(|value| {
+            let mode = parse_present_mode(&value);
+            if mode.is_none() {
+                warn!("Unknown present mode {:?}, expected immediate, mailbox, fifo or fifo_relaxed", value);
+            }
+            mode
+        });
+        let validation = validation.map(|value| (VALIDATION_ARG, value))
+            .or_else(|| env::var(VALIDATION_ENV).ok().map(|value| (VALIDATION_ENV, value)));
+        if let Some((source, value)) = validation {
+            match value.as_str() {
+                "on" | "1" => ret.validation = true,
+                "off" | "0" => ret.validation = false,
+                _ => warn!("Invalid {} {:?}, expected on or off", source, value),
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn values_from_the_variables() {
+        let config = LaunchConfig::new(800, 600, None, text("mailbox"), text("off"));
+        assert_eq!((config.width, config.height), (800, 600));
+        assert_eq!(config.present_mode, Some(PresentModeKHR::Mailbox));
+        assert!(!config.validation);
+        assert_eq!(config.device, None);
+    }
+
+    #[test]
+    fn zero_sizes_fall_back_to_the_defaults() {
+        let config = LaunchConfig::new(0, 0, None, None, None);
+        assert_eq!((config.width, config.height), (DEFAULT_WIDTH, DEFAULT_HEIGHT));
+    }
+
+    #[test]
+    fn unknown_present_mode_is_ignored() {
+        let config = LaunchConfig::new(DEFAULT_WIDTH, DEFAULT_HEIGHT, None, text("vsync"), None);
+        assert_eq!(config.present_mode, None);
+        for &(name, mode) in [("immediate", PresentModeKHR::Immediate), ("fifo", PresentModeKHR::Fifo), ("fifo_relaxed", PresentModeKHR::FifoRelaxed)].iter() {
+            assert_eq!(parse_present_mode(name), Some(mode));
+        }
+    }
+
+    #[test]
+    fn device_by_index_or_name() {
+        assert_eq!(DeviceSelector::parse("1"), DeviceSelector::Index(1));
+        let by_name = DeviceSelector::parse("GeForce");
+        assert_eq!(by_name, DeviceSelector::Name("geforce".to_string()));
+        assert!(by_name.matches(0, "NVIDIA GeForce RTX"));
+        assert!(!by_name.matches(0, "AMD Radeon"));
+        assert!(DeviceSelector::Index(1).matches(1, "anything"));
+        assert!(!DeviceSelector::Index(1).matches(0, "anything"));
+        let config = LaunchConfig::new(DEFAULT_WIDTH, DEFAULT_HEIGHT, text("radeon"), None, None);
+        assert_eq!(config.device, Some(DeviceSelector::Name("radeon".to_string())));
+    }
+}
@@ -0,0 +1,172 @@
+//! Typed command buffer recording. Everything a `CommandRecorder` records is borrowed for `'a`,
+//! so pipelines, layouts, and render targets can't be destroyed while commands referring to them
+//! are still being recorded. Keeping them alive until the GPU is done is still up to the caller.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ops::{ Deref, DerefMut };
+use std::ptr;
+use vk::types::*;
+use ::pnext::ExtensionStruct;
+use ::rect::ExtentExt;
+use ::safe_info::RenderPassBeginInfoSafe;
+
+/// Something that knows how to record its own draw call, with its buffers already bound.
+pub trait Drawable {
+    unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer);
+}
+
+/// Three vertices without any vertex buffers, for shaders that generate a full-screen triangle
+/// from `gl_VertexIndex`.
+#[derive(Debug, Clone, Copy)]
+pub struct FullscreenTriangle;
+
+impl Drawable for FullscreenTriangle {
+    unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer) {
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// A framebuffer together with the render pass it was made for.
+#[derive(Clone)]
+pub struct RenderTarget<'a> {
+    pub render_pass: &'a RenderPass,
+    pub framebuffer: &'a Framebuffer,
+    pub extent: Extent2D,
+}
+
+pub struct CommandRecorder<'a, D: DeviceV1_0 + 'a> {
+    device: &'a D,
+    command_buffer: CommandBuffer,
+}
+
+impl<'a, D: DeviceV1_0> CommandRecorder<'a, D> {
+    /// Begins recording into `command_buffer`, which must not be pending execution.
+    pub unsafe fn begin(device: &'a D, command_buffer: CommandBuffer, flags: CommandBufferUsageFlags) -> VkResult<CommandRecorder<'a, D>> {
+        let begin_info = CommandBufferBeginInfo {
+            s_type: StructureType::CommandBufferBeginInfo,
+            p_next: ptr::null(),
+            flags: flags,
+            p_inheritance_info: ptr::null(),
+        };
+        device.begin_command_buffer(command_buffer, &begin_info)?;
+        Ok(CommandRecorder {
+            device: device,
+            command_buffer: command_buffer,
+        })
+    }
+
+    /// Records into `command_buffer`, which someone else has begun and will end, like the ones
+    /// `ImmediateContext::immediate_submit` hands out.
+    pub unsafe fn recording(device: &'a D, command_buffer: CommandBuffer) -> CommandRecorder<'a, D> {
+        CommandRecorder {
+            device: device,
+            command_buffer: command_buffer,
+        }
+    }
+
+    pub fn command_buffer(&self) -> CommandBuffer {
+        self.command_buffer
+    }
+
+    /// Starts `target`'s render pass covering the whole framebuffer. The pass ends when the
+    /// returned recorder is dropped.
+    pub fn begin_render_pass<'r>(&'r mut self, target: &RenderTarget<'a>, clear_values: &[ClearValue]) -> RenderPassRecorder<'r, 'a, D> {
+        let begin_info = RenderPassBeginInfoSafe::new(target.render_pass, target.framebuffer, target.extent.to_rect())
+            .clear_values(clear_values);
+        self.begin_render_pass_info(begin_info)
+    }
+
+    /// Like `begin_render_pass`, with `next` chained onto the begin info, e.g. the
+    /// `sample_locations::RenderPassSampleLocations` of a pass with custom sample positions.
+    pub fn begin_render_pass_with<'r, T: ExtensionStruct>(&'r mut self, target: &RenderTarget<'a>, clear_values: &[ClearValue], next: &mut T) -> RenderPassRecorder<'r, 'a, D> {
+        let begin_info = RenderPassBeginInfoSafe::new(target.render_pass, target.framebuffer, target.extent.to_rect())
+            .clear_values(clear_values)
+            .push_next(next);
+        self.begin_render_pass_info(begin_info)
+    }
+
+    fn begin_render_pass_info<'r>(&'r mut self, begin_info: RenderPassBeginInfoSafe) -> RenderPassRecorder<'r, 'a, D> {
+        unsafe {
+            self.device.cmd_begin_render_pass(self.command_buffer, begin_info.info_ref(), SubpassContents::Inline);
+        }
+        RenderPassRecorder {
+            recorder: self,
+        }
+    }
+
+    pub fn bind(&mut self, pipeline: &'a Pipeline) {
+        unsafe {
+            self.device.cmd_bind_pipeline(self.command_buffer, PipelineBindPoint::Graphics, *pipeline);
+        }
+    }
+
+    pub fn bind_descriptor_sets(&mut self, layout: &'a PipelineLayout, first_set: u32, descriptor_sets: &[DescriptorSet]) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(self.command_buffer, PipelineBindPoint::Graphics, *layout, first_set, descriptor_sets, &[]);
+        }
+    }
+
+    /// Pushes `constants` into `range`, which must be exactly the size of `T`.
+    pub fn push_constants<T: Copy>(&mut self, layout: &'a PipelineLayout, range: &PushConstantRange, constants: &T) {
+        assert_eq!(range.size as usize, std::mem::size_of::<T>());
+        unsafe {
+            self.device.fp_v1_0().cmd_push_constants(self.command_buffer, *layout, range.stage_flags, range.offset, range.size, constants as *const T as *const c_void);
+        }
+    }
+
+    pub fn end(self) -> VkResult<CommandBuffer> {
+        unsafe {
+            self.device.end_command_buffer(self.command_buffer)?;
+        }
+        Ok(self.command_buffer)
+    }
+}
+
+/// Recorder for commands that are only valid inside a render pass. Everything that's valid
+/// outside of one is available through `Deref`.
+pub struct RenderPassRecorder<'r, 'a: 'r, D: DeviceV1_0 + 'a> {
+    recorder: &'r mut CommandRecorder<'a, D>,
+}
+
+impl<'r, 'a, D: DeviceV1_0> RenderPassRecorder<'r, 'a, D> {
+    pub fn draw<M: Drawable>(&mut self, mesh: &'a M) {
+        unsafe {
+            mesh.cmd_draw(self.recorder.device, self.recorder.command_buffer);
+        }
+    }
+
+    pub fn set_viewport(&mut self, viewport: &Viewport) {
+        unsafe {
+            self.recorder.device.cmd_set_viewport(self.recorder.command_buffer, std::slice::from_ref(viewport));
+        }
+    }
+
+    pub fn set_scissor(&mut self, scissor: &Rect2D) {
+        unsafe {
+            self.recorder.device.cmd_set_scissor(self.recorder.command_buffer, std::slice::from_ref(scissor));
+        }
+    }
+}
+
+impl<'r, 'a, D: DeviceV1_0> Deref for RenderPassRecorder<'r, 'a, D> {
+    type Target = CommandRecorder<'a, D>;
+
+    fn deref(&self) -> &CommandRecorder<'a, D> {
+        self.recorder
+    }
+}
+
+impl<'r, 'a, D: DeviceV1_0> DerefMut for RenderPassRecorder<'r, 'a, D> {
+    fn deref_mut(&mut self) -> &mut CommandRecorder<'a, D> {
+        self.recorder
+    }
+}
+
+impl<'r, 'a, D: DeviceV1_0> Drop for RenderPassRecorder<'r, 'a, D> {
+    fn drop(&mut self) {
+        unsafe {
+            self.recorder.device.cmd_end_render_pass(self.recorder.command_buffer);
+        }
+    }
+}
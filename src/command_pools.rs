@@ -0,0 +1,90 @@
+//! Command pools for multithreaded recording. Each recording thread gets its own transient pool
+//! for each frame in flight, since a pool (and every command buffer allocated from it) may only be
+//! used by one thread at a time. Rather than freeing command buffers individually, a frame's
+//! pools are reset wholesale once the GPU is done with that frame, and their command buffers are
+//! handed out again.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::ptr;
+use std::sync::Mutex;
+use std::thread::{ self, ThreadId };
+use vk::types::*;
+use ::queue::QueueFamilyIndex;
+use ::safe_create::{ self, CommandBuffersSafe, Owned };
+
+struct ThreadPool<'d, D: DeviceV1_0 + 'd> {
+    /// Ahead of `pool`, so they're freed before it's destroyed.
+    command_buffers: Vec<CommandBuffersSafe<'d, D>>,
+    pool: Owned<'d, D, CommandPool>,
+    /// Number of `command_buffers` handed out since the last reset.
+    used: usize,
+}
+
+pub struct CommandPoolManager<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    queue_family_index: QueueFamilyIndex,
+    frames: Vec<Mutex<HashMap<ThreadId, ThreadPool<'d, D>>>>,
+    current_frame: usize,
+}
+
+impl<'d, D: DeviceV1_0> CommandPoolManager<'d, D> {
+    pub fn new(device: &'d D, queue_family_index: QueueFamilyIndex, frames_in_flight: usize) -> CommandPoolManager<'d, D> {
+        assert!(frames_in_flight > 0);
+        CommandPoolManager {
+            device: device,
+            queue_family_index: queue_family_index,
+            frames: (0..frames_in_flight).map(|_| Mutex::new(HashMap::new())).collect(),
+            current_frame: 0,
+        }
+    }
+
+    /// Switches to the pools for `frame_index`, resetting them. Every command buffer previously
+    /// allocated for that frame becomes invalid, so the GPU must be done executing them.
+    pub fn begin_frame(&mut self, frame_index: usize) -> VkResult<()> {
+        self.current_frame = frame_index % self.frames.len();
+        let mut pools = self.frames[self.current_frame].lock().unwrap();
+        for thread_pool in pools.values_mut() {
+            (unsafe { self.device.reset_command_pool(*thread_pool.pool, Default::default()) })?;
+            thread_pool.used = 0;
+        }
+        Ok(())
+    }
+
+    /// A primary command buffer from the calling thread's pool for the current frame. It must
+    /// only be recorded on the calling thread, and is valid until the next `begin_frame` for this
+    /// frame.
+    pub fn allocate(&self) -> VkResult<CommandBuffer> {
+        let mut pools = self.frames[self.current_frame].lock().unwrap();
+        let thread_id = thread::current().id();
+        let thread_pool = match pools.entry(thread_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let pool = self.create_pool()?;
+                trace!("Created command pool {:?} for thread {:?}, frame {}", *pool, thread_id, self.current_frame);
+                entry.insert(ThreadPool {
+                    command_buffers: Vec::new(),
+                    pool: pool,
+                    used: 0,
+                })
+            },
+        };
+        if thread_pool.used == thread_pool.command_buffers.len() {
+            let command_buffers = unsafe { safe_create::allocate_command_buffers_from_handle(self.device, *thread_pool.pool, CommandBufferLevel::Primary, 1) }?;
+            thread_pool.command_buffers.push(command_buffers);
+        }
+        thread_pool.used += 1;
+        Ok(thread_pool.command_buffers[thread_pool.used - 1][0])
+    }
+
+    fn create_pool(&self) -> VkResult<Owned<'d, D, CommandPool>> {
+        let create_info = CommandPoolCreateInfo {
+            s_type: StructureType::CommandPoolCreateInfo,
+            p_next: ptr::null(),
+            flags: COMMAND_POOL_CREATE_TRANSIENT_BIT,
+            queue_family_index: self.queue_family_index.raw(),
+        };
+        safe_create::create_command_pool_safe(self.device, &create_info, None)
+    }
+}
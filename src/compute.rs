@@ -0,0 +1,198 @@
+//! Building blocks for compute passes: a kernel (pipeline, layout and a single set layout) and
+//! host-visible storage buffers to feed it.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::buffer::DeviceBuffer;
+use ::descriptor::DescriptorWrite;
+use ::queue::QueueFamilyIndex;
+use ::shader_stage::ShaderStage;
+use ::vk_mem::MemoryAllocator;
+
+/// Storage buffer bindings `0..count` of set 0, visible to compute shaders.
+pub fn storage_buffer_bindings(count: u32) -> Vec<DescriptorSetLayoutBinding> {
+    (0..count).map(|binding| DescriptorSetLayoutBinding {
+        binding: binding,
+        descriptor_type: DescriptorType::StorageBuffer,
+        descriptor_count: 1,
+        stage_flags: SHADER_STAGE_COMPUTE_BIT,
+        p_immutable_samplers: ptr::null(),
+    }).collect()
+}
+
+/// The queue family for compute work: one without graphics if there is one, since those can run
+/// alongside rendering, and otherwise `graphics_family` if it can do compute, or failing that
+/// any family that can.
+pub fn choose_family(queue_families: &[QueueFamilyProperties], graphics_family: QueueFamilyIndex) -> Option<QueueFamilyIndex> {
+    let supports_compute = |properties: &QueueFamilyProperties| properties.queue_count > 0 && properties.queue_flags.subset(QUEUE_COMPUTE_BIT);
+    let dedicated = QueueFamilyIndex::enumerate(queue_families)
+        .find(|&(_, properties)| supports_compute(properties) && !properties.queue_flags.subset(QUEUE_GRAPHICS_BIT));
+    if let Some((family, _)) = dedicated {
+        return Some(family);
+    }
+    if graphics_family.properties(queue_families).map(&supports_compute).unwrap_or(false) {
+        return Some(graphics_family);
+    }
+    QueueFamilyIndex::enumerate(queue_families).find(|&(_, properties)| supports_compute(properties)).map(|(family, _)| family)
+}
+
+/// Makes storage writes from earlier dispatches visible to later ones.
+pub unsafe fn cmd_compute_barrier<D: DeviceV1_0>(device: &D, command_buffer: CommandBuffer) {
+    let barrier = MemoryBarrier {
+        s_type: StructureType::MemoryBarrier,
+        p_next: ptr::null(),
+        src_access_mask: ACCESS_SHADER_WRITE_BIT,
+        dst_access_mask: ACCESS_SHADER_READ_BIT | ACCESS_SHADER_WRITE_BIT,
+    };
+    device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[barrier], &[], &[]);
+}
+
+pub struct ComputeKernel<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    set_layout: DescriptorSetLayout,
+    layout: PipelineLayout,
+    pipeline: Pipeline,
+    push_constant_size: u32,
+}
+
+impl<'d, D: DeviceV1_0> ComputeKernel<'d, D> {
+    /// Creates a kernel from SPIR-V with a `main` entry point, one descriptor set laid out by
+    /// `bindings`, and `push_constant_size` bytes of push constants (0 for none).
+    pub fn new(device: &'d D, spirv: &[u8], bindings: &[DescriptorSetLayoutBinding], push_constant_size: u32) -> VkResult<ComputeKernel<'d, D>> {
+        let mut ret = ComputeKernel {
+            device: device,
+            set_layout: DescriptorSetLayout::null(),
+            layout: PipelineLayout::null(),
+            pipeline: Pipeline::null(),
+            push_constant_size: push_constant_size,
+        };
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        ret.set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        let push_constant_range = PushConstantRange {
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            offset: 0,
+            size: push_constant_size,
+        };
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 1,
+            p_set_layouts: &ret.set_layout,
+            push_constant_range_count: if push_constant_size > 0 { 1 } else { 0 },
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+
+        let shader_create_info = ShaderModuleCreateInfo {
+            s_type: StructureType::ShaderModuleCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            code_size: spirv.len(),
+            p_code: spirv.as_ptr() as *const u32,
+        };
+        let shader = unsafe { device.create_shader_module(&shader_create_info, None) }?;
+        let layout = ret.layout;
+        let pipelines = ShaderStage::compute(shader).with_create_info(|stage| {
+            let pipeline_create_info = ComputePipelineCreateInfo {
+                s_type: StructureType::ComputePipelineCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                stage: stage,
+                layout: layout,
+                base_pipeline_handle: Pipeline::null(),
+                base_pipeline_index: -1,
+            };
+            unsafe { device.create_compute_pipelines(PipelineCache::null(), &[pipeline_create_info], None) }
+        });
+        unsafe { device.destroy_shader_module(shader, None); }
+        ret.pipeline = pipelines.map_err(|(_, res)| res)?[0];
+        Ok(ret)
+    }
+
+    pub fn set_layout(&self) -> DescriptorSetLayout {
+        self.set_layout
+    }
+
+    /// Binds the kernel and `descriptor_set`, pushes `push_constants` (which must be
+    /// `push_constant_size` bytes), and dispatches `group_count` workgroups.
+    pub unsafe fn cmd_dispatch<T: Copy>(&self, command_buffer: CommandBuffer, descriptor_set: DescriptorSet, push_constants: &T, group_count: (u32, u32, u32)) {
+        assert_eq!(std::mem::size_of::<T>() as u32, self.push_constant_size);
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Compute, self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Compute, self.layout, 0, &[descriptor_set], &[]);
+        if self.push_constant_size > 0 {
+            self.device.fp_v1_0().cmd_push_constants(command_buffer, self.layout, SHADER_STAGE_COMPUTE_BIT, 0, self.push_constant_size, push_constants as *const T as *const c_void);
+        }
+        self.device.cmd_dispatch(command_buffer, group_count.0, group_count.1, group_count.2);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for ComputeKernel<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying compute pipeline: {:?}", self.pipeline);
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+/// A host-visible storage buffer, also usable as a transfer source and destination.
+pub struct StorageBuffer<'d, D: DeviceV1_0 + 'd> {
+    buffer: DeviceBuffer<'d, D>,
+}
+
+impl<'d, D: DeviceV1_0> StorageBuffer<'d, D> {
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, size: DeviceSize) -> VkResult<StorageBuffer<'d, D>> {
+        let usage = BUFFER_USAGE_STORAGE_BUFFER_BIT | BUFFER_USAGE_TRANSFER_SRC_BIT | BUFFER_USAGE_TRANSFER_DST_BIT;
+        Ok(StorageBuffer {
+            buffer: DeviceBuffer::host_visible(device, allocator, size, usage)?,
+        })
+    }
+
+    /// Creates a buffer holding `data`.
+    pub fn with_data<T: Copy>(device: &'d D, allocator: &'d MemoryAllocator<D>, data: &[T]) -> VkResult<StorageBuffer<'d, D>> {
+        let size = std::cmp::max(std::mem::size_of_val(data), 4) as DeviceSize;
+        let ret = StorageBuffer::new(device, allocator, size)?;
+        ret.write(data)?;
+        Ok(ret)
+    }
+
+    /// Copies `data` to the start of the buffer. The GPU must not be using it.
+    pub fn write<T: Copy>(&self, data: &[T]) -> VkResult<()> {
+        self.buffer.write(0, data)
+    }
+
+    /// Reads `count` values from the start of the buffer. Shader writes have to have been made
+    /// visible to the host first.
+    pub fn read<T: Copy>(&self, count: usize) -> Vec<T> {
+        self.buffer.read(0, count)
+    }
+
+    pub fn buffer(&self) -> Buffer {
+        self.buffer.buffer()
+    }
+
+    pub fn size(&self) -> DeviceSize {
+        self.buffer.size()
+    }
+
+    /// The whole buffer as a storage buffer descriptor at `binding`.
+    pub fn descriptor_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::StorageBuffer {
+            binding: binding,
+            buffer: self.buffer(),
+            offset: 0,
+            range: self.size(),
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! Headless compute shader tests. `--compute-tests` skips the window entirely, creates a device
+//! without a surface (so a software implementation like lavapipe works on CI machines), and runs
+//! small compute shaders over known inputs, checking the read-back outputs against the CPU side
+//! of the same math.
+//!
+//! Test shaders read `uint count` from push constants, their input from binding 0 and write
+//! their output to binding 1 of set 0, with a local size of `LOCAL_SIZE`.
+use ash;
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, EntryV1_0, InstanceV1_0, V1_0 };
+use std;
+use std::ffi::{ CStr, CString };
+use std::ptr;
+use vk::types::*;
+use ::chunked::{ ChunkPushConstants, ChunkedDispatch };
+use ::compute::{ self, ComputeKernel, StorageBuffer };
+use ::cvar::Flag;
+use ::descriptor::{ self, DescriptorAllocator };
+use ::gpu_algo::{ self, ExclusiveScan };
+use ::immediate::ImmediateContext;
+use ::math::Vec3;
+use ::math::prelude::div_ceil;
+use ::quantize;
+use ::queue::{ self, QueueFamilyIndex, QueueIndex };
+use ::radix_sort::{ self, RadixSort };
+use ::readback::Readback;
+use ::safe_create::{ CreateDeviceSafeV1_0, SafeDeviceV1_0 };
+use ::subgroup::{ self, Reduction, ReductionPath, SubgroupProperties };
+use ::vk_mem::MemoryAllocator;
+
+pub const LOCAL_SIZE: u32 = 64;
+
+pub const ARG: &'static str = "--compute-tests";
+pub const CVAR: &'static str = "test.compute";
+pub const FLAGS: &'static [Flag] = &[Flag::Switch { arg: ARG, name: CVAR }];
+
+const INPUT_BINDING: u32 = 0;
+const OUTPUT_BINDING: u32 = 1;
+
+/// The loader and an instance without any extensions, for a `ComputeHarness`.
+pub struct ComputeInstance {
+    // The loader has to outlive the instance
+    entry: ash::Entry<V1_0>,
+    instance: ash::Instance<V1_0>,
+}
+
+impl ComputeInstance {
+    pub fn new() -> std::result::Result<ComputeInstance, String> {
+        let entry: ash::Entry<V1_0> = ash::Entry::new().map_err(|e| format!("Failed to load Vulkan: {:?}", e))?;
+        let application_name = CString::new("Smolder compute tests").unwrap();
+        let application_info = ApplicationInfo {
+            s_type: StructureType::ApplicationInfo,
+            p_next: ptr::null(),
+            p_application_name: application_name.as_ptr(),
+            application_version: vk_make_version!(0, 1, 0),
+            p_engine_name: application_name.as_ptr(),
+            engine_version: vk_make_version!(0, 1, 0),
+            api_version: subgroup::instance_api_version(&entry),
+        };
+        let instance_create_info = InstanceCreateInfo {
+            s_type: StructureType::InstanceCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            p_application_info: &application_info,
+            enabled_layer_count: 0,
+            pp_enabled_layer_names: ptr::null(),
+            enabled_extension_count: 0,
+            pp_enabled_extension_names: ptr::null(),
+        };
+        let instance = entry.create_instance(&instance_create_info, None).map_err(|e| format!("Failed to create instance: {:?}", e))?;
+        Ok(ComputeInstance {
+            entry: entry,
+            instance: instance,
+        })
+    }
+}
+
+impl Drop for ComputeInstance {
+    fn drop(&mut self) {
+        debug!("Destroying instance");
+        unsafe { self.instance.destroy_instance(None); }
+    }
+}
+
+/// A device with a compute queue and nothing else.
+pub struct ComputeHarness<'i> {
+    device: SafeDeviceV1_0<'i>,
+    queue_family_index: QueueFamilyIndex,
+    queue: Queue,
+    allocator: MemoryAllocator<ash::Device<V1_0>>,
+    subgroup_properties: Option<SubgroupProperties>,
+}
+
+impl<'i> ComputeHarness<'i> {
+    /// Uses the first physical device with a compute queue, whatever its type.
+    pub fn new(compute_instance: &'i ComputeInstance) -> std::result::Result<ComputeHarness<'i>, String> {
+        let instance = &compute_instance.instance;
+        let found = instance.enumerate_physical_devices().unwrap_or(vec![]).into_iter()
+            .filter_map(|dev| {
+                QueueFamilyIndex::enumerate(&instance.get_physical_device_queue_family_properties(dev))
+                    .find(|&(_, family)| family.queue_count > 0 && family.queue_flags.subset(QUEUE_COMPUTE_BIT))
+                    .map(|(idx, _)| (dev, idx))
+            })
+            .next();
+        let (physical_device, queue_family_index) = found.ok_or_else(|| "No physical device with a compute queue".to_string())?;
+        let properties = instance.get_physical_device_properties(physical_device);
+        info!("Running compute tests on {:?}", unsafe { CStr::from_ptr(properties.device_name.as_ptr()) });
+
+        let queue_priorities = [1.0];
+        let queue_create_info = DeviceQueueCreateInfo {
+            s_type: StructureType::DeviceQueueCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            queue_family_index: queue_family_index.raw(),
+            queue_count: 1,
+            p_queue_priorities: queue_priorities.as_ptr(),
+        };
+        let features: PhysicalDeviceFeatures = Default::default();
+        let device_create_info = DeviceCreateInfo {
+            s_type: StructureType::DeviceCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            queue_create_info_count: 1,
+            p_queue_create_infos: &queue_create_info,
+            enabled_layer_count: 0,
+            pp_enabled_layer_names: ptr::null(),
+            enabled_extension_count: 0,
+            pp_enabled_extension_names: ptr::null(),
+            p_enabled_features: &features,
+        };
+        let device = instance.create_device_safe(physical_device, &device_create_info, None).map_err(|e| format!("Failed to create device: {:?}", e))?;
+        let queue = unsafe { queue::get_queue(&*device, queue_family_index, QueueIndex::FIRST) };
+        let subgroup_properties = SubgroupProperties::query(&compute_instance.entry, instance, physical_device);
+        match subgroup_properties {
+            Some(ref properties) => properties.log(),
+            None => info!("Subgroup properties aren't available"),
+        }
+        Ok(ComputeHarness {
+            allocator: MemoryAllocator::new((*device).clone(), instance.get_physical_device_memory_properties(physical_device)),
+            subgroup_properties: subgroup_properties,
+            device: device,
+            queue_family_index: queue_family_index,
+            queue: queue,
+        })
+    }
+
+    pub fn device(&self) -> &ash::Device<V1_0> {
+        &self.device
+    }
+
+    pub fn allocator(&self) -> &MemoryAllocator<ash::Device<V1_0>> {
+        &self.allocator
+    }
+
+    pub fn subgroup_properties(&self) -> Option<&SubgroupProperties> {
+        self.subgroup_properties.as_ref()
+    }
+
+    /// Records `f` into a command buffer and waits for it to execute.
+    pub fn submit<R, F: FnOnce(CommandBuffer) -> R>(&self, f: F) -> VkResult<R> {
+        let immediate = ImmediateContext::new(&*self.device, self.queue_family_index, self.queue)?;
+        immediate.immediate_submit(f)
+    }
+
+    /// A context for submitting through directly, e.g. for work split over several submissions.
+    pub fn immediate<'a>(&'a self) -> VkResult<ImmediateContext<'a, ash::Device<V1_0>>> {
+        ImmediateContext::new(&*self.device, self.queue_family_index, self.queue)
+    }
+
+    /// Reads `count` values of `T` from the start of `buffer`, after compute shader writes.
+    pub fn read_buffer<T: Copy>(&self, buffer: Buffer, count: usize) -> VkResult<Vec<T>> {
+        let immediate = ImmediateContext::new(&*self.device, self.queue_family_index, self.queue)?;
+        let size = std::cmp::max(count * std::mem::size_of::<T>(), 4) as DeviceSize;
+        let mut readback = Readback::new(&*self.device, &self.allocator, size)?;
+        readback.read_buffer(&immediate, buffer, 0, count, PIPELINE_STAGE_COMPUTE_SHADER_BIT, ACCESS_SHADER_WRITE_BIT)
+    }
+
+    /// Runs the compute shader in `spirv` once per output element, over `input`, and reads back
+    /// `output_count` values of `O`.
+    pub fn dispatch<I: Copy, O: Copy>(&self, spirv: &[u8], input: &[I], output_count: usize) -> VkResult<Vec<O>> {
+        let count = output_count as u32;
+        self.dispatch_groups(spirv, input, output_count, count, div_ceil(count, LOCAL_SIZE))
+    }
+
+    /// Like `dispatch`, for shaders that don't produce one output per invocation: `count` is
+    /// pushed to the shader as is, and `group_count` workgroups are dispatched.
+    pub fn dispatch_groups<I: Copy, O: Copy>(&self, spirv: &[u8], input: &[I], output_count: usize, count: u32, group_count: u32) -> VkResult<Vec<O>> {
+        let device = &*self.device;
+        let input_buffer = StorageBuffer::with_data(device, &self.allocator, input)?;
+        let output_buffer = StorageBuffer::new(device, &self.allocator, std::cmp::max(output_count * std::mem::size_of::<O>(), 4) as DeviceSize)?;
+        let kernel = ComputeKernel::new(device, spirv, &compute::storage_buffer_bindings(2), std::mem::size_of::<u32>() as u32)?;
+
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: 2,
+        }];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, 1);
+        let descriptor_set = descriptor_allocator.allocate(kernel.set_layout())?;
+        descriptor::update_descriptor_set(device, descriptor_set, &[input_buffer.descriptor_write(INPUT_BINDING), output_buffer.descriptor_write(OUTPUT_BINDING)]);
+
+        self.submit(|command_buffer| unsafe {
+            kernel.cmd_dispatch(command_buffer, descriptor_set, &count, (group_count, 1, 1));
+        })?;
+        self.read_buffer(output_buffer.buffer(), output_count)
+    }
+}
+
+impl<'i> Drop for ComputeHarness<'i> {
+    fn drop(&mut self) {
+        let _ = self.device.device_wait_idle();
+        self.allocator.free_blocks();
+    }
+}
+
+pub struct ComputeTest {
+    pub name: &'static str,
+    pub run: fn(&ComputeHarness) -> std::result::Result<(), String>,
+}
+
+pub const TESTS: [ComputeTest; 7] = [
+    ComputeTest { name: "copy", run: test_copy },
+    ComputeTest { name: "octahedral_decode", run: test_octahedral_decode },
+    ComputeTest { name: "reduce", run: test_reduce },
+    ComputeTest { name: "exclusive_scan", run: test_exclusive_scan },
+    ComputeTest { name: "exclusive_scan_in_place", run: test_exclusive_scan_in_place },
+    ComputeTest { name: "radix_sort", run: test_radix_sort },
+    ComputeTest { name: "chunked_copy", run: test_chunked_copy },
+];
+
+fn load_shader(path: &str) -> std::result::Result<Vec<u8>, String> {
+    ::shader_compile::load_spirv(path).map_err(|e| format!("Failed to load {}: {}", path, e))
+}
+
+/// Sanity check of the harness itself: the shader copies its input to its output.
+fn test_copy(harness: &ComputeHarness) -> std::result::Result<(), String> {
+    let spirv = load_shader("shaders/test_copy.comp.spv")?;
+    let input: Vec<u32> = (0..1000).map(|i| i * 7 + 3).collect();
+    let output: Vec<u32> = harness.dispatch(&spirv, &input, input.len()).map_err(|e| format!("{:?}", e))?;
+    match input.iter().zip(output.iter()).position(|(a, b)| a != b) {
+        Some(idx) => Err(format!("Element {} is {}, expected {}", idx, output[idx], input[idx])),
+        None => Ok(()),
+    }
+}
+
+/// Decodes octahedral normals from `quantize::octahedral_encode` with the same GLSL function the
+/// quantized mesh shader uses, and compares against `quantize::octahedral_decode`.
+fn test_octahedral_decode(harness: &ComputeHarness) -> std::result::Result<(), String> {
+    let spirv = load_shader("shaders/test_octahedral_decode.comp.spv")?;
+    // Points on a sphere, including the poles and the seams of the unfolded octahedron
+    let mut normals = vec![Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)];
+    for i in 0..32 {
+        for j in 0..16 {
+            let theta = i as f32 / 32.0 * 2.0 * std::f32::consts::PI;
+            let phi = j as f32 / 15.0 * std::f32::consts::PI;
+            normals.push(Vec3::new(theta.cos() * phi.sin(), theta.sin() * phi.sin(), phi.cos()));
+        }
+    }
+    let encoded: Vec<[i16; 2]> = normals.iter().map(|&n| quantize::octahedral_encode(n)).collect();
+    // Matches `unpackSnorm2x16`: x in the low half
+    let packed: Vec<u32> = encoded.iter().map(|e| (e[0] as u16 as u32) | ((e[1] as u16 as u32) << 16)).collect();
+    let output: Vec<[f32; 4]> = harness.dispatch(&spirv, &packed, packed.len()).map_err(|e| format!("{:?}", e))?;
+    for (idx, (e, out)) in encoded.iter().zip(output.iter()).enumerate() {
+        let expected = quantize::octahedral_decode(*e);
+        let actual = Vec3::new(out[0], out[1], out[2]);
+        if (actual - expected).length() > 1e-5 {
+            return Err(format!("Normal {} ({:?}) decoded to {:?} on the GPU, {:?} on the CPU", idx, normals[idx], actual, expected));
+        }
+    }
+    Ok(())
+}
+
+fn run_reduction(harness: &ComputeHarness, path: ReductionPath, values: &[u32]) -> std::result::Result<Reduction, String> {
+    let spirv = load_shader(path.shader_path())?;
+    let group_count = Reduction::group_count(values.len());
+    let partials: Vec<Reduction> = harness.dispatch_groups(&spirv, values, group_count as usize, values.len() as u32, group_count).map_err(|e| format!("{:?}", e))?;
+    Ok(Reduction::combine_all(&partials))
+}
+
+/// Sum/min/max over a count that doesn't fill the last workgroup, through the scalar path and,
+/// when the device supports it, the subgroup path.
+fn test_reduce(harness: &ComputeHarness) -> std::result::Result<(), String> {
+    let values: Vec<u32> = (0..1000u32).map(|i| i.wrapping_mul(2654435761) >> 20).collect();
+    let expected = Reduction::of(&values);
+    let mut paths = vec![ReductionPath::Scalar];
+    if ReductionPath::choose(harness.subgroup_properties()) == ReductionPath::Subgroup {
+        paths.push(ReductionPath::Subgroup);
+    } else {
+        info!("Skipping the subgroup reduction, subgroup arithmetic isn't supported in compute shaders");
+    }
+    for path in paths {
+        let actual = run_reduction(harness, path, &values)?;
+        if actual != expected {
+            return Err(format!("{:?} reduction gave {:?}, expected {:?}", path, actual, expected));
+        }
+    }
+    Ok(())
+}
+
+fn run_exclusive_scan(harness: &ComputeHarness, values: &[u32], in_place: bool) -> std::result::Result<(), String> {
+    let block_spirv = load_shader(gpu_algo::SCAN_BLOCK_SHADER)?;
+    let add_spirv = load_shader(gpu_algo::SCAN_ADD_SHADER)?;
+    let expected: Vec<u32> = values.iter().scan(0u32, |sum, &v| {
+        let ret = *sum;
+        *sum = sum.wrapping_add(v);
+        Some(ret)
+    }).collect();
+
+    let device = harness.device();
+    let count = values.len() as u32;
+    let result = (|| {
+        let input = StorageBuffer::with_data(device, harness.allocator(), values)?;
+        let output = StorageBuffer::new(device, harness.allocator(), input.size())?;
+        let output_buffer = if in_place { input.buffer() } else { output.buffer() };
+        let scan = ExclusiveScan::new(device, harness.allocator(), input.buffer(), output_buffer, count, &block_spirv, &add_spirv)?;
+        harness.submit(|command_buffer| unsafe { scan.cmd_scan(command_buffer, count) })?;
+        harness.read_buffer::<u32>(output_buffer, values.len())
+    })();
+    let actual = result.map_err(|e| format!("{:?}", e))?;
+    match expected.iter().zip(actual.iter()).position(|(a, b)| a != b) {
+        Some(idx) => Err(format!("Element {} is {}, expected {}", idx, actual[idx], expected[idx])),
+        None => Ok(()),
+    }
+}
+
+/// Enough elements for three levels of block sums, and a partial last block.
+fn test_exclusive_scan(harness: &ComputeHarness) -> std::result::Result<(), String> {
+    let values: Vec<u32> = (0..100000u32).map(|i| i.wrapping_mul(2654435761) >> 24).collect();
+    run_exclusive_scan(harness, &values, false)
+}
+
+fn test_exclusive_scan_in_place(harness: &ComputeHarness) -> std::result::Result<(), String> {
+    let values: Vec<u32> = (0..1000u32).map(|i| i % 7).collect();
+    run_exclusive_scan(harness, &values, true)
+}
+
+/// Sorts pairs with many duplicate keys across several workgroups, and checks the result
+/// against a stable sort on the CPU.
+fn test_radix_sort(harness: &ComputeHarness) -> std::result::Result<(), String> {
+    let histogram_spirv = load_shader(radix_sort::HISTOGRAM_SHADER)?;
+    let scatter_spirv = load_shader(radix_sort::SCATTER_SHADER)?;
+    let scan_block_spirv = load_shader(gpu_algo::SCAN_BLOCK_SHADER)?;
+    let scan_add_spirv = load_shader(gpu_algo::SCAN_ADD_SHADER)?;
+    let count = 3000u32;
+    let keys: Vec<u32> = (0..count).map(|i| i.wrapping_mul(2654435761) % 1024 * 4099).collect();
+    let values: Vec<u32> = (0..count).collect();
+    let mut expected: Vec<(u32, u32)> = keys.iter().cloned().zip(values.iter().cloned()).collect();
+    expected.sort_by_key(|&(key, _)| key);
+
+    let device = harness.device();
+    let result = (|| {
+        let key_buffer = StorageBuffer::with_data(device, harness.allocator(), &keys)?;
+        let value_buffer = StorageBuffer::with_data(device, harness.allocator(), &values)?;
+        let sort = RadixSort::new(device, harness.allocator(), key_buffer.buffer(), value_buffer.buffer(), count, &histogram_spirv, &scatter_spirv, &scan_block_spirv, &scan_add_spirv)?;
+        harness.submit(|command_buffer| unsafe { sort.cmd_sort(command_buffer, count) })?;
+        let sorted_keys: Vec<u32> = harness.read_buffer(key_buffer.buffer(), count as usize)?;
+        let sorted_values: Vec<u32> = harness.read_buffer(value_buffer.buffer(), count as usize)?;
+        Ok(sorted_keys.into_iter().zip(sorted_values).collect::<Vec<(u32, u32)>>())
+    })();
+    let actual = result.map_err(|e: Result| format!("{:?}", e))?;
+    match expected.iter().zip(actual.iter()).position(|(a, b)| a != b) {
+        Some(idx) => Err(format!("Pair {} is {:?}, expected {:?}", idx, actual[idx], expected[idx])),
+        None => Ok(()),
+    }
+}
+
+/// A copy dispatched a few rows of workgroups at a time by `ChunkedDispatch`, which has to cover
+/// every group exactly once across its chunks.
+fn test_chunked_copy(harness: &ComputeHarness) -> std::result::Result<(), String> {
+    const GROUPS_X: u32 = 4;
+    let spirv = load_shader("shaders/test_chunked_copy.comp.spv")?;
+    let device = harness.device();
+    let input: Vec<u32> = (0..5000).map(|i| i * 13 + 1).collect();
+    let count = input.len() as u32;
+    let rows = div_ceil(count, GROUPS_X * LOCAL_SIZE);
+    let input_buffer = StorageBuffer::with_data(device, harness.allocator(), &input).map_err(|e| format!("{:?}", e))?;
+    let output_buffer = StorageBuffer::new(device, harness.allocator(), (input.len() * std::mem::size_of::<u32>()) as DeviceSize).map_err(|e| format!("{:?}", e))?;
+    let kernel = ComputeKernel::new(device, &spirv, &compute::storage_buffer_bindings(2), ChunkPushConstants::<u32>::size()).map_err(|e| format!("{:?}", e))?;
+    let pool_sizes = [DescriptorPoolSize {
+        typ: DescriptorType::StorageBuffer,
+        descriptor_count: 2,
+    }];
+    let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, 1);
+    let descriptor_set = descriptor_allocator.allocate(kernel.set_layout()).map_err(|e| format!("{:?}", e))?;
+    descriptor::update_descriptor_set(device, descriptor_set, &[input_buffer.descriptor_write(INPUT_BINDING), output_buffer.descriptor_write(OUTPUT_BINDING)]);
+
+    let immediate = harness.immediate().map_err(|e| format!("{:?}", e))?;
+    let mut chunked = ChunkedDispatch::default();
+    // Two rows per chunk, so the dispatch takes several
+    chunked.set_groups_per_chunk(GROUPS_X * 2);
+    let progress = chunked.run(device, &immediate, &kernel, descriptor_set, &count, (GROUPS_X, rows, 1), |progress| progress.log("chunked_copy"))
+        .map_err(|e| format!("{:?}", e))?;
+    if !progress.is_done() || progress.chunks < 2 {
+        return Err(format!("Dispatched {} of {} groups in {} chunks", progress.groups_done, progress.groups_total, progress.chunks));
+    }
+    let output: Vec<u32> = harness.read_buffer(output_buffer.buffer(), input.len()).map_err(|e| format!("{:?}", e))?;
+    match input.iter().zip(output.iter()).position(|(a, b)| a != b) {
+        Some(idx) => Err(format!("Element {} is {}, expected {}", idx, output[idx], input[idx])),
+        None => Ok(()),
+    }
+}
+
+/// Whether `test.compute`, which `ARG` sets, is on.
+pub fn requested() -> bool {
+    cvar!(CVAR, false).get()
+}
+
+/// Runs every test in `TESTS`, logging each result. Returns whether all of them passed.
+pub fn run_all() -> bool {
+    let instance = match ComputeInstance::new() {
+        Ok(instance) => instance,
+        Err(e) => {
+            error!("Failed to set up the compute test harness: {}", e);
+            return false;
+        },
+    };
+    let harness = match ComputeHarness::new(&instance) {
+        Ok(harness) => harness,
+        Err(e) => {
+            error!("Failed to set up the compute test harness: {}", e);
+            return false;
+        },
+    };
+    let mut failed = 0;
+    for test in TESTS.iter() {
+        match (test.run)(&harness) {
+            Ok(()) => info!("{} ... ok", test.name),
+            Err(e) => {
+                error!("{} ... FAILED: {}", test.name, e);
+                failed += 1;
+            },
+        }
+    }
+    info!("{} compute tests passed, {} failed", TESTS.len() - failed, failed);
+    failed == 0
+}
@@ -0,0 +1,173 @@
+//! Diagnostic bundles for when things go wrong. On a panic, or when the device is lost, everything
+//! known about the run goes into `crash_reports/<unix time>/`, one file per part: the command line
+//! and options, the console variables, the device with its enabled extensions and features, the
+//! last log lines, the allocator's usage, and the last checkpoints the renderer passed.
+//!
+//! Parts are filled in as they become available, so a panic while setting up the device still
+//! gets a report with whatever was known by then.
+use std;
+use std::collections::VecDeque;
+use std::fs::{ self, File };
+use std::io::{ self, Write };
+use std::panic;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
+use std::time::{ Instant, SystemTime, UNIX_EPOCH };
+use ::log_console::LogHistory;
+use ::vk_mem::AllocatorStatsSource;
+
+pub const REPORT_DIR: &'static str = "crash_reports";
+/// Log lines written to a report.
+pub const LOG_LINES: usize = 200;
+/// Checkpoints kept for a report.
+pub const CHECKPOINTS: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Checkpoint {
+    at: Instant,
+    frame: u64,
+    what: &'static str,
+}
+
+/// The last few places the renderer got to, to tell where a frame was when it went wrong.
+#[derive(Clone, Default)]
+pub struct Checkpoints {
+    marks: Arc<Mutex<VecDeque<Checkpoint>>>,
+}
+
+impl Checkpoints {
+    pub fn mark(&self, frame: u64, what: &'static str) {
+        let mut marks = self.marks.lock().unwrap();
+        if marks.len() >= CHECKPOINTS {
+            marks.pop_front();
+        }
+        marks.push_back(Checkpoint {
+            at: Instant::now(),
+            frame: frame,
+            what: what,
+        });
+    }
+
+    /// Oldest first, with how long before `now` each one was. `None` if a mark is being added.
+    pub fn try_describe(&self, now: Instant) -> Option<String> {
+        self.marks.try_lock().ok().map(|marks| {
+            marks.iter()
+                .map(|mark| format!("frame {}: {} ({:.3} ms before the report)\n", mark.frame, mark.what, ::time::seconds(now.duration_since(mark.at)) * 1000.0))
+                .collect()
+        })
+    }
+}
+
+struct CrashInfo {
+    config: Option<String>,
+    device: Option<String>,
+    history: LogHistory,
+    allocator: Option<AllocatorStatsSource>,
+    checkpoints: Checkpoints,
+    /// Only the first report is written; a device loss is usually followed by a panic about it.
+    written: bool,
+}
+
+/// Collects what goes into a report. Clones share the same parts.
+#[derive(Clone)]
+pub struct CrashReporter {
+    info: Arc<Mutex<CrashInfo>>,
+}
+
+impl CrashReporter {
+    pub fn new(history: LogHistory) -> CrashReporter {
+        CrashReporter {
+            info: Arc::new(Mutex::new(CrashInfo {
+                config: None,
+                device: None,
+                history: history,
+                allocator: None,
+                checkpoints: Checkpoints::default(),
+                written: false,
+            })),
+        }
+    }
+
+    pub fn set_config(&self, config: String) {
+        self.info.lock().unwrap().config = Some(config);
+    }
+
+    pub fn set_device(&self, device: String) {
+        self.info.lock().unwrap().device = Some(device);
+    }
+
+    pub fn set_allocator(&self, allocator: AllocatorStatsSource) {
+        self.info.lock().unwrap().allocator = Some(allocator);
+    }
+
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.info.lock().unwrap().checkpoints.clone()
+    }
+
+    /// Writes the bundle, returning its directory, or `None` if one was already written.
+    pub fn write(&self, reason: &str) -> io::Result<Option<PathBuf>> {
+        // A panic while the lock was held still leaves the parts usable
+        let mut info = match self.info.lock() {
+            Ok(info) => info,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if info.written {
+            return Ok(None);
+        }
+        info.written = true;
+        let now = Instant::now();
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(std::time::Duration::from_secs(0));
+        let dir = PathBuf::from(REPORT_DIR).join(format!("{}.{:03}", since_epoch.as_secs(), since_epoch.subsec_millis()));
+        fs::create_dir_all(&dir)?;
+        let unavailable = "(unavailable)\n".to_string();
+
+        write_file(&dir, "summary.txt", &format!("{}\n", reason))?;
+        // The options were read from the console variables these set, which are written too
+        let mut config = format!("Arguments: {:?}\n", ::cvar::args());
+        if let Some(ref options) = info.config {
+            config.push_str(&format!("Options: {}\n", options));
+        }
+        write_file(&dir, "config.txt", &config)?;
+        write_file(&dir, "cvars.txt", &::cvar::registry().try_describe().unwrap_or(unavailable.clone()))?;
+        write_file(&dir, "device.txt", info.device.as_ref().unwrap_or(&unavailable))?;
+        let log = info.history.try_recent(LOG_LINES)
+            .map(|lines| lines.iter().map(|line| format!("{}\n", line.text())).collect())
+            .unwrap_or(unavailable.clone());
+        write_file(&dir, "log.txt", &log)?;
+        let allocator = info.allocator.as_ref()
+            .and_then(|allocator| allocator.try_stats())
+            .map(|stats| format!("{}", stats))
+            .unwrap_or(unavailable.clone());
+        write_file(&dir, "allocator.txt", &allocator)?;
+        let checkpoints = info.checkpoints.try_describe(now).unwrap_or(unavailable.clone());
+        write_file(&dir, "checkpoints.txt", &checkpoints)?;
+        Ok(Some(dir))
+    }
+
+    /// Writes a report on any panic, after the previous hook has printed the message.
+    pub fn install_panic_hook(&self) {
+        let reporter = self.clone();
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |panic_info| {
+            previous(panic_info);
+            let payload = panic_info.payload();
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or("(no message)".to_string());
+            let reason = match panic_info.location() {
+                Some(location) => format!("Panicked at {}:{}: {}", location.file(), location.line(), message),
+                None => format!("Panicked: {}", message),
+            };
+            match reporter.write(&reason) {
+                Ok(Some(dir)) => error!("Wrote crash report to {:?}", dir),
+                Ok(None) => {},
+                Err(e) => error!("Failed to write crash report: {}", e),
+            }
+        }));
+    }
+}
+
+fn write_file(dir: &Path, name: &str, contents: &str) -> io::Result<()> {
+    let mut file = File::create(dir.join(name))?;
+    file.write_all(contents.as_bytes())
+}
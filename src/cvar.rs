@@ -0,0 +1,561 @@
+//! Console variables: named, typed values that can be tweaked while running, without each one
+//! needing its own plumbing. Code reads a variable through the handle `cvar!` returns, which
+//! registers it with its default the first time:
+//!
+//! ```ignore
+//! let spin_rate = cvar!("scene.spin_rate", 1.0).get();
+//! ```
+//!
+//! Everything else writes them by name through the one registry: `--cvar=name=value` on the
+//! command line, `--cvars=<path>` for a file of `name value` lines, and the log console's
+//! `/name value` commands. Values set before their variable is registered are kept as text and
+//! parsed once it is, so the command line doesn't need to know every variable up front.
+//!
+//! The command line is read once, by `init`, which also takes the `Flag`s that are shorter
+//! spellings of variables, like `--msaa=4` for `r.msaa`. Options are read back from their
+//! variables rather than from the arguments, and `args` keeps the arguments for crash reports.
+//!
+//! Names are dotted paths, by convention starting with the subsystem: `r.` for the renderer,
+//! `scene.` for the demo scene.
+use std;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, BufRead, BufReader };
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{ Mutex, Once };
+
+pub const ARG_PREFIX: &'static str = "--cvar=";
+pub const FILE_ARG_PREFIX: &'static str = "--cvars=";
+
+/// Repeats of a `Flag::List` are joined with this.
+pub const LIST_SEPARATOR: char = ',';
+
+/// Registers a console variable with a default if it isn't yet, and returns a typed handle to it.
+#[macro_export]
+macro_rules! cvar {
+    ($name:expr, $default:expr) => {
+        ::cvar::CVar::register($name, $default)
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CVarValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl CVarValue {
+    /// Parses `s` as the same kind of value as `self`.
+    pub fn parse_like(&self, s: &str) -> Option<CVarValue> {
+        let s = s.trim();
+        match *self {
+            CVarValue::Bool(_) => match s {
+                "1" | "true" | "on" => Some(CVarValue::Bool(true)),
+                "0" | "false" | "off" => Some(CVarValue::Bool(false)),
+                _ => None,
+            },
+            CVarValue::Int(_) => s.parse().ok().map(CVarValue::Int),
+            CVarValue::Float(_) => s.parse().ok().map(CVarValue::Float),
+            CVarValue::Str(_) => Some(CVarValue::Str(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for CVarValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CVarValue::Bool(b) => write!(f, "{}", b),
+            CVarValue::Int(i) => write!(f, "{}", i),
+            CVarValue::Float(x) => write!(f, "{}", x),
+            CVarValue::Str(ref s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// Types a console variable can have.
+pub trait CVarType: Sized {
+    fn to_value(&self) -> CVarValue;
+    fn from_value(value: &CVarValue) -> Option<Self>;
+}
+
+impl CVarType for bool {
+    fn to_value(&self) -> CVarValue {
+        CVarValue::Bool(*self)
+    }
+
+    fn from_value(value: &CVarValue) -> Option<bool> {
+        match *value {
+            CVarValue::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! int_cvar_type {
+    ($t:ty) => {
+        impl CVarType for $t {
+            fn to_value(&self) -> CVarValue {
+                CVarValue::Int(*self as i64)
+            }
+
+            fn from_value(value: &CVarValue) -> Option<$t> {
+                match *value {
+                    CVarValue::Int(i) if i >= (<$t>::min_value() as i64) && i <= (<$t>::max_value() as i64) => Some(i as $t),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+int_cvar_type!(i32);
+int_cvar_type!(u32);
+int_cvar_type!(i64);
+
+macro_rules! float_cvar_type {
+    ($t:ty) => {
+        impl CVarType for $t {
+            fn to_value(&self) -> CVarValue {
+                CVarValue::Float(*self as f64)
+            }
+
+            fn from_value(value: &CVarValue) -> Option<$t> {
+                match *value {
+                    CVarValue::Float(x) => Some(x as $t),
+                    CVarValue::Int(i) => Some(i as $t),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+float_cvar_type!(f32);
+float_cvar_type!(f64);
+
+impl CVarType for String {
+    fn to_value(&self) -> CVarValue {
+        CVarValue::Str(self.clone())
+    }
+
+    fn from_value(value: &CVarValue) -> Option<String> {
+        match *value {
+            CVarValue::Str(ref s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Registered { value: CVarValue, default: CVarValue },
+    /// Set by name before anything registered it.
+    Pending(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CVarError {
+    /// The value doesn't parse as the variable's type.
+    InvalidValue { name: String, value: String },
+    Syntax(String),
+}
+
+impl fmt::Display for CVarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CVarError::InvalidValue { ref name, ref value } => write!(f, "{:?} isn't a valid value for {}", value, name),
+            CVarError::Syntax(ref line) => write!(f, "expected `name value`, got {:?}", line),
+        }
+    }
+}
+
+/// A command line spelling of a console variable, which `init` turns into setting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    /// `arg` on its own sets the bool variable `name`.
+    Switch { arg: &'static str, name: &'static str },
+    /// `<prefix><value>` sets `name` to `value`. The last one wins.
+    Value { prefix: &'static str, name: &'static str },
+    /// `arg=value` or `arg value` sets `name` to `value`. The last one wins.
+    Separable { arg: &'static str, name: &'static str },
+    /// Every `<prefix><value>`, joined by `LIST_SEPARATOR`, sets `name`.
+    List { prefix: &'static str, name: &'static str },
+}
+
+impl Flag {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Flag::Switch { name, .. } | Flag::Value { name, .. } | Flag::Separable { name, .. } | Flag::List { name, .. } => name,
+        }
+    }
+
+    /// The value `args` give the variable, if any mention it.
+    fn value_in(&self, args: &[String]) -> std::result::Result<Option<String>, String> {
+        let value_after = |prefix: &str| args.iter().filter(|arg| arg.starts_with(prefix)).map(|arg| arg[prefix.len()..].to_string()).next_back();
+        match *self {
+            Flag::Switch { arg, .. } => Ok(if args.iter().any(|candidate| candidate == arg) { Some("true".to_string()) } else { None }),
+            Flag::Value { prefix, .. } => Ok(value_after(prefix)),
+            Flag::Separable { arg, .. } => {
+                let prefix = format!("{}=", arg);
+                let mut ret = None;
+                for (idx, candidate) in args.iter().enumerate() {
+                    if candidate.starts_with(&prefix) {
+                        ret = Some(candidate[prefix.len()..].to_string());
+                    } else if candidate == arg {
+                        match args.get(idx + 1) {
+                            Some(value) => ret = Some(value.clone()),
+                            None => return Err(format!("{} needs a value", arg)),
+                        }
+                    }
+                }
+                Ok(ret)
+            },
+            Flag::List { prefix, .. } => {
+                let values: Vec<&str> = args.iter().filter(|arg| arg.starts_with(prefix)).map(|arg| &arg[prefix.len()..]).collect();
+                Ok(if values.is_empty() { None } else { Some(values.join(&LIST_SEPARATOR.to_string())) })
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Registry {
+    vars: Mutex<BTreeMap<String, Entry>>,
+}
+
+impl Registry {
+    fn register(&self, name: &str, default: CVarValue) {
+        let mut vars = self.vars.lock().unwrap();
+        let value = match vars.get(name) {
+            Some(&Entry::Registered { .. }) => return,
+            Some(Entry::Pending(text)) => default.parse_like(text).unwrap_or_else(|| {
+                warn!("Ignoring {:?} for console variable {}, it isn't a valid value", text, name);
+                default.clone()
+            }),
+            None => default.clone(),
+        };
+        vars.insert(name.to_string(), Entry::Registered {
+            value: value,
+            default: default,
+        });
+    }
+
+    /// The current value, or `None` if `name` hasn't been registered.
+    pub fn get(&self, name: &str) -> Option<CVarValue> {
+        match self.vars.lock().unwrap().get(name) {
+            Some(Entry::Registered { value, .. }) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Sets `name` from text, parsed as the variable's type. Unregistered names keep the text
+    /// until they are.
+    pub fn set_str(&self, name: &str, text: &str) -> std::result::Result<(), CVarError> {
+        let mut vars = self.vars.lock().unwrap();
+        if let Some(&mut Entry::Registered { ref mut value, .. }) = vars.get_mut(name) {
+            *value = value.parse_like(text).ok_or(CVarError::InvalidValue {
+                name: name.to_string(),
+                value: text.to_string(),
+            })?;
+            debug!("Set console variable {} to {}", name, value);
+            return Ok(());
+        }
+        vars.insert(name.to_string(), Entry::Pending(text.to_string()));
+        Ok(())
+    }
+
+    /// Back to the registered default.
+    pub fn reset(&self, name: &str) {
+        if let Some(&mut Entry::Registered { ref mut value, ref default }) = self.vars.lock().unwrap().get_mut(name) {
+            *value = default.clone();
+        }
+    }
+
+    /// Runs one `name value` line, as typed in the console or read from a file. Just a name
+    /// logs the current value, and `name default` resets it.
+    pub fn execute(&self, line: &str) -> std::result::Result<(), CVarError> {
+        let line = line.trim();
+        let mut parts = line.splitn(2, char::is_whitespace);
+        match (parts.next(), parts.next().map(|value| value.trim())) {
+            (Some(""), _) | (None, _) => Err(CVarError::Syntax(line.to_string())),
+            (Some(name), None) => {
+                match self.get(name) {
+                    Some(value) => info!("{} = {}", name, value),
+                    None => info!("{} isn't registered", name),
+                }
+                Ok(())
+            },
+            (Some(name), Some("default")) => {
+                self.reset(name);
+                Ok(())
+            },
+            (Some(name), Some(value)) => self.set_str(name, value),
+        }
+    }
+
+    /// Applies every `--cvars=` file, then the `flags` found in `args`, then every
+    /// `--cvar=name=value`, so the most explicit spelling wins. Returns what couldn't be applied.
+    pub fn apply_args(&self, args: &[String], flags: &[Flag]) -> Vec<String> {
+        let mut problems = Vec::new();
+        for path in args.iter().filter(|arg| arg.starts_with(FILE_ARG_PREFIX)).map(|arg| &arg[FILE_ARG_PREFIX.len()..]) {
+            if let Err(e) = self.load_file(path) {
+                problems.push(format!("Failed to read console variables from {:?}: {}", path, e));
+            }
+        }
+        for flag in flags {
+            let res = flag.value_in(args).and_then(|value| match value {
+                Some(value) => self.set_str(flag.name(), &value).map_err(|e| e.to_string()),
+                None => Ok(()),
+            });
+            if let Err(e) = res {
+                problems.push(format!("Ignoring {}: {}", flag.name(), e));
+            }
+        }
+        for assignment in args.iter().filter(|arg| arg.starts_with(ARG_PREFIX)).map(|arg| &arg[ARG_PREFIX.len()..]) {
+            let mut parts = assignment.splitn(2, '=');
+            let res = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if !name.is_empty() => self.set_str(name, value),
+                _ => Err(CVarError::Syntax(assignment.to_string())),
+            };
+            if let Err(e) = res {
+                problems.push(format!("Ignoring {}{}: {}", ARG_PREFIX, assignment, e));
+            }
+        }
+        problems
+    }
+
+    /// Runs each line of the file at `path`, skipping blank lines and `#` comments.
+    pub fn load_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let reader = BufReader::new(File::open(path.as_ref())?);
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = self.execute(line) {
+                warn!("{:?} line {}: {}", path.as_ref(), idx + 1, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every variable as a `name value` line, which `load_file` reads back. `None` if the
+    /// registry is busy, so it's safe to call from a panic hook.
+    pub fn try_describe(&self) -> Option<String> {
+        self.vars.try_lock().ok().map(|vars| {
+            vars.iter().map(|(name, entry)| match *entry {
+                Entry::Registered { ref value, ref default } if value != default => format!("{} {}  # default {}\n", name, value, default),
+                Entry::Registered { ref value, .. } => format!("{} {}\n", name, value),
+                Entry::Pending(ref text) => format!("# {} {}  (not registered)\n", name, text),
+            }).collect()
+        })
+    }
+}
+
+static REGISTRY_INIT: Once = Once::new();
+static mut REGISTRY: *const Registry = std::ptr::null::<Registry>();
+
+/// The process-wide registry every `cvar!` goes through.
+pub fn registry() -> &'static Registry {
+    unsafe {
+        // Never freed, like any other static
+        REGISTRY_INIT.call_once(|| REGISTRY = Box::into_raw(Box::new(Registry::default())));
+        &*REGISTRY
+    }
+}
+
+static ARGS_INIT: Once = Once::new();
+static mut ARGS: *const Vec<String> = std::ptr::null::<Vec<String>>();
+
+/// Reads the process's command line into the registry (see `Registry::apply_args`) with the
+/// `flags` of every module that has some. Only the first call reads anything. Returns what
+/// couldn't be applied, for logging once there's a logger.
+pub fn init(flags: &[&[Flag]]) -> Vec<String> {
+    let mut problems = Vec::new();
+    unsafe {
+        ARGS_INIT.call_once(|| {
+            let args: Vec<String> = std::env::args().collect();
+            let flags: Vec<Flag> = flags.iter().flat_map(|flags| flags.iter().cloned()).collect();
+            problems = registry().apply_args(&args, &flags);
+            ARGS = Box::into_raw(Box::new(args));
+        });
+    }
+    problems
+}
+
+/// The command line `init` read, program name first. Empty before `init`.
+pub fn args() -> &'static [String] {
+    unsafe {
+        if ARGS.is_null() {
+            &[]
+        } else {
+            &*ARGS
+        }
+    }
+}
+
+/// A handle to a registered console variable of type `T`.
+#[derive(Debug)]
+pub struct CVar<T: CVarType> {
+    name: &'static str,
+    phantom: PhantomData<T>,
+}
+
+// Deriving these would require `T` to be `Copy` too
+impl<T: CVarType> Clone for CVar<T> {
+    fn clone(&self) -> CVar<T> {
+        *self
+    }
+}
+
+impl<T: CVarType> Copy for CVar<T> {}
+
+impl<T: CVarType> CVar<T> {
+    /// Use `cvar!` instead.
+    pub fn register(name: &'static str, default: T) -> CVar<T> {
+        registry().register(name, default.to_value());
+        CVar {
+            name: name,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The current value. Panics if the name was registered with a different type.
+    pub fn get(&self) -> T {
+        registry().get(self.name).and_then(|value| T::from_value(&value)).unwrap_or_else(|| {
+            panic!("Console variable {} was registered with another type", self.name)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_like_keeps_the_type() {
+        assert_eq!(CVarValue::Bool(false).parse_like("on"), Some(CVarValue::Bool(true)));
+        assert_eq!(CVarValue::Bool(true).parse_like(" 0 "), Some(CVarValue::Bool(false)));
+        assert_eq!(CVarValue::Bool(true).parse_like("yes"), None);
+        assert_eq!(CVarValue::Int(0).parse_like("-42"), Some(CVarValue::Int(-42)));
+        assert_eq!(CVarValue::Int(0).parse_like("4.2"), None);
+        assert_eq!(CVarValue::Float(0.0).parse_like("4.5"), Some(CVarValue::Float(4.5)));
+        assert_eq!(CVarValue::Str(String::new()).parse_like(" two words "), Some(CVarValue::Str("two words".to_string())));
+    }
+
+    #[test]
+    fn typed_conversions() {
+        assert_eq!(u32::from_value(&CVarValue::Int(-1)), None);
+        assert_eq!(u32::from_value(&CVarValue::Int(7)), Some(7));
+        assert_eq!(i32::from_value(&CVarValue::Int(1 << 40)), None);
+        assert_eq!(f32::from_value(&CVarValue::Int(2)), Some(2.0));
+        assert_eq!(bool::from_value(&CVarValue::Int(1)), None);
+    }
+
+    #[test]
+    fn set_before_register_is_kept_until_registration() {
+        let registry = Registry::default();
+        registry.set_str("r.pending", "12").unwrap();
+        assert_eq!(registry.get("r.pending"), None);
+        registry.register("r.pending", CVarValue::Int(1));
+        assert_eq!(registry.get("r.pending"), Some(CVarValue::Int(12)));
+        // Registering again keeps the value
+        registry.register("r.pending", CVarValue::Int(1));
+        assert_eq!(registry.get("r.pending"), Some(CVarValue::Int(12)));
+    }
+
+    #[test]
+    fn invalid_pending_value_falls_back_to_the_default() {
+        let registry = Registry::default();
+        registry.set_str("r.flag", "maybe").unwrap();
+        registry.register("r.flag", CVarValue::Bool(true));
+        assert_eq!(registry.get("r.flag"), Some(CVarValue::Bool(true)));
+    }
+
+    #[test]
+    fn execute_lines() {
+        let registry = Registry::default();
+        registry.register("scene.spin_rate", CVarValue::Float(1.0));
+        registry.execute("scene.spin_rate 2.5").unwrap();
+        assert_eq!(registry.get("scene.spin_rate"), Some(CVarValue::Float(2.5)));
+        registry.execute("  scene.spin_rate  ").unwrap();
+        assert_eq!(registry.get("scene.spin_rate"), Some(CVarValue::Float(2.5)));
+        registry.execute("scene.spin_rate default").unwrap();
+        assert_eq!(registry.get("scene.spin_rate"), Some(CVarValue::Float(1.0)));
+        assert_eq!(registry.execute("scene.spin_rate fast"), Err(CVarError::InvalidValue {
+            name: "scene.spin_rate".to_string(),
+            value: "fast".to_string(),
+        }));
+        assert_eq!(registry.execute("   "), Err(CVarError::Syntax(String::new())));
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn apply_args_sets_by_name() {
+        let registry = Registry::default();
+        registry.register("r.vsync", CVarValue::Bool(true));
+        let problems = registry.apply_args(&args(&["--width=640", "--cvar=r.vsync=off", "--cvar=r.title=a=b", "--cvar==1"]), &[]);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(registry.get("r.vsync"), Some(CVarValue::Bool(false)));
+        registry.register("r.title", CVarValue::Str(String::new()));
+        assert_eq!(registry.get("r.title"), Some(CVarValue::Str("a=b".to_string())));
+    }
+
+    #[test]
+    fn flags_set_their_variables() {
+        let flags = [
+            Flag::Switch { arg: "--hdr", name: "r.hdr" },
+            Flag::Value { prefix: "--msaa=", name: "r.msaa" },
+            Flag::Separable { arg: "--width", name: "window.width" },
+            Flag::List { prefix: "--effect-scale=", name: "r.effect_scale" },
+            Flag::Switch { arg: "--checkerboard", name: "r.checkerboard" },
+        ];
+        let registry = Registry::default();
+        registry.register("r.hdr", CVarValue::Bool(false));
+        registry.register("r.checkerboard", CVarValue::Bool(false));
+        let problems = registry.apply_args(&args(&["--hdr", "--msaa=2", "--msaa=4", "--width", "800", "--effect-scale=ssao:full", "--effect-scale=fog:quarter"]), &flags);
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(registry.get("r.hdr"), Some(CVarValue::Bool(true)));
+        assert_eq!(registry.get("r.checkerboard"), Some(CVarValue::Bool(false)));
+        registry.register("r.msaa", CVarValue::Str(String::new()));
+        assert_eq!(registry.get("r.msaa"), Some(CVarValue::Str("4".to_string())));
+        registry.register("window.width", CVarValue::Int(1280));
+        assert_eq!(registry.get("window.width"), Some(CVarValue::Int(800)));
+        registry.register("r.effect_scale", CVarValue::Str(String::new()));
+        assert_eq!(registry.get("r.effect_scale"), Some(CVarValue::Str("ssao:full,fog:quarter".to_string())));
+    }
+
+    #[test]
+    fn separable_flags_take_the_last_value_either_way() {
+        let width = Flag::Separable { arg: "--width", name: "window.width" };
+        assert_eq!(width.value_in(&args(&["--width=1", "--width", "2", "--width=3"])), Ok(Some("3".to_string())));
+        assert!(width.value_in(&args(&["--width"])).is_err());
+        assert_eq!(width.value_in(&args(&["--widths=4"])), Ok(None));
+    }
+
+    #[test]
+    fn explicit_cvars_win_over_flags() {
+        let registry = Registry::default();
+        registry.register("r.msaa", CVarValue::Str(String::new()));
+        registry.apply_args(&args(&["--cvar=r.msaa=8", "--msaa=4"]), &[Flag::Value { prefix: "--msaa=", name: "r.msaa" }]);
+        assert_eq!(registry.get("r.msaa"), Some(CVarValue::Str("8".to_string())));
+    }
+
+    #[test]
+    fn describe_marks_changed_and_pending_variables() {
+        let registry = Registry::default();
+        registry.register("a.same", CVarValue::Int(1));
+        registry.register("b.changed", CVarValue::Int(1));
+        registry.set_str("b.changed", "2").unwrap();
+        registry.set_str("c.pending", "x").unwrap();
+        assert_eq!(registry.try_describe().unwrap(), "a.same 1\nb.changed 2  # default 1\n# c.pending x  (not registered)\n");
+    }
+}
@@ -0,0 +1,297 @@
+//! Immediate-mode debug line drawing. Shapes are accumulated on the CPU into a list of colored
+//! line segments each frame, copied into a host-visible vertex buffer, and drawn with a single
+//! `LineList` pipeline using the view-projection matrix from a push constant.
+//!
+//! Line widths other than 1 need the `wideLines` feature and are rasterized differently between
+//! implementations, so wide lines go through a second pipeline instead: each segment is an
+//! instance whose quad is expanded in screen space by `shaders/thick_line_vertex.glsl`, and
+//! `shaders/thick_line_fragment.glsl` rounds off the caps and antialiases the edges. Connected
+//! segments overlap at their round caps, which gives round joins.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use vk::types::*;
+use ::buffer::DeviceBuffer;
+use ::math::{ Mat4, Vec3 };
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::vk_mem::MemoryAllocator;
+
+pub const VERTEX_SHADER: &'static str = "shaders/debug_draw_vertex.vert.spv";
+pub const FRAGMENT_SHADER: &'static str = "shaders/debug_draw_fragment.frag.spv";
+pub const THICK_LINE_VERTEX_SHADER: &'static str = "shaders/thick_line_vertex.vert.spv";
+pub const THICK_LINE_FRAGMENT_SHADER: &'static str = "shaders/thick_line_fragment.frag.spv";
+
+pub const RED: [f32; 3] = [1.0, 0.0, 0.0];
+pub const GREEN: [f32; 3] = [0.0, 1.0, 0.0];
+pub const BLUE: [f32; 3] = [0.0, 0.0, 1.0];
+pub const YELLOW: [f32; 3] = [1.0, 1.0, 0.0];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+impl DebugVertex {
+    pub fn binding_descriptions() -> [VertexInputBindingDescription; 1] {
+        [VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<DebugVertex>() as u32,
+            input_rate: VertexInputRate::Vertex,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [VertexInputAttributeDescription; 2] {
+        [
+            VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: Format::R32g32b32Sfloat,
+                offset: 0,
+            },
+            VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: Format::R32g32b32Sfloat,
+                offset: 3 * std::mem::size_of::<f32>() as u32,
+            },
+        ]
+    }
+}
+
+/// Push constant block read by `shaders/debug_draw_vertex.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DebugDrawPushConstants {
+    pub view_projection: Mat4,
+}
+
+impl DebugDrawPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            offset: 0,
+            size: std::mem::size_of::<DebugDrawPushConstants>() as u32,
+        }
+    }
+}
+
+/// Push constant block read by `shaders/thick_line_vertex.glsl` and
+/// `shaders/thick_line_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ThickLinePushConstants {
+    pub view_projection: Mat4,
+    /// In pixels.
+    pub width: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl ThickLinePushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT | SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<ThickLinePushConstants>() as u32,
+        }
+    }
+}
+
+/// Vertex input for the thick line pipeline: the same buffer as the `LineList` pipeline, read a
+/// segment (two `DebugVertex`es) per instance.
+fn thick_line_binding_descriptions() -> [VertexInputBindingDescription; 1] {
+    [VertexInputBindingDescription {
+        binding: 0,
+        stride: 2 * std::mem::size_of::<DebugVertex>() as u32,
+        input_rate: VertexInputRate::Instance,
+    }]
+}
+
+fn thick_line_attribute_descriptions() -> [VertexInputAttributeDescription; 4] {
+    let vertex_size = std::mem::size_of::<DebugVertex>() as u32;
+    let attribute = |location: u32, offset: u32| VertexInputAttributeDescription {
+        location: location,
+        binding: 0,
+        format: Format::R32g32b32Sfloat,
+        offset: offset,
+    };
+    let color_offset = 3 * std::mem::size_of::<f32>() as u32;
+    [
+        attribute(0, 0),
+        attribute(1, color_offset),
+        attribute(2, vertex_size),
+        attribute(3, vertex_size + color_offset),
+    ]
+}
+
+/// CPU-side list of line segments for the current frame.
+#[derive(Debug, Clone, Default)]
+pub struct DebugDraw {
+    vertices: Vec<DebugVertex>,
+}
+
+impl DebugDraw {
+    pub fn new() -> DebugDraw {
+        DebugDraw {
+            vertices: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn vertices(&self) -> &[DebugVertex] {
+        self.vertices.as_slice()
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: [f32; 3]) {
+        self.vertices.push(DebugVertex {
+            position: a.to_array(),
+            color: color,
+        });
+        self.vertices.push(DebugVertex {
+            position: b.to_array(),
+            color: color,
+        });
+    }
+
+    /// Line from `from` to `to` with a small four-pronged head at `to`.
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: [f32; 3]) {
+        self.line(from, to, color);
+        let direction = to - from;
+        let length = direction.length();
+        if length <= 0.0 {
+            return;
+        }
+        let direction = direction * (1.0 / length);
+        let side = direction.any_perpendicular();
+        let up = direction.cross(side);
+        let head_base = to - direction * (length * 0.15);
+        let head_radius = length * 0.05;
+        for &offset in [side, -side, up, -up].iter() {
+            self.line(to, head_base + offset * head_radius, color);
+        }
+    }
+
+    /// Circle of `radius` around `center` in the plane perpendicular to `normal`.
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: [f32; 3], segments: u32) {
+        let u = normal.any_perpendicular();
+        let v = normal.normalize().cross(u);
+        let point = |i: u32| {
+            let angle = (i as f32 / segments as f32) * 2.0 * std::f32::consts::PI;
+            center + u * (angle.cos() * radius) + v * (angle.sin() * radius)
+        };
+        for i in 0..segments {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Wireframe axis-aligned box.
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: [f32; 3]) {
+        let corner = |i: u32| Vec3::new(
+            if i & 1 != 0 { max.x } else { min.x },
+            if i & 2 != 0 { max.y } else { min.y },
+            if i & 4 != 0 { max.z } else { min.z }
+        );
+        for i in 0..8 {
+            for &bit in [1, 2, 4].iter() {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+}
+
+/// Host-visible vertex buffer that the `DebugDraw` lines are copied into every frame.
+pub struct DebugDrawBuffer<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    buffer: DeviceBuffer<'d, D>,
+    capacity: usize,
+    vertex_count: u32,
+}
+
+impl<'d, D: DeviceV1_0> DebugDrawBuffer<'d, D> {
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, capacity: usize) -> VkResult<DebugDrawBuffer<'d, D>> {
+        let size = (capacity * std::mem::size_of::<DebugVertex>()) as DeviceSize;
+        Ok(DebugDrawBuffer {
+            device: device,
+            buffer: DeviceBuffer::host_visible(device, allocator, size, BUFFER_USAGE_VERTEX_BUFFER_BIT)?,
+            capacity: capacity,
+            vertex_count: 0,
+        })
+    }
+
+    /// Copies this frame's lines into the buffer. Lines past the buffer's capacity are dropped.
+    /// Must not be called while a previous frame's draw from this buffer is still in flight.
+    pub fn upload(&mut self, lines: &DebugDraw) -> VkResult<()> {
+        let vertices = lines.vertices();
+        let count = std::cmp::min(vertices.len(), self.capacity);
+        if count < vertices.len() {
+            warn!("Dropping {} debug line vertices past the buffer capacity of {}", vertices.len() - count, self.capacity);
+        }
+        self.buffer.write(0, &vertices[..count])?;
+        self.vertex_count = count as u32;
+        Ok(())
+    }
+
+    /// Records the draw of the last uploaded lines. The debug draw pipeline must already be bound.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view_projection: &Mat4) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        let constants = DebugDrawPushConstants {
+            view_projection: *view_projection,
+        };
+        let range = DebugDrawPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const DebugDrawPushConstants as *const c_void);
+        self.device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.buffer.buffer()], &[0]);
+        self.device.cmd_draw(command_buffer, self.vertex_count, 1, 0, 0);
+    }
+
+    /// Records the draw of the last uploaded lines `width` pixels wide. The thick line pipeline
+    /// must already be bound.
+    pub unsafe fn cmd_draw_thick(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view_projection: &Mat4, width: f32, extent: &Extent2D) {
+        if self.vertex_count < 2 {
+            return;
+        }
+        let constants = ThickLinePushConstants {
+            view_projection: *view_projection,
+            width: width,
+            viewport_width: extent.width as f32,
+            viewport_height: extent.height as f32,
+        };
+        let range = ThickLinePushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const ThickLinePushConstants as *const c_void);
+        self.device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.buffer.buffer()], &[0]);
+        self.device.cmd_draw(command_buffer, 4, self.vertex_count / 2, 0, 0);
+    }
+}
+
+/// Pipeline state for the debug lines: depth tested against the scene but not written, so
+/// overlapping debug geometry doesn't occlude itself.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .vertex_input(&DebugVertex::binding_descriptions(), &DebugVertex::attribute_descriptions())
+        .topology(PrimitiveTopology::LineList)
+        .cull_mode(CULL_MODE_NONE)
+        .depth_test(false, CompareOp::LessOrEqual)
+}
+
+/// Pipeline state for thick lines, with the same depth testing as `pipeline_builder` and alpha
+/// blending for the antialiased edges.
+pub fn thick_pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .vertex_input(&thick_line_binding_descriptions(), &thick_line_attribute_descriptions())
+        .topology(PrimitiveTopology::TriangleStrip)
+        .cull_mode(CULL_MODE_NONE)
+        .depth_test(false, CompareOp::LessOrEqual)
+        .blend(BlendPreset::Alpha)
+}
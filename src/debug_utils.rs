@@ -0,0 +1,290 @@
+//! Validation messages through `VK_EXT_debug_utils`, and names for the objects they mention. The
+//! messenger is filtered by severity and type with `--debug-severity=<list>` and
+//! `--debug-types=<list>` (comma separated, everything by default), and names given with
+//! `set_object_name` show up in validation messages and in captures.
+//!
+//! `ash` predates the extension, so its structures and entry points are declared here and loaded
+//! by hand. Without the extension nothing is reported or named.
+use ash::version::{ EntryV1_0, InstanceV1_0 };
+use std;
+use std::ffi::{ CStr, CString };
+use std::ptr;
+use vk::types::*;
+use ::cvar::Flag;
+use ::handle::Handle;
+use ::shader_printf;
+
+pub const EXTENSION: &'static str = "VK_EXT_debug_utils";
+pub const SEVERITY_ARG_PREFIX: &'static str = "--debug-severity=";
+pub const TYPES_ARG_PREFIX: &'static str = "--debug-types=";
+/// Comma separated lists of names, or empty for all of them.
+pub const SEVERITY_CVAR: &'static str = "debug.severities";
+pub const TYPES_CVAR: &'static str = "debug.types";
+pub const FLAGS: &'static [Flag] = &[
+    Flag::Value { prefix: SEVERITY_ARG_PREFIX, name: SEVERITY_CVAR },
+    Flag::Value { prefix: TYPES_ARG_PREFIX, name: TYPES_CVAR },
+];
+
+pub const SEVERITY_VERBOSE: u32 = 0x1;
+pub const SEVERITY_INFO: u32 = 0x10;
+pub const SEVERITY_WARNING: u32 = 0x100;
+pub const SEVERITY_ERROR: u32 = 0x1000;
+const ALL_SEVERITIES: u32 = SEVERITY_VERBOSE | SEVERITY_INFO | SEVERITY_WARNING | SEVERITY_ERROR;
+
+pub const TYPE_GENERAL: u32 = 0x1;
+pub const TYPE_VALIDATION: u32 = 0x2;
+pub const TYPE_PERFORMANCE: u32 = 0x4;
+const ALL_TYPES: u32 = TYPE_GENERAL | TYPE_VALIDATION | TYPE_PERFORMANCE;
+
+const STRUCTURE_TYPE_OBJECT_NAME_INFO: u32 = 1000128000;
+const STRUCTURE_TYPE_MESSENGER_CREATE_INFO: u32 = 1000128004;
+
+/// A `VkDebugUtilsMessengerEXT`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugUtilsMessenger(pub u64);
+
+impl DebugUtilsMessenger {
+    pub fn null() -> DebugUtilsMessenger {
+        DebugUtilsMessenger(0)
+    }
+}
+
+#[repr(C)]
+struct ObjectNameInfoRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    object_type: u32,
+    object_handle: u64,
+    p_object_name: *const c_char,
+}
+
+#[repr(C)]
+struct LabelRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    p_label_name: *const c_char,
+    color: [f32; 4],
+}
+
+#[repr(C)]
+struct CallbackDataRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    flags: u32,
+    p_message_id_name: *const c_char,
+    message_id_number: i32,
+    p_message: *const c_char,
+    queue_label_count: u32,
+    p_queue_labels: *const LabelRaw,
+    cmd_buf_label_count: u32,
+    p_cmd_buf_labels: *const LabelRaw,
+    object_count: u32,
+    p_objects: *const ObjectNameInfoRaw,
+}
+
+type MessengerCallbackFn = unsafe extern "system" fn(u32, u32, *const CallbackDataRaw, *mut c_void) -> Bool32;
+
+#[repr(C)]
+struct MessengerCreateInfoRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    flags: u32,
+    message_severity: u32,
+    message_type: u32,
+    pfn_user_callback: MessengerCallbackFn,
+    p_user_data: *mut c_void,
+}
+
+type CreateMessengerFn = unsafe extern "system" fn(Instance, *const MessengerCreateInfoRaw, *const AllocationCallbacks, *mut DebugUtilsMessenger) -> Result;
+type DestroyMessengerFn = unsafe extern "system" fn(Instance, DebugUtilsMessenger, *const AllocationCallbacks);
+type SetObjectNameFn = unsafe extern "system" fn(Device, *const ObjectNameInfoRaw) -> Result;
+
+/// Which messages the messenger passes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageFilter {
+    /// `SEVERITY_*` bits.
+    pub severities: u32,
+    /// `TYPE_*` bits.
+    pub types: u32,
+}
+
+impl Default for MessageFilter {
+    fn default() -> MessageFilter {
+        MessageFilter {
+            severities: ALL_SEVERITIES,
+            types: ALL_TYPES,
+        }
+    }
+}
+
+impl MessageFilter {
+    /// Severities are `verbose`, `info`, `warning` and `error`; types are `general`,
+    /// `validation` and `performance`.
+    pub fn from_cvars() -> MessageFilter {
+        let mut ret = MessageFilter::default();
+        let parse = |list: &str, names: &[(&str, u32)]| -> u32 {
+            list.split(',').filter(|name| !name.is_empty()).fold(0, |bits, name| {
+                match names.iter().find(|&&(candidate, _)| candidate == name) {
+                    Some(&(_, bit)) => bits | bit,
+                    None => {
+                        warn!("Unknown debug message filter {:?}, expected one of {:?}", name, names.iter().map(|&(name, _)| name).collect::<Vec<&str>>());
+                        bits
+                    },
+                }
+            })
+        };
+        let severities: String = cvar!(SEVERITY_CVAR, String::new()).get();
+        if !severities.is_empty() {
+            ret.severities = parse(&severities, &[("verbose", SEVERITY_VERBOSE), ("info", SEVERITY_INFO), ("warning", SEVERITY_WARNING), ("error", SEVERITY_ERROR)]);
+        }
+        let types: String = cvar!(TYPES_CVAR, String::new()).get();
+        if !types.is_empty() {
+            ret.types = parse(&types, &[("general", TYPE_GENERAL), ("validation", TYPE_VALIDATION), ("performance", TYPE_PERFORMANCE)]);
+        }
+        ret
+    }
+}
+
+/// Whether the instance can have `EXTENSION`.
+pub fn instance_supported<E: EntryV1_0>(entry: &E) -> bool {
+    entry.enumerate_instance_extension_properties()
+        .map(|extensions| extensions.iter().any(|properties| unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) }.to_bytes() == EXTENSION.as_bytes()))
+        .unwrap_or(false)
+}
+
+/// An object that can be given a debug name: its `VkObjectType`, along with its raw handle.
+pub trait DebugObject: Handle {
+    fn object_type() -> u32;
+}
+
+macro_rules! debug_object {
+    ($handle: ident, $object_type: expr) => {
+        impl DebugObject for $handle {
+            fn object_type() -> u32 {
+                $object_type
+            }
+        }
+    }
+}
+
+debug_object!(Device, 3);
+debug_object!(Buffer, 9);
+debug_object!(Image, 10);
+debug_object!(ImageView, 14);
+debug_object!(ShaderModule, 15);
+debug_object!(PipelineLayout, 17);
+debug_object!(RenderPass, 18);
+debug_object!(Pipeline, 19);
+debug_object!(Framebuffer, 24);
+
+/// The extension's entry points, loaded from the instance.
+pub struct DebugUtils {
+    instance: Instance,
+    create_messenger: CreateMessengerFn,
+    destroy_messenger: DestroyMessengerFn,
+    set_object_name: SetObjectNameFn,
+}
+
+impl DebugUtils {
+    /// `None` if `instance` wasn't created with `EXTENSION`.
+    pub fn new<E: EntryV1_0, I: InstanceV1_0>(entry: &E, instance: &I) -> Option<DebugUtils> {
+        let load = |name: &str| {
+            let name = CString::new(name).unwrap();
+            let pfn = entry.get_instance_proc_addr(instance.handle(), name.as_ptr());
+            if pfn as usize == 0 { None } else { Some(pfn) }
+        };
+        match (load("vkCreateDebugUtilsMessengerEXT"), load("vkDestroyDebugUtilsMessengerEXT"), load("vkSetDebugUtilsObjectNameEXT")) {
+            (Some(create_messenger), Some(destroy_messenger), Some(set_object_name)) => Some(unsafe {
+                DebugUtils {
+                    instance: instance.handle(),
+                    create_messenger: std::mem::transmute::<PFN_vkVoidFunction, CreateMessengerFn>(create_messenger),
+                    destroy_messenger: std::mem::transmute::<PFN_vkVoidFunction, DestroyMessengerFn>(destroy_messenger),
+                    set_object_name: std::mem::transmute::<PFN_vkVoidFunction, SetObjectNameFn>(set_object_name),
+                }
+            }),
+            _ => None,
+        }
+    }
+
+    /// A messenger that logs the messages `filter` lets through.
+    pub fn create_messenger(&self, filter: &MessageFilter) -> std::result::Result<DebugUtilsMessenger, Result> {
+        let create_info = MessengerCreateInfoRaw {
+            s_type: STRUCTURE_TYPE_MESSENGER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: 0,
+            message_severity: filter.severities,
+            message_type: filter.types,
+            pfn_user_callback: messenger_callback,
+            p_user_data: ptr::null_mut(),
+        };
+        let mut messenger = DebugUtilsMessenger::null();
+        match unsafe { (self.create_messenger)(self.instance, &create_info, ptr::null(), &mut messenger) } {
+            Result::Success => Ok(messenger),
+            e => Err(e),
+        }
+    }
+
+    /// Destroying a null messenger is a no-op.
+    pub unsafe fn destroy_messenger(&self, messenger: DebugUtilsMessenger) {
+        (self.destroy_messenger)(self.instance, messenger, ptr::null());
+    }
+
+    /// Names `object`, which belongs to `device`, for validation messages and captures.
+    pub fn set_object_name<T: DebugObject>(&self, device: Device, object: &T, name: &str) {
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => return,
+        };
+        let name_info = ObjectNameInfoRaw {
+            s_type: STRUCTURE_TYPE_OBJECT_NAME_INFO,
+            p_next: ptr::null(),
+            object_type: T::object_type(),
+            object_handle: object.as_raw(),
+            p_object_name: name.as_ptr(),
+        };
+        match unsafe { (self.set_object_name)(device, &name_info) } {
+            Result::Success => (),
+            e => warn!("Failed to name {:x} {:?}: {:?}", object.as_raw(), name, e),
+        }
+    }
+}
+
+unsafe fn optional_str<'a>(s: *const c_char) -> std::borrow::Cow<'a, str> {
+    if s.is_null() {
+        "".into()
+    } else {
+        CStr::from_ptr(s).to_string_lossy()
+    }
+}
+
+unsafe extern "system" fn messenger_callback(severity: u32, types: u32, data: *const CallbackDataRaw, _: *mut c_void) -> Bool32 {
+    let data = &*data;
+    let id_name = optional_str(data.p_message_id_name);
+    let message = optional_str(data.p_message);
+    if shader_printf::is_printf_message(&id_name, &message) {
+        shader_printf::log_message(&message);
+        return false as Bool32;
+    }
+    let objects: Vec<String> = (0..data.object_count as usize)
+        .map(|idx| &*data.p_objects.add(idx))
+        .filter(|object| !object.p_object_name.is_null())
+        .map(|object| optional_str(object.p_object_name).into_owned())
+        .collect();
+    let kind = if types & TYPE_VALIDATION != 0 { "validation" } else if types & TYPE_PERFORMANCE != 0 { "performance" } else { "general" };
+    let msg_string = if objects.is_empty() {
+        format!("[{}] {}: {}", kind, id_name, message)
+    } else {
+        format!("[{}] {}: {} (objects: {})", kind, id_name, message, objects.join(", "))
+    };
+    if severity & SEVERITY_ERROR != 0 {
+        error!("{}", &msg_string);
+    } else if severity & SEVERITY_WARNING != 0 {
+        warn!("{}", &msg_string);
+    } else if severity & SEVERITY_INFO != 0 {
+        info!("{}", &msg_string);
+    } else {
+        debug!("{}", &msg_string);
+    }
+    false as Bool32
+}
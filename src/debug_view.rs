@@ -0,0 +1,253 @@
+//! Per-mesh debug visualizations: bounding boxes, vertex normals and tangents as lines, and a UV
+//! checker material that replaces the mesh's own material. The line modes go through
+//! `debug_draw`; the checker is a separate fragment shader (`shaders/uv_checker_fragment.glsl`)
+//! that the renderer swaps in while `uv_checker` is enabled.
+//!
+//! The heatmap views instead replace the pipeline of every draw in the scene, see
+//! `SceneOverride`.
+use ash::version::DeviceV1_0;
+use glfw;
+use std;
+use vk::types::*;
+use ::debug_draw::{ self, DebugDraw };
+use ::math::{ Aabb, Mat4, Vec3 };
+use ::mesh::MeshPushConstants;
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+
+pub const UV_CHECKER_SHADER: &'static str = "shaders/uv_checker_fragment.frag.spv";
+pub const OVERDRAW_SHADER: &'static str = "shaders/overdraw_fragment.frag.spv";
+pub const COMPLEXITY_SHADER: &'static str = "shaders/complexity_fragment.frag.spv";
+
+const NORMAL_COLOR: [f32; 3] = [0.2, 0.6, 1.0];
+const TANGENT_COLOR: [f32; 3] = [1.0, 0.4, 0.2];
+
+/// Push constant space the views' pipelines need: `MeshPushConstants` followed by the larger of the
+/// fragment blocks.
+pub const PUSH_CONSTANTS_SIZE: u32 = (std::mem::size_of::<MeshPushConstants>() + if std::mem::size_of::<UvCheckerPushConstants>() > std::mem::size_of::<ComplexityPushConstants>() {
+    std::mem::size_of::<UvCheckerPushConstants>()
+} else {
+    std::mem::size_of::<ComplexityPushConstants>()
+}) as u32;
+
+/// Push constant block read by `shaders/uv_checker_fragment.glsl`. It replaces the fragment
+/// shader of mesh pipelines, so it comes after the vertex shader's `MeshPushConstants`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UvCheckerPushConstants {
+    /// Number of checker squares along each texture coordinate axis.
+    pub scale: f32,
+}
+
+impl UvCheckerPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: std::mem::size_of::<MeshPushConstants>() as u32,
+            size: std::mem::size_of::<UvCheckerPushConstants>() as u32,
+        }
+    }
+
+    pub unsafe fn cmd_push<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let range = UvCheckerPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, self as *const UvCheckerPushConstants as *const c_void);
+    }
+}
+
+/// Pipelines that replace the whole scene's pipelines, to judge how well culling and sorting are
+/// doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneOverride {
+    None,
+    /// Every fragment adds a fixed amount of heat, with depth testing off, so bright areas are
+    /// shaded many times over.
+    Overdraw,
+    /// Each draw is colored by the estimated cost of its material's fragment shader.
+    ShaderComplexity,
+}
+
+impl SceneOverride {
+    pub fn next(&self) -> SceneOverride {
+        match *self {
+            SceneOverride::None => SceneOverride::Overdraw,
+            SceneOverride::Overdraw => SceneOverride::ShaderComplexity,
+            SceneOverride::ShaderComplexity => SceneOverride::None,
+        }
+    }
+
+    /// Derives the override variant of a scene pipeline from the builder it was made with.
+    /// Returns `None` when the scene's own pipeline should be used.
+    pub fn pipeline_builder(&self, scene: &GraphicsPipelineBuilder, shaders: &HeatmapShaders) -> Option<GraphicsPipelineBuilder> {
+        match *self {
+            SceneOverride::None => None,
+            SceneOverride::Overdraw => Some(scene.clone()
+                .replace_stage(SHADER_STAGE_FRAGMENT_BIT, shaders.overdraw)
+                .depth_test(false, CompareOp::Always)
+                .blend(BlendPreset::Additive)),
+            SceneOverride::ShaderComplexity => Some(scene.clone()
+                .replace_stage(SHADER_STAGE_FRAGMENT_BIT, shaders.complexity)),
+        }
+    }
+}
+
+/// Fragment shader modules for the `SceneOverride` pipelines, loaded from
+/// `shaders/overdraw_fragment.glsl` and `shaders/complexity_fragment.glsl`.
+#[derive(Debug, Clone, Copy)]
+pub struct HeatmapShaders {
+    pub overdraw: ShaderModule,
+    pub complexity: ShaderModule,
+}
+
+/// Push constant block read by `shaders/complexity_fragment.glsl`, after `MeshPushConstants` like
+/// `UvCheckerPushConstants`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ComplexityPushConstants {
+    /// Estimated cost of the draw's real fragment shader, normalized to `[0, 1]`.
+    pub cost: f32,
+}
+
+impl ComplexityPushConstants {
+    pub fn new(instruction_count: u32, max_instruction_count: u32) -> ComplexityPushConstants {
+        ComplexityPushConstants {
+            cost: (instruction_count as f32 / std::cmp::max(max_instruction_count, 1) as f32).min(1.0),
+        }
+    }
+
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: std::mem::size_of::<MeshPushConstants>() as u32,
+            size: std::mem::size_of::<ComplexityPushConstants>() as u32,
+        }
+    }
+
+    pub unsafe fn cmd_push<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let range = ComplexityPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, self as *const ComplexityPushConstants as *const c_void);
+    }
+}
+
+const SPIRV_MAGIC: u32 = 0x07230203;
+const SPIRV_HEADER_WORDS: usize = 5;
+const SPIRV_OP_FUNCTION: u32 = 54;
+
+/// Rough fragment cost: the number of SPIR-V instructions inside function bodies. It ignores
+/// loops and what each instruction actually costs, but it's enough to tell a flat color shader
+/// from a full lighting one. Returns `None` if `code` isn't SPIR-V.
+pub fn estimate_instruction_count(code: &[u8]) -> Option<u32> {
+    if !code.len().is_multiple_of(4) || code.len() < SPIRV_HEADER_WORDS * 4 {
+        return None;
+    }
+    let words: Vec<u32> = code.chunks(4)
+        .map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+        .collect();
+    if words[0] != SPIRV_MAGIC {
+        return None;
+    }
+    let mut idx = SPIRV_HEADER_WORDS;
+    let mut in_function = false;
+    let mut count = 0;
+    while idx < words.len() {
+        let word_count = (words[idx] >> 16) as usize;
+        let opcode = words[idx] & 0xffff;
+        if word_count == 0 {
+            return None;
+        }
+        if opcode == SPIRV_OP_FUNCTION {
+            in_function = true;
+        } else if in_function {
+            count += 1;
+        }
+        idx += word_count;
+    }
+    Some(count)
+}
+
+#[derive(Debug, Clone)]
+pub struct DebugView {
+    pub show_aabbs: bool,
+    pub show_normals: bool,
+    pub show_tangents: bool,
+    /// Replaces every mesh's material with the UV checker.
+    pub uv_checker: bool,
+    pub scene_override: SceneOverride,
+    /// World-space length of the normal and tangent lines.
+    pub vector_length: f32,
+    pub checker_scale: f32,
+}
+
+impl DebugView {
+    pub fn new() -> DebugView {
+        DebugView {
+            show_aabbs: false,
+            show_normals: false,
+            show_tangents: false,
+            uv_checker: false,
+            scene_override: SceneOverride::None,
+            vector_length: 0.1,
+            checker_scale: 8.0,
+        }
+    }
+
+    /// Toggles the mode bound to `key` (F1 through F4, F5 cycles the scene override). Returns
+    /// whether the key was handled.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key == glfw::Key::F5 {
+            self.scene_override = self.scene_override.next();
+            debug!("Debug scene override: {:?}", self.scene_override);
+            return true;
+        }
+        let (name, flag) = match key {
+            glfw::Key::F1 => ("bounding boxes", &mut self.show_aabbs),
+            glfw::Key::F2 => ("normals", &mut self.show_normals),
+            glfw::Key::F3 => ("tangents", &mut self.show_tangents),
+            glfw::Key::F4 => ("UV checker", &mut self.uv_checker),
+            _ => return false,
+        };
+        *flag = !*flag;
+        debug!("Debug view {}: {}", name, *flag);
+        true
+    }
+
+    pub fn checker_push_constants(&self) -> UvCheckerPushConstants {
+        UvCheckerPushConstants {
+            scale: self.checker_scale,
+        }
+    }
+
+    /// Adds the enabled line visualizations for one mesh instance to this frame's debug lines.
+    /// `normals` and `tangents` are per-vertex and may be empty if the mesh doesn't have them.
+    pub fn draw_mesh(&self, lines: &mut DebugDraw, model: &Mat4, bounds: &Aabb, positions: &[Vec3], normals: &[Vec3], tangents: &[Vec3]) {
+        if self.show_aabbs {
+            let world_bounds = bounds.transform(model);
+            lines.aabb(world_bounds.min, world_bounds.max, debug_draw::YELLOW);
+        }
+        if !self.show_normals && !self.show_tangents {
+            return;
+        }
+        let world_positions: Vec<Vec3> = positions.iter().map(|&p| model.transform_point(p)).collect();
+        if self.show_normals {
+            // Normals need the inverse transpose so they stay perpendicular under non-uniform scale
+            let normal_matrix = match model.inverse() {
+                Some(inverse) => inverse.transpose(),
+                None => {
+                    warn!("Skipping normals for singular model matrix");
+                    return;
+                },
+            };
+            self.draw_vectors(lines, &world_positions, normals, &normal_matrix, NORMAL_COLOR);
+        }
+        if self.show_tangents {
+            self.draw_vectors(lines, &world_positions, tangents, model, TANGENT_COLOR);
+        }
+    }
+
+    fn draw_vectors(&self, lines: &mut DebugDraw, world_positions: &[Vec3], vectors: &[Vec3], matrix: &Mat4, color: [f32; 3]) {
+        for (&p, &v) in world_positions.iter().zip(vectors.iter()) {
+            let v = matrix.transform_vector(v);
+            if v.length() > 0.0 {
+                lines.line(p, p + v.normalize() * self.vector_length, color);
+            }
+        }
+    }
+}
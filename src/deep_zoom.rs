@@ -0,0 +1,235 @@
+//! An endless zoom into the Mandelbrot set, drawn by `shaders/deep_zoom_fragment.glsl` over a
+//! full-screen triangle. Floats run out of precision a few thousand times in, so the shader
+//! iterates in doubles, which hold out to around `END_HEIGHT` before the zoom starts over.
+//!
+//! The deeper the zoom, the more iterations the boundary takes to resolve. To tell when the
+//! budget runs short, the shader counts pixels that only escaped in its last `LATE_FRACTION`
+//! with an atomic add into a storage buffer, which `DeepZoom::adapt` reads back a few frames
+//! later to raise the budget when there are too many.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::StorageBuffer;
+use ::descriptor::{ self, DescriptorAllocator };
+use ::pipeline::GraphicsPipelineBuilder;
+use ::vk_mem::MemoryAllocator;
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/deep_zoom_fragment.frag.spv";
+
+/// Binding of the late escape counter in the draw's descriptor set.
+pub const COUNTER_BINDING: u32 = 0;
+
+/// A point in Seahorse Valley, on the boundary of the set, so there's detail however far in the
+/// zoom goes.
+const TARGET: [f64; 2] = [-0.743643887037151, 0.131825904205330];
+/// Height of the view on the complex plane when the zoom starts, and where it starts over.
+const START_HEIGHT: f64 = 3.0;
+const END_HEIGHT: f64 = 1e-11;
+/// What the height is multiplied by every second.
+const ZOOM_PER_SECOND: f64 = 0.7;
+
+const START_ITERATIONS: u32 = 256;
+const MAX_ITERATIONS: u32 = 16384;
+/// Escaping in this last part of the iteration budget counts as late.
+const LATE_FRACTION: f32 = 0.2;
+/// The budget grows when more than this much of the screen escaped late.
+const LATE_PIXEL_FRACTION: f32 = 0.002;
+
+/// Push constant block read by `shaders/deep_zoom_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DeepZoomPushConstants {
+    /// The point at the center of the screen.
+    pub center: [f64; 2],
+    /// Of the complex plane, per pixel.
+    pub scale: f64,
+    pub max_iterations: u32,
+    /// Pixels escaping at this iteration or later count as late.
+    pub late_iteration: u32,
+    /// Half the framebuffer's size, in pixels.
+    pub half_extent: [f32; 2],
+}
+
+impl DeepZoomPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<DeepZoomPushConstants>() as u32,
+        }
+    }
+
+    /// Records pushing the constants and the full-screen draw, with the deep zoom pipeline and a
+    /// counter's descriptor set bound.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let range = DeepZoomPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, self as *const DeepZoomPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Where the zoom is, and how many iterations it takes to draw there.
+#[derive(Debug, Clone)]
+pub struct DeepZoom {
+    /// Seconds since the zoom last started over.
+    time: f64,
+    pub max_iterations: u32,
+}
+
+impl DeepZoom {
+    pub fn new() -> DeepZoom {
+        DeepZoom {
+            time: 0.0,
+            max_iterations: START_ITERATIONS,
+        }
+    }
+
+    /// Height of the view on the complex plane.
+    pub fn height(&self) -> f64 {
+        START_HEIGHT * ZOOM_PER_SECOND.powf(self.time)
+    }
+
+    /// Zooms in for `dt` seconds, starting over once doubles can't go any deeper.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt as f64;
+        if self.height() < END_HEIGHT {
+            *self = DeepZoom::new();
+        }
+    }
+
+    /// Raises the iteration budget by half if more than `LATE_PIXEL_FRACTION` of the
+    /// `pixel_count` pixels of a recent frame escaped late. Returns whether it did.
+    pub fn adapt(&mut self, late_escapes: u32, pixel_count: u32) -> bool {
+        if late_escapes as f32 <= pixel_count as f32 * LATE_PIXEL_FRACTION || self.max_iterations >= MAX_ITERATIONS {
+            return false;
+        }
+        self.max_iterations = std::cmp::min(self.max_iterations + self.max_iterations / 2, MAX_ITERATIONS);
+        true
+    }
+
+    pub fn push_constants(&self, extent: &Extent2D) -> DeepZoomPushConstants {
+        DeepZoomPushConstants {
+            center: TARGET,
+            scale: self.height() / std::cmp::max(extent.height, 1) as f64,
+            max_iterations: self.max_iterations,
+            late_iteration: self.max_iterations - (self.max_iterations as f32 * LATE_FRACTION) as u32,
+            half_extent: [extent.width as f32 * 0.5, extent.height as f32 * 0.5],
+        }
+    }
+}
+
+/// A late escape counter for each frame in flight, so a frame's count can be read once the frame
+/// is done without stalling the ones after it.
+pub struct LateEscapeCounters<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    counters: Vec<StorageBuffer<'d, D>>,
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+    sets: Vec<DescriptorSet>,
+}
+
+impl<'d, D: DeviceV1_0> LateEscapeCounters<'d, D> {
+    /// `slot_count` counters, starting at 0. `set_layout` must be made from `bindings`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, set_layout: DescriptorSetLayout, slot_count: usize) -> VkResult<LateEscapeCounters<'d, D>> {
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: slot_count as u32,
+        }];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, slot_count as u32);
+        let mut counters = Vec::with_capacity(slot_count);
+        let mut sets = Vec::with_capacity(slot_count);
+        for _ in 0..slot_count {
+            let counter = StorageBuffer::with_data(device, allocator, &[0u32])?;
+            let set = descriptor_allocator.allocate(set_layout)?;
+            descriptor::update_descriptor_set(device, set, &[counter.descriptor_write(COUNTER_BINDING)]);
+            counters.push(counter);
+            sets.push(set);
+        }
+        Ok(LateEscapeCounters {
+            device: device,
+            counters: counters,
+            _descriptor_allocator: descriptor_allocator,
+            sets: sets,
+        })
+    }
+
+    pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: COUNTER_BINDING,
+            descriptor_type: DescriptorType::StorageBuffer,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    pub fn descriptor_set(&self, slot: usize) -> DescriptorSet {
+        self.sets[slot]
+    }
+
+    /// What the last frame drawn with `slot`'s counter counted, resetting it for the next. That
+    /// frame has to be done, with `cmd_make_host_visible` recorded after its draw.
+    pub fn take(&self, slot: usize) -> VkResult<u32> {
+        let count = self.counters[slot].read::<u32>(1)[0];
+        self.counters[slot].write(&[0u32])?;
+        Ok(count)
+    }
+
+    /// Records making the draw's counts visible to `take`. Must be outside a render pass.
+    pub unsafe fn cmd_make_host_visible(&self, command_buffer: CommandBuffer) {
+        let to_host = MemoryBarrier {
+            s_type: StructureType::MemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_HOST_READ_BIT,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_FRAGMENT_SHADER_BIT, PIPELINE_STAGE_HOST_BIT, Default::default(), &[to_host], &[], &[]);
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl`, colored by `fragment_shader`.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom_starts_over_when_doubles_run_out() {
+        let mut zoom = DeepZoom::new();
+        zoom.max_iterations = 1000;
+        zoom.update(10.0);
+        assert!(zoom.height() < START_HEIGHT && zoom.height() > END_HEIGHT);
+        assert_eq!(zoom.max_iterations, 1000);
+        zoom.update(100.0);
+        assert_eq!(zoom.height(), START_HEIGHT);
+        assert_eq!(zoom.max_iterations, START_ITERATIONS);
+    }
+
+    #[test]
+    fn budget_grows_with_late_escapes_up_to_the_cap() {
+        let mut zoom = DeepZoom::new();
+        let pixels = 1000 * 1000;
+        assert!(!zoom.adapt(100, pixels));
+        assert_eq!(zoom.max_iterations, START_ITERATIONS);
+        assert!(zoom.adapt(10000, pixels));
+        assert_eq!(zoom.max_iterations, START_ITERATIONS * 3 / 2);
+        while zoom.adapt(10000, pixels) {}
+        assert_eq!(zoom.max_iterations, MAX_ITERATIONS);
+    }
+
+    #[test]
+    fn late_iterations_are_the_end_of_the_budget() {
+        let zoom = DeepZoom::new();
+        let constants = zoom.push_constants(&Extent2D { width: 1280, height: 720 });
+        assert_eq!(constants.late_iteration, START_ITERATIONS - 51);
+        assert_eq!(constants.half_extent, [640.0, 360.0]);
+        assert_eq!(constants.scale, START_HEIGHT / 720.0);
+    }
+}
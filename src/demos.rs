@@ -0,0 +1,2410 @@
+//! The demos listed in `capabilities::DEMOS`, drawn instead of the scene while they're selected.
+//! Only the selected demo's pass exists: the renderer creates it the first frame the demo is
+//! drawn, and hands the old one to the frame loop's deletion queue when another is selected. A
+//! pass records its compute work in `prepare`, before the frame's render pass begins, and draws
+//! into the render pass with `cmd_draw`, or writes the swapchain image itself with `cmd_blit`.
+use ash;
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, V1_0 };
+use glfw;
+use std;
+use std::ptr;
+use std::time::Duration;
+use vk::types::*;
+use ::automata::{ self, CellularAutomaton, Rule };
+use ::buffer::DeviceBuffer;
+use ::camera::Camera;
+use ::checkerboard::{ self, CheckerboardPushConstants, CheckerboardTarget, RenderMode };
+use ::command::{ CommandRecorder, Drawable };
+use ::compute::ComputeKernel;
+use ::debug_draw::{ self, DebugDraw, DebugDrawBuffer, DebugDrawPushConstants, ThickLinePushConstants };
+use ::debug_view::{ self, ComplexityPushConstants, DebugView, HeatmapShaders, SceneOverride, UvCheckerPushConstants };
+use ::deep_zoom::{ self, DeepZoom, LateEscapeCounters };
+use ::descriptor::{ self, DescriptorWrite };
+#[cfg(feature = "physics")]
+use ::fixed_update::FixedTimestep;
+use ::flow_field::{ self, CurlNoisePushConstants, FlowField, FlowFieldPushConstants };
+use ::format_support::{ self, FormatSupport };
+use ::frame::{ self, FrameContext };
+use ::gamma::TextureEncoding;
+use ::gbuffer_view::{ self, DepthTarget, GBufferViewPushConstants, GBufferViewer };
+use ::gizmo::{ self, Gizmo, Transform };
+use ::gpu_algo;
+use ::gradient::{ self, Gradient };
+use ::grid::{ self, GridPushConstants, ReferenceGrid };
+use ::ground_plane::{ self, GroundPlanePushConstants };
+use ::half_res::{ self, DownsamplePushConstants, Effect, EffectScale, EffectTarget, UpsamplePushConstants };
+use ::image::{ self, ImageData, Texture };
+use ::immediate::ImmediateContext;
+use ::math::{ Aabb, Frustum, Mat4, Quat, Ray, Vec3 };
+use ::math::prelude::div_ceil;
+use ::mesh::{ self, MeshBuffer, MeshPushConstants, MeshVertex, VertexFetch };
+use ::nbody::{ self, NBody, NBodyDrawPushConstants, NBodyPushConstants, XorShift };
+use ::noise::{ self, NoiseKind, NoiseParams, NoisePushConstants, NoiseTexture };
+use ::normal_vis::{ self, NormalVisPushConstants };
+use ::particles::{ self, ParticleBuffers, ParticleFountain, ParticlePushConstants, SortShaders };
+#[cfg(feature = "physics")]
+use ::physics::PhysicsWorld;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::pipeline_library::{ self, PipelineLibrary };
+use ::point_cloud::{ self, PointCloud, PointCloudPushConstants, PointMode };
+use ::primitives::Primitive;
+use ::push_descriptor::{ self, PushDescriptors, PushedSet };
+use ::quantize::{ self, QuantizedMesh, VertexFormat };
+use ::radix_sort;
+use ::rect::{ self, ExtentExt, OffsetExt, RectExt, ViewportExt };
+use ::renderer::{ self, Context, RendererError };
+use ::safe_create::{ self, Owned, Shared };
+use ::sdf::{ self, SdfPushConstants };
+use ::shader_compile;
+use ::shadow_atlas::{ self, CubeShadowRegions, ShadowAtlas, ShadowRegion };
+use ::subgroup::{ self, Reduction, ReductionBarsPushConstants, ReductionBuffers, ReductionPath };
+use ::ssao::{ self, SsaoPushConstants };
+use ::texture_array::{ self, MaterialAtlas, MaterialSlot, TextureArray };
+use ::vk_mem::MemoryAllocator;
+use ::volumetric_fog::{ self, FogApplyPushConstants, FogView, VolumetricFog };
+
+/// What every demo draws from, for one frame.
+#[derive(Debug, Clone)]
+pub struct DemoView {
+    /// Of the render pass, which is the swapchain's.
+    pub extent: Extent2D,
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub eye: Vec3,
+    /// The camera's planes, which `projection` was made with.
+    pub near: f32,
+    pub far: f32,
+    /// Seconds of scene time, which stops while paused.
+    pub time: f32,
+}
+
+impl DemoView {
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection * self.view
+    }
+}
+
+/// One demo's pipelines and resources.
+pub trait DemoPass<'c> {
+    /// Records what has to happen before the frame's render pass, like compute dispatches.
+    fn prepare(&mut self, _frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        Ok(())
+    }
+
+    /// How long the GPU spent on what `prepare` recorded in a recent frame, in milliseconds.
+    /// Only called while the GPU profiler is on.
+    fn gpu_time(&mut self, _milliseconds: f32) {}
+
+    /// Handles a key press while the demo is selected, returning whether it did anything.
+    fn handle_key(&mut self, _key: glfw::Key) -> bool {
+        false
+    }
+
+    /// Handles a cursor move or mouse button while the demo is selected, returning whether it
+    /// used it.
+    fn handle_mouse(&mut self, _event: &glfw::WindowEvent) -> bool {
+        false
+    }
+
+    /// Whether the demo writes the whole swapchain image with `cmd_blit` instead of drawing into
+    /// the frame's render pass, which then doesn't begin at all, console included.
+    fn blits(&self) -> bool {
+        false
+    }
+
+    /// Records writing all of the swapchain `image`, at `extent`, outside any render pass, leaving
+    /// it ready to present. Only called for demos that `blits`.
+    unsafe fn cmd_blit(&self, _command_buffer: CommandBuffer, _image: Image, _extent: &Extent2D) {}
+
+    /// Records the demo's draws inside the frame's render pass.
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView);
+
+    /// Records what has to follow the frame's render pass, like making what the draws wrote
+    /// visible to the host. Not called for demos that `blits`.
+    unsafe fn cmd_finish(&self, _command_buffer: CommandBuffer) {}
+}
+
+/// Layouts that several demos' pipelines are made with, created once by the renderer. Passes keep
+/// a share of the ones they use, so a retired pass still waiting in the deletion queue keeps them
+/// alive alongside its replacement instead of each making its own.
+pub struct SharedLayouts<'c> {
+    /// `MeshPushConstants` alone, for the mesh pipelines and their depth-only variants.
+    mesh: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    /// `descriptor::texture_view_bindings`, for full-screen views of one texture.
+    texture_view_set: Shared<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// `texture_view_set` without push constants.
+    texture_view: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+}
+
+impl<'c> SharedLayouts<'c> {
+    pub fn new(context: &'c Context) -> std::result::Result<SharedLayouts<'c>, RendererError> {
+        let texture_view_set = create_set_layout(context, &descriptor::texture_view_bindings())?;
+        let texture_view = create_pipeline_layout(context, &[*texture_view_set], &[])?;
+        Ok(SharedLayouts {
+            mesh: create_pipeline_layout(context, &[], &[MeshPushConstants::range()])?.into_shared(),
+            texture_view_set: texture_view_set.into_shared(),
+            texture_view: texture_view.into_shared(),
+        })
+    }
+}
+
+/// The pass for the demo called `name`, drawing in subpass 0 of `render_pass` at `extent`.
+/// `None` for demos that are the scene itself, which is also what any demo without a pass of its
+/// own shows.
+pub fn create<'c>(context: &'c Context, layouts: &SharedLayouts<'c>, name: &str, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<Option<Box<dyn DemoPass<'c> + 'c>>, RendererError> {
+    Ok(match name {
+        "Primitives" => Some(Box::new(PrimitivesDemo::new(context, layouts, render_pass, extent)?)),
+        "Wireframe" => Some(Box::new(PrimitivesDemo::wireframe(context, layouts, render_pass, extent)?)),
+        "Vertex pulling" => Some(Box::new(VertexPullingDemo::new(context, layouts, render_pass, extent)?)),
+        "Vertex quantization" => Some(Box::new(QuantizationDemo::new(context, layouts, render_pass, extent)?)),
+        "Pipeline library" => {
+            log_link_cost(context, layouts, render_pass, extent)?;
+            Some(Box::new(PrimitivesDemo::new(context, layouts, render_pass, extent)?))
+        },
+        "Checkerboard" => Some(Box::new(CheckerboardDemo::new(context, layouts, render_pass, extent)?)),
+        "Debug views" => Some(Box::new(DebugViewsDemo::new(context, layouts, render_pass, extent)?)),
+        "Signed distance fields" => Some(Box::new(SdfDemo::new(context, render_pass, extent)?)),
+        "Compute gradient" => Some(Box::new(GradientDemo::new(context, extent)?)),
+        "Cellular automata" => Some(Box::new(AutomataDemo::new(context, layouts, render_pass, extent)?)),
+        "Noise textures" => Some(Box::new(NoiseDemo::new(context, layouts, render_pass, extent)?)),
+        "N-body" => Some(Box::new(NBodyDemo::new(context, render_pass, extent)?)),
+        "Flow field" => Some(Box::new(FlowFieldDemo::new(context, render_pass, extent)?)),
+        "Volumetric fog" => Some(Box::new(FogDemo::new(context, render_pass, extent)?)),
+        "Ambient occlusion" => Some(Box::new(AmbientOcclusionDemo::new(context, layouts, render_pass, extent)?)),
+        "Normal visualization" => Some(Box::new(NormalVisDemo::new(context, layouts, render_pass, extent)?)),
+        "Point cloud" => Some(Box::new(PointCloudDemo::new(context, render_pass, extent)?)),
+        "Shadow atlas" => Some(Box::new(ShadowAtlasDemo::new(context, layouts, render_pass, extent)?)),
+        "Texture array" => Some(Box::new(TextureArrayDemo::new(context, render_pass, extent)?)),
+        "Anisotropic filtering" => Some(Box::new(AnisotropyDemo::new(context, layouts, render_pass, extent)?)),
+        "Deep zoom" => Some(Box::new(DeepZoomDemo::new(context, render_pass, extent)?)),
+        "Radix sort" => Some(Box::new(ParticlesDemo::new(context, render_pass, extent)?)),
+        "Subgroup reduction" => Some(Box::new(ReductionDemo::new(context, render_pass, extent)?)),
+        #[cfg(feature = "physics")]
+        "Box drop" => Some(Box::new(BoxDropDemo::new(context, render_pass, extent)?)),
+        _ => None,
+    })
+}
+
+fn create_pipeline_layout<'c>(context: &'c Context, set_layouts: &[DescriptorSetLayout], push_constant_ranges: &[PushConstantRange]) -> std::result::Result<Owned<'c, ash::Device<V1_0>, PipelineLayout>, RendererError> {
+    context.limits.check_push_constants(push_constant_ranges)
+        .map_err(|e| RendererError::Unsupported(format!("demo pipeline layout: {}", e)))?;
+    let create_info = PipelineLayoutCreateInfo {
+        s_type: StructureType::PipelineLayoutCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        set_layout_count: set_layouts.len() as u32,
+        p_set_layouts: set_layouts.as_ptr(),
+        push_constant_range_count: push_constant_ranges.len() as u32,
+        p_push_constant_ranges: push_constant_ranges.as_ptr(),
+    };
+    Ok(safe_create::create_pipeline_layout_safe(context.device(), &create_info, None)?)
+}
+
+fn create_set_layout<'c>(context: &'c Context, bindings: &[DescriptorSetLayoutBinding]) -> std::result::Result<Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>, RendererError> {
+    context.limits.check_descriptor_bindings(bindings)
+        .map_err(|e| RendererError::Unsupported(format!("demo descriptor set layout: {}", e)))?;
+    let create_info = DescriptorSetLayoutCreateInfo {
+        s_type: StructureType::DescriptorSetLayoutCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        binding_count: bindings.len() as u32,
+        p_bindings: bindings.as_ptr(),
+    };
+    Ok(safe_create::create_descriptor_set_layout_safe(context.device(), &create_info, None)?)
+}
+
+fn load_kernel<'c>(context: &'c Context, path: &str, bindings: &[DescriptorSetLayoutBinding], push_constant_size: u32) -> std::result::Result<ComputeKernel<'c, ash::Device<V1_0>>, RendererError> {
+    let spirv = shader_compile::load_spirv(path).map_err(|e| RendererError::Shader(path.to_string(), e))?;
+    Ok(ComputeKernel::new(context.device(), &spirv, bindings, push_constant_size)?)
+}
+
+/// The ray marched scene from `sdf`, lit from above and to the side.
+struct SdfDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+}
+
+impl<'c> SdfDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<SdfDemo<'c>, RendererError> {
+        let device = context.device();
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, sdf::FRAGMENT_SHADER)?;
+        let layout = create_pipeline_layout(context, &[], &[SdfPushConstants::range()])?;
+        let pipeline = sdf::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        Ok(SdfDemo {
+            device: device,
+            pipeline: pipeline,
+            layout: layout,
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for SdfDemo<'c> {
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        let constants = match SdfPushConstants::new(&view.view_projection(), view.eye, Vec3::new(0.6, 1.0, 0.4), view.time) {
+            Some(constants) => constants,
+            None => return,
+        };
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        constants.cmd_draw(self.device, command_buffer, *self.layout);
+    }
+}
+
+/// `gradient`'s storage image, written on the compute queue and blitted to the swapchain image.
+/// The compute queue doesn't share the frame's semaphores, so `prepare` waits for the dispatch
+/// before the frame is submitted. Each frame slot has its own image, since the frame before might
+/// still be blitting from the last one.
+struct GradientDemo<'c> {
+    gradients: Vec<Gradient<'c, ash::Device<V1_0>>>,
+    /// Into `gradients`, for the frame being recorded.
+    current: usize,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    immediate: ImmediateContext<'c, ash::Device<V1_0>>,
+}
+
+impl<'c> GradientDemo<'c> {
+    fn new(context: &'c Context, extent: &Extent2D) -> std::result::Result<GradientDemo<'c>, RendererError> {
+        let device = context.device();
+        if !gradient::is_supported(context.instance().instance(), context.physical_device, context.surface_format.format) {
+            return Err(RendererError::Unsupported("blitting from the gradient's storage image".to_string()));
+        }
+        let shader = renderer::load_shader_module(device, gradient::SHADER)?;
+        let families = if context.compute_family == context.graphics_family {
+            vec![context.graphics_family]
+        } else {
+            vec![context.graphics_family, context.compute_family]
+        };
+        let gradients = (0..frame::FRAMES_IN_FLIGHT)
+            .map(|_| Gradient::new(device, &context.allocator, extent.clone(), &families))
+            .collect::<VkResult<Vec<_>>>()?;
+        let pipeline = gradient::create_pipeline(device, gradients[0].pipeline_layout(), *shader)?;
+        Ok(GradientDemo {
+            gradients: gradients,
+            current: 0,
+            pipeline: pipeline,
+            immediate: ImmediateContext::new(device, context.compute_family, context.compute_queue)?,
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for GradientDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.current = frame.slot;
+        let (gradient, pipeline) = (&self.gradients[self.current], *self.pipeline);
+        self.immediate.immediate_submit(|command_buffer| unsafe { gradient.cmd_dispatch(command_buffer, pipeline, view.time) })
+    }
+
+    fn blits(&self) -> bool {
+        true
+    }
+
+    unsafe fn cmd_blit(&self, command_buffer: CommandBuffer, image: Image, extent: &Extent2D) {
+        self.gradients[self.current].cmd_blit_to(command_buffer, image, extent);
+    }
+
+    unsafe fn cmd_draw(&self, _command_buffer: CommandBuffer, _view: &DemoView) {}
+}
+
+/// Pixels per side of a cell.
+const AUTOMATA_CELL_SIZE: u32 = 4;
+
+/// A cellular automaton covering the screen, stepped at `automata.steps_per_second` under the
+/// rule in `automata.rule`, and reseeded when it's selected again.
+struct AutomataDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    automaton: CellularAutomaton<'c, ash::Device<V1_0>>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    _draw_set_layout: Shared<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    kernel: ComputeKernel<'c, ash::Device<V1_0>>,
+    seeded: bool,
+    /// Seconds of scene time not yet stepped through.
+    pending: f32,
+}
+
+impl<'c> AutomataDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<AutomataDemo<'c>, RendererError> {
+        let device = context.device();
+        let kernel = load_kernel(context, automata::STEP_SHADER, &CellularAutomaton::<ash::Device<V1_0>>::compute_bindings(), CellularAutomaton::<ash::Device<V1_0>>::push_constant_size())?;
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, automata::FRAGMENT_SHADER)?;
+        let draw_set_layout = layouts.texture_view_set.clone();
+        let layout = layouts.texture_view.clone();
+        let pipeline = automata::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let grid = rect::extent(std::cmp::max(extent.width / AUTOMATA_CELL_SIZE, 1), std::cmp::max(extent.height / AUTOMATA_CELL_SIZE, 1));
+        let mut automaton = CellularAutomaton::new(device, &context.allocator, &kernel, *draw_set_layout, grid)?;
+        let rule: String = cvar!("automata.rule", "B3/S23".to_string()).get();
+        automaton.rule = Rule::parse(&rule).unwrap_or_else(|| {
+            warn!("Invalid automata.rule {:?}, using Conway's", rule);
+            Rule::conway()
+        });
+        debug!("Created {}x{} cellular automaton", automaton.extent().width, automaton.extent().height);
+        Ok(AutomataDemo {
+            device: device,
+            automaton: automaton,
+            pipeline: pipeline,
+            layout: layout,
+            _draw_set_layout: draw_set_layout,
+            kernel: kernel,
+            seeded: false,
+            pending: 0.0,
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for AutomataDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        let command_buffer = frame.recorder.command_buffer();
+        if !self.seeded {
+            // Any frame index makes a different starting grid, as long as the seed isn't 0
+            unsafe { self.automaton.cmd_seed(command_buffer, &self.kernel, frame.frame_index as u32 | 1) };
+            self.seeded = true;
+            return Ok(());
+        }
+        let steps_per_second: f32 = cvar!("automata.steps_per_second", 15.0).get();
+        self.pending += frame.time.delta() * steps_per_second;
+        // Catching up after a hitch isn't worth more than a few steps a frame
+        let steps = std::cmp::min(self.pending as u32, 4);
+        self.pending -= self.pending.floor();
+        for _ in 0..steps {
+            unsafe { self.automaton.cmd_step(command_buffer, &self.kernel) };
+        }
+        Ok(())
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, _view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &[self.automaton.draw_descriptor_set()], &[]);
+        self.automaton.cmd_draw(command_buffer);
+    }
+}
+
+/// Steps timed between throughput logs.
+const NBODY_TIMED_STEPS: u32 = 600;
+
+/// `nbody.count` bodies in a disk around the origin, stepped once a frame. The throughput is
+/// logged every `NBODY_TIMED_STEPS` steps the GPU profiler timed.
+struct NBodyDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    bodies: NBody<'c, ash::Device<V1_0>>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    _draw_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    kernel: ComputeKernel<'c, ash::Device<V1_0>>,
+    timed_steps: u32,
+    timed_milliseconds: f64,
+}
+
+impl<'c> NBodyDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<NBodyDemo<'c>, RendererError> {
+        let device = context.device();
+        let kernel = load_kernel(context, nbody::STEP_SHADER, &NBody::<ash::Device<V1_0>>::compute_bindings(), std::mem::size_of::<NBodyPushConstants>() as u32)?;
+        let vertex_shader = renderer::load_shader_module(device, nbody::VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, nbody::FRAGMENT_SHADER)?;
+        let draw_set_layout = create_set_layout(context, &NBody::<ash::Device<V1_0>>::draw_bindings())?;
+        let layout = create_pipeline_layout(context, &[*draw_set_layout], &[NBodyDrawPushConstants::range()])?;
+        let pipeline = nbody::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let count: u32 = cvar!("nbody.count", 16384).get();
+        let bodies = NBody::new(device, &context.allocator, &kernel, *draw_set_layout, std::cmp::max(count, 1))?;
+        Ok(NBodyDemo {
+            device: device,
+            bodies: bodies,
+            pipeline: pipeline,
+            layout: layout,
+            _draw_set_layout: draw_set_layout,
+            kernel: kernel,
+            timed_steps: 0,
+            timed_milliseconds: 0.0,
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for NBodyDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        self.bodies.follow_time(frame.time);
+        unsafe { self.bodies.cmd_step(frame.recorder.command_buffer(), &self.kernel) };
+        Ok(())
+    }
+
+    fn gpu_time(&mut self, milliseconds: f32) {
+        self.timed_steps += 1;
+        self.timed_milliseconds += milliseconds as f64;
+        if self.timed_steps == NBODY_TIMED_STEPS {
+            self.bodies.log_throughput(self.timed_steps, Duration::from_nanos((self.timed_milliseconds * 1e6) as u64));
+            self.timed_steps = 0;
+            self.timed_milliseconds = 0.0;
+        }
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &[self.bodies.draw_descriptor_set()], &[]);
+        // Sizes other than 1 would need the largePoints feature
+        self.bodies.cmd_draw(command_buffer, *self.layout, &view.view_projection(), 1.0);
+    }
+}
+
+/// Half the side of the cube the flow field fills, in world units.
+const FLOW_FIELD_EXTENT: f32 = 1.0;
+
+/// A `flow_field.resolution`³ curl-noise field around the origin, regenerated every frame so it
+/// flows with scene time.
+struct FlowFieldDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    field: FlowField<'c, ash::Device<V1_0>>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    draw_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    kernel: ComputeKernel<'c, ash::Device<V1_0>>,
+    /// This frame's, from the frame's descriptor sets.
+    draw_set: DescriptorSet,
+}
+
+impl<'c> FlowFieldDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<FlowFieldDemo<'c>, RendererError> {
+        let device = context.device();
+        let kernel = load_kernel(context, flow_field::NOISE_SHADER, &FlowField::<ash::Device<V1_0>>::compute_bindings(), std::mem::size_of::<CurlNoisePushConstants>() as u32)?;
+        let vertex_shader = renderer::load_shader_module(device, flow_field::VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, flow_field::FRAGMENT_SHADER)?;
+        let draw_set_layout = create_set_layout(context, &FlowField::<ash::Device<V1_0>>::draw_bindings())?;
+        let layout = create_pipeline_layout(context, &[*draw_set_layout], &[FlowFieldPushConstants::range()])?;
+        let pipeline = flow_field::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let resolution: u32 = cvar!("flow_field.resolution", 32).get();
+        let field = FlowField::new(device, &context.allocator, std::cmp::max(resolution, 1))?;
+        Ok(FlowFieldDemo {
+            device: device,
+            field: field,
+            pipeline: pipeline,
+            layout: layout,
+            draw_set_layout: draw_set_layout,
+            kernel: kernel,
+            draw_set: DescriptorSet::null(),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for FlowFieldDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        let compute_set = frame.descriptors.allocate(self.kernel.set_layout())?;
+        descriptor::update_descriptor_set(self.device, compute_set, &[self.field.storage_write()]);
+        self.draw_set = frame.descriptors.allocate(*self.draw_set_layout)?;
+        descriptor::update_descriptor_set(self.device, self.draw_set, &[self.field.sampled_write()]);
+        let frequency: f32 = cvar!("flow_field.frequency", 2.0).get();
+        unsafe { self.field.cmd_generate(frame.recorder.command_buffer(), &self.kernel, compute_set, &CurlNoisePushConstants::at(frame.time, frequency)) };
+        Ok(())
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &[self.draw_set], &[]);
+        self.field.cmd_draw(command_buffer, *self.layout, &view.view_projection(), FLOW_FIELD_EXTENT);
+    }
+}
+
+/// Froxels per axis, which is plenty for 1080p.
+const FOG_GRID: Extent3D = Extent3D { width: 160, height: 90, depth: 64 };
+/// Camera movement in one frame that counts as a cut, after which the history is dropped.
+const FOG_CUT_DISTANCE: f32 = 2.0;
+
+/// Volumetric fog with `fog.density` at ground level, over nothing: the depth the apply pass
+/// reads is a 1x1 texture at the far plane, so every pixel shows the fog out to `far`.
+struct FogDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    fog: VolumetricFog<'c, ash::Device<V1_0>>,
+    far_depth: Texture<'c, ash::Device<V1_0>>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    apply_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    inject: ComputeKernel<'c, ash::Device<V1_0>>,
+    integrate: ComputeKernel<'c, ash::Device<V1_0>>,
+    /// This frame's, from the frame's descriptor sets.
+    apply_set: DescriptorSet,
+    /// What the grid was last updated for.
+    fog_view: FogView,
+}
+
+impl<'c> FogDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<FogDemo<'c>, RendererError> {
+        let device = context.device();
+        let inject = load_kernel(context, volumetric_fog::INJECT_SHADER, &VolumetricFog::<ash::Device<V1_0>>::inject_bindings(), VolumetricFog::<ash::Device<V1_0>>::inject_push_constant_size())?;
+        let integrate = load_kernel(context, volumetric_fog::INTEGRATE_SHADER, &VolumetricFog::<ash::Device<V1_0>>::integrate_bindings(), VolumetricFog::<ash::Device<V1_0>>::integrate_push_constant_size())?;
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, volumetric_fog::APPLY_SHADER)?;
+        let apply_set_layout = create_set_layout(context, &VolumetricFog::<ash::Device<V1_0>>::apply_bindings())?;
+        let layout = create_pipeline_layout(context, &[*apply_set_layout], &[FogApplyPushConstants::range()])?;
+        let pipeline = volumetric_fog::apply_pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let mut fog = VolumetricFog::new(device, &context.allocator, &inject, &integrate, FOG_GRID)?;
+        fog.settings.density = cvar!("fog.density", fog.settings.density).get();
+        // Unorm, so the red channel samples as exactly 1
+        let far_plane = ImageData {
+            width: 1,
+            height: 1,
+            pixels: vec![255, 255, 255, 255],
+        };
+        let immediate = ImmediateContext::new(device, context.graphics_family, context.graphics_queue)?;
+        let far_depth = Texture::new(device, &context.allocator, &immediate, &far_plane, TextureEncoding::Data, 1)?;
+        Ok(FogDemo {
+            device: device,
+            fog: fog,
+            far_depth: far_depth,
+            pipeline: pipeline,
+            layout: layout,
+            apply_set_layout: apply_set_layout,
+            inject: inject,
+            integrate: integrate,
+            apply_set: DescriptorSet::null(),
+            fog_view: FogView {
+                inverse_view_projection: Mat4::identity(),
+                eye: Vec3::new(0.0, 0.0, 0.0),
+            },
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for FogDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.apply_set = frame.descriptors.allocate(*self.apply_set_layout)?;
+        descriptor::update_descriptor_set(self.device, self.apply_set, &self.fog.apply_writes(self.far_depth.sampler(), self.far_depth.view(), ImageLayout::ShaderReadOnlyOptimal));
+        if (view.eye - self.fog_view.eye).length() > FOG_CUT_DISTANCE {
+            self.fog.reset_history();
+        }
+        self.fog_view = FogView {
+            // A camera that can't be inverted keeps last frame's grid
+            inverse_view_projection: view.view_projection().inverse().unwrap_or(self.fog_view.inverse_view_projection),
+            eye: view.eye,
+        };
+        unsafe { self.fog.cmd_update(frame.recorder.command_buffer(), &self.inject, &self.integrate, &self.fog_view) };
+        Ok(())
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, _view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &[self.apply_set], &[]);
+        self.fog.cmd_apply(command_buffer, *self.layout, &self.fog_view);
+    }
+}
+
+/// Cycles the ambient occlusion through full, half and quarter resolution.
+const EFFECT_SCALE_KEY: glfw::Key = glfw::Key::H;
+
+/// Where the ambient occlusion demo's ground sits, under the primitive.
+const GROUND_HEIGHT: f32 = -0.5;
+const GROUND_SIZE: f32 = 6.0;
+
+/// A spinning primitive over a ground plane, darkened by SSAO run at the scale `--effect-scale`
+/// gives `ssao`, and cycled with `EFFECT_SCALE_KEY`. The primitive's depth is drawn into its own
+/// target first, since the frame's depth buffer can't be sampled.
+struct AmbientOcclusionDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    allocator: &'c MemoryAllocator<ash::Device<V1_0>>,
+    extent: Extent2D,
+    model: PrimitiveModel<'c>,
+    ground: PrimitiveModel<'c>,
+    mesh_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    mesh_layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    depth: DepthTarget<'c, ash::Device<V1_0>>,
+    depth_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    /// What `EFFECT_SCALE_KEY` last picked, which `target` catches up to in `prepare`.
+    scale: EffectScale,
+    target: EffectTarget<'c, ash::Device<V1_0>>,
+    downsample: ComputeKernel<'c, ash::Device<V1_0>>,
+    ssao: ComputeKernel<'c, ash::Device<V1_0>>,
+    upsample_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    upsample_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    upsample_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// This frame's, from the frame's descriptor sets.
+    upsample_set: DescriptorSet,
+}
+
+impl<'c> AmbientOcclusionDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<AmbientOcclusionDemo<'c>, RendererError> {
+        let device = context.device();
+        let (mesh_pipeline, mesh_layout) = create_mesh_pipeline(context, layouts, render_pass, extent)?;
+        let mesh_vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+        let depth_format = format_support::depth_format(context.instance().instance(), context.physical_device, false, true)
+            .ok_or_else(|| RendererError::Unsupported("no sampled depth format".to_string()))?;
+        let depth = DepthTarget::new(device, &context.allocator, depth_format, extent.clone())?;
+        let depth_pipeline = gbuffer_view::depth_pipeline_builder(extent, *mesh_vertex_shader)
+            .vertex_input(&MeshVertex::binding_descriptions(), &MeshVertex::attribute_descriptions())
+            .build(device, *mesh_layout, depth.render_pass(), 0)?;
+        let scale = context.options.effect_scales.get(Effect::Ssao);
+        let target = EffectTarget::new(device, &context.allocator, Effect::Ssao, scale, extent)?;
+        let downsample = load_kernel(context, half_res::DEPTH_DOWNSAMPLE_SHADER, &EffectTarget::<ash::Device<V1_0>>::downsample_bindings(), std::mem::size_of::<DownsamplePushConstants>() as u32)?;
+        let ssao = load_kernel(context, ssao::SHADER, &ssao::bindings(), std::mem::size_of::<SsaoPushConstants>() as u32)?;
+        let fullscreen_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let upsample_shader = renderer::load_shader_module(device, half_res::UPSAMPLE_FRAGMENT_SHADER)?;
+        let upsample_set_layout = create_set_layout(context, &EffectTarget::<ash::Device<V1_0>>::upsample_bindings())?;
+        let upsample_layout = create_pipeline_layout(context, &[*upsample_set_layout], &[UpsamplePushConstants::range()])?;
+        let upsample_pipeline = half_res::upsample_pipeline_builder(extent, Effect::Ssao, *fullscreen_shader, *upsample_shader).build(device, *upsample_layout, render_pass, 0)?;
+        Ok(AmbientOcclusionDemo {
+            device: device,
+            allocator: &context.allocator,
+            extent: extent.clone(),
+            model: PrimitiveModel::new(context, Primitive::Cube)?,
+            ground: PrimitiveModel::new(context, Primitive::Plane)?,
+            mesh_pipeline: mesh_pipeline,
+            mesh_layout: mesh_layout,
+            depth: depth,
+            depth_pipeline: depth_pipeline,
+            scale: scale,
+            target: target,
+            downsample: downsample,
+            ssao: ssao,
+            upsample_pipeline: upsample_pipeline,
+            upsample_layout: upsample_layout,
+            upsample_set_layout: upsample_set_layout,
+            upsample_set: DescriptorSet::null(),
+        })
+    }
+
+    fn ground_model() -> Mat4 {
+        Mat4::translation(Vec3::new(0.0, GROUND_HEIGHT, 0.0)) * Mat4::scale(Vec3::new(GROUND_SIZE, 1.0, GROUND_SIZE))
+    }
+
+    /// Records the ground and the primitive, with a pipeline using `layout` bound.
+    unsafe fn cmd_draw_scene(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view: &DemoView) {
+        let view_projection = view.view_projection();
+        self.ground.cmd_draw_at(command_buffer, layout, &view_projection, AmbientOcclusionDemo::ground_model());
+        self.model.cmd_draw(command_buffer, layout, view);
+    }
+}
+
+impl<'c> DemoPass<'c> for AmbientOcclusionDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.model.prepare(frame)?;
+        if self.target.scale() != self.scale {
+            let target = EffectTarget::new(self.device, self.allocator, Effect::Ssao, self.scale, &self.extent)?;
+            let old = std::mem::replace(&mut self.target, target);
+            frame.defer_destroy(move || drop(old));
+        }
+        let command_buffer = frame.recorder.command_buffer();
+        {
+            let mut recorder = unsafe { CommandRecorder::recording(self.device, command_buffer) };
+            let target = self.depth.target();
+            let _pass = recorder.begin_render_pass(&target, &DepthTarget::<ash::Device<V1_0>>::clear_values());
+            unsafe {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.depth_pipeline);
+                self.cmd_draw_scene(command_buffer, *self.mesh_layout, view);
+            }
+        }
+        let depth_layout = ImageLayout::DepthStencilReadOnlyOptimal;
+        let downsample_set = frame.descriptors.allocate(self.downsample.set_layout())?;
+        descriptor::update_descriptor_set(self.device, downsample_set, &self.target.downsample_writes(self.depth.view(), depth_layout));
+        let ssao_set = frame.descriptors.allocate(self.ssao.set_layout())?;
+        descriptor::update_descriptor_set(self.device, ssao_set, &ssao::writes(&self.target));
+        self.upsample_set = frame.descriptors.allocate(*self.upsample_set_layout)?;
+        descriptor::update_descriptor_set(self.device, self.upsample_set, &self.target.upsample_writes(self.depth.view(), depth_layout));
+        let radius: f32 = cvar!("ssao.radius", 0.5).get();
+        let intensity: f32 = cvar!("ssao.intensity", 1.5).get();
+        let constants = SsaoPushConstants::new(&self.target, &view.projection, view.near, view.far, radius, intensity);
+        unsafe {
+            self.target.cmd_downsample_depth(command_buffer, &self.downsample, downsample_set);
+            self.target.cmd_begin_effect(command_buffer);
+            self.ssao.cmd_dispatch(command_buffer, ssao_set, &constants, ssao::group_count(&self.target));
+            self.target.cmd_end_effect(command_buffer);
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key == EFFECT_SCALE_KEY {
+            self.scale = self.scale.next();
+            info!("Running {} at {:?} resolution", self.target.effect().name(), self.scale);
+            return true;
+        }
+        self.model.handle_key(key)
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.mesh_pipeline);
+        self.cmd_draw_scene(command_buffer, *self.mesh_layout, view);
+        let constants = self.target.upsample_push_constants(cvar!("ssao.depth_sigma", half_res::DEFAULT_DEPTH_SIGMA).get());
+        let range = UpsamplePushConstants::range();
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.upsample_pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.upsample_layout, 0, &[self.upsample_set], &[]);
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, *self.upsample_layout, range.stage_flags, range.offset, range.size, &constants as *const UpsamplePushConstants as *const c_void);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Toggles the point cloud between points and splats.
+const POINT_MODE_KEY: glfw::Key = glfw::Key::M;
+
+/// The file in `point_cloud.path`, or `point_cloud.count` points of synthetic terrain if there
+/// isn't one, drawn as `point_cloud.size` pixel points or splats.
+struct PointCloudDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    cloud: PointCloud<'c, ash::Device<V1_0>>,
+    points_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    splats_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    point_size: f32,
+}
+
+impl<'c> PointCloudDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<PointCloudDemo<'c>, RendererError> {
+        let device = context.device();
+        let layout = create_pipeline_layout(context, &[], &[PointCloudPushConstants::range()])?;
+        let points_pipeline = {
+            let vertex_shader = renderer::load_shader_module(device, point_cloud::POINT_VERTEX_SHADER)?;
+            let fragment_shader = renderer::load_shader_module(device, point_cloud::POINT_FRAGMENT_SHADER)?;
+            PointMode::Points.pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?
+        };
+        let splats_pipeline = {
+            let vertex_shader = renderer::load_shader_module(device, point_cloud::SPLAT_VERTEX_SHADER)?;
+            let fragment_shader = renderer::load_shader_module(device, point_cloud::SPLAT_FRAGMENT_SHADER)?;
+            PointMode::Splats.pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?
+        };
+        let path: String = cvar!("point_cloud.path", String::new()).get();
+        let loaded = if path.is_empty() {
+            None
+        } else {
+            point_cloud::load(&path).map_err(|e| warn!("Failed to load point cloud {:?}, using terrain instead: {}", path, e)).ok()
+        };
+        let mut points = loaded.unwrap_or_else(|| point_cloud::synthetic_terrain(cvar!("point_cloud.count", 1 << 20).get(), 64.0));
+        let cloud = PointCloud::new(device, &context.allocator, &mut points)?;
+        let bounds = cloud.bounds();
+        info!("Point cloud: {} points in {} chunks, from {:?} to {:?}", cloud.point_count(), cloud.chunks().len(), bounds.min, bounds.max);
+        Ok(PointCloudDemo {
+            device: device,
+            cloud: cloud,
+            points_pipeline: points_pipeline,
+            splats_pipeline: splats_pipeline,
+            layout: layout,
+            point_size: cvar!("point_cloud.size", 2.0).get(),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for PointCloudDemo<'c> {
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != POINT_MODE_KEY {
+            return false;
+        }
+        let mode = self.cloud.mode().toggle();
+        info!("Drawing the point cloud as {:?}", mode);
+        self.cloud.set_mode(mode);
+        true
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        let view_projection = view.view_projection();
+        let pipeline = match self.cloud.mode() {
+            PointMode::Points => &self.points_pipeline,
+            PointMode::Splats => &self.splats_pipeline,
+        };
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, **pipeline);
+        let constants = PointCloudPushConstants::new(view_projection, self.point_size, &view.extent);
+        let range = PointCloudPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, *self.layout, range.stage_flags, range.offset, range.size, &constants as *const PointCloudPushConstants as *const c_void);
+        let stats = self.cloud.cmd_draw(command_buffer, &Frustum::from_view_projection(&view_projection));
+        trace!("Point cloud: {} of {} points in {} chunks, {} draws", stats.visible_points, self.cloud.point_count(), stats.visible_chunks, stats.draws);
+    }
+}
+
+/// Switches to the next primitive, in demos that show one.
+const PRIMITIVE_KEY: glfw::Key = glfw::Key::N;
+
+/// One of the `primitives` meshes at the origin, turning slowly about Y with scene time.
+struct PrimitiveModel<'c> {
+    device: &'c ash::Device<V1_0>,
+    allocator: &'c MemoryAllocator<ash::Device<V1_0>>,
+    /// What `PRIMITIVE_KEY` last picked, which `mesh` catches up to in `prepare`.
+    selected: Primitive,
+    shown: Primitive,
+    mesh: MeshBuffer<'c, ash::Device<V1_0>>,
+    /// Kept across uploads, since each new `mesh` starts out with fixed-function fetch.
+    fetch: VertexFetch,
+}
+
+impl<'c> PrimitiveModel<'c> {
+    fn new(context: &'c Context, primitive: Primitive) -> VkResult<PrimitiveModel<'c>> {
+        Ok(PrimitiveModel {
+            device: context.device(),
+            allocator: &context.allocator,
+            selected: primitive,
+            shown: primitive,
+            mesh: primitive.mesh().upload(context.device(), &context.allocator)?,
+            fetch: VertexFetch::FixedFunction,
+        })
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != PRIMITIVE_KEY {
+            return false;
+        }
+        self.selected = self.selected.next();
+        info!("Showing primitive {:?}", self.selected);
+        true
+    }
+
+    /// Uploads the selected primitive if it isn't the one shown yet. The old mesh goes to the
+    /// frame's deletion queue, since frames in flight might still be drawing it.
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>) -> VkResult<()> {
+        if self.selected != self.shown {
+            let mut mesh = self.selected.mesh().upload(self.device, self.allocator)?;
+            mesh.set_fetch(self.fetch);
+            let old = std::mem::replace(&mut self.mesh, mesh);
+            frame.defer_destroy(move || drop(old));
+            self.shown = self.selected;
+        }
+        Ok(())
+    }
+
+    /// Selects how the mesh is drawn, which has to match the pipeline bound for `cmd_draw`.
+    fn set_fetch(&mut self, fetch: VertexFetch) {
+        self.fetch = fetch;
+        self.mesh.set_fetch(fetch);
+    }
+
+    fn model(&self, view: &DemoView) -> Mat4 {
+        Mat4::rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), view.time * 0.5))
+    }
+
+    /// Records the shaded draw, with a `mesh::VERTEX_SHADER` pipeline bound.
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view: &DemoView) {
+        self.cmd_draw_from(command_buffer, layout, &view.view_projection(), view);
+    }
+
+    /// Records the draw as seen through `view_projection` instead of the camera, like a light's.
+    unsafe fn cmd_draw_from(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view_projection: &Mat4, view: &DemoView) {
+        self.cmd_draw_at(command_buffer, layout, view_projection, self.model(view));
+    }
+
+    /// Records the draw placed by `model` instead of spinning.
+    unsafe fn cmd_draw_at(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view_projection: &Mat4, model: Mat4) {
+        let constants = MeshPushConstants {
+            model_view_projection: *view_projection * model,
+            model: model,
+        };
+        let range = MeshPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const MeshPushConstants as *const c_void);
+        self.mesh.cmd_draw(self.device, command_buffer);
+    }
+}
+
+/// A pipeline and the layout it was created with.
+type PipelineAndLayout<'c> = (Owned<'c, ash::Device<V1_0>, Pipeline>, Shared<'c, ash::Device<V1_0>, PipelineLayout>);
+
+/// The mesh pipeline and its layout, drawing `MeshVertex` meshes shaded like the scene. It's
+/// linked from `pipeline_library` parts when the device has the extensions for it.
+fn create_mesh_pipeline<'c>(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<PipelineAndLayout<'c>, RendererError> {
+    let device = context.device();
+    let vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+    let fragment_shader = renderer::load_shader_module(device, mesh::FRAGMENT_SHADER)?;
+    let layout = layouts.mesh.clone();
+    let builder = VertexFetch::FixedFunction.pipeline_builder(extent, *vertex_shader, *fragment_shader);
+    let pipeline = if pipeline_library::is_enabled(context.enabled_extensions.iter().map(|name| name.as_str())) {
+        PipelineLibrary::new(device, PipelineCache::null(), &builder, *layout, render_pass, 0)?.link(PipelineCache::null(), *layout, false)?
+    } else {
+        builder.build(device, *layout, render_pass, 0)?
+    };
+    Ok((pipeline, layout))
+}
+
+/// Builds the mesh pipeline both monolithically and from `pipeline_library` parts, which logs
+/// how long each took, and warns if linking didn't save anything.
+fn log_link_cost(context: &Context, layouts: &SharedLayouts, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<(), RendererError> {
+    let device = context.device();
+    let vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+    let fragment_shader = renderer::load_shader_module(device, mesh::FRAGMENT_SHADER)?;
+    let layout = *layouts.mesh;
+    let builder = VertexFetch::FixedFunction.pipeline_builder(extent, *vertex_shader, *fragment_shader);
+    let timings = pipeline_library::compare_link_cost(device, PipelineCache::null(), &builder, layout, render_pass, 0)?;
+    // Libraries pay off when linking beats a monolithic build, even with the parts built up front
+    if timings.fast_link >= timings.monolithic || timings.optimized_link >= timings.monolithic + timings.libraries {
+        warn!("Linking the mesh pipeline wasn't any faster than building it monolithically");
+    }
+    Ok(())
+}
+
+/// Each procedural primitive in turn, cycled with `PRIMITIVE_KEY`.
+/// Of the "Wireframe" demo's lines, in pixels.
+const WIREFRAME_LINE_WIDTH: f32 = 2.0;
+
+struct PrimitivesDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    model: PrimitiveModel<'c>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+}
+
+impl<'c> PrimitivesDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<PrimitivesDemo<'c>, RendererError> {
+        let (pipeline, layout) = create_mesh_pipeline(context, layouts, render_pass, extent)?;
+        Ok(PrimitivesDemo {
+            device: context.device(),
+            model: PrimitiveModel::new(context, Primitive::Cube)?,
+            pipeline: pipeline,
+            layout: layout,
+        })
+    }
+
+    /// The primitives' edges only, `WIREFRAME_LINE_WIDTH` wide, which needs the fillModeNonSolid
+    /// and wideLines features.
+    fn wireframe(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<PrimitivesDemo<'c>, RendererError> {
+        let device = context.device();
+        let vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, mesh::FRAGMENT_SHADER)?;
+        let layout = layouts.mesh.clone();
+        let pipeline = VertexFetch::FixedFunction.pipeline_builder(extent, *vertex_shader, *fragment_shader)
+            .polygon_mode(PolygonMode::Line)
+            .line_width(WIREFRAME_LINE_WIDTH)
+            .cull_mode(CULL_MODE_NONE)
+            .build(device, *layout, render_pass, 0)?;
+        Ok(PrimitivesDemo {
+            device: device,
+            model: PrimitiveModel::new(context, Primitive::Cube)?,
+            pipeline: pipeline,
+            layout: layout,
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for PrimitivesDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        self.model.prepare(frame)
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        self.model.handle_key(key)
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.model.cmd_draw(command_buffer, *self.layout, view);
+    }
+}
+
+/// Switches between fixed-function vertex fetch and vertex pulling.
+const FETCH_KEY: glfw::Key = glfw::Key::V;
+
+/// A primitive drawn with either `VertexFetch`, so the frame stats can compare the two on the
+/// same mesh.
+struct VertexPullingDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    model: PrimitiveModel<'c>,
+    fetch: VertexFetch,
+    fixed_function_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    fixed_function_layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    pulling_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    pulling_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    pulling_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// This frame's, from the frame's descriptor sets, while pulling.
+    pulling_set: DescriptorSet,
+}
+
+impl<'c> VertexPullingDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<VertexPullingDemo<'c>, RendererError> {
+        let device = context.device();
+        let (fixed_function_pipeline, fixed_function_layout) = create_mesh_pipeline(context, layouts, render_pass, extent)?;
+        let vertex_shader = renderer::load_shader_module(device, mesh::PULLING_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, mesh::FRAGMENT_SHADER)?;
+        let pulling_set_layout = create_set_layout(context, &VertexFetch::Pulling.descriptor_set_layout_bindings())?;
+        let pulling_layout = create_pipeline_layout(context, &[*pulling_set_layout], &[MeshPushConstants::range()])?;
+        let pulling_pipeline = VertexFetch::Pulling.pipeline_builder(extent, *vertex_shader, *fragment_shader)
+            .build(device, *pulling_layout, render_pass, 0)?;
+        let model = PrimitiveModel::new(context, Primitive::Icosphere)?;
+        info!("Vertex pulling: {} vertices, {} indices, {:?} switches fetch", model.mesh.vertex_count(), model.mesh.index_count(), FETCH_KEY);
+        Ok(VertexPullingDemo {
+            device: device,
+            model: model,
+            fetch: VertexFetch::FixedFunction,
+            fixed_function_pipeline: fixed_function_pipeline,
+            fixed_function_layout: fixed_function_layout,
+            pulling_pipeline: pulling_pipeline,
+            pulling_layout: pulling_layout,
+            pulling_set_layout: pulling_set_layout,
+            pulling_set: DescriptorSet::null(),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for VertexPullingDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        self.model.prepare(frame)?;
+        if self.fetch == VertexFetch::Pulling {
+            self.pulling_set = frame.descriptors.allocate(*self.pulling_set_layout)?;
+            descriptor::update_descriptor_set(self.device, self.pulling_set, &[self.model.mesh.vertex_storage_write()]);
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != FETCH_KEY {
+            return self.model.handle_key(key);
+        }
+        self.fetch = self.fetch.toggle();
+        self.model.set_fetch(self.fetch);
+        info!("Vertex fetch: {:?}", self.fetch);
+        true
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        match self.fetch {
+            VertexFetch::FixedFunction => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.fixed_function_pipeline);
+                self.model.cmd_draw(command_buffer, *self.fixed_function_layout, view);
+            },
+            VertexFetch::Pulling => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pulling_pipeline);
+                self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.pulling_layout, 0, &[self.pulling_set], &[]);
+                self.model.cmd_draw(command_buffer, *self.pulling_layout, view);
+            },
+        }
+    }
+}
+
+/// Switches between full and quantized vertices.
+const VERTEX_FORMAT_KEY: glfw::Key = glfw::Key::K;
+
+/// A turning torus drawn from either `VertexFormat`. What quantizing saves in memory and vertex
+/// fetch, and what it costs in precision, is logged when the demo is selected.
+struct QuantizationDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    format: VertexFormat,
+    full: MeshBuffer<'c, ash::Device<V1_0>>,
+    /// With 16-bit indices, since the torus has few enough vertices.
+    quantized: MeshBuffer<'c, ash::Device<V1_0>>,
+    quantized_mesh: QuantizedMesh,
+    full_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    quantized_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+}
+
+impl<'c> QuantizationDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<QuantizationDemo<'c>, RendererError> {
+        let device = context.device();
+        let mesh = Primitive::Torus.mesh();
+        let quantized_mesh = QuantizedMesh::new(&mesh.vertices);
+        let full = mesh.upload(device, &context.allocator)?;
+        let quantized = MeshBuffer::from_vertices(device, &context.allocator, &quantized_mesh.vertices, &mesh.indices, true)?;
+        let stats = quantized_mesh.stats(&mesh.vertices);
+        stats.log("Torus");
+        info!("Torus buffers: {} bytes full, {} quantized, {} fewer bytes fetched per frame, {:?} switches format",
+              full.size(), quantized.size(), stats.bytes_saved_per_frame(1), VERTEX_FORMAT_KEY);
+
+        let full_vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+        let quantized_vertex_shader = renderer::load_shader_module(device, quantize::VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, mesh::FRAGMENT_SHADER)?;
+        let layout = layouts.mesh.clone();
+        let full_pipeline = VertexFormat::Full.pipeline_builder(extent, *full_vertex_shader, *fragment_shader)
+            .build(device, *layout, render_pass, 0)?;
+        let quantized_pipeline = VertexFormat::Quantized.pipeline_builder(extent, *quantized_vertex_shader, *fragment_shader)
+            .build(device, *layout, render_pass, 0)?;
+        Ok(QuantizationDemo {
+            device: device,
+            format: VertexFormat::Quantized,
+            full: full,
+            quantized: quantized,
+            quantized_mesh: quantized_mesh,
+            full_pipeline: full_pipeline,
+            quantized_pipeline: quantized_pipeline,
+            layout: layout,
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for QuantizationDemo<'c> {
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != VERTEX_FORMAT_KEY {
+            return false;
+        }
+        self.format = self.format.toggle();
+        info!("Vertex format: {:?}, {} bytes per vertex", self.format, self.format.vertex_size());
+        true
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        let model = Mat4::rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), view.time * 0.5));
+        let view_projection = view.view_projection();
+        let (pipeline, mesh, constants) = match self.format {
+            VertexFormat::Full => (&self.full_pipeline, &self.full, MeshPushConstants {
+                model_view_projection: view_projection * model,
+                model: model,
+            }),
+            VertexFormat::Quantized => (&self.quantized_pipeline, &self.quantized, self.quantized_mesh.push_constants(&view_projection, &model)),
+        };
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, **pipeline);
+        let range = MeshPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, *self.layout, range.stage_flags, range.offset, range.size, &constants as *const MeshPushConstants as *const c_void);
+        mesh.cmd_draw(self.device, command_buffer);
+    }
+}
+
+/// Switches between the spatial and temporal checkerboard reconstructions.
+const RECONSTRUCTION_KEY: glfw::Key = glfw::Key::R;
+
+/// A shaded primitive at the `render_mode` from the command line: all of the pixels every frame,
+/// or with `checkerboard::ARG` half of them into a `CheckerboardTarget`, reconstructed into the
+/// frame. Comparing the two in the frame stats shows what checkerboarding saves.
+struct CheckerboardDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    model: PrimitiveModel<'c>,
+    /// Into the frame's render pass at full rate, into the target's behind the stencil test
+    /// otherwise.
+    mesh_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    mesh_layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    checkerboard: Option<CheckerboardShading<'c>>,
+}
+
+/// What `CheckerboardDemo` needs only when checkerboarding.
+struct CheckerboardShading<'c> {
+    target: CheckerboardTarget<'c, ash::Device<V1_0>>,
+    mask_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    reconstruct_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    /// Shared by the mask, which ignores the set, and the reconstruction.
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    reconstruct_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// This frame's, from the frame's descriptor sets.
+    reconstruct_set: DescriptorSet,
+}
+
+impl<'c> CheckerboardDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<CheckerboardDemo<'c>, RendererError> {
+        let device = context.device();
+        let model = PrimitiveModel::new(context, Primitive::Torus)?;
+        if context.options.render_mode == RenderMode::FullRate {
+            let (mesh_pipeline, mesh_layout) = create_mesh_pipeline(context, layouts, render_pass, extent)?;
+            return Ok(CheckerboardDemo {
+                device: device,
+                model: model,
+                mesh_pipeline: mesh_pipeline,
+                mesh_layout: mesh_layout,
+                checkerboard: None,
+            });
+        }
+
+        let stencil_format = checkerboard::stencil_format(context.instance().instance(), context.physical_device)
+            .ok_or_else(|| RendererError::Unsupported("no stencil attachment format".to_string()))?;
+        let target = CheckerboardTarget::new(device, &context.allocator, context.surface_format.format, stencil_format, extent.clone())?;
+        let fullscreen_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let mask_shader = renderer::load_shader_module(device, checkerboard::MASK_FRAGMENT_SHADER)?;
+        let reconstruct_shader = renderer::load_shader_module(device, checkerboard::RECONSTRUCT_FRAGMENT_SHADER)?;
+        let reconstruct_set_layout = create_set_layout(context, &CheckerboardTarget::<ash::Device<V1_0>>::bindings())?;
+        let layout = create_pipeline_layout(context, &[*reconstruct_set_layout], &[CheckerboardPushConstants::range()])?;
+        let mask_pipeline = checkerboard::mask_pipeline_builder(target.extent(), *fullscreen_shader, *mask_shader).build(device, *layout, target.render_pass(), 0)?;
+        let reconstruct_pipeline = checkerboard::reconstruct_pipeline_builder(extent, *fullscreen_shader, *reconstruct_shader).build(device, *layout, render_pass, 0)?;
+
+        let vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, mesh::FRAGMENT_SHADER)?;
+        let mesh_layout = layouts.mesh.clone();
+        // The target has no depth to test against, only the stencil
+        let mesh_pipeline = VertexFetch::FixedFunction.pipeline_builder(target.extent(), *vertex_shader, *fragment_shader)
+            .depth_test(false, CompareOp::Always)
+            .stencil_test(checkerboard::SHADED_STENCIL)
+            .build(device, *mesh_layout, target.render_pass(), 0)?;
+        Ok(CheckerboardDemo {
+            device: device,
+            model: model,
+            mesh_pipeline: mesh_pipeline,
+            mesh_layout: mesh_layout,
+            checkerboard: Some(CheckerboardShading {
+                target: target,
+                mask_pipeline: mask_pipeline,
+                reconstruct_pipeline: reconstruct_pipeline,
+                layout: layout,
+                reconstruct_set_layout: reconstruct_set_layout,
+                reconstruct_set: DescriptorSet::null(),
+            }),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for CheckerboardDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.model.prepare(frame)?;
+        let shading = match self.checkerboard {
+            Some(ref mut shading) => shading,
+            None => return Ok(()),
+        };
+        let command_buffer = frame.recorder.command_buffer();
+        shading.target.advance();
+        unsafe { shading.target.cmd_prepare(command_buffer) };
+        {
+            let mut recorder = unsafe { CommandRecorder::recording(self.device, command_buffer) };
+            let target = shading.target.target();
+            let _pass = recorder.begin_render_pass(&target, &CheckerboardTarget::<ash::Device<V1_0>>::clear_values(ClearColorValue::new_float32([0.0, 0.0, 0.0, 0.0])));
+            unsafe {
+                shading.target.cmd_draw_mask(command_buffer, *shading.mask_pipeline, *shading.layout);
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.mesh_pipeline);
+                self.model.cmd_draw(command_buffer, *self.mesh_layout, view);
+            }
+        }
+        shading.reconstruct_set = frame.descriptors.allocate(*shading.reconstruct_set_layout)?;
+        descriptor::update_descriptor_set(self.device, shading.reconstruct_set, &shading.target.reconstruct_writes());
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if self.model.handle_key(key) {
+            return true;
+        }
+        match self.checkerboard {
+            Some(ref mut shading) if key == RECONSTRUCTION_KEY => {
+                shading.target.reconstruction = shading.target.reconstruction.next();
+                info!("Checkerboard reconstruction: {:?}", shading.target.reconstruction);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        let shading = match self.checkerboard {
+            Some(ref shading) => shading,
+            None => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.mesh_pipeline);
+                self.model.cmd_draw(command_buffer, *self.mesh_layout, view);
+                return;
+            },
+        };
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *shading.reconstruct_pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *shading.layout, 0, &[shading.reconstruct_set], &[]);
+        let constants = shading.target.push_constants();
+        let range = CheckerboardPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, *shading.layout, range.stage_flags, range.offset, range.size, &constants as *const CheckerboardPushConstants as *const c_void);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Switches the debug views demo's gizmo between moving and turning the primitive.
+const GIZMO_MODE_KEY: glfw::Key = glfw::Key::T;
+/// Shows or hides the debug views demo's ground grid.
+const GRID_KEY: glfw::Key = glfw::Key::G;
+/// Shows or hides the debug views demo's world axes.
+const AXES_KEY: glfw::Key = glfw::Key::X;
+/// Cycles the debug views demo between the shaded primitive and its G-buffer views.
+const GBUFFER_VIEW_KEY: glfw::Key = glfw::Key::B;
+/// A primitive's vertices, as `DebugView::draw_mesh` takes them.
+struct MeshLines {
+    primitive: Primitive,
+    bounds: Aabb,
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    tangents: Vec<Vec3>,
+}
+
+impl MeshLines {
+    fn new(primitive: Primitive) -> MeshLines {
+        let mesh = primitive.mesh();
+        MeshLines {
+            primitive: primitive,
+            bounds: mesh.bounds(),
+            positions: mesh.positions(),
+            normals: mesh.normals(),
+            tangents: mesh.tangent_vectors(),
+        }
+    }
+}
+
+/// Most debug line vertices the debug views demo draws in a frame, for each kind of line.
+const DEBUG_LINE_CAPACITY: usize = 1 << 16;
+
+/// The cursor and left mouse button, as of the last events.
+#[derive(Debug, Clone, Copy, Default)]
+struct Pointer {
+    cursor: (f64, f64),
+    pressed: bool,
+    /// Whether the button went down since the last frame, which is when a drag can start.
+    clicked: bool,
+}
+
+/// A primitive to try the debug visualizations on, placed by a gizmo: hovering a handle
+/// highlights it, dragging it with the left mouse button moves or turns the primitive along that
+/// axis, and `GIZMO_MODE_KEY` switches between the translate and rotate handles. The ground grid
+/// and world axes are drawn after the primitive, toggled with `GRID_KEY` and `AXES_KEY`. F1 to F4
+/// toggle `DebugView`'s bounding box, normals, tangents and UV checker, and F5 cycles through the
+/// overdraw and shader complexity heatmaps. `GBUFFER_VIEW_KEY` shows the primitive's linear depth
+/// instead, from a depth pass of its own, since the demo has no other G-buffer targets.
+struct DebugViewsDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    model: PrimitiveModel<'c>,
+    /// Of the primitive `model` shows.
+    mesh_lines: MeshLines,
+    debug_view: DebugView,
+    transform: Transform,
+    gizmo: Gizmo,
+    pointer: Pointer,
+    mesh_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    mesh_layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    checker_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    checker_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    /// Replacing the mesh pipeline while `DebugView::scene_override` picks them.
+    overdraw_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    complexity_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    complexity_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    /// The mesh fragment shader's estimated cost, against `debug_views.max_instructions`.
+    complexity: ComplexityPushConstants,
+    depth: DepthTarget<'c, ash::Device<V1_0>>,
+    depth_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    gbuffer_viewer: GBufferViewer,
+    gbuffer_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    gbuffer_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    gbuffer_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// This frame's, from the frame's descriptor sets.
+    gbuffer_set: DescriptorSet,
+    grid: ReferenceGrid,
+    grid_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    grid_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    line_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    line_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    thick_line_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    thick_line_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    /// This frame's lines, and its gizmo, drawn `gizmo::LINE_WIDTH` wide.
+    lines: DebugDraw,
+    gizmo_lines: DebugDraw,
+    /// A buffer of each for each frame slot, since frames in flight might still be drawing the
+    /// others.
+    line_buffers: Vec<DebugDrawBuffer<'c, ash::Device<V1_0>>>,
+    gizmo_buffers: Vec<DebugDrawBuffer<'c, ash::Device<V1_0>>>,
+    /// Into the buffers, for the frame being recorded.
+    current: usize,
+}
+
+impl<'c> DebugViewsDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<DebugViewsDemo<'c>, RendererError> {
+        let device = context.device();
+        let (mesh_pipeline, mesh_layout) = create_mesh_pipeline(context, layouts, render_pass, extent)?;
+        let mesh_vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+        let mesh_fragment_shader = renderer::load_shader_module(device, mesh::FRAGMENT_SHADER)?;
+        let mesh_builder = VertexFetch::FixedFunction.pipeline_builder(extent, *mesh_vertex_shader, *mesh_fragment_shader);
+        let checker_shader = renderer::load_shader_module(device, debug_view::UV_CHECKER_SHADER)?;
+        let checker_layout = create_pipeline_layout(context, &[], &[MeshPushConstants::range(), UvCheckerPushConstants::range()])?;
+        let checker_pipeline = mesh_builder.clone().replace_stage(SHADER_STAGE_FRAGMENT_BIT, *checker_shader).build(device, *checker_layout, render_pass, 0)?;
+        let overdraw_shader = renderer::load_shader_module(device, debug_view::OVERDRAW_SHADER)?;
+        let complexity_shader = renderer::load_shader_module(device, debug_view::COMPLEXITY_SHADER)?;
+        let heatmap_shaders = HeatmapShaders {
+            overdraw: *overdraw_shader,
+            complexity: *complexity_shader,
+        };
+        let complexity_layout = create_pipeline_layout(context, &[], &[MeshPushConstants::range(), ComplexityPushConstants::range()])?;
+        let build_override = |scene_override: SceneOverride, layout: PipelineLayout| scene_override.pipeline_builder(&mesh_builder, &heatmap_shaders)
+            .expect("Every override but None has a pipeline of its own")
+            .build(device, layout, render_pass, 0);
+        let overdraw_pipeline = build_override(SceneOverride::Overdraw, *mesh_layout)?;
+        let complexity_pipeline = build_override(SceneOverride::ShaderComplexity, *complexity_layout)?;
+        let mesh_spirv = shader_compile::load_spirv(mesh::FRAGMENT_SHADER).map_err(|e| RendererError::Shader(mesh::FRAGMENT_SHADER.to_string(), e))?;
+        let instruction_count = debug_view::estimate_instruction_count(&mesh_spirv).unwrap_or(0);
+        let depth_format = format_support::depth_format(context.instance().instance(), context.physical_device, false, true)
+            .ok_or_else(|| RendererError::Unsupported("no sampled depth format".to_string()))?;
+        let depth = DepthTarget::new(device, &context.allocator, depth_format, extent.clone())?;
+        let depth_pipeline = gbuffer_view::depth_pipeline_builder(extent, *mesh_vertex_shader)
+            .vertex_input(&MeshVertex::binding_descriptions(), &MeshVertex::attribute_descriptions())
+            .build(device, *mesh_layout, depth.render_pass(), 0)?;
+        let fullscreen_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let gbuffer_shader = renderer::load_shader_module(device, gbuffer_view::FRAGMENT_SHADER)?;
+        let gbuffer_set_layout = create_set_layout(context, &[gbuffer_view::descriptor_set_layout_binding()])?;
+        let gbuffer_layout = create_pipeline_layout(context, &[*gbuffer_set_layout], &[GBufferViewPushConstants::range()])?;
+        let gbuffer_pipeline = gbuffer_view::pipeline_builder(extent, *fullscreen_shader, *gbuffer_shader).build(device, *gbuffer_layout, render_pass, 0)?;
+        let camera = Camera::default();
+        let grid_vertex_shader = renderer::load_shader_module(device, grid::VERTEX_SHADER)?;
+        let grid_fragment_shader = renderer::load_shader_module(device, grid::FRAGMENT_SHADER)?;
+        let grid_layout = create_pipeline_layout(context, &[], &[GridPushConstants::range()])?;
+        let grid_pipeline = grid::pipeline_builder(extent, *grid_vertex_shader, *grid_fragment_shader).build(device, *grid_layout, render_pass, 0)?;
+        let line_vertex_shader = renderer::load_shader_module(device, debug_draw::VERTEX_SHADER)?;
+        let line_fragment_shader = renderer::load_shader_module(device, debug_draw::FRAGMENT_SHADER)?;
+        let line_layout = create_pipeline_layout(context, &[], &[DebugDrawPushConstants::range()])?;
+        let line_pipeline = debug_draw::pipeline_builder(extent, *line_vertex_shader, *line_fragment_shader).build(device, *line_layout, render_pass, 0)?;
+        let thick_vertex_shader = renderer::load_shader_module(device, debug_draw::THICK_LINE_VERTEX_SHADER)?;
+        let thick_fragment_shader = renderer::load_shader_module(device, debug_draw::THICK_LINE_FRAGMENT_SHADER)?;
+        let thick_line_layout = create_pipeline_layout(context, &[], &[ThickLinePushConstants::range()])?;
+        let thick_line_pipeline = debug_draw::thick_pipeline_builder(extent, *thick_vertex_shader, *thick_fragment_shader)
+            .dynamic_viewport_scissor()
+            .build(device, *thick_line_layout, render_pass, 0)?;
+        let create_buffers = || (0..frame::FRAMES_IN_FLIGHT)
+            .map(|_| DebugDrawBuffer::new(device, &context.allocator, DEBUG_LINE_CAPACITY))
+            .collect::<VkResult<Vec<_>>>();
+        Ok(DebugViewsDemo {
+            device: device,
+            model: PrimitiveModel::new(context, Primitive::Cube)?,
+            mesh_lines: MeshLines::new(Primitive::Cube),
+            debug_view: DebugView::new(),
+            transform: Transform::default(),
+            gizmo: Gizmo::new(cvar!("debug_views.gizmo_size", 1.0).get()),
+            pointer: Pointer::default(),
+            mesh_pipeline: mesh_pipeline,
+            mesh_layout: mesh_layout,
+            checker_pipeline: checker_pipeline,
+            checker_layout: checker_layout,
+            overdraw_pipeline: overdraw_pipeline,
+            complexity_pipeline: complexity_pipeline,
+            complexity_layout: complexity_layout,
+            complexity: ComplexityPushConstants::new(instruction_count, cvar!("debug_views.max_instructions", 512).get()),
+            depth: depth,
+            depth_pipeline: depth_pipeline,
+            gbuffer_viewer: GBufferViewer::new(camera.near, camera.far),
+            gbuffer_pipeline: gbuffer_pipeline,
+            gbuffer_layout: gbuffer_layout,
+            gbuffer_set_layout: gbuffer_set_layout,
+            gbuffer_set: DescriptorSet::null(),
+            grid: ReferenceGrid::new(),
+            grid_pipeline: grid_pipeline,
+            grid_layout: grid_layout,
+            line_pipeline: line_pipeline,
+            line_layout: line_layout,
+            thick_line_pipeline: thick_line_pipeline,
+            thick_line_layout: thick_line_layout,
+            lines: DebugDraw::new(),
+            gizmo_lines: DebugDraw::new(),
+            line_buffers: create_buffers()?,
+            gizmo_buffers: create_buffers()?,
+            current: 0,
+        })
+    }
+
+    /// Hovers, grabs, drags or lets go of the gizmo's handles, for the cursor as seen from `view`.
+    fn update_gizmo(&mut self, view: &DemoView) {
+        let clicked = std::mem::replace(&mut self.pointer.clicked, false);
+        let inverse_view_projection = match view.view_projection().inverse() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        let ray = Ray::from_cursor(self.pointer.cursor, view.extent.to_tuple(), &inverse_view_projection);
+        let (x, y) = self.pointer.cursor;
+        let in_view = view.extent.to_rect().contains(x as i32, y as i32);
+        if self.gizmo.is_dragging() {
+            if self.pointer.pressed {
+                self.gizmo.update_drag(&ray, &mut self.transform);
+            } else {
+                self.gizmo.end_drag();
+            }
+        } else if clicked && in_view {
+            self.gizmo.begin_drag(&ray, &self.transform);
+        }
+        self.gizmo.hover(&ray, &self.transform);
+    }
+}
+
+impl<'c> DemoPass<'c> for DebugViewsDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.model.prepare(frame)?;
+        self.update_gizmo(view);
+        self.current = frame.slot;
+        if self.mesh_lines.primitive != self.model.shown {
+            self.mesh_lines = MeshLines::new(self.model.shown);
+        }
+        self.lines.clear();
+        self.grid.draw_axes(&mut self.lines);
+        let mesh = &self.mesh_lines;
+        self.debug_view.draw_mesh(&mut self.lines, &self.transform.matrix(), &mesh.bounds, &mesh.positions, &mesh.normals, &mesh.tangents);
+        self.line_buffers[self.current].upload(&self.lines)?;
+        self.gizmo_lines.clear();
+        self.gizmo.draw(&self.transform, &mut self.gizmo_lines);
+        self.gizmo_buffers[self.current].upload(&self.gizmo_lines)?;
+        if !self.gbuffer_viewer.is_active(&self.depth.targets()) {
+            return Ok(());
+        }
+        self.gbuffer_viewer.near = view.near;
+        self.gbuffer_viewer.far = view.far;
+        let command_buffer = frame.recorder.command_buffer();
+        {
+            let mut recorder = unsafe { CommandRecorder::recording(self.device, command_buffer) };
+            let target = self.depth.target();
+            let _pass = recorder.begin_render_pass(&target, &DepthTarget::<ash::Device<V1_0>>::clear_values());
+            unsafe {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.depth_pipeline);
+                self.model.cmd_draw_at(command_buffer, *self.mesh_layout, &view.view_projection(), self.transform.matrix());
+            }
+        }
+        self.gbuffer_set = frame.descriptors.allocate(*self.gbuffer_set_layout)?;
+        unsafe { self.gbuffer_viewer.write_descriptor_set(self.device, self.gbuffer_set, self.depth.sampler(), &self.depth.targets()) };
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        match key {
+            GIZMO_MODE_KEY => {
+                self.gizmo.toggle_mode();
+                info!("Gizmo mode: {:?}", self.gizmo.mode);
+            },
+            GRID_KEY => self.grid.toggle_grid(),
+            AXES_KEY => self.grid.toggle_axes(),
+            GBUFFER_VIEW_KEY => self.gbuffer_viewer.cycle(&self.depth.targets()),
+            _ => return self.debug_view.handle_key(key) || self.model.handle_key(key),
+        }
+        true
+    }
+
+    fn handle_mouse(&mut self, event: &glfw::WindowEvent) -> bool {
+        match *event {
+            glfw::WindowEvent::CursorPos(x, y) => {
+                self.pointer.cursor = (x, y);
+                self.gizmo.is_dragging()
+            },
+            glfw::WindowEvent::MouseButton(glfw::MouseButtonLeft, action, _) => {
+                self.pointer.pressed = action != glfw::Action::Release;
+                self.pointer.clicked |= self.pointer.pressed;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        let view_projection = view.view_projection();
+        let model = self.transform.matrix();
+        let gbuffer_targets = self.depth.targets();
+        match self.debug_view.scene_override {
+            _ if self.gbuffer_viewer.is_active(&gbuffer_targets) => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.gbuffer_pipeline);
+                self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.gbuffer_layout, 0, &[self.gbuffer_set], &[]);
+                self.gbuffer_viewer.cmd_draw(self.device, command_buffer, *self.gbuffer_layout, &gbuffer_targets);
+            },
+            SceneOverride::Overdraw => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.overdraw_pipeline);
+                self.model.cmd_draw_at(command_buffer, *self.mesh_layout, &view_projection, model);
+            },
+            SceneOverride::ShaderComplexity => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.complexity_pipeline);
+                self.complexity.cmd_push(self.device, command_buffer, *self.complexity_layout);
+                self.model.cmd_draw_at(command_buffer, *self.complexity_layout, &view_projection, model);
+            },
+            SceneOverride::None if self.debug_view.uv_checker => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.checker_pipeline);
+                self.debug_view.checker_push_constants().cmd_push(self.device, command_buffer, *self.checker_layout);
+                self.model.cmd_draw_at(command_buffer, *self.checker_layout, &view_projection, model);
+            },
+            SceneOverride::None => {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.mesh_pipeline);
+                self.model.cmd_draw_at(command_buffer, *self.mesh_layout, &view_projection, model);
+            },
+        }
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.grid_pipeline);
+        self.grid.cmd_draw(self.device, command_buffer, *self.grid_layout, &view_projection);
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.line_pipeline);
+        self.line_buffers[self.current].cmd_draw(command_buffer, *self.line_layout, &view_projection);
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.thick_line_pipeline);
+        // Squashed onto the near plane, so the model never hides the handles
+        self.device.cmd_set_viewport(command_buffer, &[view.extent.to_viewport().with_depth(0.0, 0.0)]);
+        self.device.cmd_set_scissor(command_buffer, &[view.extent.to_rect()]);
+        self.gizmo_buffers[self.current].cmd_draw_thick(command_buffer, *self.thick_line_layout, &view_projection, gizmo::LINE_WIDTH, &view.extent);
+    }
+}
+
+/// A shaded primitive with its vertex normals and face normals drawn over it as
+/// `normal_vis.length` long lines.
+struct NormalVisDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    model: PrimitiveModel<'c>,
+    mesh_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    mesh_layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    normals_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    normals_layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    normal_length: f32,
+}
+
+impl<'c> NormalVisDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<NormalVisDemo<'c>, RendererError> {
+        let device = context.device();
+        let (mesh_pipeline, mesh_layout) = create_mesh_pipeline(context, layouts, render_pass, extent)?;
+        let vertex_shader = renderer::load_shader_module(device, normal_vis::VERTEX_SHADER)?;
+        let geometry_shader = renderer::load_shader_module(device, normal_vis::GEOMETRY_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, debug_draw::FRAGMENT_SHADER)?;
+        let normals_layout = create_pipeline_layout(context, &[], &[NormalVisPushConstants::range()])?;
+        let normals_pipeline = normal_vis::pipeline_builder(extent, *vertex_shader, *geometry_shader, *fragment_shader).build(device, *normals_layout, render_pass, 0)?;
+        Ok(NormalVisDemo {
+            device: device,
+            model: PrimitiveModel::new(context, Primitive::Icosphere)?,
+            mesh_pipeline: mesh_pipeline,
+            mesh_layout: mesh_layout,
+            normals_pipeline: normals_pipeline,
+            normals_layout: normals_layout,
+            normal_length: cvar!("normal_vis.length", 0.05).get(),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for NormalVisDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        self.model.prepare(frame)
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        self.model.handle_key(key)
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.mesh_pipeline);
+        self.model.cmd_draw(command_buffer, *self.mesh_layout, view);
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.normals_pipeline);
+        let constants = NormalVisPushConstants {
+            model_view_projection: view.view_projection() * self.model.model(view),
+            normal_length: self.normal_length,
+        };
+        constants.cmd_push(self.device, command_buffer, *self.normals_layout);
+        self.model.mesh.cmd_draw(self.device, command_buffer);
+    }
+}
+
+/// Switches to the next number of lights in the shadow atlas demo.
+const LIGHT_COUNT_KEY: glfw::Key = glfw::Key::L;
+/// Most lights the shadow atlas demo packs into its atlas.
+const SHADOW_MAX_LIGHTS: usize = 8;
+/// How far the shadow atlas demo's lights are from the primitive they circle.
+const SHADOW_LIGHT_DISTANCE: f32 = 3.0;
+/// Smallest region the shadow atlas hands out.
+const SHADOW_MIN_TILE_SIZE: u32 = 128;
+
+/// The shadow map regions of one light.
+#[derive(Debug, Clone, Copy)]
+enum ShadowMaps {
+    Spot(ShadowRegion),
+    Point(CubeShadowRegions),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ShadowCaster {
+    position: Vec3,
+    maps: ShadowMaps,
+}
+
+impl ShadowCaster {
+    /// Each region with the view projection to render it from.
+    fn views(&self) -> Vec<(ShadowRegion, Mat4)> {
+        let near = 0.1;
+        let far = SHADOW_LIGHT_DISTANCE * 4.0;
+        match self.maps {
+            ShadowMaps::Spot(region) => {
+                let projection = Mat4::perspective(60f32.to_radians(), 1.0, near, far);
+                vec![(region, projection * Mat4::look_at(self.position, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)))]
+            },
+            ShadowMaps::Point(faces) => {
+                let projection = Mat4::perspective(90f32.to_radians(), 1.0, near, far);
+                // The usual cube face directions and ups, in `CubeShadowRegions` order
+                let directions = [
+                    (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+                    (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+                    (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+                    (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+                    (Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, -1.0, 0.0)),
+                    (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, -1.0, 0.0)),
+                ];
+                faces.iter().zip(directions.iter())
+                    .map(|(&region, &(direction, up))| (region, projection * Mat4::look_at(self.position, self.position + direction, up)))
+                    .collect()
+            },
+        }
+    }
+}
+
+/// A shaded primitive circled by up to `SHADOW_MAX_LIGHTS` lights, cycled with
+/// `LIGHT_COUNT_KEY`, whose shadow maps all go into one `shadow_atlas.size` atlas. Every third
+/// light is a point light with six maps, and the spot lights' maps get smaller down the line, so
+/// the atlas fills with a mix of sizes. The atlas is shown in the top left corner.
+struct ShadowAtlasDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    atlas: ShadowAtlas<'c, ash::Device<V1_0>>,
+    model: PrimitiveModel<'c>,
+    mesh_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    mesh_layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    depth_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    view_pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    view_layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    view_set_layout: Shared<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// This frame's, from the frame's descriptor sets.
+    view_set: DescriptorSet,
+    lights: Vec<ShadowCaster>,
+    light_count: usize,
+    /// Side of the first spot light's map.
+    light_size: u32,
+}
+
+impl<'c> ShadowAtlasDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<ShadowAtlasDemo<'c>, RendererError> {
+        let device = context.device();
+        let format = format_support::depth_format(context.instance().instance(), context.physical_device, false, true)
+            .ok_or_else(|| RendererError::Unsupported("no sampled depth format".to_string()))?;
+        let size: u32 = cvar!("shadow_atlas.size", 4096).get();
+        let size = std::cmp::min(std::cmp::max(size, SHADOW_MIN_TILE_SIZE).next_power_of_two(), context.limits.limits().max_image_dimension2d);
+        let atlas = ShadowAtlas::new(device, &context.allocator, format, size, SHADOW_MIN_TILE_SIZE)?;
+        let (mesh_pipeline, mesh_layout) = create_mesh_pipeline(context, layouts, render_pass, extent)?;
+        let vertex_shader = renderer::load_shader_module(device, mesh::VERTEX_SHADER)?;
+        let depth_pipeline = shadow_atlas::depth_pipeline_builder(*vertex_shader)
+            .vertex_input(&MeshVertex::binding_descriptions(), &MeshVertex::attribute_descriptions())
+            .build(device, *mesh_layout, atlas.render_pass(), 0)?;
+        let fullscreen_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let view_shader = renderer::load_shader_module(device, shadow_atlas::VIEW_FRAGMENT_SHADER)?;
+        let view_set_layout = layouts.texture_view_set.clone();
+        let view_layout = layouts.texture_view.clone();
+        let view_size = std::cmp::min(extent.width, extent.height) / 2;
+        let view_pipeline = shadow_atlas::view_pipeline_builder(view_size, *fullscreen_shader, *view_shader).build(device, *view_layout, render_pass, 0)?;
+        let mut ret = ShadowAtlasDemo {
+            device: device,
+            atlas: atlas,
+            model: PrimitiveModel::new(context, Primitive::Torus)?,
+            mesh_pipeline: mesh_pipeline,
+            mesh_layout: mesh_layout,
+            depth_pipeline: depth_pipeline,
+            view_pipeline: view_pipeline,
+            view_layout: view_layout,
+            view_set_layout: view_set_layout,
+            view_set: DescriptorSet::null(),
+            lights: Vec::new(),
+            light_count: 4,
+            light_size: cvar!("shadow_atlas.light_size", size / 4).get(),
+        };
+        ret.place_lights();
+        Ok(ret)
+    }
+
+    /// Gives the atlas back and allocates maps for `light_count` lights, evenly around the
+    /// primitive. Lights that don't fit go without.
+    fn place_lights(&mut self) {
+        for light in self.lights.drain(..) {
+            match light.maps {
+                ShadowMaps::Spot(region) => self.atlas.allocator_mut().free(region),
+                ShadowMaps::Point(faces) => self.atlas.allocator_mut().free_cube(faces),
+            }
+        }
+        for i in 0..self.light_count {
+            let angle = i as f32 / self.light_count as f32 * 2.0 * std::f32::consts::PI;
+            let position = Vec3::new(angle.cos() * SHADOW_LIGHT_DISTANCE, 2.0, angle.sin() * SHADOW_LIGHT_DISTANCE);
+            let maps = if i % 3 == 2 {
+                self.atlas.allocator_mut().allocate_cube(self.light_size / 2).map(ShadowMaps::Point)
+            } else {
+                self.atlas.allocator_mut().allocate(self.light_size >> (i / 3)).map(ShadowMaps::Spot)
+            };
+            match maps {
+                Some(maps) => {
+                    if let ShadowMaps::Spot(region) = maps {
+                        debug!("Spot light {} shadow UVs scale and offset: {:?}", i, region.uv_transform(self.atlas.allocator().size()));
+                    }
+                    self.lights.push(ShadowCaster {
+                        position: position,
+                        maps: maps,
+                    });
+                },
+                None => warn!("Shadow atlas is full, light {} has no shadow map", i),
+            }
+        }
+        info!("{} lights with shadow maps", self.lights.len());
+    }
+}
+
+impl<'c> DemoPass<'c> for ShadowAtlasDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.model.prepare(frame)?;
+        let command_buffer = frame.recorder.command_buffer();
+        {
+            let mut recorder = unsafe { CommandRecorder::recording(self.device, command_buffer) };
+            let target = self.atlas.target();
+            let _pass = recorder.begin_render_pass(&target, &ShadowAtlas::<ash::Device<V1_0>>::clear_values());
+            unsafe {
+                self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.depth_pipeline);
+                for light in self.lights.iter() {
+                    for (region, view_projection) in light.views() {
+                        region.cmd_set(self.device, command_buffer);
+                        self.model.cmd_draw_from(command_buffer, *self.mesh_layout, &view_projection, view);
+                    }
+                }
+            }
+        }
+        self.view_set = frame.descriptors.allocate(*self.view_set_layout)?;
+        descriptor::update_descriptor_set(self.device, self.view_set, &[self.atlas.sampled_write(shadow_atlas::VIEW_BINDING)]);
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key == LIGHT_COUNT_KEY {
+            self.light_count = self.light_count % SHADOW_MAX_LIGHTS + 1;
+            self.place_lights();
+            return true;
+        }
+        self.model.handle_key(key)
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.mesh_pipeline);
+        self.model.cmd_draw(command_buffer, *self.mesh_layout, view);
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.view_pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.view_layout, 0, &[self.view_set], &[]);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Side of the texture array demo's large materials. The small ones are half as big.
+const MATERIAL_SIZE: u32 = 256;
+/// One texture array demo material per tint.
+const MATERIAL_TINTS: [[u32; 3]; 6] = [
+    [255, 96, 96],
+    [96, 255, 96],
+    [96, 96, 255],
+    [255, 255, 96],
+    [96, 255, 255],
+    [255, 96, 255],
+];
+
+/// Checkerboard materials in a grid of tiles, packed `texture_array.layers` to an array by a
+/// `MaterialAtlas`. Every other material is half size, so those get arrays of their own. The
+/// tiles are drawn sorted by slot, so an array's descriptors are pushed once and the tiles
+/// sampling it only push their layer.
+struct TextureArrayDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    atlas: MaterialAtlas<'c, ash::Device<V1_0>>,
+    /// Sorted.
+    slots: Vec<MaterialSlot>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    push_descriptors: PushDescriptors<'c, ash::Device<V1_0>>,
+    set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// One for each of the atlas's arrays, for this frame.
+    sets: Vec<PushedSet>,
+    columns: u32,
+    tile_size: u32,
+}
+
+impl<'c> TextureArrayDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<TextureArrayDemo<'c>, RendererError> {
+        let device = context.device();
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, texture_array::FRAGMENT_SHADER)?;
+        let push_descriptors_enabled = context.enabled_extensions.iter().any(|name| name == push_descriptor::EXTENSION_NAME);
+        let push_descriptors = PushDescriptors::new(context.instance().instance(), device, push_descriptors_enabled);
+        let set_layout = push_descriptors.create_set_layout(&TextureArray::<ash::Device<V1_0>>::bindings())?;
+        let layout = create_pipeline_layout(context, &[*set_layout], &[texture_array::MaterialPushConstants::range()])?;
+        let pipeline = GraphicsPipelineBuilder::new(&rect::placeholder_extent())
+            .stage(SHADER_STAGE_VERTEX_BIT, *vertex_shader)
+            .stage(SHADER_STAGE_FRAGMENT_BIT, *fragment_shader)
+            .cull_mode(CULL_MODE_NONE)
+            .dynamic_viewport_scissor()
+            .build(device, *layout, render_pass, 0)?;
+
+        let layers: u32 = cvar!("texture_array.layers", 2).get();
+        let mut atlas = MaterialAtlas::new(device, &context.allocator, std::cmp::max(layers, 1));
+        let immediate = ImmediateContext::new(device, context.graphics_family, context.graphics_queue)?;
+        let mut slots = Vec::with_capacity(MATERIAL_TINTS.len());
+        for (i, tint) in MATERIAL_TINTS.iter().enumerate() {
+            let size = if i % 2 == 0 { MATERIAL_SIZE } else { MATERIAL_SIZE / 2 };
+            let mut data = ImageData::checkerboard(size, 2 << i);
+            for pixel in data.pixels.chunks_mut(4) {
+                for c in 0..3 {
+                    pixel[c] = (pixel[c] as u32 * tint[c] / 255) as u8;
+                }
+            }
+            let slot = atlas.allocate(Format::R8g8b8a8Srgb, data.extent())?;
+            let staging = DeviceBuffer::host_visible(device, &context.allocator, data.pixels.len() as DeviceSize, BUFFER_USAGE_TRANSFER_SRC_BIT)?;
+            staging.write(0, &data.pixels)?;
+            atlas.array(slot).upload_layer(&immediate, staging.buffer(), 0, slot.layer)?;
+            slots.push(slot);
+        }
+        slots.sort();
+        debug!("Packed {} materials into {} texture arrays", slots.len(), atlas.arrays().len());
+
+        let columns = (slots.len() as f32).sqrt().ceil() as u32;
+        let rows = div_ceil(slots.len() as u32, columns);
+        Ok(TextureArrayDemo {
+            device: device,
+            atlas: atlas,
+            slots: slots,
+            pipeline: pipeline,
+            layout: layout,
+            push_descriptors: push_descriptors,
+            set_layout: set_layout,
+            sets: Vec::new(),
+            columns: columns,
+            tile_size: std::cmp::min(extent.width / columns, extent.height / rows),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for TextureArrayDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        self.sets.clear();
+        for array in self.atlas.arrays() {
+            let set = self.push_descriptors.prepare(frame.descriptors, *self.set_layout, vec![array.sampled_write(texture_array::MATERIAL_BINDING)])?;
+            self.sets.push(set);
+        }
+        Ok(())
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, _view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        let mut bound = None;
+        for (i, &slot) in self.slots.iter().enumerate() {
+            if bound != Some(slot.array) {
+                self.push_descriptors.cmd_push(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &self.sets[slot.array]);
+                bound = Some(slot.array);
+            }
+            texture_array::cmd_push_material_slot(self.device, command_buffer, *self.layout, slot);
+            let (column, row) = (i as u32 % self.columns, i as u32 / self.columns);
+            let origin = Offset2D::from_tuple(((column * self.tile_size) as i32, (row * self.tile_size) as i32));
+            let tile = rect::rect(origin, rect::extent(self.tile_size, self.tile_size));
+            self.device.cmd_set_viewport(command_buffer, &[tile.to_viewport()]);
+            self.device.cmd_set_scissor(command_buffer, &[tile]);
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+/// Switches to the next kind of noise.
+const NOISE_KIND_KEY: glfw::Key = glfw::Key::N;
+/// Switches between noise from the compute shader and from the CPU, which should look the same.
+const NOISE_CPU_KEY: glfw::Key = glfw::Key::C;
+/// Side of the blue noise texture, which is slow to make bigger.
+const BLUE_NOISE_SIZE: u32 = 64;
+
+/// Fractal value, Perlin and simplex noise and blue noise, cycled with `NOISE_KIND_KEY`. Blue
+/// noise always comes from the CPU, the others from the compute shader unless `NOISE_CPU_KEY`
+/// switched them over.
+struct NoiseDemo<'c> {
+    context: &'c Context,
+    texture: NoiseTexture<'c, ash::Device<V1_0>>,
+    /// What `texture` holds.
+    generated: Option<NoiseParams>,
+    /// The latest noise made on the CPU, and what it was made with.
+    cpu_texture: Option<(NoiseParams, Texture<'c, ash::Device<V1_0>>)>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Shared<'c, ash::Device<V1_0>, PipelineLayout>,
+    view_set_layout: Shared<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    kernel: ComputeKernel<'c, ash::Device<V1_0>>,
+    params: NoiseParams,
+    cpu: bool,
+    /// This frame's, from the frame's descriptor sets.
+    view_set: DescriptorSet,
+}
+
+impl<'c> NoiseDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<NoiseDemo<'c>, RendererError> {
+        let device = context.device();
+        let kernel = load_kernel(context, noise::NOISE_SHADER, &NoiseTexture::<ash::Device<V1_0>>::compute_bindings(), std::mem::size_of::<NoisePushConstants>() as u32)?;
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, noise::VIEW_FRAGMENT_SHADER)?;
+        let view_set_layout = layouts.texture_view_set.clone();
+        let layout = layouts.texture_view.clone();
+        let pipeline = noise::view_pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let size: u32 = cvar!("noise.size", 256).get();
+        let texture = NoiseTexture::new(device, &context.allocator, rect::extent(size, size))?;
+        Ok(NoiseDemo {
+            context: context,
+            texture: texture,
+            generated: None,
+            cpu_texture: None,
+            pipeline: pipeline,
+            layout: layout,
+            view_set_layout: view_set_layout,
+            kernel: kernel,
+            params: Default::default(),
+            cpu: false,
+            view_set: DescriptorSet::null(),
+        })
+    }
+
+    /// Noise for `params` made on the CPU, uploaded and waited for on the graphics queue.
+    fn create_cpu_texture(&self) -> VkResult<Texture<'c, ash::Device<V1_0>>> {
+        let size = if self.params.kind == NoiseKind::Blue { BLUE_NOISE_SIZE } else { self.texture.extent().width };
+        let values = noise::generate(&self.params, size, size);
+        let data = ImageData {
+            width: size,
+            height: size,
+            pixels: noise::encode(&values, Format::R8g8b8a8Unorm).unwrap(),
+        };
+        let immediate = ImmediateContext::new(self.context.device(), self.context.graphics_family, self.context.graphics_queue)?;
+        Texture::new(self.context.device(), &self.context.allocator, &immediate, &data, TextureEncoding::Data, 1)
+    }
+}
+
+impl<'c> DemoPass<'c> for NoiseDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        let device = self.context.device();
+        self.view_set = frame.descriptors.allocate(*self.view_set_layout)?;
+        match NoisePushConstants::new(&self.params) {
+            Some(ref constants) if !self.cpu => {
+                if self.generated != Some(self.params) {
+                    let compute_set = frame.descriptors.allocate(self.kernel.set_layout())?;
+                    descriptor::update_descriptor_set(device, compute_set, &[self.texture.storage_write()]);
+                    unsafe { self.texture.cmd_generate(frame.recorder.command_buffer(), &self.kernel, compute_set, constants) };
+                    self.generated = Some(self.params);
+                }
+                descriptor::update_descriptor_set(device, self.view_set, &[self.texture.sampled_write(noise::VIEW_BINDING)]);
+            },
+            _ => {
+                if self.cpu_texture.as_ref().map(|&(params, _)| params) != Some(self.params) {
+                    let texture = self.create_cpu_texture()?;
+                    // Frames in flight might still be showing the old one
+                    if let Some(old) = self.cpu_texture.replace((self.params, texture)) {
+                        frame.defer_destroy(move || drop(old));
+                    }
+                }
+                let (_, texture) = self.cpu_texture.as_ref().unwrap();
+                descriptor::update_descriptor_set(device, self.view_set, &[texture.descriptor_write(noise::VIEW_BINDING)]);
+            },
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        match key {
+            NOISE_KIND_KEY => self.params.kind = self.params.kind.next(),
+            NOISE_CPU_KEY => self.cpu = !self.cpu,
+            _ => return false,
+        }
+        info!("Showing {:?} noise from the {}", self.params.kind, if self.cpu || self.params.kind == NoiseKind::Blue { "CPU" } else { "GPU" });
+        true
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, _view: &DemoView) {
+        let device = self.context.device();
+        device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &[self.view_set], &[]);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Switches between trilinear and anisotropic filtering.
+const ANISOTROPY_KEY: glfw::Key = glfw::Key::F;
+
+/// Checkers per side of the ground plane's texture, and its size in texels.
+const GROUND_CHECKERS: u32 = 64;
+const GROUND_TEXTURE_SIZE: u32 = 1024;
+
+/// The `ground_plane`, sampled with anisotropic filtering or, after `ANISOTROPY_KEY`, without.
+struct AnisotropyDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    texture: Texture<'c, ash::Device<V1_0>>,
+    /// Takes the place of the texture's own trilinear sampler while `anisotropic`.
+    anisotropic_sampler: Owned<'c, ash::Device<V1_0>, Sampler>,
+    anisotropic: bool,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    set_layout: Shared<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// This frame's, from the frame's descriptor sets.
+    set: DescriptorSet,
+}
+
+impl<'c> AnisotropyDemo<'c> {
+    fn new(context: &'c Context, layouts: &SharedLayouts<'c>, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<AnisotropyDemo<'c>, RendererError> {
+        let device = context.device();
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, ground_plane::FRAGMENT_SHADER)?;
+        let set_layout = layouts.texture_view_set.clone();
+        let layout = create_pipeline_layout(context, &[*set_layout], &[GroundPlanePushConstants::range()])?;
+        let pipeline = ground_plane::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+
+        // Without mips there's nothing for either filter to choose from
+        let data = ImageData::checkerboard(GROUND_TEXTURE_SIZE, GROUND_CHECKERS);
+        let support = FormatSupport::query(context.instance().instance(), context.physical_device, TextureEncoding::Color.format(Format::R8g8b8a8Unorm));
+        let mip_levels = image::mip_levels(&support, &data);
+        if mip_levels == 1 {
+            return Err(RendererError::Unsupported("mips for the ground plane's texture".to_string()));
+        }
+        let immediate = ImmediateContext::new(device, context.graphics_family, context.graphics_queue)?;
+        let texture = Texture::new(device, &context.allocator, &immediate, &data, TextureEncoding::Color, mip_levels)?;
+        let max_anisotropy = ground_plane::max_anisotropy(context.limits.limits());
+        let anisotropic_sampler = safe_create::create_sampler_safe(device, &image::sampler_create_info(mip_levels, max_anisotropy), None)?;
+        info!("Ground plane: {} mips, up to {}x anisotropy, {:?} switches filtering", mip_levels, max_anisotropy, ANISOTROPY_KEY);
+        Ok(AnisotropyDemo {
+            device: device,
+            texture: texture,
+            anisotropic_sampler: anisotropic_sampler,
+            anisotropic: true,
+            pipeline: pipeline,
+            layout: layout,
+            set_layout: set_layout,
+            set: DescriptorSet::null(),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for AnisotropyDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        self.set = frame.descriptors.allocate(*self.set_layout)?;
+        let sampler = if self.anisotropic { *self.anisotropic_sampler } else { self.texture.sampler() };
+        descriptor::update_descriptor_set(self.device, self.set, &[DescriptorWrite::CombinedImageSampler {
+            binding: 0,
+            sampler: sampler,
+            image_view: self.texture.view(),
+            image_layout: ImageLayout::ShaderReadOnlyOptimal,
+        }]);
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != ANISOTROPY_KEY {
+            return false;
+        }
+        self.anisotropic = !self.anisotropic;
+        info!("Ground plane filtering: {}", if self.anisotropic { "anisotropic" } else { "trilinear" });
+        true
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        let constants = match GroundPlanePushConstants::new(&view.view_projection(), view.eye) {
+            Some(constants) => constants,
+            None => return,
+        };
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &[self.set], &[]);
+        constants.cmd_draw(self.device, command_buffer, *self.layout);
+    }
+}
+
+/// `deep_zoom`'s Mandelbrot zoom, iterated in doubles, with its iteration budget raised as the
+/// counts of late escapes come back.
+struct DeepZoomDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    zoom: DeepZoom,
+    counters: LateEscapeCounters<'c, ash::Device<V1_0>>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    _set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// Into `counters`, for the frame being recorded.
+    current: usize,
+    /// Whether `counters[i]` has a frame's count in it yet.
+    counted: [bool; frame::FRAMES_IN_FLIGHT],
+}
+
+impl<'c> DeepZoomDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<DeepZoomDemo<'c>, RendererError> {
+        let device = context.device();
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, deep_zoom::FRAGMENT_SHADER)?;
+        let set_layout = create_set_layout(context, &LateEscapeCounters::<ash::Device<V1_0>>::bindings())?;
+        let layout = create_pipeline_layout(context, &[*set_layout], &[deep_zoom::DeepZoomPushConstants::range()])?;
+        let pipeline = deep_zoom::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        Ok(DeepZoomDemo {
+            device: device,
+            zoom: DeepZoom::new(),
+            counters: LateEscapeCounters::new(device, &context.allocator, *set_layout, frame::FRAMES_IN_FLIGHT)?,
+            pipeline: pipeline,
+            layout: layout,
+            _set_layout: set_layout,
+            current: 0,
+            counted: [false; frame::FRAMES_IN_FLIGHT],
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for DeepZoomDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.current = frame.slot;
+        // The frame that last used this slot is done, so its count is in
+        let late_escapes = self.counters.take(self.current)?;
+        if self.counted[self.current] && self.zoom.adapt(late_escapes, view.extent.width * view.extent.height) {
+            debug!("Deep zoom: {} late escapes at a height of {:e}, raised the budget to {} iterations", late_escapes, self.zoom.height(), self.zoom.max_iterations);
+        }
+        self.counted[self.current] = true;
+        self.zoom.update(frame.time.delta());
+        Ok(())
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, *self.layout, 0, &[self.counters.descriptor_set(self.current)], &[]);
+        self.zoom.push_constants(&view.extent).cmd_draw(self.device, command_buffer, *self.layout);
+    }
+
+    unsafe fn cmd_finish(&self, command_buffer: CommandBuffer) {
+        self.counters.cmd_make_host_visible(command_buffer);
+    }
+}
+
+/// Of each particle's billboard, in world units.
+const PARTICLE_SIZE: f32 = 0.08;
+
+/// A `ParticleFountain` of up to `particles.count` particles, sorted back to front by
+/// `RadixSort` every frame so they blend right.
+struct ParticlesDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    fountain: ParticleFountain,
+    buffers: ParticleBuffers<'c, ash::Device<V1_0>>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    _draw_set_layout: Owned<'c, ash::Device<V1_0>, DescriptorSetLayout>,
+    /// Into `buffers`, for the frame being recorded.
+    current: usize,
+}
+
+impl<'c> ParticlesDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<ParticlesDemo<'c>, RendererError> {
+        let device = context.device();
+        let vertex_shader = renderer::load_shader_module(device, particles::VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, particles::FRAGMENT_SHADER)?;
+        let draw_set_layout = create_set_layout(context, &ParticleBuffers::<ash::Device<V1_0>>::draw_bindings())?;
+        let layout = create_pipeline_layout(context, &[*draw_set_layout], &[ParticlePushConstants::range()])?;
+        let pipeline = particles::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let load = |path: &str| shader_compile::load_spirv(path).map_err(|e| RendererError::Shader(path.to_string(), e));
+        let (histogram, scatter) = (load(radix_sort::HISTOGRAM_SHADER)?, load(radix_sort::SCATTER_SHADER)?);
+        let (scan_block, scan_add) = (load(gpu_algo::SCAN_BLOCK_SHADER)?, load(gpu_algo::SCAN_ADD_SHADER)?);
+        let shaders = SortShaders {
+            histogram: &histogram,
+            scatter: &scatter,
+            scan_block: &scan_block,
+            scan_add: &scan_add,
+        };
+        let count: u32 = cvar!("particles.count", 4096).get();
+        let buffers = ParticleBuffers::new(device, &context.allocator, *draw_set_layout, count, frame::FRAMES_IN_FLIGHT, &shaders)?;
+        // Particles live about two seconds, so this keeps around `count` of them alive
+        let fountain = ParticleFountain::new(count, count as f32 * 0.5);
+        Ok(ParticlesDemo {
+            device: device,
+            fountain: fountain,
+            buffers: buffers,
+            pipeline: pipeline,
+            layout: layout,
+            _draw_set_layout: draw_set_layout,
+            current: 0,
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for ParticlesDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        self.current = frame.slot;
+        self.fountain.update(frame.time.delta());
+        self.buffers.write(self.current, &self.fountain, &view.view)?;
+        unsafe { self.buffers.cmd_sort(frame.recorder.command_buffer(), self.current) };
+        Ok(())
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        let constants = ParticlePushConstants::new(&view.view, &view.projection, PARTICLE_SIZE);
+        self.buffers.cmd_draw(command_buffer, *self.layout, self.current, &constants);
+    }
+}
+
+/// Values are below this, so the default `reduction.count` of them sum without wrapping.
+const REDUCTION_RANGE: u32 = 1 << 16;
+
+/// `reduction.count` random values a frame, with a ceiling that rises and falls over scene time,
+/// reduced to their sum, minimum and maximum with subgroup arithmetic. Each frame's result is
+/// read back once its slot comes around again, checked against the CPU's and drawn as bars.
+struct ReductionDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    kernel: ComputeKernel<'c, ash::Device<V1_0>>,
+    buffers: ReductionBuffers<'c, ash::Device<V1_0>>,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    random: XorShift,
+    values: Vec<u32>,
+    /// The CPU's reduction of what each slot was given, until it's compared with the GPU's.
+    expected: [Option<(Reduction, u32)>; frame::FRAMES_IN_FLIGHT],
+    bars: ReductionBarsPushConstants,
+}
+
+impl<'c> ReductionDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<ReductionDemo<'c>, RendererError> {
+        let device = context.device();
+        let bindings = ReductionBuffers::<ash::Device<V1_0>>::bindings();
+        let kernel = load_kernel(context, ReductionPath::Subgroup.shader_path(), &bindings, std::mem::size_of::<u32>() as u32)?;
+        let vertex_shader = renderer::load_shader_module(device, renderer::FULLSCREEN_VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, subgroup::BARS_SHADER)?;
+        let layout = create_pipeline_layout(context, &[], &[ReductionBarsPushConstants::range()])?;
+        let pipeline = subgroup::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let count: u32 = cvar!("reduction.count", 1 << 16).get();
+        let buffers = ReductionBuffers::new(device, &context.allocator, kernel.set_layout(), count, frame::FRAMES_IN_FLIGHT)?;
+        Ok(ReductionDemo {
+            device: device,
+            kernel: kernel,
+            buffers: buffers,
+            pipeline: pipeline,
+            layout: layout,
+            random: XorShift(0x2545f491),
+            values: Vec::new(),
+            expected: [None; frame::FRAMES_IN_FLIGHT],
+            bars: ReductionBarsPushConstants::new(&Reduction::identity(), 0, REDUCTION_RANGE, false),
+        })
+    }
+}
+
+impl<'c> DemoPass<'c> for ReductionDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, view: &DemoView) -> VkResult<()> {
+        // The frame that last used this slot is done, so its partials are in
+        if let Some((expected, count)) = self.expected[frame.slot].take() {
+            let actual = self.buffers.result(frame.slot);
+            if actual != expected {
+                warn!("Subgroup reduction of {} values gave {:?}, expected {:?}", count, actual, expected);
+            }
+            self.bars = ReductionBarsPushConstants::new(&actual, count, REDUCTION_RANGE, actual != expected);
+        }
+        let ceiling = (0.55 + 0.45 * view.time.sin()) * REDUCTION_RANGE as f32;
+        let random = &mut self.random;
+        self.values.clear();
+        self.values.extend((0..self.buffers.capacity()).map(|_| (random.next() * ceiling) as u32));
+        self.buffers.write(frame.slot, &self.values)?;
+        self.expected[frame.slot] = Some((Reduction::of(&self.values), self.values.len() as u32));
+        unsafe { self.buffers.cmd_reduce(frame.recorder.command_buffer(), &self.kernel, frame.slot) };
+        Ok(())
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, _view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.bars.cmd_draw(self.device, command_buffer, *self.layout);
+    }
+}
+
+/// Drops the boxes again.
+#[cfg(feature = "physics")]
+const BOX_DROP_RESET_KEY: glfw::Key = glfw::Key::R;
+
+/// `physics.boxes` boxes dropped onto a ground plane by `PhysicsWorld::box_drop`, stepped at
+/// `physics.rate` steps a second and drawn as their colliders' outlines.
+#[cfg(feature = "physics")]
+struct BoxDropDemo<'c> {
+    device: &'c ash::Device<V1_0>,
+    world: PhysicsWorld,
+    box_count: u32,
+    timestep: FixedTimestep,
+    pipeline: Owned<'c, ash::Device<V1_0>, Pipeline>,
+    layout: Owned<'c, ash::Device<V1_0>, PipelineLayout>,
+    lines: DebugDraw,
+    /// One for each frame in flight.
+    line_buffers: Vec<DebugDrawBuffer<'c, ash::Device<V1_0>>>,
+    /// The frame-in-flight slot `prepare` last uploaded to.
+    current: usize,
+}
+
+#[cfg(feature = "physics")]
+impl<'c> BoxDropDemo<'c> {
+    fn new(context: &'c Context, render_pass: RenderPass, extent: &Extent2D) -> std::result::Result<BoxDropDemo<'c>, RendererError> {
+        let device = context.device();
+        let vertex_shader = renderer::load_shader_module(device, debug_draw::VERTEX_SHADER)?;
+        let fragment_shader = renderer::load_shader_module(device, debug_draw::FRAGMENT_SHADER)?;
+        let layout = create_pipeline_layout(context, &[], &[DebugDrawPushConstants::range()])?;
+        let pipeline = debug_draw::pipeline_builder(extent, *vertex_shader, *fragment_shader).build(device, *layout, render_pass, 0)?;
+        let line_buffers = (0..frame::FRAMES_IN_FLIGHT)
+            .map(|_| DebugDrawBuffer::new(device, &context.allocator, DEBUG_LINE_CAPACITY))
+            .collect::<VkResult<Vec<_>>>()?;
+        let box_count: u32 = cvar!("physics.boxes", 16).get();
+        let rate: f32 = cvar!("physics.rate", 60.0).get();
+        Ok(BoxDropDemo {
+            device: device,
+            world: PhysicsWorld::box_drop(box_count),
+            box_count: box_count,
+            timestep: FixedTimestep::new(1.0 / rate.max(1.0)),
+            pipeline: pipeline,
+            layout: layout,
+            lines: DebugDraw::new(),
+            line_buffers: line_buffers,
+            current: 0,
+        })
+    }
+}
+
+#[cfg(feature = "physics")]
+impl<'c> DemoPass<'c> for BoxDropDemo<'c> {
+    fn prepare(&mut self, frame: &mut FrameContext<'_, 'c, ash::Device<V1_0>>, _view: &DemoView) -> VkResult<()> {
+        let world = &mut self.world;
+        self.timestep.run(frame.time, |dt| world.step(dt));
+        self.current = frame.slot;
+        self.lines.clear();
+        self.world.debug_draw(&mut self.lines, self.timestep.alpha());
+        self.line_buffers[self.current].upload(&self.lines)
+    }
+
+    fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != BOX_DROP_RESET_KEY {
+            return false;
+        }
+        self.world = PhysicsWorld::box_drop(self.box_count);
+        true
+    }
+
+    unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, view: &DemoView) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, *self.pipeline);
+        self.line_buffers[self.current].cmd_draw(command_buffer, *self.layout, &view.view_projection());
+    }
+}
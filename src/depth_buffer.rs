@@ -0,0 +1,126 @@
+//! Depth attachments for render passes that draw 3D geometry. The depth only matters while the
+//! pass runs, so it's cleared on load and never stored, and one image can be shared by every
+//! framebuffer of the same size.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::ptr;
+use vk::types::*;
+use ::format_support;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+/// What the depth is cleared to: the far plane, for `CompareOp::Less`.
+pub const CLEAR_DEPTH: f32 = 1.0;
+
+pub fn clear_value() -> ClearValue {
+    ClearValue::new_depth_stencil(ClearDepthStencilValue {
+        depth: CLEAR_DEPTH,
+        stencil: 0,
+    })
+}
+
+/// The attachment for a depth buffer in `format`, cleared on load and discarded after the pass.
+pub fn attachment_description(format: Format) -> AttachmentDescription {
+    AttachmentDescription {
+        flags: Default::default(),
+        format: format,
+        samples: SAMPLE_COUNT_1_BIT,
+        load_op: AttachmentLoadOp::Clear,
+        store_op: AttachmentStoreOp::DontCare,
+        stencil_load_op: AttachmentLoadOp::DontCare,
+        stencil_store_op: AttachmentStoreOp::DontCare,
+        initial_layout: ImageLayout::Undefined,
+        final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+    }
+}
+
+/// The aspects of a `format` image: depth, and stencil if it has any.
+pub fn aspect_mask(format: Format) -> ImageAspectFlags {
+    if format_support::has_stencil(format) {
+        IMAGE_ASPECT_DEPTH_BIT | IMAGE_ASPECT_STENCIL_BIT
+    } else {
+        IMAGE_ASPECT_DEPTH_BIT
+    }
+}
+
+pub struct DepthBuffer<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+}
+
+impl<'d, D: DeviceV1_0> DepthBuffer<'d, D> {
+    /// `format` should come from `format_support::depth_format`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, extent: Extent2D) -> VkResult<DepthBuffer<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: format,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+        // Destroying/freeing null handles is a no-op, so from here on out `Drop` will clean up
+        // after us if anything goes wrong.
+        let mut ret = DepthBuffer {
+            device: device,
+            image: image,
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+        };
+        ret.memory = allocator.allocate_image_memory(image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: image,
+            view_type: ImageViewType::Type2d,
+            format: format,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: ImageSubresourceRange {
+                aspect_mask: aspect_mask(format),
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+        debug!("Created {}x{} {:?} depth buffer: {:?}", extent.width, extent.height, format, image);
+        Ok(ret)
+    }
+
+    pub fn view(&self) -> ImageView {
+        self.view
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for DepthBuffer<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying depth buffer: {:?}", self.image);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Descriptor set allocation and updates. `DescriptorAllocator` hands out sets from a growing list
+//! of pools, and gets reset wholesale instead of freeing individual sets, which suits sets that
+//! only live for one frame.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::ptr;
+use vk::types::*;
+use ::safe_create::{ self, Owned };
+
+pub struct DescriptorAllocator<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    pool_sizes: Vec<DescriptorPoolSize>,
+    sets_per_pool: u32,
+    current: Option<Owned<'d, D, DescriptorPool>>,
+    full: Vec<Owned<'d, D, DescriptorPool>>,
+    free: Vec<Owned<'d, D, DescriptorPool>>,
+}
+
+impl<'d, D: DeviceV1_0> DescriptorAllocator<'d, D> {
+    /// Each pool is created with `pool_sizes` descriptors and room for `sets_per_pool` sets.
+    pub fn new(device: &'d D, pool_sizes: &[DescriptorPoolSize], sets_per_pool: u32) -> DescriptorAllocator<'d, D> {
+        DescriptorAllocator {
+            device: device,
+            pool_sizes: pool_sizes.to_vec(),
+            sets_per_pool: sets_per_pool,
+            current: None,
+            full: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn next_pool(&mut self) -> VkResult<Owned<'d, D, DescriptorPool>> {
+        if let Some(pool) = self.free.pop() {
+            return Ok(pool);
+        }
+        let create_info = DescriptorPoolCreateInfo {
+            s_type: StructureType::DescriptorPoolCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            max_sets: self.sets_per_pool,
+            pool_size_count: self.pool_sizes.len() as u32,
+            p_pool_sizes: self.pool_sizes.as_ptr(),
+        };
+        let pool = safe_create::create_descriptor_pool_safe(self.device, &create_info, None)?;
+        trace!("Created descriptor pool: {:?}", *pool);
+        Ok(pool)
+    }
+
+    /// The sets are freed with the pool, or when it's reset.
+    fn allocate_from(&self, pool: &DescriptorPool, layout: DescriptorSetLayout) -> VkResult<DescriptorSet> {
+        safe_create::allocate_descriptor_sets_safe(self.device, pool, &[layout]).map(|sets| sets[0])
+    }
+
+    pub fn allocate(&mut self, layout: DescriptorSetLayout) -> VkResult<DescriptorSet> {
+        let pool = match self.current.take() {
+            Some(pool) => pool,
+            None => self.next_pool()?,
+        };
+        match self.allocate_from(&pool, layout) {
+            Ok(set) => {
+                self.current = Some(pool);
+                Ok(set)
+            },
+            Err(res) => {
+                // Without VK_KHR_maintenance1 there's no dedicated "pool is full" error, so retry
+                // any failure once with a fresh pool
+                trace!("Descriptor pool {:?} is exhausted ({:?})", *pool, res);
+                self.full.push(pool);
+                let pool = self.next_pool()?;
+                let ret = self.allocate_from(&pool, layout);
+                self.current = Some(pool);
+                ret
+            },
+        }
+    }
+
+    /// Frees every set allocated so far. None of them may still be in use by the GPU.
+    pub fn reset(&mut self) -> VkResult<()> {
+        if let Some(pool) = self.current.take() {
+            self.full.push(pool);
+        }
+        while let Some(pool) = self.full.pop() {
+            if let Err(e) = unsafe { self.device.reset_descriptor_pool(*pool, Default::default()) } {
+                self.full.push(pool);
+                return Err(e);
+            }
+            self.free.push(pool);
+        }
+        Ok(())
+    }
+}
+
+/// The set read by full-screen views of a single texture, like `shaders/noise_view_fragment.glsl`,
+/// `shaders/automata_fragment.glsl` and `shaders/shadow_atlas_view_fragment.glsl`: one combined
+/// image sampler at binding 0, for the fragment shader.
+pub fn texture_view_bindings() -> [DescriptorSetLayoutBinding; 1] {
+    [DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptor_type: DescriptorType::CombinedImageSampler,
+        descriptor_count: 1,
+        stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+        p_immutable_samplers: ptr::null(),
+    }]
+}
+
+/// A single descriptor update, owning the info struct that `WriteDescriptorSet` points to.
+#[derive(Debug, Clone)]
+pub enum DescriptorWrite {
+    CombinedImageSampler { binding: u32, sampler: Sampler, image_view: ImageView, image_layout: ImageLayout },
+    StorageImage { binding: u32, image_view: ImageView, image_layout: ImageLayout },
+    UniformBuffer { binding: u32, buffer: Buffer, offset: DeviceSize, range: DeviceSize },
+    StorageBuffer { binding: u32, buffer: Buffer, offset: DeviceSize, range: DeviceSize },
+}
+
+impl DescriptorWrite {
+    fn binding(&self) -> u32 {
+        match *self {
+            DescriptorWrite::CombinedImageSampler { binding, .. } => binding,
+            DescriptorWrite::StorageImage { binding, .. } => binding,
+            DescriptorWrite::UniformBuffer { binding, .. } => binding,
+            DescriptorWrite::StorageBuffer { binding, .. } => binding,
+        }
+    }
+
+    fn descriptor_type(&self) -> DescriptorType {
+        match *self {
+            DescriptorWrite::CombinedImageSampler { .. } => DescriptorType::CombinedImageSampler,
+            DescriptorWrite::StorageImage { .. } => DescriptorType::StorageImage,
+            DescriptorWrite::UniformBuffer { .. } => DescriptorType::UniformBuffer,
+            DescriptorWrite::StorageBuffer { .. } => DescriptorType::StorageBuffer,
+        }
+    }
+}
+
+/// Builds the raw writes for `dst_set`, and hands them to `f` while the info structs they point
+/// into are still alive.
+pub fn with_raw_writes<R, F: FnOnce(&[WriteDescriptorSet]) -> R>(dst_set: DescriptorSet, writes: &[DescriptorWrite], f: F) -> R {
+    let image_infos: Vec<DescriptorImageInfo> = writes.iter().map(|write| match *write {
+        DescriptorWrite::CombinedImageSampler { sampler, image_view, image_layout, .. } => DescriptorImageInfo {
+            sampler: sampler,
+            image_view: image_view,
+            image_layout: image_layout,
+        },
+        DescriptorWrite::StorageImage { image_view, image_layout, .. } => DescriptorImageInfo {
+            sampler: Sampler::null(),
+            image_view: image_view,
+            image_layout: image_layout,
+        },
+        _ => DescriptorImageInfo {
+            sampler: Sampler::null(),
+            image_view: ImageView::null(),
+            image_layout: ImageLayout::Undefined,
+        },
+    }).collect();
+    let buffer_infos: Vec<DescriptorBufferInfo> = writes.iter().map(|write| match *write {
+        DescriptorWrite::UniformBuffer { buffer, offset, range, .. } | DescriptorWrite::StorageBuffer { buffer, offset, range, .. } => DescriptorBufferInfo {
+            buffer: buffer,
+            offset: offset,
+            range: range,
+        },
+        _ => DescriptorBufferInfo {
+            buffer: Buffer::null(),
+            offset: 0,
+            range: 0,
+        },
+    }).collect();
+    let raw_writes: Vec<WriteDescriptorSet> = writes.iter().enumerate().map(|(idx, write)| {
+        let is_image = matches!(write.descriptor_type(), DescriptorType::CombinedImageSampler | DescriptorType::StorageImage);
+        WriteDescriptorSet {
+            s_type: StructureType::WriteDescriptorSet,
+            p_next: ptr::null(),
+            dst_set: dst_set,
+            dst_binding: write.binding(),
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: write.descriptor_type(),
+            p_image_info: if is_image { &image_infos[idx] } else { ptr::null() },
+            p_buffer_info: if is_image { ptr::null() } else { &buffer_infos[idx] },
+            p_texel_buffer_view: ptr::null(),
+        }
+    }).collect();
+    f(&raw_writes)
+}
+
+pub fn update_descriptor_set<D: DeviceV1_0>(device: &D, dst_set: DescriptorSet, writes: &[DescriptorWrite]) {
+    with_raw_writes(dst_set, writes, |raw_writes| unsafe {
+        device.update_descriptor_sets(raw_writes, &[]);
+    })
+}
@@ -0,0 +1,145 @@
+//! Ranking the physical devices that can run the renderer at all, so a laptop with only an
+//! integrated GPU (or a software rasterizer) still gets a window. Whether a device is usable is
+//! decided elsewhere: graphics, presentation and compute queues, the required extensions, and a
+//! surface format and present mode. The score only orders the usable ones, preferring discrete
+//! GPUs, then more device-local memory, bigger limits, and a friendlier queue layout.
+use std;
+use vk::types::*;
+use ::queue::QueueFamilyIndex;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// The parts of a usable device's score, kept apart so the log can say why one won.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceScore {
+    pub device_type: u64,
+    pub memory: u64,
+    pub limits: u64,
+    pub queues: u64,
+    pub features: u64,
+}
+
+impl DeviceScore {
+    /// `graphics_family` and `presentation_family` are the families the renderer would use on it.
+    pub fn new(properties: &PhysicalDeviceProperties, features: &PhysicalDeviceFeatures, memory_properties: &PhysicalDeviceMemoryProperties, queue_families: &[QueueFamilyProperties], graphics_family: QueueFamilyIndex, presentation_family: QueueFamilyIndex) -> DeviceScore {
+        let device_type = match properties.device_type {
+            PhysicalDeviceType::DiscreteGpu => 10000,
+            PhysicalDeviceType::IntegratedGpu => 5000,
+            PhysicalDeviceType::VirtualGpu => 2000,
+            PhysicalDeviceType::Cpu => 500,
+            PhysicalDeviceType::Other => 0,
+        };
+        // Integrated GPUs report shared system memory as device local, so this is capped well
+        // below the gap between device types
+        let device_local = (0..memory_properties.memory_heap_count as usize)
+            .map(|idx| &memory_properties.memory_heaps[idx])
+            .filter(|heap| heap.flags.subset(MEMORY_HEAP_DEVICE_LOCAL_BIT))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or(0);
+        let memory = std::cmp::min(16, device_local / GIB) * 100;
+        let limits = properties.limits.max_image_dimension2d as u64 / 1024 * 10;
+        let dedicated_compute = queue_families.iter()
+            .any(|family| family.queue_count > 0 && family.queue_flags.subset(QUEUE_COMPUTE_BIT) && !family.queue_flags.subset(QUEUE_GRAPHICS_BIT));
+        let queues = (if graphics_family == presentation_family { 100 } else { 0 }) + (if dedicated_compute { 50 } else { 0 });
+        // Optional, but the demos that want it are listed as unavailable without it
+        let features = if features.geometry_shader != 0 { 50 } else { 0 };
+        DeviceScore {
+            device_type: device_type,
+            memory: memory,
+            limits: limits,
+            queues: queues,
+            features: features,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.device_type + self.memory + self.limits + self.queues + self.features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(device_type: PhysicalDeviceType, max_image_dimension2d: u32) -> PhysicalDeviceProperties {
+        let mut properties: PhysicalDeviceProperties = unsafe { std::mem::zeroed() };
+        properties.device_type = device_type;
+        properties.limits.max_image_dimension2d = max_image_dimension2d;
+        properties
+    }
+
+    fn memory(heaps: &[(u64, MemoryHeapFlags)]) -> PhysicalDeviceMemoryProperties {
+        let mut memory: PhysicalDeviceMemoryProperties = unsafe { std::mem::zeroed() };
+        memory.memory_heap_count = heaps.len() as u32;
+        for (heap, &(size, flags)) in memory.memory_heaps.iter_mut().zip(heaps.iter()) {
+            heap.size = size;
+            heap.flags = flags;
+        }
+        memory
+    }
+
+    fn family(queue_flags: QueueFlags) -> QueueFamilyProperties {
+        QueueFamilyProperties {
+            queue_flags: queue_flags,
+            queue_count: 1,
+            timestamp_valid_bits: 64,
+            min_image_transfer_granularity: Extent3D { width: 1, height: 1, depth: 1 },
+        }
+    }
+
+    fn no_features() -> PhysicalDeviceFeatures {
+        unsafe { std::mem::zeroed() }
+    }
+
+    #[test]
+    fn parts_of_the_score() {
+        let mut features = no_features();
+        features.geometry_shader = 1;
+        let families = [family(QUEUE_GRAPHICS_BIT | QUEUE_COMPUTE_BIT), family(QUEUE_COMPUTE_BIT)];
+        let score = DeviceScore::new(
+            &properties(PhysicalDeviceType::DiscreteGpu, 16384),
+            &features,
+            &memory(&[(8 * GIB, MEMORY_HEAP_DEVICE_LOCAL_BIT), (32 * GIB, MemoryHeapFlags::empty())]),
+            &families,
+            QueueFamilyIndex(0),
+            QueueFamilyIndex(0)
+        );
+        assert_eq!(score, DeviceScore {
+            device_type: 10000,
+            memory: 800,
+            limits: 160,
+            queues: 150,
+            features: 50,
+        });
+        assert_eq!(score.total(), 11160);
+    }
+
+    #[test]
+    fn device_type_outweighs_everything_else() {
+        let families = [family(QUEUE_GRAPHICS_BIT | QUEUE_COMPUTE_BIT)];
+        let mut features = no_features();
+        features.geometry_shader = 1;
+        // Shared system memory counts as device local on integrated GPUs
+        let integrated = DeviceScore::new(&properties(PhysicalDeviceType::IntegratedGpu, 16384), &features, &memory(&[(64 * GIB, MEMORY_HEAP_DEVICE_LOCAL_BIT)]), &families, QueueFamilyIndex(0), QueueFamilyIndex(0));
+        assert_eq!(integrated.memory, 1600);
+        let discrete = DeviceScore::new(&properties(PhysicalDeviceType::DiscreteGpu, 4096), &no_features(), &memory(&[(GIB / 2, MEMORY_HEAP_DEVICE_LOCAL_BIT)]), &families, QueueFamilyIndex(0), QueueFamilyIndex(1));
+        assert!(discrete.total() > integrated.total(), "{:?} vs {:?}", discrete, integrated);
+        let software = DeviceScore::new(&properties(PhysicalDeviceType::Cpu, 16384), &features, &memory(&[]), &families, QueueFamilyIndex(0), QueueFamilyIndex(0));
+        assert!(integrated.total() > software.total());
+    }
+
+    #[test]
+    fn shared_presentation_and_compute_only_queues_score_higher() {
+        let props = properties(PhysicalDeviceType::DiscreteGpu, 8192);
+        let heaps = memory(&[(4 * GIB, MEMORY_HEAP_DEVICE_LOCAL_BIT)]);
+        let combined = [family(QUEUE_GRAPHICS_BIT | QUEUE_COMPUTE_BIT)];
+        let separate = DeviceScore::new(&props, &no_features(), &heaps, &combined, QueueFamilyIndex(0), QueueFamilyIndex(1));
+        let shared = DeviceScore::new(&props, &no_features(), &heaps, &combined, QueueFamilyIndex(0), QueueFamilyIndex(0));
+        assert_eq!((separate.queues, shared.queues), (0, 100));
+        let mut empty_compute = family(QUEUE_COMPUTE_BIT);
+        empty_compute.queue_count = 0;
+        assert_eq!(DeviceScore::new(&props, &no_features(), &heaps, &[combined[0].clone(), empty_compute], QueueFamilyIndex(0), QueueFamilyIndex(0)).queues, 100);
+        assert_eq!(DeviceScore::new(&props, &no_features(), &heaps, &[combined[0].clone(), family(QUEUE_COMPUTE_BIT)], QueueFamilyIndex(0), QueueFamilyIndex(0)).queues, 150);
+    }
+}
@@ -0,0 +1,205 @@
+//! Fixed-timestep simulation decoupled from rendering. Each frame's delta goes into an
+//! accumulator, which is drained in whole simulation steps, so physics and particles behave the
+//! same at 30 or 240 frames per second and under any present mode. What's left in the accumulator
+//! is how far rendering is between the last two steps, and `Interpolated` values blend their two
+//! latest states by that fraction so motion stays smooth when the rates don't line up.
+use ::camera_path::CameraPose;
+use ::gizmo::Transform;
+use ::math::{ Quat, Vec3 };
+use ::math::prelude::lerp;
+use ::time::Time;
+
+/// Linear blending between two states, `t = 0` giving `self` and `t = 1` giving `other`.
+pub trait Lerp: Copy {
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &f32, t: f32) -> f32 {
+        lerp(*self, *other, t)
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(&self, other: &Vec3, t: f32) -> Vec3 {
+        *self + (*other - *self) * t
+    }
+}
+
+impl Lerp for Quat {
+    /// Normalized lerp along the shorter arc, which is close enough to slerp for the small
+    /// rotations of a single step.
+    fn lerp(&self, other: &Quat, t: f32) -> Quat {
+        let dot = self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w;
+        let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+        Quat {
+            x: self.x.lerp(&(other.x * sign), t),
+            y: self.y.lerp(&(other.y * sign), t),
+            z: self.z.lerp(&(other.z * sign), t),
+            w: self.w.lerp(&(other.w * sign), t),
+        }.normalize()
+    }
+}
+
+impl Lerp for Transform {
+    fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            position: self.position.lerp(&other.position, t),
+            rotation: self.rotation.lerp(&other.rotation, t),
+        }
+    }
+}
+
+impl Lerp for CameraPose {
+    fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        CameraPose {
+            eye: self.eye.lerp(&other.eye, t),
+            target: self.target.lerp(&other.target, t),
+        }
+    }
+}
+
+/// A simulated value's previous and current state.
+#[derive(Debug, Clone, Copy)]
+pub struct Interpolated<T: Lerp> {
+    previous: T,
+    current: T,
+}
+
+impl<T: Lerp> Interpolated<T> {
+    pub fn new(value: T) -> Interpolated<T> {
+        Interpolated {
+            previous: value,
+            current: value,
+        }
+    }
+
+    /// The state at the latest step.
+    #[cfg_attr(not(feature = "physics"), allow(dead_code))]
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+
+    /// Records the state after a step.
+    pub fn update(&mut self, value: T) {
+        self.previous = self.current;
+        self.current = value;
+    }
+
+    /// Moves to `value` without blending from the old state, e.g. after a teleport.
+    pub fn reset(&mut self, value: T) {
+        self.previous = value;
+        self.current = value;
+    }
+
+    /// The state to render, `alpha` of the way from the previous step to the latest.
+    pub fn get(&self, alpha: f32) -> T {
+        self.previous.lerp(&self.current, alpha)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: f32,
+    /// Steps a single frame may run, so a slow frame can't snowball into ever slower ones.
+    max_steps_per_frame: u32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    /// Steps of `step` seconds, e.g. `1.0 / 60.0`.
+    pub fn new(step: f32) -> FixedTimestep {
+        assert!(step > 0.0);
+        FixedTimestep {
+            step: step,
+            max_steps_per_frame: 8,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Adds `delta` seconds and returns how many steps to run now. Time beyond
+    /// `max_steps_per_frame` steps is dropped, so the simulation slows down instead.
+    pub fn advance(&mut self, delta: f32) -> u32 {
+        self.accumulator += delta.max(0.0);
+        let mut steps = (self.accumulator / self.step) as u32;
+        if steps > self.max_steps_per_frame {
+            debug!("Fixed update fell behind by {} steps, dropping them", steps - self.max_steps_per_frame);
+            steps = self.max_steps_per_frame;
+            self.accumulator = self.step * steps as f32;
+        }
+        self.accumulator -= self.step * steps as f32;
+        steps
+    }
+
+    /// Runs `update` for each step due after this frame of `time`. Pausing `time` stops the
+    /// steps, and its scale speeds them up or slows them down.
+    #[cfg_attr(not(feature = "physics"), allow(dead_code))]
+    pub fn run<F: FnMut(f32)>(&mut self, time: &Time, mut update: F) {
+        let steps = self.advance(time.delta());
+        for _ in 0..steps {
+            update(self.step);
+        }
+    }
+
+    /// How far rendering is between the last step and the next, in `[0, 1)`.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulated_time_drains_in_whole_steps() {
+        let mut timestep = FixedTimestep::new(0.25);
+        assert_eq!(timestep.advance(0.1), 0);
+        assert_eq!(timestep.alpha(), 0.4);
+        assert_eq!(timestep.advance(0.4), 2);
+        assert!(timestep.alpha() < 1e-6);
+        assert_eq!(timestep.advance(0.625), 2);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(-1.0), 0);
+    }
+
+    #[test]
+    fn slow_frames_drop_the_steps_past_the_limit() {
+        let mut timestep = FixedTimestep::new(0.25);
+        assert_eq!(timestep.advance(10.1), 8);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(0.25), 1);
+    }
+
+    #[test]
+    fn interpolated_blends_the_last_two_states() {
+        let mut value = Interpolated::new(1.0f32);
+        assert_eq!(value.get(0.5), 1.0);
+        value.update(3.0);
+        assert_eq!((value.get(0.0), value.get(0.5), value.get(1.0)), (1.0, 2.0, 3.0));
+        value.reset(7.0);
+        assert_eq!((value.get(0.0), *value.current()), (7.0, 7.0));
+    }
+
+    #[test]
+    fn camera_poses_lerp_eye_and_target() {
+        let a = CameraPose { eye: Vec3::new(0.0, 2.0, 4.0), target: Vec3::zero() };
+        let b = CameraPose { eye: Vec3::new(4.0, 2.0, 0.0), target: Vec3::new(0.0, 1.0, 0.0) };
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+        assert_eq!(a.lerp(&b, 0.25), CameraPose { eye: Vec3::new(1.0, 2.0, 3.0), target: Vec3::new(0.0, 0.25, 0.0) });
+    }
+
+    #[test]
+    fn quats_lerp_along_the_shorter_arc() {
+        let identity = Quat { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+        let negated = Quat { x: 0.0, y: 0.0, z: 0.0, w: -1.0 };
+        // The same rotation, so every blend is too
+        let halfway = identity.lerp(&negated, 0.5);
+        assert!((halfway.w.abs() - 1.0).abs() < 1e-6, "{:?}", halfway);
+    }
+}
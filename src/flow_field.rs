@@ -0,0 +1,289 @@
+//! Curl-noise flow field. A compute pass (`shaders/curl_noise.glsl`) fills a 3D storage image
+//! with the curl of a noise potential, which is divergence free and so looks like fluid flow.
+//! The field is drawn as one instanced line per voxel, with the vertex shader sampling the same
+//! image as a 3D texture to orient and color its line.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::ComputeKernel;
+use ::descriptor::DescriptorWrite;
+use ::math::Mat4;
+use ::math::prelude::div_ceil;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::time::Time;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const NOISE_SHADER: &'static str = "shaders/curl_noise.comp.spv";
+pub const VERTEX_SHADER: &'static str = "shaders/flow_field_vertex.vert.spv";
+pub const FRAGMENT_SHADER: &'static str = "shaders/flow_field_fragment.frag.spv";
+
+/// Binding of the field in both the compute and the draw descriptor sets.
+pub const FIELD_BINDING: u32 = 0;
+
+/// Invocations per axis of a `shaders/curl_noise.glsl` workgroup.
+const LOCAL_SIZE: u32 = 4;
+
+const FIELD_FORMAT: Format = Format::R16g16b16a16Sfloat;
+
+/// Push constant block read by `shaders/curl_noise.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CurlNoisePushConstants {
+    /// Scrolls the noise through the field, so the flow evolves.
+    pub time: f32,
+    /// Noise features across the whole field.
+    pub frequency: f32,
+}
+
+impl CurlNoisePushConstants {
+    /// Noise at the current animation time, so the flow pauses and scales along with it.
+    pub fn at(time: &Time, frequency: f32) -> CurlNoisePushConstants {
+        CurlNoisePushConstants {
+            time: time.push_constants().time,
+            frequency: frequency,
+        }
+    }
+}
+
+/// Push constant block read by `shaders/flow_field_vertex.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FlowFieldPushConstants {
+    pub view_projection: Mat4,
+    /// The field spans `[-extent, extent]` on every axis.
+    pub extent: f32,
+    pub line_length: f32,
+    pub resolution: u32,
+}
+
+impl FlowFieldPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            offset: 0,
+            size: std::mem::size_of::<FlowFieldPushConstants>() as u32,
+        }
+    }
+}
+
+pub struct FlowField<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+    sampler: Sampler,
+    resolution: u32,
+    /// Whether the image has left `Undefined` for `General`.
+    initialized: bool,
+}
+
+impl<'d, D: DeviceV1_0> FlowField<'d, D> {
+    /// Creates a `resolution`³ field. It's undefined until the first `cmd_generate`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, resolution: u32) -> VkResult<FlowField<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type3d,
+            format: FIELD_FORMAT,
+            extent: Extent3D {
+                width: resolution,
+                height: resolution,
+                depth: resolution,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_STORAGE_BIT | IMAGE_USAGE_SAMPLED_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+        let mut ret = FlowField {
+            device: device,
+            image: image,
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+            sampler: Sampler::null(),
+            resolution: resolution,
+            initialized: false,
+        };
+        ret.memory = allocator.allocate_image_memory(image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: image,
+            view_type: ImageViewType::Type3d,
+            format: FIELD_FORMAT,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: FlowField::<D>::subresource_range(),
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+        debug!("Created {}³ flow field: {:?}", resolution, image);
+        Ok(ret)
+    }
+
+    fn subresource_range() -> ImageSubresourceRange {
+        ImageSubresourceRange {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    /// Descriptor set layout bindings for the noise compute kernel.
+    pub fn compute_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: FIELD_BINDING,
+            descriptor_type: DescriptorType::StorageImage,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    /// Descriptor set layout bindings for the line draw.
+    pub fn draw_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: FIELD_BINDING,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    pub fn storage_write(&self) -> DescriptorWrite {
+        DescriptorWrite::StorageImage {
+            binding: FIELD_BINDING,
+            image_view: self.view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    /// The field stays in `General` between the passes, so that's what it's sampled in.
+    pub fn sampled_write(&self) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: FIELD_BINDING,
+            sampler: self.sampler,
+            image_view: self.view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    /// Records the noise pass with `kernel` (built from `NOISE_SHADER` and `compute_bindings`)
+    /// and `descriptor_set` (holding `storage_write`), followed by a barrier for the draw's
+    /// vertex shader reads. Must be outside a render pass.
+    pub unsafe fn cmd_generate(&mut self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>, descriptor_set: DescriptorSet, constants: &CurlNoisePushConstants) {
+        let (old_layout, src_stage, src_access) = if self.initialized {
+            // Earlier draws may still be sampling the field
+            (ImageLayout::General, PIPELINE_STAGE_VERTEX_SHADER_BIT, ACCESS_SHADER_READ_BIT)
+        } else {
+            (ImageLayout::Undefined, PIPELINE_STAGE_TOP_OF_PIPE_BIT, Default::default())
+        };
+        let to_compute = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: src_access,
+            dst_access_mask: ACCESS_SHADER_WRITE_BIT,
+            old_layout: old_layout,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: FlowField::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, src_stage, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[to_compute]);
+        self.initialized = true;
+
+        let groups = div_ceil(self.resolution, LOCAL_SIZE);
+        kernel.cmd_dispatch(command_buffer, descriptor_set, constants, (groups, groups, groups));
+
+        let to_vertex = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::General,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: FlowField::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_VERTEX_SHADER_BIT, Default::default(), &[], &[], &[to_vertex]);
+    }
+
+    /// Records the line draw, one instance per voxel, with the flow field pipeline and the
+    /// descriptor set holding `sampled_write` already bound.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view_projection: &Mat4, extent: f32) {
+        let constants = FlowFieldPushConstants {
+            view_projection: *view_projection,
+            extent: extent,
+            // About one voxel long at full speed
+            line_length: 2.0 * extent / self.resolution as f32,
+            resolution: self.resolution,
+        };
+        let range = FlowFieldPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const FlowFieldPushConstants as *const c_void);
+        let instance_count = self.resolution * self.resolution * self.resolution;
+        self.device.cmd_draw(command_buffer, 2, instance_count, 0, 0);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for FlowField<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying flow field: {:?}", self.image);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+/// Instanced lines from `shaders/flow_field_vertex.glsl`, with no vertex buffers.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .topology(PrimitiveTopology::LineList)
+        .cull_mode(CULL_MODE_NONE)
+        .depth_test(true, CompareOp::Less)
+}
@@ -0,0 +1,56 @@
+//! Switching the swapchain's surface format at runtime with F, to try the renderer against every
+//! format and color space the surface reports, not only the one `choose_format` picked at
+//! startup. Render passes are made for one format, so a switch rebuilds the scene pipelines, the
+//! splash and the log console along with the swapchain, and the splash shows again while the
+//! scene's pipelines compile.
+use glfw;
+use vk::types::*;
+use ::hdr::ColorSpace;
+
+pub const KEY: glfw::Key = glfw::Key::F;
+
+pub struct SurfaceFormatCycle {
+    formats: Vec<(Format, ColorSpace)>,
+    current: usize,
+}
+
+impl SurfaceFormatCycle {
+    /// Cycles through `formats`, starting from `initial`. `Undefined`, which means the surface
+    /// takes anything, isn't a format to switch to.
+    pub fn new(formats: Vec<(Format, ColorSpace)>, initial: (Format, ColorSpace)) -> SurfaceFormatCycle {
+        let mut formats: Vec<(Format, ColorSpace)> = formats.into_iter()
+            .filter(|&(format, _)| format != Format::Undefined)
+            .collect();
+        let current = match formats.iter().position(|&candidate| candidate == initial) {
+            Some(idx) => idx,
+            None => {
+                formats.insert(0, initial);
+                0
+            },
+        };
+        SurfaceFormatCycle {
+            formats: formats,
+            current: current,
+        }
+    }
+
+    /// The format and color space the swapchain should have.
+    pub fn current(&self) -> (Format, ColorSpace) {
+        self.formats[self.current]
+    }
+
+    /// Moves to the next format on `KEY`. Returns whether it handled `key`.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != KEY {
+            return false;
+        }
+        if self.formats.len() < 2 {
+            info!("The surface only offers {:?} in {}, so there's nothing to switch to", self.formats[0].0, self.formats[0].1);
+            return true;
+        }
+        self.current = (self.current + 1) % self.formats.len();
+        let (format, color_space) = self.current();
+        info!("Switching to surface format {:?} in {} ({} of {})", format, color_space, self.current + 1, self.formats.len());
+        true
+    }
+}
@@ -0,0 +1,142 @@
+//! What a physical device can do with a format, for the places that would otherwise assume:
+//! picking a depth format, checking that a format can be blitted to generate mips, choosing
+//! between compressed texture encodings, and storage images in compute passes. Only a handful
+//! of usages are guaranteed by the spec for any given format, and which ones differ by tiling.
+use ash::version::InstanceV1_0;
+use vk::types::*;
+
+/// Depth formats in order of preference. `D32Sfloat` is the most precise, and with a reversed
+/// depth range keeps precision out to the far plane. Stencil formats come after the plain ones.
+const DEPTH_FORMATS: [Format; 4] = [Format::D32Sfloat, Format::D32SfloatS8Uint, Format::D24UnormS8Uint, Format::D16Unorm];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatUsage {
+    Sampled,
+    /// Sampled through a sampler with linear filtering.
+    SampledLinear,
+    Storage,
+    StorageAtomic,
+    ColorAttachment,
+    BlendedColorAttachment,
+    DepthStencilAttachment,
+    BlitSrc,
+    BlitDst,
+    VertexBuffer,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+}
+
+impl FormatUsage {
+    pub const ALL: [FormatUsage; 12] = [
+        FormatUsage::Sampled,
+        FormatUsage::SampledLinear,
+        FormatUsage::Storage,
+        FormatUsage::StorageAtomic,
+        FormatUsage::ColorAttachment,
+        FormatUsage::BlendedColorAttachment,
+        FormatUsage::DepthStencilAttachment,
+        FormatUsage::BlitSrc,
+        FormatUsage::BlitDst,
+        FormatUsage::VertexBuffer,
+        FormatUsage::UniformTexelBuffer,
+        FormatUsage::StorageTexelBuffer,
+    ];
+
+    pub fn features(&self) -> FormatFeatureFlags {
+        match *self {
+            FormatUsage::Sampled => FORMAT_FEATURE_SAMPLED_IMAGE_BIT,
+            FormatUsage::SampledLinear => FORMAT_FEATURE_SAMPLED_IMAGE_BIT | FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT,
+            FormatUsage::Storage => FORMAT_FEATURE_STORAGE_IMAGE_BIT,
+            FormatUsage::StorageAtomic => FORMAT_FEATURE_STORAGE_IMAGE_BIT | FORMAT_FEATURE_STORAGE_IMAGE_ATOMIC_BIT,
+            FormatUsage::ColorAttachment => FORMAT_FEATURE_COLOR_ATTACHMENT_BIT,
+            FormatUsage::BlendedColorAttachment => FORMAT_FEATURE_COLOR_ATTACHMENT_BIT | FORMAT_FEATURE_COLOR_ATTACHMENT_BLEND_BIT,
+            FormatUsage::DepthStencilAttachment => FORMAT_FEATURE_DEPTH_STENCIL_ATTACHMENT_BIT,
+            FormatUsage::BlitSrc => FORMAT_FEATURE_BLIT_SRC_BIT,
+            FormatUsage::BlitDst => FORMAT_FEATURE_BLIT_DST_BIT,
+            FormatUsage::VertexBuffer => FORMAT_FEATURE_VERTEX_BUFFER_BIT,
+            FormatUsage::UniformTexelBuffer => FORMAT_FEATURE_UNIFORM_TEXEL_BUFFER_BIT,
+            FormatUsage::StorageTexelBuffer => FORMAT_FEATURE_STORAGE_TEXEL_BUFFER_BIT,
+        }
+    }
+
+    /// Whether the usage is of a buffer rather than an image, and so doesn't depend on tiling.
+    pub fn is_buffer(&self) -> bool {
+        matches!(*self, FormatUsage::VertexBuffer | FormatUsage::UniformTexelBuffer | FormatUsage::StorageTexelBuffer)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatSupport {
+    pub format: Format,
+    pub properties: FormatProperties,
+}
+
+impl FormatSupport {
+    pub fn query<I: InstanceV1_0>(instance: &I, physical_device: PhysicalDevice, format: Format) -> FormatSupport {
+        FormatSupport {
+            format: format,
+            properties: instance.get_physical_device_format_properties(physical_device, format),
+        }
+    }
+
+    fn features(&self, tiling: ImageTiling, usage: FormatUsage) -> FormatFeatureFlags {
+        if usage.is_buffer() {
+            return self.properties.buffer_features;
+        }
+        match tiling {
+            ImageTiling::Optimal => self.properties.optimal_tiling_features,
+            ImageTiling::Linear => self.properties.linear_tiling_features,
+        }
+    }
+
+    /// Whether images with `tiling` (ignored for buffer usages) support `usage`.
+    pub fn supports(&self, tiling: ImageTiling, usage: FormatUsage) -> bool {
+        self.features(tiling, usage).subset(usage.features())
+    }
+
+    pub fn supports_all(&self, tiling: ImageTiling, usages: &[FormatUsage]) -> bool {
+        usages.iter().all(|&usage| self.supports(tiling, usage))
+    }
+
+    /// Every usage images with `tiling`, or buffers, support, e.g. to say why a demo needing one
+    /// isn't available.
+    pub fn usages(&self, tiling: ImageTiling) -> Vec<FormatUsage> {
+        FormatUsage::ALL.iter().cloned().filter(|&usage| self.supports(tiling, usage)).collect()
+    }
+
+    /// Whether mips can be generated by blitting each level from the one above it with linear
+    /// filtering, which also needs the format to be sampled with linear filtering.
+    pub fn supports_blit_mip_generation(&self) -> bool {
+        self.supports_all(ImageTiling::Optimal, &[FormatUsage::BlitSrc, FormatUsage::BlitDst, FormatUsage::SampledLinear])
+    }
+}
+
+/// The first of `candidates` supporting every one of `usages` with optimal tiling. Compressed
+/// texture loaders pass their encodings in order of preference, e.g. BC7 before BC1.
+pub fn first_supported<I: InstanceV1_0>(instance: &I, physical_device: PhysicalDevice, candidates: &[Format], usages: &[FormatUsage]) -> Option<Format> {
+    candidates.iter()
+        .map(|&format| FormatSupport::query(instance, physical_device, format))
+        .find(|support| support.supports_all(ImageTiling::Optimal, usages))
+        .map(|support| support.format)
+}
+
+/// The preferred depth format that can be rendered to and, when `sampled`, read back in shaders
+/// like shadow maps do. With `stencil`, only formats with a stencil aspect are considered.
+pub fn depth_format<I: InstanceV1_0>(instance: &I, physical_device: PhysicalDevice, stencil: bool, sampled: bool) -> Option<Format> {
+    let candidates: Vec<Format> = DEPTH_FORMATS.iter()
+        .cloned()
+        .filter(|&format| !stencil || has_stencil(format))
+        .collect();
+    let usages: &[FormatUsage] = if sampled {
+        &[FormatUsage::DepthStencilAttachment, FormatUsage::Sampled]
+    } else {
+        &[FormatUsage::DepthStencilAttachment]
+    };
+    let format = first_supported(instance, physical_device, &candidates, usages);
+    debug!("Using depth format {:?} (stencil: {}, sampled: {})", format, stencil, sampled);
+    format
+}
+
+pub fn has_stencil(format: Format) -> bool {
+    matches!(format, Format::S8Uint | Format::D16UnormS8Uint | Format::D24UnormS8Uint | Format::D32SfloatS8Uint)
+}
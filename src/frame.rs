@@ -0,0 +1,296 @@
+//! Per-frame state for the render loop. `FrameLoop` owns what each frame in flight needs (a
+//! fence, the acquire and render semaphores, command pools, and a transient descriptor allocator)
+//! and waits for a slot's previous frame before handing it out again. Recording goes through a
+//! `FrameContext`, which bundles everything a demo may touch during one frame, so demos take the
+//! context rather than reaching into the loop's locals.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::collections::VecDeque;
+use std::ptr;
+use vk::types::*;
+use ::command::{ CommandRecorder, RenderTarget };
+use ::command_pools::CommandPoolManager;
+use ::descriptor::DescriptorAllocator;
+use ::queue::QueueFamilyIndex;
+use ::safe_create::{ self, Owned };
+use ::safe_info::{ self, SubmitInfoSafe };
+use ::time::Time;
+use ::timeline::{ TimelineSemaphore, TimelineSemaphoreSubmitInfo };
+
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// Pool sizes for each frame's transient descriptor allocator.
+const TRANSIENT_POOL_SIZES: [DescriptorPoolSize; 4] = [
+    DescriptorPoolSize { typ: DescriptorType::UniformBuffer, descriptor_count: 64 },
+    DescriptorPoolSize { typ: DescriptorType::StorageBuffer, descriptor_count: 64 },
+    DescriptorPoolSize { typ: DescriptorType::CombinedImageSampler, descriptor_count: 64 },
+    DescriptorPoolSize { typ: DescriptorType::StorageImage, descriptor_count: 16 },
+];
+const TRANSIENT_SETS_PER_POOL: u32 = 64;
+
+/// Destruction deferred until the GPU is done with the frame that last used a resource.
+pub struct DeletionQueue<'d> {
+    pending: VecDeque<(u64, Box<dyn FnMut() + 'd>)>,
+}
+
+impl<'d> DeletionQueue<'d> {
+    pub fn new() -> DeletionQueue<'d> {
+        DeletionQueue {
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Runs `destroy` once frame `frame_index` has finished on the GPU.
+    pub fn defer<F: FnOnce() + 'd>(&mut self, frame_index: u64, destroy: F) {
+        let mut destroy = Some(destroy);
+        self.pending.push_back((frame_index, Box::new(move || {
+            if let Some(destroy) = destroy.take() {
+                destroy();
+            }
+        })));
+    }
+
+    /// Runs everything deferred up to and including `completed_frame`.
+    pub fn collect(&mut self, completed_frame: u64) {
+        // Frames are deferred in order, so everything due is at the front
+        while self.pending.front().map(|&(frame, _)| frame <= completed_frame).unwrap_or(false) {
+            let (_, mut destroy) = self.pending.pop_front().unwrap();
+            destroy();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl<'d> Drop for DeletionQueue<'d> {
+    fn drop(&mut self) {
+        trace!("Running {} deferred deletions", self.pending.len());
+        self.collect(u64::MAX);
+    }
+}
+
+struct FrameSlot<'d, D: DeviceV1_0 + 'd> {
+    fence: Owned<'d, D, Fence>,
+    /// Signaled by work submitted ahead of the acquire, see `submit_early`.
+    early_fence: Owned<'d, D, Fence>,
+    image_available: Owned<'d, D, Semaphore>,
+    render_finished: Owned<'d, D, Semaphore>,
+    /// The frame last submitted from this slot, if any.
+    submitted: Option<u64>,
+    early_submitted: Option<u64>,
+}
+
+pub struct FrameLoop<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    slots: Vec<FrameSlot<'d, D>>,
+    command_pools: CommandPoolManager<'d, D>,
+    descriptors: Vec<DescriptorAllocator<'d, D>>,
+    deletion_queue: DeletionQueue<'d>,
+    frame_index: u64,
+    /// Signaled with `frame_index + 1` as each frame finishes, when the device has timelines.
+    timeline: Option<TimelineSemaphore<'d, D>>,
+}
+
+impl<'d, D: DeviceV1_0> FrameLoop<'d, D> {
+    pub fn new(device: &'d D, queue_family_index: QueueFamilyIndex, frames_in_flight: usize) -> VkResult<FrameLoop<'d, D>> {
+        let mut ret = FrameLoop {
+            device: device,
+            slots: Vec::with_capacity(frames_in_flight),
+            command_pools: CommandPoolManager::new(device, queue_family_index, frames_in_flight),
+            descriptors: (0..frames_in_flight).map(|_| DescriptorAllocator::new(device, &TRANSIENT_POOL_SIZES, TRANSIENT_SETS_PER_POOL)).collect(),
+            deletion_queue: DeletionQueue::new(),
+            frame_index: 0,
+            timeline: None,
+        };
+        let semaphore_create_info = SemaphoreCreateInfo {
+            s_type: StructureType::SemaphoreCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+        };
+        let fence_create_info = FenceCreateInfo {
+            s_type: StructureType::FenceCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+        };
+        for _ in 0..frames_in_flight {
+            ret.slots.push(FrameSlot {
+                fence: safe_create::create_fence_safe(device, &fence_create_info, None)?,
+                early_fence: safe_create::create_fence_safe(device, &fence_create_info, None)?,
+                image_available: safe_create::create_semaphore_safe(device, &semaphore_create_info, None)?,
+                render_finished: safe_create::create_semaphore_safe(device, &semaphore_create_info, None)?,
+                submitted: None,
+                early_submitted: None,
+            });
+        }
+        debug!("Created frame loop with {} frames in flight", frames_in_flight);
+        Ok(ret)
+    }
+
+    /// Also signals `timeline`, which has to start at 0, with every frame submitted, so how far
+    /// the GPU has got can be read without waiting on anything.
+    pub fn with_timeline(mut self, timeline: TimelineSemaphore<'d, D>) -> FrameLoop<'d, D> {
+        self.timeline = Some(timeline);
+        self
+    }
+
+    /// The frame-in-flight slot the current frame records into, for per-frame resources kept
+    /// outside the loop.
+    pub fn slot_index(&self) -> usize {
+        (self.frame_index % self.slots.len() as u64) as usize
+    }
+
+    /// How many frames have been submitted, which is also the value the last one signals the
+    /// timeline with.
+    pub fn submitted_frames(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// What each slot last submitted, for diagnosing a frame that doesn't finish.
+    pub fn describe_submissions(&self) -> String {
+        let slots: Vec<String> = self.slots.iter().enumerate().map(|(index, slot)| {
+            let frame = slot.submitted.map(|frame| format!("frame {}", frame)).unwrap_or("nothing".to_string());
+            match slot.early_submitted {
+                Some(early) => format!("slot {}: {}, early work of frame {}", index, frame, early),
+                None => format!("slot {}: {}", index, frame),
+            }
+        }).collect();
+        let completed = match self.timeline.as_ref().map(|timeline| timeline.value()) {
+            Some(Ok(0)) => "; the GPU hasn't finished a frame".to_string(),
+            Some(Ok(value)) => format!("; the GPU finished frame {}", value - 1),
+            Some(Err(e)) => format!("; the timeline couldn't be read: {:?}", e),
+            None => String::new(),
+        };
+        format!("{}; recording frame {}, {} deletions pending{}", slots.join("; "), self.frame_index, self.deletion_queue.len(), completed)
+    }
+
+    /// Waits until the current slot's previous frame is done on the GPU, then recycles its
+    /// command buffers and descriptor sets and runs the deletions it was holding up. A frame
+    /// that's given up before `submit`, e.g. because no image could be acquired, just calls
+    /// `begin` again for the next one.
+    pub fn begin(&mut self) -> VkResult<()> {
+        let index = self.slot_index();
+        if let Some(submitted) = self.slots[index].submitted {
+            (unsafe { self.device.wait_for_fences(&[*self.slots[index].fence], true, u64::MAX) })?;
+            self.deletion_queue.collect(submitted);
+        }
+        if self.slots[index].early_submitted.take().is_some() {
+            (unsafe { self.device.wait_for_fences(&[*self.slots[index].early_fence], true, u64::MAX) })?;
+        }
+        self.command_pools.begin_frame(index)?;
+        self.descriptors[index].reset()
+    }
+
+    /// The semaphore to acquire this frame's swapchain image with.
+    pub fn image_available(&self) -> Semaphore {
+        *self.slots[self.slot_index()].image_available
+    }
+
+    /// Starts recording the frame into a fresh command buffer. Must come after `begin`.
+    pub fn context<'a>(&'a mut self, image_index: u32, time: &'a Time, target: RenderTarget<'a>) -> VkResult<FrameContext<'a, 'd, D>> {
+        let index = self.slot_index();
+        let command_buffer = self.command_pools.allocate()?;
+        let recorder = unsafe { CommandRecorder::begin(self.device, command_buffer, COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT) }?;
+        Ok(FrameContext {
+            frame_index: self.frame_index,
+            slot: index,
+            image_index: image_index,
+            recorder: recorder,
+            descriptors: &mut self.descriptors[index],
+            deletion_queue: &mut self.deletion_queue,
+            time: time,
+            target: target,
+        })
+    }
+
+    /// Submits work that doesn't touch the swapchain image before it's acquired, e.g. rendering
+    /// offscreen. Waits on nothing and doesn't end the frame; `submit` still has to follow, with
+    /// a command buffer from a second `context`.
+    pub fn submit_early(&mut self, queue: Queue, command_buffer: CommandBuffer) -> VkResult<()> {
+        let index = self.slot_index();
+        let fence = *self.slots[index].early_fence;
+        let submit_info = SubmitInfoSafe::new()
+            .command_buffer(command_buffer);
+        unsafe {
+            self.device.reset_fences(&[fence])?;
+            safe_info::queue_submit_safe(self.device, queue, &[submit_info], fence)?;
+        }
+        self.slots[index].early_submitted = Some(self.frame_index);
+        Ok(())
+    }
+
+    /// Submits the frame's command buffer, waiting on `image_available` at `wait_stage`, and
+    /// moves on to the next frame. Returns the semaphore the present has to wait on.
+    pub fn submit(&mut self, queue: Queue, command_buffer: CommandBuffer, wait_stage: PipelineStageFlags) -> VkResult<Semaphore> {
+        let index = self.slot_index();
+        let (fence, wait_semaphore, signal_semaphore) = {
+            let slot = &self.slots[index];
+            (*slot.fence, *slot.image_available, *slot.render_finished)
+        };
+        let submit_info = SubmitInfoSafe::new()
+            .wait(wait_semaphore, wait_stage)
+            .command_buffer(command_buffer)
+            .signal(signal_semaphore);
+        // The binary semaphores' values are ignored, but every semaphore needs one
+        let wait_values = [0];
+        let signal_values = [0, self.frame_index + 1];
+        let mut timeline_info = TimelineSemaphoreSubmitInfo::new(&wait_values, &signal_values);
+        let submit_info = match self.timeline {
+            Some(ref timeline) => submit_info
+                .signal(timeline.handle())
+                .push_next(&mut timeline_info),
+            None => submit_info,
+        };
+        unsafe {
+            self.device.reset_fences(&[fence])?;
+            safe_info::queue_submit_safe(self.device, queue, &[submit_info], fence)?;
+        }
+        self.slots[index].submitted = Some(self.frame_index);
+        self.frame_index += 1;
+        Ok(signal_semaphore)
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for FrameLoop<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            let fences: Vec<Fence> = self.slots.iter()
+                .flat_map(|slot| slot.submitted.map(|_| *slot.fence).into_iter().chain(slot.early_submitted.map(|_| *slot.early_fence)))
+                .collect();
+            // The slots' fences and semaphores are destroyed after this, once nothing uses them
+            if !fences.is_empty() {
+                let _ = self.device.wait_for_fences(&fences, true, u64::MAX);
+            }
+        }
+    }
+}
+
+/// Everything a demo gets to record one frame.
+pub struct FrameContext<'a, 'd: 'a, D: DeviceV1_0 + 'd> {
+    /// Counts every submitted frame, unlike `time.frame()` which also counts skipped ones.
+    pub frame_index: u64,
+    /// Which frame-in-flight slot this frame uses, for indexing per-frame resources.
+    pub slot: usize,
+    /// Of the swapchain image `target` renders to.
+    pub image_index: u32,
+    pub recorder: CommandRecorder<'a, D>,
+    /// Descriptor sets that only live for this frame.
+    pub descriptors: &'a mut DescriptorAllocator<'d, D>,
+    pub deletion_queue: &'a mut DeletionQueue<'d>,
+    pub time: &'a Time,
+    /// The swapchain image's framebuffer.
+    pub target: RenderTarget<'a>,
+}
+
+impl<'a, 'd, D: DeviceV1_0> FrameContext<'a, 'd, D> {
+    /// Destroys something with `destroy` once the GPU is done with this frame.
+    pub fn defer_destroy<F: FnOnce() + 'd>(&mut self, destroy: F) {
+        self.deletion_queue.defer(self.frame_index, destroy);
+    }
+
+    /// Finishes recording, giving back the command buffer for `FrameLoop::submit`.
+    pub fn end(self) -> VkResult<CommandBuffer> {
+        self.recorder.end()
+    }
+}
@@ -0,0 +1,262 @@
+//! Frame diffing, for seeing what a change of render path does to the image. With
+//! `--frame-diff=<variant>,<variant>` the renderer draws the first frame after the scene loads
+//! once with each variant, diffs them on the GPU with `shaders/frame_diff.glsl`, writes both
+//! frames and the difference to `frame_diffs/<unix time>/` as PNGs, and exits.
+//!
+//! A variant is `native`, or a render scale with an optional upscale filter, e.g. `0.5:nearest`
+//! (see `upscale`). Plain `--frame-diff` compares native rendering with half resolution.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::fs::{ self, File };
+use std::io::{ self, Write };
+use std::path::PathBuf;
+use std::ptr;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use vk::types::*;
+use ::compute::{ self, ComputeKernel, StorageBuffer };
+use ::cvar::Flag;
+use ::descriptor::{ self, DescriptorAllocator };
+use ::image::ImageData;
+use ::math::prelude::div_ceil;
+use ::png;
+use ::upscale::{ UpscaleFilter, Upscaler };
+use ::vk_mem::MemoryAllocator;
+
+pub const ARG: &'static str = "--frame-diff";
+/// `ARG` alone compares native and half resolution; `<ARG>=<a>,<b>` picks the variants.
+pub const CVAR: &'static str = "frame_diff.enabled";
+pub const VARIANTS_CVAR: &'static str = "frame_diff.variants";
+pub const FLAGS: &'static [Flag] = &[
+    Flag::Switch { arg: ARG, name: CVAR },
+    Flag::Value { prefix: "--frame-diff=", name: VARIANTS_CVAR },
+];
+pub const SHADER: &'static str = "shaders/frame_diff.comp.spv";
+pub const OUTPUT_DIR: &'static str = "frame_diffs";
+const LOCAL_SIZE: u32 = 64;
+
+const FIRST_BINDING: u32 = 0;
+const SECOND_BINDING: u32 = 1;
+const DIFFERENCE_BINDING: u32 = 2;
+const STATS_BINDING: u32 = 3;
+
+fn parse_variant(text: &str) -> std::result::Result<Upscaler, String> {
+    if text == "native" {
+        return Ok(Upscaler::new(1.0));
+    }
+    let mut parts = text.splitn(2, ':');
+    let scale = (parts.next().unwrap().parse::<f32>().map_err(|e| format!("invalid render scale in {:?}: {}", text, e)))?;
+    let mut ret = Upscaler::new(scale);
+    if let Some(filter) = parts.next() {
+        ret.filter = match filter {
+            "nearest" => UpscaleFilter::Nearest,
+            "bilinear" => UpscaleFilter::Bilinear,
+            "sharpened" => UpscaleFilter::Sharpened,
+            _ => return Err(format!("unknown upscale filter {:?}", filter)),
+        };
+    }
+    Ok(ret)
+}
+
+/// The two variants to compare, from the command line.
+#[derive(Debug, Clone)]
+pub struct FrameDiffConfig {
+    pub variants: [Upscaler; 2],
+}
+
+impl FrameDiffConfig {
+    /// `None` without `frame_diff.enabled` or `frame_diff.variants`, or if the variants can't be
+    /// parsed.
+    pub fn from_cvars() -> Option<FrameDiffConfig> {
+        let variants: String = cvar!(VARIANTS_CVAR, String::new()).get();
+        if variants.is_empty() {
+            return if cvar!(CVAR, false).get() {
+                Some(FrameDiffConfig {
+                    variants: [Upscaler::new(1.0), Upscaler::new(0.5)],
+                })
+            } else {
+                None
+            };
+        }
+        let parsed: std::result::Result<Vec<Upscaler>, String> = variants.split(',').map(parse_variant).collect();
+        match parsed {
+            Ok(ref parsed) if parsed.len() == 2 => Some(FrameDiffConfig {
+                variants: [parsed[0].clone(), parsed[1].clone()],
+            }),
+            Ok(parsed) => {
+                warn!("Frame diffs compare 2 variants, not {}", parsed.len());
+                None
+            },
+            Err(e) => {
+                warn!("Invalid frame diff {:?}: {}", variants, e);
+                None
+            },
+        }
+    }
+}
+
+/// Whether frames in `format` can be diffed: any 4 byte, 8 bits per channel color format.
+pub fn supports_format(format: Format) -> bool {
+    matches!(format, Format::R8g8b8a8Unorm | Format::R8g8b8a8Srgb | Format::B8g8r8a8Unorm | Format::B8g8r8a8Srgb)
+}
+
+/// Push constant block read by `shaders/frame_diff.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct FrameDiffPushConstants {
+    count: u32,
+    amplify: f32,
+}
+
+/// Read back results, as texels in the frames' format.
+#[derive(Debug, Clone)]
+pub struct FrameDiff {
+    pub format: Format,
+    pub extent: Extent2D,
+    pub frames: [Vec<u32>; 2],
+    pub difference: Vec<u32>,
+    /// Pixels whose color differs at all.
+    pub differing: u32,
+    /// Of any channel of any pixel, out of 255.
+    pub max_difference: u32,
+}
+
+impl FrameDiff {
+    fn image_data(&self, texels: &[u32]) -> ImageData {
+        let swap_red_blue = matches!(self.format, Format::B8g8r8a8Unorm | Format::B8g8r8a8Srgb);
+        let mut pixels = Vec::with_capacity(texels.len() * 4);
+        for &texel in texels.iter() {
+            let (c0, c1, c2, a) = (texel as u8, (texel >> 8) as u8, (texel >> 16) as u8, (texel >> 24) as u8);
+            if swap_red_blue {
+                pixels.extend_from_slice(&[c2, c1, c0, a]);
+            } else {
+                pixels.extend_from_slice(&[c0, c1, c2, a]);
+            }
+        }
+        ImageData {
+            width: self.extent.width,
+            height: self.extent.height,
+            pixels: pixels,
+        }
+    }
+
+    /// Writes `first.png`, `second.png` and `difference.png` into a new directory, returning it.
+    pub fn write(&self) -> io::Result<PathBuf> {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(std::time::Duration::from_secs(0));
+        let dir = PathBuf::from(OUTPUT_DIR).join(format!("{}.{:03}", since_epoch.as_secs(), since_epoch.subsec_millis()));
+        fs::create_dir_all(&dir)?;
+        for &(name, texels) in [("first.png", &self.frames[0]), ("second.png", &self.frames[1]), ("difference.png", &self.difference)].iter() {
+            let mut file = File::create(dir.join(name))?;
+            file.write_all(&png::encode(&self.image_data(texels)))?;
+        }
+        Ok(dir)
+    }
+}
+
+/// The diff kernel with buffers for two frames of one size.
+pub struct FrameDiffer<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    kernel: ComputeKernel<'d, D>,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_set: DescriptorSet,
+    frames: [StorageBuffer<'d, D>; 2],
+    difference: StorageBuffer<'d, D>,
+    stats: StorageBuffer<'d, D>,
+    format: Format,
+    extent: Extent2D,
+}
+
+impl<'d, D: DeviceV1_0> FrameDiffer<'d, D> {
+    /// `spirv` is `SHADER`, and `format` one for which `supports_format` holds.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, spirv: &[u8], format: Format, extent: Extent2D) -> VkResult<FrameDiffer<'d, D>> {
+        assert!(supports_format(format));
+        let size = (extent.width * extent.height * 4) as DeviceSize;
+        let kernel = ComputeKernel::new(device, spirv, &compute::storage_buffer_bindings(4), std::mem::size_of::<FrameDiffPushConstants>() as u32)?;
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: 4,
+        }];
+        let mut ret = FrameDiffer {
+            device: device,
+            kernel: kernel,
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, 1),
+            descriptor_set: DescriptorSet::null(),
+            frames: [StorageBuffer::new(device, allocator, size)?, StorageBuffer::new(device, allocator, size)?],
+            difference: StorageBuffer::new(device, allocator, size)?,
+            stats: StorageBuffer::with_data(device, allocator, &[0u32; 2])?,
+            format: format,
+            extent: extent,
+        };
+        ret.descriptor_set = ret.descriptor_allocator.allocate(ret.kernel.set_layout())?;
+        descriptor::update_descriptor_set(device, ret.descriptor_set, &[
+            ret.frames[0].descriptor_write(FIRST_BINDING),
+            ret.frames[1].descriptor_write(SECOND_BINDING),
+            ret.difference.descriptor_write(DIFFERENCE_BINDING),
+            ret.stats.descriptor_write(STATS_BINDING),
+        ]);
+        Ok(ret)
+    }
+
+    /// Records copying the two frames out of `images`, which must be in `TransferSrcOptimal`
+    /// with their rendering finished, and diffing them. Differences are scaled by `amplify`.
+    pub unsafe fn cmd_diff(&self, command_buffer: CommandBuffer, images: [Image; 2], amplify: f32) {
+        let region = BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: ImageSubresourceLayers {
+                aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: Offset3D {
+                x: 0,
+                y: 0,
+                z: 0,
+            },
+            image_extent: Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            },
+        };
+        for (&image, frame) in images.iter().zip(self.frames.iter()) {
+            self.device.cmd_copy_image_to_buffer(command_buffer, image, ImageLayout::TransferSrcOptimal, frame.buffer(), std::slice::from_ref(&region));
+        }
+        let to_compute = MemoryBarrier {
+            s_type: StructureType::MemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[to_compute], &[], &[]);
+        let count = self.extent.width * self.extent.height;
+        let push_constants = FrameDiffPushConstants {
+            count: count,
+            amplify: amplify,
+        };
+        self.kernel.cmd_dispatch(command_buffer, self.descriptor_set, &push_constants, (div_ceil(count, LOCAL_SIZE), 1, 1));
+        let to_host = MemoryBarrier {
+            s_type: StructureType::MemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_TRANSFER_WRITE_BIT | ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_HOST_READ_BIT,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT | PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_HOST_BIT, Default::default(), &[to_host], &[], &[]);
+    }
+
+    /// The results of `cmd_diff`, once it's finished executing.
+    pub fn read(&self) -> FrameDiff {
+        let count = (self.extent.width * self.extent.height) as usize;
+        let stats: Vec<u32> = self.stats.read(2);
+        FrameDiff {
+            format: self.format,
+            extent: self.extent.clone(),
+            frames: [self.frames[0].read(count), self.frames[1].read(count)],
+            difference: self.difference.read(count),
+            differing: stats[0],
+            max_difference: stats[1],
+        }
+    }
+}
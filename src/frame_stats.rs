@@ -0,0 +1,159 @@
+//! Per-frame outcomes and the policy for frames that couldn't acquire an image. Acquiring waits a
+//! bounded time rather than forever, since some compositors can hold on to every image
+//! indefinitely; a frame that times out is skipped and acquiring is retried on the next one, and
+//! the skips are counted here instead of disappearing.
+use ash::prelude::VkResult;
+use std::time::Duration;
+use vk::types::*;
+use ::safe_ext::{ AcquireOutcome, PresentStatus };
+
+#[derive(Debug, Clone, Copy)]
+pub struct AcquirePolicy {
+    /// How long one acquire may wait for an image.
+    pub timeout: Duration,
+    /// Frames in a row that may be skipped before acquiring is considered hung.
+    pub max_consecutive_skips: u32,
+}
+
+impl Default for AcquirePolicy {
+    fn default() -> AcquirePolicy {
+        AcquirePolicy {
+            timeout: Duration::from_millis(100),
+            max_consecutive_skips: 50,
+        }
+    }
+}
+
+impl AcquirePolicy {
+    pub fn timeout_nanos(&self) -> u64 {
+        self.timeout.as_secs() * 1000000000 + self.timeout.subsec_nanos() as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOutcome {
+    Presented,
+    /// Presented, but the swapchain should be recreated.
+    Suboptimal,
+    /// Not presented, and the swapchain must be recreated.
+    OutOfDate,
+    /// Skipped because no image was ready.
+    SkippedNotReady,
+    /// Skipped because acquiring timed out.
+    SkippedTimeout,
+}
+
+impl FrameOutcome {
+    /// The outcome of a frame that ended at acquiring, or `None` if it got an image.
+    pub fn from_acquire(outcome: AcquireOutcome) -> Option<FrameOutcome> {
+        match outcome {
+            AcquireOutcome::Acquired(_) | AcquireOutcome::Suboptimal(_) => None,
+            AcquireOutcome::NotReady => Some(FrameOutcome::SkippedNotReady),
+            AcquireOutcome::Timeout => Some(FrameOutcome::SkippedTimeout),
+            AcquireOutcome::OutOfDate => Some(FrameOutcome::OutOfDate),
+        }
+    }
+
+    pub fn from_present(status: PresentStatus) -> FrameOutcome {
+        match status {
+            PresentStatus::Optimal => FrameOutcome::Presented,
+            PresentStatus::Suboptimal => FrameOutcome::Suboptimal,
+            PresentStatus::OutOfDate => FrameOutcome::OutOfDate,
+        }
+    }
+
+    pub fn needs_recreation(&self) -> bool {
+        matches!(*self, FrameOutcome::Suboptimal | FrameOutcome::OutOfDate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub presented: u64,
+    pub suboptimal: u64,
+    pub out_of_date: u64,
+    pub skipped_not_ready: u64,
+    pub skipped_timeout: u64,
+    consecutive_skips: u32,
+}
+
+impl FrameStats {
+    /// Counts `outcome`. Fails with `Timeout` once more frames in a row were skipped than
+    /// `policy` allows, so a hung presentation engine ends in an error rather than a frozen window.
+    pub fn record(&mut self, outcome: FrameOutcome, policy: &AcquirePolicy) -> VkResult<()> {
+        match outcome {
+            FrameOutcome::Presented => self.presented += 1,
+            FrameOutcome::Suboptimal => {
+                self.presented += 1;
+                self.suboptimal += 1;
+            },
+            FrameOutcome::OutOfDate => self.out_of_date += 1,
+            FrameOutcome::SkippedNotReady => self.skipped_not_ready += 1,
+            FrameOutcome::SkippedTimeout => self.skipped_timeout += 1,
+        }
+        match outcome {
+            FrameOutcome::SkippedNotReady | FrameOutcome::SkippedTimeout => {
+                self.consecutive_skips += 1;
+                debug!("Skipped frame ({:?}), {} in a row", outcome, self.consecutive_skips);
+                if self.consecutive_skips > policy.max_consecutive_skips {
+                    error!("Couldn't acquire a swapchain image for {} frames in a row", self.consecutive_skips);
+                    return Err(Result::Timeout);
+                }
+            },
+            _ => self.consecutive_skips = 0,
+        }
+        Ok(())
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped_not_ready + self.skipped_timeout
+    }
+
+    pub fn log(&self) {
+        info!("Frames: {} presented ({} suboptimal), {} out of date, {} skipped ({} not ready, {} timed out)", self.presented, self.suboptimal, self.out_of_date, self.skipped(), self.skipped_not_ready, self.skipped_timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcomes_are_counted() {
+        let policy = AcquirePolicy::default();
+        let mut stats = FrameStats::default();
+        for &outcome in [FrameOutcome::Presented, FrameOutcome::Suboptimal, FrameOutcome::OutOfDate, FrameOutcome::SkippedNotReady, FrameOutcome::SkippedTimeout, FrameOutcome::SkippedTimeout].iter() {
+            stats.record(outcome, &policy).unwrap();
+        }
+        assert_eq!((stats.presented, stats.suboptimal, stats.out_of_date), (2, 1, 1));
+        assert_eq!((stats.skipped_not_ready, stats.skipped_timeout, stats.skipped()), (1, 2, 3));
+    }
+
+    #[test]
+    fn too_many_skips_in_a_row_time_out() {
+        let policy = AcquirePolicy {
+            timeout: Duration::from_millis(1),
+            max_consecutive_skips: 3,
+        };
+        let mut stats = FrameStats::default();
+        for _ in 0..3 {
+            stats.record(FrameOutcome::SkippedTimeout, &policy).unwrap();
+        }
+        // Any frame that gets an image starts the count over
+        stats.record(FrameOutcome::OutOfDate, &policy).unwrap();
+        for _ in 0..3 {
+            stats.record(FrameOutcome::SkippedNotReady, &policy).unwrap();
+        }
+        assert_eq!(stats.record(FrameOutcome::SkippedTimeout, &policy), Err(Result::Timeout));
+    }
+
+    #[test]
+    fn outcomes_from_acquire_and_present() {
+        assert_eq!(FrameOutcome::from_acquire(AcquireOutcome::Timeout), Some(FrameOutcome::SkippedTimeout));
+        assert_eq!(FrameOutcome::from_acquire(AcquireOutcome::OutOfDate), Some(FrameOutcome::OutOfDate));
+        assert_eq!(FrameOutcome::from_acquire(AcquireOutcome::Suboptimal(0)), None);
+        assert!(FrameOutcome::from_present(PresentStatus::Suboptimal).needs_recreation());
+        assert!(!FrameOutcome::from_present(PresentStatus::Optimal).needs_recreation());
+        assert_eq!(AcquirePolicy::default().timeout_nanos(), 100000000);
+    }
+}
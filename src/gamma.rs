@@ -0,0 +1,247 @@
+//! Gamma-correct output. Shading happens in linear space; the swapchain should be an sRGB format
+//! so the hardware encodes on write, and when the surface only offers UNORM formats the final
+//! pass (`shaders/gamma_fragment.glsl`) encodes manually instead. The surface format can be
+//! cycled at runtime (see `format_cycle`), so `GammaOutput` follows the swapchain's format, and
+//! `COMPARISON_KEY` splits the screen against the gamma-incorrect image either way. The pass is
+//! drawn by `output_filter::FilterPass` in place of the filter while no filter is on.
+//!
+//! Where the swapchain can be created with `MUTABLE_FORMAT_EXTENSIONS`, a UNORM surface format
+//! doesn't need the manual encode either: its images are viewed through the sRGB pair instead
+//! (see `view_format`).
+//!
+//! Textures need the same care on the way in: color data (albedo, emissive) is authored in sRGB
+//! and must be sampled through an sRGB format to come out linear, while data maps (normals,
+//! roughness, masks) are already linear and must use UNORM.
+use ash::version::DeviceV1_0;
+use glfw;
+use std;
+use std::marker::PhantomData;
+use std::ptr;
+use vk::types::*;
+use ::hdr::ColorSpace;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::pnext::ExtensionStruct;
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/gamma_fragment.frag.spv";
+
+/// Toggles the split-screen comparison against the gamma-incorrect image.
+pub const COMPARISON_KEY: glfw::Key = glfw::Key::Semicolon;
+
+/// Surface formats in order of preference when sRGB output is wanted.
+const SRGB_SURFACE_FORMATS: [Format; 2] = [Format::B8g8r8a8Srgb, Format::R8g8b8a8Srgb];
+/// Fallbacks that need manual gamma, and the preference when sRGB output isn't wanted.
+const UNORM_SURFACE_FORMATS: [Format; 2] = [Format::B8g8r8a8Unorm, Format::R8g8b8a8Unorm];
+
+/// What a swapchain needs enabled to be created with mutable formats, dependencies first.
+pub const MUTABLE_FORMAT_EXTENSIONS: [&'static str; 3] = ["VK_KHR_maintenance2", "VK_KHR_image_format_list", "VK_KHR_swapchain_mutable_format"];
+
+/// `VK_SWAPCHAIN_CREATE_MUTABLE_FORMAT_BIT_KHR`, which `ash` doesn't know.
+const SWAPCHAIN_CREATE_MUTABLE_FORMAT_BIT: u32 = 0x4;
+const STRUCTURE_TYPE_IMAGE_FORMAT_LIST_CREATE_INFO: u32 = 1000147000;
+
+/// The formats a mutable-format image may be viewed as.
+#[repr(C)]
+pub struct ImageFormatListCreateInfo<'a> {
+    s_type: u32,
+    p_next: *const c_void,
+    view_format_count: u32,
+    p_view_formats: *const Format,
+    phantom: PhantomData<&'a [Format]>,
+}
+
+unsafe impl<'a> ExtensionStruct for ImageFormatListCreateInfo<'a> {}
+
+impl<'a> ImageFormatListCreateInfo<'a> {
+    pub fn new(view_formats: &'a [Format]) -> ImageFormatListCreateInfo<'a> {
+        ImageFormatListCreateInfo {
+            s_type: STRUCTURE_TYPE_IMAGE_FORMAT_LIST_CREATE_INFO,
+            p_next: ptr::null(),
+            view_format_count: view_formats.len() as u32,
+            p_view_formats: view_formats.as_ptr(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+pub fn is_srgb(format: Format) -> bool {
+    srgb_pair(format).map(|(_, srgb)| srgb == format).unwrap_or(false)
+}
+
+/// The (UNORM, sRGB) variants of formats that have both.
+fn srgb_pair(format: Format) -> Option<(Format, Format)> {
+    let pairs = [
+        (Format::R8Unorm, Format::R8Srgb),
+        (Format::R8g8Unorm, Format::R8g8Srgb),
+        (Format::R8g8b8a8Unorm, Format::R8g8b8a8Srgb),
+        (Format::B8g8r8a8Unorm, Format::B8g8r8a8Srgb),
+        (Format::Bc1RgbUnormBlock, Format::Bc1RgbSrgbBlock),
+        (Format::Bc1RgbaUnormBlock, Format::Bc1RgbaSrgbBlock),
+        (Format::Bc2UnormBlock, Format::Bc2SrgbBlock),
+        (Format::Bc3UnormBlock, Format::Bc3SrgbBlock),
+        (Format::Bc7UnormBlock, Format::Bc7SrgbBlock),
+    ];
+    pairs.iter().find(|&&(unorm, srgb)| unorm == format || srgb == format).cloned()
+}
+
+/// The format to view swapchain images of `surface_format` through, and so the one render passes
+/// into them are made for. With `mutable_format`, i.e. when the swapchain can be created with
+/// `MUTABLE_FORMAT_EXTENSIONS`, an sRGB surface's UNORM format is viewed as its sRGB pair so the
+/// hardware still encodes. Anything else is viewed as is.
+pub fn view_format(surface_format: (Format, ColorSpace), mutable_format: bool) -> Format {
+    match srgb_pair(surface_format.0) {
+        Some((unorm, srgb)) if mutable_format && unorm == surface_format.0 && surface_format.1 == ColorSpace::SRGB_NONLINEAR => srgb,
+        _ => surface_format.0,
+    }
+}
+
+/// Lets the images of `create_info` be viewed in other formats than their own, which have to be
+/// listed with an `ImageFormatListCreateInfo` on its chain. Like `hdr::set_color_space`, do it
+/// right before creating the swapchain, since the flags are left holding a bit their type doesn't
+/// know.
+pub unsafe fn set_mutable_format(create_info: &mut SwapchainCreateInfoKHR) {
+    let flags = &mut create_info.flags as *mut SwapchainCreateFlagsKHR as *mut u32;
+    ptr::write(flags, ptr::read(flags) | SWAPCHAIN_CREATE_MUTABLE_FORMAT_BIT);
+}
+
+/// Picks a surface format, preferring sRGB ones when `prefer_srgb` is set. Falls back to a known
+/// UNORM format, then to whatever the surface lists first.
+pub fn choose_surface_format(formats: &[SurfaceFormatKHR], prefer_srgb: bool) -> Option<&SurfaceFormatKHR> {
+    let preferred: Vec<Format> = if prefer_srgb {
+        SRGB_SURFACE_FORMATS.iter().chain(UNORM_SURFACE_FORMATS.iter()).cloned().collect()
+    } else {
+        UNORM_SURFACE_FORMATS.to_vec()
+    };
+    preferred.iter()
+        .filter_map(|&format| formats.iter().find(|f| f.format == format && f.color_space == ColorSpaceKHR::SrgbNonlinear))
+        .next()
+        .or_else(|| formats.iter().next())
+}
+
+/// How a texture's texels should be interpreted when sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureEncoding {
+    /// Authored colors (albedo, emissive), decoded from sRGB by the sampler.
+    Color,
+    /// Linear data (normals, roughness/metalness, masks), sampled as is.
+    Data,
+}
+
+impl TextureEncoding {
+    /// The variant of `format` to create the texture with. Formats without an sRGB variant are
+    /// returned unchanged.
+    pub fn format(&self, format: Format) -> Format {
+        match srgb_pair(format) {
+            Some((unorm, srgb)) => match *self {
+                TextureEncoding::Color => srgb,
+                TextureEncoding::Data => unorm,
+            },
+            None => format,
+        }
+    }
+}
+
+/// Push constant block read by `shaders/gamma_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GammaPushConstants {
+    /// Non-zero when the shader has to encode to sRGB itself.
+    pub manual_encode: u32,
+    /// Pixels left of this fraction of the screen skip encoding, to compare against the
+    /// gamma-incorrect image. Negative disables the split.
+    pub split: f32,
+}
+
+impl GammaPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<GammaPushConstants>() as u32,
+        }
+    }
+}
+
+/// The final pass from the linear scene color into the swapchain image.
+#[derive(Debug, Clone)]
+pub struct GammaOutput {
+    manual_encode: bool,
+    pub show_comparison: bool,
+}
+
+impl GammaOutput {
+    pub fn new(swapchain_format: Format) -> GammaOutput {
+        let manual_encode = !is_srgb(swapchain_format);
+        if manual_encode {
+            warn!("Swapchain format {:?} isn't sRGB, falling back to manual gamma encoding", swapchain_format);
+        }
+        GammaOutput {
+            manual_encode: manual_encode,
+            show_comparison: false,
+        }
+    }
+
+    /// Whether the pass changes the image at all, i.e. whether it needs drawing.
+    pub fn is_active(&self) -> bool {
+        self.manual_encode || self.show_comparison
+    }
+
+    /// Follows the swapchain to `format`, keeping the comparison as it was.
+    pub fn set_swapchain_format(&mut self, format: Format) {
+        let show_comparison = self.show_comparison;
+        *self = GammaOutput::new(format);
+        self.show_comparison = show_comparison;
+    }
+
+    /// Toggles the comparison on `COMPARISON_KEY`. Returns whether the key was handled.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key != COMPARISON_KEY {
+            return false;
+        }
+        self.toggle_comparison();
+        true
+    }
+
+    pub fn toggle_comparison(&mut self) {
+        self.show_comparison = !self.show_comparison;
+        debug!("Gamma comparison: {}", self.show_comparison);
+    }
+
+    pub fn push_constants(&self) -> GammaPushConstants {
+        GammaPushConstants {
+            manual_encode: self.manual_encode as u32,
+            split: if self.show_comparison { 0.5 } else { -1.0 },
+        }
+    }
+
+    /// Records the full-screen pass, with the gamma pipeline and the scene color descriptor set
+    /// already bound.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let constants = self.push_constants();
+        let range = GammaPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const GammaPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl` into the swapchain image.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unorm_surfaces_are_viewed_as_srgb_only_when_mutable() {
+        let unorm = (Format::B8g8r8a8Unorm, ColorSpace::SRGB_NONLINEAR);
+        assert_eq!(view_format(unorm, true), Format::B8g8r8a8Srgb);
+        assert_eq!(view_format(unorm, false), Format::B8g8r8a8Unorm);
+        assert_eq!(view_format((Format::B8g8r8a8Srgb, ColorSpace::SRGB_NONLINEAR), true), Format::B8g8r8a8Srgb);
+        assert_eq!(view_format((Format::A2b10g10r10UnormPack32, ColorSpace::SRGB_NONLINEAR), true), Format::A2b10g10r10UnormPack32);
+        assert_eq!(view_format((Format::B8g8r8a8Unorm, ColorSpace::EXTENDED_SRGB_NONLINEAR), true), Format::B8g8r8a8Unorm);
+    }
+}
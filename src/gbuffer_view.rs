@@ -0,0 +1,416 @@
+//! Full-screen views of the intermediate render targets: linearized depth, world normals, albedo,
+//! roughness/metalness, and velocity. Render paths only fill in the targets they actually have,
+//! and the selector skips over views whose target is missing.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::command::RenderTarget;
+use ::image_view_cache::ImageViewCache;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::safe_create::{ self, FramebufferCreateInfoSafe, Owned, OwnedFramebuffer };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/gbuffer_view_fragment.frag.spv";
+
+/// Binding of the sampled target in `shaders/gbuffer_view_fragment.glsl`.
+pub const TARGET_BINDING: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferView {
+    /// The normally lit image, no debug view.
+    Final,
+    LinearDepth,
+    Normals,
+    Albedo,
+    RoughnessMetalness,
+    Velocity,
+}
+
+impl BufferView {
+    pub fn all() -> [BufferView; 6] {
+        [
+            BufferView::Final,
+            BufferView::LinearDepth,
+            BufferView::Normals,
+            BufferView::Albedo,
+            BufferView::RoughnessMetalness,
+            BufferView::Velocity,
+        ]
+    }
+
+    /// Value of `mode` in the shader's push constants.
+    fn mode(&self) -> u32 {
+        match *self {
+            BufferView::Final => 0,
+            BufferView::LinearDepth => 1,
+            BufferView::Normals => 2,
+            BufferView::Albedo => 3,
+            BufferView::RoughnessMetalness => 4,
+            BufferView::Velocity => 5,
+        }
+    }
+
+    /// The target this view reads, or `None` if the active render path doesn't have it.
+    /// `Final` never reads a target.
+    pub fn target(&self, targets: &GBufferTargets) -> Option<ImageView> {
+        match *self {
+            BufferView::Final => None,
+            BufferView::LinearDepth => targets.depth,
+            BufferView::Normals => targets.normals,
+            BufferView::Albedo => targets.albedo,
+            BufferView::RoughnessMetalness => targets.roughness_metalness,
+            BufferView::Velocity => targets.velocity,
+        }
+    }
+
+    fn is_available(&self, targets: &GBufferTargets) -> bool {
+        *self == BufferView::Final || self.target(targets).is_some()
+    }
+
+    /// The next view after this one that `targets` can provide, wrapping back to `Final`.
+    pub fn next(&self, targets: &GBufferTargets) -> BufferView {
+        let views = BufferView::all();
+        let start = views.iter().position(|v| v == self).unwrap();
+        (1..views.len())
+            .map(|offset| views[(start + offset) % views.len()])
+            .find(|v| v.is_available(targets))
+            .unwrap_or(BufferView::Final)
+    }
+
+    /// Layout the target is expected to be in while it's sampled.
+    fn image_layout(&self) -> ImageLayout {
+        match *self {
+            BufferView::LinearDepth => ImageLayout::DepthStencilReadOnlyOptimal,
+            _ => ImageLayout::ShaderReadOnlyOptimal,
+        }
+    }
+}
+
+/// Views of whichever intermediate targets the active render path writes. A forward path might
+/// only have `depth`, for instance.
+#[derive(Debug, Clone, Default)]
+pub struct GBufferTargets {
+    pub depth: Option<ImageView>,
+    pub normals: Option<ImageView>,
+    pub albedo: Option<ImageView>,
+    pub roughness_metalness: Option<ImageView>,
+    pub velocity: Option<ImageView>,
+}
+
+/// Push constant block read by `shaders/gbuffer_view_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GBufferViewPushConstants {
+    pub mode: u32,
+    /// Camera planes, needed to linearize depth.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl GBufferViewPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<GBufferViewPushConstants>() as u32,
+        }
+    }
+}
+
+pub fn descriptor_set_layout_binding() -> DescriptorSetLayoutBinding {
+    DescriptorSetLayoutBinding {
+        binding: TARGET_BINDING,
+        descriptor_type: DescriptorType::CombinedImageSampler,
+        descriptor_count: 1,
+        stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+        p_immutable_samplers: ptr::null(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GBufferViewer {
+    pub view: BufferView,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl GBufferViewer {
+    pub fn new(near: f32, far: f32) -> GBufferViewer {
+        GBufferViewer {
+            view: BufferView::Final,
+            near: near,
+            far: far,
+        }
+    }
+
+    pub fn cycle(&mut self, targets: &GBufferTargets) {
+        self.view = self.view.next(targets);
+        debug!("G-buffer view: {:?}", self.view);
+    }
+
+    /// Whether a debug view replaces the final image this frame. Falls back to the final image
+    /// if the selected target went away, e.g. after switching render paths.
+    pub fn is_active(&self, targets: &GBufferTargets) -> bool {
+        self.view.target(targets).is_some()
+    }
+
+    /// Points `descriptor_set` at the selected view's target. Must not be called while the set is
+    /// in use by a pending command buffer.
+    pub unsafe fn write_descriptor_set<D: DeviceV1_0>(&self, device: &D, descriptor_set: DescriptorSet, sampler: Sampler, targets: &GBufferTargets) {
+        let image_view = match self.view.target(targets) {
+            Some(view) => view,
+            None => return,
+        };
+        let image_info = DescriptorImageInfo {
+            sampler: sampler,
+            image_view: image_view,
+            image_layout: self.view.image_layout(),
+        };
+        let write = WriteDescriptorSet {
+            s_type: StructureType::WriteDescriptorSet,
+            p_next: ptr::null(),
+            dst_set: descriptor_set,
+            dst_binding: TARGET_BINDING,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            p_image_info: &image_info,
+            p_buffer_info: ptr::null(),
+            p_texel_buffer_view: ptr::null(),
+        };
+        device.update_descriptor_sets(&[write], &[]);
+    }
+
+    /// Records the full-screen view, with the view pipeline and descriptor set already bound.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout, targets: &GBufferTargets) {
+        if !self.is_active(targets) {
+            return;
+        }
+        let constants = GBufferViewPushConstants {
+            mode: self.view.mode(),
+            near: self.near,
+            far: self.far,
+        };
+        let range = GBufferViewPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const GBufferViewPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl` with no depth testing.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
+
+/// A builder for drawing only depth into a `DepthTarget`, with `vertex_shader` alone. Add the
+/// vertex input to match the shader.
+pub fn depth_pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .depth_test(true, CompareOp::Less)
+        .blend_attachments(&[])
+}
+
+/// A depth image for render paths without a G-buffer of their own, with a depth only render pass
+/// that leaves it in `DepthStencilReadOnlyOptimal`, as `BufferView::LinearDepth` samples it.
+pub struct DepthTarget<'d, D: DeviceV1_0 + 'd> {
+    // In the order they're destroyed
+    framebuffer: OwnedFramebuffer<'d, D>,
+    render_pass: Owned<'d, D, RenderPass>,
+    sampler: Owned<'d, D, Sampler>,
+    _views: ImageViewCache<'d, D>,
+    /// From `views`.
+    view: ImageView,
+    _image: Owned<'d, D, Image>,
+    _memory: VkAllocation<'d, D>,
+    extent: Extent2D,
+}
+
+impl<'d, D: DeviceV1_0> DepthTarget<'d, D> {
+    /// `format` must be a depth format that can be rendered to and sampled, see
+    /// `format_support::depth_format`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, extent: Extent2D) -> VkResult<DepthTarget<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: format,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT | IMAGE_USAGE_SAMPLED_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = safe_create::create_image_safe(device, &image_create_info, None)?;
+        let memory = allocator.allocate_image_memory(*image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let mut views = ImageViewCache::new(device);
+        let view = views.get(*image, ImageViewType::Type2d, format, &ImageSubresourceRange {
+            aspect_mask: IMAGE_ASPECT_DEPTH_BIT,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })?;
+
+        let attachment = AttachmentDescription {
+            flags: Default::default(),
+            format: format,
+            samples: SAMPLE_COUNT_1_BIT,
+            load_op: AttachmentLoadOp::Clear,
+            store_op: AttachmentStoreOp::Store,
+            stencil_load_op: AttachmentLoadOp::DontCare,
+            stencil_store_op: AttachmentStoreOp::DontCare,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: ImageLayout::DepthStencilReadOnlyOptimal,
+        };
+        let depth_attachment_ref = AttachmentReference {
+            attachment: 0,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+        };
+        let subpass = SubpassDescription {
+            flags: Default::default(),
+            pipeline_bind_point: PipelineBindPoint::Graphics,
+            input_attachment_count: 0,
+            p_input_attachments: ptr::null(),
+            color_attachment_count: 0,
+            p_color_attachments: ptr::null(),
+            p_resolve_attachments: ptr::null(),
+            p_depth_stencil_attachment: &depth_attachment_ref,
+            preserve_attachment_count: 0,
+            p_preserve_attachments: ptr::null(),
+        };
+        let dependencies = [
+            // Last frame's view, or a downsample, sampled the depth
+            SubpassDependency {
+                src_subpass: VK_SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PIPELINE_STAGE_FRAGMENT_SHADER_BIT | PIPELINE_STAGE_COMPUTE_SHADER_BIT,
+                src_access_mask: ACCESS_SHADER_READ_BIT,
+                dst_stage_mask: PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+                dst_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dependency_flags: Default::default(),
+            },
+            SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: VK_SUBPASS_EXTERNAL,
+                src_stage_mask: PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+                src_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dst_stage_mask: PIPELINE_STAGE_FRAGMENT_SHADER_BIT | PIPELINE_STAGE_COMPUTE_SHADER_BIT,
+                dst_access_mask: ACCESS_SHADER_READ_BIT,
+                dependency_flags: Default::default(),
+            },
+        ];
+        let render_pass_create_info = RenderPassCreateInfo {
+            s_type: StructureType::RenderPassCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            attachment_count: 1,
+            p_attachments: &attachment,
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+        };
+        let render_pass = safe_create::create_render_pass_safe(device, &render_pass_create_info, None)?;
+
+        let framebuffer_create_info = FramebufferCreateInfo {
+            s_type: StructureType::FramebufferCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            render_pass: RenderPass::null(),
+            attachment_count: 0,
+            p_attachments: ptr::null(),
+            width: extent.width,
+            height: extent.height,
+            layers: 1,
+        };
+        let framebuffer_create_info = unsafe { FramebufferCreateInfoSafe::from_handles(framebuffer_create_info, *render_pass, &[view]) };
+        let framebuffer = safe_create::create_framebuffer_safe(device, framebuffer_create_info, None)?;
+
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatOpaqueWhite,
+            unnormalized_coordinates: 0,
+        };
+        let sampler = safe_create::create_sampler_safe(device, &sampler_create_info, None)?;
+        debug!("Created {}x{} {:?} G-buffer depth target: {:?}", extent.width, extent.height, format, *image);
+        Ok(DepthTarget {
+            framebuffer: framebuffer,
+            render_pass: render_pass,
+            sampler: sampler,
+            _views: views,
+            view: view,
+            _image: image,
+            _memory: memory,
+            extent: extent,
+        })
+    }
+
+    pub fn render_pass(&self) -> RenderPass {
+        *self.render_pass
+    }
+
+    pub fn target<'a>(&'a self) -> RenderTarget<'a> {
+        RenderTarget {
+            render_pass: &self.render_pass,
+            framebuffer: &self.framebuffer,
+            extent: self.extent.clone(),
+        }
+    }
+
+    pub fn sampler(&self) -> Sampler {
+        *self.sampler
+    }
+
+    /// The depth, in `ImageLayout::DepthStencilReadOnlyOptimal` after the pass.
+    pub fn view(&self) -> ImageView {
+        self.view
+    }
+
+    /// Just `depth`, for paths that draw nothing else offscreen.
+    pub fn targets(&self) -> GBufferTargets {
+        GBufferTargets {
+            depth: Some(self.view),
+            ..Default::default()
+        }
+    }
+
+    /// Clears to the far plane.
+    pub fn clear_values() -> [ClearValue; 1] {
+        [ClearValue::new_depth_stencil(ClearDepthStencilValue {
+            depth: 1.0,
+            stencil: 0,
+        })]
+    }
+}
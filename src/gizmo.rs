@@ -0,0 +1,197 @@
+//! Translate and rotate gizmos for repositioning lights and objects with the mouse. Gizmos are
+//! drawn through `debug_draw`, and picked by casting the ray under the cursor against each handle.
+use std;
+use ::debug_draw::{ self, DebugDraw };
+use ::math::{ Mat4, Quat, Ray, Vec3 };
+
+/// Fraction of the gizmo size within which the cursor ray counts as hitting a handle.
+const PICK_TOLERANCE: f32 = 0.08;
+const RING_SEGMENTS: u32 = 48;
+
+/// Width in pixels to draw gizmos with through `DebugDrawBuffer::cmd_draw_thick`, since
+/// single-pixel handles are hard to see and to aim at.
+pub const LINE_WIDTH: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn all() -> [Axis; 3] {
+        [Axis::X, Axis::Y, Axis::Z]
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        match *self {
+            Axis::X => Vec3::unit_x(),
+            Axis::Y => Vec3::unit_y(),
+            Axis::Z => Vec3::unit_z(),
+        }
+    }
+
+    fn color(&self) -> [f32; 3] {
+        match *self {
+            Axis::X => debug_draw::RED,
+            Axis::Y => debug_draw::GREEN,
+            Axis::Z => debug_draw::BLUE,
+        }
+    }
+}
+
+/// Position and orientation of whatever the gizmo is attached to.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Transform {
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+impl Transform {
+    /// The model matrix, rotating and then translating.
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translation(self.position) * Mat4::rotation(self.rotation)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    axis: Axis,
+    start: Transform,
+    /// Distance along the axis for translation, or angle around it for rotation.
+    start_param: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    /// World-space length of the translate handles and radius of the rotate rings.
+    pub size: f32,
+    hovered: Option<Axis>,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn new(size: f32) -> Gizmo {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            size: size,
+            hovered: None,
+            drag: None,
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            GizmoMode::Translate => GizmoMode::Rotate,
+            GizmoMode::Rotate => GizmoMode::Translate,
+        };
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Returns the handle under `ray`, if any. The closest handle wins when several are hit.
+    pub fn pick(&self, ray: &Ray, transform: &Transform) -> Option<Axis> {
+        let tolerance = self.size * PICK_TOLERANCE;
+        let mut hits: Vec<(Axis, f32)> = Axis::all().iter()
+            .filter_map(|&axis| {
+                let direction = axis.direction();
+                match self.mode {
+                    GizmoMode::Translate => ray.closest_to_line(transform.position, direction)
+                        .and_then(|(t_ray, t_axis)| {
+                            if t_ray < 0.0 || t_axis < 0.0 || t_axis > self.size {
+                                return None;
+                            }
+                            let distance = (ray.at(t_ray) - (transform.position + direction * t_axis)).length();
+                            if distance < tolerance { Some((axis, distance)) } else { None }
+                        }),
+                    GizmoMode::Rotate => ray.intersect_plane(transform.position, direction)
+                        .and_then(|t| {
+                            let distance = ((ray.at(t) - transform.position).length() - self.size).abs();
+                            if distance < tolerance { Some((axis, distance)) } else { None }
+                        }),
+                }
+            })
+            .collect();
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        hits.first().map(|&(axis, _)| axis)
+    }
+
+    /// Updates the highlighted handle for the current cursor ray.
+    pub fn hover(&mut self, ray: &Ray, transform: &Transform) {
+        if self.drag.is_none() {
+            self.hovered = self.pick(ray, transform);
+        }
+    }
+
+    fn drag_param(&self, axis: Axis, ray: &Ray, origin: Vec3) -> Option<f32> {
+        let direction = axis.direction();
+        match self.mode {
+            GizmoMode::Translate => ray.closest_to_line(origin, direction).map(|(_, t_axis)| t_axis),
+            GizmoMode::Rotate => ray.intersect_plane(origin, direction).map(|t| {
+                let u = direction.any_perpendicular();
+                let v = direction.cross(u);
+                let offset = ray.at(t) - origin;
+                offset.dot(v).atan2(offset.dot(u))
+            }),
+        }
+    }
+
+    /// Starts dragging the handle under `ray`. Returns whether a handle was grabbed.
+    pub fn begin_drag(&mut self, ray: &Ray, transform: &Transform) -> bool {
+        let drag = self.pick(ray, transform).and_then(|axis| {
+            self.drag_param(axis, ray, transform.position).map(|param| Drag {
+                axis: axis,
+                start: *transform,
+                start_param: param,
+            })
+        });
+        self.drag = drag;
+        self.hovered = drag.map(|drag| drag.axis);
+        drag.is_some()
+    }
+
+    /// Applies the current drag to `transform`, relative to where the drag started.
+    pub fn update_drag(&mut self, ray: &Ray, transform: &mut Transform) {
+        let drag = match self.drag {
+            Some(drag) => drag,
+            None => return,
+        };
+        let param = match self.drag_param(drag.axis, ray, drag.start.position) {
+            Some(param) => param,
+            None => return,
+        };
+        let delta = param - drag.start_param;
+        match self.mode {
+            GizmoMode::Translate => {
+                transform.position = drag.start.position + drag.axis.direction() * delta;
+            },
+            GizmoMode::Rotate => {
+                transform.rotation = (Quat::from_axis_angle(drag.axis.direction(), delta) * drag.start.rotation).normalize();
+            },
+        }
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    pub fn draw(&self, transform: &Transform, lines: &mut DebugDraw) {
+        for &axis in Axis::all().iter() {
+            let color = if self.hovered == Some(axis) { debug_draw::YELLOW } else { axis.color() };
+            match self.mode {
+                GizmoMode::Translate => lines.arrow(transform.position, transform.position + axis.direction() * self.size, color),
+                GizmoMode::Rotate => lines.circle(transform.position, axis.direction(), self.size, color, RING_SEGMENTS),
+            }
+        }
+    }
+}
@@ -0,0 +1,669 @@
+//! glTF 2.0 loading, from `.gltf` files (with their buffers and images in separate files or
+//! `data:` URIs) or binary `.glb` ones. What's read:
+//!
+//! - Triangle primitives' positions, normals, first texture coordinates and indices. Normals are
+//!   generated if a primitive has none.
+//! - The node hierarchy of the default scene, with matrix or translation/rotation/scale
+//!   transforms.
+//! - Materials' base color factor and texture, with the texture's image decoded by `image`.
+//!
+//! Everything else, like skins, morph targets, animations, cameras, sparse accessors and the rest
+//! of the material, is skipped.
+use std;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use ::image::{ self, ImageData, ImageError };
+use ::json::{ self, Value };
+use ::math::{ Mat4, Quat, Vec3 };
+use ::math::prelude::div_ceil;
+use ::mesh::{ MeshData, MeshVertex };
+
+const GLB_MAGIC: &'static [u8] = b"glTF";
+const GLB_HEADER_SIZE: usize = 12;
+const GLB_CHUNK_JSON: u32 = 0x4e4f534a;
+const GLB_CHUNK_BIN: u32 = 0x004e4942;
+
+const MODE_TRIANGLES: usize = 4;
+
+const COMPONENT_UNSIGNED_BYTE: usize = 5121;
+const COMPONENT_UNSIGNED_SHORT: usize = 5123;
+const COMPONENT_UNSIGNED_INT: usize = 5125;
+const COMPONENT_FLOAT: usize = 5126;
+
+const DATA_URI_PREFIX: &'static str = "data:";
+const BASE64_MARKER: &'static str = ";base64,";
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io(io::Error),
+    Json(json::ParseError),
+    Image(ImageError),
+    /// Something the spec doesn't allow, or that points outside the file.
+    Invalid(String),
+    /// Valid, but using something this loader doesn't handle.
+    Unsupported(String),
+}
+
+impl From<io::Error> for GltfError {
+    fn from(e: io::Error) -> GltfError {
+        GltfError::Io(e)
+    }
+}
+
+impl From<json::ParseError> for GltfError {
+    fn from(e: json::ParseError) -> GltfError {
+        GltfError::Json(e)
+    }
+}
+
+impl From<ImageError> for GltfError {
+    fn from(e: ImageError) -> GltfError {
+        GltfError::Image(e)
+    }
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GltfError::Io(ref e) => write!(f, "{}", e),
+            GltfError::Json(ref e) => write!(f, "invalid JSON {}", e),
+            GltfError::Image(ref e) => write!(f, "image: {}", e),
+            GltfError::Invalid(ref what) => write!(f, "invalid: {}", what),
+            GltfError::Unsupported(ref what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+fn invalid<T, S: Into<String>>(what: S) -> std::result::Result<T, GltfError> {
+    Err(GltfError::Invalid(what.into()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    /// Linear RGBA, multiplying the texture.
+    pub base_color_factor: [f32; 4],
+    /// Index into `GltfScene::images`.
+    pub base_color_texture: Option<usize>,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Primitive {
+    pub mesh: MeshData,
+    /// Index into `GltfScene::materials`, or the default material if `None`.
+    pub material: Option<usize>,
+}
+
+/// A mesh placed in the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instance {
+    /// Index into `GltfScene::meshes`.
+    pub mesh: usize,
+    /// From the mesh into the scene.
+    pub transform: Mat4,
+}
+
+/// The parts of a glTF file the renderer can draw.
+#[derive(Debug, Clone, Default)]
+pub struct GltfScene {
+    /// The primitives of each glTF mesh.
+    pub meshes: Vec<Vec<Primitive>>,
+    pub instances: Vec<Instance>,
+    pub materials: Vec<Material>,
+    pub images: Vec<ImageData>,
+}
+
+impl GltfScene {
+    /// Every instance's triangles moved into the scene and merged into one mesh, for drawing with
+    /// one material.
+    pub fn flatten(&self) -> MeshData {
+        let mut ret = MeshData::default();
+        for instance in self.instances.iter() {
+            let normal_transform = instance.transform.inverse().map(|m| m.transpose()).unwrap_or(instance.transform);
+            for primitive in self.meshes[instance.mesh].iter() {
+                let base = ret.vertices.len() as u32;
+                ret.vertices.extend(primitive.mesh.vertices.iter().map(|v| {
+                    let normal = normal_transform.transform_vector(Vec3::new(v.normal[0], v.normal[1], v.normal[2]));
+                    MeshVertex {
+                        position: instance.transform.transform_point(Vec3::new(v.position[0], v.position[1], v.position[2])).to_array(),
+                        normal: if normal.length() > 0.0 { normal.normalize().to_array() } else { v.normal },
+                        uv: v.uv,
+                    }
+                }));
+                ret.indices.extend(primitive.mesh.indices.iter().map(|&idx| base + idx));
+            }
+        }
+        ret
+    }
+
+    /// The first base color texture any instance uses, if any.
+    pub fn base_color_image(&self) -> Option<&ImageData> {
+        self.instances.iter()
+            .flat_map(|instance| self.meshes[instance.mesh].iter())
+            .filter_map(|primitive| primitive.material.and_then(|material| self.materials[material].base_color_texture))
+            .next()
+            .map(|image| &self.images[image])
+    }
+}
+
+/// Whether `path` has the `.gltf` or `.glb` extension.
+pub fn is_gltf(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase()) {
+        Some(ref extension) => extension == "gltf" || extension == "glb",
+        None => false,
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data[offset] as u32 | (data[offset + 1] as u32) << 8 | (data[offset + 2] as u32) << 16 | (data[offset + 3] as u32) << 24
+}
+
+/// Splits a `.glb` file into its JSON and binary chunks.
+fn split_glb(data: &[u8]) -> std::result::Result<(&str, Option<&[u8]>), GltfError> {
+    if data.len() < GLB_HEADER_SIZE {
+        return invalid("GLB header cut short");
+    }
+    let (version, length) = (read_u32(data, 4), read_u32(data, 8) as usize);
+    if version != 2 {
+        return Err(GltfError::Unsupported(format!("GLB version {}", version)));
+    }
+    if length > data.len() {
+        return invalid(format!("GLB says it's {} bytes, but it's {}", length, data.len()));
+    }
+    let (mut json, mut bin) = (None, None);
+    let mut offset = GLB_HEADER_SIZE;
+    while offset + 8 <= length {
+        let (chunk_length, chunk_type) = (read_u32(data, offset) as usize, read_u32(data, offset + 4));
+        let start = offset + 8;
+        if start + chunk_length > length {
+            return invalid("GLB chunk runs past the end of the file");
+        }
+        let chunk = &data[start..start + chunk_length];
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(chunk),
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(chunk),
+            _ => {},
+        }
+        // Chunks are padded to 4 bytes
+        offset = start + div_ceil(chunk_length, 4) * 4;
+    }
+    match json {
+        Some(json) => Ok((std::str::from_utf8(json).map_err(|_| GltfError::Invalid("GLB JSON chunk isn't UTF-8".to_string()))?, bin)),
+        None => invalid("GLB has no JSON chunk"),
+    }
+}
+
+fn decode_base64(text: &str) -> std::result::Result<Vec<u8>, GltfError> {
+    let mut ret = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut bit_count) = (0u32, 0);
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return invalid(format!("{:?} in base64 data", c as char)),
+        };
+        bits = bits << 6 | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            ret.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+    Ok(ret)
+}
+
+/// The contents of `uri`, from a `data:` URI or a file next to `base`.
+fn read_uri(uri: &str, base: &Path) -> std::result::Result<Vec<u8>, GltfError> {
+    if uri.starts_with(DATA_URI_PREFIX) {
+        return match uri.find(BASE64_MARKER) {
+            Some(idx) => decode_base64(&uri[idx + BASE64_MARKER.len()..]),
+            None => Err(GltfError::Unsupported("data URIs that aren't base64".to_string())),
+        };
+    }
+    if uri.contains("://") {
+        return Err(GltfError::Unsupported(format!("remote URI {:?}", uri)));
+    }
+    let path = base.join(uri.replace("%20", " "));
+    Ok(::read_full_file(&path.to_string_lossy())?)
+}
+
+struct Document<'a> {
+    root: &'a Value,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl<'a> Document<'a> {
+    fn buffer_view(&self, idx: usize) -> std::result::Result<(&[u8], Option<usize>), GltfError> {
+        let view = self.root.get("bufferViews").at(idx);
+        let buffer = match view.get("buffer").as_usize().and_then(|buffer| self.buffers.get(buffer)) {
+            Some(buffer) => buffer,
+            None => return invalid(format!("buffer view {} has no buffer", idx)),
+        };
+        let offset = view.get("byteOffset").as_usize().unwrap_or(0);
+        let length = match view.get("byteLength").as_usize() {
+            Some(length) => length,
+            None => return invalid(format!("buffer view {} has no length", idx)),
+        };
+        if offset + length > buffer.len() {
+            return invalid(format!("buffer view {} runs past the end of its buffer", idx));
+        }
+        Ok((&buffer[offset..offset + length], view.get("byteStride").as_usize()))
+    }
+
+    /// Accessor `idx`'s elements as raw components: `count` elements of `components` numbers
+    /// each, every one read by `read` from its bytes.
+    fn read_accessor<T: Copy + Default, F: Fn(&[u8], usize) -> T>(&self, idx: usize, components: usize, component_types: &[(usize, usize)], read: F) -> std::result::Result<Vec<T>, GltfError> {
+        let accessor = self.root.get("accessors").at(idx);
+        if !accessor.get("sparse").is_null() {
+            return Err(GltfError::Unsupported(format!("sparse accessor {}", idx)));
+        }
+        let count = match accessor.get("count").as_usize() {
+            Some(count) => count,
+            None => return invalid(format!("accessor {} has no count", idx)),
+        };
+        let component_type = accessor.get("componentType").as_usize().unwrap_or(0);
+        let component_size = match component_types.iter().find(|&&(typ, _)| typ == component_type) {
+            Some(&(_, size)) => size,
+            None => return Err(GltfError::Unsupported(format!("component type {} in accessor {}", component_type, idx))),
+        };
+        let mut ret = vec![T::default(); count * components];
+        // Without a buffer view, the elements are all zero
+        let view = match accessor.get("bufferView").as_usize() {
+            Some(view) => view,
+            None => return Ok(ret),
+        };
+        let (data, stride) = self.buffer_view(view)?;
+        let element_size = components * component_size;
+        let stride = stride.unwrap_or(element_size);
+        let offset = accessor.get("byteOffset").as_usize().unwrap_or(0);
+        if count > 0 && offset + stride * (count - 1) + element_size > data.len() {
+            return invalid(format!("accessor {} runs past the end of its buffer view", idx));
+        }
+        for element in 0..count {
+            for component in 0..components {
+                ret[element * components + component] = read(data, offset + element * stride + component * component_size);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn read_floats(&self, idx: usize, components: usize) -> std::result::Result<Vec<f32>, GltfError> {
+        let component_type = self.root.get("accessors").at(idx).get("componentType").as_usize();
+        // Texture coordinates can also be normalized integers
+        self.read_accessor(idx, components, &[(COMPONENT_FLOAT, 4), (COMPONENT_UNSIGNED_BYTE, 1), (COMPONENT_UNSIGNED_SHORT, 2)], |data, offset| match component_type {
+            Some(COMPONENT_UNSIGNED_BYTE) => data[offset] as f32 / 255.0,
+            Some(COMPONENT_UNSIGNED_SHORT) => (data[offset] as u16 | (data[offset + 1] as u16) << 8) as f32 / 65535.0,
+            _ => f32::from_bits(read_u32(data, offset)),
+        })
+    }
+
+    fn read_indices(&self, idx: usize) -> std::result::Result<Vec<u32>, GltfError> {
+        let component_type = self.root.get("accessors").at(idx).get("componentType").as_usize();
+        self.read_accessor(idx, 1, &[(COMPONENT_UNSIGNED_BYTE, 1), (COMPONENT_UNSIGNED_SHORT, 2), (COMPONENT_UNSIGNED_INT, 4)], |data, offset| match component_type {
+            Some(COMPONENT_UNSIGNED_BYTE) => data[offset] as u32,
+            Some(COMPONENT_UNSIGNED_SHORT) => data[offset] as u32 | (data[offset + 1] as u32) << 8,
+            _ => read_u32(data, offset),
+        })
+    }
+
+    fn primitive(&self, mesh: usize, primitive: &Value) -> std::result::Result<Option<Primitive>, GltfError> {
+        let mode = primitive.get("mode").as_usize().unwrap_or(MODE_TRIANGLES);
+        if mode != MODE_TRIANGLES {
+            warn!("Skipping primitive of mesh {} with mode {}, only triangles are loaded", mesh, mode);
+            return Ok(None);
+        }
+        let attributes = primitive.get("attributes");
+        let positions = match attributes.get("POSITION").as_usize() {
+            Some(accessor) => self.read_floats(accessor, 3)?,
+            None => return invalid(format!("primitive of mesh {} has no positions", mesh)),
+        };
+        let count = positions.len() / 3;
+        let normals = match attributes.get("NORMAL").as_usize() {
+            Some(accessor) => Some(self.read_floats(accessor, 3)?),
+            None => None,
+        };
+        let uvs = match attributes.get("TEXCOORD_0").as_usize() {
+            Some(accessor) => Some(self.read_floats(accessor, 2)?),
+            None => None,
+        };
+        if normals.as_ref().map(|normals| normals.len() != count * 3).unwrap_or(false) || uvs.as_ref().map(|uvs| uvs.len() != count * 2).unwrap_or(false) {
+            return invalid(format!("primitive of mesh {} has attributes of different lengths", mesh));
+        }
+        let indices = match primitive.get("indices").as_usize() {
+            Some(accessor) => self.read_indices(accessor)?,
+            None => (0..count as u32).collect(),
+        };
+        if indices.iter().any(|&idx| idx as usize >= count) {
+            return invalid(format!("primitive of mesh {} has indices past its {} vertices", mesh, count));
+        }
+        let mut ret = MeshData {
+            vertices: (0..count).map(|i| MeshVertex {
+                position: [positions[i * 3], positions[i * 3 + 1], positions[i * 3 + 2]],
+                normal: normals.as_ref().map(|n| [n[i * 3], n[i * 3 + 1], n[i * 3 + 2]]).unwrap_or([0.0; 3]),
+                uv: uvs.as_ref().map(|t| [t[i * 2], t[i * 2 + 1]]).unwrap_or([0.0; 2]),
+            }).collect(),
+            indices: indices,
+        };
+        if normals.is_none() {
+            ret.generate_normals();
+        }
+        Ok(Some(Primitive {
+            mesh: ret,
+            material: primitive.get("material").as_usize(),
+        }))
+    }
+
+    fn image(&self, idx: usize, base: &Path) -> std::result::Result<ImageData, GltfError> {
+        let image = self.root.get("images").at(idx);
+        let data = match (image.get("uri").as_str(), image.get("bufferView").as_usize()) {
+            (Some(uri), _) => read_uri(uri, base)?,
+            (None, Some(view)) => self.buffer_view(view)?.0.to_vec(),
+            (None, None) => return invalid(format!("image {} has no data", idx)),
+        };
+        Ok(image::decode(&data)?)
+    }
+}
+
+fn vec3(value: &Value, default: Vec3) -> Vec3 {
+    match (value.at(0).as_f64(), value.at(1).as_f64(), value.at(2).as_f64()) {
+        (Some(x), Some(y), Some(z)) => Vec3::new(x as f32, y as f32, z as f32),
+        _ => default,
+    }
+}
+
+fn node_transform(node: &Value) -> Mat4 {
+    let matrix = node.get("matrix").elements();
+    if matrix.len() == 16 {
+        let mut ret = Mat4::identity();
+        for (idx, value) in matrix.iter().enumerate() {
+            ret.cols[idx / 4][idx % 4] = value.as_f64().unwrap_or(0.0) as f32;
+        }
+        return ret;
+    }
+    let rotation = node.get("rotation");
+    let rotation = match (rotation.at(0).as_f64(), rotation.at(1).as_f64(), rotation.at(2).as_f64(), rotation.at(3).as_f64()) {
+        (Some(x), Some(y), Some(z), Some(w)) => Quat {
+            x: x as f32,
+            y: y as f32,
+            z: z as f32,
+            w: w as f32,
+        }.normalize(),
+        _ => Quat::identity(),
+    };
+    Mat4::translation(vec3(node.get("translation"), Vec3::zero())) * Mat4::rotation(rotation) * Mat4::scale(vec3(node.get("scale"), Vec3::new(1.0, 1.0, 1.0)))
+}
+
+/// Adds an instance for `node` and its descendants' meshes, `depth` nodes down.
+fn add_instances(root: &Value, node: usize, parent: &Mat4, depth: usize, instances: &mut Vec<Instance>) -> std::result::Result<(), GltfError> {
+    // Nodes form a forest, so a deeper one has to be a cycle
+    if depth > root.get("nodes").elements().len() {
+        return invalid("node hierarchy has a cycle");
+    }
+    let value = root.get("nodes").at(node);
+    if value.is_null() {
+        return invalid(format!("no node {}", node));
+    }
+    let transform = *parent * node_transform(value);
+    if let Some(mesh) = value.get("mesh").as_usize() {
+        instances.push(Instance {
+            mesh: mesh,
+            transform: transform,
+        });
+    }
+    for child in value.get("children").elements() {
+        match child.as_usize() {
+            Some(child) => add_instances(root, child, &transform, depth + 1, instances)?,
+            None => return invalid(format!("node {} has an invalid child", node)),
+        }
+    }
+    Ok(())
+}
+
+/// The nodes at the top of the default scene, or every node that isn't a child if there are no
+/// scenes.
+fn root_nodes(root: &Value) -> Vec<usize> {
+    let scenes = root.get("scenes");
+    if !scenes.elements().is_empty() {
+        let scene = root.get("scene").as_usize().unwrap_or(0);
+        return scenes.at(scene).get("nodes").elements().iter().filter_map(Value::as_usize).collect();
+    }
+    let nodes = root.get("nodes").elements();
+    let children: Vec<usize> = nodes.iter().flat_map(|node| node.get("children").elements().iter().filter_map(Value::as_usize)).collect();
+    (0..nodes.len()).filter(|idx| !children.contains(idx)).collect()
+}
+
+/// Parses a glTF document in `text`, with buffer 0 being `bin` if there is one, and reads any
+/// other files it uses relative to `base`.
+pub fn parse(text: &str, bin: Option<&[u8]>, base: &Path) -> std::result::Result<GltfScene, GltfError> {
+    let root = json::parse(text)?;
+    let version = root.get("asset").get("version").as_str().unwrap_or("");
+    if !version.starts_with("2.") {
+        return Err(GltfError::Unsupported(format!("glTF version {:?}", version)));
+    }
+    let mut buffers = Vec::new();
+    for (idx, buffer) in root.get("buffers").elements().iter().enumerate() {
+        buffers.push(match (buffer.get("uri").as_str(), bin) {
+            (Some(uri), _) => read_uri(uri, base)?,
+            (None, Some(bin)) if idx == 0 => bin.to_vec(),
+            (None, _) => return invalid(format!("buffer {} has no data", idx)),
+        });
+    }
+    let document = Document {
+        root: &root,
+        buffers: buffers,
+    };
+
+    let mut meshes = Vec::new();
+    for (idx, mesh) in root.get("meshes").elements().iter().enumerate() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.get("primitives").elements() {
+            if let Some(primitive) = document.primitive(idx, primitive)? {
+                primitives.push(primitive);
+            }
+        }
+        meshes.push(primitives);
+    }
+
+    // Only images used as base colors are decoded, since nothing else is drawn
+    let mut images = Vec::new();
+    let mut image_indices: HashMap<usize, usize> = HashMap::new();
+    let mut materials = Vec::new();
+    for material in root.get("materials").elements() {
+        let pbr = material.get("pbrMetallicRoughness");
+        let factor = pbr.get("baseColorFactor");
+        let mut base_color_factor = [1.0; 4];
+        for (idx, component) in base_color_factor.iter_mut().enumerate() {
+            *component = factor.at(idx).as_f64().unwrap_or(1.0) as f32;
+        }
+        let base_color_texture = match pbr.get("baseColorTexture").get("index").as_usize().and_then(|texture| root.get("textures").at(texture).get("source").as_usize()) {
+            Some(image) => Some(match image_indices.get(&image).cloned() {
+                Some(idx) => idx,
+                None => {
+                    images.push(document.image(image, base)?);
+                    image_indices.insert(image, images.len() - 1);
+                    images.len() - 1
+                },
+            }),
+            None => None,
+        };
+        materials.push(Material {
+            base_color_factor: base_color_factor,
+            base_color_texture: base_color_texture,
+        });
+    }
+
+    let mut instances = Vec::new();
+    for node in root_nodes(&root) {
+        add_instances(&root, node, &Mat4::identity(), 0, &mut instances)?;
+    }
+    if instances.iter().any(|instance| instance.mesh >= meshes.len()) {
+        return invalid("node uses a mesh that doesn't exist");
+    }
+    for primitive in meshes.iter().flat_map(|primitives| primitives.iter()) {
+        if primitive.material.map(|material| material >= materials.len()).unwrap_or(false) {
+            return invalid("primitive uses a material that doesn't exist");
+        }
+    }
+    Ok(GltfScene {
+        meshes: meshes,
+        instances: instances,
+        materials: materials,
+        images: images,
+    })
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> std::result::Result<GltfScene, GltfError> {
+    let path = path.as_ref();
+    let data = ::read_full_file(&path.to_string_lossy())?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let ret = if data.starts_with(GLB_MAGIC) {
+        let (text, bin) = split_glb(&data)?;
+        parse(text, bin, base)?
+    } else {
+        let text = std::str::from_utf8(&data).map_err(|_| GltfError::Invalid("not UTF-8".to_string()))?;
+        parse(text, None, base)?
+    };
+    debug!("Loaded {:?}: {} meshes, {} instances, {} materials, {} images", path, ret.meshes.len(), ret.instances.len(), ret.materials.len(), ret.images.len());
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions (0, 0, 0), (1, 0, 0) and (0, 1, 0) as floats, then indices 0, 1, 2 as shorts.
+    const TRIANGLE_BASE64: &'static str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIA";
+
+    /// One triangle mesh, placed by `nodes` (a JSON array) under a scene rooted at node 0, with
+    /// buffer 0 at `uri` or in the GLB binary chunk if that's `None`.
+    fn document(nodes: &str, uri: Option<&str>) -> String {
+        let buffer = match uri {
+            Some(uri) => format!(r#"{{ "byteLength": 42, "uri": "{}" }}"#, uri),
+            None => r#"{ "byteLength": 42 }"#.to_string(),
+        };
+        format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}],
+            "materials": [{{ "pbrMetallicRoughness": {{ "baseColorFactor": [0.5, 0.25, 1.0, 1.0] }} }}],
+            "nodes": {},
+            "scenes": [{{ "nodes": [0] }}]
+        }}"#, buffer, nodes)
+    }
+
+    fn data_uri() -> String {
+        format!("data:application/octet-stream;base64,{}", TRIANGLE_BASE64)
+    }
+
+    #[test]
+    fn triangle_from_a_data_uri() {
+        let scene = parse(&document(r#"[{ "mesh": 0 }]"#, Some(&data_uri())), None, Path::new("")).unwrap();
+        assert_eq!(scene.meshes.len(), 1);
+        let mesh = &scene.meshes[0][0].mesh;
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.vertices[1].position, [1.0, 0.0, 0.0]);
+        // Generated, since the primitive has no normals
+        assert_eq!(mesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(scene.materials[0].base_color_factor, [0.5, 0.25, 1.0, 1.0]);
+        assert_eq!(scene.materials[0].base_color_texture, None);
+        assert_eq!(scene.instances.len(), 1);
+    }
+
+    #[test]
+    fn node_transforms_are_inherited() {
+        let nodes = r#"[
+            { "translation": [10, 0, 0], "children": [1] },
+            { "mesh": 0, "scale": [2, 2, 2] }
+        ]"#;
+        let scene = parse(&document(nodes, Some(&data_uri())), None, Path::new("")).unwrap();
+        assert_eq!(scene.instances.len(), 1);
+        let flat = scene.flatten();
+        assert_eq!(flat.vertices[1].position, [12.0, 0.0, 0.0]);
+        assert_eq!(flat.vertices[2].position, [10.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn glb_chunks() {
+        let bin = decode_base64(TRIANGLE_BASE64).unwrap();
+        let mut json = document(r#"[{ "mesh": 0 }]"#, None).into_bytes();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut glb = Vec::new();
+        let push_u32 = |out: &mut Vec<u8>, value: u32| out.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+        let padded_bin = div_ceil(bin.len(), 4) * 4;
+        glb.extend_from_slice(GLB_MAGIC);
+        push_u32(&mut glb, 2);
+        push_u32(&mut glb, (GLB_HEADER_SIZE + 8 + json.len() + 8 + padded_bin) as u32);
+        push_u32(&mut glb, json.len() as u32);
+        push_u32(&mut glb, GLB_CHUNK_JSON);
+        glb.extend_from_slice(&json);
+        push_u32(&mut glb, bin.len() as u32);
+        push_u32(&mut glb, GLB_CHUNK_BIN);
+        glb.extend_from_slice(&bin);
+        glb.resize(GLB_HEADER_SIZE + 8 + json.len() + 8 + padded_bin, 0);
+
+        let (text, chunk) = split_glb(&glb).unwrap();
+        assert_eq!(chunk, Some(&bin[..]));
+        let scene = parse(text, chunk, Path::new("")).unwrap();
+        assert_eq!(scene.meshes[0][0].mesh.vertices[2].position, [0.0, 1.0, 0.0]);
+
+        assert!(split_glb(&glb[..GLB_HEADER_SIZE - 1]).is_err());
+        assert!(split_glb(&glb[..glb.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn base64_variants() {
+        assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello".to_vec());
+        assert_eq!(decode_base64("aGVsbG8").unwrap(), b"hello".to_vec());
+        assert_eq!(decode_base64("-_-_").unwrap(), decode_base64("+/+/").unwrap());
+        assert!(decode_base64("a*b").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        match parse(r#"{ "asset": { "version": "1.0" } }"#, None, Path::new("")) {
+            Err(GltfError::Unsupported(_)) => {},
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+        let cycle = r#"[{ "mesh": 0, "children": [1] }, { "children": [0] }]"#;
+        match parse(&document(cycle, Some(&data_uri())), None, Path::new("")) {
+            Err(GltfError::Invalid(_)) => {},
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        match parse(&document(r#"[{ "mesh": 3 }]"#, Some(&data_uri())), None, Path::new("")) {
+            Err(GltfError::Invalid(_)) => {},
+            other => panic!("expected a missing mesh, got {:?}", other),
+        }
+        match parse(&document(r#"[{ "mesh": 0 }]"#, None), None, Path::new("")) {
+            Err(GltfError::Invalid(_)) => {},
+            other => panic!("expected a buffer without data, got {:?}", other),
+        }
+        match parse(&document("[]", Some("https://example.com/triangle.bin")), None, Path::new("")) {
+            Err(GltfError::Unsupported(_)) => {},
+            other => panic!("expected a remote URI, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Reusable GPU algorithms over `u32` storage buffers.
+//!
+//! `ExclusiveScan` is a prefix sum: each workgroup scans its block of 256 elements in shared
+//! memory and writes out the block's total, the totals get scanned the same way (recursively,
+//! until one block covers them), and the scanned totals are added back to their blocks.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use vk::types::*;
+use ::compute::{ self, ComputeKernel, StorageBuffer };
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::math::prelude::div_ceil;
+use ::vk_mem::MemoryAllocator;
+
+pub const SCAN_BLOCK_SHADER: &'static str = "shaders/scan_block.comp.spv";
+pub const SCAN_ADD_SHADER: &'static str = "shaders/scan_add.comp.spv";
+
+/// Elements scanned by each workgroup.
+pub const SCAN_BLOCK_SIZE: u32 = 256;
+
+const INPUT_BINDING: u32 = 0;
+const OUTPUT_BINDING: u32 = 1;
+const BLOCK_SUMS_BINDING: u32 = 2;
+
+fn block_count(count: u32) -> u32 {
+    div_ceil(count, SCAN_BLOCK_SIZE)
+}
+
+fn whole_buffer(binding: u32, buffer: Buffer, element_count: u32) -> DescriptorWrite {
+    DescriptorWrite::StorageBuffer {
+        binding: binding,
+        buffer: buffer,
+        offset: 0,
+        range: std::cmp::max(element_count, 1) as DeviceSize * std::mem::size_of::<u32>() as DeviceSize,
+    }
+}
+
+/// One level of the scan: the block scan of its data, and adding its scanned block sums back.
+struct ScanLevel<'d, D: DeviceV1_0 + 'd> {
+    block_sums: StorageBuffer<'d, D>,
+    descriptor_set: DescriptorSet,
+}
+
+pub struct ExclusiveScan<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    block: ComputeKernel<'d, D>,
+    add: ComputeKernel<'d, D>,
+    levels: Vec<ScanLevel<'d, D>>,
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+    capacity: u32,
+}
+
+impl<'d, D: DeviceV1_0> ExclusiveScan<'d, D> {
+    /// Prepares to scan up to `capacity` elements of `input` into `output`, which may be the
+    /// same buffer. `block_spirv` and `add_spirv` are the compiled `SCAN_BLOCK_SHADER` and
+    /// `SCAN_ADD_SHADER`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, input: Buffer, output: Buffer, capacity: u32, block_spirv: &[u8], add_spirv: &[u8]) -> VkResult<ExclusiveScan<'d, D>> {
+        let bindings = compute::storage_buffer_bindings(3);
+        let push_constant_size = std::mem::size_of::<u32>() as u32;
+        let block = ComputeKernel::new(device, block_spirv, &bindings, push_constant_size)?;
+        let add = ComputeKernel::new(device, add_spirv, &bindings, push_constant_size)?;
+
+        // Level i scans `counts[i]` elements, leaving one sum per block for level i + 1
+        let mut counts = vec![capacity];
+        while block_count(*counts.last().unwrap()) > 1 {
+            let next = block_count(*counts.last().unwrap());
+            counts.push(next);
+        }
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: 3 * counts.len() as u32,
+        }];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, counts.len() as u32);
+        let mut levels: Vec<ScanLevel<'d, D>> = Vec::with_capacity(counts.len());
+        for (idx, &count) in counts.iter().enumerate() {
+            let sums_count = std::cmp::max(block_count(count), 1);
+            let block_sums = StorageBuffer::new(device, allocator, sums_count as DeviceSize * std::mem::size_of::<u32>() as DeviceSize)?;
+            let descriptor_set = descriptor_allocator.allocate(block.set_layout())?;
+            // Levels past the first scan the previous level's block sums in place
+            let (level_input, level_output) = match levels.last() {
+                Some(previous) => (previous.block_sums.buffer(), previous.block_sums.buffer()),
+                None => (input, output),
+            };
+            descriptor::update_descriptor_set(device, descriptor_set, &[
+                whole_buffer(INPUT_BINDING, level_input, count),
+                whole_buffer(OUTPUT_BINDING, level_output, count),
+                block_sums.descriptor_write(BLOCK_SUMS_BINDING),
+            ]);
+            trace!("Exclusive scan level {}: {} elements", idx, count);
+            levels.push(ScanLevel {
+                block_sums: block_sums,
+                descriptor_set: descriptor_set,
+            });
+        }
+        Ok(ExclusiveScan {
+            device: device,
+            block: block,
+            add: add,
+            levels: levels,
+            _descriptor_allocator: descriptor_allocator,
+            capacity: capacity,
+        })
+    }
+
+    /// Records an exclusive scan of the first `count` elements. Writes to the input must
+    /// already be visible to compute shaders; the output is visible to later compute work.
+    pub unsafe fn cmd_scan(&self, command_buffer: CommandBuffer, count: u32) {
+        assert!(count <= self.capacity);
+        if count == 0 {
+            return;
+        }
+        let mut counts = vec![count];
+        while block_count(*counts.last().unwrap()) > 1 {
+            let next = block_count(*counts.last().unwrap());
+            counts.push(next);
+        }
+        for (level, &level_count) in self.levels.iter().zip(counts.iter()) {
+            self.block.cmd_dispatch(command_buffer, level.descriptor_set, &level_count, (block_count(level_count), 1, 1));
+            compute::cmd_compute_barrier(self.device, command_buffer);
+        }
+        // The top level fits in one block, so it's already complete
+        for (level, &level_count) in self.levels.iter().zip(counts.iter()).rev().skip(1) {
+            self.add.cmd_dispatch(command_buffer, level.descriptor_set, &level_count, (block_count(level_count), 1, 1));
+            compute::cmd_compute_barrier(self.device, command_buffer);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! GPU timing with timestamp queries. Work is timed in named scopes, each a pair of timestamps
+//! written around it into a query pool belonging to the frame slot. The results are read back
+//! when the slot comes around again, since by then `FrameLoop::begin` has waited for the GPU to
+//! finish with it, so reading never stalls.
+//!
+//! Each frame's timings are logged at debug level under `logging::PROFILER`, and `log` sums them
+//! up at the end.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::collections::BTreeMap;
+use std::mem;
+use std::ptr;
+use vk::types::*;
+use ::logging;
+use ::safe_create::{ self, Owned };
+
+/// Scopes one frame can have, as each takes two queries.
+pub const MAX_SCOPES: usize = 32;
+const QUERIES_PER_SLOT: u32 = MAX_SCOPES as u32 * 2;
+
+/// Whether to time frames at all, checked when the renderer is created.
+pub fn enabled() -> bool {
+    cvar!("r.gpu_profiler", true).get()
+}
+
+/// A scope started by `GpuProfiler::begin_scope`, to pass to `end_scope`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScopeId(usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeTiming {
+    pub name: &'static str,
+    pub milliseconds: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ScopeStats {
+    frames: u32,
+    total: f32,
+    worst: f32,
+}
+
+struct Slot<'d, D: DeviceV1_0 + 'd> {
+    pool: Owned<'d, D, QueryPool>,
+    /// Names of the scopes written this frame, with whether each was ended.
+    scopes: Vec<(&'static str, bool)>,
+}
+
+pub struct GpuProfiler<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    slots: Vec<Slot<'d, D>>,
+    current: usize,
+    /// Nanoseconds per tick.
+    timestamp_period: f32,
+    /// Mask of the bits timestamps actually have.
+    valid_mask: u64,
+    last_frame: Vec<ScopeTiming>,
+    stats: BTreeMap<&'static str, ScopeStats>,
+    warned_full: bool,
+}
+
+impl<'d, D: DeviceV1_0> GpuProfiler<'d, D> {
+    /// A profiler with a query pool for each of `frames_in_flight`, timing work on a queue whose
+    /// family has `timestamp_valid_bits`. `None` if that queue can't write timestamps.
+    pub fn new(device: &'d D, frames_in_flight: usize, limits: &PhysicalDeviceLimits, timestamp_valid_bits: u32) -> VkResult<Option<GpuProfiler<'d, D>>> {
+        if timestamp_valid_bits == 0 {
+            info!(target: logging::PROFILER, "The graphics queue doesn't support timestamps, so GPU times won't be measured");
+            return Ok(None);
+        }
+        let create_info = QueryPoolCreateInfo {
+            s_type: StructureType::QueryPoolCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            query_type: QueryType::Timestamp,
+            query_count: QUERIES_PER_SLOT,
+            pipeline_statistics: Default::default(),
+        };
+        let mut ret = GpuProfiler {
+            device: device,
+            slots: Vec::with_capacity(frames_in_flight),
+            current: 0,
+            timestamp_period: limits.timestamp_period,
+            valid_mask: if timestamp_valid_bits >= 64 { !0 } else { (1 << timestamp_valid_bits) - 1 },
+            last_frame: Vec::new(),
+            stats: BTreeMap::new(),
+            warned_full: false,
+        };
+        for _ in 0..frames_in_flight {
+            ret.slots.push(Slot {
+                pool: safe_create::create_query_pool_safe(device, &create_info, None)?,
+                scopes: Vec::new(),
+            });
+        }
+        debug!(target: logging::PROFILER, "Created {} timestamp query pools, {} ns per tick", frames_in_flight, ret.timestamp_period);
+        Ok(Some(ret))
+    }
+
+    /// Reads back what `slot` timed last time it was used, and starts a new frame in it. Call
+    /// once `FrameLoop::begin` has waited for the slot.
+    pub fn begin_frame(&mut self, slot: usize) -> VkResult<()> {
+        self.current = slot;
+        let scopes = std::mem::take(&mut self.slots[slot].scopes);
+        if scopes.is_empty() {
+            return Ok(());
+        }
+        let mut timestamps = vec![0u64; scopes.len() * 2];
+        let err_code = unsafe {
+            self.device.fp_v1_0().get_query_pool_results(self.device.handle(), *self.slots[slot].pool, 0, timestamps.len() as u32, timestamps.len() * mem::size_of::<u64>(), timestamps.as_mut_ptr() as *mut c_void, mem::size_of::<u64>() as DeviceSize, QUERY_RESULT_64_BIT)
+        };
+        match err_code {
+            Result::Success => {},
+            // Part of the frame was never submitted, e.g. because it was dropped after acquiring
+            // failed, so there's nothing to read
+            Result::NotReady => return Ok(()),
+            _ => return Err(err_code),
+        }
+        self.last_frame.clear();
+        for (idx, &(name, ended)) in scopes.iter().enumerate() {
+            if !ended {
+                continue;
+            }
+            let ticks = (timestamps[idx * 2 + 1] & self.valid_mask).wrapping_sub(timestamps[idx * 2] & self.valid_mask) & self.valid_mask;
+            let milliseconds = ticks as f32 * self.timestamp_period / 1000000.0;
+            self.last_frame.push(ScopeTiming {
+                name: name,
+                milliseconds: milliseconds,
+            });
+            let stats = self.stats.entry(name).or_default();
+            stats.frames += 1;
+            stats.total += milliseconds;
+            stats.worst = stats.worst.max(milliseconds);
+        }
+        debug!(target: logging::PROFILER, "GPU: {}", self.last_frame.iter().map(|timing| format!("{} {:.3} ms", timing.name, timing.milliseconds)).collect::<Vec<String>>().join(", "));
+        Ok(())
+    }
+
+    /// The scopes of the most recent frame that's been read back.
+    pub fn last_frame(&self) -> &[ScopeTiming] {
+        &self.last_frame
+    }
+
+    /// Records a timestamp starting the scope `name` into `command_buffer`, outside any render
+    /// pass. The first scope of a frame also resets the frame's queries, so it has to be recorded
+    /// into the frame's first submitted command buffer. `None` if the frame has run out of
+    /// scopes, which `end_scope` ignores.
+    pub unsafe fn begin_scope(&mut self, command_buffer: CommandBuffer, name: &'static str) -> Option<ScopeId> {
+        let slot = &mut self.slots[self.current];
+        if slot.scopes.len() >= MAX_SCOPES {
+            if !self.warned_full {
+                warn!(target: logging::PROFILER, "More than {} GPU profiler scopes in a frame, not timing {:?} or any after it", MAX_SCOPES, name);
+                self.warned_full = true;
+            }
+            return None;
+        }
+        if slot.scopes.is_empty() {
+            self.device.fp_v1_0().cmd_reset_query_pool(command_buffer, *slot.pool, 0, QUERIES_PER_SLOT);
+        }
+        let idx = slot.scopes.len();
+        self.device.fp_v1_0().cmd_write_timestamp(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, *slot.pool, idx as u32 * 2);
+        slot.scopes.push((name, false));
+        Some(ScopeId(idx))
+    }
+
+    /// Records a timestamp ending `scope` into `command_buffer`, once everything before it is done.
+    pub unsafe fn end_scope(&mut self, command_buffer: CommandBuffer, scope: Option<ScopeId>) {
+        if let Some(ScopeId(idx)) = scope {
+            let slot = &mut self.slots[self.current];
+            self.device.fp_v1_0().cmd_write_timestamp(command_buffer, PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT, *slot.pool, idx as u32 * 2 + 1);
+            slot.scopes[idx].1 = true;
+        }
+    }
+
+    /// Logs the average and worst time of each scope over every frame read back.
+    pub fn log(&self) {
+        for (name, stats) in self.stats.iter() {
+            info!(target: logging::PROFILER, "GPU {}: {:.3} ms average, {:.3} ms worst, over {} frames", name, stats.total / stats.frames as f32, stats.worst, stats.frames);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! The simplest compute demo: `shaders/gradient.glsl` writes a gradient into a storage image,
+//! which is then blitted straight to the swapchain image, with no render pass involved. The image
+//! stays in `General` while it's written, and moves to `TransferSrcOptimal` for the blit.
+//!
+//! Blitting to the swapchain needs it created with `IMAGE_USAGE_TRANSFER_DST_BIT`, and
+//! `R8g8b8a8Unorm` has to support being blitted from, which it does wherever it can be a storage
+//! image in practice, but `is_supported` checks anyway.
+//!
+//! The gradient can be written on a compute queue of another family than the blit's, in which
+//! case the image is shared between both families rather than handed from one to the other.
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, InstanceV1_0 };
+use std::mem;
+use std::ptr;
+use vk::types::*;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::format_support::{ FormatSupport, FormatUsage };
+use ::queue::QueueFamilyIndex;
+use ::rect::ExtentExt;
+use ::safe_create;
+use ::shader_stage::{ ShaderStage, Specialization };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const SHADER: &'static str = "shaders/gradient.comp.spv";
+
+/// Invocations per axis of a `shaders/gradient.glsl` workgroup, given to it as specialization
+/// constants `LOCAL_SIZE_X_ID` and `LOCAL_SIZE_Y_ID`.
+const LOCAL_SIZE: u32 = 8;
+const LOCAL_SIZE_X_ID: u32 = 0;
+const LOCAL_SIZE_Y_ID: u32 = 1;
+
+const DST_BINDING: u32 = 0;
+
+const IMAGE_FORMAT: Format = Format::R8g8b8a8Unorm;
+
+/// Push constant block read by `shaders/gradient.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct GradientPushConstants {
+    time: f32,
+}
+
+/// Whether `physical_device` can write the gradient and blit it into swapchain images of
+/// `swapchain_format`.
+pub fn is_supported<I: InstanceV1_0>(instance: &I, physical_device: PhysicalDevice, swapchain_format: Format) -> bool {
+    FormatSupport::query(instance, physical_device, IMAGE_FORMAT).supports_all(ImageTiling::Optimal, &[FormatUsage::Storage, FormatUsage::BlitSrc])
+        && FormatSupport::query(instance, physical_device, swapchain_format).supports(ImageTiling::Optimal, FormatUsage::BlitDst)
+}
+
+/// Creates the gradient pipeline from a module of `SHADER`, with `layout` from
+/// `Gradient::pipeline_layout`.
+pub fn create_pipeline<'d, D: DeviceV1_0>(device: &'d D, layout: PipelineLayout, shader: ShaderModule) -> VkResult<safe_create::Owned<'d, D, Pipeline>> {
+    let specialization = Specialization::new()
+        .constant(LOCAL_SIZE_X_ID, LOCAL_SIZE)
+        .constant(LOCAL_SIZE_Y_ID, LOCAL_SIZE);
+    ShaderStage::compute(shader).specialize(specialization).with_create_info(|stage| {
+        let create_info = ComputePipelineCreateInfo {
+            s_type: StructureType::ComputePipelineCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            stage: stage,
+            layout: layout,
+            base_pipeline_handle: Pipeline::null(),
+            base_pipeline_index: -1,
+        };
+        safe_create::create_compute_pipelines_safe(device, &PipelineCache::null(), &[create_info], None)
+    })
+        .map_err(|(_, res)| res)
+        .map(|pipelines| pipelines.into_iter()
+             .next()
+             .expect("Expected successful creation of a compute pipeline to actually give us a compute pipeline"))
+}
+
+pub struct Gradient<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+    extent: Extent2D,
+    set_layout: DescriptorSetLayout,
+    pipeline_layout: PipelineLayout,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_set: DescriptorSet,
+}
+
+impl<'d, D: DeviceV1_0> Gradient<'d, D> {
+    /// A storage image of `extent` to write the gradient into, with the layouts to write it. It's
+    /// shared between `queue_families` when there's more than one.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, extent: Extent2D, queue_families: &[QueueFamilyIndex]) -> VkResult<Gradient<'d, D>> {
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageImage,
+            descriptor_count: 1,
+        }];
+        let mut ret = Gradient {
+            device: device,
+            image: Image::null(),
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+            extent: extent,
+            set_layout: DescriptorSetLayout::null(),
+            pipeline_layout: PipelineLayout::null(),
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, 1),
+            descriptor_set: DescriptorSet::null(),
+        };
+        // Destroying/freeing null handles is a no-op, so from here on out `Drop` will clean up
+        // after us if anything goes wrong.
+        let family_indices: Vec<u32> = queue_families.iter().map(|family| family.raw()).collect();
+        let sharing_mode = if family_indices.len() > 1 { SharingMode::Concurrent } else { SharingMode::Exclusive };
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: IMAGE_FORMAT,
+            extent: Extent3D {
+                width: ret.extent.width,
+                height: ret.extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_STORAGE_BIT | IMAGE_USAGE_TRANSFER_SRC_BIT,
+            sharing_mode: sharing_mode,
+            queue_family_index_count: if sharing_mode == SharingMode::Concurrent { family_indices.len() as u32 } else { 0 },
+            p_queue_family_indices: if sharing_mode == SharingMode::Concurrent { family_indices.as_ptr() } else { ptr::null() },
+            initial_layout: ImageLayout::Undefined,
+        };
+        ret.image = unsafe { device.create_image(&image_create_info, None) }?;
+        ret.memory = allocator.allocate_image_memory(ret.image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: ret.image,
+            view_type: ImageViewType::Type2d,
+            format: IMAGE_FORMAT,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: subresource_range(),
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+
+        let binding = DescriptorSetLayoutBinding {
+            binding: DST_BINDING,
+            descriptor_type: DescriptorType::StorageImage,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            p_immutable_samplers: ptr::null(),
+        };
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: 1,
+            p_bindings: &binding,
+        };
+        ret.set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        let push_constant_range = PushConstantRange {
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            offset: 0,
+            size: mem::size_of::<GradientPushConstants>() as u32,
+        };
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 1,
+            p_set_layouts: &ret.set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.pipeline_layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+
+        let set_layout = ret.set_layout;
+        ret.descriptor_set = ret.descriptor_allocator.allocate(set_layout)?;
+        descriptor::update_descriptor_set(device, ret.descriptor_set, &[DescriptorWrite::StorageImage {
+            binding: DST_BINDING,
+            image_view: ret.view,
+            image_layout: ImageLayout::General,
+        }]);
+        debug!("Created {}x{} gradient image", ret.extent.width, ret.extent.height);
+        Ok(ret)
+    }
+
+    pub fn pipeline_layout(&self) -> PipelineLayout {
+        self.pipeline_layout
+    }
+
+    /// Records writing the gradient at `time` seconds with `pipeline`, from `create_pipeline`.
+    /// Must be outside a render pass, and leaves the image ready for `cmd_blit_to`.
+    pub unsafe fn cmd_dispatch(&self, command_buffer: CommandBuffer, pipeline: Pipeline, time: f32) {
+        // The last frame's contents were already blitted, so they can be discarded
+        let before = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_TRANSFER_READ_BIT,
+            dst_access_mask: ACCESS_SHADER_WRITE_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[before]);
+
+        let constants = GradientPushConstants {
+            time: time,
+        };
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Compute, pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Compute, self.pipeline_layout, 0, &[self.descriptor_set], &[]);
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, self.pipeline_layout, SHADER_STAGE_COMPUTE_BIT, 0, mem::size_of::<GradientPushConstants>() as u32, &constants as *const GradientPushConstants as *const c_void);
+        let groups = self.extent.div_ceil(LOCAL_SIZE);
+        self.device.cmd_dispatch(command_buffer, groups.width, groups.height, 1);
+
+        let after = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_TRANSFER_READ_BIT,
+            old_layout: ImageLayout::General,
+            new_layout: ImageLayout::TransferSrcOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[after]);
+    }
+
+    /// Records blitting the gradient over all of `swapchain_image`, which has `swapchain_extent`,
+    /// and leaves it in `PresentSrcKhr`.
+    pub unsafe fn cmd_blit_to(&self, command_buffer: CommandBuffer, swapchain_image: Image, swapchain_extent: &Extent2D) {
+        let to_transfer_dst = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: Default::default(),
+            dst_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::TransferDstOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: swapchain_image,
+            subresource_range: subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[to_transfer_dst]);
+
+        let layers = ImageSubresourceLayers {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let corner = |extent: &Extent2D| Offset3D {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: 1,
+        };
+        let origin = Offset3D {
+            x: 0,
+            y: 0,
+            z: 0,
+        };
+        let region = ImageBlit {
+            src_subresource: layers.clone(),
+            src_offsets: [origin.clone(), corner(&self.extent)],
+            dst_subresource: layers,
+            dst_offsets: [origin, corner(swapchain_extent)],
+        };
+        self.device.fp_v1_0().cmd_blit_image(command_buffer, self.image, ImageLayout::TransferSrcOptimal, swapchain_image, ImageLayout::TransferDstOptimal, 1, &region, Filter::Linear);
+
+        let to_present = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            dst_access_mask: ACCESS_MEMORY_READ_BIT,
+            old_layout: ImageLayout::TransferDstOptimal,
+            new_layout: ImageLayout::PresentSrcKhr,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: swapchain_image,
+            subresource_range: subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT, Default::default(), &[], &[], &[to_present]);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for Gradient<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying gradient image: {:?}", self.image);
+            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+fn subresource_range() -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
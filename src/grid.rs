@@ -0,0 +1,102 @@
+//! Infinite ground grid and world axis indicators. The grid is a fullscreen triangle whose
+//! fragment shader intersects the view ray with the `y = 0` plane, and writes the depth of the
+//! hit point so it is correctly hidden behind opaque geometry drawn earlier in the pass.
+use ash::version::DeviceV1_0;
+use std;
+use vk::types::*;
+use ::debug_draw::{ self, DebugDraw };
+use ::math::{ Mat4, Vec3 };
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+
+pub const VERTEX_SHADER: &'static str = "shaders/grid_vertex.vert.spv";
+pub const FRAGMENT_SHADER: &'static str = "shaders/grid_fragment.frag.spv";
+
+/// Push constant block read by `shaders/grid_vertex.glsl` and `shaders/grid_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GridPushConstants {
+    pub view_projection: Mat4,
+    pub inverse_view_projection: Mat4,
+}
+
+impl GridPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT | SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<GridPushConstants>() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReferenceGrid {
+    pub show_grid: bool,
+    pub show_axes: bool,
+    /// Length of the world axis indicators drawn from the origin.
+    pub axis_length: f32,
+}
+
+impl ReferenceGrid {
+    pub fn new() -> ReferenceGrid {
+        ReferenceGrid {
+            show_grid: true,
+            show_axes: true,
+            axis_length: 1.0,
+        }
+    }
+
+    pub fn toggle_grid(&mut self) {
+        self.show_grid = !self.show_grid;
+        debug!("Reference grid: {}", self.show_grid);
+    }
+
+    pub fn toggle_axes(&mut self) {
+        self.show_axes = !self.show_axes;
+        debug!("World axes: {}", self.show_axes);
+    }
+
+    /// Adds the world axis indicators to this frame's debug lines.
+    pub fn draw_axes(&self, lines: &mut DebugDraw) {
+        if !self.show_axes {
+            return;
+        }
+        let origin = Vec3::zero();
+        lines.arrow(origin, Vec3::unit_x() * self.axis_length, debug_draw::RED);
+        lines.arrow(origin, Vec3::unit_y() * self.axis_length, debug_draw::GREEN);
+        lines.arrow(origin, Vec3::unit_z() * self.axis_length, debug_draw::BLUE);
+    }
+
+    /// Records the grid draw. Should come after all opaque geometry in the render pass, with the
+    /// grid pipeline already bound.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout, view_projection: &Mat4) {
+        if !self.show_grid {
+            return;
+        }
+        let inverse_view_projection = match view_projection.inverse() {
+            Some(inverse) => inverse,
+            None => {
+                warn!("Skipping grid draw for singular view-projection matrix");
+                return;
+            },
+        };
+        let constants = GridPushConstants {
+            view_projection: *view_projection,
+            inverse_view_projection: inverse_view_projection,
+        };
+        let range = GridPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const GridPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// The grid blends over the scene and is depth tested against it, but doesn't write depth so
+/// that transparent geometry drawn afterwards still shows through it.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .depth_test(false, CompareOp::LessOrEqual)
+        .blend(BlendPreset::Alpha)
+}
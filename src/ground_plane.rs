@@ -0,0 +1,67 @@
+//! A checkered ground plane stretching to the horizon, ray cast by
+//! `shaders/ground_plane_fragment.glsl` over a full-screen triangle, for comparing texture
+//! filtering. Towards the horizon each pixel covers a long, thin footprint of the texture: a
+//! trilinear sampler picks the mip for the long side and blurs the checkers into grey, while an
+//! anisotropic one takes several samples along it and keeps them sharp.
+use ash::version::DeviceV1_0;
+use std;
+use vk::types::*;
+use ::math::{ Mat4, Vec3 };
+use ::pipeline::GraphicsPipelineBuilder;
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/ground_plane_fragment.frag.spv";
+
+/// The most anisotropy asked for, before the device's maxSamplerAnisotropy.
+pub const MAX_ANISOTROPY: f32 = 16.0;
+
+/// Texture repeats per world unit.
+const TILING: f32 = 0.25;
+
+/// Push constant block read by `shaders/ground_plane_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GroundPlanePushConstants {
+    /// Unprojects the full-screen triangle into world-space rays.
+    pub inverse_view_projection: Mat4,
+    /// Camera position, and the texture repeats per world unit in `w`.
+    pub eye: [f32; 4],
+}
+
+impl GroundPlanePushConstants {
+    /// `None` if `view_projection` can't be inverted.
+    pub fn new(view_projection: &Mat4, eye: Vec3) -> Option<GroundPlanePushConstants> {
+        view_projection.inverse().map(|inverse| GroundPlanePushConstants {
+            inverse_view_projection: inverse,
+            eye: [eye.x, eye.y, eye.z, TILING],
+        })
+    }
+
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<GroundPlanePushConstants>() as u32,
+        }
+    }
+
+    /// Records pushing the constants and the full-screen draw, with the ground plane pipeline and
+    /// its texture bound.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let range = GroundPlanePushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, self as *const GroundPlanePushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// The anisotropy to sample with on a device whose limits are `limits`.
+pub fn max_anisotropy(limits: &PhysicalDeviceLimits) -> f32 {
+    MAX_ANISOTROPY.min(limits.max_sampler_anisotropy)
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl`, ray cast by `fragment_shader`.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
@@ -0,0 +1,528 @@
+//! Running expensive screen-space effects (SSAO, volumetrics, reflections) below full resolution
+//! and bringing them back up with a depth-aware bilateral upsample while compositing. Each effect
+//! gets an `EffectTarget` at its configured `EffectScale`: a storage image for the effect's own
+//! compute pass to write, and a copy of the scene depth reduced to the same size
+//! (`shaders/depth_downsample.glsl`). The composite (`shaders/bilateral_upsample_fragment.glsl`)
+//! weighs the four nearest low resolution texels by how close their depth is to the full
+//! resolution pixel's, so effects don't bleed across silhouettes the way a plain bilinear
+//! upsample would. Scales are set per effect with `--effect-scale=<effect>:<scale>`, e.g.
+//! `--effect-scale=ssao:quarter`.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::ComputeKernel;
+use ::cvar::{ self, Flag };
+use ::descriptor::DescriptorWrite;
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::rect::ExtentExt;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const ARG_PREFIX: &'static str = "--effect-scale=";
+pub const CVAR: &'static str = "r.effect_scale";
+pub const FLAGS: &'static [Flag] = &[Flag::List { prefix: ARG_PREFIX, name: CVAR }];
+
+pub const DEPTH_DOWNSAMPLE_SHADER: &'static str = "shaders/depth_downsample.comp.spv";
+pub const UPSAMPLE_FRAGMENT_SHADER: &'static str = "shaders/bilateral_upsample_fragment.frag.spv";
+
+/// Binding of the full resolution depth, read through a sampler, in both the downsample and the
+/// upsample sets.
+pub const FULL_DEPTH_BINDING: u32 = 0;
+/// Binding of the reduced depth: a storage image when downsampling, sampled when upsampling.
+pub const REDUCED_DEPTH_BINDING: u32 = 1;
+/// Binding of the reduced effect in the upsample set.
+pub const EFFECT_BINDING: u32 = 2;
+
+/// Invocations per axis of a `shaders/depth_downsample.glsl` workgroup.
+const LOCAL_SIZE: u32 = 8;
+
+/// Matches the `rgba16f` the effects write.
+const EFFECT_FORMAT: Format = Format::R16g16b16a16Sfloat;
+/// Matches the `r32f` qualifier in `shaders/depth_downsample.glsl`.
+const DEPTH_FORMAT: Format = Format::R32Sfloat;
+
+/// How far apart depths can be, relative to the pixel's own, before a low resolution texel stops
+/// counting, see `UpsamplePushConstants::depth_sigma`.
+pub const DEFAULT_DEPTH_SIGMA: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    Ssao,
+    Volumetrics,
+    Reflections,
+}
+
+impl Effect {
+    pub fn all() -> [Effect; 3] {
+        [Effect::Ssao, Effect::Volumetrics, Effect::Reflections]
+    }
+
+    /// The name used by `--effect-scale`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Effect::Ssao => "ssao",
+            Effect::Volumetrics => "volumetrics",
+            Effect::Reflections => "reflections",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Effect> {
+        Effect::all().iter().cloned().find(|effect| effect.name() == name)
+    }
+
+    /// How the upsampled effect is blended onto the lit image. SSAO writes black with the
+    /// occlusion in alpha and volumetrics write premultiplied in-scattering with one minus the
+    /// transmittance in alpha, so both darken or fog what's behind them; reflections add on top.
+    pub fn blend(&self) -> BlendPreset {
+        match *self {
+            Effect::Ssao | Effect::Volumetrics => BlendPreset::Premultiplied,
+            Effect::Reflections => BlendPreset::Additive,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match *self {
+            Effect::Ssao => 0,
+            Effect::Volumetrics => 1,
+            Effect::Reflections => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectScale {
+    Full,
+    Half,
+    Quarter,
+}
+
+impl EffectScale {
+    /// Full resolution pixels per reduced texel along each axis.
+    pub fn divisor(&self) -> u32 {
+        match *self {
+            EffectScale::Full => 1,
+            EffectScale::Half => 2,
+            EffectScale::Quarter => 4,
+        }
+    }
+
+    pub fn next(&self) -> EffectScale {
+        match *self {
+            EffectScale::Full => EffectScale::Half,
+            EffectScale::Half => EffectScale::Quarter,
+            EffectScale::Quarter => EffectScale::Full,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EffectScale> {
+        match name {
+            "full" => Some(EffectScale::Full),
+            "half" => Some(EffectScale::Half),
+            "quarter" => Some(EffectScale::Quarter),
+            _ => None,
+        }
+    }
+
+    /// The reduced size of a `full` sized image, rounding up so every full resolution pixel is
+    /// covered.
+    pub fn extent(&self, full: &Extent2D) -> Extent2D {
+        full.div_ceil(self.divisor())
+    }
+}
+
+/// The resolution each effect runs at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectScales {
+    scales: [EffectScale; 3],
+}
+
+impl Default for EffectScales {
+    fn default() -> EffectScales {
+        EffectScales {
+            scales: [EffectScale::Half; 3],
+        }
+    }
+}
+
+impl EffectScales {
+    /// Every effect at half resolution, except those in `r.effect_scale`, a list of
+    /// `<effect>:<scale>` where the scale is `full`, `half` or `quarter`. Each
+    /// `--effect-scale=<effect>:<scale>` adds one.
+    pub fn from_cvars() -> EffectScales {
+        EffectScales::parse(&cvar!(CVAR, String::new()).get())
+    }
+
+    fn parse(list: &str) -> EffectScales {
+        let mut ret = EffectScales::default();
+        for entry in list.split(cvar::LIST_SEPARATOR).filter(|entry| !entry.is_empty()) {
+            let mut parts = entry.splitn(2, ':');
+            let effect = parts.next().and_then(Effect::from_name);
+            let scale = parts.next().and_then(EffectScale::from_name);
+            match (effect, scale) {
+                (Some(effect), Some(scale)) => ret.set(effect, scale),
+                _ => warn!("Invalid effect scale {:?}, expected {}<effect>:<full|half|quarter>", entry, ARG_PREFIX),
+            }
+        }
+        ret
+    }
+
+    pub fn get(&self, effect: Effect) -> EffectScale {
+        self.scales[effect.index()]
+    }
+
+    pub fn set(&mut self, effect: Effect, scale: EffectScale) {
+        self.scales[effect.index()] = scale;
+        debug!("Running {} at {:?} resolution", effect.name(), scale);
+    }
+}
+
+/// Push constant block read by `shaders/depth_downsample.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DownsamplePushConstants {
+    /// `EffectScale::divisor` of the target.
+    pub divisor: u32,
+}
+
+/// Push constant block read by `shaders/bilateral_upsample_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UpsamplePushConstants {
+    /// Size of one reduced texel in texture coordinates.
+    pub reduced_texel: [f32; 2],
+    /// Falloff of the depth weight, as a fraction of the full resolution pixel's depth. Smaller
+    /// keeps edges sharper but shows more of the reduced resolution's stair-stepping.
+    pub depth_sigma: f32,
+}
+
+impl UpsamplePushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<UpsamplePushConstants>() as u32,
+        }
+    }
+}
+
+/// One effect's reduced resolution output and depth. Sized to the full resolution target, so
+/// it's recreated with it.
+pub struct EffectTarget<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    effect: Effect,
+    scale: EffectScale,
+    extent: Extent2D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+    depth_image: Image,
+    depth_memory: VkAllocation<'d, D>,
+    depth_view: ImageView,
+    /// Nearest, since both passes compare individual texels' depths.
+    sampler: Sampler,
+    /// Whether the images have left `Undefined` for `General`.
+    initialized: bool,
+    depth_initialized: bool,
+}
+
+impl<'d, D: DeviceV1_0> EffectTarget<'d, D> {
+    /// Creates the target for running `effect` at `scale` of `full_extent`. Both images are
+    /// undefined until the first `cmd_downsample_depth` and `cmd_begin_effect`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, effect: Effect, scale: EffectScale, full_extent: &Extent2D) -> VkResult<EffectTarget<'d, D>> {
+        // Destroying/freeing null handles is a no-op, so from here on out `Drop` will clean up
+        // after us if anything goes wrong.
+        let mut ret = EffectTarget {
+            device: device,
+            effect: effect,
+            scale: scale,
+            extent: scale.extent(full_extent),
+            image: Image::null(),
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+            depth_image: Image::null(),
+            depth_memory: VkAllocation::null(),
+            depth_view: ImageView::null(),
+            sampler: Sampler::null(),
+            initialized: false,
+            depth_initialized: false,
+        };
+        create_image(device, allocator, EFFECT_FORMAT, &ret.extent, &mut ret.image, &mut ret.memory, &mut ret.view)?;
+        create_image(device, allocator, DEPTH_FORMAT, &ret.extent, &mut ret.depth_image, &mut ret.depth_memory, &mut ret.depth_view)?;
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+        debug!("Created {}x{} {} target at {:?} resolution", ret.extent.width, ret.extent.height, effect.name(), scale);
+        Ok(ret)
+    }
+
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+
+    pub fn scale(&self) -> EffectScale {
+        self.scale
+    }
+
+    /// The reduced size, what the effect's own pass dispatches over.
+    pub fn extent(&self) -> &Extent2D {
+        &self.extent
+    }
+
+    /// Descriptor set layout bindings for the depth downsample kernel.
+    pub fn downsample_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![
+            DescriptorSetLayoutBinding {
+                binding: FULL_DEPTH_BINDING,
+                descriptor_type: DescriptorType::CombinedImageSampler,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+            DescriptorSetLayoutBinding {
+                binding: REDUCED_DEPTH_BINDING,
+                descriptor_type: DescriptorType::StorageImage,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+        ]
+    }
+
+    /// Descriptor set layout bindings for the composite's bilateral upsample.
+    pub fn upsample_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        [FULL_DEPTH_BINDING, REDUCED_DEPTH_BINDING, EFFECT_BINDING].iter()
+            .map(|&binding| DescriptorSetLayoutBinding {
+                binding: binding,
+                descriptor_type: DescriptorType::CombinedImageSampler,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+                p_immutable_samplers: ptr::null(),
+            })
+            .collect()
+    }
+
+    /// Writes for the downsample kernel's set, reading the scene depth from `full_depth` in
+    /// `full_depth_layout`.
+    pub fn downsample_writes(&self, full_depth: ImageView, full_depth_layout: ImageLayout) -> [DescriptorWrite; 2] {
+        [
+            DescriptorWrite::CombinedImageSampler {
+                binding: FULL_DEPTH_BINDING,
+                sampler: self.sampler,
+                image_view: full_depth,
+                image_layout: full_depth_layout,
+            },
+            DescriptorWrite::StorageImage {
+                binding: REDUCED_DEPTH_BINDING,
+                image_view: self.depth_view,
+                image_layout: ImageLayout::General,
+            },
+        ]
+    }
+
+    /// Writes for the composite's set. Like `downsample_writes`, these only change when the
+    /// scene depth does.
+    pub fn upsample_writes(&self, full_depth: ImageView, full_depth_layout: ImageLayout) -> [DescriptorWrite; 3] {
+        [
+            DescriptorWrite::CombinedImageSampler {
+                binding: FULL_DEPTH_BINDING,
+                sampler: self.sampler,
+                image_view: full_depth,
+                image_layout: full_depth_layout,
+            },
+            self.reduced_depth_write(REDUCED_DEPTH_BINDING),
+            DescriptorWrite::CombinedImageSampler {
+                binding: EFFECT_BINDING,
+                sampler: self.sampler,
+                image_view: self.view,
+                image_layout: ImageLayout::General,
+            },
+        ]
+    }
+
+    /// The effect's output as a storage image, for the effect's own pass.
+    pub fn effect_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::StorageImage {
+            binding: binding,
+            image_view: self.view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    /// The reduced depth, sampled, for effects that want depth at their own resolution.
+    pub fn reduced_depth_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: binding,
+            sampler: self.sampler,
+            image_view: self.depth_view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    pub fn upsample_push_constants(&self, depth_sigma: f32) -> UpsamplePushConstants {
+        UpsamplePushConstants {
+            reduced_texel: [1.0 / self.extent.width as f32, 1.0 / self.extent.height as f32],
+            depth_sigma: depth_sigma,
+        }
+    }
+
+    /// Records reducing the scene depth with `kernel` (built from `DEPTH_DOWNSAMPLE_SHADER` and
+    /// `downsample_bindings`) and `descriptor_set` (holding `downsample_writes`), followed by a
+    /// barrier for compute and fragment reads. Each reduced texel keeps the nearest or the
+    /// farthest depth of its footprint in a checkerboard, so both sides of an edge survive. The
+    /// scene depth must already be readable by compute shaders. Must be outside a render pass.
+    pub unsafe fn cmd_downsample_depth(&mut self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>, descriptor_set: DescriptorSet) {
+        let initialized = self.depth_initialized;
+        self.cmd_to_compute_write(command_buffer, self.depth_image, initialized);
+        self.depth_initialized = true;
+        let groups = self.extent.div_ceil(LOCAL_SIZE);
+        kernel.cmd_dispatch(command_buffer, descriptor_set, &DownsamplePushConstants { divisor: self.scale.divisor() }, (groups.width, groups.height, 1));
+        self.cmd_to_reads(command_buffer, self.depth_image);
+    }
+
+    /// Records the barrier before the effect's own pass writes its output in a compute shader.
+    pub unsafe fn cmd_begin_effect(&mut self, command_buffer: CommandBuffer) {
+        let initialized = self.initialized;
+        self.cmd_to_compute_write(command_buffer, self.image, initialized);
+        self.initialized = true;
+    }
+
+    /// Records the barrier after the effect's pass, before the composite samples its output.
+    pub unsafe fn cmd_end_effect(&self, command_buffer: CommandBuffer) {
+        self.cmd_to_reads(command_buffer, self.image);
+    }
+
+    unsafe fn cmd_to_compute_write(&self, command_buffer: CommandBuffer, image: Image, initialized: bool) {
+        let (old_layout, src_stage, src_access) = if initialized {
+            // The previous frame's passes may still be reading it
+            (ImageLayout::General, PIPELINE_STAGE_COMPUTE_SHADER_BIT | PIPELINE_STAGE_FRAGMENT_SHADER_BIT, ACCESS_SHADER_READ_BIT)
+        } else {
+            (ImageLayout::Undefined, PIPELINE_STAGE_TOP_OF_PIPE_BIT, Default::default())
+        };
+        let barrier = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: src_access,
+            dst_access_mask: ACCESS_SHADER_WRITE_BIT,
+            old_layout: old_layout,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: image,
+            subresource_range: color_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, src_stage, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[barrier]);
+    }
+
+    unsafe fn cmd_to_reads(&self, command_buffer: CommandBuffer, image: Image) {
+        let barrier = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::General,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: image,
+            subresource_range: color_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT | PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &[barrier]);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for EffectTarget<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying {} target: {:?}", self.effect.name(), self.image);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_image_view(self.depth_view, None);
+            self.device.destroy_image(self.depth_image, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+fn color_range() -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// Creates a storage and sampled 2D image with its memory and view, storing each handle as soon
+/// as it exists so the owner's `Drop` cleans up after a failure part way.
+fn create_image<'d, D: DeviceV1_0>(device: &D, allocator: &'d MemoryAllocator<D>, format: Format, extent: &Extent2D, image: &mut Image, memory: &mut VkAllocation<'d, D>, view: &mut ImageView) -> VkResult<()> {
+    let image_create_info = ImageCreateInfo {
+        s_type: StructureType::ImageCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        image_type: ImageType::Type2d,
+        format: format,
+        extent: Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+        mip_levels: 1,
+        array_layers: 1,
+        samples: SAMPLE_COUNT_1_BIT,
+        tiling: ImageTiling::Optimal,
+        usage: IMAGE_USAGE_STORAGE_BIT | IMAGE_USAGE_SAMPLED_BIT,
+        sharing_mode: SharingMode::Exclusive,
+        queue_family_index_count: 0,
+        p_queue_family_indices: ptr::null(),
+        initial_layout: ImageLayout::Undefined,
+    };
+    *image = unsafe { device.create_image(&image_create_info, None) }?;
+    *memory = allocator.allocate_image_memory(*image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+    let view_create_info = ImageViewCreateInfo {
+        s_type: StructureType::ImageViewCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        image: *image,
+        view_type: ImageViewType::Type2d,
+        format: format,
+        components: ComponentMapping {
+            r: ComponentSwizzle::Identity,
+            g: ComponentSwizzle::Identity,
+            b: ComponentSwizzle::Identity,
+            a: ComponentSwizzle::Identity,
+        },
+        subresource_range: color_range(),
+    };
+    *view = unsafe { device.create_image_view(&view_create_info, None) }?;
+    Ok(())
+}
+
+/// A builder for the composite pipeline of `effect`, drawn with `shaders/fullscreen_vertex.glsl`
+/// and `UPSAMPLE_FRAGMENT_SHADER` over the lit image.
+pub fn upsample_pipeline_builder(extent: &Extent2D, effect: Effect, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .blend(effect.blend())
+}
@@ -0,0 +1,33 @@
+//! Raw handle values, in the shape of newer ash's `vk::Handle`. ash 0.18 keeps them private, so
+//! this is the one place that reaches into a handle's representation; code that needs the raw
+//! value (debug names, for one) goes through `Handle::as_raw` instead of transmuting.
+use std;
+use vk::types::*;
+
+pub trait Handle: Copy {
+    /// The handle as the `uint64_t` Vulkan passes it around as, e.g. in
+    /// `VkDebugUtilsObjectNameInfoEXT`.
+    fn as_raw(self) -> u64;
+}
+
+macro_rules! non_dispatchable {
+    ($($handle: ident),*) => {
+        $(
+            impl Handle for $handle {
+                fn as_raw(self) -> u64 {
+                    // Non-dispatchable handles are a `u64` underneath
+                    unsafe { std::mem::transmute::<$handle, u64>(self) }
+                }
+            }
+        )*
+    }
+}
+
+non_dispatchable!(Buffer, Image, ImageView, ShaderModule, PipelineLayout, RenderPass, Pipeline, Framebuffer);
+
+impl Handle for Device {
+    fn as_raw(self) -> u64 {
+        // Dispatchable handles are pointers
+        unsafe { std::mem::transmute::<Device, usize>(self) as u64 }
+    }
+}
@@ -0,0 +1,248 @@
+//! HDR output, with `--hdr`. When the surface offers an HDR color space (which needs the
+//! `VK_EXT_swapchain_colorspace` instance extension to be listed at all), the swapchain uses it,
+//! and with `VK_EXT_hdr_metadata` it's told the mastering display's primaries and luminance from
+//! the `hdr.*` cvars.
+//!
+//! `ash`'s `ColorSpaceKHR` only knows sRGB, and an enum can't hold the others, so color spaces
+//! are kept as raw `ColorSpace` values and surface formats are queried through a hand-loaded
+//! `vkGetPhysicalDeviceSurfaceFormatsKHR`. The scene writes linear color, which scRGB takes as
+//! is; HDR10 wants PQ-encoded color, which nothing writes yet, so it's only the fallback.
+use ash::version::{ DeviceV1_0, EntryV1_0, InstanceV1_0 };
+use std;
+use std::ffi::{ CStr, CString };
+use std::fmt;
+use std::ptr;
+use vk::types::*;
+use ::cvar::Flag;
+
+pub const ARG: &'static str = "--hdr";
+pub const CVAR: &'static str = "hdr.enabled";
+pub const FLAGS: &'static [Flag] = &[Flag::Switch { arg: ARG, name: CVAR }];
+pub const COLORSPACE_EXTENSION: &'static str = "VK_EXT_swapchain_colorspace";
+pub const METADATA_EXTENSION: &'static str = "VK_EXT_hdr_metadata";
+
+const STRUCTURE_TYPE_HDR_METADATA: u32 = 1000105000;
+
+/// A `VkColorSpaceKHR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpace(pub u32);
+
+impl ColorSpace {
+    pub const SRGB_NONLINEAR: ColorSpace = ColorSpace(0);
+    pub const DISPLAY_P3_NONLINEAR: ColorSpace = ColorSpace(1000104001);
+    pub const EXTENDED_SRGB_LINEAR: ColorSpace = ColorSpace(1000104002);
+    pub const DISPLAY_P3_LINEAR: ColorSpace = ColorSpace(1000104003);
+    pub const DCI_P3_NONLINEAR: ColorSpace = ColorSpace(1000104004);
+    pub const BT709_LINEAR: ColorSpace = ColorSpace(1000104005);
+    pub const BT709_NONLINEAR: ColorSpace = ColorSpace(1000104006);
+    pub const BT2020_LINEAR: ColorSpace = ColorSpace(1000104007);
+    pub const HDR10_ST2084: ColorSpace = ColorSpace(1000104008);
+    pub const DOLBY_VISION: ColorSpace = ColorSpace(1000104009);
+    pub const HDR10_HLG: ColorSpace = ColorSpace(1000104010);
+    pub const ADOBE_RGB_LINEAR: ColorSpace = ColorSpace(1000104011);
+    pub const ADOBE_RGB_NONLINEAR: ColorSpace = ColorSpace(1000104012);
+    pub const PASS_THROUGH: ColorSpace = ColorSpace(1000104013);
+    pub const EXTENDED_SRGB_NONLINEAR: ColorSpace = ColorSpace(1000104014);
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ColorSpace::SRGB_NONLINEAR => "sRGB",
+            ColorSpace::DISPLAY_P3_NONLINEAR => "Display P3",
+            ColorSpace::EXTENDED_SRGB_LINEAR => "scRGB linear",
+            ColorSpace::DISPLAY_P3_LINEAR => "Display P3 linear",
+            ColorSpace::DCI_P3_NONLINEAR => "DCI-P3",
+            ColorSpace::BT709_LINEAR => "BT.709 linear",
+            ColorSpace::BT709_NONLINEAR => "BT.709",
+            ColorSpace::BT2020_LINEAR => "BT.2020 linear",
+            ColorSpace::HDR10_ST2084 => "HDR10 (PQ)",
+            ColorSpace::DOLBY_VISION => "Dolby Vision",
+            ColorSpace::HDR10_HLG => "HDR10 (HLG)",
+            ColorSpace::ADOBE_RGB_LINEAR => "Adobe RGB linear",
+            ColorSpace::ADOBE_RGB_NONLINEAR => "Adobe RGB",
+            ColorSpace::PASS_THROUGH => "pass-through",
+            ColorSpace::EXTENDED_SRGB_NONLINEAR => "scRGB",
+            _ => "unknown",
+        }
+    }
+
+    /// Whether HDR metadata means anything for it.
+    pub fn is_hdr(&self) -> bool {
+        matches!(*self, ColorSpace::EXTENDED_SRGB_LINEAR | ColorSpace::EXTENDED_SRGB_NONLINEAR | ColorSpace::BT2020_LINEAR | ColorSpace::HDR10_ST2084 | ColorSpace::DOLBY_VISION | ColorSpace::HDR10_HLG)
+    }
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            "unknown" => write!(f, "color space {}", self.0),
+            name => write!(f, "{}", name),
+        }
+    }
+}
+
+/// HDR surface formats in order of preference.
+const HDR_SURFACE_FORMATS: [(Format, ColorSpace); 2] = [
+    (Format::R16g16b16a16Sfloat, ColorSpace::EXTENDED_SRGB_LINEAR),
+    (Format::A2b10g10r10UnormPack32, ColorSpace::HDR10_ST2084),
+];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SurfaceFormatRaw {
+    format: Format,
+    color_space: u32,
+}
+
+type GetPhysicalDeviceSurfaceFormatsFn = unsafe extern "system" fn(PhysicalDevice, SurfaceKHR, *mut u32, *mut SurfaceFormatRaw) -> Result;
+
+#[repr(C)]
+struct XyColorRaw {
+    x: f32,
+    y: f32,
+}
+
+#[repr(C)]
+struct HdrMetadataRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    display_primary_red: XyColorRaw,
+    display_primary_green: XyColorRaw,
+    display_primary_blue: XyColorRaw,
+    white_point: XyColorRaw,
+    max_luminance: f32,
+    min_luminance: f32,
+    max_content_light_level: f32,
+    max_frame_average_light_level: f32,
+}
+
+type SetHdrMetadataFn = unsafe extern "system" fn(Device, u32, *const SwapchainKHR, *const HdrMetadataRaw);
+
+/// Whether `hdr.enabled`, which `ARG` sets, is on.
+pub fn requested() -> bool {
+    cvar!(CVAR, false).get()
+}
+
+/// Whether the instance can have `COLORSPACE_EXTENSION`.
+pub fn instance_supported<E: EntryV1_0>(entry: &E) -> bool {
+    entry.enumerate_instance_extension_properties()
+        .map(|extensions| extensions.iter().any(|properties| unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) }.to_bytes() == COLORSPACE_EXTENSION.as_bytes()))
+        .unwrap_or(false)
+}
+
+/// Every format and color space `surface` offers, including the ones `ash` can't represent.
+pub fn surface_formats<E: EntryV1_0, I: InstanceV1_0>(entry: &E, instance: &I, physical_device: PhysicalDevice, surface: SurfaceKHR) -> Vec<(Format, ColorSpace)> {
+    let name = CString::new("vkGetPhysicalDeviceSurfaceFormatsKHR").unwrap();
+    let pfn = entry.get_instance_proc_addr(instance.handle(), name.as_ptr());
+    if pfn as usize == 0 {
+        return Vec::new();
+    }
+    let get_surface_formats = unsafe { std::mem::transmute::<PFN_vkVoidFunction, GetPhysicalDeviceSurfaceFormatsFn>(pfn) };
+    let mut count = 0;
+    unsafe { get_surface_formats(physical_device, surface, &mut count, ptr::null_mut()) };
+    let mut formats = Vec::with_capacity(count as usize);
+    match unsafe { get_surface_formats(physical_device, surface, &mut count, formats.as_mut_ptr()) } {
+        Result::Success | Result::Incomplete => unsafe { formats.set_len(count as usize) },
+        _ => return Vec::new(),
+    }
+    formats.into_iter().map(|format| (format.format, ColorSpace(format.color_space))).collect()
+}
+
+/// The most preferred HDR format and color space of `formats`.
+pub fn choose_surface_format(formats: &[(Format, ColorSpace)]) -> Option<(Format, ColorSpace)> {
+    HDR_SURFACE_FORMATS.iter().cloned().find(|candidate| formats.contains(candidate))
+}
+
+/// Makes `create_info` use `color_space`. Do it right before creating the swapchain, since the
+/// field is left holding a value its type doesn't know, which isn't safe to look at from Rust.
+pub unsafe fn set_color_space(create_info: &mut SwapchainCreateInfoKHR, color_space: ColorSpace) {
+    ptr::write(&mut create_info.image_color_space as *mut ColorSpaceKHR as *mut u32, color_space.0);
+}
+
+/// CIE 1931 xy chromaticities of a display's primaries and white point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Primaries {
+    pub red: (f32, f32),
+    pub green: (f32, f32),
+    pub blue: (f32, f32),
+    pub white: (f32, f32),
+}
+
+/// The D65 white point all the named primaries use.
+const D65: (f32, f32) = (0.3127, 0.3290);
+
+impl Primaries {
+    /// `bt2020`, `p3` or `bt709`.
+    pub fn named(name: &str) -> Option<Primaries> {
+        let (red, green, blue) = match name {
+            "bt2020" => ((0.708, 0.292), (0.170, 0.797), (0.131, 0.046)),
+            "p3" => ((0.680, 0.320), (0.265, 0.690), (0.150, 0.060)),
+            "bt709" => ((0.640, 0.330), (0.300, 0.600), (0.150, 0.060)),
+            _ => return None,
+        };
+        Some(Primaries {
+            red: red,
+            green: green,
+            blue: blue,
+            white: D65,
+        })
+    }
+}
+
+/// What the content was mastered for, which the display uses to tone map it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrMetadata {
+    pub primaries: Primaries,
+    /// In nits.
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+    pub max_content_light_level: f32,
+    pub max_frame_average_light_level: f32,
+}
+
+impl HdrMetadata {
+    /// From the `hdr.*` cvars, read when the swapchain is created.
+    pub fn from_cvars() -> HdrMetadata {
+        let primaries_name: String = cvar!("hdr.primaries", "bt2020".to_string()).get();
+        let primaries = Primaries::named(&primaries_name).unwrap_or_else(|| {
+            warn!("Unknown HDR primaries {:?}, expected bt2020, p3 or bt709; using bt2020", primaries_name);
+            Primaries::named("bt2020").unwrap()
+        });
+        HdrMetadata {
+            primaries: primaries,
+            max_luminance: cvar!("hdr.max_luminance", 1000.0).get(),
+            min_luminance: cvar!("hdr.min_luminance", 0.001).get(),
+            max_content_light_level: cvar!("hdr.max_content_light_level", 1000.0).get(),
+            max_frame_average_light_level: cvar!("hdr.max_frame_average_light_level", 400.0).get(),
+        }
+    }
+
+    /// Sets it on `swapchain`, which needs `METADATA_EXTENSION` enabled on `device`. Returns whether
+    /// the entry point was there to.
+    pub fn apply<I: InstanceV1_0, D: DeviceV1_0>(&self, instance: &I, device: &D, swapchain: SwapchainKHR) -> bool {
+        let name = CString::new("vkSetHdrMetadataEXT").unwrap();
+        let pfn = instance.get_device_proc_addr(device.handle(), name.as_ptr());
+        if pfn as usize == 0 {
+            return false;
+        }
+        let set_hdr_metadata = unsafe { std::mem::transmute::<PFN_vkVoidFunction, SetHdrMetadataFn>(pfn) };
+        let xy = |(x, y): (f32, f32)| XyColorRaw {
+            x: x,
+            y: y,
+        };
+        let metadata = HdrMetadataRaw {
+            s_type: STRUCTURE_TYPE_HDR_METADATA,
+            p_next: ptr::null(),
+            display_primary_red: xy(self.primaries.red),
+            display_primary_green: xy(self.primaries.green),
+            display_primary_blue: xy(self.primaries.blue),
+            white_point: xy(self.primaries.white),
+            max_luminance: self.max_luminance,
+            min_luminance: self.min_luminance,
+            max_content_light_level: self.max_content_light_level,
+            max_frame_average_light_level: self.max_frame_average_light_level,
+        };
+        unsafe { set_hdr_metadata(device.handle(), 1, &swapchain, &metadata) };
+        debug!("Set HDR metadata on {:?}: {:?}", swapchain, self);
+        true
+    }
+}
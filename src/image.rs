@@ -0,0 +1,421 @@
+//! Textures loaded from image files. PNG and baseline JPEG files are decoded to RGBA8 on the CPU
+//! (see `png` and `jpeg`), copied into a device-local image through a staging buffer, blitted
+//! down into mips when the format allows it, and transitioned to `ShaderReadOnlyOptimal`. A
+//! `SampledTexture` adds the descriptor set binding it as a combined image sampler for fragment
+//! shaders.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::ptr;
+use vk::types::*;
+use ::buffer::DeviceBuffer;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::format_support::FormatSupport;
+use ::gamma::TextureEncoding;
+use ::immediate::ImmediateContext;
+use ::jpeg;
+use ::logging;
+use ::png;
+use ::rect;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+/// Binding of the texture in a `SampledTexture`'s set.
+pub const BINDING: u32 = 0;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    /// A valid file using something the decoders don't handle.
+    Unsupported(String),
+    Corrupt(String),
+}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> ImageError {
+        ImageError::Io(e)
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageError::Io(ref e) => write!(f, "{}", e),
+            ImageError::Unsupported(ref what) => write!(f, "unsupported: {}", what),
+            ImageError::Corrupt(ref what) => write!(f, "corrupt: {}", what),
+        }
+    }
+}
+
+/// Decoded pixels, tightly packed RGBA8 rows from the top down.
+#[derive(Debug, Clone)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl ImageData {
+    /// A `size` square checkerboard with `cells` cells across, for when there's no file to load.
+    pub fn checkerboard(size: u32, cells: u32) -> ImageData {
+        let cell_size = std::cmp::max(size / cells, 1);
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let value = if (x / cell_size + y / cell_size).is_multiple_of(2) { 255 } else { 64 };
+                pixels.extend_from_slice(&[value, value, value, 255]);
+            }
+        }
+        ImageData {
+            width: size,
+            height: size,
+            pixels: pixels,
+        }
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        rect::extent(self.width, self.height)
+    }
+}
+
+/// Decodes a PNG or JPEG, going by its contents rather than the extension.
+pub fn decode(data: &[u8]) -> std::result::Result<ImageData, ImageError> {
+    if data.starts_with(&png::SIGNATURE) {
+        png::decode(data)
+    } else if jpeg::is_jpeg(data) {
+        jpeg::decode(data)
+    } else {
+        Err(ImageError::Unsupported("file format, only PNG and JPEG can be loaded".to_string()))
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> std::result::Result<ImageData, ImageError> {
+    let data = ::read_full_file(&path.as_ref().to_string_lossy())?;
+    let ret = decode(&data)?;
+    debug!(target: logging::UPLOAD, "Decoded {}x{} image from {:?}", ret.width, ret.height, path.as_ref());
+    Ok(ret)
+}
+
+/// A device-local 2D image with a view and a sampler, ready for sampling from fragment shaders.
+pub struct Texture<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+    sampler: Sampler,
+    extent: Extent2D,
+    mip_levels: u32,
+}
+
+/// Levels in a full mip chain for `extent`, down to 1x1.
+pub fn full_mip_count(extent: &Extent2D) -> u32 {
+    32 - std::cmp::max(std::cmp::max(extent.width, extent.height), 1).leading_zeros()
+}
+
+/// Mips to give a texture of `data` in the format `support` is for: the full chain when each
+/// level can be blitted from the one above, or only the top level when it can't.
+pub fn mip_levels(support: &FormatSupport, data: &ImageData) -> u32 {
+    if support.supports_blit_mip_generation() {
+        full_mip_count(&data.extent())
+    } else {
+        debug!(target: logging::UPLOAD, "{:?} can't be blitted with linear filtering, so its textures get no mips", support.format);
+        1
+    }
+}
+
+/// How `Texture::new` creates the image for `data`, e.g. to check it against the device's limits
+/// first.
+pub fn texture_create_info(data: &ImageData, encoding: TextureEncoding, mip_levels: u32) -> ImageCreateInfo {
+    // Mips are blitted from the level above, so that has to be a transfer source too
+    let usage = if mip_levels > 1 {
+        IMAGE_USAGE_TRANSFER_SRC_BIT | IMAGE_USAGE_TRANSFER_DST_BIT | IMAGE_USAGE_SAMPLED_BIT
+    } else {
+        IMAGE_USAGE_TRANSFER_DST_BIT | IMAGE_USAGE_SAMPLED_BIT
+    };
+    ImageCreateInfo {
+        s_type: StructureType::ImageCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        image_type: ImageType::Type2d,
+        format: encoding.format(Format::R8g8b8a8Unorm),
+        extent: Extent3D {
+            width: data.width,
+            height: data.height,
+            depth: 1,
+        },
+        mip_levels: mip_levels,
+        array_layers: 1,
+        samples: SAMPLE_COUNT_1_BIT,
+        tiling: ImageTiling::Optimal,
+        usage: usage,
+        sharing_mode: SharingMode::Exclusive,
+        queue_family_index_count: 0,
+        p_queue_family_indices: ptr::null(),
+        initial_layout: ImageLayout::Undefined,
+    }
+}
+
+/// A repeating, trilinear sampler over `mip_levels` mips, which also filters anisotropically with
+/// a `max_anisotropy` over 1. That needs the samplerAnisotropy feature, and is limited to the
+/// device's maxSamplerAnisotropy.
+pub fn sampler_create_info(mip_levels: u32, max_anisotropy: f32) -> SamplerCreateInfo {
+    SamplerCreateInfo {
+        s_type: StructureType::SamplerCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        mag_filter: Filter::Linear,
+        min_filter: Filter::Linear,
+        mipmap_mode: SamplerMipmapMode::Linear,
+        address_mode_u: SamplerAddressMode::Repeat,
+        address_mode_v: SamplerAddressMode::Repeat,
+        address_mode_w: SamplerAddressMode::Repeat,
+        mip_lod_bias: 0.0,
+        anisotropy_enable: (max_anisotropy > 1.0) as Bool32,
+        max_anisotropy: max_anisotropy.max(1.0),
+        compare_enable: 0,
+        compare_op: CompareOp::Always,
+        min_lod: 0.0,
+        max_lod: mip_levels as f32,
+        border_color: BorderColor::FloatTransparentBlack,
+        unnormalized_coordinates: 0,
+    }
+}
+
+impl<'d, D: DeviceV1_0> Texture<'d, D> {
+    /// Uploads `data` on `immediate`, blocking until it's done. `encoding` says whether the pixels
+    /// are colors, decoded from sRGB when sampled, or data sampled as is. With more than one of
+    /// `mip_levels`, which should come from `mip_levels`, the rest are generated from the first.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, immediate: &ImmediateContext<D>, data: &ImageData, encoding: TextureEncoding, mip_levels: u32) -> VkResult<Texture<'d, D>> {
+        let image_create_info = texture_create_info(data, encoding, mip_levels);
+        let format = image_create_info.format;
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+        // Destroying/freeing null handles is a no-op, so from here on out `Drop` will clean up
+        // after us if anything goes wrong.
+        let mut ret = Texture {
+            device: device,
+            image: image,
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+            sampler: Sampler::null(),
+            extent: data.extent(),
+            mip_levels: mip_levels,
+        };
+        ret.memory = allocator.allocate_image_memory(image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let staging = DeviceBuffer::host_visible(device, allocator, data.pixels.len() as DeviceSize, BUFFER_USAGE_TRANSFER_SRC_BIT)?;
+        staging.write(0, &data.pixels)?;
+        immediate.immediate_submit(|command_buffer| unsafe {
+            ret.cmd_upload(command_buffer, staging.buffer());
+        })?;
+        debug!(target: logging::UPLOAD, "Uploaded {}x{} {:?} texture with {} mips: {:?}", data.width, data.height, format, mip_levels, image);
+
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: image,
+            view_type: ImageViewType::Type2d,
+            format: format,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: color_range(0, mip_levels),
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+        let sampler_create_info = sampler_create_info(mip_levels, 1.0);
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+        Ok(ret)
+    }
+
+    /// Copies the staging buffer into the top level of the image, with the transitions from
+    /// `Undefined` to `TransferDstOptimal` and then, after generating any other levels, on to
+    /// `ShaderReadOnlyOptimal`.
+    unsafe fn cmd_upload(&self, command_buffer: CommandBuffer, src_buffer: Buffer) {
+        let to_transfer_dst = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: Default::default(),
+            dst_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::TransferDstOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: color_range(0, self.mip_levels),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[to_transfer_dst]);
+        let region = BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: ImageSubresourceLayers {
+                aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: Offset3D {
+                x: 0,
+                y: 0,
+                z: 0,
+            },
+            image_extent: Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            },
+        };
+        self.device.cmd_copy_buffer_to_image(command_buffer, src_buffer, self.image, ImageLayout::TransferDstOptimal, &[region]);
+        let mip_size = |size: u32, level: u32| std::cmp::max(size >> level, 1) as i32;
+        let corner = |level: u32| Offset3D {
+            x: mip_size(self.extent.width, level),
+            y: mip_size(self.extent.height, level),
+            z: 1,
+        };
+        for level in 1..self.mip_levels {
+            // The level above is written, so it can be read for this one and then sampled
+            let to_transfer_src = self.mip_barrier(level - 1, ImageLayout::TransferDstOptimal, ImageLayout::TransferSrcOptimal, ACCESS_TRANSFER_WRITE_BIT, ACCESS_TRANSFER_READ_BIT);
+            self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[to_transfer_src]);
+            let blit = ImageBlit {
+                src_subresource: color_layers(level - 1),
+                src_offsets: [Offset3D { x: 0, y: 0, z: 0 }, corner(level - 1)],
+                dst_subresource: color_layers(level),
+                dst_offsets: [Offset3D { x: 0, y: 0, z: 0 }, corner(level)],
+            };
+            self.device.fp_v1_0().cmd_blit_image(command_buffer, self.image, ImageLayout::TransferSrcOptimal, self.image, ImageLayout::TransferDstOptimal, 1, &blit, Filter::Linear);
+            let to_shader_read = self.mip_barrier(level - 1, ImageLayout::TransferSrcOptimal, ImageLayout::ShaderReadOnlyOptimal, ACCESS_TRANSFER_READ_BIT, ACCESS_SHADER_READ_BIT);
+            self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &[to_shader_read]);
+        }
+        let to_shader_read = self.mip_barrier(self.mip_levels - 1, ImageLayout::TransferDstOptimal, ImageLayout::ShaderReadOnlyOptimal, ACCESS_TRANSFER_WRITE_BIT, ACCESS_SHADER_READ_BIT);
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &[to_shader_read]);
+    }
+
+    fn mip_barrier(&self, level: u32, old_layout: ImageLayout, new_layout: ImageLayout, src_access_mask: AccessFlags, dst_access_mask: AccessFlags) -> ImageMemoryBarrier {
+        ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: src_access_mask,
+            dst_access_mask: dst_access_mask,
+            old_layout: old_layout,
+            new_layout: new_layout,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: color_range(level, 1),
+        }
+    }
+
+    pub fn descriptor_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: binding,
+            sampler: self.sampler,
+            image_view: self.view,
+            image_layout: ImageLayout::ShaderReadOnlyOptimal,
+        }
+    }
+
+    pub fn view(&self) -> ImageView {
+        self.view
+    }
+
+    pub fn sampler(&self) -> Sampler {
+        self.sampler
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for Texture<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying texture: {:?}", self.image);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+fn color_range(base_mip_level: u32, level_count: u32) -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        base_mip_level: base_mip_level,
+        level_count: level_count,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+fn color_layers(mip_level: u32) -> ImageSubresourceLayers {
+    ImageSubresourceLayers {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        mip_level: mip_level,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// A texture with its own descriptor set, binding it as a combined image sampler at `BINDING`.
+pub struct SampledTexture<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    texture: Texture<'d, D>,
+    set_layout: DescriptorSetLayout,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_set: DescriptorSet,
+}
+
+impl<'d, D: DeviceV1_0> SampledTexture<'d, D> {
+    pub fn new(device: &'d D, texture: Texture<'d, D>, stages: ShaderStageFlags) -> VkResult<SampledTexture<'d, D>> {
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+        }];
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = SampledTexture {
+            device: device,
+            texture: texture,
+            set_layout: DescriptorSetLayout::null(),
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, 1),
+            descriptor_set: DescriptorSet::null(),
+        };
+        let bindings = [DescriptorSetLayoutBinding {
+            binding: BINDING,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+            stage_flags: stages,
+            p_immutable_samplers: ptr::null(),
+        }];
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        ret.set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        ret.descriptor_set = ret.descriptor_allocator.allocate(ret.set_layout)?;
+        descriptor::update_descriptor_set(device, ret.descriptor_set, &[ret.texture.descriptor_write(BINDING)]);
+        Ok(ret)
+    }
+
+    pub fn set_layout(&self) -> DescriptorSetLayout {
+        self.set_layout
+    }
+
+    pub fn descriptor_set(&self) -> DescriptorSet {
+        self.descriptor_set
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for SampledTexture<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying sampled texture set layout: {:?}", self.set_layout);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Image views created on demand and kept until their image goes away. Owners of an image (a
+//! texture array, an offscreen target, a depth target) get their views from a cache of their own
+//! and clear it before destroying the image, instead of creating and destroying each view by
+//! hand. Asking again for the same range and format returns the same view.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::collections::HashMap;
+use std::ptr;
+use vk::types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ImageViewKey {
+    image: Image,
+    view_type: ImageViewType,
+    format: Format,
+    aspect_mask: ImageAspectFlags,
+    base_mip_level: u32,
+    level_count: u32,
+    base_array_layer: u32,
+    layer_count: u32,
+}
+
+pub struct ImageViewCache<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    views: HashMap<ImageViewKey, ImageView>,
+}
+
+impl<'d, D: DeviceV1_0> ImageViewCache<'d, D> {
+    pub fn new(device: &'d D) -> ImageViewCache<'d, D> {
+        ImageViewCache {
+            device: device,
+            views: HashMap::new(),
+        }
+    }
+
+    /// The view of `range` of `image`, with identity swizzles, creating it on first use. The view
+    /// stays valid until `clear`.
+    pub fn get(&mut self, image: Image, view_type: ImageViewType, format: Format, range: &ImageSubresourceRange) -> VkResult<ImageView> {
+        let key = ImageViewKey {
+            image: image,
+            view_type: view_type,
+            format: format,
+            aspect_mask: range.aspect_mask,
+            base_mip_level: range.base_mip_level,
+            level_count: range.level_count,
+            base_array_layer: range.base_array_layer,
+            layer_count: range.layer_count,
+        };
+        if let Some(&view) = self.views.get(&key) {
+            return Ok(view);
+        }
+        let create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: image,
+            view_type: view_type,
+            format: format,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: range.clone(),
+        };
+        let view = unsafe { self.device.create_image_view(&create_info, None) }?;
+        trace!("Cached {:?} view of {:?} mips {}+{} layers {}+{}: {:?}", view_type, image, range.base_mip_level, range.level_count, range.base_array_layer, range.layer_count, view);
+        self.views.insert(key, view);
+        Ok(view)
+    }
+
+    /// Destroys every view. Owners call this before destroying their image; the cache also does
+    /// on drop.
+    pub fn clear(&mut self) {
+        for (_, view) in self.views.drain() {
+            unsafe { self.device.destroy_image_view(view, None) };
+        }
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for ImageViewCache<'d, D> {
+    fn drop(&mut self) {
+        trace!("Destroying {} cached image views", self.views.len());
+        self.clear();
+    }
+}
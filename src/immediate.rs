@@ -0,0 +1,71 @@
+//! One-shot GPU work (uploads, mip generation, acceleration structure builds) that the caller
+//! waits on right away, so each feature doesn't need its own pool, fence, and submission code.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::ptr;
+use vk::types::*;
+use ::queue::QueueFamilyIndex;
+use ::safe_create;
+use ::safe_info::{ self, SubmitInfoSafe };
+
+pub struct ImmediateContext<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    queue: Queue,
+    command_pool: safe_create::Owned<'d, D, CommandPool>,
+    fence: safe_create::Owned<'d, D, Fence>,
+}
+
+impl<'d, D: DeviceV1_0> ImmediateContext<'d, D> {
+    /// `queue` must belong to `queue_family_index`, and support whatever commands will be
+    /// submitted through this context.
+    pub fn new(device: &'d D, queue_family_index: QueueFamilyIndex, queue: Queue) -> VkResult<ImmediateContext<'d, D>> {
+        let command_pool_create_info = CommandPoolCreateInfo {
+            s_type: StructureType::CommandPoolCreateInfo,
+            p_next: ptr::null(),
+            flags: COMMAND_POOL_CREATE_TRANSIENT_BIT,
+            queue_family_index: queue_family_index.raw(),
+        };
+        let command_pool = safe_create::create_command_pool_safe(device, &command_pool_create_info, None)?;
+        let fence_create_info = FenceCreateInfo {
+            s_type: StructureType::FenceCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+        };
+        let fence = safe_create::create_fence_safe(device, &fence_create_info, None)?;
+        Ok(ImmediateContext {
+            device: device,
+            queue: queue,
+            command_pool: command_pool,
+            fence: fence,
+        })
+    }
+
+    /// Records `f` into a fresh command buffer, submits it, and blocks until the GPU has
+    /// finished executing it.
+    pub fn immediate_submit<R, F: FnOnce(CommandBuffer) -> R>(&self, f: F) -> VkResult<R> {
+        let command_buffers = safe_create::allocate_command_buffers_safe(self.device, &self.command_pool, CommandBufferLevel::Primary, 1)?;
+        // Freed on the way out, since waiting on the fence means it's no longer pending
+        self.submit_and_wait(command_buffers[0], f)
+    }
+
+    fn submit_and_wait<R, F: FnOnce(CommandBuffer) -> R>(&self, command_buffer: CommandBuffer, f: F) -> VkResult<R> {
+        let begin_info = CommandBufferBeginInfo {
+            s_type: StructureType::CommandBufferBeginInfo,
+            p_next: ptr::null(),
+            flags: COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT,
+            p_inheritance_info: ptr::null(),
+        };
+        (unsafe { self.device.begin_command_buffer(command_buffer, &begin_info) })?;
+        let ret = f(command_buffer);
+        (unsafe { self.device.end_command_buffer(command_buffer) })?;
+
+        let submit_info = SubmitInfoSafe::new()
+            .command_buffer(command_buffer);
+        unsafe {
+            self.device.reset_fences(&[*self.fence])?;
+            safe_info::queue_submit_safe(self.device, self.queue, &[submit_info], *self.fence)?;
+            self.device.wait_for_fences(&[*self.fence], true, u64::MAX)?;
+        }
+        Ok(ret)
+    }
+}
@@ -0,0 +1,332 @@
+//! DEFLATE (RFC 1951) and zlib (RFC 1950) decompression, for the image data in PNGs. Only whole
+//! buffers, which is all the loaders need. Going the other way there's only `zlib_stored`, which
+//! wraps data without compressing it.
+use std;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order code length code lengths are stored in.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+const MAX_BITS: usize = 15;
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bit(&mut self) -> std::result::Result<u32, String> {
+        let byte = self.data.get(self.pos).ok_or("unexpected end of compressed data".to_string())?;
+        let ret = ((*byte as u32) >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(ret)
+    }
+
+    /// `count` bits, least significant first.
+    fn bits(&mut self, count: u8) -> std::result::Result<u32, String> {
+        let mut ret = 0;
+        for idx in 0..count {
+            ret |= self.bit()? << idx;
+        }
+        Ok(ret)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths.iter() {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman {
+            counts: counts,
+            symbols: symbols,
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> std::result::Result<u16, String> {
+        // Codes are stored most significant bit first, unlike everything else
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..(MAX_BITS + 1) {
+            code |= reader.bit()? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_string())
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = if symbol < 144 {
+            8
+        } else if symbol < 256 {
+            9
+        } else if symbol < 280 {
+            7
+        } else {
+            8
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5u8; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> std::result::Result<(Huffman, Huffman), String> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    let mut code_length_lengths = [0u8; 19];
+    for &idx in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_length_lengths[idx] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_length_lengths);
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut idx = 0;
+    while idx < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            _ if symbol < 16 => (symbol as u8, 1),
+            16 => {
+                if idx == 0 {
+                    return Err("code length repeat with nothing to repeat".to_string());
+                }
+                (lengths[idx - 1], 3 + reader.bits(2)? as usize)
+            },
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if idx + repeat > lengths.len() {
+            return Err("code lengths overflow".to_string());
+        }
+        for length in lengths[idx..idx + repeat].iter_mut() {
+            *length = value;
+        }
+        idx += repeat;
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(reader: &mut BitReader, literals: &Huffman, distances: &Huffman, out: &mut Vec<u8>) -> std::result::Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let idx = symbol - 257;
+            if idx >= LENGTH_BASE.len() {
+                return Err(format!("invalid length symbol {}", symbol));
+            }
+            let length = LENGTH_BASE[idx] as usize + reader.bits(LENGTH_EXTRA[idx])? as usize;
+            let idx = distances.decode(reader)? as usize;
+            if idx >= DIST_BASE.len() {
+                return Err(format!("invalid distance symbol {}", idx));
+            }
+            let distance = DIST_BASE[idx] as usize + reader.bits(DIST_EXTRA[idx])? as usize;
+            if distance > out.len() {
+                return Err("distance too far back".to_string());
+            }
+            // The source can overlap what's being written, so this has to go a byte at a time
+            let start = out.len() - distance;
+            for offset in 0..length {
+                let byte = out[start + offset];
+                out.push(byte);
+            }
+        }
+    }
+}
+
+/// Decompresses a raw DEFLATE stream.
+pub fn inflate(data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let mut reader = BitReader {
+        data: data,
+        pos: 0,
+        bit: 0,
+    };
+    let mut out = Vec::with_capacity(data.len() * 4);
+    loop {
+        let last = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data.get(reader.pos..reader.pos + 4).ok_or("truncated stored block".to_string())?;
+                let length = header[0] as usize | (header[1] as usize) << 8;
+                let start = reader.pos + 4;
+                out.extend_from_slice(data.get(start..start + length).ok_or("truncated stored block".to_string())?);
+                reader.pos = start + length;
+            },
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut reader, &literals, &distances, &mut out)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut out)?;
+            },
+            _ => return Err("invalid block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decompresses a zlib stream, checking its header and Adler-32 checksum.
+pub fn zlib_decompress(data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    if data.len() < 6 {
+        return Err("truncated zlib stream".to_string());
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) {
+        return Err("invalid zlib header".to_string());
+    }
+    if flg & 0x20 != 0 {
+        return Err("zlib preset dictionaries aren't supported".to_string());
+    }
+    let out = inflate(&data[2..])?;
+    let trailer = &data[data.len() - 4..];
+    let expected = (trailer[0] as u32) << 24 | (trailer[1] as u32) << 16 | (trailer[2] as u32) << 8 | trailer[3] as u32;
+    if adler32(&out) != expected {
+        return Err("zlib checksum mismatch".to_string());
+    }
+    Ok(out)
+}
+
+/// A zlib stream holding `data` in stored blocks, i.e. without any compression.
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    // Deflate with a 32K window and the fastest level, with the check bits making it divisible by 31
+    out.extend_from_slice(&[0x78, 0x01]);
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&[length as u8, (length >> 8) as u8, !length as u8, (!length >> 8) as u8]);
+        out.extend_from_slice(block);
+    }
+    let checksum = adler32(data);
+    out.extend_from_slice(&[(checksum >> 24) as u8, (checksum >> 16) as u8, (checksum >> 8) as u8, checksum as u8]);
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // Big enough chunks to keep the modulo out of the inner loop, small enough not to overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk.iter() {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `zlib.compress(b"hello hello hello", 9)`: one fixed-code block whose match overlaps itself.
+    const FIXED: [u8; 16] = [120, 218, 203, 72, 205, 201, 201, 87, 200, 64, 144, 0, 58, 46, 6, 125];
+
+    /// `zlib.compress(DYNAMIC_TEXT, 9)`: one block with its own code tables.
+    const DYNAMIC: [u8; 79] = [
+        120, 218, 85, 140, 81, 14, 128, 48, 8, 67, 175, 194, 213, 80, 203, 36, 113, 100, 155, 195,
+        120, 124, 23, 137, 70, 127, 154, 242, 90, 90, 29, 14, 170, 31, 21, 216, 12, 218, 87, 94,
+        208, 72, 51, 167, 129, 26, 167, 12, 235, 116, 160, 117, 156, 79, 24, 205, 162, 5, 155, 26,
+        104, 114, 145, 65, 99, 229, 119, 132, 198, 86, 248, 247, 233, 134, 23, 20, 115, 49, 239,
+    ];
+    const DYNAMIC_TEXT: &'static str = "queue queue queue fence shader image fragment vertex shader fence pipeline buffer queue buffer queue queue image queue pipeline image";
+
+    #[test]
+    fn fixed_codes() {
+        assert_eq!(zlib_decompress(&FIXED).unwrap(), b"hello hello hello".to_vec());
+    }
+
+    #[test]
+    fn dynamic_codes() {
+        assert_eq!(zlib_decompress(&DYNAMIC).unwrap(), DYNAMIC_TEXT.as_bytes().to_vec());
+    }
+
+    #[test]
+    fn raw_deflate_without_the_zlib_wrapper() {
+        assert_eq!(inflate(&FIXED[2..FIXED.len() - 4]).unwrap(), b"hello hello hello".to_vec());
+    }
+
+    #[test]
+    fn stored_round_trip() {
+        for &size in [0, 1, 65535, 65536, 200000].iter() {
+            let data: Vec<u8> = (0..size).map(|i| (i * 31 + i / 7) as u8).collect();
+            assert_eq!(zlib_decompress(&zlib_stored(&data)).unwrap(), data, "{} bytes", size);
+        }
+    }
+
+    #[test]
+    fn rejects_a_bad_checksum() {
+        let mut data = FIXED.to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(zlib_decompress(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_a_bad_header() {
+        let mut data = FIXED.to_vec();
+        data[1] ^= 1;
+        assert!(zlib_decompress(&data).is_err());
+        assert!(zlib_decompress(&[0x78]).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert!(zlib_decompress(&DYNAMIC[..40]).is_err());
+        assert!(inflate(&[0x01, 0x10, 0x00]).is_err());
+    }
+}
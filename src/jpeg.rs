@@ -0,0 +1,619 @@
+//! Baseline JPEG decoding to RGBA8: sequential Huffman-coded 8-bit images, grayscale or YCbCr,
+//! with any chroma subsampling and restart intervals. Progressive and arithmetic-coded JPEGs
+//! aren't supported.
+use std;
+use ::image::{ ImageData, ImageError };
+use ::math::prelude::{ clamp, div_ceil };
+
+/// Natural (row-major) index of each coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOF0: u8 = 0xc0;
+const SOF1: u8 = 0xc1;
+const DHT: u8 = 0xc4;
+const SOS: u8 = 0xda;
+const DQT: u8 = 0xdb;
+const DRI: u8 = 0xdd;
+const RST0: u8 = 0xd0;
+
+fn corrupt(what: &str) -> ImageError {
+    ImageError::Corrupt(format!("JPEG {}", what))
+}
+
+fn be_u16(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 8 | bytes[1] as usize
+}
+
+#[derive(Debug, Clone, Default)]
+struct HuffmanTable {
+    /// The largest code of each length, or -1 if there are none.
+    max_code: [i32; 17],
+    /// Index into `values` of the first code of each length, minus that code.
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8], values: &[u8]) -> HuffmanTable {
+        let mut ret = HuffmanTable {
+            max_code: [-1; 17],
+            offset: [0; 17],
+            values: values.to_vec(),
+        };
+        let mut code = 0i32;
+        let mut index = 0i32;
+        for length in 1..17 {
+            let count = counts[length - 1] as i32;
+            if count > 0 {
+                ret.offset[length] = index - code;
+                code += count;
+                index += count;
+                ret.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        ret
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    /// Blocks across and down, padded out to whole MCUs.
+    blocks_wide: usize,
+    blocks_high: usize,
+    samples: Vec<u8>,
+    dc_table: usize,
+    ac_table: usize,
+    dc_prediction: i32,
+}
+
+impl Component {
+    fn stride(&self) -> usize {
+        self.blocks_wide * 8
+    }
+}
+
+/// Reads entropy-coded bits, dropping the stuffed zero after every 0xff.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn fill(&mut self) {
+        while self.bit_count <= 24 {
+            let byte = match self.data.get(self.pos) {
+                Some(&0xff) => match self.data.get(self.pos + 1) {
+                    Some(&0) => {
+                        self.pos += 2;
+                        0xff
+                    },
+                    // A marker, which ends the entropy-coded data. Feed zeros until it's handled.
+                    _ => 0,
+                },
+                Some(&byte) => {
+                    self.pos += 1;
+                    byte
+                },
+                None => 0,
+            };
+            self.bits |= (byte as u32) << (24 - self.bit_count);
+            self.bit_count += 8;
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        self.fill();
+        let ret = self.bits >> (32 - count);
+        self.bits <<= count;
+        self.bit_count -= count;
+        ret
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> std::result::Result<u8, ImageError> {
+        let mut code = 0i32;
+        for length in 1..17 {
+            code = (code << 1) | self.bits(1) as i32;
+            if code <= table.max_code[length] {
+                return table.values.get((table.offset[length] + code) as usize).cloned().ok_or(corrupt("Huffman code is out of range"));
+            }
+        }
+        Err(corrupt("Huffman code is invalid"))
+    }
+
+    /// An `count` bit value, sign extended the JPEG way.
+    fn receive_extend(&mut self, count: u32) -> i32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = self.bits(count) as i32;
+        if value < 1 << (count - 1) {
+            value - (1 << count) + 1
+        } else {
+            value
+        }
+    }
+
+    /// Drops the rest of the current byte and expects a restart marker, at `pos` or after
+    /// whatever was read ahead.
+    fn restart(&mut self) -> std::result::Result<(), ImageError> {
+        self.bits = 0;
+        self.bit_count = 0;
+        match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+            (Some(&0xff), Some(&marker)) if marker & 0xf8 == RST0 => {
+                self.pos += 2;
+                Ok(())
+            },
+            _ => Err(corrupt("restart marker is missing")),
+        }
+    }
+}
+
+/// Separable inverse DCT of one block, from dequantized coefficients in natural order.
+fn idct(coefficients: &[i32; 64], cosines: &[[f32; 8]; 8], out: &mut [f32; 64]) {
+    let mut rows = [0f32; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            let mut sum = 0.0;
+            for u in 0..8 {
+                sum += coefficients[v * 8 + u] as f32 * cosines[x][u];
+            }
+            rows[v * 8 + x] = sum;
+        }
+    }
+    for y in 0..8 {
+        for x in 0..8 {
+            let mut sum = 0.0;
+            for v in 0..8 {
+                sum += rows[v * 8 + x] * cosines[y][v];
+            }
+            out[y * 8 + x] = sum;
+        }
+    }
+}
+
+/// `cosines[x][u]`, including the normalization for `u == 0` and the 1/2 per dimension.
+fn idct_cosines() -> [[f32; 8]; 8] {
+    let mut ret = [[0f32; 8]; 8];
+    for (x, row) in ret.iter_mut().enumerate() {
+        for (u, cosine) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+            *cosine = 0.5 * scale * (((2 * x + 1) * u) as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    ret
+}
+
+struct Decoder {
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    quant_tables: [[u16; 64]; 4],
+    dc_tables: [HuffmanTable; 4],
+    ac_tables: [HuffmanTable; 4],
+    restart_interval: usize,
+    max_h: usize,
+    max_v: usize,
+    cosines: [[f32; 8]; 8],
+}
+
+impl Decoder {
+    fn read_quant_tables(&mut self, mut data: &[u8]) -> std::result::Result<(), ImageError> {
+        while !data.is_empty() {
+            let (precision, id) = ((data[0] >> 4) as usize, (data[0] & 0x0f) as usize);
+            let size = if precision == 0 { 64 } else { 128 };
+            if id >= 4 || data.len() < 1 + size {
+                return Err(corrupt("quantization table is invalid"));
+            }
+            for k in 0..64 {
+                self.quant_tables[id][k] = if precision == 0 { data[1 + k] as u16 } else { be_u16(&data[1 + k * 2..]) as u16 };
+            }
+            data = &data[1 + size..];
+        }
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, mut data: &[u8]) -> std::result::Result<(), ImageError> {
+        while !data.is_empty() {
+            if data.len() < 17 {
+                return Err(corrupt("Huffman table is truncated"));
+            }
+            let (class, id) = (data[0] >> 4, (data[0] & 0x0f) as usize);
+            let counts = &data[1..17];
+            let total: usize = counts.iter().map(|&count| count as usize).sum();
+            if id >= 4 || data.len() < 17 + total {
+                return Err(corrupt("Huffman table is invalid"));
+            }
+            let table = HuffmanTable::new(counts, &data[17..17 + total]);
+            if class == 0 {
+                self.dc_tables[id] = table;
+            } else {
+                self.ac_tables[id] = table;
+            }
+            data = &data[17 + total..];
+        }
+        Ok(())
+    }
+
+    fn read_frame(&mut self, data: &[u8]) -> std::result::Result<(), ImageError> {
+        if data.len() < 6 || data[0] != 8 {
+            return Err(ImageError::Unsupported("JPEGs that aren't 8 bits per sample".to_string()));
+        }
+        self.height = be_u16(&data[1..]);
+        self.width = be_u16(&data[3..]);
+        let count = data[5] as usize;
+        if self.width == 0 || self.height == 0 {
+            return Err(ImageError::Unsupported("JPEGs with the height in a DNL marker".to_string()));
+        }
+        if count != 1 && count != 3 {
+            return Err(ImageError::Unsupported(format!("JPEGs with {} components", count)));
+        }
+        if data.len() < 6 + count * 3 {
+            return Err(corrupt("frame header is truncated"));
+        }
+        for idx in 0..count {
+            let spec = &data[6 + idx * 3..9 + idx * 3];
+            let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 0x0f) as usize);
+            if h == 0 || h > 4 || v == 0 || v > 4 || spec[2] >= 4 {
+                return Err(corrupt("component is invalid"));
+            }
+            self.components.push(Component {
+                id: spec[0],
+                h: h,
+                v: v,
+                quant_table: spec[2] as usize,
+                blocks_wide: 0,
+                blocks_high: 0,
+                samples: Vec::new(),
+                dc_table: 0,
+                ac_table: 0,
+                dc_prediction: 0,
+            });
+        }
+        self.max_h = self.components.iter().map(|component| component.h).max().unwrap();
+        self.max_v = self.components.iter().map(|component| component.v).max().unwrap();
+        let (mcus_wide, mcus_high) = self.mcu_counts();
+        for component in self.components.iter_mut() {
+            component.blocks_wide = mcus_wide * component.h;
+            component.blocks_high = mcus_high * component.v;
+            component.samples = vec![0; component.blocks_wide * component.blocks_high * 64];
+        }
+        Ok(())
+    }
+
+    fn mcu_counts(&self) -> (usize, usize) {
+        let (mcu_width, mcu_height) = (self.max_h * 8, self.max_v * 8);
+        (div_ceil(self.width, mcu_width), div_ceil(self.height, mcu_height))
+    }
+
+    fn decode_block(&mut self, reader: &mut BitReader, component_idx: usize, block_x: usize, block_y: usize) -> std::result::Result<(), ImageError> {
+        let mut coefficients = [0i32; 64];
+        {
+            let component = &mut self.components[component_idx];
+            let quant = &self.quant_tables[component.quant_table];
+            let size = reader.decode(&self.dc_tables[component.dc_table])?;
+            component.dc_prediction += reader.receive_extend(size as u32);
+            coefficients[0] = component.dc_prediction * quant[0] as i32;
+            let mut k = 1;
+            while k < 64 {
+                let rs = reader.decode(&self.ac_tables[component.ac_table])?;
+                let (run, size) = ((rs >> 4) as usize, (rs & 0x0f) as u32);
+                if size == 0 {
+                    if run == 15 {
+                        k += 16;
+                        continue;
+                    }
+                    break;
+                }
+                k += run;
+                if k >= 64 {
+                    return Err(corrupt("coefficient index is out of range"));
+                }
+                coefficients[ZIGZAG[k]] = reader.receive_extend(size) * quant[k] as i32;
+                k += 1;
+            }
+        }
+        let mut block = [0f32; 64];
+        idct(&coefficients, &self.cosines, &mut block);
+        let component = &mut self.components[component_idx];
+        let stride = component.stride();
+        for y in 0..8 {
+            for x in 0..8 {
+                let value = clamp((block[y * 8 + x] + 128.0).round(), 0.0, 255.0);
+                component.samples[(block_y * 8 + y) * stride + block_x * 8 + x] = value as u8;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the scan starting at `data`, returning about how many bytes it took.
+    fn read_scan(&mut self, header: &[u8], data: &[u8]) -> std::result::Result<usize, ImageError> {
+        let count = header.first().map(|&count| count as usize).unwrap_or(0);
+        if count == 0 || header.len() < 1 + count * 2 + 3 {
+            return Err(corrupt("scan header is truncated"));
+        }
+        let mut scan_components = Vec::with_capacity(count);
+        for idx in 0..count {
+            let (id, tables) = (header[1 + idx * 2], header[2 + idx * 2]);
+            let component_idx = self.components.iter().position(|component| component.id == id).ok_or(corrupt("scan refers to a missing component"))?;
+            let component = &mut self.components[component_idx];
+            component.dc_table = (tables >> 4) as usize & 3;
+            component.ac_table = (tables & 0x0f) as usize & 3;
+            component.dc_prediction = 0;
+            scan_components.push(component_idx);
+        }
+        let mut reader = BitReader {
+            data: data,
+            pos: 0,
+            bits: 0,
+            bit_count: 0,
+        };
+        let mut mcu = 0;
+        if scan_components.len() == 1 {
+            // A single component isn't interleaved: its blocks go in plain raster order, and
+            // only as many as cover the image
+            let component_idx = scan_components[0];
+            let (h, v) = (self.components[component_idx].h, self.components[component_idx].v);
+            let blocks_wide = div_ceil(self.width * h / self.max_h, 8);
+            let blocks_high = div_ceil(self.height * v / self.max_v, 8);
+            for block_y in 0..blocks_high {
+                for block_x in 0..blocks_wide {
+                    self.start_mcu(&mut reader, mcu)?;
+                    mcu += 1;
+                    self.decode_block(&mut reader, component_idx, block_x, block_y)?;
+                }
+            }
+        } else {
+            let (mcus_wide, mcus_high) = self.mcu_counts();
+            for mcu_y in 0..mcus_high {
+                for mcu_x in 0..mcus_wide {
+                    self.start_mcu(&mut reader, mcu)?;
+                    mcu += 1;
+                    for &component_idx in scan_components.iter() {
+                        let (h, v) = (self.components[component_idx].h, self.components[component_idx].v);
+                        for y in 0..v {
+                            for x in 0..h {
+                                self.decode_block(&mut reader, component_idx, mcu_x * h + x, mcu_y * v + y)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        // The reader never reads past a marker, so this is at most a few bytes short of the end
+        Ok(reader.pos)
+    }
+
+    /// Handles the restart before MCU number `mcu` of a scan, if there is one.
+    fn start_mcu(&mut self, reader: &mut BitReader, mcu: usize) -> std::result::Result<(), ImageError> {
+        if self.restart_interval > 0 && mcu > 0 && mcu.is_multiple_of(self.restart_interval) {
+            reader.restart()?;
+            for component in self.components.iter_mut() {
+                component.dc_prediction = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Upsamples the components by repetition and converts to RGBA.
+    fn to_image(&self) -> ImageData {
+        let mut pixels = Vec::with_capacity(self.width * self.height * 4);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let sample = |component: &Component| -> f32 {
+                    let (sx, sy) = (x * component.h / self.max_h, y * component.v / self.max_v);
+                    component.samples[sy * component.stride() + sx] as f32
+                };
+                let rgb = if self.components.len() == 1 {
+                    let gray = sample(&self.components[0]) as u8;
+                    [gray, gray, gray]
+                } else {
+                    let (luma, cb, cr) = (sample(&self.components[0]), sample(&self.components[1]) - 128.0, sample(&self.components[2]) - 128.0);
+                    let to_byte = |value: f32| clamp(value.round(), 0.0, 255.0) as u8;
+                    [to_byte(luma + 1.402 * cr), to_byte(luma - 0.344136 * cb - 0.714136 * cr), to_byte(luma + 1.772 * cb)]
+                };
+                pixels.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+            }
+        }
+        ImageData {
+            width: self.width as u32,
+            height: self.height as u32,
+            pixels: pixels,
+        }
+    }
+}
+
+pub fn is_jpeg(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] == 0xff && data[1] == SOI
+}
+
+pub fn decode(data: &[u8]) -> std::result::Result<ImageData, ImageError> {
+    if !is_jpeg(data) {
+        return Err(corrupt("start of image marker is missing"));
+    }
+    let mut decoder = Decoder {
+        width: 0,
+        height: 0,
+        components: Vec::new(),
+        quant_tables: [[0; 64]; 4],
+        dc_tables: Default::default(),
+        ac_tables: Default::default(),
+        restart_interval: 0,
+        max_h: 1,
+        max_v: 1,
+        cosines: idct_cosines(),
+    };
+    let mut pos = 2;
+    loop {
+        // Markers can be padded with any number of 0xff
+        while data.get(pos) == Some(&0xff) && data.get(pos + 1) == Some(&0xff) {
+            pos += 1;
+        }
+        let marker = match (data.get(pos), data.get(pos + 1)) {
+            (Some(&0xff), Some(&marker)) => marker,
+            _ => return Err(corrupt("marker is missing")),
+        };
+        pos += 2;
+        if marker == EOI {
+            break;
+        }
+        let length = data.get(pos..pos + 2).ok_or(corrupt("segment is truncated")).map(be_u16)?;
+        let segment = data.get(pos + 2..pos + length).ok_or(corrupt("segment is truncated"))?;
+        pos += length;
+        match marker {
+            DQT => decoder.read_quant_tables(segment)?,
+            DHT => decoder.read_huffman_tables(segment)?,
+            SOF0 | SOF1 => decoder.read_frame(segment)?,
+            DRI => decoder.restart_interval = segment.get(0..2).ok_or(corrupt("restart interval is truncated")).map(be_u16)?,
+            SOS => {
+                if decoder.components.is_empty() {
+                    return Err(corrupt("scan comes before the frame header"));
+                }
+                pos += decoder.read_scan(segment, &data[pos..])?;
+                // Skip to the next marker that isn't a restart, in case the scan ended early
+                while pos + 1 < data.len() && !(data[pos] == 0xff && data[pos + 1] != 0 && data[pos + 1] & 0xf8 != RST0) {
+                    pos += 1;
+                }
+            },
+            // Other frame types: progressive, lossless, hierarchical and arithmetic-coded
+            0xc2 | 0xc3 | 0xc5 | 0xc6 | 0xc7 | 0xc9 | 0xca | 0xcb | 0xcd | 0xce | 0xcf => return Err(ImageError::Unsupported(format!("JPEG frame type {:#x}", marker))),
+            _ => {},
+        }
+    }
+    if decoder.components.is_empty() {
+        return Err(corrupt("frame header is missing"));
+    }
+    Ok(decoder.to_image())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(out: &mut Vec<u8>, marker: u8, body: &[u8]) {
+        let length = body.len() + 2;
+        out.extend_from_slice(&[0xff, marker, (length >> 8) as u8, length as u8]);
+        out.extend_from_slice(body);
+    }
+
+    /// A grayscale baseline JPEG of flat 8x8 blocks, one per `dc_differences` entry, left to
+    /// right. Each difference has to be 5 bits, 16 to 31 either way: the only DC code is `0` for
+    /// size 5, and the only AC code is `0` for end of block.
+    fn flat_gray(width: u16, height: u16, dc_differences: &[i32], restart_interval: Option<u16>) -> Vec<u8> {
+        let mut out = vec![0xff, SOI];
+        let mut quant = vec![0u8];
+        quant.push(16);
+        quant.extend_from_slice(&[1; 63]);
+        segment(&mut out, DQT, &quant);
+        segment(&mut out, SOF0, &[8, (height >> 8) as u8, height as u8, (width >> 8) as u8, width as u8, 1, 1, 0x11, 0]);
+        let mut one_code = vec![0u8, 1];
+        one_code.extend_from_slice(&[0; 15]);
+        let mut tables = one_code.clone();
+        tables.push(5);
+        tables.push(0x10);
+        tables.extend_from_slice(&one_code[1..]);
+        tables.push(0);
+        segment(&mut out, DHT, &tables);
+        if let Some(interval) = restart_interval {
+            segment(&mut out, DRI, &[(interval >> 8) as u8, interval as u8]);
+        }
+        segment(&mut out, SOS, &[1, 1, 0x00, 0, 63, 0]);
+        // Bits MSB first: the DC code, 5 magnitude bits and the EOB code per block, padded with 1s
+        // to a byte before each restart marker and at the end
+        let mut bits: Vec<bool> = Vec::new();
+        let flush = |bits: &mut Vec<bool>, out: &mut Vec<u8>| {
+            while !bits.len().is_multiple_of(8) {
+                bits.push(true);
+            }
+            for byte in bits.chunks(8).map(|byte| byte.iter().fold(0u8, |acc, &bit| acc << 1 | bit as u8)) {
+                out.push(byte);
+                if byte == 0xff {
+                    out.push(0);
+                }
+            }
+            bits.clear();
+        };
+        for (idx, &difference) in dc_differences.iter().enumerate() {
+            if let Some(interval) = restart_interval {
+                if idx > 0 && idx % interval as usize == 0 {
+                    flush(&mut bits, &mut out);
+                    out.extend_from_slice(&[0xff, RST0 + ((idx / interval as usize - 1) % 8) as u8]);
+                }
+            }
+            let magnitude = if difference < 0 { difference + 31 } else { difference };
+            bits.push(false);
+            bits.extend((0..5).rev().map(|bit| magnitude >> bit & 1 != 0));
+            bits.push(false);
+        }
+        flush(&mut bits, &mut out);
+        out.extend_from_slice(&[0xff, EOI]);
+        out
+    }
+
+    fn grays(image: &ImageData) -> Vec<u8> {
+        image.pixels.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn flat_block() {
+        // A DC of 16 times the quantizer's 16 is 256, or 32 over mid-gray after the IDCT's 1/8
+        let image = decode(&flat_gray(8, 8, &[16], None)).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        assert!(grays(&image).iter().all(|&gray| gray == 160), "{:?}", grays(&image));
+        assert!(image.pixels.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+    }
+
+    #[test]
+    fn dc_is_predicted_from_the_previous_block() {
+        let image = decode(&flat_gray(16, 8, &[16, -16], None)).unwrap();
+        let grays = grays(&image);
+        assert_eq!(grays[0], 160);
+        assert_eq!(grays[8], 128);
+    }
+
+    #[test]
+    fn restart_resets_the_prediction() {
+        let image = decode(&flat_gray(16, 8, &[16, 16], Some(1))).unwrap();
+        let grays = grays(&image);
+        assert_eq!(grays[0], 160);
+        assert_eq!(grays[8], 160);
+    }
+
+    #[test]
+    fn crops_partial_blocks() {
+        let image = decode(&flat_gray(5, 3, &[16], None)).unwrap();
+        assert_eq!((image.width, image.height), (5, 3));
+        assert_eq!(image.pixels.len(), 5 * 3 * 4);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(!is_jpeg(b"\x89PNG"));
+        assert!(decode(b"\x89PNG").is_err());
+        let data = flat_gray(8, 8, &[16], None);
+        assert!(decode(&data[..20]).is_err());
+        let mut progressive = data.clone();
+        let sof = progressive.windows(2).position(|w| w == [0xff, SOF0]).unwrap();
+        progressive[sof + 1] = 0xc2;
+        match decode(&progressive) {
+            Err(ImageError::Unsupported(_)) => {},
+            other => panic!("expected an unsupported error, got {:?}", other.map(|image| image.pixels)),
+        }
+    }
+}
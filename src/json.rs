@@ -0,0 +1,338 @@
+//! A small JSON parser, for reading glTF. The whole document is parsed into a tree of `Value`s;
+//! objects keep their keys sorted, which is fine since nothing here cares about key order.
+use std;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Arrays and objects nested deeper than this are rejected, rather than overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    /// The member `key` of an object, or `Null` if there isn't one or this isn't an object.
+    pub fn get(&self, key: &str) -> &Value {
+        match *self {
+            Value::Object(ref members) => members.get(key).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+
+    /// Element `idx` of an array, or `Null` if there isn't one or this isn't an array.
+    pub fn at(&self, idx: usize) -> &Value {
+        match *self {
+            Value::Array(ref elements) => elements.get(idx).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Only for whole, non-negative numbers.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= usize::MAX as f64 => Some(n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array. Anything else is empty.
+    pub fn elements(&self) -> &[Value] {
+        match *self {
+            Value::Array(ref elements) => elements,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Byte offset into the text.
+    pub offset: usize,
+    pub what: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.what)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    offset: usize,
+    depth: usize,
+}
+
+pub fn parse(text: &str) -> std::result::Result<Value, ParseError> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        offset: 0,
+        depth: 0,
+    };
+    let ret = parser.value()?;
+    parser.skip_whitespace();
+    if parser.offset < parser.text.len() {
+        return Err(parser.error("trailing characters after the document"));
+    }
+    Ok(ret)
+}
+
+impl<'a> Parser<'a> {
+    fn error<S: Into<String>>(&self, what: S) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            what: what.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.offset).cloned()
+    }
+
+    fn next(&mut self) -> std::result::Result<u8, ParseError> {
+        match self.peek() {
+            Some(c) => {
+                self.offset += 1;
+                Ok(c)
+            },
+            None => Err(self.error("unexpected end of text")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.offset += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> std::result::Result<(), ParseError> {
+        if self.text[self.offset..].starts_with(literal.as_bytes()) {
+            self.offset += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {:?}", literal)))
+        }
+    }
+
+    fn value(&mut self) -> std::result::Result<Value, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => self.nested(|parser| parser.array()),
+            Some(b'{') => self.nested(|parser| parser.object()),
+            Some(c) if c == b'-' || (c as char).is_ascii_digit() => self.number(),
+            Some(c) => Err(self.error(format!("unexpected {:?}", c as char))),
+            None => Err(self.error("unexpected end of text")),
+        }
+    }
+
+    fn nested<F: FnOnce(&mut Parser<'a>) -> std::result::Result<Value, ParseError>>(&mut self, f: F) -> std::result::Result<Value, ParseError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let ret = f(self);
+        self.depth -= 1;
+        ret
+    }
+
+    fn array(&mut self) -> std::result::Result<Value, ParseError> {
+        self.expect("[")?;
+        let mut ret = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.offset += 1;
+            return Ok(Value::Array(ret));
+        }
+        loop {
+            ret.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                b',' => {},
+                b']' => return Ok(Value::Array(ret)),
+                c => return Err(self.error(format!("expected ',' or ']', found {:?}", c as char))),
+            }
+        }
+    }
+
+    fn object(&mut self) -> std::result::Result<Value, ParseError> {
+        self.expect("{")?;
+        let mut ret = BTreeMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.offset += 1;
+            return Ok(Value::Object(ret));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            let value = self.value()?;
+            ret.insert(key, value);
+            self.skip_whitespace();
+            match self.next()? {
+                b',' => {},
+                b'}' => return Ok(Value::Object(ret)),
+                c => return Err(self.error(format!("expected ',' or '}}', found {:?}", c as char))),
+            }
+        }
+    }
+
+    fn number(&mut self) -> std::result::Result<Value, ParseError> {
+        let start = self.offset;
+        while self.peek().map(|c| (c as char).is_ascii_digit() || b"-+.eE".contains(&c)).unwrap_or(false) {
+            self.offset += 1;
+        }
+        // Only ASCII was consumed, so this can't split a character
+        let text = std::str::from_utf8(&self.text[start..self.offset]).unwrap();
+        text.parse::<f64>().map(Value::Number).map_err(|_| ParseError {
+            offset: start,
+            what: format!("invalid number {:?}", text),
+        })
+    }
+
+    fn hex4(&mut self) -> std::result::Result<u32, ParseError> {
+        let mut ret = 0;
+        for _ in 0..4 {
+            let c = self.next()?;
+            let digit = (c as char).to_digit(16).ok_or_else(|| self.error(format!("invalid hex digit {:?}", c as char)))?;
+            ret = ret << 4 | digit;
+        }
+        Ok(ret)
+    }
+
+    fn string(&mut self) -> std::result::Result<String, ParseError> {
+        self.expect("\"")?;
+        let mut ret: Vec<u8> = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => match self.next()? {
+                    b'"' => ret.push(b'"'),
+                    b'\\' => ret.push(b'\\'),
+                    b'/' => ret.push(b'/'),
+                    b'b' => ret.push(8),
+                    b'f' => ret.push(12),
+                    b'n' => ret.push(b'\n'),
+                    b'r' => ret.push(b'\r'),
+                    b't' => ret.push(b'\t'),
+                    b'u' => {
+                        let mut code = self.hex4()?;
+                        // Characters outside the BMP come as a UTF-16 surrogate pair
+                        if (0xd800..0xdc00).contains(&code) && self.text[self.offset..].starts_with(b"\\u") {
+                            self.offset += 2;
+                            let low = self.hex4()?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        let c = std::char::from_u32(code).unwrap_or('\u{fffd}');
+                        let mut buf = [0; 4];
+                        ret.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    },
+                    c => return Err(self.error(format!("invalid escape {:?}", c as char))),
+                },
+                c => ret.push(c),
+            }
+        }
+        // The text was a str and escapes add whole characters, so this is still UTF-8
+        Ok(String::from_utf8(ret).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalars() {
+        assert_eq!(parse("null").unwrap(), Value::Null);
+        assert_eq!(parse(" true ").unwrap(), Value::Bool(true));
+        assert_eq!(parse("false").unwrap(), Value::Bool(false));
+        assert_eq!(parse("-12.5e1").unwrap(), Value::Number(-125.0));
+        assert_eq!(parse("0").unwrap(), Value::Number(0.0));
+        assert_eq!(parse("\"hi\"").unwrap(), Value::String("hi".to_string()));
+    }
+
+    #[test]
+    fn nested_documents() {
+        let value = parse(r#"{ "asset": { "version": "2.0" }, "nodes": [ { "mesh": 0 }, { "children": [0, 2] } ], "empty": [], "none": {} }"#).unwrap();
+        assert_eq!(value.get("asset").get("version").as_str(), Some("2.0"));
+        assert_eq!(value.get("nodes").at(0).get("mesh").as_usize(), Some(0));
+        assert_eq!(value.get("nodes").at(1).get("children").elements().len(), 2);
+        assert!(value.get("empty").elements().is_empty());
+        assert_eq!(value.get("none"), &Value::Object(BTreeMap::new()));
+    }
+
+    #[test]
+    fn missing_members_are_null() {
+        let value = parse("[1, 2]").unwrap();
+        assert!(value.at(2).is_null());
+        assert!(value.get("key").is_null());
+        assert!(value.at(0).get("key").at(3).is_null());
+    }
+
+    #[test]
+    fn as_usize_only_takes_whole_non_negative_numbers() {
+        assert_eq!(Value::Number(3.0).as_usize(), Some(3));
+        assert_eq!(Value::Number(3.5).as_usize(), None);
+        assert_eq!(Value::Number(-1.0).as_usize(), None);
+        assert_eq!(Value::Bool(true).as_usize(), None);
+    }
+
+    #[test]
+    fn string_escapes() {
+        let value = parse(r#""a\"b\\c\/d\n\té😀""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"b\\c/d\n\t\u{e9}\u{1f600}"));
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12g4""#).is_err());
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(parse("[1, 2,]").unwrap_err().offset, 6);
+        assert_eq!(parse("{\"a\" 1}").unwrap_err().offset, 5);
+        assert_eq!(parse("[1] x").unwrap_err().offset, 4);
+        assert!(parse("").is_err());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("\"unterminated").is_err());
+        assert!(parse("1.2.3").is_err());
+        assert!(parse("nul").is_err());
+    }
+
+    #[test]
+    fn depth_is_limited() {
+        let deep = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&deep(MAX_DEPTH)).is_ok());
+        assert!(parse(&deep(MAX_DEPTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! Acquiring the swapchain image as late as possible, for latency experiments. Normally a frame
+//! acquires first and renders straight into the swapchain image, so rendering can't start until
+//! the presentation engine hands an image back. With `--late-acquire`, the scene is rendered
+//! into an `offscreen::OffscreenTarget` and submitted first, and only then is an image acquired
+//! and the result copied into it just before presenting. `LatencyStats` measures both ways the
+//! same, from the start of the frame to the present call, so runs with and without the flag can
+//! be compared.
+use std::time::{ Duration, Instant };
+use ::cvar::Flag;
+use ::time;
+
+pub const ARG: &'static str = "--late-acquire";
+pub const CVAR: &'static str = "r.late_acquire";
+pub const FLAGS: &'static [Flag] = &[Flag::Switch { arg: ARG, name: CVAR }];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentTiming {
+    /// Acquire first, then render into the swapchain image.
+    Direct,
+    /// Render offscreen, then acquire and copy.
+    LateAcquire,
+}
+
+impl PresentTiming {
+    /// From `r.late_acquire`, which `ARG` sets.
+    pub fn from_cvars() -> PresentTiming {
+        if cvar!(CVAR, false).get() {
+            PresentTiming::LateAcquire
+        } else {
+            PresentTiming::Direct
+        }
+    }
+}
+
+/// CPU-side latency of presented frames: from the start of the frame (right after input is
+/// polled) to the present call, and how much of that was spent waiting to acquire.
+#[derive(Debug, Clone)]
+pub struct LatencyStats {
+    timing: PresentTiming,
+    frames: u32,
+    total: Duration,
+    acquire_wait: Duration,
+    worst: Duration,
+}
+
+impl LatencyStats {
+    pub fn new(timing: PresentTiming) -> LatencyStats {
+        LatencyStats {
+            timing: timing,
+            frames: 0,
+            total: Duration::new(0, 0),
+            acquire_wait: Duration::new(0, 0),
+            worst: Duration::new(0, 0),
+        }
+    }
+
+    /// Records a presented frame that started at `start`, began acquiring at `acquire_start`,
+    /// got its image at `acquired` and was presented at `presented`.
+    pub fn record(&mut self, start: Instant, acquire_start: Instant, acquired: Instant, presented: Instant) {
+        let latency = presented.duration_since(start);
+        self.frames += 1;
+        self.total += latency;
+        self.acquire_wait += acquired.duration_since(acquire_start);
+        if latency > self.worst {
+            self.worst = latency;
+        }
+    }
+
+    pub fn log(&self) {
+        if self.frames == 0 {
+            return;
+        }
+        let frames = self.frames as f32;
+        info!("{:?} latency: {:.2} ms average to present ({:.2} ms waiting to acquire), {:.2} ms worst, over {} frames",
+              self.timing, time::seconds(self.total) * 1000.0 / frames, time::seconds(self.acquire_wait) * 1000.0 / frames, time::seconds(self.worst) * 1000.0, self.frames);
+    }
+}
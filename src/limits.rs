@@ -0,0 +1,136 @@
+//! Checks of create infos against the device's `PhysicalDeviceLimits`, so an out-of-limit image,
+//! framebuffer, push constant block or descriptor set layout fails with a message naming the
+//! limit, rather than as undefined behavior or a bare error code from the driver.
+use std;
+use std::fmt;
+use vk::types::*;
+
+/// Shader stages with their own per-stage descriptor limits.
+const STAGES: [(ShaderStageFlags, &'static str); 6] = [
+    (SHADER_STAGE_VERTEX_BIT, "vertex"),
+    (SHADER_STAGE_TESSELLATION_CONTROL_BIT, "tessellation control"),
+    (SHADER_STAGE_TESSELLATION_EVALUATION_BIT, "tessellation evaluation"),
+    (SHADER_STAGE_GEOMETRY_BIT, "geometry"),
+    (SHADER_STAGE_FRAGMENT_BIT, "fragment"),
+    (SHADER_STAGE_COMPUTE_BIT, "compute"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitError {
+    /// What was requested, e.g. "2D image width".
+    pub what: String,
+    pub requested: u32,
+    /// Name of the limit in the spec.
+    pub limit: &'static str,
+    pub max: u32,
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} exceeds the device's {} of {}", self.what, self.requested, self.limit, self.max)
+    }
+}
+
+impl std::error::Error for LimitError {
+    fn description(&self) -> &str {
+        "request exceeds a device limit"
+    }
+}
+
+fn check<S: Into<String>>(what: S, requested: u32, limit: &'static str, max: u32) -> std::result::Result<(), LimitError> {
+    if requested <= max {
+        return Ok(());
+    }
+    Err(LimitError {
+        what: what.into(),
+        requested: requested,
+        limit: limit,
+        max: max,
+    })
+}
+
+pub struct DeviceLimits {
+    limits: PhysicalDeviceLimits,
+}
+
+impl DeviceLimits {
+    pub fn new(limits: &PhysicalDeviceLimits) -> DeviceLimits {
+        DeviceLimits {
+            limits: limits.clone(),
+        }
+    }
+
+    pub fn limits(&self) -> &PhysicalDeviceLimits {
+        &self.limits
+    }
+
+    pub fn check_image(&self, create_info: &ImageCreateInfo) -> std::result::Result<(), LimitError> {
+        let extent = &create_info.extent;
+        if create_info.flags.intersects(IMAGE_CREATE_CUBE_COMPATIBLE_BIT) {
+            let max = self.limits.max_image_dimension_cube;
+            check("Cube image width", extent.width, "maxImageDimensionCube", max)?;
+            check("Cube image height", extent.height, "maxImageDimensionCube", max)?;
+        } else {
+            match create_info.image_type {
+                ImageType::Type1d => {
+                    check("1D image width", extent.width, "maxImageDimension1D", self.limits.max_image_dimension1d)?;
+                },
+                ImageType::Type2d => {
+                    let max = self.limits.max_image_dimension2d;
+                    check("2D image width", extent.width, "maxImageDimension2D", max)?;
+                    check("2D image height", extent.height, "maxImageDimension2D", max)?;
+                },
+                ImageType::Type3d => {
+                    let max = self.limits.max_image_dimension3d;
+                    check("3D image width", extent.width, "maxImageDimension3D", max)?;
+                    check("3D image height", extent.height, "maxImageDimension3D", max)?;
+                    check("3D image depth", extent.depth, "maxImageDimension3D", max)?;
+                },
+            }
+        }
+        check("Image array layer count", create_info.array_layers, "maxImageArrayLayers", self.limits.max_image_array_layers)
+    }
+
+    pub fn check_framebuffer(&self, width: u32, height: u32, layers: u32) -> std::result::Result<(), LimitError> {
+        check("Framebuffer width", width, "maxFramebufferWidth", self.limits.max_framebuffer_width)?;
+        check("Framebuffer height", height, "maxFramebufferHeight", self.limits.max_framebuffer_height)?;
+        check("Framebuffer layer count", layers, "maxFramebufferLayers", self.limits.max_framebuffer_layers)
+    }
+
+    pub fn check_push_constants(&self, ranges: &[PushConstantRange]) -> std::result::Result<(), LimitError> {
+        for range in ranges {
+            check(format!("Push constant range ({:?}) end", range.stage_flags), range.offset + range.size, "maxPushConstantsSize", self.limits.max_push_constants_size)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the per-stage descriptor counts of one set layout. Limits apply across every set of
+    /// a pipeline layout, so for pipelines with several sets, pass all of their bindings.
+    pub fn check_descriptor_bindings(&self, bindings: &[DescriptorSetLayoutBinding]) -> std::result::Result<(), LimitError> {
+        for &(stage, stage_name) in STAGES.iter() {
+            let count = |types: &[DescriptorType]| -> u32 {
+                bindings.iter()
+                    .filter(|b| b.stage_flags.intersects(stage) && types.contains(&b.descriptor_type))
+                    .map(|b| b.descriptor_count)
+                    .sum()
+            };
+            let checks = [
+                ("sampler", &[DescriptorType::Sampler, DescriptorType::CombinedImageSampler][..], "maxPerStageDescriptorSamplers", self.limits.max_per_stage_descriptor_samplers),
+                ("uniform buffer", &[DescriptorType::UniformBuffer, DescriptorType::UniformBufferDynamic][..], "maxPerStageDescriptorUniformBuffers", self.limits.max_per_stage_descriptor_uniform_buffers),
+                ("storage buffer", &[DescriptorType::StorageBuffer, DescriptorType::StorageBufferDynamic][..], "maxPerStageDescriptorStorageBuffers", self.limits.max_per_stage_descriptor_storage_buffers),
+                ("sampled image", &[DescriptorType::CombinedImageSampler, DescriptorType::SampledImage, DescriptorType::UniformTexelBuffer][..], "maxPerStageDescriptorSampledImages", self.limits.max_per_stage_descriptor_sampled_images),
+                ("storage image", &[DescriptorType::StorageImage, DescriptorType::StorageTexelBuffer][..], "maxPerStageDescriptorStorageImages", self.limits.max_per_stage_descriptor_storage_images),
+                ("input attachment", &[DescriptorType::InputAttachment][..], "maxPerStageDescriptorInputAttachments", self.limits.max_per_stage_descriptor_input_attachments),
+            ];
+            for &(name, types, limit, max) in checks.iter() {
+                check(format!("Number of {} {} descriptors", stage_name, name), count(types), limit, max)?;
+            }
+            let resources = bindings.iter()
+                .filter(|b| b.stage_flags.intersects(stage) && b.descriptor_type != DescriptorType::Sampler)
+                .map(|b| b.descriptor_count)
+                .sum();
+            check(format!("Number of {} shader resources", stage_name), resources, "maxPerStageResources", self.limits.max_per_stage_resources)?;
+        }
+        Ok(())
+    }
+}
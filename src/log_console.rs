@@ -0,0 +1,429 @@
+//! An in-app console showing recent log output, so validation messages and debug output are
+//! visible without a terminal. The logger from `logging::init` keeps everything up to
+//! `logging::CAPTURE_LEVEL` in a ring buffer shared with the `LogConsole`.
+//!
+//! The console is toggled with the grave accent key. While it's open it takes the keyboard:
+//! typing filters the lines by a case-insensitive search, Backspace and Delete edit it, Home
+//! cycles the minimum severity shown, End cycles the level logged to stderr and the JSON file
+//! (for targets without their own level), and Up/Down and PageUp/PageDown scroll. Text starting
+//! with `/` is a console variable command instead of a search, run with Enter: `/name value` sets
+//! a variable and `/name` logs it (see `cvar`). It's drawn by `shaders/log_console_fragment.glsl`
+//! with the font from `bitmap_font`.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use glfw;
+use log::LogLevel;
+use std;
+use std::collections::VecDeque;
+use std::ptr;
+use std::sync::{ Arc, Mutex };
+use vk::types::*;
+use ::bitmap_font;
+use ::cvar;
+use ::buffer::DeviceBuffer;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::logging::{ self, LogHandles, LogLevels };
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::rect::{ self, ExtentExt, RectExt, ViewportExt };
+use ::vk_mem::MemoryAllocator;
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/log_console_fragment.frag.spv";
+
+/// Binding of the glyphs and text cells.
+pub const TEXT_BINDING: u32 = 0;
+
+const HISTORY_LINES: usize = 2048;
+
+const COLUMNS: u32 = 120;
+/// The header plus the log lines below it.
+const ROWS: u32 = 25;
+const CELL_SCALE: f32 = 1.0;
+const MARGIN: f32 = 8.0;
+const OPACITY: f32 = 0.8;
+/// Starts a console variable command instead of a search.
+const COMMAND_PREFIX: char = '/';
+
+/// Lines of a panel, drawn with the console's font and colors but outside of it, e.g. the demo
+/// list. Longer or more lines are cut off.
+const PANEL_COLUMNS: u32 = COLUMNS;
+const PANEL_ROWS: u32 = 24;
+
+/// Color index of a cell, see `COLORS` in `shaders/log_console_fragment.glsl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellColor {
+    Header,
+    Level(LogLevel),
+    /// Greyed out, for something that can't be used.
+    Disabled,
+}
+
+impl CellColor {
+    fn index(&self) -> u32 {
+        match *self {
+            CellColor::Header => 0,
+            CellColor::Level(level) => level as u32,
+            // Shares trace's grey
+            CellColor::Disabled => 5,
+        }
+    }
+}
+
+/// `text` as a row of cells in `color`, cut off or padded with spaces to `columns`.
+fn row_cells(text: &str, color: CellColor, columns: usize) -> Vec<u32> {
+    let mut cells: Vec<u32> = text.chars().take(columns).map(|c| bitmap_font::char_code(c) as u32 | color.index() << 8).collect();
+    cells.resize(columns, b' ' as u32);
+    cells
+}
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: LogLevel,
+    pub target: String,
+    pub message: String,
+}
+
+impl LogLine {
+    pub fn text(&self) -> String {
+        format!("{:5} {}: {}", self.level, self.target, self.message)
+    }
+}
+
+/// The captured lines, oldest first, shared between the logger and the console.
+#[derive(Clone, Default)]
+pub struct LogHistory {
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+}
+
+impl LogHistory {
+    pub fn push(&self, line: LogLine) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == HISTORY_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Up to the last `count` lines, or nothing if the logger is in the middle of adding one,
+    /// which is what a thread panicking while logging would find rather than deadlocking.
+    pub fn try_recent(&self, count: usize) -> Option<Vec<LogLine>> {
+        self.lines.try_lock().ok().map(|lines| {
+            let skip = lines.len().saturating_sub(count);
+            lines.iter().skip(skip).cloned().collect()
+        })
+    }
+
+    /// The lines at or above `min_level` that contain `search`, which must be lowercase.
+    fn matching(&self, min_level: LogLevel, search: &str) -> Vec<LogLine> {
+        self.lines.lock().unwrap().iter()
+            .filter(|line| line.level <= min_level)
+            .filter(|line| search.is_empty() || line.message.to_lowercase().contains(search) || line.target.to_lowercase().contains(search))
+            .cloned()
+            .collect()
+    }
+}
+
+fn next_level(level: LogLevel) -> LogLevel {
+    match level {
+        LogLevel::Error => LogLevel::Warn,
+        LogLevel::Warn => LogLevel::Info,
+        LogLevel::Info => LogLevel::Debug,
+        LogLevel::Debug | LogLevel::Trace => LogLevel::Error,
+    }
+}
+
+/// What the console shows and whether it's open, kept across swapchain recreation.
+pub struct LogConsole {
+    history: LogHistory,
+    levels: LogLevels,
+    open: bool,
+    min_level: LogLevel,
+    /// Lowercase, so matching is case-insensitive.
+    search: String,
+    /// Lines up from the newest.
+    scroll: usize,
+}
+
+impl LogConsole {
+    pub fn new(logs: LogHandles) -> LogConsole {
+        LogConsole {
+            history: logs.history,
+            levels: logs.levels,
+            open: false,
+            min_level: LogLevel::Debug,
+            search: String::new(),
+            scroll: 0,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Returns whether the console handled `key`, which it does for every key while it's open.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        let page = (ROWS - 1) as usize;
+        match key {
+            glfw::Key::GraveAccent => {
+                self.open = !self.open;
+                return true;
+            },
+            _ if !self.open => return false,
+            glfw::Key::Home => {
+                self.min_level = next_level(self.min_level);
+                self.scroll = 0;
+            },
+            glfw::Key::End => self.levels.update(|filter| {
+                let level = logging::next_level_filter(filter.default_level());
+                filter.set_default_level(level);
+            }),
+            glfw::Key::Backspace => {
+                self.search.pop();
+                self.scroll = 0;
+            },
+            glfw::Key::Delete => {
+                self.search.clear();
+                self.scroll = 0;
+            },
+            glfw::Key::Enter | glfw::Key::KpEnter => self.run_command(),
+            glfw::Key::Up => self.scroll += 1,
+            glfw::Key::Down => self.scroll = self.scroll.saturating_sub(1),
+            glfw::Key::PageUp => self.scroll += page,
+            glfw::Key::PageDown => self.scroll = self.scroll.saturating_sub(page),
+            _ => {},
+        }
+        true
+    }
+
+    /// Adds typed characters to the search while the console is open.
+    pub fn handle_char(&mut self, c: char) -> bool {
+        // The toggle key types one too
+        if !self.open || c == '`' {
+            return false;
+        }
+        if self.is_command() {
+            // Values can be case-sensitive
+            self.search.push(c);
+        } else {
+            self.search.extend(c.to_lowercase());
+        }
+        self.scroll = 0;
+        true
+    }
+
+    fn is_command(&self) -> bool {
+        self.search.starts_with(COMMAND_PREFIX)
+    }
+
+    fn run_command(&mut self) {
+        if !self.is_command() {
+            return;
+        }
+        match cvar::registry().execute(&self.search[COMMAND_PREFIX.len_utf8()..]) {
+            Ok(()) => self.search.clear(),
+            Err(e) => warn!("{}", e),
+        }
+        self.scroll = 0;
+    }
+
+    /// The text grid, `COLUMNS` by `ROWS` cells of a character code and a `CellColor`: a header
+    /// with the filter, then as many of the matching lines as fit, newest at the bottom.
+    fn cells(&self) -> Vec<u32> {
+        let lines = self.history.matching(self.min_level, if self.is_command() { "" } else { &self.search });
+        let visible = (ROWS - 1) as usize;
+        let scroll = std::cmp::min(self.scroll, lines.len().saturating_sub(visible));
+        let end = lines.len() - scroll;
+        let start = end.saturating_sub(visible);
+        let header = format!("log >= {}  {}: {}_  {} lines, {} up from the newest  (logging {} by default)", self.min_level, if self.is_command() { "command" } else { "search" }, self.search, lines.len(), scroll, self.levels.filter().default_level());
+
+        let mut cells = row_cells(&header, CellColor::Header, COLUMNS as usize);
+        for line in lines[start..end].iter() {
+            cells.extend(row_cells(&line.text(), CellColor::Level(line.level), COLUMNS as usize));
+        }
+        cells.resize((COLUMNS * ROWS) as usize, b' ' as u32);
+        cells
+    }
+}
+
+/// Push constant block read by `shaders/log_console_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ConsolePushConstants {
+    pub origin: [f32; 2],
+    pub cell_size: [f32; 2],
+    pub columns: u32,
+    pub rows: u32,
+    pub opacity: f32,
+    /// Where in the buffer the grid starts, in cells.
+    pub first_cell: u32,
+}
+
+impl ConsolePushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<ConsolePushConstants>() as u32,
+        }
+    }
+}
+
+/// Draws the `LogConsole` over the final image, and panels of other text with `cmd_draw_panel`.
+/// The text is rewritten every frame it's drawn, so there's a buffer for each frame in flight,
+/// with room for the console and one panel.
+pub struct ConsolePass<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    buffers: Vec<DeviceBuffer<'d, D>>,
+    set_layout: DescriptorSetLayout,
+    layout: PipelineLayout,
+    pipeline: Pipeline,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_sets: Vec<DescriptorSet>,
+}
+
+impl<'d, D: DeviceV1_0> ConsolePass<'d, D> {
+    /// Draws into subpass 0 of `render_pass`, with a dynamic viewport. `vertex_shader` must be
+    /// `shaders/fullscreen_vertex.glsl` and `fragment_shader` `FRAGMENT_SHADER`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, render_pass: RenderPass, frames_in_flight: usize, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> VkResult<ConsolePass<'d, D>> {
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: frames_in_flight as u32,
+        }];
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = ConsolePass {
+            device: device,
+            buffers: Vec::with_capacity(frames_in_flight),
+            set_layout: DescriptorSetLayout::null(),
+            layout: PipelineLayout::null(),
+            pipeline: Pipeline::null(),
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, frames_in_flight as u32),
+            descriptor_sets: Vec::with_capacity(frames_in_flight),
+        };
+        let bindings = [DescriptorSetLayoutBinding {
+            binding: TEXT_BINDING,
+            descriptor_type: DescriptorType::StorageBuffer,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            p_immutable_samplers: ptr::null(),
+        }];
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        ret.set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        let push_constant_range = ConsolePushConstants::range();
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 1,
+            p_set_layouts: &ret.set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+        ret.pipeline = unsafe { pipeline_builder(vertex_shader, fragment_shader).build_raw(device, PipelineCache::null(), ret.layout, render_pass, 0) }?;
+
+        // The glyphs never change, so they're only written once
+        let glyphs = bitmap_font::packed_glyphs();
+        let glyph_size = (glyphs.len() * std::mem::size_of::<u32>()) as DeviceSize;
+        let size = glyph_size + (COLUMNS * ROWS + PANEL_COLUMNS * PANEL_ROWS) as DeviceSize * std::mem::size_of::<u32>() as DeviceSize;
+        for _ in 0..frames_in_flight {
+            let buffer = DeviceBuffer::host_visible(device, allocator, size, BUFFER_USAGE_STORAGE_BUFFER_BIT)?;
+            buffer.write(0, &glyphs)?;
+            let descriptor_set = ret.descriptor_allocator.allocate(ret.set_layout)?;
+            descriptor::update_descriptor_set(device, descriptor_set, &[DescriptorWrite::StorageBuffer {
+                binding: TEXT_BINDING,
+                buffer: buffer.buffer(),
+                offset: 0,
+                range: size,
+            }]);
+            ret.buffers.push(buffer);
+            ret.descriptor_sets.push(descriptor_set);
+        }
+        Ok(ret)
+    }
+
+    /// Writes `console`'s text into frame-in-flight `slot`'s buffer, and records the draw over
+    /// the whole of `extent` inside the render pass. The GPU must be done with the last frame
+    /// that used `slot`.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, slot: usize, console: &LogConsole, extent: &Extent2D) -> VkResult<()> {
+        self.cmd_draw_cells(command_buffer, slot, &console.cells(), [MARGIN, MARGIN], COLUMNS, ROWS, 0, extent)
+    }
+
+    /// Like `cmd_draw`, but for `lines` in the bottom left corner of `extent`, sized to fit them.
+    pub unsafe fn cmd_draw_panel(&self, command_buffer: CommandBuffer, slot: usize, lines: &[(String, CellColor)], extent: &Extent2D) -> VkResult<()> {
+        let rows = std::cmp::min(lines.len() as u32, PANEL_ROWS);
+        let columns = lines.iter().map(|(text, _)| text.chars().count() as u32).max().map(|longest| std::cmp::min(longest, PANEL_COLUMNS)).unwrap_or(0);
+        if rows == 0 || columns == 0 {
+            return Ok(());
+        }
+        let cells: Vec<u32> = lines[..rows as usize].iter().flat_map(|&(ref text, color)| row_cells(text, color, columns as usize)).collect();
+        let origin = [MARGIN, extent.height as f32 - MARGIN - rows as f32 * 8.0 * CELL_SCALE];
+        self.cmd_draw_cells(command_buffer, slot, &cells, origin, columns, rows, COLUMNS * ROWS, extent)
+    }
+
+    /// Writes `cells` into `slot`'s buffer at `first_cell`, and draws them as a `columns` by
+    /// `rows` grid at `origin`.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn cmd_draw_cells(&self, command_buffer: CommandBuffer, slot: usize, cells: &[u32], origin: [f32; 2], columns: u32, rows: u32, first_cell: u32, extent: &Extent2D) -> VkResult<()> {
+        let glyph_size = (bitmap_font::GLYPH_COUNT * bitmap_font::WORDS_PER_GLYPH * std::mem::size_of::<u32>()) as DeviceSize;
+        self.buffers[slot].write(glyph_size + first_cell as DeviceSize * std::mem::size_of::<u32>() as DeviceSize, cells)?;
+        let constants = ConsolePushConstants {
+            origin: origin,
+            cell_size: [8.0 * CELL_SCALE, 8.0 * CELL_SCALE],
+            columns: columns,
+            rows: rows,
+            opacity: OPACITY,
+            first_cell: first_cell,
+        };
+        // Only the grid's pixels have anything to shade
+        let grid = Viewport {
+            x: origin[0],
+            y: origin[1],
+            width: columns as f32 * constants.cell_size[0],
+            height: rows as f32 * constants.cell_size[1],
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = match grid.to_rect().intersect(&extent.to_rect()) {
+            Some(scissor) => scissor,
+            None => return Ok(()),
+        };
+        let viewport = extent.to_viewport();
+        let range = ConsolePushConstants::range();
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, self.pipeline);
+        self.device.cmd_set_viewport(command_buffer, &[viewport]);
+        self.device.cmd_set_scissor(command_buffer, &[scissor]);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, self.layout, 0, &[self.descriptor_sets[slot]], &[]);
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, self.layout, range.stage_flags, range.offset, range.size, &constants as *const ConsolePushConstants as *const c_void);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        Ok(())
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for ConsolePass<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying log console pass: {:?}", self.pipeline);
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl`, alpha blended over the image.
+pub fn pipeline_builder(vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    // The extent only matters for the static viewport, which the dynamic one replaces
+    let extent = rect::placeholder_extent();
+    GraphicsPipelineBuilder::new(&extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .blend(BlendPreset::Alpha)
+        .dynamic_viewport_scissor()
+}
@@ -0,0 +1,292 @@
+//! The `log` backend. Records are filtered per target, with directives in the same syntax as
+//! `RUST_LOG` (`info,swapchain=debug,allocator=trace`), from `RUST_LOG` and then `--log-level=`.
+//! Whatever passes the filter is printed to stderr and, with `--log-json=<path>`, appended to a
+//! file as one JSON object per line. The levels can be changed while running through
+//! `LogLevels`, e.g. from the log console. Independently of the filter, everything up to
+//! `CAPTURE_LEVEL` goes into the `LogHistory` the console shows.
+//!
+//! Subsystems log under their own targets instead of their module paths, so they can be turned
+//! up on their own: `debug!(target: logging::SWAPCHAIN, ...)`.
+use log::{ self, Log, LogLevelFilter, LogMetadata, LogRecord, MaxLogLevelFilter, SetLoggerError };
+use std;
+use std::fs::File;
+use std::io::{ LineWriter, Write };
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex, RwLock };
+use std::time::{ SystemTime, UNIX_EPOCH };
+use ::cvar::Flag;
+use ::log_console::{ LogHistory, LogLine };
+
+pub const SWAPCHAIN: &'static str = "swapchain";
+pub const ALLOCATOR: &'static str = "allocator";
+pub const UPLOAD: &'static str = "upload";
+pub const PIPELINE: &'static str = "pipeline";
+pub const SHADER: &'static str = "shader";
+pub const PROFILER: &'static str = "profiler";
+
+pub const LEVEL_ARG_PREFIX: &'static str = "--log-level=";
+pub const JSON_ARG_PREFIX: &'static str = "--log-json=";
+/// Directives applied over `RUST_LOG`'s, and where to write JSON lines, empty for nowhere.
+pub const LEVEL_CVAR: &'static str = "log.level";
+pub const JSON_CVAR: &'static str = "log.json";
+pub const FLAGS: &'static [Flag] = &[
+    Flag::List { prefix: LEVEL_ARG_PREFIX, name: LEVEL_CVAR },
+    Flag::Value { prefix: JSON_ARG_PREFIX, name: JSON_CVAR },
+];
+
+/// Records up to this level are kept for the log console, whatever the filter says.
+pub const CAPTURE_LEVEL: LogLevelFilter = LogLevelFilter::Debug;
+
+/// Levels per target. A directive applies to its target and to every target under it, i.e.
+/// `renderer` covers `vk_playground::renderer` as well as `renderer::offscreen`, and the longest
+/// matching directive wins.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    default: LogLevelFilter,
+    targets: Vec<(String, LogLevelFilter)>,
+}
+
+impl Default for LogFilter {
+    /// Only errors, like `env_logger` without `RUST_LOG`.
+    fn default() -> LogFilter {
+        LogFilter {
+            default: LogLevelFilter::Error,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    /// Applies the comma separated directives in `spec` over the current ones. Returns the
+    /// directives that couldn't be parsed.
+    pub fn apply(&mut self, spec: &str) -> Vec<String> {
+        let mut invalid = Vec::new();
+        for directive in spec.split(',').map(|directive| directive.trim()).filter(|directive| !directive.is_empty()) {
+            let mut parts = directive.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(level), None) => match level.parse() {
+                    Ok(level) => self.default = level,
+                    // A bare target turns everything on for it, like in `RUST_LOG`
+                    Err(_) => self.set_target(level, LogLevelFilter::Trace),
+                },
+                (Some(target), Some(level)) => match level.parse() {
+                    Ok(level) => self.set_target(target, level),
+                    Err(_) => invalid.push(directive.to_string()),
+                },
+                _ => invalid.push(directive.to_string()),
+            }
+        }
+        invalid
+    }
+
+    pub fn set_target(&mut self, target: &str, level: LogLevelFilter) {
+        match self.targets.iter().position(|(existing, _)| existing == target) {
+            Some(idx) => self.targets[idx].1 = level,
+            None => self.targets.push((target.to_string(), level)),
+        }
+    }
+
+    pub fn default_level(&self) -> LogLevelFilter {
+        self.default
+    }
+
+    pub fn set_default_level(&mut self, level: LogLevelFilter) {
+        self.default = level;
+    }
+
+    pub fn level_for(&self, target: &str) -> LogLevelFilter {
+        self.targets.iter()
+            .filter(|&(directive, _)| matches_target(directive, target))
+            .max_by_key(|&(directive, _)| directive.len())
+            .map(|&(_, level)| level)
+            .unwrap_or(self.default)
+    }
+
+    /// The most verbose level anything can log at.
+    pub fn max_level(&self) -> LogLevelFilter {
+        self.targets.iter().map(|&(_, level)| level).fold(self.default, std::cmp::max)
+    }
+}
+
+/// Whether `directive` covers `target`: the same path, a path above it, or (so the crate name
+/// can be left out) the same path after the crate name.
+fn matches_target(directive: &str, target: &str) -> bool {
+    let covers = |path: &str| path == directive || (path.starts_with(directive) && path[directive.len()..].starts_with("::"));
+    covers(target) || target.find("::").map(|idx| covers(&target[idx + 2..])).unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogConfig {
+    pub filter: LogFilter,
+    pub json_path: Option<PathBuf>,
+    /// Directives that couldn't be parsed, reported once the logger is up.
+    invalid: Vec<String>,
+}
+
+impl LogConfig {
+    /// `RUST_LOG`, then `log.level` and `log.json`, which `--log-level=` and `--log-json=` set.
+    pub fn from_cvars() -> LogConfig {
+        let mut ret = LogConfig::default();
+        if let Ok(spec) = std::env::var("RUST_LOG") {
+            ret.invalid = ret.filter.apply(&spec);
+        }
+        let invalid = ret.filter.apply(&cvar!(LEVEL_CVAR, String::new()).get());
+        ret.invalid.extend(invalid);
+        let json_path: String = cvar!(JSON_CVAR, String::new()).get();
+        if !json_path.is_empty() {
+            ret.json_path = Some(PathBuf::from(json_path));
+        }
+        ret
+    }
+}
+
+struct SharedLevels {
+    filter: RwLock<LogFilter>,
+    max_level: MaxLogLevelFilter,
+}
+
+/// The installed logger's filter, to change levels at runtime with.
+#[derive(Clone)]
+pub struct LogLevels {
+    shared: Arc<SharedLevels>,
+}
+
+impl LogLevels {
+    pub fn filter(&self) -> LogFilter {
+        self.shared.filter.read().unwrap().clone()
+    }
+
+    pub fn level_for(&self, target: &str) -> LogLevelFilter {
+        self.shared.filter.read().unwrap().level_for(target)
+    }
+
+    /// Changes the filter through `f`.
+    pub fn update<F: FnOnce(&mut LogFilter)>(&self, f: F) {
+        let mut filter = self.shared.filter.write().unwrap();
+        f(&mut filter);
+        self.shared.max_level.set(std::cmp::max(filter.max_level(), CAPTURE_LEVEL));
+    }
+}
+
+/// What `init` hands back: the console's history and the runtime levels.
+#[derive(Clone)]
+pub struct LogHandles {
+    pub history: LogHistory,
+    pub levels: LogLevels,
+}
+
+struct Logger {
+    levels: LogLevels,
+    json: Option<Mutex<LineWriter<File>>>,
+    history: LogHistory,
+}
+
+impl Logger {
+    fn passes(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.levels.level_for(metadata.target())
+    }
+
+    fn write_json(&self, record: &LogRecord, message: &str) {
+        if let Some(ref json) = self.json {
+            let time = SystemTime::now().duration_since(UNIX_EPOCH).map(::time::seconds).unwrap_or(0.0);
+            let location = record.location();
+            let line = format!("{{\"time\":{:.3},\"level\":\"{}\",\"target\":{},\"module\":{},\"file\":{},\"line\":{},\"message\":{}}}\n",
+                time, record.level(), json_string(record.target()), json_string(location.module_path()), json_string(location.file()), location.line(), json_string(message));
+            // Nowhere left to report a failure to
+            let _ = json.lock().unwrap().write_all(line.as_bytes());
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= CAPTURE_LEVEL || self.passes(metadata)
+    }
+
+    fn log(&self, record: &LogRecord) {
+        let passes = self.passes(record.metadata());
+        if !passes && record.level() > CAPTURE_LEVEL {
+            return;
+        }
+        let message = format!("{}", record.args());
+        if passes {
+            let _ = writeln!(std::io::stderr(), "{}:{}: {}", record.level(), record.target(), message);
+            self.write_json(record, &message);
+        }
+        if record.level() <= CAPTURE_LEVEL {
+            self.history.push(LogLine {
+                level: record.level(),
+                target: record.target().to_string(),
+                message: message,
+            });
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// Installs the logger. A JSON file that can't be created is reported and skipped.
+pub fn init(config: LogConfig) -> std::result::Result<LogHandles, SetLoggerError> {
+    let history = LogHistory::default();
+    let (json, json_error) = match config.json_path {
+        Some(ref path) => match File::create(path) {
+            Ok(file) => (Some(Mutex::new(LineWriter::new(file))), None),
+            Err(e) => (None, Some(e)),
+        },
+        None => (None, None),
+    };
+    let mut levels = None;
+    log::set_logger(|max_level| {
+        max_level.set(std::cmp::max(config.filter.max_level(), CAPTURE_LEVEL));
+        let shared = LogLevels {
+            shared: Arc::new(SharedLevels {
+                filter: RwLock::new(config.filter.clone()),
+                max_level: max_level,
+            }),
+        };
+        levels = Some(shared.clone());
+        Box::new(Logger {
+            levels: shared,
+            json: json,
+            history: history.clone(),
+        })
+    })?;
+    for directive in config.invalid.iter() {
+        warn!("Ignoring invalid log directive {:?}", directive);
+    }
+    if let (Some(path), Some(e)) = (config.json_path.as_ref(), json_error) {
+        warn!("Failed to create JSON log file {:?}: {}", path, e);
+    }
+    Ok(LogHandles {
+        history: history,
+        levels: levels.unwrap(),
+    })
+}
+
+/// The next more verbose level, wrapping from `Trace` around to `Off`.
+pub fn next_level_filter(level: LogLevelFilter) -> LogLevelFilter {
+    match level {
+        LogLevelFilter::Off => LogLevelFilter::Error,
+        LogLevelFilter::Error => LogLevelFilter::Warn,
+        LogLevelFilter::Warn => LogLevelFilter::Info,
+        LogLevelFilter::Info => LogLevelFilter::Debug,
+        LogLevelFilter::Debug => LogLevelFilter::Trace,
+        LogLevelFilter::Trace => LogLevelFilter::Off,
+    }
+}
@@ -0,0 +1,496 @@
+//! Just enough linear algebra for cameras, gizmos, and debug drawing. Matrices are column-major
+//! to match what GLSL expects in uniform and push constant blocks.
+use std::ops::{ Add, Sub, Mul, Neg };
+
+pub mod prelude;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 {
+            x: x,
+            y: y,
+            z: z,
+        }
+    }
+
+    pub fn zero() -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    pub fn unit_x() -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    pub fn unit_y() -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+
+    pub fn unit_z() -> Vec3 {
+        Vec3::new(0.0, 0.0, 1.0)
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Vec3 {
+        self * (1.0 / self.length())
+    }
+
+    /// Any unit vector perpendicular to this one.
+    pub fn any_perpendicular(self) -> Vec3 {
+        let other = if self.x.abs() < 0.9 { Vec3::unit_x() } else { Vec3::unit_y() };
+        self.cross(other).normalize()
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, s: f32) -> Vec3 {
+        Vec3::new(self.x * s, self.y * s, self.z * s)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// Unit quaternion representing a rotation.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    pub fn identity() -> Quat {
+        Quat {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+
+    pub fn from_axis_angle(axis: Vec3, radians: f32) -> Quat {
+        let axis = axis.normalize();
+        let (s, c) = (radians * 0.5).sin_cos();
+        Quat {
+            x: axis.x * s,
+            y: axis.y * s,
+            z: axis.z * s,
+            w: c,
+        }
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        let u = Vec3::new(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.0;
+        v + t * self.w + u.cross(t)
+    }
+
+    pub fn normalize(self) -> Quat {
+        let len = (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt();
+        Quat {
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len,
+            w: self.w / len,
+        }
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Quat {
+        Quat::identity()
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, o: Quat) -> Quat {
+        Quat {
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+        }
+    }
+}
+
+/// Column-major 4x4 matrix. `cols[c][r]` is the element at row `r` of column `c`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub cols: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4 {
+            cols: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn translation(t: Vec3) -> Mat4 {
+        let mut ret = Mat4::identity();
+        ret.cols[3] = [t.x, t.y, t.z, 1.0];
+        ret
+    }
+
+    pub fn scale(s: Vec3) -> Mat4 {
+        let mut ret = Mat4::identity();
+        ret.cols[0][0] = s.x;
+        ret.cols[1][1] = s.y;
+        ret.cols[2][2] = s.z;
+        ret
+    }
+
+    pub fn rotation(q: Quat) -> Mat4 {
+        let x = q.rotate(Vec3::unit_x());
+        let y = q.rotate(Vec3::unit_y());
+        let z = q.rotate(Vec3::unit_z());
+        Mat4 {
+            cols: [
+                [x.x, x.y, x.z, 0.0],
+                [y.x, y.y, y.z, 0.0],
+                [z.x, z.y, z.z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Right-handed view matrix looking from `eye` towards `target`.
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+        let f = (target - eye).normalize();
+        let s = f.cross(up).normalize();
+        let u = s.cross(f);
+        Mat4 {
+            cols: [
+                [s.x, u.x, -f.x, 0.0],
+                [s.y, u.y, -f.y, 0.0],
+                [s.z, u.z, -f.z, 0.0],
+                [-s.dot(eye), -u.dot(eye), f.dot(eye), 1.0],
+            ],
+        }
+    }
+
+    /// Perspective projection for Vulkan's clip space (Y pointing down, depth in `[0, 1]`).
+    pub fn perspective(fov_y_radians: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+        let f = 1.0 / (fov_y_radians * 0.5).tan();
+        Mat4 {
+            cols: [
+                [f / aspect, 0.0, 0.0, 0.0],
+                [0.0, -f, 0.0, 0.0],
+                [0.0, 0.0, far / (near - far), -1.0],
+                [0.0, 0.0, (near * far) / (near - far), 0.0],
+            ],
+        }
+    }
+
+    /// Transforms a direction (`w = 0`), ignoring translation.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let v = self.transform([v.x, v.y, v.z, 0.0]);
+        Vec3::new(v[0], v[1], v[2])
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let v = self.transform([p.x, p.y, p.z, 1.0]);
+        Vec3::new(v[0] / v[3], v[1] / v[3], v[2] / v[3])
+    }
+
+    pub fn transform(&self, v: [f32; 4]) -> [f32; 4] {
+        let mut ret = [0.0; 4];
+        for (r, value) in ret.iter_mut().enumerate() {
+            *value = (0..4).map(|c| self.cols[c][r] * v[c]).sum();
+        }
+        ret
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut ret = Mat4::identity();
+        for c in 0..4 {
+            for r in 0..4 {
+                ret.cols[c][r] = self.cols[r][c];
+            }
+        }
+        ret
+    }
+
+    /// General inverse via cofactor expansion. Returns `None` for singular matrices.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let m = &self.cols;
+        let a2323 = m[2][2] * m[3][3] - m[2][3] * m[3][2];
+        let a1323 = m[2][1] * m[3][3] - m[2][3] * m[3][1];
+        let a1223 = m[2][1] * m[3][2] - m[2][2] * m[3][1];
+        let a0323 = m[2][0] * m[3][3] - m[2][3] * m[3][0];
+        let a0223 = m[2][0] * m[3][2] - m[2][2] * m[3][0];
+        let a0123 = m[2][0] * m[3][1] - m[2][1] * m[3][0];
+        let a2313 = m[1][2] * m[3][3] - m[1][3] * m[3][2];
+        let a1313 = m[1][1] * m[3][3] - m[1][3] * m[3][1];
+        let a1213 = m[1][1] * m[3][2] - m[1][2] * m[3][1];
+        let a2312 = m[1][2] * m[2][3] - m[1][3] * m[2][2];
+        let a1312 = m[1][1] * m[2][3] - m[1][3] * m[2][1];
+        let a1212 = m[1][1] * m[2][2] - m[1][2] * m[2][1];
+        let a0313 = m[1][0] * m[3][3] - m[1][3] * m[3][0];
+        let a0213 = m[1][0] * m[3][2] - m[1][2] * m[3][0];
+        let a0312 = m[1][0] * m[2][3] - m[1][3] * m[2][0];
+        let a0212 = m[1][0] * m[2][2] - m[1][2] * m[2][0];
+        let a0113 = m[1][0] * m[3][1] - m[1][1] * m[3][0];
+        let a0112 = m[1][0] * m[2][1] - m[1][1] * m[2][0];
+
+        let det = m[0][0] * (m[1][1] * a2323 - m[1][2] * a1323 + m[1][3] * a1223)
+            - m[0][1] * (m[1][0] * a2323 - m[1][2] * a0323 + m[1][3] * a0223)
+            + m[0][2] * (m[1][0] * a1323 - m[1][1] * a0323 + m[1][3] * a0123)
+            - m[0][3] * (m[1][0] * a1223 - m[1][1] * a0223 + m[1][2] * a0123);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        Some(Mat4 {
+            cols: [
+                [
+                    inv_det * (m[1][1] * a2323 - m[1][2] * a1323 + m[1][3] * a1223),
+                    inv_det * -(m[0][1] * a2323 - m[0][2] * a1323 + m[0][3] * a1223),
+                    inv_det * (m[0][1] * a2313 - m[0][2] * a1313 + m[0][3] * a1213),
+                    inv_det * -(m[0][1] * a2312 - m[0][2] * a1312 + m[0][3] * a1212),
+                ],
+                [
+                    inv_det * -(m[1][0] * a2323 - m[1][2] * a0323 + m[1][3] * a0223),
+                    inv_det * (m[0][0] * a2323 - m[0][2] * a0323 + m[0][3] * a0223),
+                    inv_det * -(m[0][0] * a2313 - m[0][2] * a0313 + m[0][3] * a0213),
+                    inv_det * (m[0][0] * a2312 - m[0][2] * a0312 + m[0][3] * a0212),
+                ],
+                [
+                    inv_det * (m[1][0] * a1323 - m[1][1] * a0323 + m[1][3] * a0123),
+                    inv_det * -(m[0][0] * a1323 - m[0][1] * a0323 + m[0][3] * a0123),
+                    inv_det * (m[0][0] * a1313 - m[0][1] * a0313 + m[0][3] * a0113),
+                    inv_det * -(m[0][0] * a1312 - m[0][1] * a0312 + m[0][3] * a0112),
+                ],
+                [
+                    inv_det * -(m[1][0] * a1223 - m[1][1] * a0223 + m[1][2] * a0123),
+                    inv_det * (m[0][0] * a1223 - m[0][1] * a0223 + m[0][2] * a0123),
+                    inv_det * -(m[0][0] * a1213 - m[0][1] * a0213 + m[0][2] * a0113),
+                    inv_det * (m[0][0] * a1212 - m[0][1] * a0212 + m[0][2] * a0112),
+                ],
+            ],
+        })
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::identity()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut ret = Mat4 { cols: [[0.0; 4]; 4] };
+        for c in 0..4 {
+            ret.cols[c] = self.transform(other.cols[c]);
+        }
+        ret
+    }
+}
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_points(points: &[Vec3]) -> Option<Aabb> {
+        points.split_first().map(|(&first, rest)| {
+            rest.iter().fold(Aabb { min: first, max: first }, |aabb, p| Aabb {
+                min: Vec3::new(aabb.min.x.min(p.x), aabb.min.y.min(p.y), aabb.min.z.min(p.z)),
+                max: Vec3::new(aabb.max.x.max(p.x), aabb.max.y.max(p.y), aabb.max.z.max(p.z)),
+            })
+        })
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extents(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// Bounds of this box after transformation by `m`. This is conservative, so a rotated box
+    /// grows to still contain all of its corners.
+    pub fn transform(&self, m: &Mat4) -> Aabb {
+        let corners: Vec<Vec3> = self.corners().iter().map(|&c| m.transform_point(c)).collect();
+        Aabb::from_points(&corners).unwrap()
+    }
+}
+
+/// The six planes bounding a view-projection's clip volume, for culling. Each plane is
+/// `(normal, distance)` with the normal pointing inwards, so points inside have
+/// `normal.dot(p) + distance >= 0` for every plane.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [(Vec3, f32); 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a matrix projecting into Vulkan's clip space, with depth in
+    /// `[0, 1]`.
+    pub fn from_view_projection(m: &Mat4) -> Frustum {
+        let row = |r: usize| [m.cols[0][r], m.cols[1][r], m.cols[2][r], m.cols[3][r]];
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let plane = |p: [f32; 4]| {
+            let normal = Vec3::new(p[0], p[1], p[2]);
+            let length = normal.length();
+            (normal * (1.0 / length), p[3] / length)
+        };
+        let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+        let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+        Frustum {
+            planes: [
+                plane(add(w, x)),
+                plane(sub(w, x)),
+                plane(add(w, y)),
+                plane(sub(w, y)),
+                plane(z),
+                plane(sub(w, z)),
+            ],
+        }
+    }
+
+    /// Whether any of `aabb` may be inside. Conservative: boxes near the frustum's corners can
+    /// pass without actually intersecting it.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|&(normal, distance)| {
+            // The corner furthest along the normal
+            let p = Vec3::new(
+                if normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if normal.z >= 0.0 { aabb.max.z } else { aabb.min.z }
+            );
+            normal.dot(p) + distance >= 0.0
+        })
+    }
+}
+
+/// A half-line used for mouse picking.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// Builds the world-space ray under a cursor position given in window pixels.
+    pub fn from_cursor(cursor: (f64, f64), viewport_size: (u32, u32), inverse_view_projection: &Mat4) -> Ray {
+        let ndc_x = (2.0 * cursor.0 / viewport_size.0 as f64 - 1.0) as f32;
+        let ndc_y = (2.0 * cursor.1 / viewport_size.1 as f64 - 1.0) as f32;
+        let near = inverse_view_projection.transform_point(Vec3::new(ndc_x, ndc_y, 0.0));
+        let far = inverse_view_projection.transform_point(Vec3::new(ndc_x, ndc_y, 1.0));
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// Distance along the ray at which it crosses the plane, if it does so in front of the origin.
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let denom = normal.dot(self.direction);
+        if denom.abs() < 1e-6 {
+            return None;
+        }
+        let t = (point - self.origin).dot(normal) / denom;
+        if t >= 0.0 { Some(t) } else { None }
+    }
+
+    /// Parameters `(t_ray, t_line)` of the closest points between this ray and the infinite line
+    /// through `point` along unit `direction`. `None` if the two are parallel.
+    pub fn closest_to_line(&self, point: Vec3, direction: Vec3) -> Option<(f32, f32)> {
+        let w = self.origin - point;
+        let b = self.direction.dot(direction);
+        let d = self.direction.dot(w);
+        let e = direction.dot(w);
+        let denom = 1.0 - b * b;
+        if denom.abs() < 1e-6 {
+            return None;
+        }
+        Some(((b * e - d) / denom, (e - b * d) / denom))
+    }
+}
@@ -0,0 +1,130 @@
+//! Scalar helpers that come up everywhere: clamping, blending, and rounding sizes for the GPU.
+//! `use ::math::prelude::*;` brings them all in.
+use std;
+use std::ops::{ Add, Div, Sub };
+
+/// `value` limited to `min..max`, inclusive. `min` may equal `max`, e.g. for a surface that only
+/// takes one size.
+pub fn clamp<T: PartialOrd>(value: T, min: T, max: T) -> T {
+    debug_assert!(min <= max);
+    if value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    }
+}
+
+/// `a` at `t = 0`, `b` at `t = 1`, and a straight line between and beyond.
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// 0 at `edge0` and below, 1 at `edge1` and above, and a smooth Hermite curve between, like GLSL's.
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// The first multiple of `alignment` at or after `value`, e.g. for buffer offsets. Vulkan's
+/// alignments are powers of two, but any non-zero one works.
+pub fn align_up(value: u64, alignment: u64) -> u64 {
+    debug_assert!(alignment > 0);
+    value.div_ceil(alignment) * alignment
+}
+
+/// `value` over `divisor`, rounded up, e.g. for how many workgroups cover `value` invocations.
+pub fn div_ceil<T>(value: T, divisor: T) -> T where T: Copy + Add<Output=T> + Sub<Output=T> + Div<Output=T> + From<u8> {
+    (value + (divisor - T::from(1))) / divisor
+}
+
+/// The smallest power of two at or above `value`, with 0 rounding up to 1, or `None` above the
+/// largest power of two a `u32` holds.
+pub fn next_pow2(value: u32) -> Option<u32> {
+    std::cmp::max(value, 1).checked_next_power_of_two()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_limits_to_the_bounds() {
+        assert_eq!(clamp(5, 0, 10), 5);
+        assert_eq!(clamp(-3, 0, 10), 0);
+        assert_eq!(clamp(12, 0, 10), 10);
+        assert_eq!(clamp(0.5, 0.0, 1.0), 0.5);
+    }
+
+    #[test]
+    fn clamp_with_equal_bounds() {
+        assert_eq!(clamp(0, 4, 4), 4);
+        assert_eq!(clamp(4, 4, 4), 4);
+        assert_eq!(clamp(9, 4, 4), 4);
+    }
+
+    #[test]
+    fn lerp_hits_the_ends_and_extrapolates() {
+        assert_eq!(lerp(2.0, 6.0, 0.0), 2.0);
+        assert_eq!(lerp(2.0, 6.0, 1.0), 6.0);
+        assert_eq!(lerp(2.0, 6.0, 0.5), 4.0);
+        assert_eq!(lerp(2.0, 6.0, 2.0), 10.0);
+        assert_eq!(lerp(2.0, 6.0, -1.0), -2.0);
+    }
+
+    #[test]
+    fn smoothstep_is_flat_outside_the_edges() {
+        assert_eq!(smoothstep(1.0, 3.0, 0.0), 0.0);
+        assert_eq!(smoothstep(1.0, 3.0, 1.0), 0.0);
+        assert_eq!(smoothstep(1.0, 3.0, 2.0), 0.5);
+        assert_eq!(smoothstep(1.0, 3.0, 3.0), 1.0);
+        assert_eq!(smoothstep(1.0, 3.0, 4.0), 1.0);
+        assert!(smoothstep(0.0, 1.0, 0.25) < 0.25);
+        assert!(smoothstep(0.0, 1.0, 0.75) > 0.75);
+    }
+
+    #[test]
+    fn align_up_rounds_to_the_next_multiple() {
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert_eq!(align_up(257, 256), 512);
+        assert_eq!(align_up(7, 1), 7);
+        assert_eq!(align_up(10, 3), 12);
+    }
+
+    #[test]
+    fn align_up_near_the_top_of_the_range() {
+        let top = u64::MAX - 7;
+        assert_eq!(align_up(top, 8), top);
+        assert_eq!(align_up(top - 3, 8), top);
+    }
+
+    #[test]
+    fn div_ceil_rounds_up() {
+        assert_eq!(div_ceil(0u32, 8), 0);
+        assert_eq!(div_ceil(1u32, 8), 1);
+        assert_eq!(div_ceil(8u32, 8), 1);
+        assert_eq!(div_ceil(9u32, 8), 2);
+        assert_eq!(div_ceil(10usize, 3), 4);
+    }
+
+    #[test]
+    fn next_pow2_rounds_up() {
+        assert_eq!(next_pow2(0), Some(1));
+        assert_eq!(next_pow2(1), Some(1));
+        assert_eq!(next_pow2(2), Some(2));
+        assert_eq!(next_pow2(3), Some(4));
+        assert_eq!(next_pow2(1000), Some(1024));
+        assert_eq!(next_pow2(1 << 20), Some(1 << 20));
+    }
+
+    #[test]
+    fn next_pow2_is_none_past_the_largest_power_of_two() {
+        assert_eq!(next_pow2((1 << 31) - 1), Some(1 << 31));
+        assert_eq!(next_pow2(1 << 31), Some(1 << 31));
+        assert_eq!(next_pow2((1 << 31) + 1), None);
+        assert_eq!(next_pow2(u32::MAX), None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Indexed triangle meshes and the pipelines that draw them. Vertices can be fetched two ways:
+//! through the fixed-function vertex input state, or pulled by the vertex shader from a storage
+//! buffer by `gl_VertexIndex` (`shaders/mesh_pulling_vertex.glsl`), so the two can be compared
+//! on the same data.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::buffer::{ self, DeviceBuffer, IndexedVertexBuffer };
+use ::command::Drawable;
+use ::descriptor::DescriptorWrite;
+use ::immediate::ImmediateContext;
+use ::math::{ Aabb, Mat4, Vec3 };
+use ::pipeline::GraphicsPipelineBuilder;
+use ::vk_mem::MemoryAllocator;
+
+pub const VERTEX_SHADER: &'static str = "shaders/mesh_vertex.vert.spv";
+pub const PULLING_VERTEX_SHADER: &'static str = "shaders/mesh_pulling_vertex.vert.spv";
+pub const FRAGMENT_SHADER: &'static str = "shaders/mesh_fragment.frag.spv";
+
+/// Binding of the vertex storage buffer in `shaders/mesh_pulling_vertex.glsl`.
+pub const VERTEX_BUFFER_BINDING: u32 = 0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl MeshVertex {
+    pub fn binding_descriptions() -> [VertexInputBindingDescription; 1] {
+        [VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<MeshVertex>() as u32,
+            input_rate: VertexInputRate::Vertex,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [VertexInputAttributeDescription; 3] {
+        [
+            VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: Format::R32g32b32Sfloat,
+                offset: 0,
+            },
+            VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: Format::R32g32b32Sfloat,
+                offset: 12,
+            },
+            VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: Format::R32g32Sfloat,
+                offset: 24,
+            },
+        ]
+    }
+}
+
+fn vec3(a: [f32; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+/// Triangles of `MeshVertex`es on the CPU, e.g. from `obj::load`.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn bounds(&self) -> Aabb {
+        let positions: Vec<Vec3> = self.vertices.iter().map(|v| vec3(v.position)).collect();
+        Aabb::from_points(&positions).unwrap_or(Aabb { min: Vec3::zero(), max: Vec3::zero() })
+    }
+
+    /// Replaces every normal with the average of the faces around its vertex, weighted by area.
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![Vec3::zero(); self.vertices.len()];
+        for triangle in self.indices.chunks(3).filter(|triangle| triangle.len() == 3) {
+            let (a, b, c) = (vec3(self.vertices[triangle[0] as usize].position), vec3(self.vertices[triangle[1] as usize].position), vec3(self.vertices[triangle[2] as usize].position));
+            let face_normal = (b - a).cross(c - a);
+            for &idx in triangle.iter() {
+                normals[idx as usize] = normals[idx as usize] + face_normal;
+            }
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = if normal.length() > 0.0 { normal.normalize().to_array() } else { [0.0, 1.0, 0.0] };
+        }
+    }
+
+    /// Centers the mesh on the origin and scales it so its largest side is `size`, so any model
+    /// shows up at a known size.
+    pub fn fit_to_size(&mut self, size: f32) {
+        let bounds = self.bounds();
+        let (center, extents) = (bounds.center(), bounds.extents());
+        let largest = extents.x.max(extents.y).max(extents.z);
+        let scale = if largest > 0.0 { size / largest } else { 1.0 };
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = ((vec3(vertex.position) - center) * scale).to_array();
+        }
+    }
+
+    /// Uploads to device-local vertex and index buffers.
+    pub fn upload<'d, D: DeviceV1_0>(&self, device: &'d D, allocator: &'d MemoryAllocator<D>, immediate: &ImmediateContext<D>) -> VkResult<IndexedVertexBuffer<'d, D>> {
+        IndexedVertexBuffer::new(device, allocator, immediate, &self.vertices, &self.indices)
+    }
+}
+
+/// Push constant block read by the mesh vertex shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MeshPushConstants {
+    pub model_view_projection: Mat4,
+    pub model: Mat4,
+}
+
+impl MeshPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            offset: 0,
+            size: std::mem::size_of::<MeshPushConstants>() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexFetch {
+    /// Attributes come through the pipeline's vertex input state.
+    FixedFunction,
+    /// The vertex shader reads the vertex buffer as a storage buffer.
+    Pulling,
+}
+
+impl VertexFetch {
+    pub fn toggle(&self) -> VertexFetch {
+        match *self {
+            VertexFetch::FixedFunction => VertexFetch::Pulling,
+            VertexFetch::Pulling => VertexFetch::FixedFunction,
+        }
+    }
+
+    /// Descriptor set layout bindings the vertex stage needs for this fetch mode.
+    pub fn descriptor_set_layout_bindings(&self) -> Vec<DescriptorSetLayoutBinding> {
+        match *self {
+            VertexFetch::FixedFunction => Vec::new(),
+            VertexFetch::Pulling => vec![DescriptorSetLayoutBinding {
+                binding: VERTEX_BUFFER_BINDING,
+                descriptor_type: DescriptorType::StorageBuffer,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_VERTEX_BIT,
+                p_immutable_samplers: ptr::null(),
+            }],
+        }
+    }
+
+    /// Mesh pipeline with depth testing. `vertex_shader` must match the fetch mode:
+    /// `mesh_vertex.glsl` for fixed function, `mesh_pulling_vertex.glsl` for pulling.
+    pub fn pipeline_builder(&self, extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+        let builder = GraphicsPipelineBuilder::new(extent)
+            .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+            .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+            .depth_test(true, CompareOp::Less);
+        match *self {
+            VertexFetch::FixedFunction => builder.vertex_input(&MeshVertex::binding_descriptions(), &MeshVertex::attribute_descriptions()),
+            VertexFetch::Pulling => builder,
+        }
+    }
+}
+
+/// A mesh uploaded to host-visible memory, with the vertices followed by the indices in the same
+/// buffer. The buffer is usable both as a vertex buffer and as a storage buffer, so either
+/// fetch mode can draw it.
+pub struct MeshBuffer<'d, D: DeviceV1_0 + 'd> {
+    buffer: DeviceBuffer<'d, D>,
+    vertex_count: u32,
+    vertex_size: DeviceSize,
+    index_offset: DeviceSize,
+    index_count: u32,
+    index_type: IndexType,
+    fetch: VertexFetch,
+}
+
+impl<'d, D: DeviceV1_0> MeshBuffer<'d, D> {
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, vertices: &[MeshVertex], indices: &[u32]) -> VkResult<MeshBuffer<'d, D>> {
+        MeshBuffer::from_vertices(device, allocator, vertices, indices, false)
+    }
+
+    /// Uploads vertices of any layout; the pipeline's vertex input has to match `V`. With
+    /// `compact_indices`, indices are stored as 16 bits when every vertex is addressable that way.
+    pub fn from_vertices<V: Copy>(device: &'d D, allocator: &'d MemoryAllocator<D>, vertices: &[V], indices: &[u32], compact_indices: bool) -> VkResult<MeshBuffer<'d, D>> {
+        let index_type = if compact_indices && vertices.len() <= (u16::MAX as usize) + 1 { IndexType::Uint16 } else { IndexType::Uint32 };
+        let vertex_size = std::mem::size_of_val(vertices) as DeviceSize;
+        // Index buffer offsets have to be a multiple of the index size
+        let index_offset = (vertex_size + 3) & !3;
+        let index_size = (indices.len() * buffer::index_type_size(index_type)) as DeviceSize;
+        let usage = BUFFER_USAGE_VERTEX_BUFFER_BIT | BUFFER_USAGE_INDEX_BUFFER_BIT | BUFFER_USAGE_STORAGE_BUFFER_BIT;
+        let buffer = DeviceBuffer::host_visible(device, allocator, index_offset + index_size, usage)?;
+        buffer.write(0, vertices)?;
+        if index_type == IndexType::Uint16 {
+            let compact: Vec<u16> = indices.iter().map(|&idx| idx as u16).collect();
+            buffer.write(index_offset, &compact)?;
+        } else {
+            buffer.write(index_offset, indices)?;
+        }
+        Ok(MeshBuffer {
+            buffer: buffer,
+            vertex_count: vertices.len() as u32,
+            vertex_size: vertex_size,
+            index_offset: index_offset,
+            index_count: indices.len() as u32,
+            index_type: index_type,
+            fetch: VertexFetch::FixedFunction,
+        })
+    }
+
+    /// Bytes of vertex and index data, for comparing vertex formats.
+    pub fn size(&self) -> DeviceSize {
+        self.vertex_size + self.index_count as DeviceSize * buffer::index_type_size(self.index_type) as DeviceSize
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    /// Selects how `cmd_draw` will feed vertices. It must match the bound pipeline.
+    pub fn set_fetch(&mut self, fetch: VertexFetch) {
+        self.fetch = fetch;
+    }
+
+    /// The vertices as a storage buffer, for the pulling path's descriptor set.
+    pub fn vertex_storage_write(&self) -> DescriptorWrite {
+        DescriptorWrite::StorageBuffer {
+            binding: VERTEX_BUFFER_BINDING,
+            buffer: self.buffer.buffer(),
+            offset: 0,
+            range: self.vertex_size,
+        }
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drawable for MeshBuffer<'d, D> {
+    /// With vertex pulling, the descriptor set from `vertex_storage_write` must already be bound.
+    unsafe fn cmd_draw<E: DeviceV1_0>(&self, device: &E, command_buffer: CommandBuffer) {
+        if self.fetch == VertexFetch::FixedFunction {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.buffer.buffer()], &[0]);
+        }
+        device.cmd_bind_index_buffer(command_buffer, self.buffer.buffer(), self.index_offset, self.index_type);
+        device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+    }
+}
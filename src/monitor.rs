@@ -0,0 +1,284 @@
+//! Monitors and their video modes, and picking which one to go fullscreen on. From the command
+//! line:
+//!
+//! - `--list-monitors` logs every monitor and its modes.
+//! - `--fullscreen` goes fullscreen in the monitor's current mode, and
+//!   `--fullscreen=<width>x<height>[@<hz>]` in the given one.
+//! - `--monitor=<monitor>` picks the monitor to go fullscreen on, by its index in
+//!   `--list-monitors` or part of its name. The primary monitor is used otherwise.
+use glfw;
+use std::fmt;
+use std::sync::mpsc::Receiver;
+use ::cvar::Flag;
+
+pub const LIST_ARG: &'static str = "--list-monitors";
+pub const FULLSCREEN_ARG: &'static str = "--fullscreen";
+pub const MONITOR_ARG_PREFIX: &'static str = "--monitor=";
+pub const LIST_CVAR: &'static str = "window.list_monitors";
+pub const FULLSCREEN_CVAR: &'static str = "window.fullscreen";
+/// `<width>x<height>[@<hz>]`, which also makes the window fullscreen.
+pub const MODE_CVAR: &'static str = "window.mode";
+pub const MONITOR_CVAR: &'static str = "window.monitor";
+pub const FLAGS: &'static [Flag] = &[
+    Flag::Switch { arg: LIST_ARG, name: LIST_CVAR },
+    Flag::Switch { arg: FULLSCREEN_ARG, name: FULLSCREEN_CVAR },
+    Flag::Value { prefix: "--fullscreen=", name: MODE_CVAR },
+    Flag::Value { prefix: MONITOR_ARG_PREFIX, name: MONITOR_CVAR },
+];
+
+/// What `glfwGetMonitorContentScale` would call a scale of 1.
+const BASE_DPI: f32 = 96.0;
+const MM_PER_INCH: f32 = 25.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: u32,
+    /// Bits of red, green and blue.
+    pub bits: (u32, u32, u32),
+}
+
+impl<'a> From<&'a glfw::VidMode> for VideoMode {
+    fn from(mode: &'a glfw::VidMode) -> VideoMode {
+        VideoMode {
+            width: mode.width,
+            height: mode.height,
+            refresh_rate: mode.refresh_rate,
+            bits: (mode.red_bits, mode.green_bits, mode.blue_bits),
+        }
+    }
+}
+
+impl fmt::Display for VideoMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{} @ {} Hz ({}/{}/{} bits)", self.width, self.height, self.refresh_rate, self.bits.0, self.bits.1, self.bits.2)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    pub name: String,
+    /// Of the top left corner, in screen coordinates.
+    pub position: (i32, i32),
+    /// In millimetres, or zero if the monitor doesn't say.
+    pub physical_size: (i32, i32),
+    pub current_mode: Option<VideoMode>,
+    pub modes: Vec<VideoMode>,
+    pub is_primary: bool,
+}
+
+impl MonitorInfo {
+    fn new(monitor: &glfw::Monitor, is_primary: bool) -> MonitorInfo {
+        MonitorInfo {
+            name: monitor.get_name(),
+            position: monitor.get_pos(),
+            physical_size: monitor.get_physical_size(),
+            current_mode: monitor.get_video_mode().as_ref().map(VideoMode::from),
+            modes: monitor.get_video_modes().iter().map(VideoMode::from).collect(),
+            is_primary: is_primary,
+        }
+    }
+
+    /// How much bigger than 96 DPI the current mode is, horizontally. GLFW 3.2 can't ask the OS
+    /// for its content scale, so this is worked out from the physical size, and `None` if that
+    /// isn't known.
+    pub fn content_scale(&self) -> Option<f32> {
+        match (self.current_mode, self.physical_size.0) {
+            (Some(mode), width_mm) if width_mm > 0 => Some(mode.width as f32 / (width_mm as f32 / MM_PER_INCH) / BASE_DPI),
+            _ => None,
+        }
+    }
+
+    /// The mode closest to `request`: its size exactly, at the requested refresh rate if there
+    /// is one or the highest otherwise.
+    pub fn find_mode(&self, request: &ModeRequest) -> Option<VideoMode> {
+        let matching = self.modes.iter().filter(|mode| mode.width == request.width && mode.height == request.height);
+        match request.refresh_rate {
+            Some(refresh_rate) => matching.filter(|mode| mode.refresh_rate == refresh_rate).max_by_key(|mode| mode.bits),
+            None => matching.max_by_key(|mode| (mode.refresh_rate, mode.bits)),
+        }.cloned()
+    }
+}
+
+impl fmt::Display for MonitorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at {:?}, {}x{} mm", self.name, self.position, self.physical_size.0, self.physical_size.1)?;
+        if let Some(mode) = self.current_mode {
+            write!(f, ", {}", mode)?;
+        }
+        if let Some(scale) = self.content_scale() {
+            write!(f, ", scale {:.2}", scale)?;
+        }
+        if self.is_primary {
+            write!(f, " (primary)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeRequest {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: Option<u32>,
+}
+
+impl ModeRequest {
+    /// Parses `<width>x<height>[@<hz>]`.
+    pub fn parse(text: &str) -> Option<ModeRequest> {
+        let mut parts = text.splitn(2, '@');
+        let (size, refresh_rate) = (parts.next().unwrap(), parts.next());
+        let mut dimensions = size.splitn(2, 'x');
+        match (dimensions.next().unwrap().parse::<u32>(), dimensions.next().map(|height| height.parse::<u32>())) {
+            (Ok(width), Some(Ok(height))) => Some(ModeRequest {
+                width: width,
+                height: height,
+                refresh_rate: match refresh_rate.map(|hz| hz.parse::<u32>()) {
+                    Some(Ok(hz)) => Some(hz),
+                    Some(Err(_)) => return None,
+                    None => None,
+                },
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorSelector {
+    Index(usize),
+    /// Part of the name, ignoring case.
+    Name(String),
+}
+
+impl MonitorSelector {
+    pub fn parse(text: &str) -> MonitorSelector {
+        match text.parse::<usize>() {
+            Ok(index) => MonitorSelector::Index(index),
+            Err(_) => MonitorSelector::Name(text.to_lowercase()),
+        }
+    }
+
+    /// The index of the first of `monitors` this picks.
+    pub fn find(&self, monitors: &[MonitorInfo]) -> Option<usize> {
+        match *self {
+            MonitorSelector::Index(index) if index < monitors.len() => Some(index),
+            MonitorSelector::Index(_) => None,
+            MonitorSelector::Name(ref name) => monitors.iter().position(|monitor| monitor.name.to_lowercase().contains(name.as_str())),
+        }
+    }
+}
+
+/// Where the window goes, from the command line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DisplayConfig {
+    pub list_monitors: bool,
+    pub fullscreen: bool,
+    /// The current mode if `None`.
+    pub mode: Option<ModeRequest>,
+    /// The primary monitor if `None`.
+    pub monitor: Option<MonitorSelector>,
+}
+
+impl DisplayConfig {
+    /// From the `window.` variables that `FLAGS` set.
+    pub fn from_cvars() -> DisplayConfig {
+        let mut ret = DisplayConfig {
+            list_monitors: cvar!(LIST_CVAR, false).get(),
+            fullscreen: cvar!(FULLSCREEN_CVAR, false).get(),
+            mode: None,
+            monitor: None,
+        };
+        let mode: String = cvar!(MODE_CVAR, String::new()).get();
+        if !mode.is_empty() {
+            ret.fullscreen = true;
+            ret.mode = ModeRequest::parse(&mode);
+            if ret.mode.is_none() {
+                warn!("Invalid video mode {:?}, expected <width>x<height>[@<hz>], using the current one", mode);
+            }
+        }
+        let monitor: String = cvar!(MONITOR_CVAR, String::new()).get();
+        if !monitor.is_empty() {
+            ret.monitor = Some(MonitorSelector::parse(&monitor));
+        }
+        ret
+    }
+}
+
+/// Monitor support for the windowing library.
+pub trait MonitorBackend {
+    type Window;
+    type Events;
+
+    /// Every connected monitor, primary first.
+    fn monitors(&mut self) -> Vec<MonitorInfo>;
+
+    /// Creates a window with `title`, `width` by `height` or fullscreen as `config` says.
+    fn create_configured_window(&mut self, config: &DisplayConfig, width: u32, height: u32, title: &str) -> Option<(Self::Window, Self::Events)>;
+}
+
+impl MonitorBackend for glfw::Glfw {
+    type Window = glfw::Window;
+    type Events = Receiver<(f64, glfw::WindowEvent)>;
+
+    fn monitors(&mut self) -> Vec<MonitorInfo> {
+        self.with_connected_monitors(|_, monitors| {
+            monitors.iter().enumerate().map(|(idx, monitor)| MonitorInfo::new(monitor, idx == 0)).collect()
+        })
+    }
+
+    fn create_configured_window(&mut self, config: &DisplayConfig, width: u32, height: u32, title: &str) -> Option<(glfw::Window, Receiver<(f64, glfw::WindowEvent)>)> {
+        if !config.fullscreen {
+            return self.create_window(width, height, title, glfw::WindowMode::Windowed);
+        }
+        let infos = self.monitors();
+        let index = match config.monitor {
+            Some(ref selector) => selector.find(&infos).unwrap_or_else(|| {
+                warn!("No monitor matches {:?}, using the primary one", selector);
+                0
+            }),
+            None => 0,
+        };
+        let info = match infos.get(index) {
+            Some(info) => info,
+            None => {
+                warn!("No monitors connected, opening a window instead");
+                return self.create_window(width, height, title, glfw::WindowMode::Windowed);
+            },
+        };
+        let mode = match config.mode {
+            Some(ref request) => info.find_mode(request).or_else(|| {
+                warn!("{} has no {:?} mode, using the current one", info.name, request);
+                info.current_mode
+            }),
+            None => info.current_mode,
+        };
+        let mode = match mode {
+            Some(mode) => mode,
+            None => {
+                warn!("Couldn't get a video mode for {}, opening a window instead", info.name);
+                return self.create_window(width, height, title, glfw::WindowMode::Windowed);
+            },
+        };
+        info!("Going fullscreen on {} in {}", info, mode);
+        self.window_hint(glfw::WindowHint::RefreshRate(Some(mode.refresh_rate)));
+        self.with_connected_monitors(|glfw, monitors| {
+            glfw.create_window(mode.width, mode.height, title, glfw::WindowMode::FullScreen(&monitors[index]))
+        })
+    }
+}
+
+/// Logs every monitor and its modes.
+pub fn log_monitors<B: MonitorBackend>(backend: &mut B) {
+    let monitors = backend.monitors();
+    if monitors.is_empty() {
+        info!("No monitors connected");
+    }
+    for (idx, monitor) in monitors.iter().enumerate() {
+        info!("Monitor {}: {}", idx, monitor);
+        for mode in monitor.modes.iter() {
+            info!("    {}", mode);
+        }
+    }
+}
@@ -0,0 +1,980 @@
+//! Multisampled scene rendering, enabled with `--msaa=<samples>[:<resolve>]`. The scene renders
+//! into multisampled color and depth images, which are resolved into a single-sampled image in
+//! the surface format (so sRGB surfaces resolve in linear space), and that's copied into the
+//! swapchain image like the offscreen target in `offscreen`. The color resolve is either:
+//!
+//! - `renderpass` (the default): a resolve attachment of the scene's subpass, so on tiled GPUs
+//!   the multisampled image never leaves tile memory.
+//! - `command`: `cmd_resolve_image` after the render pass, which keeps the multisampled image
+//!   around, e.g. to look at in a capture.
+//!
+//! `--msaa-depth-resolve=<sample_zero|average|min|max>` also resolves depth into a
+//! single-sampled image, for trying TAA on top of MSAA. `cmd_resolve_image` can't resolve depth,
+//! so that's always done in the render pass with `VK_KHR_depth_stencil_resolve`, whatever the
+//! color resolve. Its resolve attachments only exist in render passes made with
+//! `VK_KHR_create_renderpass2`, and `ash` knows neither, so the structs are declared here and the
+//! entry point is loaded by hand.
+//!
+//! `sample_locations::ARG_PREFIX` moves the samples to another pattern, with
+//! `VK_EXT_sample_locations`.
+//!
+//! The log console is drawn after the resolve, in a single-sampled render pass compatible with
+//! the scene's, so its pipeline works unchanged.
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, EntryV1_0, InstanceV1_0 };
+use std;
+use std::ffi::CString;
+use std::ptr;
+use vk::types::*;
+use ::command::RenderTarget;
+use ::cvar::Flag;
+use ::depth_buffer;
+use ::format_support;
+use ::offscreen;
+use ::pnext::{ ExtensionStruct, PNextChain };
+use ::rect;
+use ::safe_create::{ self, FramebufferCreateInfoSafe, Owned, OwnedFramebuffer };
+use ::sample_locations::{ self, PhysicalDeviceSampleLocationsPropertiesRaw, RenderPassSampleLocations, SampleLocations, SamplePattern };
+use ::subgroup::{ self, PhysicalDeviceProperties2Raw };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const ARG_PREFIX: &'static str = "--msaa=";
+pub const DEPTH_RESOLVE_ARG_PREFIX: &'static str = "--msaa-depth-resolve=";
+/// `<samples>[:renderpass|command]`, or empty for none.
+pub const CVAR: &'static str = "r.msaa";
+pub const DEPTH_RESOLVE_CVAR: &'static str = "r.msaa.depth_resolve";
+pub const SAMPLE_LOCATIONS_CVAR: &'static str = "r.msaa.sample_locations";
+pub const FLAGS: &'static [Flag] = &[
+    Flag::Value { prefix: ARG_PREFIX, name: CVAR },
+    Flag::Value { prefix: DEPTH_RESOLVE_ARG_PREFIX, name: DEPTH_RESOLVE_CVAR },
+    Flag::Value { prefix: sample_locations::ARG_PREFIX, name: SAMPLE_LOCATIONS_CVAR },
+];
+/// What depth resolves need enabled on the device, dependencies first.
+pub const DEPTH_RESOLVE_EXTENSIONS: [&'static str; 4] = ["VK_KHR_multiview", "VK_KHR_maintenance2", "VK_KHR_create_renderpass2", "VK_KHR_depth_stencil_resolve"];
+
+const STRUCTURE_TYPE_ATTACHMENT_DESCRIPTION_2: u32 = 1000109000;
+const STRUCTURE_TYPE_ATTACHMENT_REFERENCE_2: u32 = 1000109001;
+const STRUCTURE_TYPE_SUBPASS_DESCRIPTION_2: u32 = 1000109002;
+const STRUCTURE_TYPE_SUBPASS_DEPENDENCY_2: u32 = 1000109003;
+const STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO_2: u32 = 1000109004;
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_DEPTH_STENCIL_RESOLVE_PROPERTIES: u32 = 1000199000;
+const STRUCTURE_TYPE_SUBPASS_DESCRIPTION_DEPTH_STENCIL_RESOLVE: u32 = 1000199001;
+
+/// Of the multisampled depth, in the scene's render pass and `MsaaTarget`'s framebuffer for it.
+const DEPTH_ATTACHMENT: u32 = 1;
+
+/// `VK_RESOLVE_MODE_NONE`, the one `VkResolveModeFlagBits` value that isn't a `DepthResolve`.
+const RESOLVE_MODE_NONE: u32 = 0;
+
+#[repr(C)]
+struct AttachmentDescription2Raw {
+    s_type: u32,
+    p_next: *const c_void,
+    flags: AttachmentDescriptionFlags,
+    format: Format,
+    samples: SampleCountFlags,
+    load_op: AttachmentLoadOp,
+    store_op: AttachmentStoreOp,
+    stencil_load_op: AttachmentLoadOp,
+    stencil_store_op: AttachmentStoreOp,
+    initial_layout: ImageLayout,
+    final_layout: ImageLayout,
+}
+
+#[repr(C)]
+struct AttachmentReference2Raw {
+    s_type: u32,
+    p_next: *const c_void,
+    attachment: u32,
+    layout: ImageLayout,
+    aspect_mask: ImageAspectFlags,
+}
+
+#[repr(C)]
+struct SubpassDescription2Raw {
+    s_type: u32,
+    p_next: *const c_void,
+    flags: SubpassDescriptionFlags,
+    pipeline_bind_point: PipelineBindPoint,
+    view_mask: u32,
+    input_attachment_count: u32,
+    p_input_attachments: *const AttachmentReference2Raw,
+    color_attachment_count: u32,
+    p_color_attachments: *const AttachmentReference2Raw,
+    p_resolve_attachments: *const AttachmentReference2Raw,
+    p_depth_stencil_attachment: *const AttachmentReference2Raw,
+    preserve_attachment_count: u32,
+    p_preserve_attachments: *const u32,
+}
+
+#[repr(C)]
+struct SubpassDependency2Raw {
+    s_type: u32,
+    p_next: *const c_void,
+    src_subpass: u32,
+    dst_subpass: u32,
+    src_stage_mask: PipelineStageFlags,
+    dst_stage_mask: PipelineStageFlags,
+    src_access_mask: AccessFlags,
+    dst_access_mask: AccessFlags,
+    dependency_flags: DependencyFlags,
+    view_offset: i32,
+}
+
+#[repr(C)]
+struct RenderPassCreateInfo2Raw {
+    s_type: u32,
+    p_next: *const c_void,
+    flags: RenderPassCreateFlags,
+    attachment_count: u32,
+    p_attachments: *const AttachmentDescription2Raw,
+    subpass_count: u32,
+    p_subpasses: *const SubpassDescription2Raw,
+    dependency_count: u32,
+    p_dependencies: *const SubpassDependency2Raw,
+    correlated_view_mask_count: u32,
+    p_correlated_view_masks: *const u32,
+}
+
+#[repr(C)]
+struct SubpassDescriptionDepthStencilResolveRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    depth_resolve_mode: u32,
+    stencil_resolve_mode: u32,
+    p_depth_stencil_resolve_attachment: *const AttachmentReference2Raw,
+}
+
+unsafe impl ExtensionStruct for SubpassDescriptionDepthStencilResolveRaw {}
+
+#[repr(C)]
+struct PhysicalDeviceDepthStencilResolvePropertiesRaw {
+    s_type: u32,
+    p_next: *mut c_void,
+    supported_depth_resolve_modes: u32,
+    supported_stencil_resolve_modes: u32,
+    independent_resolve_none: Bool32,
+    independent_resolve: Bool32,
+}
+
+unsafe impl ExtensionStruct for PhysicalDeviceDepthStencilResolvePropertiesRaw {}
+
+type CreateRenderPass2Fn = unsafe extern "system" fn(Device, *const RenderPassCreateInfo2Raw, *const AllocationCallbacks, *mut RenderPass) -> Result;
+
+/// Where the multisampled color gets resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveMode {
+    /// A resolve attachment at the end of the scene's subpass.
+    RenderPass,
+    /// `cmd_resolve_image` after the scene's render pass.
+    Command,
+}
+
+impl ResolveMode {
+    fn parse(text: &str) -> Option<ResolveMode> {
+        match text {
+            "renderpass" => Some(ResolveMode::RenderPass),
+            "command" => Some(ResolveMode::Command),
+            _ => None,
+        }
+    }
+}
+
+/// How the samples of each depth pixel are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthResolve {
+    SampleZero,
+    Average,
+    Min,
+    Max,
+}
+
+impl DepthResolve {
+    fn parse(text: &str) -> Option<DepthResolve> {
+        match text {
+            "sample_zero" => Some(DepthResolve::SampleZero),
+            "average" => Some(DepthResolve::Average),
+            "min" => Some(DepthResolve::Min),
+            "max" => Some(DepthResolve::Max),
+            _ => None,
+        }
+    }
+
+    /// Its `VkResolveModeFlagBits`.
+    fn bit(&self) -> u32 {
+        match *self {
+            DepthResolve::SampleZero => 0x1,
+            DepthResolve::Average => 0x2,
+            DepthResolve::Min => 0x4,
+            DepthResolve::Max => 0x8,
+        }
+    }
+}
+
+/// MSAA as asked for on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsaaConfig {
+    /// A power of two, more than 1.
+    pub samples: u32,
+    pub resolve: ResolveMode,
+    pub depth_resolve: Option<DepthResolve>,
+    pub sample_locations: Option<SamplePattern>,
+}
+
+impl MsaaConfig {
+    /// `None` unless `r.msaa`, which `--msaa` sets, asks for more than one sample.
+    pub fn from_cvars() -> Option<MsaaConfig> {
+        let text: String = cvar!(CVAR, String::new()).get();
+        let ret = if text.is_empty() {
+            None
+        } else {
+            let mut parts = text.splitn(2, ':');
+            let samples = parts.next().unwrap().parse::<u32>();
+            let resolve = parts.next().map(ResolveMode::parse);
+            match (samples, resolve) {
+                (Ok(samples), _) if samples <= 1 => None,
+                (Ok(samples), None) if samples.is_power_of_two() => Some((samples, ResolveMode::RenderPass)),
+                (Ok(samples), Some(Some(resolve))) if samples.is_power_of_two() => Some((samples, resolve)),
+                _ => {
+                    warn!("Invalid MSAA {:?}, expected {}<samples>[:renderpass|command] with a power of two sample count", text, ARG_PREFIX);
+                    None
+                },
+            }
+        };
+        let text: String = cvar!(DEPTH_RESOLVE_CVAR, String::new()).get();
+        let depth_resolve = if text.is_empty() { None } else { DepthResolve::parse(&text) };
+        if !text.is_empty() && depth_resolve.is_none() {
+            warn!("Invalid depth resolve {:?}, expected {}<sample_zero|average|min|max>", text, DEPTH_RESOLVE_ARG_PREFIX);
+        }
+        let text: String = cvar!(SAMPLE_LOCATIONS_CVAR, String::new()).get();
+        let sample_locations = if text.is_empty() { None } else { SamplePattern::parse(&text) };
+        if !text.is_empty() && sample_locations.is_none() {
+            warn!("Invalid sample locations {:?}, expected {}<ordered|center>", text, sample_locations::ARG_PREFIX);
+        }
+        if ret.is_none() && depth_resolve.is_some() {
+            warn!("{} does nothing without {}<samples>", DEPTH_RESOLVE_ARG_PREFIX, ARG_PREFIX);
+        }
+        if ret.is_none() && sample_locations.is_some() {
+            warn!("{} does nothing without {}<samples>", sample_locations::ARG_PREFIX, ARG_PREFIX);
+        }
+        ret.map(|(samples, resolve)| MsaaConfig {
+            samples: samples,
+            resolve: resolve,
+            depth_resolve: depth_resolve,
+            sample_locations: sample_locations,
+        })
+    }
+
+    /// What `physical_device` can do of this: the most samples up to the ones asked for that both
+    /// color and depth attachments support, and the depth resolve if the device has
+    /// `DEPTH_RESOLVE_EXTENSIONS` among its `extensions` and supports the mode. `None` if it can't
+    /// multisample at all.
+    pub fn resolve<E: EntryV1_0, I: InstanceV1_0>(&self, entry: &E, instance: &I, physical_device: PhysicalDevice, depth_format: Format, extensions: &[String]) -> Option<MsaaSettings> {
+        let limits = instance.get_physical_device_properties(physical_device).limits;
+        let supported = (limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts).flags();
+        let mut samples = self.samples;
+        while samples > 1 && supported & samples == 0 {
+            samples /= 2;
+        }
+        if samples <= 1 {
+            warn!("The device can't multisample color and depth attachments, rendering without MSAA");
+            return None;
+        }
+        if samples != self.samples {
+            warn!("The device can't do {}x MSAA, using {}x", self.samples, samples);
+        }
+        let depth_resolve = self.depth_resolve.and_then(|mode| {
+            let missing: Vec<&str> = DEPTH_RESOLVE_EXTENSIONS.iter().cloned().filter(|&name| !extensions.iter().any(|extension| extension == name)).collect();
+            if !missing.is_empty() {
+                warn!("Depth resolves need {:?}, which the device doesn't have, so depth won't be resolved", missing);
+                return None;
+            }
+            Some(DepthResolveModes::choose(mode, &query_depth_stencil_resolve(entry, instance, physical_device), depth_format))
+        });
+        let sample_locations = self.sample_locations.and_then(|pattern| {
+            if !extensions.iter().any(|extension| extension == sample_locations::EXTENSION_NAME) {
+                warn!("Custom sample locations need {}, which the device doesn't have, so the standard ones are used", sample_locations::EXTENSION_NAME);
+                return None;
+            }
+            match query_sample_locations(entry, instance, physical_device) {
+                Some(ref properties) if properties.sample_location_sample_counts.flags() & samples != 0 => Some(pattern),
+                _ => {
+                    warn!("The device can't move the samples of {}x MSAA, so the standard locations are used", samples);
+                    None
+                },
+            }
+        });
+        let ret = MsaaSettings {
+            samples: SampleCountFlags::from_flags_truncate(samples),
+            resolve: self.resolve,
+            depth_resolve: depth_resolve,
+            sample_locations: sample_locations,
+        };
+        info!("Using {}x MSAA, resolving color with {:?} and depth with {:?}", samples, ret.resolve, ret.depth_resolve);
+        if let Some(locations) = ret.locations() {
+            info!("Sampling at {:?} ({:?})", locations.positions(), sample_locations);
+        }
+        Some(ret)
+    }
+}
+
+/// The depth resolve modes the device supports, or just sample zero (which every device with the
+/// extension does) when they can't be queried.
+fn query_depth_stencil_resolve<E: EntryV1_0, I: InstanceV1_0>(entry: &E, instance: &I, physical_device: PhysicalDevice) -> PhysicalDeviceDepthStencilResolvePropertiesRaw {
+    let mut resolve = PhysicalDeviceDepthStencilResolvePropertiesRaw {
+        s_type: STRUCTURE_TYPE_PHYSICAL_DEVICE_DEPTH_STENCIL_RESOLVE_PROPERTIES,
+        p_next: ptr::null_mut(),
+        supported_depth_resolve_modes: DepthResolve::SampleZero.bit(),
+        supported_stencil_resolve_modes: DepthResolve::SampleZero.bit(),
+        independent_resolve_none: 0,
+        independent_resolve: 0,
+    };
+    match subgroup::load_get_physical_device_properties2(entry, instance, physical_device) {
+        Some(get_physical_device_properties2) => {
+            let mut next = PNextChain::new().push(&mut resolve);
+            let mut properties = PhysicalDeviceProperties2Raw {
+                s_type: subgroup::STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2,
+                p_next: next.head_mut(),
+                properties: instance.get_physical_device_properties(physical_device),
+            };
+            unsafe { get_physical_device_properties2(physical_device, &mut properties) };
+        },
+        None => info!("Can't query the supported depth resolve modes, assuming only sample zero"),
+    }
+    resolve
+}
+
+/// The sample counts that can have custom locations, or `None` if they can't be queried.
+fn query_sample_locations<E: EntryV1_0, I: InstanceV1_0>(entry: &E, instance: &I, physical_device: PhysicalDevice) -> Option<PhysicalDeviceSampleLocationsPropertiesRaw> {
+    let get_physical_device_properties2 = subgroup::load_get_physical_device_properties2(entry, instance, physical_device)?;
+    let mut properties = PhysicalDeviceSampleLocationsPropertiesRaw {
+        s_type: sample_locations::STRUCTURE_TYPE_PHYSICAL_DEVICE_SAMPLE_LOCATIONS_PROPERTIES,
+        p_next: ptr::null_mut(),
+        sample_location_sample_counts: SampleCountFlags::empty(),
+        max_sample_location_grid_size: rect::extent(0, 0),
+        sample_location_coordinate_range: [0.0, 0.0],
+        sample_location_sub_pixel_bits: 0,
+        variable_sample_locations: 0,
+    };
+    {
+        let mut next = PNextChain::new().push(&mut properties);
+        let mut properties2 = PhysicalDeviceProperties2Raw {
+            s_type: subgroup::STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2,
+            p_next: next.head_mut(),
+            properties: instance.get_physical_device_properties(physical_device),
+        };
+        unsafe { get_physical_device_properties2(physical_device, &mut properties2) };
+    }
+    Some(properties)
+}
+
+/// The `VkResolveModeFlagBits` of the depth and stencil resolves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthResolveModes {
+    pub depth: u32,
+    /// `RESOLVE_MODE_NONE` for formats without stencil.
+    pub stencil: u32,
+}
+
+impl DepthResolveModes {
+    /// `mode` if it's supported and sample zero otherwise, with a stencil mode the device allows
+    /// next to it.
+    fn choose(mode: DepthResolve, properties: &PhysicalDeviceDepthStencilResolvePropertiesRaw, depth_format: Format) -> DepthResolveModes {
+        let mut depth = mode.bit();
+        if properties.supported_depth_resolve_modes & depth == 0 {
+            warn!("The device can't resolve depth with {:?}, using {:?}", mode, DepthResolve::SampleZero);
+            depth = DepthResolve::SampleZero.bit();
+        }
+        if !format_support::has_stencil(depth_format) {
+            return DepthResolveModes {
+                depth: depth,
+                stencil: RESOLVE_MODE_NONE,
+            };
+        }
+        // Without independent resolves, depth and stencil have to match, or one of them be none
+        // if that's allowed
+        let stencil = if properties.independent_resolve != 0 {
+            DepthResolve::SampleZero.bit()
+        } else if properties.independent_resolve_none != 0 {
+            RESOLVE_MODE_NONE
+        } else if properties.supported_stencil_resolve_modes & depth != 0 {
+            depth
+        } else {
+            warn!("The device can't resolve stencil like depth with {:?}, resolving both with {:?}", mode, DepthResolve::SampleZero);
+            depth = DepthResolve::SampleZero.bit();
+            depth
+        };
+        DepthResolveModes {
+            depth: depth,
+            stencil: stencil,
+        }
+    }
+}
+
+/// The `MsaaConfig` the device can actually do, from `MsaaConfig::resolve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsaaSettings {
+    pub samples: SampleCountFlags,
+    pub resolve: ResolveMode,
+    /// `None` if depth isn't resolved.
+    pub depth_resolve: Option<DepthResolveModes>,
+    /// `None` for the device's standard locations.
+    pub sample_locations: Option<SamplePattern>,
+}
+
+impl MsaaSettings {
+    /// Where the samples are moved to, if they are.
+    pub fn locations(&self) -> Option<SampleLocations> {
+        self.sample_locations.map(|pattern| SampleLocations::new(pattern, self.samples.flags()))
+    }
+}
+
+/// The attachments of the scene's multisampled render pass and of `MsaaTarget`'s framebuffer for
+/// it, in order: multisampled color and depth, then the single-sampled color resolve with
+/// `ResolveMode::RenderPass`, then the depth resolve if there is one.
+fn attachment_descriptions(settings: &MsaaSettings, format: Format, depth_format: Format) -> Vec<AttachmentDescription> {
+    let mut ret = vec![
+        AttachmentDescription {
+            flags: Default::default(),
+            format: format,
+            samples: settings.samples,
+            load_op: AttachmentLoadOp::Clear,
+            store_op: match settings.resolve {
+                ResolveMode::RenderPass => AttachmentStoreOp::DontCare,
+                ResolveMode::Command => AttachmentStoreOp::Store,
+            },
+            stencil_load_op: AttachmentLoadOp::DontCare,
+            stencil_store_op: AttachmentStoreOp::DontCare,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: match settings.resolve {
+                ResolveMode::RenderPass => ImageLayout::ColorAttachmentOptimal,
+                ResolveMode::Command => ImageLayout::TransferSrcOptimal,
+            },
+        },
+        AttachmentDescription {
+            samples: settings.samples,
+            ..depth_buffer::attachment_description(depth_format)
+        },
+    ];
+    if settings.resolve == ResolveMode::RenderPass {
+        ret.push(AttachmentDescription {
+            flags: Default::default(),
+            format: format,
+            samples: SAMPLE_COUNT_1_BIT,
+            load_op: AttachmentLoadOp::DontCare,
+            store_op: AttachmentStoreOp::Store,
+            stencil_load_op: AttachmentLoadOp::DontCare,
+            stencil_store_op: AttachmentStoreOp::DontCare,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: ImageLayout::ColorAttachmentOptimal,
+        });
+    }
+    if let Some(modes) = settings.depth_resolve {
+        ret.push(AttachmentDescription {
+            flags: Default::default(),
+            format: depth_format,
+            samples: SAMPLE_COUNT_1_BIT,
+            load_op: AttachmentLoadOp::DontCare,
+            store_op: AttachmentStoreOp::Store,
+            stencil_load_op: AttachmentLoadOp::DontCare,
+            stencil_store_op: if modes.stencil == RESOLVE_MODE_NONE { AttachmentStoreOp::DontCare } else { AttachmentStoreOp::Store },
+            initial_layout: ImageLayout::Undefined,
+            final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+        });
+    }
+    ret
+}
+
+fn dependencies() -> [SubpassDependency; 2] {
+    [
+        // The last frame's copy out of the resolve image, and its resolve out of the multisampled
+        // one, have to finish before they're overwritten, and so do its depth writes
+        SubpassDependency {
+            src_subpass: VK_SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT | PIPELINE_STAGE_TRANSFER_BIT,
+            src_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+            dst_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+            dst_access_mask: ACCESS_COLOR_ATTACHMENT_READ_BIT | ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+            dependency_flags: Default::default(),
+        },
+        // Resolves are written as color (or depth) attachment writes, read by the overlay pass or
+        // by `cmd_resolve_image`
+        SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: VK_SUBPASS_EXTERNAL,
+            src_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+            src_access_mask: ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+            dst_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT | PIPELINE_STAGE_TRANSFER_BIT,
+            dst_access_mask: ACCESS_COLOR_ATTACHMENT_READ_BIT | ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT | ACCESS_TRANSFER_READ_BIT,
+            dependency_flags: Default::default(),
+        },
+    ]
+}
+
+/// Creates the scene's multisampled render pass, with the attachments in `attachment_descriptions`.
+/// A depth resolve needs `vkCreateRenderPass2KHR`, and so `DEPTH_RESOLVE_EXTENSIONS` enabled on
+/// `device`.
+pub fn create_render_pass<'d, I: InstanceV1_0, D: DeviceV1_0>(instance: &I, device: &'d D, settings: &MsaaSettings, format: Format, depth_format: Format) -> VkResult<Owned<'d, D, RenderPass>> {
+    let attachments = attachment_descriptions(settings, format, depth_format);
+    let color_attachment_ref = AttachmentReference {
+        attachment: 0,
+        layout: ImageLayout::ColorAttachmentOptimal,
+    };
+    let depth_attachment_ref = AttachmentReference {
+        attachment: 1,
+        layout: ImageLayout::DepthStencilAttachmentOptimal,
+    };
+    let resolve_attachment_ref = AttachmentReference {
+        attachment: 2,
+        layout: ImageLayout::ColorAttachmentOptimal,
+    };
+    let resolve_attachment = if settings.resolve == ResolveMode::RenderPass { Some(&resolve_attachment_ref) } else { None };
+    let dependencies = dependencies();
+    let modes = match settings.depth_resolve {
+        Some(modes) => modes,
+        None => {
+            let subpass = SubpassDescription {
+                flags: Default::default(),
+                pipeline_bind_point: PipelineBindPoint::Graphics,
+                input_attachment_count: 0,
+                p_input_attachments: ptr::null(),
+                color_attachment_count: 1,
+                p_color_attachments: &color_attachment_ref,
+                p_resolve_attachments: resolve_attachment.map(|r| r as *const AttachmentReference).unwrap_or(ptr::null()),
+                p_depth_stencil_attachment: &depth_attachment_ref,
+                preserve_attachment_count: 0,
+                p_preserve_attachments: ptr::null(),
+            };
+            let create_info = RenderPassCreateInfo {
+                s_type: StructureType::RenderPassCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                attachment_count: attachments.len() as u32,
+                p_attachments: attachments.as_ptr(),
+                subpass_count: 1,
+                p_subpasses: &subpass,
+                dependency_count: dependencies.len() as u32,
+                p_dependencies: dependencies.as_ptr(),
+            };
+            return safe_create::create_render_pass_safe(device, &create_info, None);
+        },
+    };
+
+    let name = CString::new("vkCreateRenderPass2KHR").unwrap();
+    let pfn = instance.get_device_proc_addr(device.handle(), name.as_ptr());
+    if pfn as usize == 0 {
+        return Err(Result::ErrorExtensionNotPresent);
+    }
+    let create_render_pass2 = unsafe { std::mem::transmute::<PFN_vkVoidFunction, CreateRenderPass2Fn>(pfn) };
+    let attachments2: Vec<AttachmentDescription2Raw> = attachments.iter().map(attachment_description2).collect();
+    let depth_aspect = depth_buffer::aspect_mask(depth_format);
+    let color_attachment_ref2 = attachment_reference2(&color_attachment_ref, IMAGE_ASPECT_COLOR_BIT);
+    let depth_attachment_ref2 = attachment_reference2(&depth_attachment_ref, depth_aspect);
+    let resolve_attachment_ref2 = resolve_attachment.map(|r| attachment_reference2(r, IMAGE_ASPECT_COLOR_BIT));
+    let depth_resolve_attachment_ref2 = attachment_reference2(&AttachmentReference {
+        attachment: attachments.len() as u32 - 1,
+        layout: ImageLayout::DepthStencilAttachmentOptimal,
+    }, depth_aspect);
+    let mut depth_stencil_resolve = SubpassDescriptionDepthStencilResolveRaw {
+        s_type: STRUCTURE_TYPE_SUBPASS_DESCRIPTION_DEPTH_STENCIL_RESOLVE,
+        p_next: ptr::null(),
+        depth_resolve_mode: modes.depth,
+        stencil_resolve_mode: modes.stencil,
+        p_depth_stencil_resolve_attachment: &depth_resolve_attachment_ref2,
+    };
+    let subpass_next = PNextChain::new().push(&mut depth_stencil_resolve);
+    let subpass = SubpassDescription2Raw {
+        s_type: STRUCTURE_TYPE_SUBPASS_DESCRIPTION_2,
+        p_next: subpass_next.head(),
+        flags: Default::default(),
+        pipeline_bind_point: PipelineBindPoint::Graphics,
+        view_mask: 0,
+        input_attachment_count: 0,
+        p_input_attachments: ptr::null(),
+        color_attachment_count: 1,
+        p_color_attachments: &color_attachment_ref2,
+        p_resolve_attachments: resolve_attachment_ref2.as_ref().map(|r| r as *const AttachmentReference2Raw).unwrap_or(ptr::null()),
+        p_depth_stencil_attachment: &depth_attachment_ref2,
+        preserve_attachment_count: 0,
+        p_preserve_attachments: ptr::null(),
+    };
+    let dependencies2: Vec<SubpassDependency2Raw> = dependencies.iter().map(subpass_dependency2).collect();
+    let create_info = RenderPassCreateInfo2Raw {
+        s_type: STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO_2,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        attachment_count: attachments2.len() as u32,
+        p_attachments: attachments2.as_ptr(),
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        dependency_count: dependencies2.len() as u32,
+        p_dependencies: dependencies2.as_ptr(),
+        correlated_view_mask_count: 0,
+        p_correlated_view_masks: ptr::null(),
+    };
+    let mut render_pass = RenderPass::null();
+    let err_code = unsafe { create_render_pass2(device.handle(), &create_info, ptr::null(), &mut render_pass) };
+    match err_code {
+        Result::Success => Ok(unsafe { safe_create::take_render_pass_ownership(device, None, render_pass) }),
+        _ => Err(err_code),
+    }
+}
+
+fn attachment_description2(description: &AttachmentDescription) -> AttachmentDescription2Raw {
+    AttachmentDescription2Raw {
+        s_type: STRUCTURE_TYPE_ATTACHMENT_DESCRIPTION_2,
+        p_next: ptr::null(),
+        flags: description.flags,
+        format: description.format,
+        samples: description.samples,
+        load_op: description.load_op,
+        store_op: description.store_op,
+        stencil_load_op: description.stencil_load_op,
+        stencil_store_op: description.stencil_store_op,
+        initial_layout: description.initial_layout,
+        final_layout: description.final_layout,
+    }
+}
+
+fn attachment_reference2(reference: &AttachmentReference, aspect_mask: ImageAspectFlags) -> AttachmentReference2Raw {
+    AttachmentReference2Raw {
+        s_type: STRUCTURE_TYPE_ATTACHMENT_REFERENCE_2,
+        p_next: ptr::null(),
+        attachment: reference.attachment,
+        layout: reference.layout,
+        aspect_mask: aspect_mask,
+    }
+}
+
+fn subpass_dependency2(dependency: &SubpassDependency) -> SubpassDependency2Raw {
+    SubpassDependency2Raw {
+        s_type: STRUCTURE_TYPE_SUBPASS_DEPENDENCY_2,
+        p_next: ptr::null(),
+        src_subpass: dependency.src_subpass,
+        dst_subpass: dependency.dst_subpass,
+        src_stage_mask: dependency.src_stage_mask,
+        dst_stage_mask: dependency.dst_stage_mask,
+        src_access_mask: dependency.src_access_mask,
+        dst_access_mask: dependency.dst_access_mask,
+        dependency_flags: dependency.dependency_flags,
+        view_offset: 0,
+    }
+}
+
+/// An image with its memory and a view of the whole thing.
+struct AttachmentImage<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+}
+
+impl<'d, D: DeviceV1_0> AttachmentImage<'d, D> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, samples: SampleCountFlags, usage: ImageUsageFlags, aspect_mask: ImageAspectFlags, flags: ImageCreateFlags, extent: &Extent2D) -> VkResult<AttachmentImage<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: flags,
+            image_type: ImageType::Type2d,
+            format: format,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: samples,
+            tiling: ImageTiling::Optimal,
+            usage: usage,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+        // Destroying/freeing null handles is a no-op, so from here on out `Drop` will clean up
+        // after us if anything goes wrong.
+        let mut ret = AttachmentImage {
+            device: device,
+            image: image,
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+        };
+        ret.memory = allocator.allocate_image_memory(image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: image,
+            view_type: ImageViewType::Type2d,
+            format: format,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: subresource_range(aspect_mask),
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+        Ok(ret)
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for AttachmentImage<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+/// The images the scene renders into with MSAA, a framebuffer of them for the scene's
+/// multisampled render pass, and the single-sampled overlay pass drawn after resolving.
+pub struct MsaaTarget<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    settings: MsaaSettings,
+    /// The scene's multisampled render pass, owned by whoever created it.
+    render_pass: RenderPass,
+    // Ahead of the attachments, so they're dropped first
+    framebuffer: OwnedFramebuffer<'d, D>,
+    overlay_render_pass: Owned<'d, D, RenderPass>,
+    overlay_framebuffer: OwnedFramebuffer<'d, D>,
+    color: AttachmentImage<'d, D>,
+    _depth: AttachmentImage<'d, D>,
+    /// The resolved color, which the overlay pass draws on and gets copied to the swapchain.
+    resolve: AttachmentImage<'d, D>,
+    /// The resolved depth if there's a depth resolve, and otherwise just the overlay pass's depth
+    /// buffer.
+    _resolved_depth: AttachmentImage<'d, D>,
+    extent: Extent2D,
+    /// `None` for the device's standard sample locations.
+    locations: Option<SampleLocations>,
+}
+
+impl<'d, D: DeviceV1_0> MsaaTarget<'d, D> {
+    /// `render_pass` is the scene's multisampled one from `create_render_pass` with the same
+    /// `settings`, `format` and `depth_format`. The overlay pass is compatible with single-sampled
+    /// `format` and `depth_format` render passes like the scene's.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, settings: &MsaaSettings, render_pass: RenderPass, format: Format, depth_format: Format, extent: Extent2D) -> VkResult<MsaaTarget<'d, D>> {
+        let depth_aspect = depth_buffer::aspect_mask(depth_format);
+        let color_usage = match settings.resolve {
+            ResolveMode::RenderPass => IMAGE_USAGE_COLOR_ATTACHMENT_BIT | IMAGE_USAGE_TRANSIENT_ATTACHMENT_BIT,
+            ResolveMode::Command => IMAGE_USAGE_COLOR_ATTACHMENT_BIT | IMAGE_USAGE_TRANSFER_SRC_BIT,
+        };
+        let locations = settings.locations();
+        let depth_flags = if locations.is_some() { sample_locations::depth_image_flags() } else { Default::default() };
+        let color = AttachmentImage::new(device, allocator, format, settings.samples, color_usage, IMAGE_ASPECT_COLOR_BIT, Default::default(), &extent)?;
+        let depth = AttachmentImage::new(device, allocator, depth_format, settings.samples, IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT | IMAGE_USAGE_TRANSIENT_ATTACHMENT_BIT, depth_aspect, depth_flags, &extent)?;
+        let resolve = AttachmentImage::new(device, allocator, format, SAMPLE_COUNT_1_BIT, IMAGE_USAGE_COLOR_ATTACHMENT_BIT | IMAGE_USAGE_TRANSFER_SRC_BIT | IMAGE_USAGE_TRANSFER_DST_BIT, IMAGE_ASPECT_COLOR_BIT, Default::default(), &extent)?;
+        let resolved_depth = AttachmentImage::new(device, allocator, depth_format, SAMPLE_COUNT_1_BIT, IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT, depth_aspect, Default::default(), &extent)?;
+
+        let mut attachments = vec![color.view, depth.view];
+        if settings.resolve == ResolveMode::RenderPass {
+            attachments.push(resolve.view);
+        }
+        if settings.depth_resolve.is_some() {
+            attachments.push(resolved_depth.view);
+        }
+        let framebuffer = create_framebuffer(device, render_pass, &attachments, &extent)?;
+
+        let overlay_attachments = [
+            AttachmentDescription {
+                flags: Default::default(),
+                format: format,
+                samples: SAMPLE_COUNT_1_BIT,
+                load_op: AttachmentLoadOp::Load,
+                store_op: AttachmentStoreOp::Store,
+                stencil_load_op: AttachmentLoadOp::DontCare,
+                stencil_store_op: AttachmentStoreOp::DontCare,
+                initial_layout: match settings.resolve {
+                    ResolveMode::RenderPass => ImageLayout::ColorAttachmentOptimal,
+                    ResolveMode::Command => ImageLayout::TransferDstOptimal,
+                },
+                final_layout: ImageLayout::TransferSrcOptimal,
+            },
+            // Kept as it is when it's the resolved depth
+            if settings.depth_resolve.is_some() {
+                AttachmentDescription {
+                    load_op: AttachmentLoadOp::Load,
+                    store_op: AttachmentStoreOp::Store,
+                    initial_layout: ImageLayout::DepthStencilAttachmentOptimal,
+                    ..depth_buffer::attachment_description(depth_format)
+                }
+            } else {
+                depth_buffer::attachment_description(depth_format)
+            },
+        ];
+        let color_attachment_ref = AttachmentReference {
+            attachment: 0,
+            layout: ImageLayout::ColorAttachmentOptimal,
+        };
+        let depth_attachment_ref = AttachmentReference {
+            attachment: 1,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+        };
+        let subpass = SubpassDescription {
+            flags: Default::default(),
+            pipeline_bind_point: PipelineBindPoint::Graphics,
+            input_attachment_count: 0,
+            p_input_attachments: ptr::null(),
+            color_attachment_count: 1,
+            p_color_attachments: &color_attachment_ref,
+            p_resolve_attachments: ptr::null(),
+            p_depth_stencil_attachment: &depth_attachment_ref,
+            preserve_attachment_count: 0,
+            p_preserve_attachments: ptr::null(),
+        };
+        let overlay_dependencies = [
+            // After the resolve, whichever way it was done
+            SubpassDependency {
+                src_subpass: VK_SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT | PIPELINE_STAGE_TRANSFER_BIT,
+                src_access_mask: ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT | ACCESS_TRANSFER_WRITE_BIT,
+                dst_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+                dst_access_mask: ACCESS_COLOR_ATTACHMENT_READ_BIT | ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dependency_flags: Default::default(),
+            },
+            SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: VK_SUBPASS_EXTERNAL,
+                src_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+                src_access_mask: ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+                dst_stage_mask: PIPELINE_STAGE_TRANSFER_BIT,
+                dst_access_mask: ACCESS_TRANSFER_READ_BIT,
+                dependency_flags: Default::default(),
+            },
+        ];
+        let render_pass_create_info = RenderPassCreateInfo {
+            s_type: StructureType::RenderPassCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            attachment_count: overlay_attachments.len() as u32,
+            p_attachments: overlay_attachments.as_ptr(),
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: overlay_dependencies.len() as u32,
+            p_dependencies: overlay_dependencies.as_ptr(),
+        };
+        let overlay_render_pass = safe_create::create_render_pass_safe(device, &render_pass_create_info, None)?;
+        let overlay_framebuffer = create_framebuffer(device, *overlay_render_pass, &[resolve.view, resolved_depth.view], &extent)?;
+        debug!("Created {}x{} {:?} MSAA target with {:?}: {:?}", extent.width, extent.height, format, settings, color.image);
+        Ok(MsaaTarget {
+            device: device,
+            settings: *settings,
+            render_pass: render_pass,
+            framebuffer: framebuffer,
+            overlay_render_pass: overlay_render_pass,
+            overlay_framebuffer: overlay_framebuffer,
+            color: color,
+            _depth: depth,
+            resolve: resolve,
+            _resolved_depth: resolved_depth,
+            extent: extent,
+            locations: locations,
+        })
+    }
+
+    /// For beginning `target`'s render pass with `CommandRecorder::begin_render_pass_with`, when
+    /// the samples are moved.
+    pub fn render_pass_sample_locations<'a>(&'a self) -> Option<RenderPassSampleLocations<'a>> {
+        self.locations.as_ref().map(|locations| locations.render_pass_begin_info(DEPTH_ATTACHMENT))
+    }
+
+    /// The multisampled images, to draw the scene into.
+    pub fn target<'a>(&'a self) -> RenderTarget<'a> {
+        RenderTarget {
+            render_pass: &self.render_pass,
+            framebuffer: &self.framebuffer,
+            extent: self.extent.clone(),
+        }
+    }
+
+    /// The resolved color, to draw overlays on after `cmd_resolve`.
+    pub fn overlay_target<'a>(&'a self) -> RenderTarget<'a> {
+        RenderTarget {
+            render_pass: &self.overlay_render_pass,
+            framebuffer: &self.overlay_framebuffer,
+            extent: self.extent.clone(),
+        }
+    }
+
+    /// Records resolving the multisampled color into the single-sampled image with
+    /// `ResolveMode::Command`, after the scene's render pass. Nothing with `ResolveMode::RenderPass`,
+    /// since the render pass already did.
+    pub unsafe fn cmd_resolve(&self, command_buffer: CommandBuffer) {
+        if self.settings.resolve != ResolveMode::Command {
+            return;
+        }
+        // The last frame's copy to the swapchain might still be reading it
+        let to_transfer_dst = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: Default::default(),
+            dst_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::TransferDstOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.resolve.image,
+            subresource_range: subresource_range(IMAGE_ASPECT_COLOR_BIT),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[to_transfer_dst]);
+        let layers = ImageSubresourceLayers {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let region = ImageResolve {
+            src_subresource: layers.clone(),
+            src_offset: Offset3D { x: 0, y: 0, z: 0 },
+            dst_subresource: layers,
+            dst_offset: Offset3D { x: 0, y: 0, z: 0 },
+            extent: Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            },
+        };
+        self.device.cmd_resolve_image(command_buffer, self.color.image, ImageLayout::TransferSrcOptimal, self.resolve.image, ImageLayout::TransferDstOptimal, &[region]);
+    }
+
+    /// Records copying the resolved image into `swapchain_image` after the overlay pass, as
+    /// `OffscreenTarget::cmd_copy_to` does.
+    pub unsafe fn cmd_copy_to(&self, command_buffer: CommandBuffer, swapchain_image: Image) {
+        offscreen::cmd_copy_to_swapchain(self.device, command_buffer, self.resolve.image, &self.extent, swapchain_image);
+    }
+}
+
+/// A framebuffer of `attachments` for `render_pass`, which the caller has to keep alive for as
+/// long as it.
+fn create_framebuffer<'d, D: DeviceV1_0>(device: &'d D, render_pass: RenderPass, attachments: &[ImageView], extent: &Extent2D) -> VkResult<OwnedFramebuffer<'d, D>> {
+    let create_info = FramebufferCreateInfo {
+        s_type: StructureType::FramebufferCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        render_pass: RenderPass::null(),
+        attachment_count: 0,
+        p_attachments: ptr::null(),
+        width: extent.width,
+        height: extent.height,
+        layers: 1,
+    };
+    safe_create::create_framebuffer_safe(device, unsafe { FramebufferCreateInfoSafe::from_handles(create_info, render_pass, attachments) }, None)
+}
+
+fn subresource_range(aspect_mask: ImageAspectFlags) -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: aspect_mask,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
@@ -0,0 +1,438 @@
+//! Catches NaN, infinite and out-of-range pixels in the HDR target before tone mapping hides
+//! them. `shaders/nan_check_fragment.glsl` marks them over the final image (magenta for NaN and
+//! infinity, translucent cyan for negative or implausibly bright values), and
+//! `shaders/nan_count.glsl` counts them into a buffer that's copied back through a `Readback`
+//! and logged, so a broken shader shows up in the log even when the bad pixels are too few to
+//! spot.
+//!
+//! With `--nan-check`, a `NanCheckPass` checks the output filter's target, which the final image
+//! is drawn into while the check is on, and marks it before the guides and console go over it.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::{ ComputeKernel, StorageBuffer };
+use ::cvar::Flag;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::offscreen::OffscreenTarget;
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::queue::QueueFamilyIndex;
+use ::readback::Readback;
+use ::rect::{ self, ExtentExt };
+use ::vk_mem::MemoryAllocator;
+
+pub const ARG: &'static str = "--nan-check";
+pub const CVAR: &'static str = "r.nan_check";
+pub const FLAGS: &'static [Flag] = &[Flag::Switch { arg: ARG, name: CVAR }];
+pub const COUNT_SHADER: &'static str = "shaders/nan_count.comp.spv";
+pub const OVERLAY_SHADER: &'static str = "shaders/nan_check_fragment.frag.spv";
+
+/// Binding of the HDR target in both the count and the overlay descriptor sets.
+pub const HDR_BINDING: u32 = 0;
+const COUNTS_BINDING: u32 = 1;
+
+/// Invocations per axis of a `shaders/nan_count.glsl` workgroup.
+const LOCAL_SIZE: u32 = 8;
+
+/// Push constant block read by `shaders/nan_count.glsl` and `shaders/nan_check_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NanCheckPushConstants {
+    /// Brightest value a pixel can plausibly have.
+    pub max_value: f32,
+}
+
+impl NanCheckPushConstants {
+    pub fn range(stage_flags: ShaderStageFlags) -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: stage_flags,
+            offset: 0,
+            size: std::mem::size_of::<NanCheckPushConstants>() as u32,
+        }
+    }
+}
+
+/// Bad pixels in one check. Each pixel counts once, towards its first problem in this order.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NanCounts {
+    pub nan: u32,
+    pub infinite: u32,
+    pub negative: u32,
+    pub over_range: u32,
+}
+
+impl NanCounts {
+    pub fn total(&self) -> u32 {
+        self.nan + self.infinite + self.negative + self.over_range
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.total() == 0
+    }
+}
+
+pub struct NanCheck<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    counts: StorageBuffer<'d, D>,
+    readback: Readback<'d, D>,
+    in_flight: bool,
+    last: NanCounts,
+    pub max_value: f32,
+}
+
+impl<'d, D: DeviceV1_0> NanCheck<'d, D> {
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>) -> VkResult<NanCheck<'d, D>> {
+        let size = std::mem::size_of::<NanCounts>() as DeviceSize;
+        Ok(NanCheck {
+            device: device,
+            counts: StorageBuffer::new(device, allocator, size)?,
+            readback: Readback::new(device, allocator, size)?,
+            in_flight: false,
+            last: Default::default(),
+            max_value: 65504.0,
+        })
+    }
+
+    pub fn push_constants(&self) -> NanCheckPushConstants {
+        NanCheckPushConstants {
+            max_value: self.max_value,
+        }
+    }
+
+    /// Descriptor set layout bindings for the count kernel.
+    pub fn count_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![
+            DescriptorSetLayoutBinding {
+                binding: HDR_BINDING,
+                descriptor_type: DescriptorType::CombinedImageSampler,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+            DescriptorSetLayoutBinding {
+                binding: COUNTS_BINDING,
+                descriptor_type: DescriptorType::StorageBuffer,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+        ]
+    }
+
+    /// Descriptor set layout bindings for the overlay.
+    pub fn overlay_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: HDR_BINDING,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    /// The count buffer, for the count kernel's descriptor set alongside the HDR target.
+    pub fn counts_write(&self) -> DescriptorWrite {
+        self.counts.descriptor_write(COUNTS_BINDING)
+    }
+
+    /// Whether a check has been submitted and not read back yet. Don't record another until
+    /// it's been polled.
+    pub fn is_in_flight(&self) -> bool {
+        self.in_flight
+    }
+
+    /// Records counting the bad pixels of the `extent` HDR target with `kernel` (built from
+    /// `COUNT_SHADER` and `count_bindings`) and `descriptor_set` (holding the target, readable
+    /// in `ShaderReadOnlyOptimal`, and `counts_write`), followed by the copy back. Must be
+    /// outside a render pass, and submitted through `submit`.
+    pub unsafe fn cmd_count(&self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>, descriptor_set: DescriptorSet, extent: &Extent2D) {
+        let size = self.counts.size();
+        self.device.cmd_fill_buffer(command_buffer, self.counts.buffer(), 0, size, 0);
+        let cleared = BufferMemoryBarrier {
+            s_type: StructureType::BufferMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT | ACCESS_SHADER_WRITE_BIT,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            buffer: self.counts.buffer(),
+            offset: 0,
+            size: size,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[cleared], &[]);
+        let groups = extent.div_ceil(LOCAL_SIZE);
+        kernel.cmd_dispatch(command_buffer, descriptor_set, &self.push_constants(), (groups.width, groups.height, 1));
+        self.readback.cmd_copy_buffer(command_buffer, self.counts.buffer(), 0, size, PIPELINE_STAGE_COMPUTE_SHADER_BIT, ACCESS_SHADER_WRITE_BIT);
+    }
+
+    /// Submits `command_buffer`, which must contain `cmd_count`.
+    pub fn submit(&mut self, queue: Queue, command_buffer: CommandBuffer) -> VkResult<()> {
+        assert!(!self.in_flight, "NaN check submitted again before its counts were polled");
+        self.readback.submit(queue, command_buffer)?;
+        self.in_flight = true;
+        Ok(())
+    }
+
+    /// Reads back the submitted check if the GPU is done with it, logging any bad pixels.
+    /// `None` while it's still running, or when nothing was submitted.
+    pub fn poll(&mut self) -> VkResult<Option<NanCounts>> {
+        if !self.in_flight || !self.readback.is_ready() {
+            return Ok(None);
+        }
+        let counts = (self.readback.read::<NanCounts>(1))?[0];
+        self.in_flight = false;
+        if !counts.is_clean() && counts != self.last {
+            warn!("HDR target has bad pixels: {} NaN, {} infinite, {} negative, {} over {}", counts.nan, counts.infinite, counts.negative, counts.over_range, self.max_value);
+        } else if counts.is_clean() && !self.last.is_clean() {
+            info!("HDR target is clean again");
+        }
+        self.last = counts;
+        Ok(Some(counts))
+    }
+
+    /// Records the marker overlay, with the overlay pipeline and a descriptor set holding the HDR
+    /// target already bound.
+    pub unsafe fn cmd_draw_overlay(&self, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let constants = self.push_constants();
+        let range = NanCheckPushConstants::range(SHADER_STAGE_FRAGMENT_BIT);
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const NanCheckPushConstants as *const c_void);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// A `NanCheck` of one target, with its own command buffer for the count and the marker
+/// overlay's pipeline. Isn't sized to the target, so it's kept across swapchain recreation and
+/// pointed at the new target with `set_source`.
+pub struct NanCheckPass<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    check: NanCheck<'d, D>,
+    kernel: ComputeKernel<'d, D>,
+    command_pool: CommandPool,
+    /// The one the count is recorded into.
+    command_buffers: Vec<CommandBuffer>,
+    sampler: Sampler,
+    overlay_set_layout: DescriptorSetLayout,
+    overlay_layout: PipelineLayout,
+    overlay_pipeline: Pipeline,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    count_set: DescriptorSet,
+    overlay_set: DescriptorSet,
+    /// Null until `set_source`.
+    source: Image,
+    extent: Extent2D,
+}
+
+impl<'d, D: DeviceV1_0> NanCheckPass<'d, D> {
+    /// Counts on the graphics queue, from `queue_family_index`, and draws the overlay into
+    /// subpass 0 of `render_pass`, with a dynamic viewport. `count_spirv` is `COUNT_SHADER`'s,
+    /// `vertex_shader` must be `shaders/fullscreen_vertex.glsl` and `fragment_shader`
+    /// `OVERLAY_SHADER`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, queue_family_index: QueueFamilyIndex, count_spirv: &[u8], render_pass: RenderPass, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> VkResult<NanCheckPass<'d, D>> {
+        // The target in both sets, and the counts
+        let pool_sizes = [
+            DescriptorPoolSize {
+                typ: DescriptorType::CombinedImageSampler,
+                descriptor_count: 2,
+            },
+            DescriptorPoolSize {
+                typ: DescriptorType::StorageBuffer,
+                descriptor_count: 1,
+            },
+        ];
+        let check = NanCheck::new(device, allocator)?;
+        let kernel = ComputeKernel::new(device, count_spirv, &NanCheck::<D>::count_bindings(), std::mem::size_of::<NanCheckPushConstants>() as u32)?;
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = NanCheckPass {
+            device: device,
+            check: check,
+            kernel: kernel,
+            command_pool: CommandPool::null(),
+            command_buffers: Vec::new(),
+            sampler: Sampler::null(),
+            overlay_set_layout: DescriptorSetLayout::null(),
+            overlay_layout: PipelineLayout::null(),
+            overlay_pipeline: Pipeline::null(),
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, 2),
+            count_set: DescriptorSet::null(),
+            overlay_set: DescriptorSet::null(),
+            source: Image::null(),
+            extent: rect::placeholder_extent(),
+        };
+
+        // Re-recorded for every check, once the last one has been polled
+        let pool_create_info = CommandPoolCreateInfo {
+            s_type: StructureType::CommandPoolCreateInfo,
+            p_next: ptr::null(),
+            flags: COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT,
+            queue_family_index: queue_family_index.raw(),
+        };
+        ret.command_pool = unsafe { device.create_command_pool(&pool_create_info, None) }?;
+        ret.command_buffers = unsafe {
+            device.allocate_command_buffers(&CommandBufferAllocateInfo {
+                s_type: StructureType::CommandBufferAllocateInfo,
+                p_next: ptr::null(),
+                command_pool: ret.command_pool,
+                level: CommandBufferLevel::Primary,
+                command_buffer_count: 1,
+            })
+        }?;
+
+        // Both shaders fetch texels
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+
+        let bindings = NanCheck::<D>::overlay_bindings();
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        ret.overlay_set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        let push_constant_range = NanCheckPushConstants::range(SHADER_STAGE_FRAGMENT_BIT);
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 1,
+            p_set_layouts: &ret.overlay_set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.overlay_layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+        ret.overlay_pipeline = unsafe { overlay_pipeline_builder(vertex_shader, fragment_shader).build_raw(device, PipelineCache::null(), ret.overlay_layout, render_pass, 0) }?;
+
+        ret.count_set = ret.descriptor_allocator.allocate(ret.kernel.set_layout())?;
+        ret.overlay_set = ret.descriptor_allocator.allocate(ret.overlay_set_layout)?;
+        Ok(ret)
+    }
+
+    /// Checks `target` from here on, which is read in `ShaderReadOnlyOptimal` after a render pass
+    /// draws it. Neither descriptor set may be in use, so the device has to be idle.
+    pub fn set_source(&mut self, target: &OffscreenTarget<'d, D>) {
+        let hdr_write = DescriptorWrite::CombinedImageSampler {
+            binding: HDR_BINDING,
+            sampler: self.sampler,
+            image_view: target.view(),
+            image_layout: ImageLayout::ShaderReadOnlyOptimal,
+        };
+        descriptor::update_descriptor_set(self.device, self.count_set, &[hdr_write.clone(), self.check.counts_write()]);
+        descriptor::update_descriptor_set(self.device, self.overlay_set, &[hdr_write]);
+        self.source = target.image();
+        self.extent = target.extent().clone();
+    }
+
+    /// Reads back the last check if it's done, see `NanCheck::poll`.
+    pub fn poll(&mut self) -> VkResult<Option<NanCounts>> {
+        self.check.poll()
+    }
+
+    /// Counts the source's bad pixels as it was left by the last submitted frame, unless the last
+    /// check is still running. Submitted to `queue` right after that frame, whose render pass
+    /// writes are made visible to the count.
+    pub fn count(&mut self, queue: Queue) -> VkResult<()> {
+        if self.check.is_in_flight() {
+            return Ok(());
+        }
+        debug_assert!(self.source != Image::null(), "NaN check counted without a source");
+        let begin_info = CommandBufferBeginInfo {
+            s_type: StructureType::CommandBufferBeginInfo,
+            p_next: ptr::null(),
+            flags: COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT,
+            p_inheritance_info: ptr::null(),
+        };
+        let subresource_range = ImageSubresourceRange {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let to_compute = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::ShaderReadOnlyOptimal,
+            new_layout: ImageLayout::ShaderReadOnlyOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.source,
+            subresource_range: subresource_range,
+        };
+        let command_buffer = self.command_buffers[0];
+        unsafe {
+            self.device.begin_command_buffer(command_buffer, &begin_info)?;
+            self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[to_compute]);
+            self.check.cmd_count(command_buffer, &self.kernel, self.count_set, &self.extent);
+            // The next frame's render pass overwrites the source, which mustn't start before the
+            // count has read it
+            self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT, Default::default(), &[], &[], &[]);
+            self.device.end_command_buffer(command_buffer)?;
+        }
+        self.check.submit(queue, command_buffer)
+    }
+
+    /// Records the markers over the whole of `extent` inside the render pass, for the source
+    /// drawn over all of it.
+    pub unsafe fn cmd_draw_overlay(&self, command_buffer: CommandBuffer, extent: &Extent2D) {
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, self.overlay_pipeline);
+        self.device.cmd_set_viewport(command_buffer, &[extent.to_viewport()]);
+        self.device.cmd_set_scissor(command_buffer, &[extent.to_rect()]);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, self.overlay_layout, 0, &[self.overlay_set], &[]);
+        self.check.cmd_draw_overlay(command_buffer, self.overlay_layout);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for NanCheckPass<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying NaN check pass: {:?}", self.overlay_pipeline);
+            self.device.destroy_pipeline(self.overlay_pipeline, None);
+            self.device.destroy_pipeline_layout(self.overlay_layout, None);
+            self.device.destroy_descriptor_set_layout(self.overlay_set_layout, None);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
+
+/// Whether `r.nan_check`, which `ARG` sets, is on.
+pub fn requested() -> bool {
+    cvar!(CVAR, false).get()
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl`, blended over the final image.
+/// The fragment shader outputs premultiplied alpha.
+pub fn overlay_pipeline_builder(vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    // The extent only matters for the static viewport, which the dynamic one replaces
+    GraphicsPipelineBuilder::new(&rect::placeholder_extent())
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .blend(BlendPreset::Premultiplied)
+        .dynamic_viewport_scissor()
+}
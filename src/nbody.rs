@@ -0,0 +1,244 @@
+//! N-body gravity, as a compute throughput benchmark. Every body attracts every other, so a step
+//! is `count²` interactions; `shaders/nbody.glsl` loads positions a workgroup-sized tile at a time
+//! into shared memory so each is fetched from the buffer once per workgroup instead of once per
+//! invocation. Positions and velocities are double buffered: a step reads one pair and writes the
+//! other, and the bodies are drawn as point sprites straight from the latest positions.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use std::time::Duration;
+use vk::types::*;
+use ::compute::{ self, ComputeKernel, StorageBuffer };
+use ::descriptor::{ self, DescriptorAllocator };
+use ::math::Mat4;
+use ::math::prelude::div_ceil;
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::time::Time;
+use ::vk_mem::MemoryAllocator;
+
+pub const STEP_SHADER: &'static str = "shaders/nbody.comp.spv";
+pub const VERTEX_SHADER: &'static str = "shaders/nbody_vertex.vert.spv";
+pub const FRAGMENT_SHADER: &'static str = "shaders/nbody_fragment.frag.spv";
+
+/// Invocations per workgroup, and bodies per shared memory tile.
+const LOCAL_SIZE: u32 = 256;
+
+/// Simulated seconds per step at a time scale of 1. Fixed, since the integration is only stable
+/// for small steps.
+const BASE_DT: f32 = 0.002;
+
+const POSITIONS_IN_BINDING: u32 = 0;
+const VELOCITIES_IN_BINDING: u32 = 1;
+const POSITIONS_OUT_BINDING: u32 = 2;
+const VELOCITIES_OUT_BINDING: u32 = 3;
+/// Binding of the positions in the draw's descriptor set.
+pub const DRAW_POSITIONS_BINDING: u32 = 0;
+
+/// Push constant block read by `shaders/nbody.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NBodyPushConstants {
+    pub count: u32,
+    pub dt: f32,
+    /// Added to squared distances, so close encounters don't fling bodies away.
+    pub softening: f32,
+    pub gravity: f32,
+}
+
+/// Push constant block read by `shaders/nbody_vertex.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NBodyDrawPushConstants {
+    pub view_projection: Mat4,
+    /// Sizes other than 1 need the `largePoints` device feature.
+    pub point_size: f32,
+}
+
+impl NBodyDrawPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            offset: 0,
+            size: std::mem::size_of::<NBodyDrawPushConstants>() as u32,
+        }
+    }
+}
+
+/// Small xorshift generator, so the initial conditions are reproducible between runs. The seed
+/// mustn't be 0.
+pub struct XorShift(pub u32);
+
+impl XorShift {
+    /// The next value, in `[0, 1)`.
+    pub fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// A flat disk of bodies in roughly circular orbits around the origin. Positions are
+/// `(x, y, z, mass)` and velocities `(x, y, z, 0)`.
+fn disk_galaxy(count: u32, radius: f32, gravity: f32) -> (Vec<[f32; 4]>, Vec<[f32; 4]>) {
+    let mut rng = XorShift(0x9e3779b9);
+    let mass = 1.0 / count as f32;
+    let mut positions = Vec::with_capacity(count as usize);
+    let mut velocities = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let r = radius * rng.next().sqrt().max(0.05);
+        let theta = rng.next() * 2.0 * std::f32::consts::PI;
+        let y = (rng.next() - 0.5) * 0.05 * radius;
+        // Orbital speed for the mass enclosed within r, assuming a uniform disk
+        let enclosed = r * r / (radius * radius);
+        let speed = (gravity * enclosed / r).sqrt();
+        positions.push([r * theta.cos(), y, r * theta.sin(), mass]);
+        velocities.push([-speed * theta.sin(), 0.0, speed * theta.cos(), 0.0]);
+    }
+    (positions, velocities)
+}
+
+pub struct NBody<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    _positions: [StorageBuffer<'d, D>; 2],
+    _velocities: [StorageBuffer<'d, D>; 2],
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+    /// `step_sets[i]` reads buffers `i` and writes the others.
+    step_sets: [DescriptorSet; 2],
+    /// `draw_sets[i]` reads `positions[i]`.
+    draw_sets: [DescriptorSet; 2],
+    current: usize,
+    pub params: NBodyPushConstants,
+}
+
+impl<'d, D: DeviceV1_0> NBody<'d, D> {
+    /// Creates `count` bodies in a disk. `kernel` must be built from `STEP_SHADER` and
+    /// `compute_bindings`, and `draw_set_layout` from `draw_bindings`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, kernel: &ComputeKernel<D>, draw_set_layout: DescriptorSetLayout, count: u32) -> VkResult<NBody<'d, D>> {
+        let params = NBodyPushConstants {
+            count: count,
+            dt: BASE_DT,
+            softening: 0.01,
+            gravity: 1.0,
+        };
+        let (initial_positions, initial_velocities) = disk_galaxy(count, 1.0, params.gravity);
+        let positions = [
+            StorageBuffer::with_data(device, allocator, &initial_positions)?,
+            StorageBuffer::with_data(device, allocator, &initial_positions)?,
+        ];
+        let velocities = [
+            StorageBuffer::with_data(device, allocator, &initial_velocities)?,
+            StorageBuffer::with_data(device, allocator, &initial_velocities)?,
+        ];
+
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: 10,
+        }];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, 4);
+        let mut step_sets = [DescriptorSet::null(); 2];
+        let mut draw_sets = [DescriptorSet::null(); 2];
+        for i in 0..2 {
+            let (src, dst) = (i, 1 - i);
+            step_sets[i] = descriptor_allocator.allocate(kernel.set_layout())?;
+            descriptor::update_descriptor_set(device, step_sets[i], &[
+                positions[src].descriptor_write(POSITIONS_IN_BINDING),
+                velocities[src].descriptor_write(VELOCITIES_IN_BINDING),
+                positions[dst].descriptor_write(POSITIONS_OUT_BINDING),
+                velocities[dst].descriptor_write(VELOCITIES_OUT_BINDING),
+            ]);
+            draw_sets[i] = descriptor_allocator.allocate(draw_set_layout)?;
+            descriptor::update_descriptor_set(device, draw_sets[i], &[positions[i].descriptor_write(DRAW_POSITIONS_BINDING)]);
+        }
+        debug!("Created N-body simulation with {} bodies", count);
+        Ok(NBody {
+            device: device,
+            _positions: positions,
+            _velocities: velocities,
+            _descriptor_allocator: descriptor_allocator,
+            step_sets: step_sets,
+            draw_sets: draw_sets,
+            current: 0,
+            params: params,
+        })
+    }
+
+    pub fn compute_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        compute::storage_buffer_bindings(4)
+    }
+
+    pub fn draw_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: DRAW_POSITIONS_BINDING,
+            descriptor_type: DescriptorType::StorageBuffer,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    /// Body interactions evaluated per step.
+    pub fn interactions_per_step(&self) -> u64 {
+        self.params.count as u64 * self.params.count as u64
+    }
+
+    /// Logs the throughput of `steps` steps that took `elapsed` on the GPU.
+    pub fn log_throughput(&self, steps: u32, elapsed: Duration) {
+        let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        if seconds <= 0.0 {
+            return;
+        }
+        let interactions = self.interactions_per_step() as f64 * steps as f64 / seconds;
+        // 20 flops per interaction is the customary count for this kernel
+        info!("N-body: {} bodies, {:.2} billion interactions/s ({:.1} GFLOP/s)", self.params.count, interactions * 1e-9, interactions * 20.0 * 1e-9);
+    }
+
+    /// Scales the step size with `time`'s scale, and stops the simulation while it's paused.
+    pub fn follow_time(&mut self, time: &Time) {
+        self.params.dt = if time.is_paused() { 0.0 } else { BASE_DT * time.scale() };
+    }
+
+    /// Records one simulation step, swapping the buffers, and a barrier for the draw's vertex
+    /// shader reads. Must be outside a render pass.
+    pub unsafe fn cmd_step(&mut self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>) {
+        // The step overwrites the buffers the previous draw read
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_VERTEX_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[]);
+        let group_count = div_ceil(self.params.count, LOCAL_SIZE);
+        kernel.cmd_dispatch(command_buffer, self.step_sets[self.current], &self.params, (group_count, 1, 1));
+        self.current = 1 - self.current;
+        let to_vertex = MemoryBarrier {
+            s_type: StructureType::MemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_VERTEX_SHADER_BIT | PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[to_vertex], &[], &[]);
+    }
+
+    /// The draw descriptor set for the latest positions.
+    pub fn draw_descriptor_set(&self) -> DescriptorSet {
+        self.draw_sets[self.current]
+    }
+
+    /// Records the point sprite draw, with the N-body pipeline and `draw_descriptor_set` bound.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view_projection: &Mat4, point_size: f32) {
+        let constants = NBodyDrawPushConstants {
+            view_projection: *view_projection,
+            point_size: point_size,
+        };
+        let range = NBodyDrawPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const NBodyDrawPushConstants as *const c_void);
+        self.device.cmd_draw(command_buffer, self.params.count, 1, 0, 0);
+    }
+}
+
+/// Additive point sprites from `shaders/nbody_vertex.glsl`, so dense regions glow.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .topology(PrimitiveTopology::PointList)
+        .cull_mode(CULL_MODE_NONE)
+        .blend(BlendPreset::Additive)
+}
@@ -0,0 +1,517 @@
+//! Noise textures for terrain, dithering, SSAO kernels and particles. Value, Perlin and simplex
+//! noise can be generated on the CPU or by `shaders/noise.glsl` into a `NoiseTexture`, and both
+//! hash their lattice the same way so they produce the same pattern. Value and Perlin noise tile
+//! at the texture's edges; simplex noise's skewed lattice doesn't line up with them, so it
+//! doesn't.
+//!
+//! Blue noise is only generated on the CPU, with void and cluster, since every point it places
+//! depends on all the points before it. It's slow enough that 64x64 or 128x128 is the usual
+//! size, tiled across the screen.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::ComputeKernel;
+use ::descriptor::DescriptorWrite;
+use ::math::prelude::{ clamp, lerp };
+use ::pipeline::GraphicsPipelineBuilder;
+use ::rect::ExtentExt;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const NOISE_SHADER: &'static str = "shaders/noise.comp.spv";
+pub const VIEW_FRAGMENT_SHADER: &'static str = "shaders/noise_view_fragment.frag.spv";
+
+/// Binding of the texture in the compute descriptor set.
+pub const NOISE_BINDING: u32 = 0;
+/// Binding of the texture in `shaders/noise_view_fragment.glsl`'s descriptor set.
+pub const VIEW_BINDING: u32 = 0;
+
+/// Invocations per axis of a `shaders/noise.glsl` workgroup.
+const LOCAL_SIZE: u32 = 8;
+
+const NOISE_FORMAT: Format = Format::R32Sfloat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseKind {
+    Value,
+    Perlin,
+    Simplex,
+    /// CPU only.
+    Blue,
+}
+
+impl NoiseKind {
+    pub fn next(&self) -> NoiseKind {
+        match *self {
+            NoiseKind::Value => NoiseKind::Perlin,
+            NoiseKind::Perlin => NoiseKind::Simplex,
+            NoiseKind::Simplex => NoiseKind::Blue,
+            NoiseKind::Blue => NoiseKind::Value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseParams {
+    pub kind: NoiseKind,
+    /// Lattice cells across the texture in the first octave. Each octave doubles it.
+    pub frequency: u32,
+    pub octaves: u32,
+    /// Amplitude of each octave relative to the one before.
+    pub gain: f32,
+    pub seed: u32,
+}
+
+impl Default for NoiseParams {
+    fn default() -> NoiseParams {
+        NoiseParams {
+            kind: NoiseKind::Perlin,
+            frequency: 8,
+            octaves: 4,
+            gain: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+fn hash(x: u32, y: u32, seed: u32) -> u32 {
+    let mut h = seed ^ x.wrapping_mul(0x27d4eb2d) ^ y.wrapping_mul(0x165667b1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b3c6d);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297a2d39);
+    h ^= h >> 15;
+    h
+}
+
+fn wrap(i: i32, period: i32) -> u32 {
+    (((i % period) + period) % period) as u32
+}
+
+fn gradient(h: u32) -> (f32, f32) {
+    let angle = (h & 7) as f32 * std::f32::consts::FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn value_noise(x: f32, y: f32, period: i32, seed: u32) -> f32 {
+    let (ix, iy) = (x.floor() as i32, y.floor() as i32);
+    let (u, v) = (fade(x - ix as f32), fade(y - iy as f32));
+    let corner = |dx: i32, dy: i32| hash(wrap(ix + dx, period), wrap(iy + dy, period), seed) as f32;
+    lerp(lerp(corner(0, 0), corner(1, 0), u), lerp(corner(0, 1), corner(1, 1), u), v) / u32::MAX as f32 - 0.5
+}
+
+fn perlin_noise(x: f32, y: f32, period: i32, seed: u32) -> f32 {
+    let (ix, iy) = (x.floor() as i32, y.floor() as i32);
+    let (fx, fy) = (x - ix as f32, y - iy as f32);
+    let corner = |dx: i32, dy: i32| {
+        let (gx, gy) = gradient(hash(wrap(ix + dx, period), wrap(iy + dy, period), seed));
+        gx * (fx - dx as f32) + gy * (fy - dy as f32)
+    };
+    let (u, v) = (fade(fx), fade(fy));
+    lerp(lerp(corner(0, 0), corner(1, 0), u), lerp(corner(0, 1), corner(1, 1), u), v) * std::f32::consts::FRAC_1_SQRT_2
+}
+
+fn simplex_noise(x: f32, y: f32, seed: u32) -> f32 {
+    const F2: f32 = 0.36602542;
+    const G2: f32 = 0.21132487;
+    let s = (x + y) * F2;
+    let (i, j) = ((x + s).floor() as i32, (y + s).floor() as i32);
+    let t = (i + j) as f32 * G2;
+    let (x0, y0) = (x - (i as f32 - t), y - (j as f32 - t));
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+        (x0, y0, i, j),
+        (x0 - i1 as f32 + G2, y0 - j1 as f32 + G2, i + i1, j + j1),
+        (x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2, i + 1, j + 1),
+    ];
+    let n: f32 = corners.iter().map(|&(cx, cy, ci, cj)| {
+        let t = (0.5 - cx * cx - cy * cy).max(0.0);
+        let (gx, gy) = gradient(hash(ci as u32, cj as u32, seed));
+        t * t * t * t * (gx * cx + gy * cy)
+    }).sum();
+    n * 35.0
+}
+
+/// Fractal noise at `(u, v)` in `[0, 1)` texture space, in `[0, 1]`. Blue noise can't be
+/// sampled pointwise, so it gives 0.5.
+pub fn sample(params: &NoiseParams, u: f32, v: f32) -> f32 {
+    let (mut sum, mut total, mut amplitude) = (0.0, 0.0, 1.0);
+    let mut period = params.frequency as i32;
+    for octave in 0..std::cmp::max(params.octaves, 1) {
+        let seed = params.seed.wrapping_add(octave.wrapping_mul(0x9e3779b9));
+        let (x, y) = (u * period as f32, v * period as f32);
+        let n = match params.kind {
+            NoiseKind::Value => value_noise(x, y, period, seed),
+            NoiseKind::Perlin => perlin_noise(x, y, period, seed),
+            NoiseKind::Simplex => simplex_noise(x, y, seed),
+            NoiseKind::Blue => 0.0,
+        };
+        sum += n * amplitude;
+        total += amplitude;
+        amplitude *= params.gain;
+        period *= 2;
+    }
+    clamp(sum / total + 0.5, 0.0, 1.0)
+}
+
+/// A `width` by `height` texture of noise in `[0, 1]`, row by row.
+pub fn generate(params: &NoiseParams, width: u32, height: u32) -> Vec<f32> {
+    if params.kind == NoiseKind::Blue {
+        return blue_noise(width, height, params.seed);
+    }
+    let mut ret = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            ret.push(sample(params, (x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32));
+        }
+    }
+    ret
+}
+
+/// Standard deviation, in pixels, of the Gaussian that void and cluster measures clustering with.
+const BLUE_NOISE_SIGMA: f32 = 1.5;
+
+/// Blue noise by void and cluster: each pixel's value is its rank in an ordering where every
+/// prefix of points is as evenly spread as possible, so thresholding at any level gives an even
+/// pattern with no low frequencies. Tiles seamlessly.
+pub fn blue_noise(width: u32, height: u32, seed: u32) -> Vec<f32> {
+    let (w, h) = (width as usize, height as usize);
+    let n = w * h;
+    if n == 0 {
+        return Vec::new();
+    }
+    // Gaussian weights by toroidal offset, so the pattern tiles
+    let mut kernel = vec![0.0f32; n];
+    for dy in 0..h {
+        for dx in 0..w {
+            let (x, y) = (std::cmp::min(dx, w - dx) as f32, std::cmp::min(dy, h - dy) as f32);
+            kernel[dy * w + dx] = (-(x * x + y * y) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp();
+        }
+    }
+    let splat = |energy: &mut [f32], p: usize, sign: f32| {
+        let (px, py) = (p % w, p / w);
+        for y in 0..h {
+            let ky = (y + h - py) % h;
+            for x in 0..w {
+                energy[y * w + x] += sign * kernel[ky * w + (x + w - px) % w];
+            }
+        }
+    };
+    // The tightest cluster among the set pixels, or the largest void among the unset ones
+    let extreme = |energy: &[f32], set: &[bool], want_set: bool| {
+        let mut best = None;
+        for i in (0..n).filter(|&i| set[i] == want_set) {
+            best = match best {
+                Some(b) if (want_set && energy[b] >= energy[i]) || (!want_set && energy[b] <= energy[i]) => Some(b),
+                _ => Some(i),
+            };
+        }
+        best.unwrap()
+    };
+
+    // Initial random pattern of about a tenth of the pixels, relaxed until the tightest cluster
+    // is also the largest void
+    let initial = std::cmp::max(n / 10, 1);
+    let mut set = vec![false; n];
+    let mut energy = vec![0.0f32; n];
+    let mut placed = 0;
+    let mut counter = 0;
+    while placed < initial {
+        let p = hash(counter, 0, seed) as usize % n;
+        counter += 1;
+        if !set[p] {
+            set[p] = true;
+            splat(&mut energy, p, 1.0);
+            placed += 1;
+        }
+    }
+    if initial < n {
+        loop {
+            let cluster = extreme(&energy, &set, true);
+            set[cluster] = false;
+            splat(&mut energy, cluster, -1.0);
+            let void = extreme(&energy, &set, false);
+            set[void] = true;
+            splat(&mut energy, void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+    }
+
+    let mut rank = vec![0usize; n];
+    // Ranks below the initial pattern: remove its tightest clusters first
+    let (mut ranked_set, mut ranked_energy) = (set.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = extreme(&ranked_energy, &ranked_set, true);
+        ranked_set[cluster] = false;
+        splat(&mut ranked_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    // Ranks above it: fill the largest voids
+    for r in initial..n {
+        let void = extreme(&energy, &set, false);
+        set[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+    debug!("Generated {}x{} blue noise", width, height);
+    rank.iter().map(|&r| (r as f32 + 0.5) / n as f32).collect()
+}
+
+/// Packs noise values as texel data in `format`, replicating them across the channels of color
+/// formats. `None` for formats this doesn't know how to write.
+pub fn encode(values: &[f32], format: Format) -> Option<Vec<u8>> {
+    let unorm8 = |v: f32| (clamp(v, 0.0, 1.0) * 255.0).round() as u8;
+    let unorm16 = |v: f32| (clamp(v, 0.0, 1.0) * 65535.0).round() as u16;
+    let mut ret = Vec::new();
+    match format {
+        Format::R8Unorm => ret.extend(values.iter().map(|&v| unorm8(v))),
+        Format::R8g8b8a8Unorm => for &v in values.iter() {
+            let b = unorm8(v);
+            ret.extend_from_slice(&[b, b, b, 255]);
+        },
+        Format::R16Unorm => for &v in values.iter() {
+            let b = unorm16(v);
+            ret.extend_from_slice(&[b as u8, (b >> 8) as u8]);
+        },
+        Format::R32Sfloat => for &v in values.iter() {
+            let b = v.to_bits();
+            ret.extend_from_slice(&[b as u8, (b >> 8) as u8, (b >> 16) as u8, (b >> 24) as u8]);
+        },
+        _ => return None,
+    }
+    Some(ret)
+}
+
+/// Push constant block read by `shaders/noise.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NoisePushConstants {
+    pub kind: u32,
+    pub frequency: u32,
+    pub octaves: u32,
+    pub seed: u32,
+    pub gain: f32,
+}
+
+impl NoisePushConstants {
+    /// `None` for blue noise, which the shader can't generate.
+    pub fn new(params: &NoiseParams) -> Option<NoisePushConstants> {
+        let kind = match params.kind {
+            NoiseKind::Value => 0,
+            NoiseKind::Perlin => 1,
+            NoiseKind::Simplex => 2,
+            NoiseKind::Blue => return None,
+        };
+        Some(NoisePushConstants {
+            kind: kind,
+            frequency: params.frequency,
+            octaves: params.octaves,
+            seed: params.seed,
+            gain: params.gain,
+        })
+    }
+}
+
+/// A single-channel float texture filled by `shaders/noise.glsl`, sampled with repeat
+/// addressing so tiling noise wraps cleanly.
+pub struct NoiseTexture<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+    sampler: Sampler,
+    extent: Extent2D,
+    initialized: bool,
+}
+
+impl<'d, D: DeviceV1_0> NoiseTexture<'d, D> {
+    /// Creates the texture. It's undefined until the first `cmd_generate`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, extent: Extent2D) -> VkResult<NoiseTexture<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: NOISE_FORMAT,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_STORAGE_BIT | IMAGE_USAGE_SAMPLED_BIT | IMAGE_USAGE_TRANSFER_SRC_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = unsafe { device.create_image(&image_create_info, None) }?;
+        let mut ret = NoiseTexture {
+            device: device,
+            image: image,
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+            sampler: Sampler::null(),
+            extent: extent,
+            initialized: false,
+        };
+        ret.memory = allocator.allocate_image_memory(image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: image,
+            view_type: ImageViewType::Type2d,
+            format: NOISE_FORMAT,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: NoiseTexture::<D>::subresource_range(),
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::Repeat,
+            address_mode_v: SamplerAddressMode::Repeat,
+            address_mode_w: SamplerAddressMode::Repeat,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+        debug!("Created {}x{} noise texture: {:?}", ret.extent.width, ret.extent.height, image);
+        Ok(ret)
+    }
+
+    fn subresource_range() -> ImageSubresourceRange {
+        ImageSubresourceRange {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    pub fn extent(&self) -> &Extent2D {
+        &self.extent
+    }
+
+    /// Descriptor set layout bindings for the noise compute kernel.
+    pub fn compute_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: NOISE_BINDING,
+            descriptor_type: DescriptorType::StorageImage,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    pub fn storage_write(&self) -> DescriptorWrite {
+        DescriptorWrite::StorageImage {
+            binding: NOISE_BINDING,
+            image_view: self.view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    /// The texture stays in `General`, so that's what it's sampled in.
+    pub fn sampled_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: binding,
+            sampler: self.sampler,
+            image_view: self.view,
+            image_layout: ImageLayout::General,
+        }
+    }
+
+    /// Records filling the texture with `kernel` (built from `NOISE_SHADER` and
+    /// `compute_bindings`) and `descriptor_set` (holding `storage_write`), followed by a barrier
+    /// for reads from fragment and compute shaders. Must be outside a render pass.
+    pub unsafe fn cmd_generate(&mut self, command_buffer: CommandBuffer, kernel: &ComputeKernel<D>, descriptor_set: DescriptorSet, constants: &NoisePushConstants) {
+        let readers = PIPELINE_STAGE_FRAGMENT_SHADER_BIT | PIPELINE_STAGE_COMPUTE_SHADER_BIT;
+        let (old_layout, src_stage, src_access) = if self.initialized {
+            (ImageLayout::General, readers, ACCESS_SHADER_READ_BIT)
+        } else {
+            (ImageLayout::Undefined, PIPELINE_STAGE_TOP_OF_PIPE_BIT, Default::default())
+        };
+        let to_compute = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: src_access,
+            dst_access_mask: ACCESS_SHADER_WRITE_BIT,
+            old_layout: old_layout,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: NoiseTexture::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, src_stage, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &[to_compute]);
+        self.initialized = true;
+
+        let groups = self.extent.div_ceil(LOCAL_SIZE);
+        kernel.cmd_dispatch(command_buffer, descriptor_set, constants, (groups.width, groups.height, 1));
+
+        let to_readers = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::General,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: NoiseTexture::<D>::subresource_range(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, readers, Default::default(), &[], &[], &[to_readers]);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for NoiseTexture<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying noise texture: {:?}", self.image);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl` showing a noise texture in grey,
+/// tiled twice across.
+pub fn view_pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
@@ -0,0 +1,50 @@
+//! Normal visualization through a geometry shader: `shaders/normal_vis_geometry.glsl` turns each
+//! triangle of a mesh into short lines along its three vertex normals and its face normal, so bad
+//! normals or flipped winding stand out when drawn on top of the shaded mesh.
+use ash::version::DeviceV1_0;
+use std;
+use vk::types::*;
+use ::math::Mat4;
+use ::mesh::MeshVertex;
+use ::pipeline::GraphicsPipelineBuilder;
+
+pub const VERTEX_SHADER: &'static str = "shaders/normal_vis_vertex.vert.spv";
+pub const GEOMETRY_SHADER: &'static str = "shaders/normal_vis_geometry.geom.spv";
+
+/// Push constant block read by `shaders/normal_vis_geometry.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NormalVisPushConstants {
+    pub model_view_projection: Mat4,
+    /// Length of the lines, in object space.
+    pub normal_length: f32,
+}
+
+impl NormalVisPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_GEOMETRY_BIT,
+            offset: 0,
+            size: std::mem::size_of::<NormalVisPushConstants>() as u32,
+        }
+    }
+
+    /// Records pushing the constants. Draw the mesh afterwards with the normal pipeline bound.
+    pub unsafe fn cmd_push<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let range = NormalVisPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, self as *const NormalVisPushConstants as *const c_void);
+    }
+}
+
+/// Lines over `MeshVertex` meshes drawn with fixed-function fetch. Depth tested, but without
+/// writes, so the lines draw over the mesh they came from. `fragment_shader` is normally
+/// `shaders/debug_draw_fragment.glsl`.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, geometry_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .geometry_shader(geometry_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .vertex_input(&MeshVertex::binding_descriptions(), &MeshVertex::attribute_descriptions())
+        .cull_mode(CULL_MODE_NONE)
+        .depth_test(false, CompareOp::LessOrEqual)
+}
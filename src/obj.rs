@@ -0,0 +1,224 @@
+//! Wavefront OBJ loading. Only geometry is read: positions, texture coordinates and normals, and
+//! faces, with polygons split into triangle fans. Everything else, like materials, groups and
+//! smoothing groups, is skipped.
+//!
+//! OBJ indexes positions, texture coordinates and normals separately, so every distinct
+//! combination a face uses becomes one vertex. Missing normals are generated by averaging the
+//! faces around each vertex, and missing texture coordinates are zero.
+use std;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use ::mesh::{ MeshData, MeshVertex };
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    /// A line that couldn't be parsed, numbered from 1.
+    Parse(usize, String),
+    /// No faces.
+    Empty,
+}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> ObjError {
+        ObjError::Io(e)
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Io(ref e) => write!(f, "{}", e),
+            ObjError::Parse(line, ref what) => write!(f, "line {}: {}", line, what),
+            ObjError::Empty => write!(f, "no faces"),
+        }
+    }
+}
+
+/// Position, texture coordinate and normal indices of one face corner, from 0.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+fn parse_floats<'a, I: Iterator<Item = &'a str>>(values: I, min: usize, max: usize) -> std::result::Result<Vec<f32>, String> {
+    let ret = (values.map(|value| value.parse::<f32>().map_err(|e| format!("{:?}: {}", value, e)))
+        .collect::<std::result::Result<Vec<f32>, String>>())?;
+    if ret.len() < min || ret.len() > max {
+        return Err(format!("expected {} to {} numbers, found {}", min, max, ret.len()));
+    }
+    Ok(ret)
+}
+
+/// Resolves a 1-based index, or a negative one counting back from the end, against `count`
+/// elements so far.
+fn resolve_index(text: &str, count: usize) -> std::result::Result<usize, String> {
+    let index = (text.parse::<i64>().map_err(|e| format!("index {:?}: {}", text, e)))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} out of range, there are {}", index, count));
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_corner(text: &str, counts: (usize, usize, usize)) -> std::result::Result<Corner, String> {
+    let mut parts = text.split('/');
+    let position = resolve_index(parts.next().unwrap(), counts.0)?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, counts.1)?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, counts.2)?),
+    };
+    Ok((position, uv, normal))
+}
+
+pub fn parse(text: &str) -> std::result::Result<MeshData, ObjError> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut vertices: Vec<MeshVertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    let mut vertex_indices: HashMap<Corner, u32> = HashMap::new();
+    let mut generate_normals = false;
+    for (idx, line) in text.lines().enumerate() {
+        let line_number = idx + 1;
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let parsed: std::result::Result<(), String> = match keyword {
+            "v" => parse_floats(words, 3, 4).map(|v| positions.push([v[0], v[1], v[2]])),
+            // Texture `v` runs up the image in OBJ, but down it in Vulkan
+            "vt" => parse_floats(words, 1, 3).map(|v| uvs.push([v[0], 1.0 - v.get(1).cloned().unwrap_or(0.0)])),
+            "vn" => parse_floats(words, 3, 3).map(|v| normals.push([v[0], v[1], v[2]])),
+            "f" => {
+                let counts = (positions.len(), uvs.len(), normals.len());
+                words.map(|word| parse_corner(word, counts))
+                    .collect::<std::result::Result<Vec<Corner>, String>>()
+                    .and_then(|corners| {
+                        if corners.len() < 3 {
+                            return Err(format!("a face needs at least 3 corners, not {}", corners.len()));
+                        }
+                        let face_indices: Vec<u32> = corners.iter().map(|&corner| {
+                            *vertex_indices.entry(corner).or_insert_with(|| {
+                                let (position, uv, normal) = corner;
+                                generate_normals |= normal.is_none();
+                                vertices.push(MeshVertex {
+                                    position: positions[position],
+                                    normal: normal.map(|n| normals[n]).unwrap_or([0.0; 3]),
+                                    uv: uv.map(|t| uvs[t]).unwrap_or([0.0; 2]),
+                                });
+                                (vertices.len() - 1) as u32
+                            })
+                        }).collect();
+                        for i in 1..face_indices.len() - 1 {
+                            indices.extend_from_slice(&[face_indices[0], face_indices[i], face_indices[i + 1]]);
+                        }
+                        Ok(())
+                    })
+            },
+            _ => Ok(()),
+        };
+        parsed.map_err(|what| ObjError::Parse(line_number, what))?;
+    }
+    if indices.is_empty() {
+        return Err(ObjError::Empty);
+    }
+    let mut ret = MeshData {
+        vertices: vertices,
+        indices: indices,
+    };
+    if generate_normals {
+        ret.generate_normals();
+    }
+    Ok(ret)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> std::result::Result<MeshData, ObjError> {
+    let data = ::read_full_file(&path.as_ref().to_string_lossy())?;
+    let ret = parse(&String::from_utf8_lossy(&data))?;
+    debug!("Loaded {:?}: {} vertices, {} triangles", path.as_ref(), ret.vertices.len(), ret.indices.len() / 3);
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad_with_normals_and_uvs() {
+        let text = "
+            # a unit quad
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            vn 0 0 1
+            f 1/1/1 2/2/1 3/3/1 4/4/1
+        ";
+        let mesh = parse(text).unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.vertices[2].position, [1.0, 1.0, 0.0]);
+        assert_eq!(mesh.vertices[2].normal, [0.0, 0.0, 1.0]);
+        // Flipped to run down the image
+        assert_eq!(mesh.vertices[2].uv, [1.0, 0.0]);
+    }
+
+    #[test]
+    fn shared_corners_are_one_vertex() {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nf 3 2 4\n").unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 3]);
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n").unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.vertices[1].position, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_normals_are_generated() {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        for vertex in mesh.vertices.iter() {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        match parse("v 0 0 0\nv 1 0 0\nf 1 2 3\n") {
+            Err(ObjError::Parse(3, _)) => {},
+            other => panic!("expected an error on line 3, got {:?}", other),
+        }
+        match parse("v 0 0\n") {
+            Err(ObjError::Parse(1, _)) => {},
+            other => panic!("expected an error on line 1, got {:?}", other),
+        }
+        match parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n") {
+            Err(ObjError::Parse(4, _)) => {},
+            other => panic!("expected an error on line 4, got {:?}", other),
+        }
+        match parse("v 0 0 0\nv 1 0 0\nf 1 2\n") {
+            Err(ObjError::Parse(3, _)) => {},
+            other => panic!("expected an error on line 3, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn no_faces_is_an_error() {
+        match parse("v 0 0 0\n") {
+            Err(ObjError::Empty) => {},
+            other => panic!("expected no faces, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Color targets that aren't swapchain images. The scene renders into one when its result goes
+//! somewhere other than straight to the screen: copied into the swapchain image after a late
+//! acquire (see `late_acquire`), or sampled by a later pass like the upscale in `upscale`.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::ptr;
+use vk::types::*;
+use ::command::RenderTarget;
+use ::depth_buffer::{ self, DepthBuffer };
+use ::image_view_cache::ImageViewCache;
+use ::safe_create::{ self, FramebufferCreateInfoSafe, Owned, OwnedFramebuffer };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+/// What the rendered image is used for afterwards, which decides the layout the render pass
+/// leaves it in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffscreenUsage {
+    /// Copied from, ending in `TransferSrcOptimal`.
+    Copy,
+    /// Sampled in fragment shaders, ending in `ShaderReadOnlyOptimal`.
+    Sampled,
+}
+
+impl OffscreenUsage {
+    fn image_usage(&self) -> ImageUsageFlags {
+        match *self {
+            OffscreenUsage::Copy => IMAGE_USAGE_COLOR_ATTACHMENT_BIT | IMAGE_USAGE_TRANSFER_SRC_BIT,
+            OffscreenUsage::Sampled => IMAGE_USAGE_COLOR_ATTACHMENT_BIT | IMAGE_USAGE_SAMPLED_BIT,
+        }
+    }
+
+    fn final_layout(&self) -> ImageLayout {
+        match *self {
+            OffscreenUsage::Copy => ImageLayout::TransferSrcOptimal,
+            OffscreenUsage::Sampled => ImageLayout::ShaderReadOnlyOptimal,
+        }
+    }
+
+    /// The stage and access of whatever reads the image after the render pass.
+    fn reader(&self) -> (PipelineStageFlags, AccessFlags) {
+        match *self {
+            OffscreenUsage::Copy => (PIPELINE_STAGE_TRANSFER_BIT, ACCESS_TRANSFER_READ_BIT),
+            OffscreenUsage::Sampled => (PIPELINE_STAGE_FRAGMENT_SHADER_BIT, ACCESS_SHADER_READ_BIT),
+        }
+    }
+}
+
+/// A color image with a render pass that leaves it ready for its `OffscreenUsage`, and optionally
+/// a depth buffer. The render pass is compatible with one for swapchain images of the same format
+/// and depth format, so the same pipelines draw into either.
+pub struct OffscreenTarget<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    // In the order they're destroyed
+    framebuffer: OwnedFramebuffer<'d, D>,
+    render_pass: Owned<'d, D, RenderPass>,
+    _views: ImageViewCache<'d, D>,
+    /// From `views`.
+    view: ImageView,
+    _depth: Option<DepthBuffer<'d, D>>,
+    image: Owned<'d, D, Image>,
+    _memory: VkAllocation<'d, D>,
+    extent: Extent2D,
+    usage: OffscreenUsage,
+}
+
+impl<'d, D: DeviceV1_0> OffscreenTarget<'d, D> {
+    /// With a `depth_format`, the render pass has a depth attachment after the color one.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, depth_format: Option<Format>, extent: Extent2D, usage: OffscreenUsage) -> VkResult<OffscreenTarget<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: format,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: usage.image_usage(),
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = safe_create::create_image_safe(device, &image_create_info, None)?;
+        let memory = allocator.allocate_image_memory(*image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let mut views = ImageViewCache::new(device);
+        let view = views.get(*image, ImageViewType::Type2d, format, &color_range())?;
+        let depth = match depth_format {
+            Some(depth_format) => Some(DepthBuffer::new(device, allocator, depth_format, extent.clone())?),
+            None => None,
+        };
+
+        let mut attachments = vec![AttachmentDescription {
+            flags: Default::default(),
+            format: format,
+            samples: SAMPLE_COUNT_1_BIT,
+            load_op: AttachmentLoadOp::Clear,
+            store_op: AttachmentStoreOp::Store,
+            stencil_load_op: AttachmentLoadOp::DontCare,
+            stencil_store_op: AttachmentStoreOp::DontCare,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: usage.final_layout(),
+        }];
+        attachments.extend(depth_format.map(depth_buffer::attachment_description));
+        let color_attachment_ref = AttachmentReference {
+            attachment: 0,
+            layout: ImageLayout::ColorAttachmentOptimal,
+        };
+        let depth_attachment_ref = AttachmentReference {
+            attachment: 1,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+        };
+        let subpass = SubpassDescription {
+            flags: Default::default(),
+            pipeline_bind_point: PipelineBindPoint::Graphics,
+            input_attachment_count: 0,
+            p_input_attachments: ptr::null(),
+            color_attachment_count: 1,
+            p_color_attachments: &color_attachment_ref,
+            p_resolve_attachments: ptr::null(),
+            p_depth_stencil_attachment: if depth.is_some() { &depth_attachment_ref } else { ptr::null() },
+            preserve_attachment_count: 0,
+            p_preserve_attachments: ptr::null(),
+        };
+        let (reader_stage, reader_access) = usage.reader();
+        let dependencies = [
+            // The previous frame's reads have to finish before this frame overwrites the image,
+            // and so do its depth writes
+            SubpassDependency {
+                src_subpass: VK_SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: reader_stage | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+                src_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dst_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+                dst_access_mask: ACCESS_COLOR_ATTACHMENT_READ_BIT | ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dependency_flags: Default::default(),
+            },
+            SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: VK_SUBPASS_EXTERNAL,
+                src_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+                src_access_mask: ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+                dst_stage_mask: reader_stage,
+                dst_access_mask: reader_access,
+                dependency_flags: Default::default(),
+            },
+        ];
+        let render_pass_create_info = RenderPassCreateInfo {
+            s_type: StructureType::RenderPassCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+        };
+        let render_pass = safe_create::create_render_pass_safe(device, &render_pass_create_info, None)?;
+
+        let mut framebuffer_attachments = vec![view];
+        framebuffer_attachments.extend(depth.as_ref().map(|depth| depth.view()));
+        let framebuffer_create_info = FramebufferCreateInfo {
+            s_type: StructureType::FramebufferCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            render_pass: RenderPass::null(),
+            attachment_count: 0,
+            p_attachments: ptr::null(),
+            width: extent.width,
+            height: extent.height,
+            layers: 1,
+        };
+        let framebuffer_create_info = unsafe { FramebufferCreateInfoSafe::from_handles(framebuffer_create_info, *render_pass, &framebuffer_attachments) };
+        let framebuffer = safe_create::create_framebuffer_safe(device, framebuffer_create_info, None)?;
+        debug!("Created {}x{} {:?} offscreen target for {:?}: {:?}", extent.width, extent.height, format, usage, *image);
+        Ok(OffscreenTarget {
+            device: device,
+            framebuffer: framebuffer,
+            render_pass: render_pass,
+            _views: views,
+            view: view,
+            _depth: depth,
+            image: image,
+            _memory: memory,
+            extent: extent,
+            usage: usage,
+        })
+    }
+
+    pub fn target<'a>(&'a self) -> RenderTarget<'a> {
+        RenderTarget {
+            render_pass: &self.render_pass,
+            framebuffer: &self.framebuffer,
+            extent: self.extent.clone(),
+        }
+    }
+
+    /// The view to sample the rendered image through, in `ShaderReadOnlyOptimal` layout.
+    pub fn view(&self) -> ImageView {
+        debug_assert_eq!(self.usage, OffscreenUsage::Sampled);
+        self.view
+    }
+
+    pub fn image(&self) -> Image {
+        *self.image
+    }
+
+    pub fn extent(&self) -> &Extent2D {
+        &self.extent
+    }
+
+    /// Records copying the rendered image into `swapchain_image`, leaving it in `PresentSrcKhr`.
+    /// The swapchain image must be the same size and format, and created with
+    /// `IMAGE_USAGE_TRANSFER_DST_BIT`.
+    pub unsafe fn cmd_copy_to(&self, command_buffer: CommandBuffer, swapchain_image: Image) {
+        debug_assert_eq!(self.usage, OffscreenUsage::Copy);
+        cmd_copy_to_swapchain(self.device, command_buffer, *self.image, &self.extent, swapchain_image);
+    }
+}
+
+/// Records copying `image`, `extent` big and in `TransferSrcOptimal`, into `swapchain_image`,
+/// leaving it in `PresentSrcKhr`. The swapchain image must be the same size and format, and
+/// created with `IMAGE_USAGE_TRANSFER_DST_BIT`.
+pub unsafe fn cmd_copy_to_swapchain<D: DeviceV1_0>(device: &D, command_buffer: CommandBuffer, image: Image, extent: &Extent2D, swapchain_image: Image) {
+    let to_transfer_dst = ImageMemoryBarrier {
+        s_type: StructureType::ImageMemoryBarrier,
+        p_next: ptr::null(),
+        src_access_mask: Default::default(),
+        dst_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+        old_layout: ImageLayout::Undefined,
+        new_layout: ImageLayout::TransferDstOptimal,
+        src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+        image: swapchain_image,
+        subresource_range: color_range(),
+    };
+    device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[to_transfer_dst]);
+    let layers = ImageSubresourceLayers {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let region = ImageCopy {
+        src_subresource: layers.clone(),
+        src_offset: Offset3D { x: 0, y: 0, z: 0 },
+        dst_subresource: layers,
+        dst_offset: Offset3D { x: 0, y: 0, z: 0 },
+        extent: Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+    };
+    device.cmd_copy_image(command_buffer, image, ImageLayout::TransferSrcOptimal, swapchain_image, ImageLayout::TransferDstOptimal, &[region]);
+    let to_present = ImageMemoryBarrier {
+        s_type: StructureType::ImageMemoryBarrier,
+        p_next: ptr::null(),
+        src_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+        dst_access_mask: Default::default(),
+        old_layout: ImageLayout::TransferDstOptimal,
+        new_layout: ImageLayout::PresentSrcKhr,
+        src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+        image: swapchain_image,
+        subresource_range: color_range(),
+    };
+    device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT, Default::default(), &[], &[], &[to_present]);
+}
+
+fn color_range() -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
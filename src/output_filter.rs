@@ -0,0 +1,281 @@
+//! Debug filters over the final image, drawn by `shaders/output_filter_fragment.glsl` as one
+//! full-screen pass: single channels shown as grayscale, luminance, and simulated protanopia and
+//! deuteranopia. The pass reads the image it filters through a sampler, so it needs the image in
+//! linear color, e.g. through an sRGB view of the scene color. It's selected from the overlay,
+//! see `Overlay::handle_key`.
+//!
+//! While a filter is on, the final image goes into the sampled `OffscreenTarget` of a
+//! `FilterPass` instead of the swapchain image, and the pass draws it filtered into the swapchain
+//! image's render pass. With sharpening on, a `CasPass` sharpens the target first and the pass
+//! samples its output instead, see `FilterPass::set_sharpened`. While no filter is on, the pass
+//! draws through `gamma`'s pipeline instead, so the final image gets encoded when the swapchain
+//! isn't sRGB; a filtered image shows the linear values as they are.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::gamma::{ self, GammaOutput, GammaPushConstants };
+use ::offscreen::{ OffscreenTarget, OffscreenUsage };
+use ::pipeline::GraphicsPipelineBuilder;
+use ::vk_mem::MemoryAllocator;
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/output_filter_fragment.frag.spv";
+
+/// Binding of the filtered image.
+pub const IMAGE_BINDING: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFilter {
+    None,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Rec. 709 relative luminance.
+    Luminance,
+    /// Missing long-wavelength cones, using Machado et al.'s model.
+    Protanopia,
+    /// Missing medium-wavelength cones, using Machado et al.'s model.
+    Deuteranopia,
+}
+
+impl OutputFilter {
+    pub fn next(&self) -> OutputFilter {
+        match *self {
+            OutputFilter::None => OutputFilter::Red,
+            OutputFilter::Red => OutputFilter::Green,
+            OutputFilter::Green => OutputFilter::Blue,
+            OutputFilter::Blue => OutputFilter::Alpha,
+            OutputFilter::Alpha => OutputFilter::Luminance,
+            OutputFilter::Luminance => OutputFilter::Protanopia,
+            OutputFilter::Protanopia => OutputFilter::Deuteranopia,
+            OutputFilter::Deuteranopia => OutputFilter::None,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        match *self {
+            OutputFilter::None => 0,
+            OutputFilter::Red => 1,
+            OutputFilter::Green => 2,
+            OutputFilter::Blue => 3,
+            OutputFilter::Alpha => 4,
+            OutputFilter::Luminance => 5,
+            OutputFilter::Protanopia => 6,
+            OutputFilter::Deuteranopia => 7,
+        }
+    }
+
+    pub fn push_constants(&self) -> OutputFilterPushConstants {
+        OutputFilterPushConstants {
+            mode: self.mode(),
+        }
+    }
+
+    pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: IMAGE_BINDING,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    /// Records the filter pass, with the filter pipeline and a descriptor set holding the image
+    /// already bound. `None` still copies the image through, so the pass can stay in the frame
+    /// unconditionally.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let constants = self.push_constants();
+        let range = OutputFilterPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const OutputFilterPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Push constant block read by `shaders/output_filter_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct OutputFilterPushConstants {
+    pub mode: u32,
+}
+
+impl OutputFilterPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<OutputFilterPushConstants>() as u32,
+        }
+    }
+}
+
+/// The full resolution target the final image is drawn into, and everything the pass needs to
+/// filter it into one swapchain render pass. Sized to one swapchain, so it's recreated with it.
+pub struct FilterPass<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    source: OffscreenTarget<'d, D>,
+    sampler: Sampler,
+    set_layout: DescriptorSetLayout,
+    layout: PipelineLayout,
+    pipeline: Pipeline,
+    /// For `gamma::FRAGMENT_SHADER`, with the same set layout.
+    gamma_layout: PipelineLayout,
+    gamma_pipeline: Pipeline,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_set: DescriptorSet,
+    /// Null until `set_sharpened`.
+    sharpened_set: DescriptorSet,
+}
+
+impl<'d, D: DeviceV1_0> FilterPass<'d, D> {
+    /// Draws the final image at `extent`, with a depth buffer if there's a `depth_format`, and
+    /// filters it into subpass 0 of `output_render_pass`, whose framebuffers are the same size.
+    /// `vertex_shader` must be `shaders/fullscreen_vertex.glsl`, `fragment_shader`
+    /// `FRAGMENT_SHADER` and `gamma_shader` `gamma::FRAGMENT_SHADER`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, depth_format: Option<Format>, extent: Extent2D, output_render_pass: RenderPass, vertex_shader: ShaderModule, fragment_shader: ShaderModule, gamma_shader: ShaderModule) -> VkResult<FilterPass<'d, D>> {
+        // The source, and the sharpened image
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::CombinedImageSampler,
+            descriptor_count: 2,
+        }];
+        let source = OffscreenTarget::new(device, allocator, format, depth_format, extent, OffscreenUsage::Sampled)?;
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = FilterPass {
+            device: device,
+            source: source,
+            sampler: Sampler::null(),
+            set_layout: DescriptorSetLayout::null(),
+            layout: PipelineLayout::null(),
+            pipeline: Pipeline::null(),
+            gamma_layout: PipelineLayout::null(),
+            gamma_pipeline: Pipeline::null(),
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, 2),
+            descriptor_set: DescriptorSet::null(),
+            sharpened_set: DescriptorSet::null(),
+        };
+
+        // One texel per pixel, so there's nothing to filter between them
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+
+        let bindings = OutputFilter::bindings();
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        ret.set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        let push_constant_range = OutputFilterPushConstants::range();
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 1,
+            p_set_layouts: &ret.set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+        ret.pipeline = unsafe { pipeline_builder(ret.source.extent(), vertex_shader, fragment_shader).build_raw(device, PipelineCache::null(), ret.layout, output_render_pass, 0) }?;
+        let gamma_range = GammaPushConstants::range();
+        let gamma_layout_create_info = PipelineLayoutCreateInfo {
+            p_push_constant_ranges: &gamma_range,
+            ..layout_create_info
+        };
+        ret.gamma_layout = unsafe { device.create_pipeline_layout(&gamma_layout_create_info, None) }?;
+        ret.gamma_pipeline = unsafe { gamma::pipeline_builder(ret.source.extent(), vertex_shader, gamma_shader).build_raw(device, PipelineCache::null(), ret.gamma_layout, output_render_pass, 0) }?;
+
+        ret.descriptor_set = ret.descriptor_allocator.allocate(ret.set_layout)?;
+        descriptor::update_descriptor_set(device, ret.descriptor_set, &[DescriptorWrite::CombinedImageSampler {
+            binding: IMAGE_BINDING,
+            sampler: ret.sampler,
+            image_view: ret.source.view(),
+            image_layout: ImageLayout::ShaderReadOnlyOptimal,
+        }]);
+        debug!("Created {}x{} output filter pass", ret.source.extent().width, ret.source.extent().height);
+        Ok(ret)
+    }
+
+    /// The target to draw the final image into while `filter` is on.
+    pub fn source(&self) -> &OffscreenTarget<'d, D> {
+        &self.source
+    }
+
+    /// Sets the image drawn instead of the source when sharpened, e.g. `CasPass::sampled_write`
+    /// at `IMAGE_BINDING`. Only called once.
+    pub fn set_sharpened(&mut self, write: DescriptorWrite) -> VkResult<()> {
+        debug_assert!(self.sharpened_set == DescriptorSet::null());
+        self.sharpened_set = self.descriptor_allocator.allocate(self.set_layout)?;
+        descriptor::update_descriptor_set(self.device, self.sharpened_set, &[write]);
+        Ok(())
+    }
+
+    /// Records the filtered image inside the output render pass, from the sharpened image if
+    /// `sharpened` (which needs `set_sharpened`). Without a filter, it goes through `gamma`
+    /// instead when that's active.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, filter: OutputFilter, gamma: &GammaOutput, sharpened: bool) {
+        let descriptor_set = if sharpened {
+            debug_assert!(self.sharpened_set != DescriptorSet::null());
+            self.sharpened_set
+        } else {
+            self.descriptor_set
+        };
+        if filter == OutputFilter::None && gamma.is_active() {
+            self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, self.gamma_pipeline);
+            self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, self.gamma_layout, 0, &[descriptor_set], &[]);
+            gamma.cmd_draw(self.device, command_buffer, self.gamma_layout);
+            return;
+        }
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, self.pipeline);
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, self.layout, 0, &[descriptor_set], &[]);
+        filter.cmd_draw(self.device, command_buffer, self.layout);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for FilterPass<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying output filter pass: {:?}", self.pipeline);
+            self.device.destroy_pipeline(self.gamma_pipeline, None);
+            self.device.destroy_pipeline_layout(self.gamma_layout, None);
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+            self.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl` replacing the image.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
@@ -0,0 +1,223 @@
+//! Screen-space guides drawn over the final image by `shaders/overlay_fragment.glsl`: pixel
+//! rulers along the top and left edges, a center cross, rule-of-thirds lines, broadcast safe
+//! areas, and the viewport's bounds. The rulers are red along X and green along Y with a white
+//! square at the origin, so a flipped viewport or a wrong pre-transform shows at a glance.
+//!
+//! The overlay also picks the `OutputFilter` applied to the final image, and the strength of
+//! the CAS sharpening pass (see `cas`).
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use glfw;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::cas::CasPushConstants;
+use ::output_filter::OutputFilter;
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::rect::{ self, ExtentExt };
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/overlay_fragment.frag.spv";
+
+const RULERS: u32 = 1;
+const CENTER: u32 = 2;
+const THIRDS: u32 = 4;
+const SAFE_AREAS: u32 = 8;
+const VIEWPORT_BOUNDS: u32 = 16;
+
+/// Cycles the sharpening strength. F12 is taken by screenshots, see `screenshot::KEY`.
+const SHARPENING_KEY: glfw::Key = glfw::Key::Backslash;
+
+/// Sharpening strengths `SHARPENING_KEY` cycles through. Off comes first, since CAS is best
+/// compared against the unsharpened image.
+const SHARPENING_STEPS: [Option<f32>; 5] = [None, Some(0.0), Some(0.25), Some(0.5), Some(1.0)];
+
+/// Push constant block read by `shaders/overlay_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct OverlayPushConstants {
+    pub flags: u32,
+    pub action_safe: f32,
+    pub title_safe: f32,
+    pub viewport_x: f32,
+    pub viewport_y: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl OverlayPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<OverlayPushConstants>() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    pub rulers: bool,
+    pub center: bool,
+    pub thirds: bool,
+    pub safe_areas: bool,
+    pub viewport_bounds: bool,
+    /// Fraction of each dimension inside the action safe area.
+    pub action_safe: f32,
+    /// Fraction of each dimension inside the title safe area.
+    pub title_safe: f32,
+    pub filter: OutputFilter,
+    /// CAS strength from 0 to 1, or `None` to skip the pass.
+    pub sharpening: Option<f32>,
+}
+
+impl Overlay {
+    pub fn new() -> Overlay {
+        Overlay {
+            rulers: false,
+            center: false,
+            thirds: false,
+            safe_areas: false,
+            viewport_bounds: false,
+            action_safe: 0.93,
+            title_safe: 0.9,
+            filter: OutputFilter::None,
+            sharpening: None,
+        }
+    }
+
+    /// Whether any guide is on, i.e. whether the pass needs drawing at all.
+    pub fn is_visible(&self) -> bool {
+        self.flags() != 0
+    }
+
+    fn flags(&self) -> u32 {
+        [(self.rulers, RULERS), (self.center, CENTER), (self.thirds, THIRDS), (self.safe_areas, SAFE_AREAS), (self.viewport_bounds, VIEWPORT_BOUNDS)].iter()
+            .filter(|&&(on, _)| on)
+            .fold(0, |flags, &(_, flag)| flags | flag)
+    }
+
+    /// Toggles the guide bound to `key` (F6 through F10), cycles the output filter (F11), or
+    /// cycles the sharpening strength (backslash). Returns whether the key was handled.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        if key == glfw::Key::F11 {
+            self.filter = self.filter.next();
+            debug!("Output filter: {:?}", self.filter);
+            return true;
+        }
+        if key == SHARPENING_KEY {
+            let current = SHARPENING_STEPS.iter().position(|&step| step == self.sharpening).unwrap_or(0);
+            self.sharpening = SHARPENING_STEPS[(current + 1) % SHARPENING_STEPS.len()];
+            debug!("Sharpening: {:?}", self.sharpening);
+            return true;
+        }
+        let (name, flag) = match key {
+            glfw::Key::F6 => ("rulers", &mut self.rulers),
+            glfw::Key::F7 => ("center cross", &mut self.center),
+            glfw::Key::F8 => ("thirds", &mut self.thirds),
+            glfw::Key::F9 => ("safe areas", &mut self.safe_areas),
+            glfw::Key::F10 => ("viewport bounds", &mut self.viewport_bounds),
+            _ => return false,
+        };
+        *flag = !*flag;
+        debug!("Overlay {}: {}", name, *flag);
+        true
+    }
+
+    /// Constants for guides over `viewport`, which should be the viewport the scene was drawn
+    /// with rather than the whole framebuffer.
+    pub fn push_constants(&self, viewport: &Viewport) -> OverlayPushConstants {
+        OverlayPushConstants {
+            flags: self.flags(),
+            action_safe: self.action_safe,
+            title_safe: self.title_safe,
+            viewport_x: viewport.x,
+            viewport_y: viewport.y,
+            viewport_width: viewport.width,
+            viewport_height: viewport.height,
+        }
+    }
+
+    /// Constants for the CAS pass, or `None` while sharpening is off.
+    pub fn sharpening_push_constants(&self) -> Option<CasPushConstants> {
+        self.sharpening.map(CasPushConstants::new)
+    }
+
+    /// Records the overlay over the whole framebuffer, with the overlay pipeline bound. Does
+    /// nothing while every guide is off.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout, viewport: &Viewport) {
+        if !self.is_visible() {
+            return;
+        }
+        let constants = self.push_constants(viewport);
+        let range = OverlayPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const OverlayPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// The overlay's pipeline, for drawing an `Overlay` into any framebuffer of one render pass.
+pub struct OverlayPass<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    layout: PipelineLayout,
+    pipeline: Pipeline,
+}
+
+impl<'d, D: DeviceV1_0> OverlayPass<'d, D> {
+    /// Draws into subpass 0 of `render_pass`, with a dynamic viewport. `vertex_shader` must be
+    /// `shaders/fullscreen_vertex.glsl` and `fragment_shader` `FRAGMENT_SHADER`.
+    pub fn new(device: &'d D, render_pass: RenderPass, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> VkResult<OverlayPass<'d, D>> {
+        let mut ret = OverlayPass {
+            device: device,
+            layout: PipelineLayout::null(),
+            pipeline: Pipeline::null(),
+        };
+        let push_constant_range = OverlayPushConstants::range();
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 0,
+            p_set_layouts: ptr::null(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+        ret.pipeline = unsafe { pipeline_builder(vertex_shader, fragment_shader).build_raw(device, PipelineCache::null(), ret.layout, render_pass, 0) }?;
+        Ok(ret)
+    }
+
+    /// Records `overlay`'s guides over the whole of `extent` inside the render pass, for a scene
+    /// drawn over all of it too. Does nothing while every guide is off.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, overlay: &Overlay, extent: &Extent2D) {
+        if !overlay.is_visible() {
+            return;
+        }
+        let viewport = extent.to_viewport();
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, self.pipeline);
+        self.device.cmd_set_viewport(command_buffer, std::slice::from_ref(&viewport));
+        self.device.cmd_set_scissor(command_buffer, &[extent.to_rect()]);
+        overlay.cmd_draw(self.device, command_buffer, self.layout, &viewport);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for OverlayPass<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying overlay pass: {:?}", self.pipeline);
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl`, blended over the image. The
+/// fragment shader outputs premultiplied alpha.
+pub fn pipeline_builder(vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    // The extent only matters for the static viewport, which the dynamic one replaces
+    GraphicsPipelineBuilder::new(&rect::placeholder_extent())
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .blend(BlendPreset::Premultiplied)
+        .dynamic_viewport_scissor()
+}
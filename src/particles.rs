@@ -0,0 +1,309 @@
+//! A particle fountain, simulated on the CPU: particles shoot up from the origin in a cone, fall
+//! under gravity and fade out over their lifetime. They're drawn as alpha-blended billboards,
+//! which only composite correctly back to front, so every frame their view depths become
+//! `radix_sort::back_to_front_key`s and a `RadixSort` orders the particle indices on the GPU.
+//! The vertex shader then reads the particles through those indices, farthest first.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::StorageBuffer;
+use ::descriptor::{ self, DescriptorAllocator };
+use ::math::{ Mat4, Vec3 };
+use ::math::prelude::smoothstep;
+use ::nbody::XorShift;
+use ::pipeline::{ BlendPreset, GraphicsPipelineBuilder };
+use ::radix_sort::{ self, RadixSort };
+use ::vk_mem::MemoryAllocator;
+
+pub const VERTEX_SHADER: &'static str = "shaders/particle_vertex.vert.spv";
+pub const FRAGMENT_SHADER: &'static str = "shaders/particle_fragment.frag.spv";
+
+/// Bindings of the draw's descriptor set.
+pub const DRAW_PARTICLES_BINDING: u32 = 0;
+pub const DRAW_ORDER_BINDING: u32 = 1;
+
+const GRAVITY: f32 = -4.0;
+/// Launch speed, and how far from straight up particles can launch, in radians.
+const SPEED: f32 = 4.0;
+const SPREAD: f32 = 0.35;
+/// Seconds a particle lives, before the random part.
+const LIFETIME: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    /// Seconds since it was emitted.
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+pub struct ParticleFountain {
+    particles: Vec<Particle>,
+    capacity: usize,
+    /// Particles emitted per second, as long as there's room.
+    pub rate: f32,
+    /// The part of a particle owed from earlier updates.
+    pending: f32,
+    rng: XorShift,
+}
+
+impl ParticleFountain {
+    pub fn new(capacity: u32, rate: f32) -> ParticleFountain {
+        ParticleFountain {
+            particles: Vec::with_capacity(capacity as usize),
+            capacity: capacity as usize,
+            rate: rate,
+            pending: 0.0,
+            rng: XorShift(0x2545f491),
+        }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Moves and ages the particles by `dt` seconds, drops the ones past their lifetime, and
+    /// emits new ones at `rate`.
+    pub fn update(&mut self, dt: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.velocity.y += GRAVITY * dt;
+            particle.position = particle.position + particle.velocity * dt;
+            particle.age += dt;
+        }
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+        self.pending += self.rate * dt;
+        while self.pending >= 1.0 {
+            self.pending -= 1.0;
+            if self.particles.len() < self.capacity {
+                let particle = self.emit();
+                self.particles.push(particle);
+            }
+        }
+    }
+
+    fn emit(&mut self) -> Particle {
+        let tilt = self.rng.next() * SPREAD;
+        let heading = self.rng.next() * 2.0 * std::f32::consts::PI;
+        let direction = Vec3::new(tilt.sin() * heading.cos(), tilt.cos(), tilt.sin() * heading.sin());
+        Particle {
+            position: Vec3::zero(),
+            velocity: direction * (SPEED * (0.8 + 0.4 * self.rng.next())),
+            age: 0.0,
+            lifetime: LIFETIME * (0.75 + 0.5 * self.rng.next()),
+        }
+    }
+
+    /// What `shaders/particle_vertex.glsl` reads for each particle: its position, and how much
+    /// of its lifetime is left in `w`, eased so it starts fading slowly and finishes gently.
+    pub fn instances(&self) -> Vec<[f32; 4]> {
+        self.particles.iter()
+            .map(|p| [p.position.x, p.position.y, p.position.z, 1.0 - smoothstep(0.0, p.lifetime, p.age)])
+            .collect()
+    }
+
+    /// A key for each particle that sorts the farthest from the camera of `view` first.
+    pub fn sort_keys(&self, view: &Mat4) -> Vec<u32> {
+        self.particles.iter()
+            .map(|p| radix_sort::back_to_front_key(-view.transform_point(p.position).z))
+            .collect()
+    }
+}
+
+/// Push constant block read by `shaders/particle_vertex.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ParticlePushConstants {
+    pub view_projection: Mat4,
+    /// The camera's right axis, and the billboards' half size in `w`.
+    pub right: [f32; 4],
+    /// The camera's up axis.
+    pub up: [f32; 4],
+}
+
+impl ParticlePushConstants {
+    /// Billboards `size` across, facing the camera of `view`.
+    pub fn new(view: &Mat4, projection: &Mat4, size: f32) -> ParticlePushConstants {
+        // The rows of the view's rotation are the camera's axes in world space
+        let row = |r: usize| [view.cols[0][r], view.cols[1][r], view.cols[2][r]];
+        let (right, up) = (row(0), row(1));
+        ParticlePushConstants {
+            view_projection: *projection * *view,
+            right: [right[0], right[1], right[2], size * 0.5],
+            up: [up[0], up[1], up[2], 0.0],
+        }
+    }
+
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            offset: 0,
+            size: std::mem::size_of::<ParticlePushConstants>() as u32,
+        }
+    }
+}
+
+/// The compiled radix sort and scan shaders `ParticleBuffers` sorts with.
+pub struct SortShaders<'a> {
+    pub histogram: &'a [u8],
+    pub scatter: &'a [u8],
+    pub scan_block: &'a [u8],
+    pub scan_add: &'a [u8],
+}
+
+/// One frame slot's copy of the particles, their keys, and the indices `sort` orders by them.
+struct SlotBuffers<'d, D: DeviceV1_0 + 'd> {
+    particles: StorageBuffer<'d, D>,
+    keys: StorageBuffer<'d, D>,
+    order: StorageBuffer<'d, D>,
+    sort: RadixSort<'d, D>,
+    draw_set: DescriptorSet,
+    count: u32,
+}
+
+/// The GPU side of a `ParticleFountain`, with a set of buffers for each frame in flight, since
+/// the frames before might still be drawing from theirs.
+pub struct ParticleBuffers<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    slots: Vec<SlotBuffers<'d, D>>,
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+}
+
+impl<'d, D: DeviceV1_0> ParticleBuffers<'d, D> {
+    /// Buffers for up to `capacity` particles in each of `slot_count` frames. `draw_set_layout`
+    /// must be made from `draw_bindings`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, draw_set_layout: DescriptorSetLayout, capacity: u32, slot_count: usize, shaders: &SortShaders) -> VkResult<ParticleBuffers<'d, D>> {
+        let capacity = std::cmp::max(capacity, 1);
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: 2 * slot_count as u32,
+        }];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, slot_count as u32);
+        let mut slots = Vec::with_capacity(slot_count);
+        for _ in 0..slot_count {
+            let particles = StorageBuffer::new(device, allocator, capacity as DeviceSize * std::mem::size_of::<[f32; 4]>() as DeviceSize)?;
+            let keys = StorageBuffer::new(device, allocator, capacity as DeviceSize * std::mem::size_of::<u32>() as DeviceSize)?;
+            let order = StorageBuffer::new(device, allocator, capacity as DeviceSize * std::mem::size_of::<u32>() as DeviceSize)?;
+            let sort = RadixSort::new(device, allocator, keys.buffer(), order.buffer(), capacity, shaders.histogram, shaders.scatter, shaders.scan_block, shaders.scan_add)?;
+            let draw_set = descriptor_allocator.allocate(draw_set_layout)?;
+            descriptor::update_descriptor_set(device, draw_set, &[
+                particles.descriptor_write(DRAW_PARTICLES_BINDING),
+                order.descriptor_write(DRAW_ORDER_BINDING),
+            ]);
+            slots.push(SlotBuffers {
+                particles: particles,
+                keys: keys,
+                order: order,
+                sort: sort,
+                draw_set: draw_set,
+                count: 0,
+            });
+        }
+        Ok(ParticleBuffers {
+            device: device,
+            slots: slots,
+            _descriptor_allocator: descriptor_allocator,
+        })
+    }
+
+    pub fn draw_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        [DRAW_PARTICLES_BINDING, DRAW_ORDER_BINDING].iter().map(|&binding| DescriptorSetLayoutBinding {
+            binding: binding,
+            descriptor_type: DescriptorType::StorageBuffer,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            p_immutable_samplers: ptr::null(),
+        }).collect()
+    }
+
+    /// Writes `fountain`'s particles, and their keys for `view`, into `slot`'s buffers, which the
+    /// GPU must be done with. Particles past the capacity are left out.
+    pub fn write(&mut self, slot: usize, fountain: &ParticleFountain, view: &Mat4) -> VkResult<()> {
+        let buffers = &mut self.slots[slot];
+        let count = std::cmp::min(fountain.particles().len(), buffers.sort.capacity() as usize);
+        let order: Vec<u32> = (0..count as u32).collect();
+        buffers.particles.write(&fountain.instances()[..count])?;
+        buffers.keys.write(&fountain.sort_keys(view)[..count])?;
+        buffers.order.write(&order)?;
+        buffers.count = count as u32;
+        Ok(())
+    }
+
+    /// Records sorting `slot`'s particle indices back to front, and a barrier for the draw's
+    /// vertex shader reads. Must be outside a render pass.
+    pub unsafe fn cmd_sort(&self, command_buffer: CommandBuffer, slot: usize) {
+        let buffers = &self.slots[slot];
+        buffers.sort.cmd_sort(command_buffer, buffers.count);
+        let to_vertex = MemoryBarrier {
+            s_type: StructureType::MemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_VERTEX_SHADER_BIT, Default::default(), &[to_vertex], &[], &[]);
+    }
+
+    /// Records drawing `slot`'s particles in sorted order, with the particle pipeline bound.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, layout: PipelineLayout, slot: usize, constants: &ParticlePushConstants) {
+        let buffers = &self.slots[slot];
+        self.device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::Graphics, layout, 0, &[buffers.draw_set], &[]);
+        let range = ParticlePushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, constants as *const ParticlePushConstants as *const c_void);
+        // Two triangles per billboard
+        self.device.cmd_draw(command_buffer, 6, buffers.count, 0, 0);
+    }
+}
+
+/// Alpha-blended billboards from `shaders/particle_vertex.glsl`, tested against the scene's depth
+/// without writing it, so they only blend right in the order they're drawn.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .blend(BlendPreset::Alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn particles_rise_fall_and_expire() {
+        let mut fountain = ParticleFountain::new(1000, 100.0);
+        fountain.update(0.1);
+        assert_eq!(fountain.particles().len(), 10);
+        for _ in 0..5 {
+            fountain.update(0.1);
+        }
+        assert!(fountain.particles().iter().all(|p| p.age <= 0.5 + 1e-5));
+        assert!(fountain.particles().iter().filter(|p| p.age > 0.0).all(|p| p.position.y > 0.0));
+
+        fountain.rate = 0.0;
+        for _ in 0..40 {
+            fountain.update(0.1);
+        }
+        assert!(fountain.particles().is_empty());
+    }
+
+    #[test]
+    fn emission_stops_at_capacity() {
+        let mut fountain = ParticleFountain::new(5, 1000.0);
+        fountain.update(0.1);
+        assert_eq!(fountain.particles().len(), 5);
+    }
+
+    #[test]
+    fn sort_keys_put_the_farthest_particle_first() {
+        let mut fountain = ParticleFountain::new(10, 30.0);
+        fountain.update(0.1);
+        let view = Mat4::look_at(Vec3::new(0.0, 1.0, 5.0), Vec3::zero(), Vec3::unit_y());
+        let keys = fountain.sort_keys(&view);
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by_key(|&i| keys[i]);
+        let depths: Vec<f32> = order.iter().map(|&i| -view.transform_point(fountain.particles()[i].position).z).collect();
+        assert!(depths.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", depths);
+    }
+}
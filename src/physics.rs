@@ -0,0 +1,287 @@
+//! A small rigid-body playground on rapier, enabled with the `physics` feature. Bodies are boxes,
+//! usually sized from a mesh's bounds, that fall under gravity, tumble and collide with each other
+//! with restitution and friction; `PhysicsWorld` owns the rapier sets and pipeline and keeps track
+//! of the boxes it has to draw.
+//!
+//! `PhysicsWorld::step` is meant to be driven by `fixed_update::FixedTimestep`, and the bodies'
+//! poses are `Interpolated` so they can be drawn between steps.
+use rapier3d::dynamics::{ CCDSolver, ImpulseJointSet, IntegrationParameters, IslandManager, MultibodyJointSet, RigidBodyBuilder, RigidBodyHandle, RigidBodySet };
+use rapier3d::geometry::{ BroadPhase, ColliderBuilder, ColliderSet, NarrowPhase };
+use rapier3d::math::{ Isometry, Real, Vector };
+use rapier3d::pipeline::PhysicsPipeline;
+use ::debug_draw::{ self, DebugDraw };
+use ::fixed_update::Interpolated;
+use ::gizmo::Transform;
+use ::math::{ Aabb, Quat, Vec3 };
+use ::primitives;
+
+/// Speed below which a body is drawn as resting.
+const RESTING_SPEED: f32 = 0.05;
+
+/// Box collision shape, relative to its body's origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collider {
+    pub offset: Vec3,
+    pub half_extents: Vec3,
+}
+
+impl Collider {
+    pub fn cuboid(half_extents: Vec3) -> Collider {
+        Collider {
+            offset: Vec3::zero(),
+            half_extents: half_extents,
+        }
+    }
+
+    /// A box exactly covering `bounds`, with the body's origin at the model origin.
+    pub fn from_bounds(bounds: &Aabb) -> Collider {
+        Collider {
+            offset: bounds.center(),
+            half_extents: bounds.extents() * 0.5,
+        }
+    }
+
+    /// The box's corners, in the space of a body at `transform`.
+    fn corners(&self, transform: &Transform) -> [Vec3; 8] {
+        let h = self.half_extents;
+        let mut ret = [Vec3::zero(); 8];
+        for (i, corner) in ret.iter_mut().enumerate() {
+            let sign = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            let local = self.offset + Vec3::new(h.x * sign(1), h.y * sign(2), h.z * sign(4));
+            *corner = transform.position + transform.rotation.rotate(local);
+        }
+        ret
+    }
+}
+
+/// A body `PhysicsWorld` draws, and its pose at the last two steps.
+#[derive(Debug, Clone)]
+pub struct RigidBody {
+    handle: RigidBodyHandle,
+    pub collider: Collider,
+    transform: Interpolated<Transform>,
+    fixed: bool,
+    resting: bool,
+}
+
+impl RigidBody {
+    pub fn is_static(&self) -> bool {
+        self.fixed
+    }
+
+    /// Pose after the latest step.
+    pub fn transform(&self) -> Transform {
+        *self.transform.current()
+    }
+
+    /// Position after the latest step.
+    pub fn position(&self) -> Vec3 {
+        self.transform().position
+    }
+
+    /// Pose to draw, `alpha` of the way from the previous step to the latest.
+    pub fn render_transform(&self, alpha: f32) -> Transform {
+        self.transform.get(alpha)
+    }
+}
+
+fn to_vector(v: Vec3) -> Vector<Real> {
+    Vector::new(v.x, v.y, v.z)
+}
+
+fn to_transform(isometry: &Isometry<Real>) -> Transform {
+    let translation = isometry.translation.vector;
+    let rotation = isometry.rotation.quaternion();
+    Transform {
+        position: Vec3::new(translation.x, translation.y, translation.z),
+        rotation: Quat {
+            x: rotation.i,
+            y: rotation.j,
+            z: rotation.k,
+            w: rotation.w,
+        },
+    }
+}
+
+pub struct PhysicsWorld {
+    pub bodies: Vec<RigidBody>,
+    pub gravity: Vec3,
+    /// Used for colliders added after it's set.
+    pub restitution: f32,
+    /// Used for colliders added after it's set.
+    pub friction: f32,
+    rigid_bodies: RigidBodySet,
+    colliders: ColliderSet,
+    parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+}
+
+impl PhysicsWorld {
+    pub fn new() -> PhysicsWorld {
+        PhysicsWorld {
+            bodies: Vec::new(),
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            restitution: 0.2,
+            friction: 0.5,
+            rigid_bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            parameters: IntegrationParameters::default(),
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+        }
+    }
+
+    /// A ground plane with `count` boxes dropped onto it from staggered heights, each with a
+    /// collider from the bounds of a `primitives::cube` its size.
+    pub fn box_drop(count: u32) -> PhysicsWorld {
+        let mut world = PhysicsWorld::new();
+        world.add_fixed(Collider::cuboid(Vec3::new(10.0, 0.5, 10.0)), Vec3::new(0.0, -0.5, 0.0));
+        let columns = (count as f32).sqrt().ceil() as u32;
+        for i in 0..count {
+            let (column, row) = (i % columns, i / columns);
+            let size = 0.3 + 0.1 * ((i * 7) % 4) as f32;
+            let position = Vec3::new(
+                (column as f32 - columns as f32 * 0.5) * 0.9 + 0.05 * (row % 2) as f32,
+                2.0 + row as f32 * 1.2 + 0.3 * column as f32,
+                (row % 3) as f32 * 0.4 - 0.4
+            );
+            world.add_dynamic(Collider::from_bounds(&primitives::cube(size).bounds()), position);
+        }
+        debug!("Created box drop physics demo with {} boxes", count);
+        world
+    }
+
+    /// Adds a body that never moves, and returns its index.
+    pub fn add_fixed(&mut self, collider: Collider, position: Vec3) -> usize {
+        let body = RigidBodyBuilder::fixed().translation(to_vector(position)).build();
+        self.add(body, collider, true)
+    }
+
+    /// Adds a body that falls and tumbles, with a mass from its collider's volume, and returns
+    /// its index.
+    pub fn add_dynamic(&mut self, collider: Collider, position: Vec3) -> usize {
+        let body = RigidBodyBuilder::dynamic().translation(to_vector(position)).build();
+        self.add(body, collider, false)
+    }
+
+    fn add(&mut self, body: ::rapier3d::dynamics::RigidBody, collider: Collider, fixed: bool) -> usize {
+        let transform = to_transform(body.position());
+        let handle = self.rigid_bodies.insert(body);
+        let h = collider.half_extents;
+        let shape = ColliderBuilder::cuboid(h.x, h.y, h.z)
+            .translation(to_vector(collider.offset))
+            .restitution(self.restitution)
+            .friction(self.friction)
+            .build();
+        self.colliders.insert_with_parent(shape, handle, &mut self.rigid_bodies);
+        self.bodies.push(RigidBody {
+            handle: handle,
+            collider: collider,
+            transform: Interpolated::new(transform),
+            fixed: fixed,
+            resting: fixed,
+        });
+        self.bodies.len() - 1
+    }
+
+    /// Advances the simulation by `dt` seconds, which should be a fixed step.
+    pub fn step(&mut self, dt: f32) {
+        self.parameters.dt = dt;
+        self.pipeline.step(
+            &to_vector(self.gravity),
+            &self.parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            None,
+            &(),
+            &()
+        );
+        for body in self.bodies.iter_mut() {
+            let simulated = &self.rigid_bodies[body.handle];
+            body.transform.update(to_transform(simulated.position()));
+            body.resting = body.fixed || simulated.is_sleeping() || simulated.linvel().norm() < RESTING_SPEED;
+        }
+    }
+
+    /// Draws every collider at its interpolated pose: static ones white, moving ones yellow, and
+    /// resting ones green.
+    pub fn debug_draw(&self, lines: &mut DebugDraw, alpha: f32) {
+        for body in self.bodies.iter() {
+            let color = if body.fixed {
+                debug_draw::WHITE
+            } else if body.resting {
+                debug_draw::GREEN
+            } else {
+                debug_draw::YELLOW
+            };
+            let corners = body.collider.corners(&body.render_transform(alpha));
+            // Corners differing in exactly one bit of their index share an edge
+            for i in 0..8 {
+                for &bit in [1, 2, 4].iter() {
+                    if i & bit == 0 {
+                        lines.line(corners[i], corners[i | bit], color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    #[test]
+    fn dropped_box_comes_to_rest_on_the_ground() {
+        let mut world = PhysicsWorld::new();
+        world.add_fixed(Collider::cuboid(Vec3::new(10.0, 0.5, 10.0)), Vec3::new(0.0, -0.5, 0.0));
+        let dropped = world.add_dynamic(Collider::cuboid(Vec3::new(0.5, 0.5, 0.5)), Vec3::new(0.0, 3.0, 0.0));
+
+        world.step(DT);
+        assert!(world.bodies[dropped].position().y < 3.0);
+        assert!(!world.bodies[dropped].resting);
+
+        for _ in 0..600 {
+            world.step(DT);
+        }
+        let resting = &world.bodies[dropped];
+        assert!(resting.resting);
+        assert!((resting.position().y - 0.5).abs() < 0.05, "box rests at {:?}", resting.position());
+    }
+
+    #[test]
+    fn render_transform_blends_the_last_two_steps() {
+        let mut world = PhysicsWorld::new();
+        let falling = world.add_dynamic(Collider::cuboid(Vec3::new(0.5, 0.5, 0.5)), Vec3::new(0.0, 10.0, 0.0));
+        world.step(DT);
+        let before = world.bodies[falling].position();
+        world.step(DT);
+        let after = world.bodies[falling].position();
+        assert!(after.y < before.y);
+
+        let body = &world.bodies[falling];
+        assert_eq!(body.render_transform(0.0).position.y, before.y);
+        assert_eq!(body.render_transform(1.0).position.y, after.y);
+        let halfway = body.render_transform(0.5).position.y;
+        assert!((halfway - (before.y + after.y) * 0.5).abs() < 1e-5);
+    }
+}
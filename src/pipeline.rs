@@ -0,0 +1,344 @@
+//! Builder for graphics pipelines. The builder owns every array that the raw create info structs
+//! point into, so the pointers are only ever built (and used) inside of `build`.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::ptr;
+use vk::types::*;
+use ::pnext::PNextChain;
+use ::safe_create;
+use ::rect::ExtentExt;
+use ::sample_locations::SampleLocations;
+use ::shader_stage::{ self, ShaderStage };
+
+#[derive(Clone)]
+pub struct GraphicsPipelineBuilder {
+    stages: Vec<ShaderStage>,
+    vertex_bindings: Vec<VertexInputBindingDescription>,
+    vertex_attributes: Vec<VertexInputAttributeDescription>,
+    topology: PrimitiveTopology,
+    viewports: Vec<Viewport>,
+    scissors: Vec<Rect2D>,
+    polygon_mode: PolygonMode,
+    cull_mode: CullModeFlags,
+    front_face: FrontFace,
+    line_width: f32,
+    samples: SampleCountFlags,
+    sample_locations: Option<SampleLocations>,
+    depth_test_enable: bool,
+    depth_write_enable: bool,
+    depth_compare_op: CompareOp,
+    stencil: Option<StencilOpState>,
+    blend_attachments: Vec<PipelineColorBlendAttachmentState>,
+    dynamic_states: Vec<DynamicState>,
+}
+
+impl GraphicsPipelineBuilder {
+    /// Starts a pipeline with a single full-`extent` viewport, back-face culling, no depth testing,
+    /// and one opaque color attachment.
+    pub fn new(extent: &Extent2D) -> GraphicsPipelineBuilder {
+        GraphicsPipelineBuilder {
+            stages: Vec::new(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: PrimitiveTopology::TriangleList,
+            viewports: vec![extent.to_viewport()],
+            scissors: vec![extent.to_rect()],
+            polygon_mode: PolygonMode::Fill,
+            cull_mode: CULL_MODE_BACK_BIT,
+            front_face: FrontFace::Clockwise,
+            line_width: 1.0,
+            samples: SAMPLE_COUNT_1_BIT,
+            sample_locations: None,
+            depth_test_enable: false,
+            depth_write_enable: false,
+            depth_compare_op: CompareOp::Less,
+            stencil: None,
+            blend_attachments: vec![BlendPreset::Opaque.attachment_state()],
+            dynamic_states: Vec::new(),
+        }
+    }
+
+    pub fn stage(self, stage: ShaderStageFlags, module: ShaderModule) -> GraphicsPipelineBuilder {
+        self.shader_stage(ShaderStage::new(stage, module))
+    }
+
+    /// Adds a stage with its own entry point or specialization constants.
+    pub fn shader_stage(mut self, stage: ShaderStage) -> GraphicsPipelineBuilder {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Adds a geometry stage between the vertex and fragment stages. Needs the `geometryShader`
+    /// device feature, which is only enabled for demos that need it when the device has it.
+    pub fn geometry_shader(self, module: ShaderModule) -> GraphicsPipelineBuilder {
+        self.shader_stage(ShaderStage::geometry(module))
+    }
+
+    /// Replaces the module for `stage` if the builder already has one, otherwise adds it. Used to
+    /// derive debug variants of an existing pipeline.
+    pub fn replace_stage(mut self, stage: ShaderStageFlags, module: ShaderModule) -> GraphicsPipelineBuilder {
+        match self.stages.iter().position(|s| s.stage() == stage) {
+            Some(idx) => self.stages[idx].set_module(module),
+            None => self.stages.push(ShaderStage::new(stage, module)),
+        }
+        self
+    }
+
+    pub fn vertex_input(mut self, bindings: &[VertexInputBindingDescription], attributes: &[VertexInputAttributeDescription]) -> GraphicsPipelineBuilder {
+        self.vertex_bindings = bindings.to_vec();
+        self.vertex_attributes = attributes.to_vec();
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> GraphicsPipelineBuilder {
+        self.topology = topology;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: PolygonMode) -> GraphicsPipelineBuilder {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullModeFlags) -> GraphicsPipelineBuilder {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn line_width(mut self, line_width: f32) -> GraphicsPipelineBuilder {
+        self.line_width = line_width;
+        self
+    }
+
+    /// Rasterizes with `samples` per pixel, which has to match the subpass's attachments.
+    pub fn samples(mut self, samples: SampleCountFlags) -> GraphicsPipelineBuilder {
+        self.samples = samples;
+        self
+    }
+
+    /// Rasterizes at `locations` instead of the device's standard sample positions. Needs
+    /// `VK_EXT_sample_locations`, and `locations` has to have as many samples as `samples`.
+    pub fn sample_locations(mut self, locations: SampleLocations) -> GraphicsPipelineBuilder {
+        self.sample_locations = Some(locations);
+        self
+    }
+
+    pub fn depth_test(mut self, write_enable: bool, compare_op: CompareOp) -> GraphicsPipelineBuilder {
+        self.depth_test_enable = true;
+        self.depth_write_enable = write_enable;
+        self.depth_compare_op = compare_op;
+        self
+    }
+
+    /// Enables the stencil test with `state` for both front and back faces.
+    pub fn stencil_test(mut self, state: StencilOpState) -> GraphicsPipelineBuilder {
+        self.stencil = Some(state);
+        self
+    }
+
+    /// Uses `preset` for the only color attachment.
+    pub fn blend(self, preset: BlendPreset) -> GraphicsPipelineBuilder {
+        self.blend_attachment(preset.attachment_state())
+    }
+
+    pub fn blend_attachment(self, state: PipelineColorBlendAttachmentState) -> GraphicsPipelineBuilder {
+        self.blend_attachments(&[state])
+    }
+
+    /// Independent blend state per color attachment of the subpass. Different states for
+    /// different attachments need the `independentBlend` device feature.
+    pub fn blend_attachments(mut self, states: &[PipelineColorBlendAttachmentState]) -> GraphicsPipelineBuilder {
+        self.blend_attachments = states.to_vec();
+        self
+    }
+
+    /// Makes viewport and scissor dynamic, so they must be set with `cmd_set_viewport` and
+    /// `cmd_set_scissor` before drawing.
+    pub fn dynamic_viewport_scissor(mut self) -> GraphicsPipelineBuilder {
+        self.dynamic_states.push(DynamicState::Viewport);
+        self.dynamic_states.push(DynamicState::Scissor);
+        self
+    }
+
+    pub fn build<'d, D: DeviceV1_0>(&self, device: &'d D, layout: PipelineLayout, render_pass: RenderPass, subpass: u32) -> VkResult<safe_create::Owned<'d, D, Pipeline>> {
+        self.build_with_cache(device, PipelineCache::null(), layout, render_pass, subpass)
+    }
+
+    pub fn build_with_cache<'d, D: DeviceV1_0>(&self, device: &'d D, pipeline_cache: PipelineCache, layout: PipelineLayout, render_pass: RenderPass, subpass: u32) -> VkResult<safe_create::Owned<'d, D, Pipeline>> {
+        self.with_create_info(layout, render_pass, subpass, |create_info| {
+            safe_create::create_graphics_pipelines_safe(device, &pipeline_cache, &[create_info], None)
+                .map_err(|(_, res)| res)
+                .map(|pipelines| pipelines.into_iter()
+                     .next()
+                     .expect("Expected successful creation of a graphics pipeline to actually give us a graphics pipeline"))
+        })
+    }
+
+    /// Creates the pipeline without taking ownership of it, for when it has to outlive the borrow
+    /// of `device` (e.g. when it's created on another thread). The caller must destroy it.
+    pub unsafe fn build_raw<D: DeviceV1_0>(&self, device: &D, pipeline_cache: PipelineCache, layout: PipelineLayout, render_pass: RenderPass, subpass: u32) -> VkResult<Pipeline> {
+        self.with_create_info(layout, render_pass, subpass, |create_info| {
+            match device.create_graphics_pipelines(pipeline_cache, &[create_info], None) {
+                Ok(pipelines) => Ok(pipelines[0]),
+                Err((_, res)) => Err(res),
+            }
+        })
+    }
+
+    /// Builds the create info, which points into `self` and locals of this function, and hands it
+    /// to `f` while all of that is still alive. The pointers must not escape `f`.
+    pub fn with_create_info<R, F: FnOnce(GraphicsPipelineCreateInfo) -> R>(&self, layout: PipelineLayout, render_pass: RenderPass, subpass: u32, f: F) -> R {
+        shader_stage::with_create_infos(&self.stages, |shader_stages| {
+            let vertex_input_state_create_info = PipelineVertexInputStateCreateInfo {
+                s_type: StructureType::PipelineVertexInputStateCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                vertex_binding_description_count: self.vertex_bindings.len() as u32,
+                p_vertex_binding_descriptions: self.vertex_bindings.as_ptr(),
+                vertex_attribute_description_count: self.vertex_attributes.len() as u32,
+                p_vertex_attribute_descriptions: self.vertex_attributes.as_ptr(),
+            };
+            let input_assembly_state_create_info = PipelineInputAssemblyStateCreateInfo {
+                s_type: StructureType::PipelineInputAssemblyStateCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                topology: self.topology,
+                primitive_restart_enable: false as Bool32,
+            };
+            let viewport_state_create_info = PipelineViewportStateCreateInfo {
+                s_type: StructureType::PipelineViewportStateCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                viewport_count: self.viewports.len() as u32,
+                p_viewports: self.viewports.as_ptr(),
+                scissor_count: self.scissors.len() as u32,
+                p_scissors: self.scissors.as_ptr(),
+            };
+            let rasterization_state_create_info = PipelineRasterizationStateCreateInfo {
+                s_type: StructureType::PipelineRasterizationStateCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                depth_clamp_enable: false as Bool32,
+                rasterizer_discard_enable: false as Bool32,
+                polygon_mode: self.polygon_mode,
+                line_width: self.line_width,
+                cull_mode: self.cull_mode,
+                front_face: self.front_face,
+                depth_bias_enable: false as Bool32,
+                depth_bias_constant_factor: 0.0,
+                depth_bias_clamp: 0.0,
+                depth_bias_slope_factor: 0.0,
+            };
+            let mut sample_locations_state = self.sample_locations.as_ref().map(|locations| locations.pipeline_state());
+            let multisample_next = PNextChain::new()
+                .push_optional(sample_locations_state.as_mut());
+            let multisample_state_create_info = PipelineMultisampleStateCreateInfo {
+                s_type: StructureType::PipelineMultisampleStateCreateInfo,
+                p_next: multisample_next.head(),
+                flags: Default::default(),
+                rasterization_samples: self.samples,
+                sample_shading_enable: false as Bool32,
+                min_sample_shading: 1.0,
+                p_sample_mask: ptr::null(),
+                alpha_to_coverage_enable: false as Bool32,
+                alpha_to_one_enable: false as Bool32,
+            };
+            let keep_stencil = StencilOpState {
+                fail_op: StencilOp::Keep,
+                pass_op: StencilOp::Keep,
+                depth_fail_op: StencilOp::Keep,
+                compare_op: CompareOp::Always,
+                compare_mask: 0,
+                write_mask: 0,
+                reference: 0,
+            };
+            let depth_stencil_state_create_info = PipelineDepthStencilStateCreateInfo {
+                s_type: StructureType::PipelineDepthStencilStateCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                depth_test_enable: self.depth_test_enable as Bool32,
+                depth_write_enable: self.depth_write_enable as Bool32,
+                depth_compare_op: self.depth_compare_op,
+                depth_bounds_test_enable: false as Bool32,
+                stencil_test_enable: self.stencil.is_some() as Bool32,
+                front: self.stencil.clone().unwrap_or(keep_stencil.clone()),
+                back: self.stencil.clone().unwrap_or(keep_stencil),
+                min_depth_bounds: 0.0,
+                max_depth_bounds: 1.0,
+            };
+            let color_blend_state_create_info = PipelineColorBlendStateCreateInfo {
+                s_type: StructureType::PipelineColorBlendStateCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                logic_op_enable: false as Bool32,
+                logic_op: LogicOp::Copy,
+                attachment_count: self.blend_attachments.len() as u32,
+                p_attachments: self.blend_attachments.as_ptr(),
+                blend_constants: [0.0, 0.0, 0.0, 0.0],
+            };
+            let dynamic_state_create_info = PipelineDynamicStateCreateInfo {
+                s_type: StructureType::PipelineDynamicStateCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                dynamic_state_count: self.dynamic_states.len() as u32,
+                p_dynamic_states: self.dynamic_states.as_ptr(),
+            };
+
+            let create_info = GraphicsPipelineCreateInfo {
+                s_type: StructureType::GraphicsPipelineCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                stage_count: shader_stages.len() as u32,
+                p_stages: shader_stages.as_ptr(),
+                p_vertex_input_state: &vertex_input_state_create_info,
+                p_input_assembly_state: &input_assembly_state_create_info,
+                p_tessellation_state: ptr::null(),
+                p_viewport_state: &viewport_state_create_info,
+                p_rasterization_state: &rasterization_state_create_info,
+                p_multisample_state: &multisample_state_create_info,
+                p_depth_stencil_state: &depth_stencil_state_create_info,
+                p_color_blend_state: &color_blend_state_create_info,
+                p_dynamic_state: if self.dynamic_states.is_empty() { ptr::null() } else { &dynamic_state_create_info },
+                layout: layout,
+                render_pass: render_pass,
+                subpass: subpass,
+                base_pipeline_handle: Pipeline::null(),
+                base_pipeline_index: -1,
+            };
+            f(create_info)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendPreset {
+    /// Source replaces destination.
+    Opaque,
+    /// Standard "over" blending with non-premultiplied source alpha.
+    Alpha,
+    /// Source premultiplied by its alpha, blended "over" the destination.
+    Premultiplied,
+    /// Adds source and destination colors, ignoring alpha.
+    Additive,
+}
+
+impl BlendPreset {
+    pub fn attachment_state(&self) -> PipelineColorBlendAttachmentState {
+        let (blend_enable, src_color, dst_color, src_alpha, dst_alpha) = match *self {
+            BlendPreset::Opaque => (false, BlendFactor::One, BlendFactor::Zero, BlendFactor::One, BlendFactor::Zero),
+            BlendPreset::Alpha => (true, BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha, BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+            BlendPreset::Premultiplied => (true, BlendFactor::One, BlendFactor::OneMinusSrcAlpha, BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+            BlendPreset::Additive => (true, BlendFactor::One, BlendFactor::One, BlendFactor::One, BlendFactor::One),
+        };
+        PipelineColorBlendAttachmentState {
+            blend_enable: blend_enable as Bool32,
+            src_color_blend_factor: src_color,
+            dst_color_blend_factor: dst_color,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: src_alpha,
+            dst_alpha_blend_factor: dst_alpha,
+            alpha_blend_op: BlendOp::Add,
+            color_write_mask: ColorComponentFlags::all(),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Pipeline caches kept on disk between runs, so the driver can skip compiling pipelines it has
+//! seen before. The cache file is whatever `vkGetPipelineCacheData` gave us last time; it's only
+//! handed back to the driver if its header says it came from the same device and driver, since
+//! a cache from anywhere else is at best useless.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::fs::{ self, File };
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+use std::ptr;
+use vk::types::*;
+
+/// Where the cache is kept, or empty to not keep one.
+pub fn path() -> String {
+    cvar!("r.pipeline_cache", "pipeline_cache.bin".to_string()).get()
+}
+
+/// Size of a `VK_PIPELINE_CACHE_HEADER_VERSION_ONE` header.
+const HEADER_SIZE: usize = 16 + VK_UUID_SIZE;
+const HEADER_VERSION_ONE: u32 = 1;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data[offset] as u32 | (data[offset + 1] as u32) << 8 | (data[offset + 2] as u32) << 16 | (data[offset + 3] as u32) << 24
+}
+
+/// Checks that cache `data` was made by the device with `properties`, saying why not if it wasn't.
+pub fn validate_header(data: &[u8], properties: &PhysicalDeviceProperties) -> std::result::Result<(), String> {
+    if data.len() < HEADER_SIZE {
+        return Err(format!("only {} bytes long", data.len()));
+    }
+    let (header_size, version) = (read_u32(data, 0), read_u32(data, 4));
+    if (header_size as usize) < HEADER_SIZE || version != HEADER_VERSION_ONE {
+        return Err(format!("unknown header version {} of size {}", version, header_size));
+    }
+    let (vendor_id, device_id) = (read_u32(data, 8), read_u32(data, 12));
+    if vendor_id != properties.vendor_id || device_id != properties.device_id {
+        return Err(format!("made by device {:#06x}:{:#06x}", vendor_id, device_id));
+    }
+    if data[16..HEADER_SIZE] != properties.pipeline_cache_uuid[..] {
+        return Err("made by another driver version".to_string());
+    }
+    Ok(())
+}
+
+/// A pipeline cache that saves itself back to the file it was loaded from when dropped.
+pub struct SafePipelineCache<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    cache: PipelineCache,
+    path: Option<PathBuf>,
+}
+
+fn create<D: DeviceV1_0>(device: &D, initial_data: &[u8]) -> VkResult<PipelineCache> {
+    let create_info = PipelineCacheCreateInfo {
+        s_type: StructureType::PipelineCacheCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        initial_data_size: initial_data.len(),
+        p_initial_data: initial_data.as_ptr() as *const c_void,
+    };
+    unsafe { device.create_pipeline_cache(&create_info, None) }
+}
+
+impl<'d, D: DeviceV1_0> SafePipelineCache<'d, D> {
+    /// An empty cache that isn't saved anywhere.
+    pub fn new(device: &'d D) -> VkResult<SafePipelineCache<'d, D>> {
+        Ok(SafePipelineCache {
+            device: device,
+            cache: create(device, &[])?,
+            path: None,
+        })
+    }
+
+    /// Starts from the cache at `path` if there's a valid one for the device with `properties`,
+    /// or empty otherwise.
+    pub fn load<P: AsRef<Path>>(device: &'d D, properties: &PhysicalDeviceProperties, path: P) -> VkResult<SafePipelineCache<'d, D>> {
+        let path = path.as_ref();
+        let data = match ::read_full_file(&path.to_string_lossy()) {
+            Ok(data) => match validate_header(&data, properties) {
+                Ok(()) => data,
+                Err(reason) => {
+                    info!("Ignoring pipeline cache {:?}: {}", path, reason);
+                    Vec::new()
+                },
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("No pipeline cache at {:?} yet", path);
+                Vec::new()
+            },
+            Err(e) => {
+                warn!("Failed to read pipeline cache {:?}: {}", path, e);
+                Vec::new()
+            },
+        };
+        let cache = match create(device, &data) {
+            Ok(cache) => cache,
+            // The header can match and the rest still be garbage, e.g. after a partial write
+            Err(e) if !data.is_empty() => {
+                warn!("Pipeline cache {:?} was rejected ({:?}), starting over", path, e);
+                create(device, &[])?
+            },
+            Err(e) => return Err(e),
+        };
+        debug!("Created pipeline cache from {} bytes of {:?}: {:?}", data.len(), path, cache);
+        Ok(SafePipelineCache {
+            device: device,
+            cache: cache,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Only valid as long as this is borrowed.
+    pub fn handle(&self) -> PipelineCache {
+        self.cache
+    }
+
+    /// The cache's contents, as they'd be saved.
+    pub fn data(&self) -> VkResult<Vec<u8>> {
+        let mut size = 0;
+        unsafe {
+            let err_code = self.device.fp_v1_0().get_pipeline_cache_data(self.device.handle(), self.cache, &mut size, ptr::null_mut());
+            if err_code != Result::Success {
+                return Err(err_code);
+            }
+            let mut data: Vec<u8> = Vec::with_capacity(size);
+            let err_code = self.device.fp_v1_0().get_pipeline_cache_data(self.device.handle(), self.cache, &mut size, data.as_mut_ptr() as *mut c_void);
+            match err_code {
+                Result::Success | Result::Incomplete => {
+                    data.set_len(size);
+                    Ok(data)
+                },
+                _ => Err(err_code),
+            }
+        }
+    }
+
+    /// Writes the cache to `path`. The file is replaced all at once, so a crash halfway through
+    /// doesn't leave a torn cache behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let path = path.as_ref();
+        let data = self.data().map_err(|e| io::Error::other(format!("failed to get cache data: {:?}", e)))?;
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(&data)?;
+        }
+        fs::rename(&temp_path, path)?;
+        Ok(data.len())
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for SafePipelineCache<'d, D> {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.path {
+            match self.save(path) {
+                Ok(size) => debug!("Saved {} bytes of pipeline cache to {:?}", size, path),
+                Err(e) => warn!("Failed to save pipeline cache to {:?}: {}", path, e),
+            }
+        }
+        unsafe {
+            trace!("Destroying pipeline cache: {:?}", self.cache);
+            self.device.destroy_pipeline_cache(self.cache, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> PhysicalDeviceProperties {
+        let mut properties: PhysicalDeviceProperties = unsafe { std::mem::zeroed() };
+        properties.vendor_id = 0x10de;
+        properties.device_id = 0x1b80;
+        for (i, byte) in properties.pipeline_cache_uuid.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        properties
+    }
+
+    fn header(header_size: u32, version: u32, vendor_id: u32, device_id: u32, uuid: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &word in [header_size, version, vendor_id, device_id].iter() {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(uuid);
+        data
+    }
+
+    #[test]
+    fn header_from_the_same_device_and_driver() {
+        let properties = properties();
+        let mut data = header(HEADER_SIZE as u32, 1, 0x10de, 0x1b80, &properties.pipeline_cache_uuid);
+        assert_eq!(validate_header(&data, &properties), Ok(()));
+        // Whatever the driver puts after the header is its own business
+        data.extend_from_slice(&[0xab; 64]);
+        assert_eq!(validate_header(&data, &properties), Ok(()));
+    }
+
+    #[test]
+    fn header_from_elsewhere_is_rejected() {
+        let properties = properties();
+        let uuid = properties.pipeline_cache_uuid;
+        assert!(validate_header(&[], &properties).is_err());
+        assert!(validate_header(&header(HEADER_SIZE as u32, 1, 0x10de, 0x1b80, &uuid[..8]), &properties).is_err());
+        assert!(validate_header(&header(HEADER_SIZE as u32, 2, 0x10de, 0x1b80, &uuid), &properties).is_err());
+        assert!(validate_header(&header(8, 1, 0x10de, 0x1b80, &uuid), &properties).is_err());
+        assert!(validate_header(&header(HEADER_SIZE as u32, 1, 0x1002, 0x1b80, &uuid), &properties).is_err());
+        assert!(validate_header(&header(HEADER_SIZE as u32, 1, 0x10de, 0x1b81, &uuid), &properties).is_err());
+        let mut other_driver = uuid;
+        other_driver[VK_UUID_SIZE - 1] ^= 1;
+        assert_eq!(validate_header(&header(HEADER_SIZE as u32, 1, 0x10de, 0x1b80, &other_driver), &properties), Err("made by another driver version".to_string()));
+    }
+}
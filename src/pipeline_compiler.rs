@@ -0,0 +1,213 @@
+//! Graphics pipeline compilation on worker threads. Requests return a handle immediately, and
+//! until a pipeline finishes compiling its handle resolves to a fallback pipeline, so neither
+//! startup nor frames have to block on the driver's shader compiler.
+//!
+//! All workers share one `PipelineCache`; Vulkan synchronizes access to caches internally.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::sync::{ Arc, Mutex };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+use vk::types::*;
+use ::logging;
+use ::pipeline::GraphicsPipelineBuilder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PipelineHandle(usize);
+
+struct Job {
+    handle: PipelineHandle,
+    builder: GraphicsPipelineBuilder,
+    layout: PipelineLayout,
+    render_pass: RenderPass,
+    subpass: u32,
+}
+
+struct Compiled {
+    handle: PipelineHandle,
+    result: VkResult<Pipeline>,
+    duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    Pending,
+    Ready(Pipeline),
+    Failed(Result),
+}
+
+/// Compile time statistics for every pipeline that has finished so far.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompileStats {
+    pub compiled: usize,
+    pub failed: usize,
+    pub total: Duration,
+    pub slowest: Duration,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1000000.0
+}
+
+pub struct AsyncPipelineCompiler<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    fallback: Pipeline,
+    jobs: Option<Sender<Job>>,
+    results: Receiver<Compiled>,
+    workers: Vec<JoinHandle<()>>,
+    slots: Vec<Slot>,
+    stats: CompileStats,
+}
+
+impl<'d, D: DeviceV1_0 + Clone + Send + 'static> AsyncPipelineCompiler<'d, D> {
+    /// Starts `worker_count` compile threads. `fallback` is not owned by the compiler, and must
+    /// stay alive as long as it does.
+    pub fn new(device: &'d D, pipeline_cache: PipelineCache, fallback: Pipeline, worker_count: usize) -> AsyncPipelineCompiler<'d, D> {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..worker_count).map(|idx| {
+            let device = device.clone();
+            let jobs = job_receiver.clone();
+            let results = result_sender.clone();
+            thread::Builder::new()
+                .name(format!("pipeline-compiler-{}", idx))
+                .spawn(move || loop {
+                    // Only hold the lock while waiting, so the other workers can compile meanwhile
+                    let job = match jobs.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let start = Instant::now();
+                    let result = unsafe { job.builder.build_raw(&device, pipeline_cache, job.layout, job.render_pass, job.subpass) };
+                    let compiled = Compiled {
+                        handle: job.handle,
+                        result: result,
+                        duration: start.elapsed(),
+                    };
+                    if let Err(mpsc::SendError(compiled)) = results.send(compiled) {
+                        if let Ok(pipeline) = compiled.result {
+                            unsafe { device.destroy_pipeline(pipeline, None); }
+                        }
+                        break;
+                    }
+                })
+                .expect("Failed to spawn pipeline compiler thread")
+        }).collect();
+        debug!(target: logging::PIPELINE, "Started {} pipeline compiler threads", worker_count);
+        AsyncPipelineCompiler {
+            device: device,
+            fallback: fallback,
+            jobs: Some(job_sender),
+            results: result_receiver,
+            workers: workers,
+            slots: Vec::new(),
+            stats: Default::default(),
+        }
+    }
+}
+
+impl<'d, D: DeviceV1_0> AsyncPipelineCompiler<'d, D> {
+    /// Queues `builder` for compilation. The shader modules, layout, and render pass it refers to
+    /// must stay alive until the pipeline is ready.
+    pub fn request(&mut self, builder: GraphicsPipelineBuilder, layout: PipelineLayout, render_pass: RenderPass, subpass: u32) -> PipelineHandle {
+        let handle = PipelineHandle(self.slots.len());
+        self.slots.push(Slot::Pending);
+        self.jobs.as_ref().unwrap().send(Job {
+            handle: handle,
+            builder: builder,
+            layout: layout,
+            render_pass: render_pass,
+            subpass: subpass,
+        }).expect("All pipeline compiler threads have exited");
+        handle
+    }
+
+    fn finish(&mut self, compiled: Compiled) {
+        let PipelineHandle(idx) = compiled.handle;
+        self.stats.total += compiled.duration;
+        if compiled.duration > self.stats.slowest {
+            self.stats.slowest = compiled.duration;
+        }
+        self.slots[idx] = match compiled.result {
+            Ok(pipeline) => {
+                self.stats.compiled += 1;
+                info!(target: logging::PIPELINE, "Compiled pipeline {} in {:.2} ms", idx, millis(compiled.duration));
+                Slot::Ready(pipeline)
+            },
+            Err(res) => {
+                self.stats.failed += 1;
+                error!(target: logging::PIPELINE, "Failed to compile pipeline {}: {:?}", idx, res);
+                Slot::Failed(res)
+            },
+        };
+    }
+
+    /// Picks up every pipeline that finished since the last call. Call once per frame.
+    pub fn poll(&mut self) {
+        while let Ok(compiled) = self.results.try_recv() {
+            self.finish(compiled);
+        }
+    }
+
+    /// Blocks until every requested pipeline has finished compiling.
+    pub fn wait_all(&mut self) {
+        while self.pending_count() > 0 {
+            let compiled = self.results.recv().expect("All pipeline compiler threads have exited");
+            self.finish(compiled);
+        }
+        let stats = self.stats;
+        info!(target: logging::PIPELINE, "Pipeline compilation: {} compiled, {} failed, {:.2} ms total, {:.2} ms slowest", stats.compiled, stats.failed, millis(stats.total), millis(stats.slowest));
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.slots.iter().filter(|slot| matches!(**slot, Slot::Pending)).count()
+    }
+
+    pub fn is_ready(&self, handle: PipelineHandle) -> bool {
+        matches!(self.slots[handle.0], Slot::Ready(_))
+    }
+
+    /// The compiled pipeline, or the fallback pipeline if it isn't ready (or failed).
+    pub fn get(&self, handle: PipelineHandle) -> Pipeline {
+        match self.slots[handle.0] {
+            Slot::Ready(pipeline) => pipeline,
+            Slot::Pending | Slot::Failed(_) => self.fallback,
+        }
+    }
+
+    pub fn error(&self, handle: PipelineHandle) -> Option<Result> {
+        match self.slots[handle.0] {
+            Slot::Failed(res) => Some(res),
+            _ => None,
+        }
+    }
+
+    pub fn stats(&self) -> CompileStats {
+        self.stats
+    }
+}
+
+/// Waits for the workers to finish whatever they are compiling and destroys every pipeline. The
+/// device must not be using any of them anymore.
+impl<'d, D: DeviceV1_0 + 'd> Drop for AsyncPipelineCompiler<'d, D> {
+    fn drop(&mut self) {
+        // Dropping the sender makes the workers' `recv` fail once the queue is empty
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!(target: logging::PIPELINE, "Pipeline compiler thread panicked");
+            }
+        }
+        while let Ok(compiled) = self.results.try_recv() {
+            self.finish(compiled);
+        }
+        for slot in self.slots.iter() {
+            if let Slot::Ready(pipeline) = *slot {
+                trace!(target: logging::PIPELINE, "Destroying pipeline: {:?}", pipeline);
+                unsafe { self.device.destroy_pipeline(pipeline, None); }
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Experimental `VK_EXT_graphics_pipeline_library` path. A pipeline is split into its four
+//! library parts (vertex input, pre-rasterization shaders, fragment shader, and fragment output)
+//! ahead of time, so that the final pipeline only has to be linked when it's first drawn.
+//!
+//! `ash` predates the extension, so its structures and flag bits are declared here. The renderer
+//! enables `EXTENSION_NAMES` along with `GraphicsPipelineLibraryFeatures::enabled()` when the
+//! Pipeline library demo is available, and the demos' mesh pipeline is linked from parts then.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use std::time::{ Duration, Instant };
+use vk::types::*;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::pnext::{ ExtensionStruct, PNextChain };
+use ::safe_create;
+
+pub const EXTENSION_NAMES: [&'static str; 2] = ["VK_KHR_pipeline_library", "VK_EXT_graphics_pipeline_library"];
+
+const STRUCTURE_TYPE_PIPELINE_LIBRARY_CREATE_INFO_KHR: u32 = 1000290000;
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_GRAPHICS_PIPELINE_LIBRARY_FEATURES_EXT: u32 = 1000320000;
+const STRUCTURE_TYPE_GRAPHICS_PIPELINE_LIBRARY_CREATE_INFO_EXT: u32 = 1000320002;
+
+const PIPELINE_CREATE_LINK_TIME_OPTIMIZATION_BIT_EXT: u32 = 0x400;
+const PIPELINE_CREATE_LIBRARY_BIT_KHR: u32 = 0x800;
+const PIPELINE_CREATE_RETAIN_LINK_TIME_OPTIMIZATION_INFO_BIT_EXT: u32 = 0x800000;
+
+const GRAPHICS_PIPELINE_LIBRARY_VERTEX_INPUT_INTERFACE_BIT_EXT: u32 = 0x1;
+const GRAPHICS_PIPELINE_LIBRARY_PRE_RASTERIZATION_SHADERS_BIT_EXT: u32 = 0x2;
+const GRAPHICS_PIPELINE_LIBRARY_FRAGMENT_SHADER_BIT_EXT: u32 = 0x4;
+const GRAPHICS_PIPELINE_LIBRARY_FRAGMENT_OUTPUT_INTERFACE_BIT_EXT: u32 = 0x8;
+
+#[repr(C)]
+pub struct GraphicsPipelineLibraryFeatures {
+    s_type: u32,
+    pub p_next: *mut c_void,
+    pub graphics_pipeline_library: Bool32,
+}
+
+unsafe impl ExtensionStruct for GraphicsPipelineLibraryFeatures {}
+
+impl GraphicsPipelineLibraryFeatures {
+    pub fn enabled() -> GraphicsPipelineLibraryFeatures {
+        GraphicsPipelineLibraryFeatures {
+            s_type: STRUCTURE_TYPE_PHYSICAL_DEVICE_GRAPHICS_PIPELINE_LIBRARY_FEATURES_EXT,
+            p_next: ptr::null_mut(),
+            graphics_pipeline_library: true as Bool32,
+        }
+    }
+}
+
+#[repr(C)]
+struct GraphicsPipelineLibraryCreateInfo {
+    s_type: u32,
+    p_next: *const c_void,
+    flags: u32,
+}
+
+unsafe impl ExtensionStruct for GraphicsPipelineLibraryCreateInfo {}
+
+#[repr(C)]
+struct PipelineLibraryCreateInfo {
+    s_type: u32,
+    p_next: *const c_void,
+    library_count: u32,
+    p_libraries: *const Pipeline,
+}
+
+unsafe impl ExtensionStruct for PipelineLibraryCreateInfo {}
+
+/// `PipelineCreateFlags` only accepts the bits that `ash` knows about, so extension bits have to
+/// go in through the raw representation.
+fn pipeline_create_flags(bits: u32) -> PipelineCreateFlags {
+    unsafe { std::mem::transmute::<u32, PipelineCreateFlags>(bits) }
+}
+
+/// Whether `enabled_extensions`, the device's, include everything this needs.
+pub fn is_enabled<'a, I: Iterator<Item = &'a str>>(enabled_extensions: I) -> bool {
+    let enabled: Vec<&str> = enabled_extensions.collect();
+    EXTENSION_NAMES.iter().all(|name| enabled.contains(name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibraryPart {
+    VertexInput,
+    PreRasterization,
+    FragmentShader,
+    FragmentOutput,
+}
+
+impl LibraryPart {
+    pub fn all() -> [LibraryPart; 4] {
+        [LibraryPart::VertexInput, LibraryPart::PreRasterization, LibraryPart::FragmentShader, LibraryPart::FragmentOutput]
+    }
+
+    fn flags(&self) -> u32 {
+        match *self {
+            LibraryPart::VertexInput => GRAPHICS_PIPELINE_LIBRARY_VERTEX_INPUT_INTERFACE_BIT_EXT,
+            LibraryPart::PreRasterization => GRAPHICS_PIPELINE_LIBRARY_PRE_RASTERIZATION_SHADERS_BIT_EXT,
+            LibraryPart::FragmentShader => GRAPHICS_PIPELINE_LIBRARY_FRAGMENT_SHADER_BIT_EXT,
+            LibraryPart::FragmentOutput => GRAPHICS_PIPELINE_LIBRARY_FRAGMENT_OUTPUT_INTERFACE_BIT_EXT,
+        }
+    }
+
+    /// Whether shaders of `stage` belong in this part.
+    fn has_stage(&self, stage: ShaderStageFlags) -> bool {
+        match *self {
+            LibraryPart::VertexInput | LibraryPart::FragmentOutput => false,
+            LibraryPart::PreRasterization => !stage.subset(SHADER_STAGE_FRAGMENT_BIT),
+            LibraryPart::FragmentShader => stage.subset(SHADER_STAGE_FRAGMENT_BIT),
+        }
+    }
+}
+
+/// The four prebuilt parts of one pipeline.
+pub struct PipelineLibrary<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    parts: [Pipeline; 4],
+}
+
+impl<'d, D: DeviceV1_0> PipelineLibrary<'d, D> {
+    pub fn new(device: &'d D, pipeline_cache: PipelineCache, builder: &GraphicsPipelineBuilder, layout: PipelineLayout, render_pass: RenderPass, subpass: u32) -> VkResult<PipelineLibrary<'d, D>> {
+        let mut ret = PipelineLibrary {
+            device: device,
+            parts: [Pipeline::null(); 4],
+        };
+        for (idx, &part) in LibraryPart::all().iter().enumerate() {
+            ret.parts[idx] = builder.with_create_info(layout, render_pass, subpass, |mut create_info| {
+                let stages: Vec<PipelineShaderStageCreateInfo> = (0..create_info.stage_count as usize)
+                    .map(|idx| unsafe { &*create_info.p_stages.add(idx) })
+                    .filter(|stage| part.has_stage(stage.stage))
+                    .cloned()
+                    .collect();
+                let mut library_info = GraphicsPipelineLibraryCreateInfo {
+                    s_type: STRUCTURE_TYPE_GRAPHICS_PIPELINE_LIBRARY_CREATE_INFO_EXT,
+                    p_next: ptr::null(),
+                    flags: part.flags(),
+                };
+                let next = PNextChain::new().push(&mut library_info);
+                create_info.p_next = next.head();
+                create_info.flags = pipeline_create_flags(PIPELINE_CREATE_LIBRARY_BIT_KHR | PIPELINE_CREATE_RETAIN_LINK_TIME_OPTIMIZATION_INFO_BIT_EXT);
+                create_info.stage_count = stages.len() as u32;
+                create_info.p_stages = stages.as_ptr();
+                match unsafe { device.create_graphics_pipelines(pipeline_cache, &[create_info], None) } {
+                    Ok(pipelines) => Ok(pipelines[0]),
+                    Err((_, res)) => Err(res),
+                }
+            })?;
+            trace!("Created {:?} pipeline library: {:?}", part, ret.parts[idx]);
+        }
+        Ok(ret)
+    }
+
+    /// Links the parts into a complete pipeline. Without `optimize` this should be fast enough to
+    /// do at draw time; with it, the driver may take about as long as a monolithic creation.
+    pub fn link(&self, pipeline_cache: PipelineCache, layout: PipelineLayout, optimize: bool) -> VkResult<safe_create::Owned<'d, D, Pipeline>> {
+        let mut library_info = PipelineLibraryCreateInfo {
+            s_type: STRUCTURE_TYPE_PIPELINE_LIBRARY_CREATE_INFO_KHR,
+            p_next: ptr::null(),
+            library_count: self.parts.len() as u32,
+            p_libraries: self.parts.as_ptr(),
+        };
+        let next = PNextChain::new().push(&mut library_info);
+        let create_info = GraphicsPipelineCreateInfo {
+            s_type: StructureType::GraphicsPipelineCreateInfo,
+            p_next: next.head(),
+            flags: pipeline_create_flags(if optimize { PIPELINE_CREATE_LINK_TIME_OPTIMIZATION_BIT_EXT } else { 0 }),
+            stage_count: 0,
+            p_stages: ptr::null(),
+            p_vertex_input_state: ptr::null(),
+            p_input_assembly_state: ptr::null(),
+            p_tessellation_state: ptr::null(),
+            p_viewport_state: ptr::null(),
+            p_rasterization_state: ptr::null(),
+            p_multisample_state: ptr::null(),
+            p_depth_stencil_state: ptr::null(),
+            p_color_blend_state: ptr::null(),
+            p_dynamic_state: ptr::null(),
+            layout: layout,
+            render_pass: RenderPass::null(),
+            subpass: 0,
+            base_pipeline_handle: Pipeline::null(),
+            base_pipeline_index: -1,
+        };
+        safe_create::create_graphics_pipelines_safe(self.device, &pipeline_cache, &[create_info], None)
+            .map_err(|(_, res)| res)
+            .map(|pipelines| pipelines.into_iter()
+                 .next()
+                 .expect("Expected successful linking of a graphics pipeline to actually give us a graphics pipeline"))
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for PipelineLibrary<'d, D> {
+    fn drop(&mut self) {
+        for &part in self.parts.iter() {
+            unsafe { self.device.destroy_pipeline(part, None); }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LinkTimings {
+    pub monolithic: Duration,
+    pub libraries: Duration,
+    pub fast_link: Duration,
+    pub optimized_link: Duration,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1000000.0
+}
+
+/// Creates the same pipeline monolithically and through libraries, and logs how long each step
+/// took. Pass a null `pipeline_cache` for a fair comparison, otherwise later creations are cache
+/// hits.
+pub fn compare_link_cost<D: DeviceV1_0>(device: &D, pipeline_cache: PipelineCache, builder: &GraphicsPipelineBuilder, layout: PipelineLayout, render_pass: RenderPass, subpass: u32) -> VkResult<LinkTimings> {
+    let start = Instant::now();
+    builder.build_with_cache(device, pipeline_cache, layout, render_pass, subpass)?;
+    let monolithic = start.elapsed();
+
+    let start = Instant::now();
+    let library = PipelineLibrary::new(device, pipeline_cache, builder, layout, render_pass, subpass)?;
+    let libraries = start.elapsed();
+
+    let start = Instant::now();
+    library.link(pipeline_cache, layout, false)?;
+    let fast_link = start.elapsed();
+
+    let start = Instant::now();
+    library.link(pipeline_cache, layout, true)?;
+    let optimized_link = start.elapsed();
+
+    info!("Pipeline creation: monolithic {:.2} ms, libraries {:.2} ms, fast link {:.2} ms, optimized link {:.2} ms",
+          millis(monolithic), millis(libraries), millis(fast_link), millis(optimized_link));
+    Ok(LinkTimings {
+        monolithic: monolithic,
+        libraries: libraries,
+        fast_link: fast_link,
+        optimized_link: optimized_link,
+    })
+}
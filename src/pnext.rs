@@ -0,0 +1,63 @@
+//! `p_next` chains without hand-cast pointers. A chain borrows every structure pushed onto it, so
+//! none of them can be moved or dropped while the chain is still in use, and only structures
+//! marked `ExtensionStruct` can be pushed at all.
+use std::marker::PhantomData;
+use std::ptr;
+use vk::types::*;
+
+/// A Vulkan structure that can go on a `p_next` chain: `repr(C)`, and starting with `s_type` and
+/// `p_next` like every extension structure does.
+///
+/// # Safety
+///
+/// Implementing this promises that layout.
+pub unsafe trait ExtensionStruct {}
+
+#[repr(C)]
+struct BaseOut {
+    s_type: u32,
+    p_next: *mut c_void,
+}
+
+/// The extension structures for one `p_next`, all borrowed for `'a`.
+pub struct PNextChain<'a> {
+    head: *mut c_void,
+    phantom: PhantomData<&'a mut c_void>,
+}
+
+impl<'a> PNextChain<'a> {
+    pub fn new() -> PNextChain<'a> {
+        PNextChain {
+            head: ptr::null_mut(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Puts `next` at the front of the chain. Whatever `next`'s own `p_next` pointed at before is
+    /// replaced.
+    pub fn push<T: ExtensionStruct>(mut self, next: &'a mut T) -> PNextChain<'a> {
+        let base = next as *mut T as *mut BaseOut;
+        unsafe { (*base).p_next = self.head };
+        self.head = base as *mut c_void;
+        self
+    }
+
+    /// Pushes `next` if there is one, for structures that are only chained sometimes.
+    pub fn push_optional<T: ExtensionStruct>(self, next: Option<&'a mut T>) -> PNextChain<'a> {
+        match next {
+            Some(next) => self.push(next),
+            None => self,
+        }
+    }
+
+    /// For the `p_next` of a structure Vulkan reads. Null for an empty chain.
+    pub fn head(&self) -> *const c_void {
+        self.head
+    }
+
+    /// For the `p_next` of a structure Vulkan writes into, like the `*Properties2` queries. The
+    /// results can be read from the pushed structures once the chain is no longer used.
+    pub fn head_mut(&mut self) -> *mut c_void {
+        self.head
+    }
+}
@@ -0,0 +1,381 @@
+//! PNG decoding to RGBA8, and encoding from it. Every color type and bit depth is supported except
+//! interlaced images; 16-bit channels are cut down to their high byte. Encoded images aren't
+//! compressed, which is fine for captures that get looked at and thrown away.
+use std;
+use ::image::{ ImageData, ImageError };
+use ::inflate;
+use ::math::prelude::div_ceil;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+const COLOR_GRAY: u8 = 0;
+const COLOR_RGB: u8 = 2;
+const COLOR_PALETTE: u8 = 3;
+const COLOR_GRAY_ALPHA: u8 = 4;
+const COLOR_RGBA: u8 = 6;
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            COLOR_RGB => 3,
+            COLOR_GRAY_ALPHA => 2,
+            COLOR_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in a scanline, not counting the filter type.
+    fn stride(&self) -> usize {
+        div_ceil(self.width as usize * self.bits_per_pixel(), 8)
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+fn corrupt(what: &str) -> ImageError {
+    ImageError::Corrupt(format!("PNG {}", what))
+}
+
+fn parse_header(data: &[u8]) -> std::result::Result<Header, ImageError> {
+    if data.len() != 13 {
+        return Err(corrupt("header has the wrong size"));
+    }
+    let header = Header {
+        width: be_u32(&data[0..4]),
+        height: be_u32(&data[4..8]),
+        bit_depth: data[8],
+        color_type: data[9],
+        interlaced: data[12] != 0,
+    };
+    let valid_depth = match header.color_type {
+        COLOR_GRAY => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+        COLOR_PALETTE => [1, 2, 4, 8].contains(&header.bit_depth),
+        COLOR_RGB | COLOR_GRAY_ALPHA | COLOR_RGBA => [8, 16].contains(&header.bit_depth),
+        _ => return Err(corrupt(&format!("color type {} is invalid", header.color_type))),
+    };
+    if !valid_depth {
+        return Err(corrupt(&format!("bit depth {} is invalid for color type {}", header.bit_depth, header.color_type)));
+    }
+    if header.width == 0 || header.height == 0 {
+        return Err(corrupt("image is empty"));
+    }
+    if header.interlaced {
+        return Err(ImageError::Unsupported("interlaced PNGs".to_string()));
+    }
+    Ok(header)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undoes the per-scanline filters, returning the scanlines without their filter types.
+fn unfilter(header: &Header, data: &[u8]) -> std::result::Result<Vec<u8>, ImageError> {
+    let stride = header.stride();
+    let height = header.height as usize;
+    if data.len() < (stride + 1) * height {
+        return Err(corrupt("image data is truncated"));
+    }
+    // Filters work on whole bytes, with the previous pixel at least a byte back
+    let bpp = std::cmp::max(header.bits_per_pixel() / 8, 1);
+    let mut out = vec![0u8; stride * height];
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let src = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (above, current) = out.split_at_mut(y * stride);
+        let above = if y > 0 { &above[(y - 1) * stride..] } else { &[][..] };
+        let current = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= bpp { current[x - bpp] } else { 0 };
+            let b = if y > 0 { above[x] } else { 0 };
+            let c = if x >= bpp && y > 0 { above[x - bpp] } else { 0 };
+            current[x] = match filter {
+                0 => src[x],
+                1 => src[x].wrapping_add(a),
+                2 => src[x].wrapping_add(b),
+                3 => src[x].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => src[x].wrapping_add(paeth(a, b, c)),
+                _ => return Err(corrupt(&format!("filter type {} is invalid", filter))),
+            };
+        }
+    }
+    Ok(out)
+}
+
+/// Sample `idx` of a scanline, scaled to 8 bits.
+fn sample(row: &[u8], bit_depth: u8, idx: usize) -> u8 {
+    match bit_depth {
+        16 => row[idx * 2],
+        8 => row[idx],
+        _ => {
+            let per_byte = 8 / bit_depth as usize;
+            let shift = 8 - bit_depth as usize * (idx % per_byte + 1);
+            let value = (row[idx / per_byte] >> shift) & ((1 << bit_depth) - 1);
+            (value as u32 * 255 / ((1 << bit_depth) - 1)) as u8
+        },
+    }
+}
+
+/// Like `sample`, but without scaling, for palette indices.
+fn index(row: &[u8], bit_depth: u8, idx: usize) -> usize {
+    if bit_depth == 8 {
+        return row[idx] as usize;
+    }
+    let per_byte = 8 / bit_depth as usize;
+    let shift = 8 - bit_depth as usize * (idx % per_byte + 1);
+    ((row[idx / per_byte] >> shift) & ((1 << bit_depth) - 1)) as usize
+}
+
+pub fn decode(data: &[u8]) -> std::result::Result<ImageData, ImageError> {
+    if data.len() < SIGNATURE.len() || data[..SIGNATURE.len()] != SIGNATURE {
+        return Err(corrupt("signature is missing"));
+    }
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    // A color key for images without an alpha channel, as raw samples
+    let mut transparent: Option<Vec<u16>> = None;
+    let mut compressed = Vec::new();
+    let mut pos = SIGNATURE.len();
+    loop {
+        if pos + 8 > data.len() {
+            return Err(corrupt("ends without an IEND chunk"));
+        }
+        let length = be_u32(&data[pos..pos + 4]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data.get(pos + 8..pos + 8 + length).ok_or(corrupt("chunk is truncated"))?;
+        // Skipping the CRC too
+        pos += 12 + length;
+        match kind {
+            b"IHDR" => header = Some(parse_header(body)?),
+            b"PLTE" => palette = body.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+            b"tRNS" => match header.map(|header| header.color_type) {
+                Some(COLOR_PALETTE) => for (entry, &alpha) in palette.iter_mut().zip(body.iter()) {
+                    entry[3] = alpha;
+                },
+                Some(COLOR_GRAY) | Some(COLOR_RGB) => transparent = Some(body.chunks(2).map(|sample| (sample[0] as u16) << 8 | sample[1] as u16).collect()),
+                _ => {},
+            },
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {
+                // Uppercase first letters mark chunks a decoder has to understand
+                if kind[0] & 0x20 == 0 {
+                    return Err(ImageError::Unsupported(format!("PNG chunk {:?}", String::from_utf8_lossy(kind))));
+                }
+            },
+        }
+    }
+    let header = header.ok_or(corrupt("header is missing"))?;
+    let raw = inflate::zlib_decompress(&compressed).map_err(|e| corrupt(&e))?;
+    let scanlines = unfilter(&header, &raw)?;
+
+    let (width, height) = (header.width as usize, header.height as usize);
+    let stride = header.stride();
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for row in scanlines.chunks(stride) {
+        for x in 0..width {
+            let rgba = match header.color_type {
+                COLOR_GRAY => {
+                    let gray = sample(row, header.bit_depth, x);
+                    [gray, gray, gray, 255]
+                },
+                COLOR_RGB => [sample(row, header.bit_depth, x * 3), sample(row, header.bit_depth, x * 3 + 1), sample(row, header.bit_depth, x * 3 + 2), 255],
+                COLOR_PALETTE => *palette.get(index(row, header.bit_depth, x)).ok_or(corrupt("palette index is out of range"))?,
+                COLOR_GRAY_ALPHA => {
+                    let gray = sample(row, header.bit_depth, x * 2);
+                    [gray, gray, gray, sample(row, header.bit_depth, x * 2 + 1)]
+                },
+                _ => [sample(row, header.bit_depth, x * 4), sample(row, header.bit_depth, x * 4 + 1), sample(row, header.bit_depth, x * 4 + 2), sample(row, header.bit_depth, x * 4 + 3)],
+            };
+            pixels.extend_from_slice(&rgba);
+        }
+    }
+    if let Some(key) = transparent {
+        apply_color_key(&header, &scanlines, &key, &mut pixels);
+    }
+    Ok(ImageData {
+        width: header.width,
+        height: header.height,
+        pixels: pixels,
+    })
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    let length = body.len() as u32;
+    out.extend_from_slice(&[(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8]);
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&[(crc >> 24) as u8, (crc >> 16) as u8, (crc >> 8) as u8, crc as u8]);
+}
+
+/// Encodes `image` as an 8-bit RGBA PNG.
+pub fn encode(image: &ImageData) -> Vec<u8> {
+    let stride = image.width as usize * 4;
+    assert_eq!(image.pixels.len(), stride * image.height as usize);
+    let mut scanlines = Vec::with_capacity((stride + 1) * image.height as usize);
+    for row in image.pixels.chunks(stride) {
+        // No filtering
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+    let (w, h) = (image.width, image.height);
+    let header = [
+        (w >> 24) as u8, (w >> 16) as u8, (w >> 8) as u8, w as u8,
+        (h >> 24) as u8, (h >> 16) as u8, (h >> 8) as u8, h as u8,
+        8, COLOR_RGBA, 0, 0, 0,
+    ];
+    let mut out = SIGNATURE.to_vec();
+    push_chunk(&mut out, b"IHDR", &header);
+    push_chunk(&mut out, b"IDAT", &inflate::zlib_stored(&scanlines));
+    push_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Clears the alpha of pixels whose raw samples match `key`, from a `tRNS` chunk.
+fn apply_color_key(header: &Header, scanlines: &[u8], key: &[u16], pixels: &mut [u8]) {
+    let channels = header.channels();
+    if key.len() != channels {
+        return;
+    }
+    let raw_sample = |row: &[u8], idx: usize| -> u16 {
+        match header.bit_depth {
+            16 => (row[idx * 2] as u16) << 8 | row[idx * 2 + 1] as u16,
+            _ => index(row, header.bit_depth, idx) as u16,
+        }
+    };
+    for (y, row) in scanlines.chunks(header.stride()).enumerate() {
+        for x in 0..header.width as usize {
+            if (0..channels).all(|channel| raw_sample(row, x * channels + channel) == key[channel]) {
+                pixels[(y * header.width as usize + x) * 4 + 3] = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32, bit_depth: u8, color_type: u8, extra: &[(&[u8; 4], &[u8])], scanlines: &[u8]) -> Vec<u8> {
+        let (w, h) = (width, height);
+        let header = [
+            (w >> 24) as u8, (w >> 16) as u8, (w >> 8) as u8, w as u8,
+            (h >> 24) as u8, (h >> 16) as u8, (h >> 8) as u8, h as u8,
+            bit_depth, color_type, 0, 0, 0,
+        ];
+        let mut out = SIGNATURE.to_vec();
+        push_chunk(&mut out, b"IHDR", &header);
+        for &(kind, body) in extra.iter() {
+            push_chunk(&mut out, kind, body);
+        }
+        push_chunk(&mut out, b"IDAT", &inflate::zlib_stored(scanlines));
+        push_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = ImageData::checkerboard(16, 4);
+        let decoded = decode(&encode(&image)).unwrap();
+        assert_eq!((decoded.width, decoded.height), (16, 16));
+        assert_eq!(decoded.pixels, image.pixels);
+    }
+
+    #[test]
+    fn low_bit_depth_gray_is_scaled_to_8_bits() {
+        // 2 bits per pixel: 0, 1, 2, 3
+        let data = png(4, 1, 2, COLOR_GRAY, &[], &[0, 0b00_01_10_11]);
+        let image = decode(&data).unwrap();
+        let grays: Vec<u8> = image.pixels.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(grays, vec![0, 85, 170, 255]);
+    }
+
+    #[test]
+    fn palette_with_transparency() {
+        let palette = [255, 0, 0, 0, 255, 0];
+        let data = png(2, 1, 8, COLOR_PALETTE, &[(b"PLTE", &palette), (b"tRNS", &[128])], &[0, 1, 0]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.pixels, vec![0, 255, 0, 255, 255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn color_key_clears_alpha() {
+        let data = png(2, 1, 8, COLOR_RGB, &[(b"tRNS", &[0, 1, 0, 2, 0, 3])], &[0, 1, 2, 3, 4, 5, 6]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.pixels, vec![1, 2, 3, 0, 4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn every_filter_type() {
+        // Gray 8-bit, 3x5, one row per filter, all decoding to 10, 20, 30 on the first row and
+        // adding 1 per row after
+        let scanlines = [
+            0, 10, 20, 30,
+            1, 11, 10, 10,
+            2, 1, 1, 1,
+            3, 7, 6, 6,
+            4, 1, 1, 1,
+        ];
+        let image = decode(&png(3, 5, 8, COLOR_GRAY, &[], &scanlines)).unwrap();
+        let grays: Vec<u8> = image.pixels.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(&grays[..3], &[10, 20, 30]);
+        assert_eq!(&grays[3..6], &[11, 21, 31]);
+        assert_eq!(&grays[6..9], &[12, 22, 32]);
+        assert_eq!(&grays[9..12], &[13, 23, 33]);
+        assert_eq!(&grays[12..15], &[14, 24, 34]);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(decode(b"not a png").is_err());
+        let mut data = encode(&ImageData::checkerboard(4, 2));
+        // Drop IEND
+        let len = data.len();
+        data.truncate(len - 12);
+        assert!(decode(&data).is_err());
+        assert!(decode(&png(1, 1, 3, COLOR_RGB, &[], &[0, 0, 0, 0])).is_err());
+        // The header's interlace method, past the signature and the chunk's length and type
+        let mut interlaced = png(1, 1, 8, COLOR_GRAY, &[], &[0, 0]);
+        interlaced[8 + 8 + 12] = 1;
+        match decode(&interlaced) {
+            Err(ImageError::Unsupported(_)) => {},
+            other => panic!("expected an unsupported error, got {:?}", other.map(|image| image.pixels)),
+        }
+    }
+}
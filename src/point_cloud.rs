@@ -0,0 +1,646 @@
+//! Large point clouds, loaded from PLY or LAS files and drawn either as hardware points or as
+//! instanced screen-space quads. Points are reordered into spatially coherent chunks at load
+//! time, so each chunk is a contiguous range of the vertex buffer with its own bounds, and
+//! `PointCloud::cmd_draw` skips the chunks outside the view frustum.
+//!
+//! Only what point clouds in the wild need is read: positions and 8- or 16-bit colors from
+//! ASCII or little-endian PLY, and LAS point formats 0 to 3 and 5, with other properties ignored.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::fs;
+use std::io::{ self, Read };
+use std::path::Path;
+use std::ptr;
+use vk::types::*;
+use ::math::{ Aabb, Frustum, Mat4, Vec3 };
+use ::math::prelude::clamp;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::safe_create::{ self, Owned };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const POINT_VERTEX_SHADER: &'static str = "shaders/point_cloud_vertex.vert.spv";
+pub const POINT_FRAGMENT_SHADER: &'static str = "shaders/point_cloud_fragment.frag.spv";
+pub const SPLAT_VERTEX_SHADER: &'static str = "shaders/point_splat_vertex.vert.spv";
+pub const SPLAT_FRAGMENT_SHADER: &'static str = "shaders/point_splat_fragment.frag.spv";
+
+/// Most points in a chunk. Smaller chunks cull tighter but cost more draw calls.
+pub const CHUNK_POINTS: usize = 64 * 1024;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointVertex {
+    pub position: [f32; 3],
+    pub color: [u8; 4],
+}
+
+impl PointVertex {
+    fn position(&self) -> Vec3 {
+        Vec3::new(self.position[0], self.position[1], self.position[2])
+    }
+}
+
+fn invalid_data<T>(message: String) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<PlyType> {
+        match name {
+            "char" | "int8" => Some(PlyType::Int8),
+            "uchar" | "uint8" => Some(PlyType::Uint8),
+            "short" | "int16" => Some(PlyType::Int16),
+            "ushort" | "uint16" => Some(PlyType::Uint16),
+            "int" | "int32" => Some(PlyType::Int32),
+            "uint" | "uint32" => Some(PlyType::Uint32),
+            "float" | "float32" => Some(PlyType::Float32),
+            "double" | "float64" => Some(PlyType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match *self {
+            PlyType::Int8 | PlyType::Uint8 => 1,
+            PlyType::Int16 | PlyType::Uint16 => 2,
+            PlyType::Int32 | PlyType::Uint32 | PlyType::Float32 => 4,
+            PlyType::Float64 => 8,
+        }
+    }
+
+    /// Full-intensity value of a color channel stored as this type.
+    fn color_scale(&self) -> f64 {
+        match *self {
+            PlyType::Uint16 | PlyType::Int16 => 65535.0,
+            PlyType::Float32 | PlyType::Float64 => 1.0,
+            _ => 255.0,
+        }
+    }
+
+    fn read_le(&self, bytes: &[u8]) -> f64 {
+        let unsigned = bytes.iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        match *self {
+            PlyType::Int8 => unsigned as u8 as i8 as f64,
+            PlyType::Uint8 => unsigned as u8 as f64,
+            PlyType::Int16 => unsigned as u16 as i16 as f64,
+            PlyType::Uint16 => unsigned as u16 as f64,
+            PlyType::Int32 => unsigned as u32 as i32 as f64,
+            PlyType::Uint32 => unsigned as u32 as f64,
+            PlyType::Float32 => f32::from_bits(unsigned as u32) as f64,
+            PlyType::Float64 => f64::from_bits(unsigned),
+        }
+    }
+}
+
+fn color_channel(value: f64, scale: f64) -> u8 {
+    clamp(value / scale * 255.0, 0.0, 255.0).round() as u8
+}
+
+/// Reads the vertices of a PLY file, which must be the file's first element.
+pub fn parse_ply(data: &[u8]) -> io::Result<Vec<PointVertex>> {
+    let header_end = match data.windows(11).position(|w| w == b"end_header\n") {
+        Some(p) => p + 11,
+        None => return invalid_data("PLY header has no end_header".to_string()),
+    };
+    let header = String::from_utf8_lossy(&data[..header_end]);
+    let mut lines = header.lines();
+    if lines.next().map(|l| l.trim()) != Some("ply") {
+        return invalid_data("Not a PLY file".to_string());
+    }
+    let mut binary = false;
+    let mut vertex_count = None;
+    let mut properties: Vec<(String, PlyType)> = Vec::new();
+    let mut in_vertex = false;
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("format") => match words.get(1).copied() {
+                Some("ascii") => binary = false,
+                Some("binary_little_endian") => binary = true,
+                other => return invalid_data(format!("Unsupported PLY format: {:?}", other)),
+            },
+            Some("element") => {
+                if vertex_count.is_none() && words.get(1) != Some(&"vertex") {
+                    return invalid_data("PLY vertex element must come first".to_string());
+                }
+                in_vertex = vertex_count.is_none();
+                if in_vertex {
+                    vertex_count = words.get(2).and_then(|c| c.parse::<usize>().ok());
+                    if vertex_count.is_none() {
+                        return invalid_data(format!("Bad PLY element: {}", line));
+                    }
+                }
+            },
+            Some("property") if in_vertex => {
+                if words.get(1) == Some(&"list") {
+                    return invalid_data("PLY vertices can't have list properties".to_string());
+                }
+                match (words.get(1).and_then(|t| PlyType::parse(t)), words.get(2)) {
+                    (Some(ty), Some(name)) => properties.push((name.to_string(), ty)),
+                    _ => return invalid_data(format!("Bad PLY property: {}", line)),
+                }
+            },
+            _ => {},
+        }
+    }
+    let vertex_count = vertex_count.unwrap_or(0);
+    let find = |name: &str| properties.iter().position(|(n, _)| n == name);
+    let (x, y, z) = match (find("x"), find("y"), find("z")) {
+        (Some(x), Some(y), Some(z)) => (x, y, z),
+        _ => return invalid_data("PLY vertices have no position".to_string()),
+    };
+    let rgb = match (find("red"), find("green"), find("blue")) {
+        (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+        _ => None,
+    };
+    let scales: Vec<f64> = properties.iter().map(|&(_, ty)| ty.color_scale()).collect();
+    let to_point = |values: &[f64]| PointVertex {
+        position: [values[x] as f32, values[y] as f32, values[z] as f32],
+        color: match rgb {
+            Some(c) => [color_channel(values[c[0]], scales[c[0]]), color_channel(values[c[1]], scales[c[1]]), color_channel(values[c[2]], scales[c[2]]), 255],
+            None => [255, 255, 255, 255],
+        },
+    };
+
+    let body = &data[header_end..];
+    let mut points = Vec::with_capacity(vertex_count);
+    let mut values = vec![0.0; properties.len()];
+    if binary {
+        let stride: usize = properties.iter().map(|&(_, ty)| ty.size()).sum();
+        if body.len() < stride * vertex_count {
+            return invalid_data(format!("PLY file ends before its {} vertices", vertex_count));
+        }
+        for record in body.chunks(stride).take(vertex_count) {
+            let mut offset = 0;
+            for (value, &(_, ty)) in values.iter_mut().zip(properties.iter()) {
+                *value = ty.read_le(&record[offset..offset + ty.size()]);
+                offset += ty.size();
+            }
+            points.push(to_point(&values));
+        }
+    } else {
+        let text = String::from_utf8_lossy(body);
+        for line in text.lines().filter(|l| !l.trim().is_empty()).take(vertex_count) {
+            for (value, word) in values.iter_mut().zip(line.split_whitespace()) {
+                *value = match word.parse() {
+                    Ok(v) => v,
+                    Err(_) => return invalid_data(format!("Bad PLY vertex: {}", line)),
+                };
+            }
+            points.push(to_point(&values));
+        }
+        if points.len() < vertex_count {
+            return invalid_data(format!("PLY file ends before its {} vertices", vertex_count));
+        }
+    }
+    Ok(points)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    PlyType::Uint16.read_le(&data[offset..offset + 2]) as u16
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    PlyType::Uint32.read_le(&data[offset..offset + 4]) as u32
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    PlyType::Int32.read_le(&data[offset..offset + 4]) as i32
+}
+
+fn read_f64(data: &[u8], offset: usize) -> f64 {
+    PlyType::Float64.read_le(&data[offset..offset + 8])
+}
+
+/// Reads the points of a LAS file. Coordinates are recentered on the header's offset, since
+/// geo-referenced coordinates are far too large for `f32`.
+pub fn parse_las(data: &[u8]) -> io::Result<Vec<PointVertex>> {
+    if data.len() < 227 || &data[0..4] != b"LASF" {
+        return invalid_data("Not a LAS file".to_string());
+    }
+    let point_offset = read_u32(data, 96) as usize;
+    let format = data[104] & 0x3f;
+    let record_length = read_u16(data, 105) as usize;
+    let count = read_u32(data, 107) as usize;
+    let scale = [read_f64(data, 131), read_f64(data, 139), read_f64(data, 147)];
+    let color_offset = match format {
+        0 | 1 => None,
+        2 => Some(20),
+        3 | 5 => Some(28),
+        _ => return invalid_data(format!("Unsupported LAS point format {}", format)),
+    };
+    if record_length < color_offset.map(|o| o + 6).unwrap_or(12) || data.len() < point_offset + record_length * count {
+        return invalid_data(format!("LAS file ends before its {} points", count));
+    }
+    let mut points = Vec::with_capacity(count);
+    for record in data[point_offset..].chunks(record_length).take(count) {
+        let position = [
+            (read_i32(record, 0) as f64 * scale[0]) as f32,
+            // LAS is Z up
+            (read_i32(record, 8) as f64 * scale[2]) as f32,
+            -(read_i32(record, 4) as f64 * scale[1]) as f32,
+        ];
+        let color = match color_offset {
+            Some(o) => [(read_u16(record, o) >> 8) as u8, (read_u16(record, o + 2) >> 8) as u8, (read_u16(record, o + 4) >> 8) as u8, 255],
+            None => [255, 255, 255, 255],
+        };
+        points.push(PointVertex {
+            position: position,
+            color: color,
+        });
+    }
+    Ok(points)
+}
+
+/// Loads a `.ply` or `.las` file by extension.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<PointVertex>> {
+    let path = path.as_ref();
+    let mut data = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut data)?;
+    let points = match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+        Some(ref e) if e == "ply" => parse_ply(&data)?,
+        Some(ref e) if e == "las" => parse_las(&data)?,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown point cloud format: {}", path.display()))),
+    };
+    info!("Loaded {} points from {}", points.len(), path.display());
+    Ok(points)
+}
+
+/// Rolling terrain colored by height, for trying out large counts without a file.
+pub fn synthetic_terrain(count: u32, size: f32) -> Vec<PointVertex> {
+    let side = (count as f32).sqrt().ceil() as u32;
+    (0..count).map(|i| {
+        let u = (i % side) as f32 / side as f32 - 0.5;
+        let v = (i / side) as f32 / side as f32 - 0.5;
+        let (x, z) = (u * size, v * size);
+        let h = ((x * 0.3).sin() * (z * 0.2).cos() + (x * 0.05 + z * 0.07).sin() * 3.0) * size * 0.01;
+        let t = clamp(h / (size * 0.04) + 0.5, 0.0, 1.0);
+        PointVertex {
+            position: [x, h, z],
+            color: [(80.0 + 175.0 * t) as u8, (140.0 + 60.0 * t) as u8, (60.0 + 180.0 * t) as u8, 255],
+        }
+    }).collect()
+}
+
+/// A contiguous range of the point buffer and its bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointChunk {
+    pub bounds: Aabb,
+    pub first: u32,
+    pub count: u32,
+}
+
+fn bounds_of(points: &[PointVertex]) -> Aabb {
+    let first = points[0].position();
+    points.iter().fold(Aabb { min: first, max: first }, |aabb, p| Aabb {
+        min: Vec3::new(aabb.min.x.min(p.position[0]), aabb.min.y.min(p.position[1]), aabb.min.z.min(p.position[2])),
+        max: Vec3::new(aabb.max.x.max(p.position[0]), aabb.max.y.max(p.position[1]), aabb.max.z.max(p.position[2])),
+    })
+}
+
+/// Reorders `points` into chunks of at most `max_points` by recursively splitting along the
+/// longest axis at the median, so chunks stay compact even for flat scans.
+pub fn build_chunks(points: &mut [PointVertex], max_points: usize) -> Vec<PointChunk> {
+    let mut chunks = Vec::new();
+    if !points.is_empty() {
+        split_chunks(points, 0, max_points, &mut chunks);
+    }
+    chunks
+}
+
+fn split_chunks(points: &mut [PointVertex], first: usize, max_points: usize, chunks: &mut Vec<PointChunk>) {
+    let bounds = bounds_of(points);
+    if points.len() <= max_points {
+        chunks.push(PointChunk {
+            bounds: bounds,
+            first: first as u32,
+            count: points.len() as u32,
+        });
+        return;
+    }
+    let extents = bounds.extents();
+    let axis = if extents.x >= extents.y && extents.x >= extents.z { 0 } else if extents.y >= extents.z { 1 } else { 2 };
+    points.sort_by(|a, b| a.position[axis].partial_cmp(&b.position[axis]).unwrap_or(std::cmp::Ordering::Equal));
+    let middle = points.len() / 2;
+    let (left, right) = points.split_at_mut(middle);
+    split_chunks(left, first, max_points, chunks);
+    split_chunks(right, first + middle, max_points, chunks);
+}
+
+/// Push constant block read by `shaders/point_cloud_vertex.glsl` and
+/// `shaders/point_splat_vertex.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PointCloudPushConstants {
+    pub view_projection: Mat4,
+    /// Diameter in pixels. Hardware points other than 1 need the `largePoints` device feature.
+    pub point_size: f32,
+    pub inverse_width: f32,
+    pub inverse_height: f32,
+}
+
+impl PointCloudPushConstants {
+    pub fn new(view_projection: Mat4, point_size: f32, extent: &Extent2D) -> PointCloudPushConstants {
+        PointCloudPushConstants {
+            view_projection: view_projection,
+            point_size: point_size,
+            inverse_width: 1.0 / extent.width as f32,
+            inverse_height: 1.0 / extent.height as f32,
+        }
+    }
+
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT,
+            offset: 0,
+            size: std::mem::size_of::<PointCloudPushConstants>() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointMode {
+    /// One `PointList` vertex per point, sized by `gl_PointSize`.
+    Points,
+    /// An instanced quad per point, which doesn't depend on the device's point size limits and
+    /// can be shaded round.
+    Splats,
+}
+
+impl PointMode {
+    pub fn toggle(&self) -> PointMode {
+        match *self {
+            PointMode::Points => PointMode::Splats,
+            PointMode::Splats => PointMode::Points,
+        }
+    }
+
+    fn binding_descriptions(&self) -> [VertexInputBindingDescription; 1] {
+        [VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<PointVertex>() as u32,
+            input_rate: match *self {
+                PointMode::Points => VertexInputRate::Vertex,
+                PointMode::Splats => VertexInputRate::Instance,
+            },
+        }]
+    }
+
+    fn attribute_descriptions() -> [VertexInputAttributeDescription; 2] {
+        [
+            VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: Format::R32g32b32Sfloat,
+                offset: 0,
+            },
+            VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: Format::R8g8b8a8Unorm,
+                offset: 12,
+            },
+        ]
+    }
+
+    /// Point cloud pipeline with depth testing. The shaders must match the mode:
+    /// `point_cloud_vertex.glsl` and `point_cloud_fragment.glsl` for points,
+    /// `point_splat_vertex.glsl` and `point_splat_fragment.glsl` for splats.
+    pub fn pipeline_builder(&self, extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+        GraphicsPipelineBuilder::new(extent)
+            .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+            .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+            .vertex_input(&self.binding_descriptions(), &PointMode::attribute_descriptions())
+            .topology(match *self {
+                PointMode::Points => PrimitiveTopology::PointList,
+                PointMode::Splats => PrimitiveTopology::TriangleStrip,
+            })
+            .cull_mode(CULL_MODE_NONE)
+            .depth_test(true, CompareOp::Less)
+    }
+}
+
+/// What the last `cmd_draw` let through culling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub visible_chunks: u32,
+    pub visible_points: u64,
+    pub draws: u32,
+}
+
+/// Points uploaded to host-visible memory in chunk order.
+pub struct PointCloud<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    buffer: Owned<'d, D, Buffer>,
+    memory: VkAllocation<'d, D>,
+    chunks: Vec<PointChunk>,
+    point_count: u32,
+    bounds: Aabb,
+    mode: PointMode,
+}
+
+impl<'d, D: DeviceV1_0> PointCloud<'d, D> {
+    /// Chunks and uploads `points`, which are reordered in the process.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, points: &mut [PointVertex]) -> VkResult<PointCloud<'d, D>> {
+        let chunks = build_chunks(points, CHUNK_POINTS);
+        let size = std::cmp::max(std::mem::size_of_val(points), 1) as DeviceSize;
+        let create_info = BufferCreateInfo {
+            s_type: StructureType::BufferCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            size: size,
+            usage: BUFFER_USAGE_VERTEX_BUFFER_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+        };
+        let mut ret = PointCloud {
+            device: device,
+            buffer: safe_create::create_buffer_safe(device, &create_info, None)?,
+            memory: VkAllocation::null(),
+            bounds: if points.is_empty() { Aabb { min: Vec3::zero(), max: Vec3::zero() } } else { bounds_of(points) },
+            chunks: chunks,
+            point_count: points.len() as u32,
+            mode: PointMode::Points,
+        };
+        ret.memory = allocator.allocate_buffer_memory(*ret.buffer, MEMORY_PROPERTY_HOST_VISIBLE_BIT | MEMORY_PROPERTY_HOST_COHERENT_BIT)?;
+        ret.memory.write(0, points);
+        debug!("Uploaded point cloud: {} points in {} chunks, {} MiB", ret.point_count, ret.chunks.len(), size >> 20);
+        Ok(ret)
+    }
+
+    pub fn point_count(&self) -> u32 {
+        self.point_count
+    }
+
+    pub fn chunks(&self) -> &[PointChunk] {
+        &self.chunks
+    }
+
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    pub fn mode(&self) -> PointMode {
+        self.mode
+    }
+
+    /// Selects how `cmd_draw` will draw. It must match the bound pipeline.
+    pub fn set_mode(&mut self, mode: PointMode) {
+        self.mode = mode;
+    }
+
+    /// Records draws for the chunks inside `frustum`, merging neighbouring visible chunks into a
+    /// single draw.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, frustum: &Frustum) -> CullStats {
+        let mut stats: CullStats = Default::default();
+        self.device.cmd_bind_vertex_buffers(command_buffer, 0, &[*self.buffer], &[0]);
+        let mut pending: Option<(u32, u32)> = None;
+        for chunk in self.chunks.iter().filter(|c| frustum.intersects_aabb(&c.bounds)) {
+            stats.visible_chunks += 1;
+            stats.visible_points += chunk.count as u64;
+            pending = match pending {
+                Some((first, count)) if first + count == chunk.first => Some((first, count + chunk.count)),
+                Some(range) => {
+                    self.cmd_draw_range(command_buffer, range);
+                    stats.draws += 1;
+                    Some((chunk.first, chunk.count))
+                },
+                None => Some((chunk.first, chunk.count)),
+            };
+        }
+        if let Some(range) = pending {
+            self.cmd_draw_range(command_buffer, range);
+            stats.draws += 1;
+        }
+        stats
+    }
+
+    unsafe fn cmd_draw_range(&self, command_buffer: CommandBuffer, (first, count): (u32, u32)) {
+        match self.mode {
+            PointMode::Points => self.device.cmd_draw(command_buffer, count, 1, first, 0),
+            PointMode::Splats => self.device.cmd_draw(command_buffer, 4, count, 0, first),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_ply_with_and_without_colors() {
+        let colored = b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+                        property uchar red\nproperty uchar green\nproperty uchar blue\nelement face 0\n\
+                        property list uchar int vertex_indices\nend_header\n1 2 3 255 128 0\n-1 0.5 0 0 0 10\n";
+        assert_eq!(parse_ply(colored).unwrap(), vec![
+            PointVertex { position: [1.0, 2.0, 3.0], color: [255, 128, 0, 255] },
+            PointVertex { position: [-1.0, 0.5, 0.0], color: [0, 0, 10, 255] },
+        ]);
+        let plain = b"ply\nformat ascii 1.0\nelement vertex 1\nproperty double x\nproperty double y\nproperty double z\nend_header\n4 5 6\n";
+        assert_eq!(parse_ply(plain).unwrap(), vec![PointVertex { position: [4.0, 5.0, 6.0], color: [255; 4] }]);
+    }
+
+    #[test]
+    fn binary_ply_with_16_bit_colors() {
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\n\
+                         property uchar intensity\nproperty ushort red\nproperty ushort green\nproperty ushort blue\nend_header\n".to_vec();
+        for &v in [0.5f32, -2.0, 8.0].iter() {
+            data.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        data.push(7);
+        for &c in [65535u16, 0, 32896].iter() {
+            data.extend_from_slice(&c.to_le_bytes());
+        }
+        assert_eq!(parse_ply(&data).unwrap(), vec![PointVertex { position: [0.5, -2.0, 8.0], color: [255, 0, 128, 255] }]);
+        data.pop();
+        assert!(parse_ply(&data).is_err());
+    }
+
+    #[test]
+    fn invalid_ply_is_rejected() {
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\n").is_err());
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nend_header\n1 2\n").is_err());
+        assert!(parse_ply(b"ply\nformat binary_big_endian 1.0\nend_header\n").is_err());
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement face 1\nelement vertex 1\nend_header\n").is_err());
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\nend_header\n1 2 3\n").is_err());
+    }
+
+    fn las(format: u8, record_length: u16, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; 227];
+        data[0..4].copy_from_slice(b"LASF");
+        data[96..100].copy_from_slice(&227u32.to_le_bytes());
+        data[104] = format;
+        data[105..107].copy_from_slice(&record_length.to_le_bytes());
+        data[107..111].copy_from_slice(&(records.len() as u32).to_le_bytes());
+        for (i, &scale) in [0.01f64, 0.01, 0.001].iter().enumerate() {
+            data[131 + i * 8..139 + i * 8].copy_from_slice(&scale.to_bits().to_le_bytes());
+        }
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    #[test]
+    fn las_points_are_z_up_and_scaled() {
+        let mut record = Vec::new();
+        for &v in [150i32, -200, 3000].iter() {
+            record.extend_from_slice(&v.to_le_bytes());
+        }
+        record.resize(20, 0);
+        for &c in [0xff00u16, 0x8000, 0x00ff].iter() {
+            record.extend_from_slice(&c.to_le_bytes());
+        }
+        let points = parse_las(&las(2, 26, &[record.clone()])).unwrap();
+        assert_eq!(points, vec![PointVertex { position: [1.5, 3.0, 2.0], color: [255, 128, 0, 255] }]);
+
+        let uncolored = parse_las(&las(1, 20, &[record[..20].to_vec()])).unwrap();
+        assert_eq!(uncolored[0].color, [255; 4]);
+    }
+
+    #[test]
+    fn invalid_las_is_rejected() {
+        assert!(parse_las(b"LASF").is_err());
+        let mut not_las = las(0, 20, &[]);
+        not_las[0] = b'X';
+        assert!(parse_las(&not_las).is_err());
+        assert!(parse_las(&las(4, 57, &[])).is_err());
+        // Too short for its colors, and too short for its points
+        assert!(parse_las(&las(2, 20, &[])).is_err());
+        let mut truncated = las(0, 20, &[vec![0; 20]]);
+        truncated.pop();
+        assert!(parse_las(&truncated).is_err());
+    }
+
+    #[test]
+    fn chunks_cover_every_point_once() {
+        let mut points = synthetic_terrain(1000, 100.0);
+        let chunks = build_chunks(&mut points, 64);
+        assert!(chunks.iter().all(|chunk| chunk.count as usize <= 64));
+        let mut next = 0;
+        for chunk in chunks.iter() {
+            assert_eq!(chunk.first, next);
+            next += chunk.count;
+            for p in points[chunk.first as usize..next as usize].iter() {
+                let (min, max) = (chunk.bounds.min, chunk.bounds.max);
+                let inside = (0..3).all(|i| [min.x, min.y, min.z][i] <= p.position[i] && p.position[i] <= [max.x, max.y, max.z][i]);
+                assert!(inside, "{:?} outside {:?}", p, chunk.bounds);
+            }
+        }
+        assert_eq!(next, 1000);
+        assert!(build_chunks(&mut [], 64).is_empty());
+    }
+}
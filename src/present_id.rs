@@ -0,0 +1,65 @@
+//! Present IDs, from `VK_KHR_present_id`: each present is tagged with the number of frames the
+//! `FrameLoop` has submitted, which is also what the frame's timeline signal sets (see
+//! `timeline`), so captures and frame pacing tools can line presents up with the frames that
+//! produced them. `ash` predates the extension, so its structures are declared here.
+use std::marker::PhantomData;
+use std::ptr;
+use vk::types::*;
+use ::capabilities::DeviceCapabilities;
+use ::pnext::ExtensionStruct;
+
+pub const EXTENSION_NAME: &'static str = "VK_KHR_present_id";
+
+const STRUCTURE_TYPE_PRESENT_ID: u32 = 1000294000;
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_PRESENT_ID_FEATURES: u32 = 1000294001;
+
+#[repr(C)]
+pub struct PresentIdFeatures {
+    s_type: u32,
+    pub p_next: *mut c_void,
+    pub present_id: Bool32,
+}
+
+unsafe impl ExtensionStruct for PresentIdFeatures {}
+
+impl PresentIdFeatures {
+    pub fn enabled() -> PresentIdFeatures {
+        PresentIdFeatures {
+            s_type: STRUCTURE_TYPE_PHYSICAL_DEVICE_PRESENT_ID_FEATURES,
+            p_next: ptr::null_mut(),
+            present_id: true as Bool32,
+        }
+    }
+}
+
+/// One ID per swapchain of a present, in the same order. IDs have to increase from one present
+/// of a swapchain to the next, and 0 means the present has none.
+#[repr(C)]
+pub struct PresentIdInfo<'a> {
+    s_type: u32,
+    p_next: *const c_void,
+    swapchain_count: u32,
+    p_present_ids: *const u64,
+    phantom: PhantomData<&'a [u64]>,
+}
+
+unsafe impl<'a> ExtensionStruct for PresentIdInfo<'a> {}
+
+impl<'a> PresentIdInfo<'a> {
+    pub fn new(present_ids: &'a [u64]) -> PresentIdInfo<'a> {
+        PresentIdInfo {
+            s_type: STRUCTURE_TYPE_PRESENT_ID,
+            p_next: ptr::null(),
+            swapchain_count: present_ids.len() as u32,
+            p_present_ids: present_ids.as_ptr(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Whether the device can have the extension enabled. Like `timeline`'s, its feature struct goes
+/// through `vkGetPhysicalDeviceFeatures2`, so this also needs a Vulkan 1.1 device.
+pub fn is_supported(capabilities: &DeviceCapabilities) -> bool {
+    capabilities.properties.api_version >= vk_make_version!(1, 1, 0)
+        && capabilities.extensions.iter().any(|name| name == EXTENSION_NAME)
+}
@@ -0,0 +1,200 @@
+//! Sharing swapchain images between the graphics and presentation queue families. Most devices
+//! can present from their graphics family, so the distinct-family path rarely runs; passing
+//! `--present-stress=concurrent` or `--present-stress=transfer` makes device selection prefer a
+//! separate presentation family and forces the corresponding sharing strategy, so the path can be
+//! exercised under the validation layers.
+//!
+//! With `OwnershipTransfer`, the images stay `Exclusive`: each frame's graphics command buffer
+//! ends with a release barrier to the presentation family, and a small command buffer on the
+//! presentation queue performs the matching acquire before the present. Nothing is transferred
+//! back, since the render pass starts from `Undefined` and discards the old contents anyway.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::collections::BTreeSet;
+use std::ptr;
+use vk::types::*;
+use ::cvar::Flag;
+use ::queue::QueueFamilyIndex;
+use ::safe_info::{ self, SubmitInfoSafe };
+
+const ARG_PREFIX: &'static str = "--present-stress=";
+pub const CVAR: &'static str = "r.present_stress";
+pub const FLAGS: &'static [Flag] = &[Flag::Value { prefix: ARG_PREFIX, name: CVAR }];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentSharing {
+    /// Graphics and presentation share a family, so there's nothing to share.
+    SameFamily,
+    /// `Concurrent` swapchain images, usable from both families without transfers.
+    Concurrent,
+    /// `Exclusive` swapchain images handed to the presentation family every frame.
+    OwnershipTransfer,
+}
+
+impl PresentSharing {
+    /// The strategy forced with `r.present_stress`, or `--present-stress=<concurrent|transfer>`,
+    /// if any.
+    pub fn from_cvars() -> Option<PresentSharing> {
+        match cvar!(CVAR, String::new()).get().as_str() {
+            "" => None,
+            "concurrent" => Some(PresentSharing::Concurrent),
+            "transfer" => Some(PresentSharing::OwnershipTransfer),
+            other => {
+                warn!("Unknown present stress mode {:?}, expected \"concurrent\" or \"transfer\"", other);
+                None
+            },
+        }
+    }
+
+    /// The strategy for the chosen families. Without a `requested` one, distinct families use
+    /// `Concurrent`.
+    pub fn resolve(requested: Option<PresentSharing>, graphics_family: QueueFamilyIndex, presentation_family: QueueFamilyIndex) -> PresentSharing {
+        if graphics_family == presentation_family {
+            if requested.is_some() {
+                warn!("Present stress test requested, but graphics and presentation share queue family {}. Nothing to stress", graphics_family);
+            }
+            return PresentSharing::SameFamily;
+        }
+        requested.unwrap_or(PresentSharing::Concurrent)
+    }
+
+    /// Sets the swapchain's sharing mode. `create_info` must already list both families.
+    pub fn apply(&self, create_info: &mut SwapchainCreateInfoKHR) {
+        match *self {
+            PresentSharing::Concurrent => {
+                assert!(create_info.queue_family_index_count > 1);
+                create_info.image_sharing_mode = SharingMode::Concurrent;
+            },
+            PresentSharing::SameFamily | PresentSharing::OwnershipTransfer => {
+                create_info.image_sharing_mode = SharingMode::Exclusive;
+            },
+        }
+    }
+}
+
+/// Picks `(graphics, presentation)` families. Normally a family that does both is preferred;
+/// with `prefer_distinct`, two different families are preferred instead.
+pub fn choose_families(graphics_families: &BTreeSet<QueueFamilyIndex>, presentation_families: &BTreeSet<QueueFamilyIndex>, prefer_distinct: bool) -> Option<(QueueFamilyIndex, QueueFamilyIndex)> {
+    let shared = graphics_families.intersection(presentation_families)
+        .next()
+        .map(|&family| (family, family));
+    let distinct = graphics_families.iter()
+        .flat_map(|&g| presentation_families.iter().filter(move |&&p| p != g).map(move |&p| (g, p)))
+        .next();
+    if prefer_distinct {
+        distinct.or(shared)
+    } else {
+        shared.or_else(|| {
+            debug!("Graphics and presentation queue families are not the same. This is not ideal");
+            distinct
+        })
+    }
+}
+
+fn ownership_barrier(image: Image, src_access_mask: AccessFlags, graphics_family: QueueFamilyIndex, presentation_family: QueueFamilyIndex) -> ImageMemoryBarrier {
+    ImageMemoryBarrier {
+        s_type: StructureType::ImageMemoryBarrier,
+        p_next: ptr::null(),
+        src_access_mask: src_access_mask,
+        dst_access_mask: Default::default(),
+        // The render pass already left the image ready to present
+        old_layout: ImageLayout::PresentSrcKhr,
+        new_layout: ImageLayout::PresentSrcKhr,
+        src_queue_family_index: graphics_family.raw(),
+        dst_queue_family_index: presentation_family.raw(),
+        image: image,
+        subresource_range: ImageSubresourceRange {
+            aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+    }
+}
+
+/// Records the release half of the transfer, after the render pass that draws `image`.
+pub unsafe fn cmd_release<D: DeviceV1_0>(device: &D, command_buffer: CommandBuffer, image: Image, graphics_family: QueueFamilyIndex, presentation_family: QueueFamilyIndex) {
+    let barrier = ownership_barrier(image, ACCESS_COLOR_ATTACHMENT_WRITE_BIT, graphics_family, presentation_family);
+    device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT, PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT, Default::default(), &[], &[], &[barrier]);
+}
+
+/// The presentation queue's half of `OwnershipTransfer`: one pre-recorded acquire per swapchain
+/// image, and the semaphore the present waits on.
+pub struct PresentOwnership<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    command_pool: CommandPool,
+    command_buffers: Vec<CommandBuffer>,
+    acquired: Semaphore,
+}
+
+impl<'d, D: DeviceV1_0> PresentOwnership<'d, D> {
+    pub fn new(device: &'d D, images: &[Image], graphics_family: QueueFamilyIndex, presentation_family: QueueFamilyIndex) -> VkResult<PresentOwnership<'d, D>> {
+        let mut ret = PresentOwnership {
+            device: device,
+            command_pool: CommandPool::null(),
+            command_buffers: Vec::new(),
+            acquired: Semaphore::null(),
+        };
+        let pool_create_info = CommandPoolCreateInfo {
+            s_type: StructureType::CommandPoolCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            queue_family_index: presentation_family.raw(),
+        };
+        ret.command_pool = unsafe { device.create_command_pool(&pool_create_info, None) }?;
+        let semaphore_create_info = SemaphoreCreateInfo {
+            s_type: StructureType::SemaphoreCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+        };
+        ret.acquired = unsafe { device.create_semaphore(&semaphore_create_info, None) }?;
+        ret.command_buffers = unsafe {
+            device.allocate_command_buffers(&CommandBufferAllocateInfo {
+                s_type: StructureType::CommandBufferAllocateInfo,
+                p_next: ptr::null(),
+                command_pool: ret.command_pool,
+                level: CommandBufferLevel::Primary,
+                command_buffer_count: images.len() as u32,
+            })
+        }?;
+        for (&command_buffer, &image) in ret.command_buffers.iter().zip(images.iter()) {
+            let begin_info = CommandBufferBeginInfo {
+                s_type: StructureType::CommandBufferBeginInfo,
+                p_next: ptr::null(),
+                flags: COMMAND_BUFFER_USAGE_SIMULTANEOUS_USE_BIT,
+                p_inheritance_info: ptr::null(),
+            };
+            unsafe {
+                device.begin_command_buffer(command_buffer, &begin_info)?;
+                // Access masks are ignored on the acquiring side; the present waits on the semaphore
+                let barrier = ownership_barrier(image, Default::default(), graphics_family, presentation_family);
+                device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT, Default::default(), &[], &[], &[barrier]);
+                device.end_command_buffer(command_buffer)?;
+            }
+        }
+        debug!("Recorded {} present ownership acquires for queue family {}", images.len(), presentation_family);
+        Ok(ret)
+    }
+
+    /// Submits the acquire for `image_idx` once `rendered` is signaled, and returns the semaphore
+    /// to present after.
+    pub fn submit(&self, presentation_queue: Queue, image_idx: u32, rendered: Semaphore) -> VkResult<Semaphore> {
+        let submit_info = SubmitInfoSafe::new()
+            .wait(rendered, PIPELINE_STAGE_ALL_COMMANDS_BIT)
+            .command_buffer(self.command_buffers[image_idx as usize])
+            .signal(self.acquired);
+        (unsafe { safe_info::queue_submit_safe(self.device, presentation_queue, &[submit_info], Fence::null()) })?;
+        Ok(self.acquired)
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for PresentOwnership<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying present ownership command pool: {:?}", self.command_pool);
+            self.device.destroy_semaphore(self.acquired, None);
+            self.device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Procedural primitive meshes, so demos have something to draw without shipping model files.
+//! Every generator produces positions, normals, UVs, and per-vertex tangents derived from the
+//! UVs. Outward-facing triangles are wound counter-clockwise in world space, which
+//! `Mat4::perspective`'s flipped Y turns into the pipeline's default clockwise front faces.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::collections::HashMap;
+use ::math::{ Aabb, Vec3 };
+use ::mesh::{ MeshBuffer, MeshVertex };
+use ::vk_mem::MemoryAllocator;
+
+#[derive(Debug, Clone, Default)]
+pub struct PrimitiveMesh {
+    pub vertices: Vec<MeshVertex>,
+    /// Per vertex: the tangent along increasing `u` in `xyz`, and in `w` the sign to give
+    /// `cross(normal, tangent)` to get the bitangent.
+    pub tangents: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+fn vec3(a: [f32; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+impl PrimitiveMesh {
+    fn new(vertices: Vec<MeshVertex>, indices: Vec<u32>) -> PrimitiveMesh {
+        let tangents = compute_tangents(&vertices, &indices);
+        PrimitiveMesh {
+            vertices: vertices,
+            tangents: tangents,
+            indices: indices,
+        }
+    }
+
+    pub fn positions(&self) -> Vec<Vec3> {
+        self.vertices.iter().map(|v| vec3(v.position)).collect()
+    }
+
+    pub fn normals(&self) -> Vec<Vec3> {
+        self.vertices.iter().map(|v| vec3(v.normal)).collect()
+    }
+
+    /// Tangents without their handedness, e.g. for `DebugView::draw_mesh`.
+    pub fn tangent_vectors(&self) -> Vec<Vec3> {
+        self.tangents.iter().map(|t| Vec3::new(t[0], t[1], t[2])).collect()
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(&self.positions()).unwrap_or(Aabb { min: Vec3::zero(), max: Vec3::zero() })
+    }
+
+    pub fn upload<'d, D: DeviceV1_0>(&self, device: &'d D, allocator: &'d MemoryAllocator<D>) -> VkResult<MeshBuffer<'d, D>> {
+        MeshBuffer::new(device, allocator, &self.vertices, &self.indices)
+    }
+
+    /// Appends `other`, e.g. to build a compound shape.
+    fn append(&mut self, other: PrimitiveMesh) {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend(other.vertices);
+        self.tangents.extend(other.tangents);
+        self.indices.extend(other.indices.iter().map(|&i| i + base));
+    }
+}
+
+/// A `columns` by `vs.len() - 1` grid of quads over a parametric surface. `surface(u, v)` gives
+/// the position and normal at `u` in `[0, 1]` and each of `vs`, and must have
+/// `cross(dP/du, dP/dv)` pointing along the normal. Texture `v` runs the other way, from the
+/// top of the image down.
+fn grid<F: Fn(f32, f32) -> (Vec3, Vec3)>(columns: u32, vs: &[f32], surface: F) -> (Vec<MeshVertex>, Vec<u32>) {
+    let mut vertices = Vec::with_capacity((columns as usize + 1) * vs.len());
+    for &v in vs.iter() {
+        for i in 0..(columns + 1) {
+            let u = i as f32 / columns as f32;
+            let (position, normal) = surface(u, v);
+            vertices.push(MeshVertex {
+                position: position.to_array(),
+                normal: normal.to_array(),
+                uv: [u, 1.0 - v],
+            });
+        }
+    }
+    let mut indices = Vec::with_capacity(columns as usize * vs.len() * 6);
+    let stride = columns + 1;
+    for j in 0..(vs.len() as u32 - 1) {
+        for i in 0..columns {
+            let a = j * stride + i;
+            let (b, c, d) = (a + 1, a + stride + 1, a + stride);
+            indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+    (vertices, indices)
+}
+
+fn uniform(count: u32) -> Vec<f32> {
+    (0..(count + 1)).map(|j| j as f32 / count as f32).collect()
+}
+
+/// Tangents from each triangle's UV gradients, averaged per vertex and made orthogonal to the
+/// normal.
+pub fn compute_tangents(vertices: &[MeshVertex], indices: &[u32]) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vec3::zero(); vertices.len()];
+    let mut bitangents = vec![Vec3::zero(); vertices.len()];
+    for triangle in indices.chunks(3).filter(|t| t.len() == 3) {
+        let (a, b, c) = (&vertices[triangle[0] as usize], &vertices[triangle[1] as usize], &vertices[triangle[2] as usize]);
+        let (e1, e2) = (vec3(b.position) - vec3(a.position), vec3(c.position) - vec3(a.position));
+        let (du1, dv1) = (b.uv[0] - a.uv[0], b.uv[1] - a.uv[1]);
+        let (du2, dv2) = (c.uv[0] - a.uv[0], c.uv[1] - a.uv[1]);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let r = 1.0 / det;
+        let t = (e1 * dv2 - e2 * dv1) * r;
+        let bt = (e2 * du1 - e1 * du2) * r;
+        for &i in triangle.iter() {
+            tangents[i as usize] = tangents[i as usize] + t;
+            bitangents[i as usize] = bitangents[i as usize] + bt;
+        }
+    }
+    vertices.iter().zip(tangents.iter().zip(bitangents.iter())).map(|(v, (&t, &bt))| {
+        let n = vec3(v.normal);
+        let t = t - n * n.dot(t);
+        let t = if t.length() > 1e-6 { t.normalize() } else { n.any_perpendicular() };
+        let w = if n.cross(t).dot(bt) < 0.0 { -1.0 } else { 1.0 };
+        [t.x, t.y, t.z, w]
+    }).collect()
+}
+
+/// Axis-aligned cube of edge `size` centered on the origin, with each face covering the whole
+/// texture.
+pub fn cube(size: f32) -> PrimitiveMesh {
+    let h = size * 0.5;
+    let (x, y, z) = (Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z());
+    // (normal, u axis, v axis) with cross(u, v) = normal
+    let faces = [(x, -z, y), (-x, z, y), (y, x, -z), (-y, x, z), (z, x, y), (-z, -x, y)];
+    let mut ret = PrimitiveMesh::default();
+    for &(n, u_axis, v_axis) in faces.iter() {
+        let (vertices, indices) = grid(1, &uniform(1), |u, v| (n * h + u_axis * ((2.0 * u - 1.0) * h) + v_axis * ((2.0 * v - 1.0) * h), n));
+        ret.append(PrimitiveMesh::new(vertices, indices));
+    }
+    ret
+}
+
+/// Flat square in the XZ plane facing +Y, split into `subdivisions` quads along each side.
+pub fn plane(size: f32, subdivisions: u32) -> PrimitiveMesh {
+    let subdivisions = std::cmp::max(subdivisions, 1);
+    let (vertices, indices) = grid(subdivisions, &uniform(subdivisions), |u, v| {
+        (Vec3::new((u - 0.5) * size, 0.0, (0.5 - v) * size), Vec3::unit_y())
+    });
+    PrimitiveMesh::new(vertices, indices)
+}
+
+/// Point on the unit sphere at longitude `u * 2π` and latitude `theta`, with `u` running
+/// eastwards seen from above.
+fn sphere_direction(u: f32, theta: f32) -> Vec3 {
+    let phi = u * 2.0 * std::f32::consts::PI;
+    Vec3::new(theta.cos() * phi.cos(), theta.sin(), -theta.cos() * phi.sin())
+}
+
+/// Latitude-longitude sphere with `segments` around and `rings` from pole to pole. The UVs are an
+/// equirectangular map.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> PrimitiveMesh {
+    let (segments, rings) = (std::cmp::max(segments, 3), std::cmp::max(rings, 2));
+    let (vertices, indices) = grid(segments, &uniform(rings), |u, v| {
+        let n = sphere_direction(u, std::f32::consts::PI * (v - 0.5));
+        (n * radius, n)
+    });
+    PrimitiveMesh::new(vertices, indices)
+}
+
+/// Sphere from a subdivided icosahedron, whose triangles are much more even than a UV sphere's.
+/// Each subdivision quadruples the triangle count, starting from 20. UVs are equirectangular,
+/// with the vertices along the seam duplicated.
+pub fn icosphere(radius: f32, subdivisions: u32) -> PrimitiveMesh {
+    let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+    let mut positions: Vec<Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ].iter().map(|&(x, y, z)| Vec3::new(x, y, z).normalize()).collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vec3>| {
+            let key = (std::cmp::min(a, b), std::cmp::max(a, b));
+            *midpoints.entry(key).or_insert_with(|| {
+                positions.push(((positions[a as usize] + positions[b as usize]) * 0.5).normalize());
+                positions.len() as u32 - 1
+            })
+        };
+        let mut subdivided = Vec::with_capacity(triangles.len() * 4);
+        for triangle in triangles.iter() {
+            let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+            let ab = midpoint(a, b, &mut positions);
+            let bc = midpoint(b, c, &mut positions);
+            let ca = midpoint(c, a, &mut positions);
+            subdivided.extend_from_slice(&[[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+        }
+        triangles = subdivided;
+    }
+
+    let uv = |n: Vec3| {
+        let u = (-n.z).atan2(n.x) / (2.0 * std::f32::consts::PI);
+        [if u < 0.0 { u + 1.0 } else { u }, 0.5 - n.y.asin() / std::f32::consts::PI]
+    };
+    let mut vertices: Vec<MeshVertex> = positions.iter().map(|&n| MeshVertex {
+        position: (n * radius).to_array(),
+        normal: n.to_array(),
+        uv: uv(n),
+    }).collect();
+    // Triangles straddling the seam get copies of their low-u vertices moved past 1
+    let mut seam_copies: HashMap<u32, u32> = HashMap::new();
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+    for triangle in triangles.iter() {
+        let us: Vec<f32> = triangle.iter().map(|&i| vertices[i as usize].uv[0]).collect();
+        let wraps = us.iter().cloned().fold(0.0f32, f32::max) - us.iter().cloned().fold(1.0f32, f32::min) > 0.5;
+        for &i in triangle.iter() {
+            if wraps && vertices[i as usize].uv[0] < 0.5 {
+                let copy = *seam_copies.entry(i).or_insert_with(|| {
+                    let mut vertex = vertices[i as usize];
+                    vertex.uv[0] += 1.0;
+                    vertices.push(vertex);
+                    vertices.len() as u32 - 1
+                });
+                indices.push(copy);
+            } else {
+                indices.push(i);
+            }
+        }
+    }
+    PrimitiveMesh::new(vertices, indices)
+}
+
+/// Torus around the Y axis: a tube of `minor_radius` swept along a circle of `major_radius`.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> PrimitiveMesh {
+    let (major_segments, minor_segments) = (std::cmp::max(major_segments, 3), std::cmp::max(minor_segments, 3));
+    let (vertices, indices) = grid(major_segments, &uniform(minor_segments), |u, v| {
+        let n = sphere_direction(u, v * 2.0 * std::f32::consts::PI);
+        let center = sphere_direction(u, 0.0) * major_radius;
+        (center + n * minor_radius, n)
+    });
+    PrimitiveMesh::new(vertices, indices)
+}
+
+/// Capsule along Y: a cylinder of `height` between its hemispherical caps, so the whole is
+/// `height + 2 * radius` tall. Texture `v` is spread by arc length, so the checker stays square.
+pub fn capsule(radius: f32, height: f32, segments: u32, cap_rings: u32) -> PrimitiveMesh {
+    let (segments, cap_rings) = (std::cmp::max(segments, 3), std::cmp::max(cap_rings, 1));
+    let half_pi = std::f32::consts::PI * 0.5;
+    let cap_length = half_pi * radius;
+    let total = 2.0 * cap_length + height;
+    let cap = cap_length / total;
+    // Rings from the bottom pole up to the bottom equator, then from the top equator up
+    let mut vs: Vec<f32> = (0..(cap_rings + 1)).map(|j| cap * j as f32 / cap_rings as f32).collect();
+    vs.extend((0..(cap_rings + 1)).map(|j| 1.0 - cap + cap * j as f32 / cap_rings as f32));
+    let (vertices, indices) = grid(segments, &vs, |u, v| {
+        let (theta, offset) = if v <= cap {
+            (half_pi * (v / cap - 1.0), -height * 0.5)
+        } else {
+            (half_pi * (v - (1.0 - cap)) / cap, height * 0.5)
+        };
+        let n = sphere_direction(u, theta);
+        (n * radius + Vec3::new(0.0, offset, 0.0), n)
+    });
+    PrimitiveMesh::new(vertices, indices)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primitive {
+    Cube,
+    UvSphere,
+    Icosphere,
+    Plane,
+    Torus,
+    Capsule,
+}
+
+impl Primitive {
+    pub fn next(&self) -> Primitive {
+        match *self {
+            Primitive::Cube => Primitive::UvSphere,
+            Primitive::UvSphere => Primitive::Icosphere,
+            Primitive::Icosphere => Primitive::Plane,
+            Primitive::Plane => Primitive::Torus,
+            Primitive::Torus => Primitive::Capsule,
+            Primitive::Capsule => Primitive::Cube,
+        }
+    }
+
+    /// The primitive at a size of about one unit, with moderate tessellation.
+    pub fn mesh(&self) -> PrimitiveMesh {
+        match *self {
+            Primitive::Cube => cube(1.0),
+            Primitive::UvSphere => uv_sphere(0.5, 32, 16),
+            Primitive::Icosphere => icosphere(0.5, 3),
+            Primitive::Plane => plane(1.0, 8),
+            Primitive::Torus => torus(0.4, 0.15, 48, 16),
+            Primitive::Capsule => capsule(0.25, 0.5, 24, 8),
+        }
+    }
+}
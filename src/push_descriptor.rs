@@ -0,0 +1,98 @@
+//! Per-draw descriptors through `VK_KHR_push_descriptor` when the device has it: bindings are
+//! recorded straight into the command buffer, with no sets to allocate. Without the extension,
+//! each push allocates a set from the frame's `DescriptorAllocator` and binds it instead, so the
+//! descriptors are made with `prepare` while the frame's sets can be allocated, and recorded with
+//! `cmd_push`.
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, InstanceV1_0 };
+use std;
+use std::ffi::CString;
+use std::ptr;
+use vk::types::*;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::safe_create;
+
+pub const EXTENSION_NAME: &'static str = "VK_KHR_push_descriptor";
+
+const DESCRIPTOR_SET_LAYOUT_CREATE_PUSH_DESCRIPTOR_BIT_KHR: u32 = 0x1;
+
+type PushDescriptorSetFn = unsafe extern "system" fn(CommandBuffer, PipelineBindPoint, PipelineLayout, u32, u32, *const WriteDescriptorSet);
+
+pub struct PushDescriptors<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    push_descriptor_set: Option<PushDescriptorSetFn>,
+}
+
+/// One set's descriptors from `PushDescriptors::prepare`.
+#[derive(Debug, Clone)]
+pub enum PushedSet {
+    /// Recorded into the command buffer as they are.
+    Writes(Vec<DescriptorWrite>),
+    /// Written into a set from the fallback allocator.
+    Allocated(DescriptorSet),
+}
+
+impl<'d, D: DeviceV1_0> PushDescriptors<'d, D> {
+    /// `extension_enabled` says whether `device` was created with `EXTENSION_NAME`.
+    pub fn new<I: InstanceV1_0>(instance: &I, device: &'d D, extension_enabled: bool) -> PushDescriptors<'d, D> {
+        let push_descriptor_set = if extension_enabled {
+            let name = CString::new("vkCmdPushDescriptorSetKHR").unwrap();
+            let pfn = instance.get_device_proc_addr(device.handle(), name.as_ptr());
+            Some(unsafe { std::mem::transmute::<PFN_vkVoidFunction, PushDescriptorSetFn>(pfn) })
+        } else {
+            info!("{} isn't available, falling back to allocating descriptor sets", EXTENSION_NAME);
+            None
+        };
+        PushDescriptors {
+            device: device,
+            push_descriptor_set: push_descriptor_set,
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.push_descriptor_set.is_some()
+    }
+
+    /// Creates a set layout for pushed descriptors. It's only flagged as a push descriptor layout
+    /// when the extension is used, since the fallback allocates regular sets with it.
+    pub fn create_set_layout(&self, bindings: &[DescriptorSetLayoutBinding]) -> VkResult<safe_create::Owned<'d, D, DescriptorSetLayout>> {
+        let flags = if self.is_native() { DESCRIPTOR_SET_LAYOUT_CREATE_PUSH_DESCRIPTOR_BIT_KHR } else { 0 };
+        let create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            // `ash` doesn't know about the push descriptor bit
+            flags: unsafe { std::mem::transmute::<u32, DescriptorSetLayoutCreateFlags>(flags) },
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        safe_create::create_descriptor_set_layout_safe(self.device, &create_info, None)
+    }
+
+    /// Makes `writes` ready to push as a set of `set_layout`, the layout made by
+    /// `create_set_layout` for it. The fallback allocates the set from `allocator`, which must
+    /// keep it until the command buffer it's pushed in is done executing, like the frame's
+    /// descriptor sets.
+    pub fn prepare(&self, allocator: &mut DescriptorAllocator<D>, set_layout: DescriptorSetLayout, writes: Vec<DescriptorWrite>) -> VkResult<PushedSet> {
+        if self.is_native() {
+            return Ok(PushedSet::Writes(writes));
+        }
+        let descriptor_set = allocator.allocate(set_layout)?;
+        descriptor::update_descriptor_set(self.device, descriptor_set, &writes);
+        Ok(PushedSet::Allocated(descriptor_set))
+    }
+
+    /// Records `pushed` as set number `set` of `layout`.
+    pub unsafe fn cmd_push(&self, command_buffer: CommandBuffer, bind_point: PipelineBindPoint, layout: PipelineLayout, set: u32, pushed: &PushedSet) {
+        match (pushed, self.push_descriptor_set) {
+            (PushedSet::Writes(writes), Some(push_descriptor_set)) => {
+                descriptor::with_raw_writes(DescriptorSet::null(), writes, |raw_writes| {
+                    push_descriptor_set(command_buffer, bind_point, layout, set, raw_writes.len() as u32, raw_writes.as_ptr());
+                });
+            },
+            (&PushedSet::Allocated(descriptor_set), _) => {
+                self.device.cmd_bind_descriptor_sets(command_buffer, bind_point, layout, set, &[descriptor_set], &[]);
+            },
+            (&PushedSet::Writes(_), None) => unreachable!("Writes are only prepared to push with {}", EXTENSION_NAME),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Quantized mesh vertices: positions as 16-bit SNORM relative to the mesh bounds, and normals
+//! octahedral-encoded into two 16-bit SNORM components. A vertex shrinks from 32 to 20 bytes;
+//! `QuantizationStats` reports what that saves and how much precision it costs.
+use std;
+use vk::types::*;
+use ::math::{ Aabb, Mat4, Vec3 };
+use ::math::prelude::clamp;
+use ::mesh::{ MeshPushConstants, MeshVertex, VertexFetch };
+use ::pipeline::GraphicsPipelineBuilder;
+
+pub const VERTEX_SHADER: &'static str = "shaders/mesh_quantized_vertex.vert.spv";
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuantizedVertex {
+    /// `xyz` in `[-1, 1]` over the mesh bounds; `w` is padding so the attribute stays aligned.
+    pub position: [i16; 4],
+    pub normal: [i16; 2],
+    pub uv: [f32; 2],
+}
+
+impl QuantizedVertex {
+    pub fn binding_descriptions() -> [VertexInputBindingDescription; 1] {
+        [VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<QuantizedVertex>() as u32,
+            input_rate: VertexInputRate::Vertex,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [VertexInputAttributeDescription; 3] {
+        [
+            VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: Format::R16g16b16a16Snorm,
+                offset: 0,
+            },
+            VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: Format::R16g16Snorm,
+                offset: 8,
+            },
+            VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: Format::R32g32Sfloat,
+                offset: 12,
+            },
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexFormat {
+    Full,
+    Quantized,
+}
+
+impl VertexFormat {
+    pub fn toggle(&self) -> VertexFormat {
+        match *self {
+            VertexFormat::Full => VertexFormat::Quantized,
+            VertexFormat::Quantized => VertexFormat::Full,
+        }
+    }
+
+    pub fn vertex_size(&self) -> usize {
+        match *self {
+            VertexFormat::Full => std::mem::size_of::<MeshVertex>(),
+            VertexFormat::Quantized => std::mem::size_of::<QuantizedVertex>(),
+        }
+    }
+
+    /// Fixed-function fetch mesh pipeline for this format. The quantized format needs
+    /// `shaders/mesh_quantized_vertex.glsl`.
+    pub fn pipeline_builder(&self, extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+        let builder = VertexFetch::FixedFunction.pipeline_builder(extent, vertex_shader, fragment_shader);
+        match *self {
+            VertexFormat::Full => builder,
+            VertexFormat::Quantized => builder.vertex_input(&QuantizedVertex::binding_descriptions(), &QuantizedVertex::attribute_descriptions()),
+        }
+    }
+}
+
+fn to_snorm16(v: f32) -> i16 {
+    (clamp(v, -1.0, 1.0) * 32767.0).round() as i16
+}
+
+fn from_snorm16(v: i16) -> f32 {
+    (v as f32 / 32767.0).max(-1.0)
+}
+
+fn sign_not_zero(v: f32) -> f32 {
+    if v >= 0.0 { 1.0 } else { -1.0 }
+}
+
+/// Maps a unit vector onto the octahedron, then unfolds it into the `[-1, 1]` square.
+pub fn octahedral_encode(n: Vec3) -> [i16; 2] {
+    let l1 = n.x.abs() + n.y.abs() + n.z.abs();
+    let (x, y) = (n.x / l1, n.y / l1);
+    let (x, y) = if n.z < 0.0 {
+        ((1.0 - y.abs()) * sign_not_zero(x), (1.0 - x.abs()) * sign_not_zero(y))
+    } else {
+        (x, y)
+    };
+    [to_snorm16(x), to_snorm16(y)]
+}
+
+/// Inverse of `octahedral_encode`, matching the decode in `shaders/mesh_quantized_vertex.glsl`.
+pub fn octahedral_decode(e: [i16; 2]) -> Vec3 {
+    let (x, y) = (from_snorm16(e[0]), from_snorm16(e[1]));
+    let z = 1.0 - x.abs() - y.abs();
+    let (x, y) = if z < 0.0 {
+        ((1.0 - y.abs()) * sign_not_zero(x), (1.0 - x.abs()) * sign_not_zero(y))
+    } else {
+        (x, y)
+    };
+    Vec3::new(x, y, z).normalize()
+}
+
+pub struct QuantizedMesh {
+    pub vertices: Vec<QuantizedVertex>,
+    /// Maps quantized positions back into object space.
+    pub dequantization: Mat4,
+}
+
+impl QuantizedMesh {
+    pub fn new(vertices: &[MeshVertex]) -> QuantizedMesh {
+        let positions: Vec<Vec3> = vertices.iter().map(|v| Vec3::new(v.position[0], v.position[1], v.position[2])).collect();
+        let bounds = Aabb::from_points(&positions).unwrap_or(Aabb { min: Vec3::zero(), max: Vec3::zero() });
+        let center = bounds.center();
+        let half_extents = bounds.extents() * 0.5;
+        // Flat meshes have a zero extent along some axis; any scale works there
+        let scale = |e: f32| if e > 0.0 { e } else { 1.0 };
+        let half_extents = Vec3::new(scale(half_extents.x), scale(half_extents.y), scale(half_extents.z));
+        let quantized = vertices.iter().zip(positions.iter()).map(|(v, &p)| {
+            let local = p - center;
+            QuantizedVertex {
+                position: [to_snorm16(local.x / half_extents.x), to_snorm16(local.y / half_extents.y), to_snorm16(local.z / half_extents.z), 0],
+                normal: octahedral_encode(Vec3::new(v.normal[0], v.normal[1], v.normal[2]).normalize()),
+                uv: v.uv,
+            }
+        }).collect();
+        QuantizedMesh {
+            vertices: quantized,
+            dequantization: Mat4::translation(center) * Mat4::scale(half_extents),
+        }
+    }
+
+    pub fn dequantize_position(&self, v: &QuantizedVertex) -> Vec3 {
+        let q = Vec3::new(from_snorm16(v.position[0]), from_snorm16(v.position[1]), from_snorm16(v.position[2]));
+        self.dequantization.transform_point(q)
+    }
+
+    /// The dequantization is folded into the model-view-projection matrix; normals only need
+    /// the model matrix, since they're decoded to unit vectors in the shader.
+    pub fn push_constants(&self, view_projection: &Mat4, model: &Mat4) -> MeshPushConstants {
+        MeshPushConstants {
+            model_view_projection: *view_projection * *model * self.dequantization,
+            model: *model,
+        }
+    }
+
+    pub fn stats(&self, original: &[MeshVertex]) -> QuantizationStats {
+        let mut max_position_error: f32 = 0.0;
+        let mut max_normal_error: f32 = 0.0;
+        for (q, v) in self.vertices.iter().zip(original.iter()) {
+            let position = Vec3::new(v.position[0], v.position[1], v.position[2]);
+            max_position_error = max_position_error.max((self.dequantize_position(q) - position).length());
+            let normal = Vec3::new(v.normal[0], v.normal[1], v.normal[2]).normalize();
+            let cos = clamp(octahedral_decode(q.normal).dot(normal), -1.0, 1.0);
+            max_normal_error = max_normal_error.max(cos.acos().to_degrees());
+        }
+        QuantizationStats {
+            full_bytes: original.len() * VertexFormat::Full.vertex_size(),
+            quantized_bytes: self.vertices.len() * VertexFormat::Quantized.vertex_size(),
+            max_position_error: max_position_error,
+            max_normal_error_degrees: max_normal_error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuantizationStats {
+    pub full_bytes: usize,
+    pub quantized_bytes: usize,
+    /// Largest object-space distance between a quantized and original position.
+    pub max_position_error: f32,
+    pub max_normal_error_degrees: f32,
+}
+
+impl QuantizationStats {
+    /// Vertex fetch bandwidth saved per frame if the mesh is drawn `draws_per_frame` times, with
+    /// each vertex fetched once per draw.
+    pub fn bytes_saved_per_frame(&self, draws_per_frame: usize) -> usize {
+        (self.full_bytes - self.quantized_bytes) * draws_per_frame
+    }
+
+    pub fn log(&self, name: &str) {
+        info!("{}: {} bytes of vertices quantized to {} ({:.0}%), max position error {:e}, max normal error {:.3} degrees",
+              name, self.full_bytes, self.quantized_bytes, 100.0 * self.quantized_bytes as f32 / std::cmp::max(self.full_bytes, 1) as f32,
+              self.max_position_error, self.max_normal_error_degrees);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], normal: [f32; 3]) -> MeshVertex {
+        MeshVertex {
+            position: position,
+            normal: normal,
+            uv: [0.25, 0.75],
+        }
+    }
+
+    #[test]
+    fn octahedral_normals_round_trip() {
+        let mut directions = vec![
+            Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0),
+        ];
+        for i in 0..16 {
+            for j in 0..8 {
+                let (theta, phi) = (i as f32 * 0.4, j as f32 * 0.4 - 1.4);
+                directions.push(Vec3::new(theta.cos() * phi.cos(), theta.sin() * phi.cos(), phi.sin()));
+            }
+        }
+        for &n in directions.iter() {
+            let decoded = octahedral_decode(octahedral_encode(n));
+            assert!(decoded.dot(n) > 0.99999, "{:?} decoded as {:?}", n, decoded);
+        }
+    }
+
+    #[test]
+    fn snorm_clamps_to_its_range() {
+        assert_eq!(to_snorm16(2.0), 32767);
+        assert_eq!(to_snorm16(-2.0), -32767);
+        assert_eq!(from_snorm16(-32768), -1.0);
+        assert_eq!(from_snorm16(to_snorm16(0.5)), 16384.0 / 32767.0);
+    }
+
+    #[test]
+    fn positions_dequantize_to_within_the_precision() {
+        let vertices = [
+            vertex([-2.0, 1.0, 0.5], [0.0, 1.0, 0.0]),
+            vertex([3.0, 4.0, 0.5], [0.0, 0.0, -1.0]),
+            vertex([0.1, 2.2, 0.5], [1.0, 1.0, 0.0]),
+        ];
+        let mesh = QuantizedMesh::new(&vertices);
+        let stats = mesh.stats(&vertices);
+        // The widest axis spans 5 units over 65534 steps; the flat one is exact
+        assert!(stats.max_position_error < 5.0 / 65534.0, "{:?}", stats);
+        // Mostly `acos` running out of precision next to 1
+        assert!(stats.max_normal_error_degrees < 0.05, "{:?}", stats);
+        assert_eq!(mesh.vertices[1].position[2], 0);
+        assert_eq!(mesh.vertices[2].uv, [0.25, 0.75]);
+        assert_eq!((stats.full_bytes, stats.quantized_bytes), (3 * 32, 3 * 20));
+        assert_eq!(stats.bytes_saved_per_frame(2), 2 * 3 * 12);
+    }
+}
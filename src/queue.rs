@@ -0,0 +1,67 @@
+//! Queue family and queue indices as their own types, so one can't be passed where the other (or
+//! a `usize` position in some unrelated list) is expected. They turn back into `u32` only where a
+//! Vulkan struct or call wants one.
+use ash::version::DeviceV1_0;
+use std::fmt;
+use std::iter;
+use std::slice;
+use vk::types::*;
+
+/// An index into the physical device's queue families.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueueFamilyIndex(pub u32);
+
+impl QueueFamilyIndex {
+    /// Every family of `queue_families`, with its index.
+    pub fn enumerate<'a>(queue_families: &'a [QueueFamilyProperties]) -> EnumerateFamilies<'a> {
+        EnumerateFamilies {
+            inner: queue_families.iter().enumerate(),
+        }
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+
+    /// This family's entry of `queue_families`.
+    pub fn properties<'a>(&self, queue_families: &'a [QueueFamilyProperties]) -> Option<&'a QueueFamilyProperties> {
+        queue_families.get(self.0 as usize)
+    }
+}
+
+impl fmt::Display for QueueFamilyIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The iterator returned by `QueueFamilyIndex::enumerate`.
+pub struct EnumerateFamilies<'a> {
+    inner: iter::Enumerate<slice::Iter<'a, QueueFamilyProperties>>,
+}
+
+impl<'a> Iterator for EnumerateFamilies<'a> {
+    type Item = (QueueFamilyIndex, &'a QueueFamilyProperties);
+
+    fn next(&mut self) -> Option<(QueueFamilyIndex, &'a QueueFamilyProperties)> {
+        self.inner.next().map(|(idx, properties)| (QueueFamilyIndex(idx as u32), properties))
+    }
+}
+
+/// An index among the queues created in one family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueueIndex(pub u32);
+
+impl QueueIndex {
+    /// The renderer only ever creates one queue per family.
+    pub const FIRST: QueueIndex = QueueIndex(0);
+
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+}
+
+/// The `index`th queue of `family`, which must have been requested when `device` was created.
+pub unsafe fn get_queue<D: DeviceV1_0>(device: &D, family: QueueFamilyIndex, index: QueueIndex) -> Queue {
+    device.get_device_queue(family.raw(), index.raw())
+}
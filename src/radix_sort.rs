@@ -0,0 +1,153 @@
+//! GPU radix sort of `u32` key/value pairs in storage buffers, eight bits per pass. Each pass
+//! builds per-workgroup digit histograms, scans them into scatter offsets with
+//! `gpu_algo::ExclusiveScan`, and moves every pair to its place in a scratch buffer, so four
+//! passes leave the sorted pairs back in the caller's buffers. The sort is stable.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use vk::types::*;
+use ::compute::{ self, ComputeKernel, StorageBuffer };
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::gpu_algo::ExclusiveScan;
+use ::math::prelude::div_ceil;
+use ::vk_mem::MemoryAllocator;
+
+pub const HISTOGRAM_SHADER: &'static str = "shaders/radix_histogram.comp.spv";
+pub const SCATTER_SHADER: &'static str = "shaders/radix_scatter.comp.spv";
+
+/// Pairs handled by each workgroup, and the number of buckets per pass.
+const RADIX: u32 = 256;
+const BITS_PER_PASS: u32 = 8;
+const PASS_COUNT: u32 = 4;
+
+const KEYS_IN_BINDING: u32 = 0;
+const VALUES_IN_BINDING: u32 = 1;
+const KEYS_OUT_BINDING: u32 = 2;
+const VALUES_OUT_BINDING: u32 = 3;
+const HISTOGRAMS_BINDING: u32 = 4;
+
+/// Push constant block shared by the radix sort shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RadixSortPushConstants {
+    count: u32,
+    shift: u32,
+    group_count: u32,
+}
+
+/// Maps a view-space depth to a key that sorts farthest first, for back-to-front blending of
+/// particles and other transparent geometry. Works for negative depths too.
+pub fn back_to_front_key(depth: f32) -> u32 {
+    let bits = depth.to_bits();
+    // Flip negative floats entirely and positive ones' sign bit, so the keys order like the floats
+    let ascending = if bits & 0x80000000 != 0 { !bits } else { bits | 0x80000000 };
+    !ascending
+}
+
+pub struct RadixSort<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    histogram: ComputeKernel<'d, D>,
+    scan: ExclusiveScan<'d, D>,
+    scatter: ComputeKernel<'d, D>,
+    // Kept alive for the descriptor sets
+    _scratch_keys: StorageBuffer<'d, D>,
+    _scratch_values: StorageBuffer<'d, D>,
+    _histograms: StorageBuffer<'d, D>,
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+    /// Sets for passes reading from the caller's buffers, and from the scratch buffers.
+    descriptor_sets: [DescriptorSet; 2],
+    capacity: u32,
+}
+
+impl<'d, D: DeviceV1_0> RadixSort<'d, D> {
+    /// Prepares to sort up to `capacity` pairs held in `keys` and `values`, which need
+    /// `BUFFER_USAGE_STORAGE_BUFFER_BIT`. The `*_spirv` arguments are the compiled
+    /// `HISTOGRAM_SHADER` and `SCATTER_SHADER`, and `gpu_algo`'s scan shaders.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, keys: Buffer, values: Buffer, capacity: u32, histogram_spirv: &[u8], scatter_spirv: &[u8], scan_block_spirv: &[u8], scan_add_spirv: &[u8]) -> VkResult<RadixSort<'d, D>> {
+        let bindings = compute::storage_buffer_bindings(5);
+        let push_constant_size = std::mem::size_of::<RadixSortPushConstants>() as u32;
+        let histogram = ComputeKernel::new(device, histogram_spirv, &bindings, push_constant_size)?;
+        let scatter = ComputeKernel::new(device, scatter_spirv, &bindings, push_constant_size)?;
+
+        let pair_buffer_size = std::cmp::max(capacity, 1) as DeviceSize * std::mem::size_of::<u32>() as DeviceSize;
+        let scratch_keys = StorageBuffer::new(device, allocator, pair_buffer_size)?;
+        let scratch_values = StorageBuffer::new(device, allocator, pair_buffer_size)?;
+        let max_group_count = std::cmp::max(RadixSort::<D>::group_count(capacity), 1) as DeviceSize;
+        let histograms = StorageBuffer::new(device, allocator, max_group_count * RADIX as DeviceSize * std::mem::size_of::<u32>() as DeviceSize)?;
+        let scan = ExclusiveScan::new(device, allocator, histograms.buffer(), histograms.buffer(), max_group_count as u32 * RADIX, scan_block_spirv, scan_add_spirv)?;
+
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: 10,
+        }];
+        // The kernels' set layouts are identical, so sets from either work with both
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, 2);
+        let forward = descriptor_allocator.allocate(histogram.set_layout())?;
+        let backward = descriptor_allocator.allocate(histogram.set_layout())?;
+        let whole = |binding: u32, buffer: Buffer| DescriptorWrite::StorageBuffer {
+            binding: binding,
+            buffer: buffer,
+            offset: 0,
+            range: pair_buffer_size,
+        };
+        descriptor::update_descriptor_set(device, forward, &[
+            whole(KEYS_IN_BINDING, keys),
+            whole(VALUES_IN_BINDING, values),
+            scratch_keys.descriptor_write(KEYS_OUT_BINDING),
+            scratch_values.descriptor_write(VALUES_OUT_BINDING),
+            histograms.descriptor_write(HISTOGRAMS_BINDING),
+        ]);
+        descriptor::update_descriptor_set(device, backward, &[
+            scratch_keys.descriptor_write(KEYS_IN_BINDING),
+            scratch_values.descriptor_write(VALUES_IN_BINDING),
+            whole(KEYS_OUT_BINDING, keys),
+            whole(VALUES_OUT_BINDING, values),
+            histograms.descriptor_write(HISTOGRAMS_BINDING),
+        ]);
+        Ok(RadixSort {
+            device: device,
+            histogram: histogram,
+            scan: scan,
+            scatter: scatter,
+            _scratch_keys: scratch_keys,
+            _scratch_values: scratch_values,
+            _histograms: histograms,
+            _descriptor_allocator: descriptor_allocator,
+            descriptor_sets: [forward, backward],
+            capacity: capacity,
+        })
+    }
+
+    fn group_count(count: u32) -> u32 {
+        div_ceil(count, RADIX)
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Records a sort of the first `count` pairs by key. Writes to the buffers must already be
+    /// visible to compute shaders; afterwards the results are visible to later compute work, and
+    /// other stages need their own barrier.
+    pub unsafe fn cmd_sort(&self, command_buffer: CommandBuffer, count: u32) {
+        assert!(count <= self.capacity);
+        let group_count = RadixSort::<D>::group_count(count);
+        if group_count == 0 {
+            return;
+        }
+        for pass in 0..PASS_COUNT {
+            let descriptor_set = self.descriptor_sets[(pass % 2) as usize];
+            let constants = RadixSortPushConstants {
+                count: count,
+                shift: pass * BITS_PER_PASS,
+                group_count: group_count,
+            };
+            self.histogram.cmd_dispatch(command_buffer, descriptor_set, &constants, (group_count, 1, 1));
+            compute::cmd_compute_barrier(self.device, command_buffer);
+            self.scan.cmd_scan(command_buffer, group_count * RADIX);
+            self.scatter.cmd_dispatch(command_buffer, descriptor_set, &constants, (group_count, 1, 1));
+            compute::cmd_compute_barrier(self.device, command_buffer);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Device to host copies. A `Readback` owns a host-visible buffer, a fence and an event: copies out
+//! of buffers or images get recorded into any command buffer, and the results can be read back as
+//! typed values once the fence (for its own submissions) or the event (for copies that went in
+//! with someone else's) says the GPU is done. Picking, screenshots, histograms and compute
+//! output checks all go through this instead of mapping their own staging memory.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::cell::Cell;
+use std::ptr;
+use std::thread;
+use vk::types::*;
+use ::immediate::ImmediateContext;
+use ::safe_create::{ self, Owned };
+use ::safe_info::{ self, SubmitInfoSafe };
+use ::vk_mem::{ self, MemoryAllocator, VkAllocation };
+
+pub struct Readback<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    buffer: Owned<'d, D, Buffer>,
+    memory: VkAllocation<'d, D>,
+    size: DeviceSize,
+    fence: Owned<'d, D, Fence>,
+    /// Set on the GPU after every recorded copy, and reset once it's been waited for.
+    event: Owned<'d, D, Event>,
+    /// Whether copies were recorded since the last wait.
+    recorded: Cell<bool>,
+    /// Whether they went through `submit`, so the fence can be waited for.
+    pending: bool,
+}
+
+impl<'d, D: DeviceV1_0> Readback<'d, D> {
+    /// Creates a readback buffer of `size` bytes. Host-cached memory is preferred, since the CPU
+    /// reads from it; any host-visible coherent memory will do otherwise.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, size: DeviceSize) -> VkResult<Readback<'d, D>> {
+        let create_info = BufferCreateInfo {
+            s_type: StructureType::BufferCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            size: size,
+            usage: BUFFER_USAGE_TRANSFER_DST_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+        };
+        let buffer = safe_create::create_buffer_safe(device, &create_info, None)?;
+        let requirements = device.get_buffer_memory_requirements(*buffer);
+        let host_coherent = MEMORY_PROPERTY_HOST_VISIBLE_BIT | MEMORY_PROPERTY_HOST_COHERENT_BIT;
+        let host_cached = host_coherent | MEMORY_PROPERTY_HOST_CACHED_BIT;
+        let flags = match vk_mem::find_memory_type_index(allocator.memory_properties(), requirements.memory_type_bits, host_cached) {
+            Some(_) => host_cached,
+            None => host_coherent,
+        };
+        let memory = allocator.allocate_buffer_memory(*buffer, flags)?;
+        let fence_create_info = FenceCreateInfo {
+            s_type: StructureType::FenceCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+        };
+        let event_create_info = EventCreateInfo {
+            s_type: StructureType::EventCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+        };
+        Ok(Readback {
+            device: device,
+            buffer: buffer,
+            memory: memory,
+            size: size,
+            fence: safe_create::create_fence_safe(device, &fence_create_info, None)?,
+            event: safe_create::create_event_safe(device, &event_create_info, None)?,
+            recorded: Cell::new(false),
+            pending: false,
+        })
+    }
+
+    /// Makes the transfer writes into the readback buffer visible to the host, and sets the event
+    /// once they are.
+    unsafe fn cmd_host_barrier(&self, command_buffer: CommandBuffer) {
+        let to_host = BufferMemoryBarrier {
+            s_type: StructureType::BufferMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            dst_access_mask: ACCESS_HOST_READ_BIT,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            buffer: *self.buffer,
+            offset: 0,
+            size: self.size,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_HOST_BIT, Default::default(), &[], &[to_host], &[]);
+        self.device.fp_v1_0().cmd_set_event(command_buffer, *self.event, PIPELINE_STAGE_TRANSFER_BIT);
+        self.recorded.set(true);
+    }
+
+    /// Records a copy of `size` bytes at `offset` in `src_buffer`. `src_stage` and `src_access`
+    /// describe the writes that produced the data, e.g. a compute shader's storage writes.
+    pub unsafe fn cmd_copy_buffer(&self, command_buffer: CommandBuffer, src_buffer: Buffer, offset: DeviceSize, size: DeviceSize, src_stage: PipelineStageFlags, src_access: AccessFlags) {
+        assert!(size <= self.size);
+        let to_transfer = BufferMemoryBarrier {
+            s_type: StructureType::BufferMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: src_access,
+            dst_access_mask: ACCESS_TRANSFER_READ_BIT,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            buffer: src_buffer,
+            offset: offset,
+            size: size,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, src_stage, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[to_transfer], &[]);
+        let region = BufferCopy {
+            src_offset: offset,
+            dst_offset: 0,
+            size: size,
+        };
+        self.device.cmd_copy_buffer(command_buffer, src_buffer, *self.buffer, &[region]);
+        self.cmd_host_barrier(command_buffer);
+    }
+
+    /// Records a tightly-packed copy of one layer of mip 0 of `image`, whose texels are
+    /// `texel_size` bytes. The image is moved from `layout` to `TransferSrcOptimal` for the copy
+    /// and back again afterwards; `src_stage` and `src_access` describe both the writes before the
+    /// copy and the accesses after it.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn cmd_copy_image(&self, command_buffer: CommandBuffer, image: Image, layout: ImageLayout, aspect_mask: ImageAspectFlags, extent: &Extent2D, texel_size: DeviceSize, layer: u32, src_stage: PipelineStageFlags, src_access: AccessFlags) {
+        assert!(extent.width as DeviceSize * extent.height as DeviceSize * texel_size <= self.size);
+        let subresource_range = ImageSubresourceRange {
+            aspect_mask: aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: layer,
+            layer_count: 1,
+        };
+        let to_transfer_src = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: src_access,
+            dst_access_mask: ACCESS_TRANSFER_READ_BIT,
+            old_layout: layout,
+            new_layout: ImageLayout::TransferSrcOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: image,
+            subresource_range: subresource_range.clone(),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, src_stage, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[to_transfer_src]);
+        let region = BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: ImageSubresourceLayers {
+                aspect_mask: aspect_mask,
+                mip_level: 0,
+                base_array_layer: layer,
+                layer_count: 1,
+            },
+            image_offset: Offset3D {
+                x: 0,
+                y: 0,
+                z: 0,
+            },
+            image_extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+        };
+        self.device.cmd_copy_image_to_buffer(command_buffer, image, ImageLayout::TransferSrcOptimal, *self.buffer, &[region]);
+        let restore = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: Default::default(),
+            dst_access_mask: src_access,
+            old_layout: ImageLayout::TransferSrcOptimal,
+            new_layout: layout,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: image,
+            subresource_range: subresource_range,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, src_stage, Default::default(), &[], &[], &[restore]);
+        self.cmd_host_barrier(command_buffer);
+    }
+
+    /// Submits `command_buffer`, which must contain the copies, and tracks it with this
+    /// readback's fence.
+    pub fn submit(&mut self, queue: Queue, command_buffer: CommandBuffer) -> VkResult<()> {
+        assert!(!self.pending, "Readback submitted again before its previous results were read");
+        let submit_info = SubmitInfoSafe::new()
+            .command_buffer(command_buffer);
+        unsafe {
+            self.device.reset_fences(&[*self.fence])?;
+            safe_info::queue_submit_safe(self.device, queue, &[submit_info], *self.fence)?;
+        }
+        self.pending = true;
+        Ok(())
+    }
+
+    fn event_status(&self) -> Result {
+        unsafe { self.device.fp_v1_0().get_event_status(self.device.handle(), *self.event) }
+    }
+
+    /// Checks without blocking whether the recorded copies have finished.
+    pub fn is_ready(&self) -> bool {
+        if !self.recorded.get() {
+            true
+        } else if self.pending {
+            unsafe { self.device.get_fence_status(*self.fence) }.is_ok()
+        } else {
+            self.event_status() == Result::EventSet
+        }
+    }
+
+    /// Blocks until the recorded copies have finished. Copies that weren't submitted through
+    /// `submit` are polled for, so their command buffer has to have been submitted already.
+    pub fn wait(&mut self) -> VkResult<()> {
+        if !self.recorded.get() {
+            return Ok(());
+        }
+        if self.pending {
+            (unsafe { self.device.wait_for_fences(&[*self.fence], true, u64::MAX) })?;
+            self.pending = false;
+        } else {
+            loop {
+                match self.event_status() {
+                    Result::EventSet => break,
+                    Result::EventReset => thread::yield_now(),
+                    e => return Err(e),
+                }
+            }
+        }
+        match unsafe { self.device.fp_v1_0().reset_event(self.device.handle(), *self.event) } {
+            Result::Success => {},
+            e => return Err(e),
+        }
+        self.recorded.set(false);
+        Ok(())
+    }
+
+    /// Reads the buffer's contents as `count` values of `T`, waiting for the copies first.
+    pub fn read<T: Copy>(&mut self, count: usize) -> VkResult<Vec<T>> {
+        assert!((count * std::mem::size_of::<T>()) as DeviceSize <= self.size);
+        self.wait()?;
+        Ok(self.memory.read(0, count))
+    }
+
+    /// Copies `count` values of `T` out of `src_buffer` through `immediate` and returns them.
+    pub fn read_buffer<T: Copy>(&mut self, immediate: &ImmediateContext<D>, src_buffer: Buffer, offset: DeviceSize, count: usize, src_stage: PipelineStageFlags, src_access: AccessFlags) -> VkResult<Vec<T>> {
+        let size = (count * std::mem::size_of::<T>()) as DeviceSize;
+        immediate.immediate_submit(|command_buffer| unsafe {
+            self.cmd_copy_buffer(command_buffer, src_buffer, offset, size, src_stage, src_access);
+        })?;
+        self.read(count)
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for Readback<'d, D> {
+    fn drop(&mut self) {
+        if self.pending {
+            // The copies still reference the buffer
+            let _ = unsafe { self.device.wait_for_fences(&[*self.fence], true, u64::MAX) };
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Shorthand for `Extent2D`, `Offset2D`, `Rect2D` and `Viewport`, which are otherwise spelled out
+//! field by field everywhere a pass sets its viewport and scissor. They're `ash`'s types, so the
+//! helpers are extension traits and free constructors rather than `From` impls.
+use std;
+use vk::types::*;
+use ::math::prelude::div_ceil;
+
+pub fn extent(width: u32, height: u32) -> Extent2D {
+    Extent2D {
+        width: width,
+        height: height,
+    }
+}
+
+pub fn offset(x: i32, y: i32) -> Offset2D {
+    Offset2D {
+        x: x,
+        y: y,
+    }
+}
+
+pub fn rect(offset: Offset2D, extent: Extent2D) -> Rect2D {
+    Rect2D {
+        offset: offset,
+        extent: extent,
+    }
+}
+
+/// For pipelines whose viewport and scissor are dynamic, where the static extent doesn't matter.
+pub fn placeholder_extent() -> Extent2D {
+    extent(1, 1)
+}
+
+pub trait ExtentExt {
+    fn from_tuple(size: (u32, u32)) -> Self;
+    fn to_tuple(&self) -> (u32, u32);
+    /// Width over height, taking a zero height as 1.
+    fn aspect_ratio(&self) -> f32;
+    /// Both sides multiplied by `factor` and rounded, at least 1x1.
+    fn scaled(&self, factor: f32) -> Self;
+    /// Both sides divided by `divisor`, rounding up so the result covers all of this.
+    fn div_ceil(&self, divisor: u32) -> Self;
+    fn is_empty(&self) -> bool;
+    fn same_as(&self, other: &Self) -> bool;
+    /// All of it, at the origin.
+    fn to_rect(&self) -> Rect2D;
+    /// All of it, with depths from 0 to 1.
+    fn to_viewport(&self) -> Viewport;
+}
+
+impl ExtentExt for Extent2D {
+    fn from_tuple((width, height): (u32, u32)) -> Extent2D {
+        extent(width, height)
+    }
+
+    fn to_tuple(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / std::cmp::max(self.height, 1) as f32
+    }
+
+    fn scaled(&self, factor: f32) -> Extent2D {
+        let scale = |size: u32| std::cmp::max(1, (size as f32 * factor).round() as u32);
+        extent(scale(self.width), scale(self.height))
+    }
+
+    fn div_ceil(&self, divisor: u32) -> Extent2D {
+        extent(div_ceil(self.width, divisor), div_ceil(self.height, divisor))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    fn same_as(&self, other: &Extent2D) -> bool {
+        self.to_tuple() == other.to_tuple()
+    }
+
+    fn to_rect(&self) -> Rect2D {
+        rect(offset(0, 0), self.clone())
+    }
+
+    fn to_viewport(&self) -> Viewport {
+        self.to_rect().to_viewport()
+    }
+}
+
+pub trait OffsetExt {
+    fn from_tuple(position: (i32, i32)) -> Self;
+    fn to_tuple(&self) -> (i32, i32);
+}
+
+impl OffsetExt for Offset2D {
+    fn from_tuple((x, y): (i32, i32)) -> Offset2D {
+        offset(x, y)
+    }
+
+    fn to_tuple(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+
+pub trait RectExt {
+    /// The rect from `(x, y)` of `width` by `height`.
+    fn from_tuple(rect: (i32, i32, u32, u32)) -> Self;
+    /// One past the right and bottom edges.
+    fn end(&self) -> (i64, i64);
+    /// The overlap of the two, or `None` if they don't overlap.
+    fn intersect(&self, other: &Self) -> Option<Self> where Self: Sized;
+    fn contains(&self, x: i32, y: i32) -> bool;
+    /// The same area, with depths from 0 to 1.
+    fn to_viewport(&self) -> Viewport;
+}
+
+impl RectExt for Rect2D {
+    fn from_tuple((x, y, width, height): (i32, i32, u32, u32)) -> Rect2D {
+        rect(offset(x, y), extent(width, height))
+    }
+
+    fn end(&self) -> (i64, i64) {
+        let ((x, y), (width, height)) = (self.offset.to_tuple(), self.extent.to_tuple());
+        (x as i64 + width as i64, y as i64 + height as i64)
+    }
+
+    fn intersect(&self, other: &Rect2D) -> Option<Rect2D> {
+        let (x, y) = (std::cmp::max(self.offset.x, other.offset.x), std::cmp::max(self.offset.y, other.offset.y));
+        let (self_end, other_end) = (self.end(), other.end());
+        let (end_x, end_y) = (std::cmp::min(self_end.0, other_end.0), std::cmp::min(self_end.1, other_end.1));
+        if end_x <= x as i64 || end_y <= y as i64 {
+            return None;
+        }
+        Some(rect(offset(x, y), extent((end_x - x as i64) as u32, (end_y - y as i64) as u32)))
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        let (end_x, end_y) = self.end();
+        x >= self.offset.x && y >= self.offset.y && (x as i64) < end_x && (y as i64) < end_y
+    }
+
+    fn to_viewport(&self) -> Viewport {
+        Viewport {
+            x: self.offset.x as f32,
+            y: self.offset.y as f32,
+            width: self.extent.width as f32,
+            height: self.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+}
+
+pub trait ViewportExt {
+    /// The same area with depths from `min_depth` to `max_depth`.
+    fn with_depth(self, min_depth: f32, max_depth: f32) -> Self;
+    /// The pixels it covers, rounding outwards.
+    fn to_rect(&self) -> Rect2D;
+}
+
+impl ViewportExt for Viewport {
+    fn with_depth(mut self, min_depth: f32, max_depth: f32) -> Viewport {
+        self.min_depth = min_depth;
+        self.max_depth = max_depth;
+        self
+    }
+
+    fn to_rect(&self) -> Rect2D {
+        let (x, y) = (self.x.floor(), self.y.floor());
+        let (end_x, end_y) = ((self.x + self.width).ceil(), (self.y + self.height).ceil());
+        rect(offset(x as i32, y as i32), extent((end_x - x) as u32, (end_y - y) as u32))
+    }
+}
@@ -0,0 +1,2359 @@
+//! The playground's Vulkan setup and per-frame rendering, pulled out of `main` so the render
+//! loop can be driven by anything with a window, not just the one main loop. It comes in two
+//! parts, since nearly everything per-frame borrows the device: a `Context` owns the instance,
+//! surface and device, and a `Renderer` borrowing it owns the swapchain and everything sized to
+//! it, and records and presents frames with `draw_frame`.
+use ash;
+use ash::version::{ DeviceV1_0, EntryV1_0, InstanceV1_0, V1_0 };
+use glfw;
+use log::LogLevel;
+use std::os::raw::{ c_char, c_float };
+use std;
+use std::collections::BTreeSet;
+use std::ffi::{ CStr, CString };
+use std::fmt;
+use std::path::Path;
+use std::ptr;
+use std::time::{ Duration, Instant };
+use vk::types::*;
+use ::asset_import::{ AssetKind, Import };
+use ::background::{ self, BackgroundPolicy };
+use ::buffer::IndexedVertexBuffer;
+use ::camera::{ Camera, FlyControls };
+use ::camera_path::{ self, CameraPose, Playback, PlaybackConfig, Recording };
+use ::capabilities::{ self, DemoSelector, DeviceCapabilities };
+use ::cas::{ self, CasPass };
+use ::checkerboard::{ self, RenderMode };
+use ::cli::{ self, LaunchConfig };
+use ::command::{ CommandRecorder, RenderPassRecorder, RenderTarget };
+use ::compute;
+use ::cvar::Flag;
+use ::crash_report::Checkpoints;
+use ::debug_utils::{ self, DebugObject, DebugUtils, DebugUtilsMessenger, MessageFilter };
+use ::demos::{ self, DemoPass, DemoView, SharedLayouts };
+use ::device_score::DeviceScore;
+use ::depth_buffer::{ self, DepthBuffer };
+use ::format_cycle::SurfaceFormatCycle;
+use ::frame::{ self, FrameContext, FrameLoop };
+use ::format_support::{ self, FormatSupport, FormatUsage };
+use ::frame_diff::{ self, FrameDiff, FrameDiffConfig, FrameDiffer };
+use ::fixed_update::{ FixedTimestep, Interpolated };
+use ::frame_stats::{ AcquirePolicy, FrameOutcome, FrameStats };
+use ::gamma::{ self, GammaOutput, ImageFormatListCreateInfo, TextureEncoding };
+use ::gltf;
+use ::gpu_profiler::{ self, GpuProfiler };
+use ::half_res::{ self, EffectScales };
+use ::image::{ self, ImageData, SampledTexture, Texture };
+use ::immediate::ImmediateContext;
+use ::late_acquire::{ self, LatencyStats, PresentTiming };
+use ::limits::DeviceLimits;
+use ::logging::{ self, LogHandles };
+use ::obj;
+use ::math::{ Mat4, Quat, Vec3 };
+use ::math::prelude::clamp;
+use ::mesh::{ MeshData, MeshVertex };
+use ::monitor::{ self, DisplayConfig };
+use ::log_console::{ self, CellColor, ConsolePass, LogConsole };
+use ::hdr::{ self, ColorSpace, HdrMetadata };
+use ::msaa::{ self, MsaaConfig, MsaaSettings, MsaaTarget };
+use ::nan_check::{ self, NanCheckPass };
+use ::offscreen::{ OffscreenTarget, OffscreenUsage };
+use ::output_filter::{ self, FilterPass, OutputFilter };
+use ::overlay::{ self, Overlay, OverlayPass };
+use ::pipeline::GraphicsPipelineBuilder;
+use ::pipeline_cache::{ self, SafePipelineCache };
+use ::pipeline_compiler::{ AsyncPipelineCompiler, PipelineHandle };
+use ::pipeline_library::{ self, GraphicsPipelineLibraryFeatures };
+use ::pnext::PNextChain;
+use ::present_id::{ self, PresentIdFeatures };
+use ::present_queue::{ self, PresentOwnership, PresentSharing };
+use ::push_descriptor;
+use ::safe_create::{ self, FramebufferCreateInfoSafe, Owned, OwnedFramebuffer };
+use ::queue::{ self, QueueFamilyIndex, QueueIndex };
+use ::readback::Readback;
+use ::rect::{ self, ExtentExt };
+use ::sample_locations;
+use ::safe_ext::{ AcquireOutcome, PresentStatus, SafeSwapchain };
+use ::safe_info::SwapchainCreateInfoSafe;
+use ::screenshot;
+use ::shader_compile::{ self, ShaderError };
+use ::shader_printf;
+use ::splash::{ self, LoadingProgress, SplashPass };
+use ::subgroup;
+use ::swapchain_config::{ self, ImageCount, SwapchainConfig, SwapchainSettings };
+use ::time::{ self, Time };
+use ::timeline::{ self, TimelineSemaphore, TimelineSemaphoreFeatures };
+use ::uniform::{ SceneUniforms, UniformBuffers };
+use ::upscale::{ self, UpscalePass, Upscaler };
+use ::validation;
+use ::vk_mem::{ Destroy, MemoryAllocator, VkOwned };
+use ::watchdog::WatchdogHandle;
+use ::glfw_surface;
+
+const REQUIRED_EXTENSIONS: [&'static str; 1] = [
+    VK_KHR_SWAPCHAIN_EXTENSION_NAME
+];
+
+const CLEAR_VALUE: [c_float; 4] = [0.0, 0.0, 0.0, 0.0];
+
+const VERTEX_SHADER: &'static str = "shaders/vertex.vert.spv";
+const FRAGMENT_SHADER: &'static str = "shaders/fragment.frag.spv";
+pub const FULLSCREEN_VERTEX_SHADER: &'static str = "shaders/fullscreen_vertex.vert.spv";
+
+/// How long the demo list shows after the selection changes.
+const DEMO_LIST_SECONDS: u64 = 4;
+/// GPU profiler scope around what the selected demo records before the render pass.
+const DEMO_PREPARE_SCOPE: &'static str = "demo prepare";
+/// Seconds per step of the fly camera, which moves in fixed steps like the simulations so how
+/// far it goes doesn't depend on the frame rate.
+const CAMERA_STEP: f32 = 1.0 / 120.0;
+
+/// A quad facing +Z, as two clockwise triangles sharing the diagonal.
+fn quad_mesh() -> MeshData {
+    let vertex = |x: f32, y: f32| MeshVertex {
+        position: [x - 0.5, y - 0.5, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [x, y],
+    };
+    MeshData {
+        vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(1.0, 1.0), vertex(0.0, 1.0)],
+        indices: vec![0, 1, 2, 2, 3, 0],
+    }
+}
+
+/// Render into an sRGB swapchain if the surface has one, so output is gamma-correct for free
+const PREFER_SRGB: bool = true;
+
+static PREFERRED_FORMAT: SurfaceFormatKHR = SurfaceFormatKHR {
+    format: Format::R8g8b8a8Srgb,
+    color_space: ColorSpaceKHR::SrgbNonlinear
+};
+
+#[derive(Debug)]
+pub enum RendererError {
+    /// The Vulkan loader, or functions it should provide, couldn't be loaded.
+    Loading(String),
+    NoSuitableDevice,
+    /// The device or surface can't do what was asked of it.
+    Unsupported(String),
+    Shader(String, ShaderError),
+    Vk(Result),
+}
+
+impl RendererError {
+    pub fn is_device_lost(&self) -> bool {
+        matches!(*self, RendererError::Vk(Result::ErrorDeviceLost))
+    }
+}
+
+impl From<Result> for RendererError {
+    fn from(result: Result) -> RendererError {
+        RendererError::Vk(result)
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RendererError::Loading(ref what) => write!(f, "failed to load {}", what),
+            RendererError::NoSuitableDevice => write!(f, "could not find a suitable physical device"),
+            RendererError::Unsupported(ref what) => write!(f, "unsupported: {}", what),
+            RendererError::Shader(ref path, ref e) => write!(f, "failed to load shader {}: {}", path, e),
+            RendererError::Vk(result) => write!(f, "Vulkan error: {:?}", result),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn description(&self) -> &str {
+        match *self {
+            RendererError::Loading(..) => "failed to load Vulkan",
+            RendererError::NoSuitableDevice => "no suitable physical device",
+            RendererError::Unsupported(..) => "unsupported device or surface",
+            RendererError::Shader(..) => "failed to load shader",
+            RendererError::Vk(..) => "Vulkan error",
+        }
+    }
+}
+
+/// The command line flags of every option, for `cvar::init`.
+pub const FLAGS: &'static [&'static [Flag]] = &[
+    late_acquire::FLAGS,
+    present_queue::FLAGS,
+    shader_printf::FLAGS,
+    upscale::FLAGS,
+    half_res::FLAGS,
+    camera_path::FLAGS,
+    frame_diff::FLAGS,
+    background::FLAGS,
+    monitor::FLAGS,
+    msaa::FLAGS,
+    checkerboard::FLAGS,
+    hdr::FLAGS,
+    nan_check::FLAGS,
+    cli::FLAGS,
+    debug_utils::FLAGS,
+    swapchain_config::FLAGS,
+];
+
+/// Everything set from the command line.
+#[derive(Debug, Clone)]
+pub struct RendererOptions {
+    pub present_timing: PresentTiming,
+    pub present_stress: Option<PresentSharing>,
+    pub swapchain_images: ImageCount,
+    pub shader_printf: bool,
+    pub upscaler: Upscaler,
+    pub effect_scales: EffectScales,
+    pub camera_path: Option<PlaybackConfig>,
+    pub frame_diff: Option<FrameDiffConfig>,
+    /// What the main loop does while the window is in the background.
+    pub background: BackgroundPolicy,
+    /// Which monitor and mode the window goes fullscreen in, if it does.
+    pub display: DisplayConfig,
+    pub msaa: Option<MsaaConfig>,
+    /// Whether the checkerboard demo shades half of the pixels a frame, or all of them.
+    pub render_mode: RenderMode,
+    /// Whether to use an HDR color space if the surface has one.
+    pub hdr: bool,
+    /// Whether to check the final image for NaN and out-of-range pixels.
+    pub nan_check: bool,
+    /// Window size, device, present mode and validation.
+    pub launch: LaunchConfig,
+    /// Which validation messages get logged.
+    pub debug_messages: MessageFilter,
+}
+
+impl RendererOptions {
+    /// From the console variables, which `FLAGS` set from the command line.
+    pub fn from_cvars() -> RendererOptions {
+        let options = RendererOptions {
+            present_timing: PresentTiming::from_cvars(),
+            present_stress: PresentSharing::from_cvars(),
+            swapchain_images: ImageCount::from_cvars(),
+            shader_printf: shader_printf::requested(),
+            upscaler: Upscaler::from_cvars(),
+            effect_scales: EffectScales::from_cvars(),
+            camera_path: PlaybackConfig::from_cvars(),
+            frame_diff: FrameDiffConfig::from_cvars(),
+            background: BackgroundPolicy::from_cvars(),
+            display: DisplayConfig::from_cvars(),
+            msaa: MsaaConfig::from_cvars(),
+            render_mode: RenderMode::from_cvars(),
+            hdr: hdr::requested(),
+            nan_check: nan_check::requested(),
+            launch: LaunchConfig::from_cvars(),
+            debug_messages: MessageFilter::from_cvars(),
+        };
+        if options.present_timing == PresentTiming::LateAcquire && options.upscaler.is_active() {
+            warn!("Render scale and upscale filters don't apply with {}, rendering at full resolution", late_acquire::ARG);
+        }
+        if options.msaa.is_some() && options.present_timing == PresentTiming::LateAcquire {
+            warn!("MSAA doesn't apply with {}, rendering without it", late_acquire::ARG);
+        }
+        options
+    }
+
+    fn swapchain_config(&self, capabilities: &SurfaceCapabilitiesKHR) -> SwapchainConfig {
+        let config = SwapchainConfig::default().with_image_count(self.swapchain_images);
+        let config = match self.present_timing {
+            // The resolved MSAA image is copied in
+            PresentTiming::Direct if self.msaa.is_some() => config.with_transfer_dst(),
+            PresentTiming::Direct => config,
+            PresentTiming::LateAcquire => config.with_transfer_dst(),
+        };
+        // Demos that blit, like the compute gradient, need to copy in
+        let config = if capabilities.supported_usage_flags.subset(IMAGE_USAGE_TRANSFER_DST_BIT) {
+            config.with_transfer_dst()
+        } else {
+            config
+        };
+        // Screenshots copy out of the swapchain image, if the surface lets them
+        if capabilities.supported_usage_flags.subset(IMAGE_USAGE_TRANSFER_SRC_BIT) {
+            config.with_transfer_src()
+        } else {
+            config
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SwapChainSupportDetails {
+    pub capabilities: SurfaceCapabilitiesKHR,
+    pub formats: Vec<SurfaceFormatKHR>,
+    pub present_modes: Vec<PresentModeKHR>
+}
+
+impl SwapChainSupportDetails {
+    pub fn new(vk_surface: &ash::extensions::Surface, device: PhysicalDevice, surface: &SurfaceKHR) -> ash::prelude::VkResult<SwapChainSupportDetails> {
+        let capabilities = vk_surface.get_physical_device_surface_capabilities_khr(device, *surface)?;
+        let formats = vk_surface.get_physical_device_surface_formats_khr(device, *surface)?;
+        let present_modes = vk_surface.get_physical_device_surface_present_modes_khr(device, *surface)?;
+        let ret = SwapChainSupportDetails {
+            capabilities: capabilities,
+            formats: formats,
+            present_modes: present_modes
+        };
+        Ok(ret)
+    }
+
+    pub fn choose_format(&self) -> Option<&SurfaceFormatKHR> {
+        if self.formats.len() == 1 && self.formats[0].format == Format::Undefined {
+            debug!("Using preferred surface format: {:?}", &PREFERRED_FORMAT);
+            Some(&PREFERRED_FORMAT)
+        } else {
+            let ret = gamma::choose_surface_format(&self.formats, PREFER_SRGB);
+            if let Some(f) = ret {
+                debug!("Using device's surface format: {:?}", f);
+            }
+            ret
+        }
+    }
+
+    /// `requested` if the surface supports it, and the surface's preferred mode otherwise.
+    pub fn choose_present_mode(&self, requested: Option<PresentModeKHR>) -> Option<PresentModeKHR> {
+        if let Some(mode) = requested {
+            if self.present_modes.contains(&mode) {
+                debug!(target: logging::SWAPCHAIN, "Using requested presentation mode: {:?}", mode);
+                return Some(mode);
+            }
+            warn!("Present mode {:?} requested, but the surface only supports {:?}", mode, self.present_modes);
+        }
+        self.present_modes.iter().max().map(|&mode| {
+            debug!(target: logging::SWAPCHAIN, "Using presentation mode: {:?}", mode);
+            mode
+        })
+    }
+
+    pub fn choose_swap_extent(&self, window: &glfw::Window) -> Extent2D {
+        if self.capabilities.current_extent.width != u32::MAX {
+            debug!(target: logging::SWAPCHAIN, "Using device's preferred extent: {:?}", &self.capabilities.current_extent);
+            self.capabilities.current_extent.clone()
+        } else {
+            let (width_hint, height_hint) = window.get_size();
+            let hint = Extent2D::from_tuple((width_hint as u32, height_hint as u32));
+            let ret = rect::extent(clamp(hint.width, self.capabilities.min_image_extent.width, self.capabilities.max_image_extent.width), clamp(hint.height, self.capabilities.min_image_extent.height, self.capabilities.max_image_extent.height));
+            debug!(target: logging::SWAPCHAIN, "Using our generated swap extent: {:?}", &ret);
+            ret
+        }
+    }
+}
+
+fn required_extensions() -> Vec<CString> {
+    REQUIRED_EXTENSIONS
+        .iter()
+        .map(|&name| CString::new(name).unwrap())
+        .collect()
+}
+
+fn check_physical_device_extension_support<I, It, Cs>(instance: &I, device: PhysicalDevice, required_extensions: It) -> bool where
+    It: IntoIterator<Item=Cs>,
+    I: InstanceV1_0,
+    Cs: AsRef<CStr>
+{
+    let available_extensions: Vec<&CStr> = instance.enumerate_device_extension_properties(device).unwrap_or(vec![])
+        .iter()
+        .map(|extension_properties| unsafe { CStr::from_ptr(&extension_properties.extension_name as *const c_char) })
+        .collect();
+    required_extensions.into_iter().all(|required_name| {
+        available_extensions.contains(&required_name.as_ref())
+    })
+}
+
+/// The instance with its debug messenger and the window's surface.
+pub struct InstanceContext {
+    entry: ash::Entry<V1_0>,
+    instance: ash::Instance<V1_0>,
+    /// `None` if the instance doesn't have `VK_EXT_debug_utils`.
+    debug_utils: Option<DebugUtils>,
+    debug_messenger: DebugUtilsMessenger,
+    vk_surface: ash::extensions::Surface,
+    surface: SurfaceKHR,
+    /// Whether shader printf was requested and the instance supports it.
+    shader_printf: bool,
+    /// Whether HDR was requested and the instance lists HDR color spaces.
+    swapchain_colorspace: bool,
+}
+
+impl InstanceContext {
+    /// The validation layer is enabled with `validation`, or when shader printf needs it.
+    pub fn new(glfw: &glfw::Glfw, window: &glfw::Window, validation: bool, debug_messages: &MessageFilter, shader_printf_requested: bool, hdr_requested: bool) -> std::result::Result<InstanceContext, RendererError> {
+        let entry: ash::Entry<V1_0> = ash::Entry::new().map_err(|e| RendererError::Loading(format!("the Vulkan loader: {:?}", e)))?;
+        let shader_printf = shader_printf_requested && {
+            let supported = shader_printf::instance_supported(&entry);
+            if !supported {
+                warn!("Shader printf requested, but {} doesn't provide {}", shader_printf::VALIDATION_LAYER, shader_printf::VALIDATION_FEATURES_EXTENSION);
+            }
+            supported
+        };
+        let debug_utils_supported = debug_utils::instance_supported(&entry);
+        if !debug_utils_supported {
+            warn!("The instance doesn't have {}, so validation messages won't be logged", debug_utils::EXTENSION);
+        }
+        let swapchain_colorspace = hdr_requested && {
+            let supported = hdr::instance_supported(&entry);
+            if !supported {
+                warn!("HDR requested, but the instance doesn't have {}", hdr::COLORSPACE_EXTENSION);
+            }
+            supported
+        };
+
+        let application_name = CString::new(::TITLE).unwrap();
+        let engine_name = CString::new("No Engine").unwrap();
+        let application_info = ApplicationInfo {
+            s_type: StructureType::ApplicationInfo,
+            p_next: ptr::null(),
+            p_application_name: application_name.as_ptr(),
+            application_version: vk_make_version!(0, 1, 0),
+            p_engine_name: engine_name.as_ptr(),
+            engine_version: vk_make_version!(0, 1, 0),
+            api_version: subgroup::instance_api_version(&entry)
+        };
+        let mut create_info = InstanceCreateInfo {
+            s_type: StructureType::InstanceCreateInfo,
+            p_next: ptr::null(),
+            flags: InstanceCreateFlags::default(),
+            p_application_info: &application_info,
+            enabled_layer_count: 0,
+            pp_enabled_layer_names: ptr::null(),
+            enabled_extension_count: 0,
+            pp_enabled_extension_names: ptr::null()
+        };
+        use std::borrow::Cow;
+        let required_extensions: Vec<CString> = glfw.get_required_instance_extensions().unwrap_or(vec![])
+            .into_iter()
+            .map(Cow::from)
+            .chain(if debug_utils_supported { Some(Cow::from(debug_utils::EXTENSION)) } else { None })
+            .chain(if shader_printf { Some(Cow::from(shader_printf::VALIDATION_FEATURES_EXTENSION)) } else { None })
+            .chain(if swapchain_colorspace { Some(Cow::from(hdr::COLORSPACE_EXTENSION)) } else { None })
+            .map(|cow| CString::new(&*cow).unwrap())
+            .collect();
+        debug!("Requiring extensions: {:?}", required_extensions.as_slice());
+        let required_extensions_ptrs: Vec<*const c_char> = required_extensions
+            .iter()
+            .map(|s| s.as_ptr())
+            .collect();
+        create_info.enabled_extension_count = required_extensions_ptrs.len() as u32;
+        create_info.pp_enabled_extension_names = required_extensions_ptrs.as_slice().as_ptr();
+        if shader_printf && !validation {
+            info!("Enabling the validation layer anyway, since shader printf goes through it");
+        }
+        let validation_layer = match (shader_printf, validation) {
+            // `shader_printf` is only set if its layer is installed
+            (true, _) => Some(shader_printf::VALIDATION_LAYER),
+            (false, true) => {
+                let layer = validation::choose_layer(&entry);
+                match layer {
+                    Some(layer) => debug!("Using validation layer {}", layer),
+                    None => warn!("Validation requested, but none of {:?} are installed. Running without validation", validation::LAYERS),
+                }
+                layer
+            },
+            (false, false) => None,
+        };
+        let validation_layers: Vec<CString> = validation_layer.into_iter()
+            .map(|s| CString::new(s).unwrap())
+            .collect();
+        let validation_layers_ptrs: Vec<*const c_char> = validation_layers
+            .iter()
+            .map(|s| s.as_ptr())
+            .collect();
+        create_info.enabled_layer_count = validation_layers_ptrs.len() as u32;
+        create_info.pp_enabled_layer_names = validation_layers_ptrs.as_slice().as_ptr();
+        let mut validation_features = shader_printf::ValidationFeaturesRaw::debug_printf();
+        let instance_next = PNextChain::new()
+            .push_optional(if shader_printf { Some(&mut validation_features) } else { None });
+        create_info.p_next = instance_next.head();
+        let instance = entry.create_instance(&create_info, None).map_err(|e| RendererError::Loading(format!("the instance: {:?}", e)))?;
+
+        let debug_utils = if debug_utils_supported { DebugUtils::new(&entry, &instance) } else { None };
+        let vk_surface = match ash::extensions::Surface::new(&entry, &instance) {
+            Ok(vk_surface) => vk_surface,
+            Err(missing) => {
+                unsafe { instance.destroy_instance(None) };
+                return Err(RendererError::Loading(format!("instance functions {:?}", missing)));
+            },
+        };
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = InstanceContext {
+            entry: entry,
+            instance: instance,
+            debug_utils: debug_utils,
+            debug_messenger: DebugUtilsMessenger::null(),
+            vk_surface: vk_surface,
+            surface: SurfaceKHR::null(),
+            shader_printf: shader_printf,
+            swapchain_colorspace: swapchain_colorspace,
+        };
+        if let Some(ref debug_utils) = ret.debug_utils {
+            ret.debug_messenger = debug_utils.create_messenger(debug_messages)?;
+        }
+        ret.surface = unsafe { glfw_surface::create_window_surface(&ret.instance, window, None) }?;
+        Ok(ret)
+    }
+
+    pub fn instance(&self) -> &ash::Instance<V1_0> {
+        &self.instance
+    }
+
+    pub fn debug_utils(&self) -> Option<&DebugUtils> {
+        self.debug_utils.as_ref()
+    }
+}
+
+impl Drop for InstanceContext {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying surface: {:?}", self.surface);
+            self.vk_surface.destroy_surface_khr(self.surface, None);
+            if let Some(ref debug_utils) = self.debug_utils {
+                trace!("Destroying debug messenger: {:?}", self.debug_messenger);
+                debug_utils.destroy_messenger(self.debug_messenger);
+            }
+            debug!("Destroying instance");
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+/// The chosen physical device, the logical device created on it, and what was learned about
+/// both along the way.
+pub struct Context {
+    pub options: RendererOptions,
+    pub physical_device: PhysicalDevice,
+    pub graphics_family: QueueFamilyIndex,
+    pub presentation_family: QueueFamilyIndex,
+    /// For compute work that doesn't have to be on the graphics queue. May be `graphics_family`.
+    pub compute_family: QueueFamilyIndex,
+    pub present_sharing: PresentSharing,
+    /// Its `color_space` is only right for sRGB, which is all `ash` can name, so the real one is
+    /// `color_space`.
+    pub surface_format: SurfaceFormatKHR,
+    pub color_space: ColorSpace,
+    pub present_mode: PresentModeKHR,
+    /// Of the scene's depth buffers.
+    pub depth_format: Format,
+    /// What the device can do of `options.msaa`.
+    pub msaa: Option<MsaaSettings>,
+    pub capabilities: DeviceCapabilities,
+    pub limits: DeviceLimits,
+    pub enabled_extensions: Vec<String>,
+    pub enabled_features: PhysicalDeviceFeatures,
+    /// Where every buffer and image gets its memory.
+    pub allocator: MemoryAllocator<ash::Device<V1_0>>,
+    pub graphics_queue: Queue,
+    pub presentation_queue: Queue,
+    pub compute_queue: Queue,
+    device: ash::Device<V1_0>,
+    // Dropped after `device` is destroyed in `Drop`
+    instance: InstanceContext,
+}
+
+impl Context {
+    pub fn new(glfw: &glfw::Glfw, window: &glfw::Window, options: &RendererOptions) -> std::result::Result<Context, RendererError> {
+        let instance_context = InstanceContext::new(glfw, window, options.launch.validation, &options.debug_messages, options.shader_printf, options.hdr)?;
+        let required_extensions = required_extensions();
+        let (physical_device, graphics_family, presentation_family, surface_format, present_mode) = {
+            let instance = &instance_context.instance;
+            let vk_surface = &instance_context.vk_surface;
+            let surface = instance_context.surface;
+            let devices = instance.enumerate_physical_devices()?;
+            debug!("Found {} possible physical device(s): {:?}", devices.len(), &devices);
+            let devices: Vec<PhysicalDevice> = match options.launch.device {
+                Some(ref selector) => {
+                    let selected: Vec<PhysicalDevice> = devices.into_iter()
+                        .enumerate()
+                        .filter(|&(idx, dev)| {
+                            let properties = instance.get_physical_device_properties(dev);
+                            selector.matches(idx, &unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy())
+                        })
+                        .map(|(_, dev)| dev)
+                        .collect();
+                    if selected.is_empty() {
+                        warn!("No physical device matches {:?}", selector);
+                    }
+                    selected
+                },
+                None => devices,
+            };
+            for extension in REQUIRED_EXTENSIONS.iter() {
+                debug!("Manually requiring extension: {:?}", extension);
+            }
+            devices.into_iter()
+                .flat_map(|dev| {
+                    let queue_families = instance.get_physical_device_queue_family_properties(dev);
+                    let gfx_families: BTreeSet<QueueFamilyIndex> = QueueFamilyIndex::enumerate(&queue_families)
+                        .filter(|&(_, queue_family)| queue_family.queue_count > 0 && queue_family.queue_flags.subset(QUEUE_GRAPHICS_BIT))
+                        .map(|(idx, _)| idx)
+                        .collect();
+                    let presentation_families: BTreeSet<QueueFamilyIndex> = QueueFamilyIndex::enumerate(&queue_families)
+                        .map(|(idx, _)| idx)
+                        .filter(|&idx| vk_surface.get_physical_device_surface_support_khr(dev, idx.raw(), surface))
+                        .collect();
+                    present_queue::choose_families(&gfx_families, &presentation_families, options.present_stress.is_some())
+                        .map(|(g, p)| (dev, g, p))
+                })
+                .filter(|&(dev, _, _)| check_physical_device_extension_support(instance, dev, &required_extensions))
+                .flat_map(|(dev, gfx, present)| {
+                    let details = match SwapChainSupportDetails::new(vk_surface, dev, &surface) {
+                        Ok(details) => details,
+                        Err(_) => return None,
+                    };
+                    let format = details.choose_format().cloned();
+                    let present_mode = details.choose_present_mode(options.launch.present_mode);
+                    format
+                        .and_then(|format| {
+                            present_mode
+                                .map(|present_mode| (dev, gfx, present, format, present_mode))
+                        })
+                })
+                .filter(|&(dev, gfx, _, _, _)| compute::choose_family(&instance.get_physical_device_queue_family_properties(dev), gfx).is_some())
+                .map(|candidate| {
+                    let dev = candidate.0;
+                    let properties = instance.get_physical_device_properties(dev);
+                    let score = DeviceScore::new(&properties, &instance.get_physical_device_features(dev), &instance.get_physical_device_memory_properties(dev), &instance.get_physical_device_queue_family_properties(dev), candidate.1, candidate.2);
+                    debug!("Physical device {:?} ({}) scores {}: {:?}", dev, unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy(), score.total(), score);
+                    (score.total(), candidate)
+                })
+                .max_by_key(|&(total, _)| total)
+                .map(|(_, candidate)| candidate)
+                .ok_or(RendererError::NoSuitableDevice)?
+        };
+        debug!("Found suitable physical device: {:?}", physical_device);
+        let capabilities = DeviceCapabilities::query(&instance_context.entry, &instance_context.instance, physical_device, capabilities::DEMOS);
+        match capabilities.subgroup {
+            Some(properties) => properties.log(),
+            None => info!("Subgroup properties aren't available, compute passes will use scalar fallbacks"),
+        }
+        let limits = DeviceLimits::new(&capabilities.properties.limits);
+        let demos = DemoSelector::new(capabilities::DEMOS, &capabilities);
+        demos.log();
+        debug!("Using graphics queue family: {}", graphics_family);
+        debug!("Using presentation queue family: {}", presentation_family);
+        let queue_families = instance_context.instance.get_physical_device_queue_family_properties(physical_device);
+        let compute_family = compute::choose_family(&queue_families, graphics_family).ok_or(RendererError::NoSuitableDevice)?;
+        debug!("Using compute queue family: {}", compute_family);
+        let present_sharing = PresentSharing::resolve(options.present_stress, graphics_family, presentation_family);
+        debug!("Using present sharing: {:?}", present_sharing);
+        let mut surface_format = surface_format;
+        let mut color_space = ColorSpace::SRGB_NONLINEAR;
+        if instance_context.swapchain_colorspace {
+            let formats = hdr::surface_formats(&instance_context.entry, &instance_context.instance, physical_device, instance_context.surface);
+            match hdr::choose_surface_format(&formats) {
+                Some((format, hdr_color_space)) => {
+                    surface_format.format = format;
+                    color_space = hdr_color_space;
+                },
+                None => warn!("HDR requested, but the surface only offers {:?}", formats.iter().map(|&(format, color_space)| format!("{:?} {}", format, color_space)).collect::<Vec<String>>()),
+            }
+        }
+        debug!("Using surface format: {:?} in {}", &surface_format.format, color_space);
+        debug!("Using present mode: {:?}", present_mode);
+        let depth_format = format_support::depth_format(&instance_context.instance, physical_device, false, false)
+            .ok_or(RendererError::Unsupported("any depth attachment format".to_string()))?;
+
+        let mut device_features: PhysicalDeviceFeatures = Default::default();
+        demos.enable_features(&mut device_features);
+        let printf_extension = CString::new(shader_printf::NON_SEMANTIC_INFO_EXTENSION).unwrap();
+        let enable_printf = instance_context.shader_printf && {
+            let supported = shader_printf::device_supported(&instance_context.instance, physical_device);
+            if !supported {
+                warn!("Shader printf requested, but the device doesn't support {}", shader_printf::NON_SEMANTIC_INFO_EXTENSION);
+            }
+            supported
+        };
+        let msaa = options.msaa.and_then(|config| config.resolve(&instance_context.entry, &instance_context.instance, physical_device, depth_format, &capabilities.extensions));
+        let depth_resolve_extensions: Vec<CString> = match msaa {
+            Some(MsaaSettings { depth_resolve: Some(_), .. }) => msaa::DEPTH_RESOLVE_EXTENSIONS.iter().map(|&name| CString::new(name).unwrap()).collect(),
+            _ => Vec::new(),
+        };
+        let sample_locations_extension = CString::new(sample_locations::EXTENSION_NAME).unwrap();
+        let enable_sample_locations = msaa.is_some_and(|settings| settings.sample_locations.is_some());
+        let hdr_metadata_extension = CString::new(hdr::METADATA_EXTENSION).unwrap();
+        let enable_hdr_metadata = color_space.is_hdr() && {
+            let supported = capabilities.extensions.iter().any(|name| name == hdr::METADATA_EXTENSION);
+            if !supported {
+                warn!("The device doesn't have {}, so the display won't get HDR metadata", hdr::METADATA_EXTENSION);
+            }
+            supported
+        };
+        // Optional, the passes that push descriptors allocate sets without it
+        let push_descriptor_extension = CString::new(push_descriptor::EXTENSION_NAME).unwrap();
+        let enable_push_descriptor = capabilities.extensions.iter().any(|name| name == push_descriptor::EXTENSION_NAME);
+        // Optional, frames are only tracked by their fences without it
+        let timeline_extension = CString::new(timeline::EXTENSION_NAME).unwrap();
+        let enable_timeline = timeline::is_supported(&capabilities);
+        // Optional, presents just aren't tagged without it
+        let present_id_extension = CString::new(present_id::EXTENSION_NAME).unwrap();
+        let enable_present_id = present_id::is_supported(&capabilities);
+        // Optional, UNORM swapchains are encoded by hand without them
+        let mutable_format_extensions: Vec<CString> = if gamma::MUTABLE_FORMAT_EXTENSIONS.iter().all(|&name| capabilities.extensions.iter().any(|extension| extension == name)) {
+            gamma::MUTABLE_FORMAT_EXTENSIONS.iter().map(|&name| CString::new(name).unwrap()).collect()
+        } else {
+            Vec::new()
+        };
+        let demo_extensions: Vec<CString> = demos.extensions().iter()
+            .map(|&name| CString::new(name).unwrap())
+            .collect();
+        let mut enabled_extensions: Vec<&CString> = required_extensions.iter()
+            .chain(if enable_printf { Some(&printf_extension) } else { None })
+            .chain(depth_resolve_extensions.iter())
+            .chain(if enable_sample_locations { Some(&sample_locations_extension) } else { None })
+            .chain(if enable_hdr_metadata { Some(&hdr_metadata_extension) } else { None })
+            .chain(if enable_push_descriptor { Some(&push_descriptor_extension) } else { None })
+            .chain(if enable_timeline { Some(&timeline_extension) } else { None })
+            .chain(if enable_present_id { Some(&present_id_extension) } else { None })
+            .chain(mutable_format_extensions.iter())
+            .chain(demo_extensions.iter())
+            .collect();
+        // Some share dependencies, and each may only be enabled once
+        enabled_extensions.sort();
+        enabled_extensions.dedup();
+        let mut library_features = if pipeline_library::is_enabled(enabled_extensions.iter().map(|name| name.to_str().unwrap())) {
+            Some(GraphicsPipelineLibraryFeatures::enabled())
+        } else {
+            None
+        };
+        let mut timeline_features = if enable_timeline { Some(TimelineSemaphoreFeatures::enabled()) } else { None };
+        let mut present_id_features = if enable_present_id { Some(PresentIdFeatures::enabled()) } else { None };
+
+        let device = {
+            let queue_priorities: [c_float; 2] = [1.0, 1.0];
+            let mut families: Vec<QueueFamilyIndex> = vec![graphics_family];
+            for &family in [presentation_family, compute_family].iter() {
+                if !families.contains(&family) {
+                    families.push(family);
+                }
+            }
+            let create_infos: Vec<DeviceQueueCreateInfo> = families.iter().map(|&family| DeviceQueueCreateInfo {
+                s_type: StructureType::DeviceQueueCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                queue_family_index: family.raw(),
+                queue_count: 1,
+                p_queue_priorities: queue_priorities.as_ptr(),
+            }).collect();
+
+            let required_extensions_data: Vec<*const c_char> = enabled_extensions.iter()
+                .map(|name| name.as_ptr())
+                .collect();
+
+            let device_next = PNextChain::new()
+                .push_optional(library_features.as_mut())
+                .push_optional(timeline_features.as_mut())
+                .push_optional(present_id_features.as_mut());
+            let create_info = DeviceCreateInfo {
+                s_type: StructureType::DeviceCreateInfo,
+                p_next: device_next.head(),
+                flags: Default::default(),
+                queue_create_info_count: create_infos.len() as u32,
+                p_queue_create_infos: create_infos.as_ptr(),
+                enabled_layer_count: 0,
+                pp_enabled_layer_names: ptr::null(),
+                enabled_extension_count: required_extensions_data.len() as u32,
+                pp_enabled_extension_names: required_extensions_data.as_slice().as_ptr(),
+                p_enabled_features: &device_features as *const PhysicalDeviceFeatures
+            };
+            (unsafe { instance_context.instance.create_device(physical_device, &create_info, None) }
+                .map_err(|e| RendererError::Loading(format!("the device: {:?}", e))))?
+        };
+        let graphics_queue = unsafe { queue::get_queue(&device, graphics_family, QueueIndex::FIRST) };
+        debug!("Using graphics queue: {:?}", graphics_queue);
+        let presentation_queue = if graphics_family == presentation_family {
+            graphics_queue
+        } else {
+            unsafe { queue::get_queue(&device, presentation_family, QueueIndex::FIRST) }
+        };
+        debug!("Using presentation queue: {:?}", presentation_queue);
+        let compute_queue = if compute_family == graphics_family {
+            graphics_queue
+        } else {
+            unsafe { queue::get_queue(&device, compute_family, QueueIndex::FIRST) }
+        };
+        debug!("Using compute queue: {:?}", compute_queue);
+        if let Some(debug_utils) = instance_context.debug_utils() {
+            debug_utils.set_object_name(device.handle(), &device.handle(), "Smolder device");
+        }
+        let memory_properties = instance_context.instance.get_physical_device_memory_properties(physical_device);
+        Ok(Context {
+            options: options.clone(),
+            physical_device: physical_device,
+            graphics_family: graphics_family,
+            presentation_family: presentation_family,
+            compute_family: compute_family,
+            present_sharing: present_sharing,
+            surface_format: surface_format,
+            color_space: color_space,
+            present_mode: present_mode,
+            depth_format: depth_format,
+            msaa: msaa,
+            capabilities: capabilities,
+            limits: limits,
+            enabled_extensions: enabled_extensions.iter().map(|name| name.to_string_lossy().into_owned()).collect(),
+            enabled_features: device_features,
+            allocator: MemoryAllocator::new(device.clone(), memory_properties),
+            graphics_queue: graphics_queue,
+            presentation_queue: presentation_queue,
+            compute_queue: compute_queue,
+            device: device,
+            instance: instance_context,
+        })
+    }
+
+    pub fn device(&self) -> &ash::Device<V1_0> {
+        &self.device
+    }
+
+    pub fn instance(&self) -> &InstanceContext {
+        &self.instance
+    }
+
+    /// Names `object` in validation messages and captures, if the instance has
+    /// `VK_EXT_debug_utils`.
+    pub fn set_debug_name<T: DebugObject>(&self, object: &T, name: &str) {
+        if let Some(debug_utils) = self.instance.debug_utils() {
+            debug_utils.set_object_name(self.device.handle(), object, name);
+        }
+    }
+
+    /// Whether swapchains can be created with `gamma::MUTABLE_FORMAT_EXTENSIONS`.
+    pub fn mutable_swapchain_format(&self) -> bool {
+        gamma::MUTABLE_FORMAT_EXTENSIONS.iter().all(|&name| self.enabled_extensions.iter().any(|extension| extension == name))
+    }
+
+    fn swapchain_support(&self) -> ash::prelude::VkResult<SwapChainSupportDetails> {
+        SwapChainSupportDetails::new(&self.instance.vk_surface, self.physical_device, &self.instance.surface)
+    }
+
+    /// Every format and color space the surface offers. Color spaces other than sRGB only show up
+    /// when the instance has `hdr::COLORSPACE_EXTENSION`.
+    pub fn surface_formats(&self) -> Vec<(Format, ColorSpace)> {
+        if self.instance.swapchain_colorspace {
+            return hdr::surface_formats(&self.instance.entry, &self.instance.instance, self.physical_device, self.instance.surface);
+        }
+        self.swapchain_support()
+            .map(|support| support.formats.iter().map(|format| (format.format, ColorSpace::SRGB_NONLINEAR)).collect())
+            .unwrap_or(vec![])
+    }
+
+    pub fn device_name(&self) -> String {
+        unsafe { CStr::from_ptr(self.capabilities.properties.device_name.as_ptr()) }.to_string_lossy().into_owned()
+    }
+
+    /// The device and everything enabled on it, for crash reports.
+    pub fn describe(&self) -> String {
+        let properties = &self.capabilities.properties;
+        let mut ret = String::new();
+        ret.push_str(&format!("Device: {:?} ({:?})\n", unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }, properties.device_type));
+        ret.push_str(&format!("Vendor: {:#06x}, device: {:#06x}, driver version: {:#x}\n", properties.vendor_id, properties.device_id, properties.driver_version));
+        ret.push_str(&format!("API version: {}.{}.{}\n", properties.api_version >> 22, (properties.api_version >> 12) & 0x3ff, properties.api_version & 0xfff));
+        ret.push_str(&format!("Graphics queue family: {}, presentation queue family: {}, compute queue family: {}, sharing: {:?}\n", self.graphics_family, self.presentation_family, self.compute_family, self.present_sharing));
+        ret.push_str(&format!("Surface format: {:?} in {}, present mode: {:?}\n", self.surface_format.format, self.color_space, self.present_mode));
+        ret.push_str("Enabled extensions:\n");
+        for extension in self.enabled_extensions.iter() {
+            ret.push_str(&format!("    {}\n", extension));
+        }
+        ret.push_str(&format!("Enabled features: {:?}\n", self.enabled_features));
+        ret.push_str(&format!("Limits: {:?}\n", properties.limits));
+        ret
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.allocator.free_blocks();
+            trace!("Destroying device");
+            self.device.destroy_device(None);
+        }
+    }
+}
+
+pub fn load_shader_module<'d, D: DeviceV1_0>(device: &'d D, path: &str) -> std::result::Result<safe_create::Owned<'d, D, ShaderModule>, RendererError> {
+    let code = shader_compile::load_spirv(path).map_err(|e| RendererError::Shader(path.to_string(), e))?;
+    let create_info = ShaderModuleCreateInfo {
+        s_type: StructureType::ShaderModuleCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        code_size: code.len(),
+        p_code: code.as_ptr() as *const u32,
+    };
+    Ok(safe_create::create_shader_module_safe(device, &create_info, None)?)
+}
+
+/// `Some` pass, or `None` with a warning if its shaders couldn't be loaded, so a missing SPIR-V
+/// file, e.g. with no shader compiler installed, turns off an optional pass instead of failing
+/// startup. Any other error is still returned.
+fn optional_pass<T>(name: &str, res: std::result::Result<T, RendererError>) -> std::result::Result<Option<T>, RendererError> {
+    match res {
+        Ok(pass) => Ok(Some(pass)),
+        Err(RendererError::Shader(path, e)) => {
+            warn!(target: logging::SHADER, "Running without the {}, {:?} couldn't be loaded: {}", name, path, e);
+            Ok(None)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+fn create_cas_pass<'c>(context: &'c Context, filter_pass: &FilterPass<'c, ash::Device<V1_0>>) -> std::result::Result<CasPass<'c, ash::Device<V1_0>>, RendererError> {
+    let spirv = shader_compile::load_spirv(cas::SHADER).map_err(|e| RendererError::Shader(cas::SHADER.to_string(), e))?;
+    let source = filter_pass.source();
+    Ok(CasPass::new(&context.device, &context.allocator, &spirv, source.image(), source.view(), source.extent().clone())?)
+}
+
+fn create_console_pass<'c>(context: &'c Context, render_pass: RenderPass) -> std::result::Result<ConsolePass<'c, ash::Device<V1_0>>, RendererError> {
+    let vert_shader_module = load_shader_module(&context.device, FULLSCREEN_VERTEX_SHADER)?;
+    let frag_shader_module = load_shader_module(&context.device, log_console::FRAGMENT_SHADER)?;
+    Ok(ConsolePass::new(&context.device, &context.allocator, render_pass, frame::FRAMES_IN_FLIGHT, *vert_shader_module, *frag_shader_module)?)
+}
+
+fn create_filter_pass<'c>(context: &'c Context, format: Format, extent: Extent2D, render_pass: RenderPass) -> std::result::Result<FilterPass<'c, ash::Device<V1_0>>, RendererError> {
+    let vert_shader_module = load_shader_module(&context.device, FULLSCREEN_VERTEX_SHADER)?;
+    let frag_shader_module = load_shader_module(&context.device, output_filter::FRAGMENT_SHADER)?;
+    let gamma_shader_module = load_shader_module(&context.device, gamma::FRAGMENT_SHADER)?;
+    Ok(FilterPass::new(&context.device, &context.allocator, format, Some(context.depth_format), extent, render_pass, *vert_shader_module, *frag_shader_module, *gamma_shader_module)?)
+}
+
+fn create_nan_check_pass<'c>(context: &'c Context, render_pass: RenderPass) -> std::result::Result<NanCheckPass<'c, ash::Device<V1_0>>, RendererError> {
+    let spirv = shader_compile::load_spirv(nan_check::COUNT_SHADER).map_err(|e| RendererError::Shader(nan_check::COUNT_SHADER.to_string(), e))?;
+    let vert_shader_module = load_shader_module(&context.device, FULLSCREEN_VERTEX_SHADER)?;
+    let frag_shader_module = load_shader_module(&context.device, nan_check::OVERLAY_SHADER)?;
+    Ok(NanCheckPass::new(&context.device, &context.allocator, context.graphics_family, &spirv, render_pass, *vert_shader_module, *frag_shader_module)?)
+}
+
+/// The scene's color texture, from `data`, with mips when its format can be blitted into them.
+/// It comes from a file, so it's checked against the device's limits first.
+fn create_scene_texture<'c>(context: &'c Context, immediate: &ImmediateContext<ash::Device<V1_0>>, data: &ImageData) -> std::result::Result<SampledTexture<'c, ash::Device<V1_0>>, RendererError> {
+    let format = TextureEncoding::Color.format(Format::R8g8b8a8Unorm);
+    let support = FormatSupport::query(&context.instance.instance, context.physical_device, format);
+    if !support.supports(ImageTiling::Optimal, FormatUsage::SampledLinear) {
+        return Err(RendererError::Unsupported(format!("scene texture: {:?} can't be sampled with linear filtering", format)));
+    }
+    let mip_levels = image::mip_levels(&support, data);
+    context.limits.check_image(&image::texture_create_info(data, TextureEncoding::Color, mip_levels))
+        .map_err(|e| RendererError::Unsupported(format!("scene texture: {}", e)))?;
+    let texture = Texture::new(&context.device, &context.allocator, immediate, data, TextureEncoding::Color, mip_levels)?;
+    Ok(SampledTexture::new(&context.device, texture, SHADER_STAGE_FRAGMENT_BIT)?)
+}
+
+/// The frame loop on the graphics queue, which signals a timeline with each frame when the
+/// device has one.
+fn create_frame_loop<'c>(context: &'c Context) -> ash::prelude::VkResult<FrameLoop<'c, ash::Device<V1_0>>> {
+    let frame_loop = FrameLoop::new(&context.device, context.graphics_family, frame::FRAMES_IN_FLIGHT)?;
+    if context.enabled_extensions.iter().any(|name| name == timeline::EXTENSION_NAME) {
+        Ok(frame_loop.with_timeline(TimelineSemaphore::new(&context.instance.instance, &context.device, 0)?))
+    } else {
+        Ok(frame_loop)
+    }
+}
+
+fn create_overlay_pass<'c>(context: &'c Context, render_pass: RenderPass) -> std::result::Result<OverlayPass<'c, ash::Device<V1_0>>, RendererError> {
+    let vert_shader_module = load_shader_module(&context.device, FULLSCREEN_VERTEX_SHADER)?;
+    let frag_shader_module = load_shader_module(&context.device, overlay::FRAGMENT_SHADER)?;
+    Ok(OverlayPass::new(&context.device, render_pass, *vert_shader_module, *frag_shader_module)?)
+}
+
+/// The scene's render pass and pipeline. The pipeline compiles on a worker thread, with the splash
+/// showing until it's ready, and has a dynamic viewport, so none of this depends on the swapchain
+/// and it's kept across recreation. With MSAA there's a multisampled render pass and pipeline
+/// too, which the single-sampled ones are kept next to for the passes that don't multisample.
+struct ScenePipelines<'c> {
+    context: &'c Context,
+    device: &'c ash::Device<V1_0>,
+    render_pass: Owned<'c, ash::Device<V1_0>, RenderPass>,
+    /// With MSAA.
+    msaa_render_pass: Option<Owned<'c, ash::Device<V1_0>, RenderPass>>,
+    msaa: Option<MsaaSettings>,
+    layout: PipelineLayout,
+    /// Kept until the compiler is done with them.
+    shader_modules: Vec<ShaderModule>,
+    vertex_shader: ShaderModule,
+    compiler: AsyncPipelineCompiler<'c, ash::Device<V1_0>>,
+    /// `None` only while being created.
+    pipeline: Option<PipelineHandle>,
+    /// For `msaa_render_pass`, with MSAA.
+    msaa_pipeline: Option<PipelineHandle>,
+    /// Replace `pipeline` and `msaa_pipeline` once they're both ready, e.g. after importing a
+    /// fragment shader.
+    pending: Option<(PipelineHandle, Option<PipelineHandle>)>,
+    /// When loading started, until it's done.
+    loading_since: Option<Instant>,
+}
+
+impl<'c> ScenePipelines<'c> {
+    /// `uniforms_layout` is set 0 of the pipeline layout, for the `SceneUniforms` block, and
+    /// `texture_layout` is set 1, for the quad's texture.
+    /// `pipeline_cache` has to outlive it.
+    fn new(context: &'c Context, format: Format, pipeline_cache: PipelineCache, uniforms_layout: DescriptorSetLayout, texture_layout: DescriptorSetLayout) -> std::result::Result<ScenePipelines<'c>, RendererError> {
+        // Nothing draws until the pipeline is ready, so there's never a need for a fallback
+        let compiler = AsyncPipelineCompiler::new(&context.device, pipeline_cache, Pipeline::null(), 1);
+        let attachment_descriptions: [AttachmentDescription; 2] = [
+            AttachmentDescription {
+                flags: Default::default(),
+                format: format,
+                samples: SAMPLE_COUNT_1_BIT,
+                load_op: AttachmentLoadOp::Clear,
+                store_op: AttachmentStoreOp::Store,
+                stencil_load_op: AttachmentLoadOp::DontCare,
+                stencil_store_op: AttachmentStoreOp::DontCare,
+                initial_layout: ImageLayout::Undefined,
+                final_layout: ImageLayout::PresentSrcKhr,
+            },
+            depth_buffer::attachment_description(context.depth_format),
+        ];
+        let color_attachment_refs: [AttachmentReference; 1] = [AttachmentReference {
+            attachment: 0,
+            layout: ImageLayout::ColorAttachmentOptimal,
+        }];
+        let depth_attachment_ref = AttachmentReference {
+            attachment: 1,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+        };
+        let subpass_description = SubpassDescription {
+            flags: Default::default(),
+            pipeline_bind_point: PipelineBindPoint::Graphics,
+            input_attachment_count: 0,
+            p_input_attachments: ptr::null(),
+            color_attachment_count: color_attachment_refs.len() as u32,
+            p_color_attachments: color_attachment_refs.as_ptr(),
+            p_resolve_attachments: ptr::null(),
+            p_depth_stencil_attachment: &depth_attachment_ref,
+            preserve_attachment_count: 0,
+            p_preserve_attachments: ptr::null(),
+        };
+        // The depth buffer is shared by every swapchain image, so the last frame's depth writes
+        // have to finish before this one clears it
+        let dependencies: [SubpassDependency; 1] = [SubpassDependency {
+            src_subpass: VK_SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+            src_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+            dst_stage_mask: PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+            dst_access_mask: ACCESS_COLOR_ATTACHMENT_READ_BIT | ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+            dependency_flags: Default::default(),
+        }];
+        let render_pass_create_info = RenderPassCreateInfo {
+            s_type: StructureType::RenderPassCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            attachment_count: attachment_descriptions.len() as u32,
+            p_attachments: attachment_descriptions.as_ptr(),
+            subpass_count: 1,
+            p_subpasses: &subpass_description,
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+        };
+        let render_pass = safe_create::create_render_pass_safe(context.device(), &render_pass_create_info, None)?;
+        context.set_debug_name(&*render_pass, "scene render pass");
+        let msaa_render_pass = match context.msaa {
+            Some(ref settings) => Some(msaa::create_render_pass(&context.instance.instance, context.device(), settings, format, context.depth_format)?),
+            None => None,
+        };
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = ScenePipelines {
+            context: context,
+            device: &context.device,
+            render_pass: render_pass,
+            msaa_render_pass: msaa_render_pass,
+            msaa: context.msaa,
+            layout: PipelineLayout::null(),
+            shader_modules: Vec::new(),
+            vertex_shader: ShaderModule::null(),
+            compiler: compiler,
+            pipeline: None,
+            msaa_pipeline: None,
+            pending: None,
+            loading_since: Some(Instant::now()),
+        };
+
+        let set_layouts = [uniforms_layout, texture_layout];
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: 0,
+            p_push_constant_ranges: ptr::null(),
+        };
+        ret.layout = unsafe { context.device.create_pipeline_layout(&layout_create_info, None) }?;
+        context.set_debug_name(&ret.layout, "scene pipeline layout");
+        ret.vertex_shader = ret.load_shader_module(VERTEX_SHADER)?;
+        let frag_shader_module = ret.load_shader_module(FRAGMENT_SHADER)?;
+        let (pipeline, msaa_pipeline) = ret.request(frag_shader_module);
+        ret.pipeline = Some(pipeline);
+        ret.msaa_pipeline = msaa_pipeline;
+        Ok(ret)
+    }
+
+    /// Starts compiling the scene pipeline with `frag_shader_module`, and the multisampled one if
+    /// there's MSAA.
+    fn request(&mut self, frag_shader_module: ShaderModule) -> (PipelineHandle, Option<PipelineHandle>) {
+        let builder = scene_pipeline_builder(self.vertex_shader, frag_shader_module);
+        let msaa_pipeline = match (self.msaa, &self.msaa_render_pass) {
+            (Some(settings), Some(msaa_render_pass)) => {
+                let msaa_builder = match settings.locations() {
+                    Some(locations) => builder.clone().samples(settings.samples).sample_locations(locations),
+                    None => builder.clone().samples(settings.samples),
+                };
+                Some(self.compiler.request(msaa_builder, self.layout, **msaa_render_pass, 0))
+            },
+            _ => None,
+        };
+        (self.compiler.request(builder, self.layout, *self.render_pass, 0), msaa_pipeline)
+    }
+
+    /// Starts compiling the scene pipeline with the fragment shader at `path`. The current one
+    /// keeps drawing until it's ready, and stays if it fails.
+    fn replace_fragment_shader(&mut self, path: &str) -> std::result::Result<(), RendererError> {
+        let frag_shader_module = self.load_shader_module(path)?;
+        self.pending = Some(self.request(frag_shader_module));
+        Ok(())
+    }
+
+    fn load_shader_module(&mut self, path: &str) -> std::result::Result<ShaderModule, RendererError> {
+        let code = shader_compile::load_spirv(path).map_err(|e| RendererError::Shader(path.to_string(), e))?;
+        let create_info = ShaderModuleCreateInfo {
+            s_type: StructureType::ShaderModuleCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            code_size: code.len(),
+            p_code: code.as_ptr() as *const u32,
+        };
+        let module = unsafe { self.device.create_shader_module(&create_info, None) }?;
+        self.shader_modules.push(module);
+        Ok(module)
+    }
+
+    /// Picks up finished pipelines, returning the scene's once it's ready.
+    fn poll(&mut self) -> std::result::Result<Option<Pipeline>, RendererError> {
+        self.compiler.poll();
+        if let Some((pending, pending_msaa)) = self.pending {
+            if let Some(res) = self.compiler.error(pending).or_else(|| pending_msaa.and_then(|handle| self.compiler.error(handle))) {
+                error!(target: logging::PIPELINE, "Keeping the old scene pipeline, the new one failed to compile: {:?}", res);
+                self.pending = None;
+            } else if self.compiler.is_ready(pending) && pending_msaa.map(|handle| self.compiler.is_ready(handle)).unwrap_or(true) {
+                info!(target: logging::PIPELINE, "Switched to the new scene pipeline");
+                self.pipeline = Some(pending);
+                self.msaa_pipeline = pending_msaa;
+                self.pending = None;
+                self.name_pipelines();
+            }
+        }
+        let pipeline = self.pipeline.expect("Scene pipelines are being created");
+        if let Some(res) = self.compiler.error(pipeline).or_else(|| self.msaa_pipeline.and_then(|handle| self.compiler.error(handle))) {
+            return Err(RendererError::Vk(res));
+        }
+        if !self.compiler.is_ready(pipeline) {
+            return Ok(None);
+        }
+        if let Some(since) = self.loading_since.take() {
+            info!(target: logging::PIPELINE, "Finished loading after {:.2} s", time::seconds(since.elapsed()));
+            self.name_pipelines();
+        }
+        Ok(Some(self.compiler.get(pipeline)))
+    }
+
+    fn name_pipelines(&self) {
+        for &(handle, name) in [(self.pipeline, "scene pipeline"), (self.msaa_pipeline, "scene pipeline (MSAA)")].iter() {
+            match handle {
+                Some(handle) if self.compiler.is_ready(handle) => self.context.set_debug_name(&self.compiler.get(handle), name),
+                _ => (),
+            }
+        }
+    }
+
+    /// The multisampled scene pipeline, once `poll` has found it ready. `None` without MSAA.
+    fn msaa_pipeline(&self) -> Option<Pipeline> {
+        match self.msaa_pipeline {
+            Some(handle) if self.compiler.is_ready(handle) => Some(self.compiler.get(handle)),
+            _ => None,
+        }
+    }
+
+    fn progress(&self) -> LoadingProgress {
+        let stats = self.compiler.stats();
+        let done = stats.compiled + stats.failed;
+        LoadingProgress {
+            done: done,
+            total: done + self.compiler.pending_count(),
+        }
+    }
+}
+
+impl<'c> Drop for ScenePipelines<'c> {
+    fn drop(&mut self) {
+        // The compiler might still be using the shader modules
+        self.compiler.wait_all();
+        unsafe {
+            trace!("Destroying scene pipelines: {:?}", *self.render_pass);
+            for &module in self.shader_modules.iter() {
+                self.device.destroy_shader_module(module, None);
+            }
+            self.device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+/// The scene pipeline with the given shaders. Its render pass is compatible with the offscreen
+/// targets', so it draws into those too.
+fn scene_pipeline_builder(vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    // The extent only matters for the static viewport, which the dynamic one replaces
+    let extent = rect::placeholder_extent();
+    GraphicsPipelineBuilder::new(&extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .vertex_input(&MeshVertex::binding_descriptions(), &MeshVertex::attribute_descriptions())
+        // The scene spins, and a model might not be closed, so both sides get seen
+        .cull_mode(CULL_MODE_NONE)
+        .depth_test(true, CompareOp::Less)
+        .dynamic_viewport_scissor()
+}
+
+/// The scene mesh spinning in front of `camera`, `time.total()` seconds in.
+fn scene_uniforms(time: &Time, extent: &Extent2D, camera: &Camera) -> SceneUniforms {
+    let aspect = extent.aspect_ratio();
+    let (view, projection) = (camera.view_matrix(), camera.projection_matrix(aspect));
+    // Radians per second
+    let spin_rate: f32 = cvar!("scene.spin_rate", 1.0).get();
+    let model = Mat4::rotation(Quat::from_axis_angle(Vec3::unit_y(), time.total() as f32 * spin_rate));
+    SceneUniforms {
+        model_view_projection: projection * view * model,
+        model: model,
+    }
+}
+
+/// Size of the largest side of a loaded model, so it fills about as much of the view as the quad.
+const MODEL_SIZE: f32 = 1.0;
+
+/// The mesh of the OBJ or glTF model at `path`, fitted to `MODEL_SIZE`, and its base color
+/// texture if it has one. A glTF scene is flattened into one mesh.
+fn load_model(path: &Path) -> std::result::Result<(MeshData, Option<ImageData>), String> {
+    let (mut mesh, texture) = if gltf::is_gltf(path) {
+        let scene = gltf::load(path).map_err(|e| e.to_string())?;
+        (scene.flatten(), scene.base_color_image().cloned())
+    } else {
+        (obj::load(path).map_err(|e| e.to_string())?, None)
+    };
+    if mesh.indices.is_empty() {
+        return Err("nothing to draw".to_string());
+    }
+    mesh.fit_to_size(MODEL_SIZE);
+    Ok((mesh, texture))
+}
+
+/// The model at the `scene.model` console variable, or a quad if it's unset or can't be loaded.
+fn load_scene_model() -> (MeshData, Option<ImageData>) {
+    let path: String = cvar!("scene.model", String::new()).get();
+    if path.is_empty() {
+        return (quad_mesh(), None);
+    }
+    match load_model(Path::new(&path)) {
+        Ok(model) => model,
+        Err(e) => {
+            warn!(target: logging::UPLOAD, "Failed to load scene model {:?}, using a quad: {}", path, e);
+            (quad_mesh(), None)
+        },
+    }
+}
+
+/// The image at the `scene.texture` console variable, or the model's texture or a checkerboard if
+/// it's unset. A checkerboard also stands in if the image can't be loaded.
+fn load_scene_texture(model_texture: Option<ImageData>) -> ImageData {
+    let path: String = cvar!("scene.texture", String::new()).get();
+    if path.is_empty() {
+        return model_texture.unwrap_or_else(|| ImageData::checkerboard(256, 8));
+    }
+    match image::load(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!(target: logging::UPLOAD, "Failed to load scene texture {:?}, using a checkerboard: {}", path, e);
+            ImageData::checkerboard(256, 8)
+        },
+    }
+}
+
+fn name_scene_vertices<D: DeviceV1_0>(context: &Context, vertices: &IndexedVertexBuffer<D>) {
+    context.set_debug_name(&vertices.vertices().buffer(), "scene vertices");
+    context.set_debug_name(&vertices.indices().buffer(), "scene indices");
+}
+
+/// Binds the scene pipeline, `uniforms` and `texture` with a viewport covering `extent`, and draws
+/// the scene's vertices.
+fn record_scene<'r, 'a, D: DeviceV1_0>(pass: &mut RenderPassRecorder<'r, 'a, D>, pipeline: &'a Pipeline, layout: &'a PipelineLayout, uniforms: DescriptorSet, texture: DescriptorSet, vertices: &'a IndexedVertexBuffer<'a, D>, extent: &Extent2D) {
+    pass.bind(pipeline);
+    pass.bind_descriptor_sets(layout, 0, &[uniforms, texture]);
+    pass.set_viewport(&extent.to_viewport());
+    pass.set_scissor(&extent.to_rect());
+    pass.draw(vertices);
+}
+
+/// Records `demo`'s frame: what it prepares, timed when the profiler is on, then either its blit
+/// into the swapchain `image` or its draws into `target`, with `overlays` over them.
+#[allow(clippy::too_many_arguments)]
+fn record_demo<'a, 'c, F>(demo: &mut dyn DemoPass<'c>, mut profiler: Option<&mut GpuProfiler<'c, ash::Device<V1_0>>>, frame: &mut FrameContext<'a, 'c, ash::Device<V1_0>>, view: &DemoView, image: Image, target: &RenderTarget<'a>, clear_values: &[ClearValue], overlays: Option<&F>) -> std::result::Result<(), RendererError>
+    where F: Fn(CommandBuffer, usize, &Extent2D) -> ash::prelude::VkResult<()> {
+    let command_buffer = frame.recorder.command_buffer();
+    let scope = profiler.as_mut().and_then(|profiler| unsafe { profiler.begin_scope(command_buffer, DEMO_PREPARE_SCOPE) });
+    demo.prepare(frame, view)?;
+    if let Some(profiler) = profiler {
+        unsafe { profiler.end_scope(command_buffer, scope) };
+    }
+    if demo.blits() {
+        unsafe { demo.cmd_blit(command_buffer, image, &view.extent) };
+    } else {
+        let pass = frame.recorder.begin_render_pass(target, clear_values);
+        unsafe { demo.cmd_draw(pass.command_buffer(), view) };
+        if let Some(overlays) = overlays {
+            overlays(pass.command_buffer(), frame.slot, &view.extent)?;
+        }
+        drop(pass);
+        unsafe { demo.cmd_finish(command_buffer) };
+    }
+    Ok(())
+}
+
+/// Bytes per texel of every swapchain format `screenshot` can save, all 32 bits wide.
+const SCREENSHOT_TEXEL_SIZE: DeviceSize = 4;
+
+/// A readback big enough for a screenshot of a swapchain image at `extent`.
+fn screenshot_readback<'c>(context: &'c Context, extent: &Extent2D) -> std::result::Result<Readback<'c, ash::Device<V1_0>>, RendererError> {
+    let size = extent.width as DeviceSize * extent.height as DeviceSize * SCREENSHOT_TEXEL_SIZE;
+    Ok(Readback::new(&context.device, &context.allocator, size)?)
+}
+
+/// Records copying the swapchain `image` into `readback`. Every path leaves the image ready to
+/// present, last written by either a render pass, a copy or a blit.
+unsafe fn cmd_copy_screenshot<D: DeviceV1_0>(readback: &Readback<D>, command_buffer: CommandBuffer, image: Image, extent: &Extent2D) {
+    readback.cmd_copy_image(command_buffer, image, ImageLayout::PresentSrcKhr, IMAGE_ASPECT_COLOR_BIT, extent, SCREENSHOT_TEXEL_SIZE, 0, PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | PIPELINE_STAGE_TRANSFER_BIT, ACCESS_COLOR_ATTACHMENT_WRITE_BIT | ACCESS_TRANSFER_WRITE_BIT);
+}
+
+/// Writes out the screenshot `readback` was given by `cmd_copy_screenshot`, once the frame it was
+/// recorded in is done. A screenshot that can't be written is logged rather than failing the frame.
+fn save_screenshot<D: DeviceV1_0>(mut readback: Readback<D>, format: Format, extent: &Extent2D) -> std::result::Result<(), RendererError> {
+    // The copy went in with the frame rather than through the readback's own submit, so this
+    // waits for its event rather than the whole queue
+    let texels: Vec<u32> = readback.read((extent.width * extent.height) as usize)?;
+    match screenshot::write(&screenshot::to_image_data(format, extent, &texels)) {
+        Ok(path) => info!("Saved screenshot to {:?}", path),
+        Err(e) => error!("Failed to save screenshot: {}", e),
+    }
+    Ok(())
+}
+
+/// Destroys swapchains with a loader of its own, so the renderer can keep one alongside the
+/// `SafeSwapchain` it was created with.
+struct SwapchainDestroyer(ash::extensions::Swapchain);
+
+impl Destroy<SwapchainKHR> for SwapchainDestroyer {
+    fn destroy(&self, swapchain: SwapchainKHR) {
+        trace!(target: logging::SWAPCHAIN, "Destroying swapchain: {:?}", swapchain);
+        unsafe { self.0.destroy_swapchain_khr(swapchain, None) };
+    }
+}
+
+/// The swapchain's images and everything created for them. The swapchain itself is destroyed by
+/// the `Renderer`, which has the extension to do it with, after this is dropped.
+struct SwapchainState<'c> {
+    /// Owned by the renderer's `swapchain_handle`.
+    swapchain: SwapchainKHR,
+    images: Vec<Image>,
+    /// Owned by the `ScenePipelines`.
+    render_pass: RenderPass,
+    // Ahead of the views and depth buffer, so they're dropped first
+    framebuffers: Vec<OwnedFramebuffer<'c, ash::Device<V1_0>>>,
+    views: Vec<Owned<'c, ash::Device<V1_0>, ImageView>>,
+    /// Shared by all of `framebuffers`.
+    depth: Option<DepthBuffer<'c, ash::Device<V1_0>>>,
+    extent: Extent2D,
+    /// What the scene renders at, which is smaller than `extent` while upscaling.
+    render_extent: Extent2D,
+    offscreen: Option<OffscreenTarget<'c, ash::Device<V1_0>>>,
+    upscale_pass: Option<UpscalePass<'c, ash::Device<V1_0>>>,
+    /// With MSAA, when rendering straight to the swapchain at full resolution.
+    msaa: Option<MsaaTarget<'c, ash::Device<V1_0>>>,
+    /// Whenever the final image is drawn in a render pass into the swapchain image, so an output
+    /// filter can be turned on without recreating the swapchain. `None` if its shaders couldn't be
+    /// loaded.
+    filter_pass: Option<FilterPass<'c, ash::Device<V1_0>>>,
+    /// Sharpens the filter pass' target. `None` without one, or if its shader couldn't be loaded.
+    cas: Option<CasPass<'c, ash::Device<V1_0>>>,
+    present_ownership: Option<PresentOwnership<'c, ash::Device<V1_0>>>,
+}
+
+impl<'c> SwapchainState<'c> {
+    /// `render_pass` and `msaa_render_pass` are the `ScenePipelines`', made for `format`.
+    #[allow(clippy::too_many_arguments)]
+    fn new(context: &'c Context, format: Format, swapchain: SwapchainKHR, images: Vec<Image>, extent: Extent2D, render_pass: RenderPass, msaa_render_pass: RenderPass, upscaler: &Upscaler) -> std::result::Result<SwapchainState<'c>, RendererError> {
+        let device = &context.device;
+        // Late acquire copies the scene into the swapchain image as is, so it always renders at
+        // full resolution
+        let upscale_active = context.options.present_timing == PresentTiming::Direct && upscaler.is_active();
+        let render_extent = if upscale_active {
+            upscaler.render_extent(&extent)
+        } else {
+            extent.clone()
+        };
+        let mut ret = SwapchainState {
+            swapchain: swapchain,
+            images: images,
+            render_pass: render_pass,
+            framebuffers: Vec::new(),
+            views: Vec::new(),
+            depth: None,
+            extent: extent,
+            render_extent: render_extent,
+            offscreen: None,
+            upscale_pass: None,
+            msaa: None,
+            filter_pass: None,
+            cas: None,
+            present_ownership: None,
+        };
+        for &image in ret.images.iter() {
+            let create_info = ImageViewCreateInfo {
+                s_type: StructureType::ImageViewCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                image: image,
+                view_type: ImageViewType::Type2d,
+                format: format,
+                components: ComponentMapping {
+                    r: ComponentSwizzle::Identity,
+                    g: ComponentSwizzle::Identity,
+                    b: ComponentSwizzle::Identity,
+                    a: ComponentSwizzle::Identity,
+                },
+                subresource_range: ImageSubresourceRange {
+                    aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+            };
+            let view = safe_create::create_image_view_safe(device, &create_info, None)?;
+            ret.views.push(view);
+        }
+
+        context.limits.check_framebuffer(ret.extent.width, ret.extent.height, 1)
+            .map_err(|e| RendererError::Unsupported(format!("swapchain framebuffer: {}", e)))?;
+        ret.depth = Some(DepthBuffer::new(device, &context.allocator, context.depth_format, ret.extent.clone())?);
+        let depth_view = ret.depth.as_ref().unwrap().view();
+        for i in 0..ret.views.len() {
+            let attachments = [*ret.views[i], depth_view];
+            let create_info = FramebufferCreateInfo {
+                s_type: StructureType::FramebufferCreateInfo,
+                p_next: ptr::null(),
+                flags: Default::default(),
+                render_pass: RenderPass::null(),
+                attachment_count: 0,
+                p_attachments: ptr::null(),
+                width: ret.extent.width,
+                height: ret.extent.height,
+                layers: 1,
+            };
+            let create_info = unsafe { FramebufferCreateInfoSafe::from_handles(create_info, ret.render_pass, &attachments) };
+            let framebuffer = safe_create::create_framebuffer_safe(device, create_info, None)?;
+            ret.framebuffers.push(framebuffer);
+        }
+
+        if context.options.present_timing == PresentTiming::LateAcquire {
+            ret.offscreen = Some(OffscreenTarget::new(device, &context.allocator, format, Some(context.depth_format), ret.extent.clone(), OffscreenUsage::Copy)?);
+        }
+        if upscale_active {
+            let vert_shader_module = load_shader_module(device, FULLSCREEN_VERTEX_SHADER)?;
+            let frag_shader_module = load_shader_module(device, upscale::FRAGMENT_SHADER)?;
+            ret.upscale_pass = Some(UpscalePass::new(device, &context.allocator, format, Some(context.depth_format), ret.render_extent.clone(), ret.extent.clone(), ret.render_pass, *vert_shader_module, *frag_shader_module)?);
+        }
+        match context.msaa {
+            Some(ref settings) if wants_msaa(context, upscale_active) => {
+                ret.msaa = Some(MsaaTarget::new(device, &context.allocator, settings, msaa_render_pass, format, context.depth_format, ret.extent.clone())?);
+            },
+            _ => {},
+        }
+        if context.options.present_timing == PresentTiming::Direct && ret.msaa.is_none() {
+            ret.filter_pass = optional_pass("output filter", create_filter_pass(context, format, ret.extent.clone(), ret.render_pass))?;
+        }
+        if let Some(ref mut filter_pass) = ret.filter_pass {
+            ret.cas = optional_pass("sharpening pass", create_cas_pass(context, filter_pass))?;
+            if let Some(ref cas) = ret.cas {
+                filter_pass.set_sharpened(cas.sampled_write(output_filter::IMAGE_BINDING))?;
+            }
+        }
+        if context.present_sharing == PresentSharing::OwnershipTransfer {
+            ret.present_ownership = Some(PresentOwnership::new(device, &ret.images, context.graphics_family, context.presentation_family)?);
+        }
+        Ok(ret)
+    }
+
+    /// Whether `upscaler`'s settings need a different render target than this was made with.
+    fn is_stale_for(&self, context: &Context, upscaler: &Upscaler) -> bool {
+        let wants_upscale = context.options.present_timing == PresentTiming::Direct && upscaler.is_active();
+        if wants_upscale != self.upscale_pass.is_some() || wants_msaa(context, wants_upscale) != self.msaa.is_some() {
+            return true;
+        }
+        let extent = upscaler.render_extent(&self.extent);
+        wants_upscale && !extent.same_as(&self.render_extent)
+    }
+
+    fn target<'a>(&'a self, image_index: u32) -> RenderTarget<'a> {
+        RenderTarget {
+            render_pass: &self.render_pass,
+            framebuffer: &self.framebuffers[image_index as usize],
+            extent: self.extent.clone(),
+        }
+    }
+
+    /// Presents `image_index` once `render_finished` is signaled, handing the image over to the
+    /// presentation queue first when it's in another family. `present_id` tags the present when
+    /// `VK_KHR_present_id` is enabled.
+    fn present(&self, context: &Context, vk_swapchain: &SafeSwapchain<ash::Instance<V1_0>, ash::Device<V1_0>>, image_index: u32, render_finished: Semaphore, present_id: u64) -> std::result::Result<PresentStatus, RendererError> {
+        let wait_semaphore = match self.present_ownership {
+            Some(ref ownership) => ownership.submit(context.presentation_queue, image_index, render_finished)?,
+            None => render_finished,
+        };
+        let present_ids = [present_id];
+        let present_ids = if context.enabled_extensions.iter().any(|name| name == present_id::EXTENSION_NAME) { Some(&present_ids[..]) } else { None };
+        let statuses = unsafe { vk_swapchain.present(context.presentation_queue, &[wait_semaphore], &[self.swapchain], &[image_index], present_ids) }?;
+        Ok(statuses[0])
+    }
+}
+
+/// Whether the scene renders with MSAA, which is only when it goes straight to the swapchain at
+/// full resolution.
+fn wants_msaa(context: &Context, upscale_active: bool) -> bool {
+    context.msaa.is_some() && context.options.present_timing == PresentTiming::Direct && !upscale_active
+}
+
+impl<'c> Drop for SwapchainState<'c> {
+    fn drop(&mut self) {
+        trace!(target: logging::SWAPCHAIN, "Destroying swapchain resources: {:?}", self.swapchain);
+    }
+}
+
+pub struct Renderer<'c> {
+    context: &'c Context,
+    vk_swapchain: SafeSwapchain<'c, ash::Instance<V1_0>, ash::Device<V1_0>>,
+    swapchain_settings: SwapchainSettings,
+    /// Kept while the rest of the swapchain's state is recreated, to be passed as the old
+    /// swapchain, then replaced by the new one.
+    swapchain_handle: Option<VkOwned<SwapchainKHR, SwapchainDestroyer>>,
+    /// `None` only while being recreated.
+    swapchain: Option<SwapchainState<'c>>,
+    scene: ScenePipelines<'c>,
+    /// Dropped after `scene`, whose compiler uses it, and saved as it is.
+    pipeline_cache: SafePipelineCache<'c, ash::Device<V1_0>>,
+    scene_uniforms: UniformBuffers<'c, ash::Device<V1_0>, SceneUniforms>,
+    scene_texture: SampledTexture<'c, ash::Device<V1_0>>,
+    scene_vertices: IndexedVertexBuffer<'c, ash::Device<V1_0>>,
+    /// Where the latest step left the camera. Frames are drawn from `camera_motion` instead.
+    camera: Camera,
+    /// `camera`'s pose at its last two steps, so frames between them blend the two.
+    camera_motion: Interpolated<CameraPose>,
+    camera_step: FixedTimestep,
+    /// Moves `camera` when there's no camera path playing.
+    fly_controls: FlyControls,
+    /// Moves `camera` when playing back a path.
+    camera_path: Option<Playback>,
+    camera_recording: Recording,
+    splash: SplashPass<'c, ash::Device<V1_0>>,
+    console: LogConsole,
+    /// `None` if its shaders couldn't be loaded.
+    console_pass: Option<ConsolePass<'c, ash::Device<V1_0>>>,
+    /// Screen-space guides, drawn under the console.
+    overlay: Overlay,
+    /// `None` if its shaders couldn't be loaded.
+    overlay_pass: Option<OverlayPass<'c, ash::Device<V1_0>>>,
+    /// With `--nan-check`, unless its shaders couldn't be loaded.
+    nan_check: Option<NanCheckPass<'c, ash::Device<V1_0>>>,
+    frame_loop: FrameLoop<'c, ash::Device<V1_0>>,
+    /// Times each frame on the GPU, if the graphics queue can.
+    gpu_profiler: Option<GpuProfiler<'c, ash::Device<V1_0>>>,
+    demos: DemoSelector,
+    /// Handed to each demo pass that's created.
+    demo_layouts: SharedLayouts<'c>,
+    /// The selected demo's pass, `None` while the scene is what's selected or still loading.
+    demo: Option<Box<dyn DemoPass<'c> + 'c>>,
+    /// The demo `demo` was created for, so a new selection is noticed.
+    demo_name: Option<&'static str>,
+    /// Passes of demos that were selected before, until a frame hands them to the deletion queue.
+    retired_demos: Vec<Box<dyn DemoPass<'c> + 'c>>,
+    /// The demo list shows over the frame until then, and while the log console is open.
+    demo_list_until: Instant,
+    upscaler: Upscaler,
+    surface_formats: SurfaceFormatCycle,
+    /// What the swapchain, the scene's render passes and everything drawn in them were made for.
+    /// Starts as the context's, and follows `surface_formats` when the swapchain is recreated.
+    surface_format: (Format, ColorSpace),
+    /// Encoding into `surface_format`, drawn by the filter pass.
+    gamma: GammaOutput,
+    /// Whether the next frame should be saved, from `request_screenshot`.
+    screenshot_requested: bool,
+    acquire_policy: AcquirePolicy,
+    frame_stats: FrameStats,
+    latency_stats: LatencyStats,
+    recreate_count: u32,
+    checkpoints: Checkpoints,
+    /// The frame `draw_frame` last started, for checkpoints outside of it.
+    last_frame: u64,
+    watchdog: Option<WatchdogHandle>,
+}
+
+impl<'c> Renderer<'c> {
+    /// `logs` are what the log console shows and changes, from `logging::init`. Progress through
+    /// each frame is marked in `checkpoints` for crash reports.
+    pub fn new(context: &'c Context, window: &glfw::Window, logs: LogHandles, checkpoints: Checkpoints) -> std::result::Result<Renderer<'c>, RendererError> {
+        let vk_swapchain = SafeSwapchain::new(&context.instance.instance, &context.device)
+            .map_err(|missing| RendererError::Loading(format!("swapchain functions {:?}", missing)))?;
+        let swap_support = context.swapchain_support()?;
+        let swapchain_settings = context.options.swapchain_config(&swap_support.capabilities).resolve(&swap_support.capabilities)
+            .map_err(|e| RendererError::Unsupported(format!("swapchain configuration: {}", e)))?;
+        debug!(target: logging::SWAPCHAIN, "Using swapchain settings: {:?}", &swapchain_settings);
+        let scene_uniforms = UniformBuffers::new(&context.device, &context.allocator, frame::FRAMES_IN_FLIGHT, SHADER_STAGE_VERTEX_BIT)?;
+        let immediate = ImmediateContext::new(&context.device, context.graphics_family, context.graphics_queue)?;
+        let (scene_mesh, model_texture) = load_scene_model();
+        let scene_texture = {
+            create_scene_texture(context, &immediate, &load_scene_texture(model_texture))?
+        };
+        let pipeline_cache = match pipeline_cache::path() {
+            ref path if path.is_empty() => SafePipelineCache::new(&context.device)?,
+            path => SafePipelineCache::load(&context.device, &context.capabilities.properties, path)?,
+        };
+        let surface_format = (context.surface_format.format, context.color_space);
+        let view_format = gamma::view_format(surface_format, context.mutable_swapchain_format());
+        let scene = ScenePipelines::new(context, view_format, pipeline_cache.handle(), scene_uniforms.set_layout(), scene_texture.set_layout())?;
+        let scene_vertices = scene_mesh.upload(&context.device, &context.allocator, &immediate)?;
+        name_scene_vertices(context, &scene_vertices);
+        let gpu_profiler = if gpu_profiler::enabled() {
+            let queue_families = context.instance.instance.get_physical_device_queue_family_properties(context.physical_device);
+            let timestamp_valid_bits = context.graphics_family.properties(&queue_families).map(|family| family.timestamp_valid_bits).unwrap_or(0);
+            GpuProfiler::new(&context.device, frame::FRAMES_IN_FLIGHT, &context.capabilities.properties.limits, timestamp_valid_bits)?
+        } else {
+            None
+        };
+        let camera_path = match context.options.camera_path {
+            Some(ref config) => Some(Playback::load(config)
+                .map_err(|e| RendererError::Loading(format!("camera path {:?}: {}", config.path, e)))?),
+            None => None,
+        };
+        let splash = SplashPass::new(&context.device, *scene.render_pass)?;
+        let console_pass = optional_pass("log console", create_console_pass(context, *scene.render_pass))?;
+        let overlay_pass = optional_pass("overlay", create_overlay_pass(context, *scene.render_pass))?;
+        let nan_check = if context.options.nan_check {
+            optional_pass("NaN check", create_nan_check_pass(context, *scene.render_pass))?
+        } else {
+            None
+        };
+        let mut demos = DemoSelector::new(capabilities::DEMOS, &context.capabilities);
+        let requested: String = cvar!("scene.demo", String::new()).get();
+        if !requested.is_empty() {
+            if demos.is_available(&requested) {
+                demos.select(&requested);
+            } else if demos.missing(&requested).is_empty() {
+                warn!("There's no demo called {:?}", requested);
+            } else {
+                warn!("Can't start with demo {:?}, it needs {}", requested, demos.missing(&requested).join(", "));
+            }
+        }
+        let mut ret = Renderer {
+            context: context,
+            vk_swapchain: vk_swapchain,
+            swapchain_settings: swapchain_settings,
+            swapchain_handle: None,
+            swapchain: None,
+            scene: scene,
+            pipeline_cache: pipeline_cache,
+            scene_uniforms: scene_uniforms,
+            scene_texture: scene_texture,
+            scene_vertices: scene_vertices,
+            camera: Camera::default(),
+            camera_motion: Interpolated::new(Camera::default().pose()),
+            camera_step: FixedTimestep::new(CAMERA_STEP),
+            fly_controls: FlyControls::default(),
+            camera_path: camera_path,
+            camera_recording: Recording::default(),
+            splash: splash,
+            console: LogConsole::new(logs),
+            console_pass: console_pass,
+            overlay: Overlay::new(),
+            overlay_pass: overlay_pass,
+            nan_check: nan_check,
+            frame_loop: create_frame_loop(context)?,
+            gpu_profiler: gpu_profiler,
+            demos: demos,
+            demo_layouts: SharedLayouts::new(context)?,
+            demo: None,
+            demo_name: None,
+            retired_demos: Vec::new(),
+            demo_list_until: Instant::now(),
+            upscaler: context.options.upscaler.clone(),
+            surface_formats: SurfaceFormatCycle::new(context.surface_formats(), surface_format),
+            surface_format: surface_format,
+            gamma: GammaOutput::new(view_format),
+            screenshot_requested: false,
+            acquire_policy: AcquirePolicy::default(),
+            frame_stats: FrameStats::default(),
+            latency_stats: LatencyStats::new(context.options.present_timing),
+            recreate_count: 0,
+            checkpoints: checkpoints,
+            last_frame: 0,
+            watchdog: None,
+        };
+        let extent = swap_support.choose_swap_extent(window);
+        debug!(target: logging::SWAPCHAIN, "Using swap extent: {:?}", &extent);
+        ret.swapchain = Some(ret.create_swapchain_or_full_resolution(&swap_support, extent)?);
+        ret.attach_nan_check();
+        Ok(ret)
+    }
+
+    /// Creates a swapchain in place of the one in `swapchain_handle`, if there is one, which is
+    /// retired and destroyed, and the state that goes with it.
+    fn create_swapchain(&mut self, swap_support: &SwapChainSupportDetails, extent: Extent2D) -> std::result::Result<SwapchainState<'c>, RendererError> {
+        let context = self.context;
+        let queue_families: BTreeSet<QueueFamilyIndex> = [context.graphics_family, context.presentation_family].iter()
+            .cloned()
+            .collect();
+        let mut create_info = SwapchainCreateInfoSafe::new(SwapchainCreateInfoKHR {
+            s_type: StructureType::SwapchainCreateInfoKhr,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            surface: context.instance.surface,
+            min_image_count: self.swapchain_settings.image_count,
+            image_format: self.surface_format.0,
+            image_color_space: context.surface_format.color_space,
+            image_extent: extent.clone(),
+            image_array_layers: 1,
+            image_usage: self.swapchain_settings.usage,
+            image_sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            pre_transform: swap_support.capabilities.current_transform,
+            composite_alpha: self.swapchain_settings.composite_alpha,
+            present_mode: context.present_mode,
+            clipped: true as Bool32,
+            old_swapchain: self.swapchain_handle.as_ref().map_or(SwapchainKHR::null(), |handle| **handle),
+        }).queue_families(queue_families.iter().map(|family| family.raw()));
+        let view_format = gamma::view_format(self.surface_format, context.mutable_swapchain_format());
+        let view_formats = [self.surface_format.0, view_format];
+        let mut format_list = ImageFormatListCreateInfo::new(&view_formats);
+        if view_format != self.surface_format.0 {
+            create_info = create_info.push_next(&mut format_list);
+        }
+        unsafe {
+            context.present_sharing.apply(create_info.info_mut());
+            if self.surface_format.1 != ColorSpace::SRGB_NONLINEAR {
+                hdr::set_color_space(create_info.info_mut(), self.surface_format.1);
+            }
+            if view_format != self.surface_format.0 {
+                gamma::set_mutable_format(create_info.info_mut());
+            }
+        }
+        debug!(target: logging::SWAPCHAIN, "Creating swapchain with parameters: {:?}", create_info.info_ref());
+        let swapchain = safe_create::create_swapchain_khr_safe(&self.vk_swapchain, create_info.info_ref(), None)?;
+        // The old swapchain is retired now, whether or not the rest succeeds
+        let swapchain = match self.swapchain_handle {
+            Some(ref mut handle) => {
+                let retired = unsafe { handle.replace(swapchain.into_raw()) };
+                trace!(target: logging::SWAPCHAIN, "Destroying retired swapchain: {:?}", retired);
+                unsafe { self.vk_swapchain.destroy_swapchain_khr(retired, None) };
+                **handle
+            },
+            None => {
+                let loader = (*self.vk_swapchain).clone();
+                let handle = swapchain.map_destroy(|_| SwapchainDestroyer(loader));
+                let swapchain = *handle;
+                self.swapchain_handle = Some(handle);
+                swapchain
+            },
+        };
+        if self.surface_format.1.is_hdr() && context.enabled_extensions.iter().any(|name| name == hdr::METADATA_EXTENSION) {
+            HdrMetadata::from_cvars().apply(&context.instance.instance, &context.device, swapchain);
+        }
+        let state = self.vk_swapchain.get_swapchain_images_khr(swapchain)
+            .map_err(RendererError::from)
+            .and_then(|images| {
+                debug!(target: logging::SWAPCHAIN, "We desired at least {} images. The swapchain is using {}", self.swapchain_settings.image_count, images.len());
+                for (idx, image) in images.iter().enumerate() {
+                    context.set_debug_name(image, &format!("swapchain image {}", idx));
+                }
+                SwapchainState::new(context, view_format, swapchain, images, extent, *self.scene.render_pass, self.scene.msaa_render_pass.as_ref().map_or(RenderPass::null(), |render_pass| **render_pass), &self.upscaler)
+            });
+        if state.is_err() {
+            self.swapchain_handle = None;
+        }
+        state
+    }
+
+    /// Like `create_swapchain`, but turns upscaling off and tries again if its shaders couldn't be
+    /// loaded, rather than failing over an optional pass.
+    fn create_swapchain_or_full_resolution(&mut self, swap_support: &SwapChainSupportDetails, extent: Extent2D) -> std::result::Result<SwapchainState<'c>, RendererError> {
+        match self.create_swapchain(swap_support, extent.clone()) {
+            Err(RendererError::Shader(path, e)) if self.upscaler.is_active() => {
+                warn!(target: logging::SHADER, "Rendering at full resolution, {:?} couldn't be loaded: {}", path, e);
+                self.upscaler = Upscaler::new(1.0);
+                self.create_swapchain(swap_support, extent)
+            },
+            res => res,
+        }
+    }
+
+    fn destroy_swapchain(&mut self) {
+        self.swapchain = None;
+        self.swapchain_handle = None;
+    }
+
+    /// Has `watchdog` watch every wait on the GPU from here on.
+    pub fn set_watchdog(&mut self, watchdog: WatchdogHandle) {
+        self.watchdog = Some(watchdog);
+    }
+
+    fn state(&self) -> &SwapchainState<'c> {
+        self.swapchain.as_ref().expect("Swapchain is being recreated")
+    }
+
+    pub fn demos(&self) -> &DemoSelector {
+        &self.demos
+    }
+
+    pub fn upscaler(&self) -> &Upscaler {
+        &self.upscaler
+    }
+
+    /// The format and color space of the swapchain.
+    pub fn surface_format(&self) -> (Format, ColorSpace) {
+        self.surface_format
+    }
+
+    /// How far along the scene's pipelines are. The splash shows in place of the scene until
+    /// they're all done.
+    pub fn loading_progress(&self) -> LoadingProgress {
+        self.scene.progress()
+    }
+
+    /// Passes `key` to the log console, then the upscaler, the demo selector, the selected demo,
+    /// the surface format cycle, the overlay and the gamma comparison. Returns whether any of them handled it. The console takes
+    /// every key while it's open.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        self.console.handle_key(key) || self.upscaler.handle_key(key) || self.demos.handle_key(key) || self.demo.as_mut().is_some_and(|demo| demo.handle_key(key)) || self.surface_formats.handle_key(key) || self.overlay.handle_key(key) || self.gamma.handle_key(key)
+    }
+
+    /// Saves the next frame presented to a PNG in `screenshot::OUTPUT_DIR`.
+    pub fn request_screenshot(&mut self) {
+        if !self.swapchain_settings.usage.subset(IMAGE_USAGE_TRANSFER_SRC_BIT) {
+            warn!("The surface doesn't allow copying out of swapchain images, so there are no screenshots");
+        } else if !screenshot::supports_format(self.surface_format.0) {
+            warn!("Screenshots of {:?} swapchains aren't supported", self.surface_format.0);
+        } else {
+            self.screenshot_requested = true;
+        }
+    }
+
+    /// Adds the camera's pose, at `time`'s scene time, to the path saved to
+    /// `camera_path::RECORDING_FILE`.
+    pub fn record_camera_keyframe(&mut self, time: &Time) {
+        match self.camera_recording.add(self.camera.pose(), time) {
+            Ok(count) => info!("Recorded camera keyframe {} to {:?}", count, camera_path::RECORDING_FILE),
+            Err(e) => error!("Failed to save camera path to {:?}: {}", camera_path::RECORDING_FILE, e),
+        }
+    }
+
+    /// Passes `event` to the camera's fly controls, returning whether they used it. Keys only get
+    /// through while the log console is closed, but releasing one always does. Losing focus lets
+    /// go of everything, since the window won't hear about keys released elsewhere.
+    pub fn handle_camera_event(&mut self, event: &glfw::WindowEvent) -> bool {
+        let typing = match *event {
+            glfw::WindowEvent::Key(_, _, glfw::Action::Release, _) => false,
+            glfw::WindowEvent::Key(..) => self.console.is_open(),
+            glfw::WindowEvent::Focus(false) => {
+                self.fly_controls.release_all();
+                return false;
+            },
+            _ => false,
+        };
+        !typing && self.fly_controls.handle_event(event)
+    }
+
+    /// Passes a cursor move or mouse button to the selected demo, returning whether it used it.
+    pub fn handle_mouse_event(&mut self, event: &glfw::WindowEvent) -> bool {
+        self.demo.as_mut().is_some_and(|demo| demo.handle_mouse(event))
+    }
+
+    /// Passes a typed character to the log console's search.
+    pub fn handle_char(&mut self, c: char) -> bool {
+        self.console.handle_char(c)
+    }
+
+    /// Whether keys should go to `handle_key` before anything else, e.g. while typing a search.
+    pub fn captures_text(&self) -> bool {
+        self.console.is_open()
+    }
+
+    /// Whether settings changed since the swapchain was created in a way that needs
+    /// `recreate_swapchain`, e.g. a new render scale or surface format.
+    pub fn needs_recreation(&self) -> bool {
+        self.state().is_stale_for(self.context, &self.upscaler) || self.surface_formats.current() != self.surface_format
+    }
+
+    /// Rebuilds the swapchain and everything sized to it for the window's current size. Returns
+    /// `false`, keeping the old swapchain, while the window has no area (e.g. when minimized),
+    /// since no swapchain can be that size; wait for events and try again.
+    pub fn recreate_swapchain(&mut self, window: &glfw::Window) -> std::result::Result<bool, RendererError> {
+        let swap_support = self.context.swapchain_support()?;
+        let extent = swap_support.choose_swap_extent(window);
+        if extent.is_empty() {
+            return Ok(false);
+        }
+        self.checkpoints.mark(self.last_frame, "recreating swapchain");
+        {
+            let _wait = self.watchdog.as_ref().map(|watchdog| watchdog.watch(self.last_frame, "waiting for the device to go idle", self.frame_loop.describe_submissions()));
+            self.context.device.device_wait_idle()?;
+        }
+        // The swapchain itself stays until the new one replaces it
+        self.swapchain = None;
+        // Demo passes are made for the old extent and render pass, and the device is idle
+        self.demo = None;
+        self.demo_name = None;
+        self.retired_demos.clear();
+        if self.surface_formats.current() != self.surface_format {
+            self.rebuild_for_surface_format()?;
+        }
+        self.recreate_count += 1;
+        info!(target: logging::SWAPCHAIN, "Recreating swapchain ({} so far) with extent {:?}", self.recreate_count, &extent);
+        self.swapchain = Some(self.create_swapchain_or_full_resolution(&swap_support, extent)?);
+        self.attach_nan_check();
+        Ok(true)
+    }
+
+    /// Points the NaN check at the filter pass' target, which the final image is drawn into while
+    /// the check is on. Without a filter pass, nothing is checked. The device has to be idle.
+    fn attach_nan_check(&mut self) {
+        if let (&mut Some(ref mut nan_check), Some(state)) = (&mut self.nan_check, &self.swapchain) {
+            if let Some(ref filter_pass) = state.filter_pass {
+                nan_check.set_source(filter_pass.source());
+            }
+        }
+    }
+
+    /// Rebuilds the scene's pipelines and render passes, and the splash, overlay, NaN check and
+    /// log console drawn in them, for the format `surface_formats` is on. The swapchain has to be destroyed
+    /// already. The scene goes back to its default fragment shader, and shows the splash until its
+    /// pipelines have compiled again.
+    fn rebuild_for_surface_format(&mut self) -> std::result::Result<(), RendererError> {
+        let context = self.context;
+        let surface_format = self.surface_formats.current();
+        let view_format = gamma::view_format(surface_format, context.mutable_swapchain_format());
+        let scene = ScenePipelines::new(context, view_format, self.pipeline_cache.handle(), self.scene_uniforms.set_layout(), self.scene_texture.set_layout())?;
+        let splash = SplashPass::new(&context.device, *scene.render_pass)?;
+        let console_pass = optional_pass("log console", create_console_pass(context, *scene.render_pass))?;
+        let overlay_pass = optional_pass("overlay", create_overlay_pass(context, *scene.render_pass))?;
+        let nan_check = if context.options.nan_check {
+            optional_pass("NaN check", create_nan_check_pass(context, *scene.render_pass))?
+        } else {
+            None
+        };
+        self.scene = scene;
+        self.splash = splash;
+        self.console_pass = console_pass;
+        self.overlay_pass = overlay_pass;
+        self.nan_check = nan_check;
+        self.surface_format = surface_format;
+        self.gamma.set_swapchain_format(view_format);
+        info!(target: logging::SWAPCHAIN, "Rebuilt render passes for surface format {:?} in {}", surface_format.0, surface_format.1);
+        Ok(())
+    }
+
+    /// Creates the pass of the selected demo if the selection changed since the last frame, and
+    /// retires the old one. A demo whose shaders can't be loaded is marked unavailable, which
+    /// moves the selection on for the next frame.
+    fn select_demo(&mut self) -> std::result::Result<(), RendererError> {
+        let selected = self.demos.selected().map(|demo| demo.name);
+        if selected == self.demo_name {
+            return Ok(());
+        }
+        if let Some(retired) = self.demo.take() {
+            self.retired_demos.push(retired);
+        }
+        self.demo_name = selected;
+        self.demo_list_until = Instant::now() + Duration::from_secs(DEMO_LIST_SECONDS);
+        let name = match selected {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let extent = self.state().extent.clone();
+        match demos::create(self.context, &self.demo_layouts, name, *self.scene.render_pass, &extent) {
+            Ok(Some(ref demo)) if demo.blits() && !self.swapchain_settings.usage.subset(IMAGE_USAGE_TRANSFER_DST_BIT) => {
+                self.demos.mark_unavailable(name, "a swapchain that can be blitted to".to_string());
+                warn!("Skipping demo {}, which needs {}", name, self.demos.missing(name).join(", "));
+            },
+            Ok(demo) => self.demo = demo,
+            Err(RendererError::Shader(path, e)) => {
+                self.demos.mark_unavailable(name, format!("shader {}", path));
+                warn!(target: logging::SHADER, "Skipping demo {}, which needs {}: {}", name, self.demos.missing(name).join(", "), e);
+            },
+            Err(RendererError::Unsupported(what)) => {
+                self.demos.mark_unavailable(name, what);
+                warn!("Skipping demo {}, which needs {}", name, self.demos.missing(name).join(", "));
+            },
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Records, submits and presents one frame, which is the splash until the scene has loaded.
+    /// A frame that couldn't get an image is skipped and reported in the outcome, as is a
+    /// swapchain that needs recreating.
+    pub fn draw_frame(&mut self, time: &Time) -> std::result::Result<FrameOutcome, RendererError> {
+        let context = self.context;
+        let scene_pipeline = self.scene.poll()?;
+        if scene_pipeline.is_some() {
+            self.select_demo()?;
+        }
+        // Demos draw straight into the swapchain image, without the scene's offscreen targets
+        let demo_active = scene_pipeline.is_some() && self.demo.is_some();
+        let msaa_pipeline = self.scene.msaa_pipeline();
+        let loading_progress = self.scene.progress();
+        let state = self.swapchain.as_ref().expect("Swapchain is being recreated");
+        let clear_values: [ClearValue; 2] = [ClearValue::new_color(ClearColorValue::new_float32(CLEAR_VALUE)), depth_buffer::clear_value()];
+        let splash_clear_values: [ClearValue; 2] = [ClearValue::new_color(ClearColorValue::new_float32(splash::BACKGROUND)), depth_buffer::clear_value()];
+        let demo_list: Vec<(String, CellColor)> = if self.console.is_open() || Instant::now() < self.demo_list_until {
+            self.demos.lines().into_iter().map(|(line, available)| (line, if available { CellColor::Level(LogLevel::Info) } else { CellColor::Disabled })).collect()
+        } else {
+            Vec::new()
+        };
+        let (console, console_pass) = (&self.console, &self.console_pass);
+        let (overlay, overlay_pass) = (&self.overlay, &self.overlay_pass);
+        // Draws the overlay's guides, then the console if it's open and the demo list if it's
+        // showing
+        let draw_overlays = |command_buffer: CommandBuffer, slot: usize, extent: &Extent2D| -> ash::prelude::VkResult<()> {
+            if let Some(ref overlay_pass) = *overlay_pass {
+                unsafe { overlay_pass.cmd_draw(command_buffer, overlay, extent) };
+            }
+            if let Some(ref console_pass) = *console_pass {
+                if console.is_open() {
+                    unsafe { console_pass.cmd_draw(command_buffer, slot, console, extent) }?;
+                }
+                unsafe { console_pass.cmd_draw_panel(command_buffer, slot, &demo_list, extent) }?;
+            }
+            Ok(())
+        };
+        self.last_frame = time.frame();
+        self.checkpoints.mark(time.frame(), "begin frame");
+        {
+            let _wait = self.watchdog.as_ref().map(|watchdog| watchdog.watch(time.frame(), "waiting for the frame slot's fences", self.frame_loop.describe_submissions()));
+            self.frame_loop.begin()?;
+        }
+        if let Some(ref mut nan_check) = self.nan_check {
+            nan_check.poll()?;
+        }
+        if let Some(ref mut profiler) = self.gpu_profiler {
+            profiler.begin_frame(self.frame_loop.slot_index())?;
+            if let Some(ref mut demo) = self.demo {
+                if let Some(timing) = profiler.last_frame().iter().find(|timing| timing.name == DEMO_PREPARE_SCOPE) {
+                    demo.gpu_time(timing.milliseconds);
+                }
+            }
+        }
+        let path_pose = match self.camera_path {
+            // Held at the start until there's a scene to see, so every run covers the whole path
+            Some(ref mut playback) if scene_pipeline.is_some() => Some(playback.advance(time)),
+            Some(ref playback) => Some(playback.pose()),
+            None => None,
+        };
+        match path_pose {
+            // Sampled for this frame already, so there's nothing to blend
+            Some(pose) => {
+                self.camera.set_pose(&pose);
+                self.camera_motion.reset(pose);
+            },
+            // Real time, so the camera still moves with the scene paused
+            None => for _ in 0..self.camera_step.advance(time.real_delta()) {
+                self.fly_controls.update(&mut self.camera, self.camera_step.step());
+                self.camera_motion.update(self.camera.pose());
+            },
+        }
+        let mut camera = self.camera;
+        camera.set_pose(&self.camera_motion.get(self.camera_step.alpha()));
+        self.scene_uniforms.write(self.frame_loop.slot_index(), &scene_uniforms(time, &state.render_extent, &camera))?;
+        let demo_view = DemoView {
+            extent: state.extent.clone(),
+            view: camera.view_matrix(),
+            projection: camera.projection_matrix(state.extent.aspect_ratio()),
+            eye: camera.position,
+            near: camera.near,
+            far: camera.far,
+            time: time.total() as f32,
+        };
+        let frame_start = Instant::now();
+        if let (Some(ref scene_pipeline), Some(offscreen), false) = (scene_pipeline, &state.offscreen, demo_active) {
+            let command_buffer = {
+                let mut frame = self.frame_loop.context(0, time, offscreen.target())?;
+                let slot = frame.slot;
+                let scope = self.gpu_profiler.as_mut().and_then(|profiler| unsafe { profiler.begin_scope(frame.recorder.command_buffer(), "offscreen scene") });
+                {
+                    let mut pass = frame.recorder.begin_render_pass(&frame.target, &clear_values);
+                    record_scene(&mut pass, scene_pipeline, &self.scene.layout, self.scene_uniforms.descriptor_set(slot), self.scene_texture.descriptor_set(), &self.scene_vertices, &state.render_extent);
+                    // The copy to the swapchain image carries it over
+                    draw_overlays(pass.command_buffer(), slot, &state.extent)?;
+                }
+                if let Some(ref mut profiler) = self.gpu_profiler {
+                    unsafe { profiler.end_scope(frame.recorder.command_buffer(), scope) };
+                }
+                frame.end()?
+            };
+            self.frame_loop.submit_early(context.graphics_queue, command_buffer)?;
+            self.checkpoints.mark(time.frame(), "submitted offscreen scene");
+        }
+        // Until the multisampled pipeline is ready too, the scene renders straight to the
+        // swapchain image without MSAA
+        let uses_msaa = scene_pipeline.is_some() && msaa_pipeline.is_some() && state.msaa.is_some() && !demo_active;
+        let demo_blits = demo_active && self.demo.as_ref().is_some_and(|demo| demo.blits());
+        let copies_offscreen = (scene_pipeline.is_some() && state.offscreen.is_some() && !demo_active) || uses_msaa || demo_blits;
+        let sharpening = match (&state.cas, self.overlay.sharpening_push_constants()) {
+            (Some(cas), Some(constants)) => Some((cas, constants)),
+            _ => None,
+        };
+        // While a filter, sharpening, the NaN check or manual gamma is on, the final image is drawn
+        // into the filter's target, and from there into the swapchain image
+        let post_active = self.overlay.filter != OutputFilter::None || sharpening.is_some() || self.nan_check.is_some() || self.gamma.is_active();
+        let filter_pass = match state.filter_pass {
+            Some(ref filter_pass) if scene_pipeline.is_some() && !demo_blits && !uses_msaa && post_active => Some(filter_pass),
+            _ => None,
+        };
+        let acquire_start = Instant::now();
+        let acquired = unsafe { self.vk_swapchain.acquire(state.swapchain, self.acquire_policy.timeout_nanos(), self.frame_loop.image_available()) }?;
+        let acquired_at = Instant::now();
+        let outcome = match acquired {
+            AcquireOutcome::Acquired(image_idx) | AcquireOutcome::Suboptimal(image_idx) => {
+                self.checkpoints.mark(time.frame(), "acquired swapchain image");
+                let screenshot = if self.screenshot_requested {
+                    self.screenshot_requested = false;
+                    Some(screenshot_readback(context, &state.extent)?)
+                } else {
+                    None
+                };
+                let command_buffer = {
+                    let mut frame = self.frame_loop.context(image_idx, time, state.target(image_idx))?;
+                    let slot = frame.slot;
+                    let image = state.images[frame.image_index as usize];
+                    let final_target = filter_pass.map_or(frame.target.clone(), |filter_pass| filter_pass.source().target());
+                    let scope = self.gpu_profiler.as_mut().and_then(|profiler| unsafe { profiler.begin_scope(frame.recorder.command_buffer(), "frame") });
+                    // Frames still in flight might be drawing them
+                    for retired in self.retired_demos.drain(..) {
+                        frame.defer_destroy(move || drop(retired));
+                    }
+                    match (&scene_pipeline, &state.offscreen, &state.upscale_pass) {
+                        (&Some(_), _, _) if demo_active => {
+                            let overlays = if filter_pass.is_none() { Some(&draw_overlays) } else { None };
+                            record_demo(&mut **self.demo.as_mut().unwrap(), self.gpu_profiler.as_mut(), &mut frame, &demo_view, image, &final_target, &clear_values, overlays)?;
+                        },
+                        (&None, _, _) => {
+                            let pass = frame.recorder.begin_render_pass(&frame.target, &splash_clear_values);
+                            unsafe { self.splash.cmd_draw(pass.command_buffer(), &state.extent, &loading_progress) };
+                            draw_overlays(pass.command_buffer(), slot, &state.extent)?;
+                        },
+                        (&Some(_), Some(offscreen), _) => unsafe { offscreen.cmd_copy_to(frame.recorder.command_buffer(), image) },
+                        (Some(scene_pipeline), &None, Some(upscale_pass)) => {
+                            {
+                                let mut pass = frame.recorder.begin_render_pass(&upscale_pass.source().target(), &clear_values);
+                                record_scene(&mut pass, scene_pipeline, &self.scene.layout, self.scene_uniforms.descriptor_set(slot), self.scene_texture.descriptor_set(), &self.scene_vertices, &state.render_extent);
+                            }
+                            let mut pass = frame.recorder.begin_render_pass(&final_target, &clear_values);
+                            upscale_pass.cmd_draw(&mut pass, &self.upscaler);
+                            if filter_pass.is_none() {
+                                draw_overlays(pass.command_buffer(), slot, &state.extent)?;
+                            }
+                        },
+                        (&Some(_), &None, &None) if uses_msaa => {
+                            let (msaa, msaa_pipeline) = (state.msaa.as_ref().unwrap(), msaa_pipeline.as_ref().unwrap());
+                            {
+                                let mut locations = msaa.render_pass_sample_locations();
+                                let mut pass = match locations {
+                                    Some(ref mut locations) => frame.recorder.begin_render_pass_with(&msaa.target(), &clear_values, locations),
+                                    None => frame.recorder.begin_render_pass(&msaa.target(), &clear_values),
+                                };
+                                record_scene(&mut pass, msaa_pipeline, &self.scene.layout, self.scene_uniforms.descriptor_set(slot), self.scene_texture.descriptor_set(), &self.scene_vertices, &state.render_extent);
+                            }
+                            unsafe { msaa.cmd_resolve(frame.recorder.command_buffer()) };
+                            {
+                                let pass = frame.recorder.begin_render_pass(&msaa.overlay_target(), &clear_values);
+                                draw_overlays(pass.command_buffer(), slot, &state.extent)?;
+                            }
+                            unsafe { msaa.cmd_copy_to(frame.recorder.command_buffer(), image) };
+                        },
+                        (Some(scene_pipeline), &None, &None) => {
+                            let mut pass = frame.recorder.begin_render_pass(&final_target, &clear_values);
+                            record_scene(&mut pass, scene_pipeline, &self.scene.layout, self.scene_uniforms.descriptor_set(slot), self.scene_texture.descriptor_set(), &self.scene_vertices, &state.render_extent);
+                            if filter_pass.is_none() {
+                                draw_overlays(pass.command_buffer(), slot, &state.extent)?;
+                            }
+                        },
+                    }
+                    if let Some(filter_pass) = filter_pass {
+                        if let Some((cas, ref constants)) = sharpening {
+                            unsafe { cas.cmd_sharpen(frame.recorder.command_buffer(), constants) };
+                        }
+                        // The guides and console go over the filtered image, so they stay legible
+                        let pass = frame.recorder.begin_render_pass(&frame.target, &clear_values);
+                        unsafe { filter_pass.cmd_draw(pass.command_buffer(), self.overlay.filter, &self.gamma, sharpening.is_some()) };
+                        if let Some(ref nan_check) = self.nan_check {
+                            unsafe { nan_check.cmd_draw_overlay(pass.command_buffer(), &state.extent) };
+                        }
+                        draw_overlays(pass.command_buffer(), slot, &state.extent)?;
+                    }
+                    if let Some(ref mut profiler) = self.gpu_profiler {
+                        unsafe { profiler.end_scope(frame.recorder.command_buffer(), scope) };
+                    }
+                    if let Some(ref readback) = screenshot {
+                        unsafe { cmd_copy_screenshot(readback, frame.recorder.command_buffer(), image, &state.extent) };
+                    }
+                    if context.present_sharing == PresentSharing::OwnershipTransfer {
+                        unsafe {
+                            present_queue::cmd_release(&context.device, frame.recorder.command_buffer(), image, context.graphics_family, context.presentation_family);
+                        }
+                    }
+                    frame.end()?
+                };
+                let wait_stage = if copies_offscreen { PIPELINE_STAGE_TRANSFER_BIT } else { PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT };
+                let render_finished = self.frame_loop.submit(context.graphics_queue, command_buffer, wait_stage)?;
+                self.checkpoints.mark(time.frame(), "submitted frame");
+                if let (Some(_), &mut Some(ref mut nan_check)) = (filter_pass, &mut self.nan_check) {
+                    nan_check.count(context.graphics_queue)?;
+                }
+                let status = state.present(context, &self.vk_swapchain, image_idx, render_finished, self.frame_loop.submitted_frames())?;
+                self.checkpoints.mark(time.frame(), "presented");
+                self.latency_stats.record(frame_start, acquire_start, acquired_at, Instant::now());
+                if let Some(readback) = screenshot {
+                    save_screenshot(readback, self.surface_format.0, &state.extent)?;
+                }
+                FrameOutcome::from_present(status)
+            },
+            // Nothing was signaled, so the frame can just be dropped
+            outcome => FrameOutcome::from_acquire(outcome).unwrap(),
+        };
+        self.frame_stats.record(outcome, &self.acquire_policy)?;
+        Ok(outcome)
+    }
+
+    pub fn log_stats(&self) {
+        self.frame_stats.log();
+        self.latency_stats.log();
+        if let Some(ref profiler) = self.gpu_profiler {
+            profiler.log();
+        }
+        info!("Device memory:\n{}", self.context.allocator.stats());
+    }
+
+    /// Loads `import` into the scene. A new texture or model waits for the device to go idle, since
+    /// frames in flight might still be using the old one.
+    pub fn import_asset(&mut self, import: &Import) -> std::result::Result<(), RendererError> {
+        let context = self.context;
+        match import.kind {
+            AssetKind::Texture => {
+                let data = image::load(&import.path).map_err(|e| RendererError::Loading(format!("texture {:?}: {}", import.path, e)))?;
+                let immediate = ImmediateContext::new(&context.device, context.graphics_family, context.graphics_queue)?;
+                let scene_texture = create_scene_texture(context, &immediate, &data)?;
+                context.device.device_wait_idle()?;
+                self.scene_texture = scene_texture;
+            },
+            AssetKind::FragmentShader => self.scene.replace_fragment_shader(&import.path.to_string_lossy())?,
+            AssetKind::Model => {
+                let (mesh, texture) = load_model(&import.path).map_err(|e| RendererError::Loading(format!("model {:?}: {}", import.path, e)))?;
+                let immediate = ImmediateContext::new(&context.device, context.graphics_family, context.graphics_queue)?;
+                let scene_vertices = mesh.upload(&context.device, &context.allocator, &immediate)?;
+                name_scene_vertices(context, &scene_vertices);
+                let scene_texture = match texture {
+                    Some(data) => Some(create_scene_texture(context, &immediate, &data)?),
+                    None => None,
+                };
+                context.device.device_wait_idle()?;
+                self.scene_vertices = scene_vertices;
+                if let Some(scene_texture) = scene_texture {
+                    self.scene_texture = scene_texture;
+                }
+            },
+        }
+        info!("Imported {} {:?}", import.kind, import.path);
+        Ok(())
+    }
+
+    /// Whether the scene has finished loading, so frames show it rather than the splash.
+    pub fn is_scene_ready(&mut self) -> std::result::Result<bool, RendererError> {
+        Ok(self.scene.poll()?.is_some())
+    }
+
+    /// Renders the scene as it is at `time` once with each of `config`'s variants, at the
+    /// swapchain's size, and diffs the two on the GPU. It waits for the device to go idle and
+    /// blocks until it's done, so it's for one-off captures rather than every frame.
+    pub fn capture_frame_diff(&mut self, time: &Time, config: &FrameDiffConfig) -> std::result::Result<FrameDiff, RendererError> {
+        let context = self.context;
+        let device = &context.device;
+        let format = self.surface_format.0;
+        if !frame_diff::supports_format(format) {
+            return Err(RendererError::Unsupported(format!("frame diffs of {:?} frames", format)));
+        }
+        let scene_pipeline = match self.scene.poll()? {
+            Some(pipeline) => pipeline,
+            None => return Err(RendererError::Unsupported("frame diffs before the scene has loaded".to_string())),
+        };
+        device.device_wait_idle()?;
+        let extent = self.state().extent.clone();
+        // Nothing is in flight, so any slot will do
+        let slot = 0;
+        self.scene_uniforms.write(slot, &scene_uniforms(time, &extent, &self.camera))?;
+
+        let vert_shader_module = load_shader_module(device, FULLSCREEN_VERTEX_SHADER)?;
+        let frag_shader_module = load_shader_module(device, upscale::FRAGMENT_SHADER)?;
+        let mut targets = Vec::new();
+        let mut upscale_passes = Vec::new();
+        for variant in config.variants.iter() {
+            let target = OffscreenTarget::new(device, &context.allocator, format, Some(context.depth_format), extent.clone(), OffscreenUsage::Copy)?;
+            upscale_passes.push(if variant.is_active() {
+                let render_pass = *target.target().render_pass;
+                Some(UpscalePass::new(device, &context.allocator, format, Some(context.depth_format), variant.render_extent(&extent), extent.clone(), render_pass, *vert_shader_module, *frag_shader_module)?)
+            } else {
+                None
+            });
+            targets.push(target);
+        }
+        let spirv = shader_compile::load_spirv(frame_diff::SHADER).map_err(|e| RendererError::Shader(frame_diff::SHADER.to_string(), e))?;
+        let differ = FrameDiffer::new(device, &context.allocator, &spirv, format, extent.clone())?;
+        let amplify: f32 = cvar!("frame_diff.amplify", 8.0).get();
+
+        let immediate = ImmediateContext::new(device, context.graphics_family, context.graphics_queue)?;
+        let clear_values: [ClearValue; 2] = [ClearValue::new_color(ClearColorValue::new_float32(CLEAR_VALUE)), depth_buffer::clear_value()];
+        let (scene, scene_uniforms, scene_texture, scene_vertices) = (&self.scene, &self.scene_uniforms, &self.scene_texture, &self.scene_vertices);
+        immediate.immediate_submit(|command_buffer| unsafe {
+            let mut recorder = CommandRecorder::recording(device, command_buffer);
+            for ((variant, target), upscale_pass) in config.variants.iter().zip(targets.iter()).zip(upscale_passes.iter()) {
+                let uniforms = scene_uniforms.descriptor_set(slot);
+                match *upscale_pass {
+                    Some(ref upscale_pass) => {
+                        {
+                            let mut pass = recorder.begin_render_pass(&upscale_pass.source().target(), &clear_values);
+                            record_scene(&mut pass, &scene_pipeline, &scene.layout, uniforms, scene_texture.descriptor_set(), scene_vertices, upscale_pass.source().extent());
+                        }
+                        let mut pass = recorder.begin_render_pass(&target.target(), &clear_values);
+                        upscale_pass.cmd_draw(&mut pass, variant);
+                    },
+                    None => {
+                        let mut pass = recorder.begin_render_pass(&target.target(), &clear_values);
+                        record_scene(&mut pass, &scene_pipeline, &scene.layout, uniforms, scene_texture.descriptor_set(), scene_vertices, &extent);
+                    },
+                }
+            }
+            differ.cmd_diff(command_buffer, [targets[0].image(), targets[1].image()], amplify);
+        })?;
+        Ok(differ.read())
+    }
+}
+
+impl<'c> Drop for Renderer<'c> {
+    fn drop(&mut self) {
+        let _ = self.context.device.device_wait_idle();
+        self.destroy_swapchain();
+    }
+}
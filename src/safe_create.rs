@@ -1,15 +1,50 @@
 //! Safe versions of `create_*` methods from `ash`.
 use ash;
+use ash::RawPtr;
 use ash::prelude::VkResult;
 use ash::version::*;
 use std;
 use std::marker::PhantomData;
+use std::fmt;
 use std::ops::Deref;
 use vk::types::*;
-use ::vk_mem::VkOwned;
+use ::vk_mem::{ Destroy, VkOwned, VkShared };
 use ::glfw_surface;
 use glfw;
 
+/// Destroys objects that `loader` (a device, or an extension loader like `Swapchain`) created
+/// with `allocator`. Unlike a closure, it can be named, so functions returning one work on stable.
+pub struct Destroyer<'a, L: 'a, A> {
+    loader: &'a L,
+    allocator: Option<&'a AllocationCallbacks>,
+    name: &'static str,
+    destroy_fn: fn(&L, A, Option<&AllocationCallbacks>),
+}
+
+impl<'a, L, A: fmt::Debug> Destroy<A> for Destroyer<'a, L, A> {
+    fn destroy(&self, a: A) {
+        trace!("Destroying {}: {:?}", self.name, a);
+        (self.destroy_fn)(self.loader, a, self.allocator);
+    }
+}
+
+/// An object destroyed by the loader that created it.
+pub type Owned<'a, L, A> = VkOwned<A, Destroyer<'a, L, A>>;
+
+/// An `Owned` object with several owners, destroyed when the last one is dropped.
+pub type Shared<'a, L, A> = VkShared<A, Destroyer<'a, L, A>>;
+
+/// Takes ownership of `a`, like `VkOwned::new`. `destroy_fn` is given `loader` and `allocator`
+/// back when it's dropped.
+unsafe fn take_ownership<'a, L, A: Copy + fmt::Debug>(loader: &'a L, allocator: Option<&'a AllocationCallbacks>, name: &'static str, a: A, destroy_fn: fn(&L, A, Option<&AllocationCallbacks>)) -> Owned<'a, L, A> {
+    VkOwned::new(a, Destroyer {
+        loader: loader,
+        allocator: allocator,
+        name: name,
+        destroy_fn: destroy_fn,
+    })
+}
+
 #[allow(non_camel_case_types)]
 pub trait CreateDeviceSafeV1_0 {
     fn create_device_safe<'a>(&'a self, physical_device: PhysicalDevice, create_info: &DeviceCreateInfo, allocator: Option<&'a AllocationCallbacks>) -> std::result::Result<SafeDeviceV1_0<'a>, ash::DeviceError>;
@@ -58,66 +93,69 @@ impl<'instance> Deref for SafeDeviceV1_0<'instance> {
     }
 }
 
-pub fn create_shader_module_safe<'d, D: DeviceV1_0>(device: &'d D, create_info: &ShaderModuleCreateInfo, allocator: Option<&'d AllocationCallbacks>) -> VkResult<VkOwned<ShaderModule, impl Fn(ShaderModule)>> {
+pub fn create_shader_module_safe<'d, D: DeviceV1_0>(device: &'d D, create_info: &ShaderModuleCreateInfo, allocator: Option<&'d AllocationCallbacks>) -> VkResult<Owned<'d, D, ShaderModule>> {
     let unsafe_shader_module = unsafe { device.create_shader_module(create_info, allocator) };
-    unsafe_shader_module.map(|unsafe_shader_module| unsafe { VkOwned::new(unsafe_shader_module, move |shader_module| {
-        trace!("Destroying shader module: {:?}", shader_module);
-        device.destroy_shader_module(shader_module, allocator);
-    }) })
+    unsafe_shader_module.map(|unsafe_shader_module| unsafe { take_ownership(device, allocator, "shader module", unsafe_shader_module, |device, shader_module, allocator| device.destroy_shader_module(shader_module, allocator)) })
 }
 
-pub fn create_swapchain_khr_safe<'s>(vk_swapchain: &'s ash::extensions::Swapchain, create_info: &SwapchainCreateInfoKHR, allocator: Option<&'s AllocationCallbacks>) -> VkResult<VkOwned<SwapchainKHR, impl Fn(SwapchainKHR)>> {
-    let unsafe_swapchain = unsafe { vk_swapchain.create_swapchain_khr(&create_info, allocator) };
-    unsafe_swapchain.map(|unsafe_swapchain| unsafe { VkOwned::new(unsafe_swapchain, move |swapchain| {
-        trace!("Destroying swapchain: {:?}", swapchain);
-        vk_swapchain.destroy_swapchain_khr(swapchain, allocator);
-    }) })
+pub fn create_swapchain_khr_safe<'s>(vk_swapchain: &'s ash::extensions::Swapchain, create_info: &SwapchainCreateInfoKHR, allocator: Option<&'s AllocationCallbacks>) -> VkResult<Owned<'s, ash::extensions::Swapchain, SwapchainKHR>> {
+    let unsafe_swapchain = unsafe { vk_swapchain.create_swapchain_khr(create_info, allocator) };
+    unsafe_swapchain.map(|unsafe_swapchain| unsafe { take_ownership(vk_swapchain, allocator, "swapchain", unsafe_swapchain, |vk_swapchain, swapchain, allocator| vk_swapchain.destroy_swapchain_khr(swapchain, allocator)) })
 }
 
-pub fn create_image_view_safe<'s, D: DeviceV1_0>(device: &'s D, create_info: &ImageViewCreateInfo, allocator: Option<&'s AllocationCallbacks>) -> VkResult<VkOwned<ImageView, impl Fn(ImageView)>> {
+pub fn create_image_view_safe<'s, D: DeviceV1_0>(device: &'s D, create_info: &ImageViewCreateInfo, allocator: Option<&'s AllocationCallbacks>) -> VkResult<Owned<'s, D, ImageView>> {
     let unsafe_image_view = unsafe { device.create_image_view(create_info, allocator) };
-    unsafe_image_view.map(|unsafe_image_view| unsafe { VkOwned::new(unsafe_image_view, move |image_view| {
-        trace!("Destroying image view: {:?}", image_view);
-        device.destroy_image_view(image_view, allocator);
-    }) })
+    unsafe_image_view.map(|unsafe_image_view| unsafe { take_ownership(device, allocator, "image view", unsafe_image_view, |device, image_view, allocator| device.destroy_image_view(image_view, allocator)) })
 }
 
-pub fn create_window_surface_safe<'s, I: InstanceV1_0>(vk: &'s I, vk_surface: &'s ash::extensions::Surface, window: &'s glfw::Window, allocator: Option<&'s AllocationCallbacks>) -> VkResult<VkOwned<SurfaceKHR, impl Fn(SurfaceKHR)>> {
+// The renderer creates its surface alongside the loader that destroys it, so it can't hold this
+#[allow(dead_code)]
+pub fn create_window_surface_safe<'s, I: InstanceV1_0>(vk: &'s I, vk_surface: &'s ash::extensions::Surface, window: &'s glfw::Window, allocator: Option<&'s AllocationCallbacks>) -> VkResult<Owned<'s, ash::extensions::Surface, SurfaceKHR>> {
     let unsafe_surface = unsafe { glfw_surface::create_window_surface(vk, window, allocator) };
-    unsafe_surface.map(|unsafe_surface| unsafe { VkOwned::new(unsafe_surface, move |surface| {
-        trace!("Destroying surface: {:?}", surface);
-        vk_surface.destroy_surface_khr(surface, allocator)
-    }) })
+    unsafe_surface.map(|unsafe_surface| unsafe { take_ownership(vk_surface, allocator, "surface", unsafe_surface, |vk_surface, surface, allocator| vk_surface.destroy_surface_khr(surface, allocator)) })
 }
 
-pub fn create_pipeline_layout_safe<'d, D: DeviceV1_0>(device: &'d D, create_info: &PipelineLayoutCreateInfo, allocator: Option<&'d AllocationCallbacks>) -> VkResult<VkOwned<PipelineLayout, impl Fn(PipelineLayout)>> {
+pub fn create_pipeline_layout_safe<'d, D: DeviceV1_0>(device: &'d D, create_info: &PipelineLayoutCreateInfo, allocator: Option<&'d AllocationCallbacks>) -> VkResult<Owned<'d, D, PipelineLayout>> {
     let unsafe_layout = unsafe { device.create_pipeline_layout(create_info, allocator) };
-    unsafe_layout.map(|unsafe_layout| unsafe { VkOwned::new(unsafe_layout, move |layout| {
-        trace!("Destroying pipeline layout: {:?}", layout);
-        device.destroy_pipeline_layout(layout, allocator);
-    }) })
+    unsafe_layout.map(|unsafe_layout| unsafe { take_ownership(device, allocator, "pipeline layout", unsafe_layout, |device, layout, allocator| device.destroy_pipeline_layout(layout, allocator)) })
 }
 
-pub fn create_render_pass_safe<'d, D: DeviceV1_0>(device: &'d D, create_info: &RenderPassCreateInfo, allocator: Option<&'d AllocationCallbacks>) -> VkResult<VkOwned<RenderPass, impl Fn(RenderPass)>> {
+pub fn create_render_pass_safe<'d, D: DeviceV1_0>(device: &'d D, create_info: &RenderPassCreateInfo, allocator: Option<&'d AllocationCallbacks>) -> VkResult<Owned<'d, D, RenderPass>> {
     let unsafe_render_pass = unsafe { device.create_render_pass(create_info, allocator) };
-    unsafe_render_pass.map(|unsafe_render_pass| unsafe { VkOwned::new(unsafe_render_pass, move |render_pass| {
-        trace!("Destroying render pass: {:?}", render_pass);
-        device.destroy_render_pass(render_pass, allocator);
-    }) })
+    unsafe_render_pass.map(|unsafe_render_pass| unsafe { take_ownership(device, allocator, "render pass", unsafe_render_pass, |device, render_pass, allocator| device.destroy_render_pass(render_pass, allocator)) })
 }
 
-unsafe fn take_pipeline_ownership<'d, D: DeviceV1_0>(device: &'d D, allocator: Option<&'d AllocationCallbacks>, pipeline: Pipeline) -> VkOwned<Pipeline, impl Fn(Pipeline)> {
-    VkOwned::new(pipeline, move |pipeline| {
-        trace!("Destroying pipeline: {:?}", pipeline);
-        device.destroy_pipeline(pipeline, allocator);
-    })
+/// Takes ownership of a render pass made without `create_render_pass_safe`, e.g. through
+/// `vkCreateRenderPass2KHR`, which `ash` has no wrapper for.
+pub unsafe fn take_render_pass_ownership<'d, D: DeviceV1_0>(device: &'d D, allocator: Option<&'d AllocationCallbacks>, render_pass: RenderPass) -> Owned<'d, D, RenderPass> {
+    take_ownership(device, allocator, "render pass", render_pass, |device, render_pass, allocator| device.destroy_render_pass(render_pass, allocator))
 }
 
-// TODO: Fix the pipeline_cache safety
-pub fn create_graphics_pipelines_safe<'d, D: DeviceV1_0>(device: &'d D, pipeline_cache: &PipelineCache, create_infos: &[GraphicsPipelineCreateInfo], allocator: Option<&'d AllocationCallbacks>) -> std::result::Result<Vec<VkOwned<Pipeline, impl Fn(Pipeline)>>, (Vec<VkOwned<Pipeline, impl Fn (Pipeline)>>, Result)> {
+unsafe fn take_pipeline_ownership<'d, D: DeviceV1_0>(device: &'d D, allocator: Option<&'d AllocationCallbacks>, pipeline: Pipeline) -> Owned<'d, D, Pipeline> {
+    take_ownership(device, allocator, "pipeline", pipeline, |device, pipeline, allocator| device.destroy_pipeline(pipeline, allocator))
+}
+
+/// The pipelines created, or on failure the ones that were created anyway along with the error.
+pub type PipelinesResult<'d, D> = std::result::Result<Vec<Owned<'d, D, Pipeline>>, (Vec<Owned<'d, D, Pipeline>>, Result)>;
+
+/// `pipeline_cache` only has to stay alive for the call, e.g. by borrowing the
+/// `pipeline_cache::SafePipelineCache` it came from.
+pub fn create_graphics_pipelines_safe<'d, D: DeviceV1_0>(device: &'d D, pipeline_cache: &PipelineCache, create_infos: &[GraphicsPipelineCreateInfo], allocator: Option<&'d AllocationCallbacks>) -> PipelinesResult<'d, D> {
     let pipelines = unsafe { device.create_graphics_pipelines(*pipeline_cache, create_infos, allocator) };
     let take_ownership = move |pipelines: Vec<Pipeline>| pipelines.into_iter().map(move |pipeline| unsafe {
-        take_pipeline_ownership::<'d, D>(device, allocator, pipeline)
+        take_pipeline_ownership(device, allocator, pipeline)
+    }).collect();
+    match pipelines {
+        Ok(pipelines) => Ok(take_ownership(pipelines)),
+        Err((pipelines, err)) => Err((take_ownership(pipelines), err)),
+    }
+}
+
+/// Like `create_graphics_pipelines_safe`, for compute pipelines.
+pub fn create_compute_pipelines_safe<'d, D: DeviceV1_0>(device: &'d D, pipeline_cache: &PipelineCache, create_infos: &[ComputePipelineCreateInfo], allocator: Option<&'d AllocationCallbacks>) -> PipelinesResult<'d, D> {
+    let pipelines = unsafe { device.create_compute_pipelines(*pipeline_cache, create_infos, allocator) };
+    let take_ownership = move |pipelines: Vec<Pipeline>| pipelines.into_iter().map(move |pipeline| unsafe {
+        take_pipeline_ownership(device, allocator, pipeline)
     }).collect();
     match pipelines {
         Ok(pipelines) => Ok(take_ownership(pipelines)),
@@ -132,13 +170,30 @@ pub struct FramebufferCreateInfoSafe<'img> {
 }
 
 impl<'img> FramebufferCreateInfoSafe<'img> {
+    // Every framebuffer so far lives next to its attachments, so they all use `from_handles`
+    #[allow(dead_code)]
     pub fn new<It>(mut create_info: FramebufferCreateInfo, render_pass: &'img RenderPass, attachments: It) -> FramebufferCreateInfoSafe<'img> where It: Iterator<Item=&'img ImageView> {
         create_info.render_pass = *render_pass;
         let mut ret = FramebufferCreateInfoSafe {
             create_info: create_info,
-            attachments: attachments.map(|&img| img).collect(),
+            attachments: attachments.copied().collect(),
+            phantom_img: PhantomData,
+        };
+        ret.create_info.attachment_count = ret.attachments.len() as u32;
+        ret.create_info.p_attachments = ret.attachments.as_slice().as_ptr();
+        ret
+    }
+
+    /// Like `new`, for a render pass and attachments that live alongside the framebuffer, e.g. in
+    /// the same struct, where no borrow can show they outlive it. Unsafe because the caller has to
+    /// make sure they do, by dropping the framebuffer first.
+    pub unsafe fn from_handles(create_info: FramebufferCreateInfo, render_pass: RenderPass, attachments: &[ImageView]) -> FramebufferCreateInfoSafe<'static> {
+        let mut ret = FramebufferCreateInfoSafe {
+            create_info: create_info,
+            attachments: attachments.to_vec(),
             phantom_img: PhantomData,
         };
+        ret.create_info.render_pass = render_pass;
         ret.create_info.attachment_count = ret.attachments.len() as u32;
         ret.create_info.p_attachments = ret.attachments.as_slice().as_ptr();
         ret
@@ -149,27 +204,167 @@ impl<'img> FramebufferCreateInfoSafe<'img> {
     }
 }
 
-pub fn create_framebuffer_safe<'device, 'img, D: DeviceV1_0>(device: &'device D, create_info: FramebufferCreateInfoSafe<'img>, allocator: Option<&'device AllocationCallbacks>) -> VkResult<VkOwned<Framebuffer, impl Fn(Framebuffer)>> {
-    let unsafe_framebuffer = unsafe { device.create_framebuffer(create_info.info_ref(), allocator) };
-    unsafe_framebuffer.map(|unsafe_framebuffer| unsafe { VkOwned::new(unsafe_framebuffer, move |framebuffer| {
+/// Keeps the create info, so the framebuffer can't outlive the attachments it was made from.
+pub struct FramebufferDestroyer<'device, 'img, D: 'device> {
+    device: &'device D,
+    allocator: Option<&'device AllocationCallbacks>,
+    create_info: FramebufferCreateInfoSafe<'img>,
+}
+
+impl<'device, 'img, D: DeviceV1_0> Destroy<Framebuffer> for FramebufferDestroyer<'device, 'img, D> {
+    fn destroy(&self, framebuffer: Framebuffer) {
         trace!("Destroying framebuffer: {:?}", framebuffer);
-        trace!("Destroyed framebuffer was created from {:?}", create_info.info_ref());
-        device.destroy_framebuffer(framebuffer, allocator);
+        trace!("Destroyed framebuffer was created from {:?}", self.create_info.info_ref());
+        unsafe { self.device.destroy_framebuffer(framebuffer, self.allocator) };
+    }
+}
+
+/// A framebuffer from `FramebufferCreateInfoSafe::from_handles`.
+pub type OwnedFramebuffer<'device, D> = VkOwned<Framebuffer, FramebufferDestroyer<'device, 'static, D>>;
+
+pub fn create_framebuffer_safe<'device, 'img, D: DeviceV1_0>(device: &'device D, create_info: FramebufferCreateInfoSafe<'img>, allocator: Option<&'device AllocationCallbacks>) -> VkResult<VkOwned<Framebuffer, FramebufferDestroyer<'device, 'img, D>>> {
+    let unsafe_framebuffer = unsafe { device.create_framebuffer(create_info.info_ref(), allocator) };
+    unsafe_framebuffer.map(|unsafe_framebuffer| unsafe { VkOwned::new(unsafe_framebuffer, FramebufferDestroyer {
+        device: device,
+        allocator: allocator,
+        create_info: create_info,
     }) })
 }
 
-pub fn create_command_pool_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &CommandPoolCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<VkOwned<CommandPool, impl Fn(CommandPool)>> {
+pub fn create_command_pool_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &CommandPoolCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, CommandPool>> {
     let unsafe_command_pool = unsafe { device.create_command_pool(create_info, allocator) };
-    unsafe_command_pool.map(|unsafe_command_pool| unsafe { VkOwned::new(unsafe_command_pool, move |command_pool| {
-        trace!("Destroying command pool: {:?}", command_pool);
-        device.destroy_command_pool(command_pool, allocator);
-    }) })
+    unsafe_command_pool.map(|unsafe_command_pool| unsafe { take_ownership(device, allocator, "command pool", unsafe_command_pool, |device, command_pool, allocator| device.destroy_command_pool(command_pool, allocator)) })
 }
 
-pub fn create_semaphore_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &SemaphoreCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<VkOwned<Semaphore, impl Fn(Semaphore)>> {
+pub fn create_semaphore_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &SemaphoreCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, Semaphore>> {
     let unsafe_sem = unsafe { device.create_semaphore(create_info, allocator) };
-    unsafe_sem.map(|unsafe_sem| unsafe { VkOwned::new(unsafe_sem, move |sem| {
-        trace!("Destroying semaphore: {:?}", sem);
-        device.destroy_semaphore(sem, allocator);
-    }) })
+    unsafe_sem.map(|unsafe_sem| unsafe { take_ownership(device, allocator, "semaphore", unsafe_sem, |device, sem, allocator| device.destroy_semaphore(sem, allocator)) })
+}
+
+pub fn create_descriptor_set_layout_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &DescriptorSetLayoutCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, DescriptorSetLayout>> {
+    let unsafe_layout = unsafe { device.create_descriptor_set_layout(create_info, allocator) };
+    unsafe_layout.map(|unsafe_layout| unsafe { take_ownership(device, allocator, "descriptor set layout", unsafe_layout, |device, layout, allocator| device.destroy_descriptor_set_layout(layout, allocator)) })
+}
+
+pub fn create_descriptor_pool_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &DescriptorPoolCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, DescriptorPool>> {
+    let unsafe_pool = unsafe { device.create_descriptor_pool(create_info, allocator) };
+    unsafe_pool.map(|unsafe_pool| unsafe { take_ownership(device, allocator, "descriptor pool", unsafe_pool, |device, pool, allocator| device.destroy_descriptor_pool(pool, allocator)) })
+}
+
+pub fn create_buffer_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &BufferCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, Buffer>> {
+    let unsafe_buffer = unsafe { device.create_buffer(create_info, allocator) };
+    unsafe_buffer.map(|unsafe_buffer| unsafe { take_ownership(device, allocator, "buffer", unsafe_buffer, |device, buffer, allocator| device.destroy_buffer(buffer, allocator)) })
+}
+
+/// The image's memory isn't freed with it; whoever bound the memory still has to free it.
+pub fn create_image_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &ImageCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, Image>> {
+    let unsafe_image = unsafe { device.create_image(create_info, allocator) };
+    unsafe_image.map(|unsafe_image| unsafe { take_ownership(device, allocator, "image", unsafe_image, |device, image, allocator| device.destroy_image(image, allocator)) })
+}
+
+pub fn create_fence_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &FenceCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, Fence>> {
+    let unsafe_fence = unsafe { device.create_fence(create_info, allocator) };
+    unsafe_fence.map(|unsafe_fence| unsafe { take_ownership(device, allocator, "fence", unsafe_fence, |device, fence, allocator| device.destroy_fence(fence, allocator)) })
+}
+
+pub fn create_sampler_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &SamplerCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, Sampler>> {
+    let unsafe_sampler = unsafe { device.create_sampler(create_info, allocator) };
+    unsafe_sampler.map(|unsafe_sampler| unsafe { take_ownership(device, allocator, "sampler", unsafe_sampler, |device, sampler, allocator| device.destroy_sampler(sampler, allocator)) })
+}
+
+/// `ash` has no wrapper for query pools, so this goes through the raw function pointers.
+pub fn create_query_pool_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &QueryPoolCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, QueryPool>> {
+    let mut unsafe_pool = QueryPool::null();
+    let err_code = unsafe { device.fp_v1_0().create_query_pool(device.handle(), create_info, allocator.as_raw_ptr(), &mut unsafe_pool) };
+    match err_code {
+        Result::Success => Ok(unsafe { take_ownership(device, allocator, "query pool", unsafe_pool, |device, pool, allocator| device.fp_v1_0().destroy_query_pool(device.handle(), pool, allocator.as_raw_ptr())) }),
+        e => Err(e),
+    }
+}
+
+/// Like `create_query_pool_safe`, for events.
+pub fn create_event_safe<'device, D: DeviceV1_0>(device: &'device D, create_info: &EventCreateInfo, allocator: Option<&'device AllocationCallbacks>) -> VkResult<Owned<'device, D, Event>> {
+    let mut unsafe_event = Event::null();
+    let err_code = unsafe { device.fp_v1_0().create_event(device.handle(), create_info, allocator.as_raw_ptr(), &mut unsafe_event) };
+    match err_code {
+        Result::Success => Ok(unsafe { take_ownership(device, allocator, "event", unsafe_event, |device, event, allocator| device.fp_v1_0().destroy_event(device.handle(), event, allocator.as_raw_ptr())) }),
+        e => Err(e),
+    }
+}
+
+/// Command buffers, freed when dropped. They borrow the pool they came from, so it can't be
+/// destroyed while they're still around.
+pub struct CommandBuffersSafe<'pool, D: DeviceV1_0 + 'pool> {
+    device: &'pool D,
+    pool: CommandPool,
+    command_buffers: Vec<CommandBuffer>,
+}
+
+impl<'pool, D: DeviceV1_0> Deref for CommandBuffersSafe<'pool, D> {
+    type Target = [CommandBuffer];
+
+    fn deref(&self) -> &[CommandBuffer] {
+        &self.command_buffers
+    }
+}
+
+impl<'pool, D: DeviceV1_0> Drop for CommandBuffersSafe<'pool, D> {
+    fn drop(&mut self) {
+        trace!("Freeing command buffers: {:?}", &self.command_buffers);
+        unsafe { self.device.free_command_buffers(self.pool, &self.command_buffers) };
+    }
+}
+
+/// Allocates `count` command buffers at `level` from `pool`. They must not be pending execution
+/// when dropped.
+pub fn allocate_command_buffers_safe<'pool, D: DeviceV1_0, F: Destroy<CommandPool>>(device: &'pool D, pool: &'pool VkOwned<CommandPool, F>, level: CommandBufferLevel, count: u32) -> VkResult<CommandBuffersSafe<'pool, D>> {
+    unsafe { allocate_command_buffers_from_handle(device, **pool, level, count) }
+}
+
+/// Like `allocate_command_buffers_safe`, for a pool that lives alongside the command buffers,
+/// e.g. in the same struct, where no borrow can show it outlives them. Unsafe because the caller
+/// has to make sure it does, by dropping the command buffers first.
+pub unsafe fn allocate_command_buffers_from_handle<'d, D: DeviceV1_0>(device: &'d D, pool: CommandPool, level: CommandBufferLevel, count: u32) -> VkResult<CommandBuffersSafe<'d, D>> {
+    let allocate_info = CommandBufferAllocateInfo {
+        s_type: StructureType::CommandBufferAllocateInfo,
+        p_next: std::ptr::null(),
+        command_pool: pool,
+        level: level,
+        command_buffer_count: count,
+    };
+    device.allocate_command_buffers(&allocate_info).map(|unsafe_command_buffers| CommandBuffersSafe {
+        device: device,
+        pool: pool,
+        command_buffers: unsafe_command_buffers,
+    })
+}
+
+/// Descriptor sets, which can't outlive the pool they came from. They're freed along with it.
+pub struct DescriptorSetsSafe<'pool> {
+    sets: Vec<DescriptorSet>,
+    phantom_pool: PhantomData<&'pool DescriptorPool>,
+}
+
+impl<'pool> Deref for DescriptorSetsSafe<'pool> {
+    type Target = [DescriptorSet];
+
+    fn deref(&self) -> &[DescriptorSet] {
+        &self.sets
+    }
+}
+
+/// Allocates one set per layout in `set_layouts` from `pool`.
+pub fn allocate_descriptor_sets_safe<'pool, D: DeviceV1_0>(device: &D, pool: &'pool DescriptorPool, set_layouts: &[DescriptorSetLayout]) -> VkResult<DescriptorSetsSafe<'pool>> {
+    let allocate_info = DescriptorSetAllocateInfo {
+        s_type: StructureType::DescriptorSetAllocateInfo,
+        p_next: std::ptr::null(),
+        descriptor_pool: *pool,
+        descriptor_set_count: set_layouts.len() as u32,
+        p_set_layouts: set_layouts.as_ptr(),
+    };
+    let unsafe_sets = unsafe { device.allocate_descriptor_sets(&allocate_info) };
+    unsafe_sets.map(|unsafe_sets| DescriptorSetsSafe {
+        sets: unsafe_sets,
+        phantom_pool: PhantomData,
+    })
 }
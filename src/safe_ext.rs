@@ -1,22 +1,114 @@
+use ash::prelude::VkResult;
 use ash::version::*;
 use ash::extensions;
+use std;
 use std::marker::PhantomData;
+use std::ffi::CString;
 use std::ops::Deref;
+use vk::types::*;
+use ::present_id::PresentIdInfo;
+use ::safe_info::PresentInfoSafe;
+
+/// How presenting to one swapchain went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentStatus {
+    Optimal,
+    /// Presented, but the swapchain no longer matches the surface exactly.
+    Suboptimal,
+    /// Not presented; the swapchain can't be used with the surface anymore.
+    OutOfDate,
+}
+
+impl PresentStatus {
+    /// Splits a per-swapchain result into a status, or the error that isn't one.
+    pub fn from_result(result: Result) -> VkResult<PresentStatus> {
+        match result {
+            Result::Success => Ok(PresentStatus::Optimal),
+            Result::SuboptimalKhr => Ok(PresentStatus::Suboptimal),
+            Result::ErrorOutOfDateKhr => Ok(PresentStatus::OutOfDate),
+            e => Err(e),
+        }
+    }
+}
+
+/// How acquiring a swapchain image went. Only the variants with an index signal the semaphore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquireOutcome {
+    Acquired(u32),
+    /// Acquired, but the swapchain no longer matches the surface exactly.
+    Suboptimal(u32),
+    /// No image was available and the timeout was zero.
+    NotReady,
+    /// No image became available within the timeout.
+    Timeout,
+    OutOfDate,
+}
+
+type AcquireNextImageFn = unsafe extern "system" fn(Device, SwapchainKHR, u64, Semaphore, Fence, *mut u32) -> Result;
 
 pub struct SafeSwapchain<'device, I: InstanceV1_0 + 'device, D: DeviceV1_0 + 'device> {
     swapchain: extensions::Swapchain,
+    device: Device,
+    // ash's wrapper loses the image index when acquiring is suboptimal
+    acquire_next_image: AcquireNextImageFn,
     phantom_instance: PhantomData<&'device I>,
     phantom_device: PhantomData<&'device D>
 }
 
 impl<'device, I: InstanceV1_0, D: DeviceV1_0> SafeSwapchain<'device, I, D> {
-    pub fn new(instance: &'device I, device: &'device D) -> Result<SafeSwapchain<'device, I, D>, Vec<&'static str>> {
+    pub fn new(instance: &'device I, device: &'device D) -> std::result::Result<SafeSwapchain<'device, I, D>, Vec<&'static str>> {
+        let name = CString::new("vkAcquireNextImageKHR").unwrap();
+        let pfn = instance.get_device_proc_addr(device.handle(), name.as_ptr());
+        if pfn as usize == 0 {
+            return Err(vec!["vkAcquireNextImageKHR"]);
+        }
+        let acquire_next_image = unsafe { std::mem::transmute::<PFN_vkVoidFunction, AcquireNextImageFn>(pfn) };
         extensions::Swapchain::new(instance, device).map(|unsafe_swapchain| SafeSwapchain {
             swapchain: unsafe_swapchain,
+            device: device.handle(),
+            acquire_next_image: acquire_next_image,
             phantom_instance: PhantomData,
             phantom_device: PhantomData
         })
     }
+
+    /// Acquires the next image of `swapchain`, waiting at most `timeout` nanoseconds, and signals
+    /// `semaphore` once it's usable. Expected non-success results are returned as outcomes.
+    pub unsafe fn acquire(&self, swapchain: SwapchainKHR, timeout: u64, semaphore: Semaphore) -> VkResult<AcquireOutcome> {
+        let mut index = 0;
+        match (self.acquire_next_image)(self.device, swapchain, timeout, semaphore, Fence::null(), &mut index) {
+            Result::Success => Ok(AcquireOutcome::Acquired(index)),
+            Result::SuboptimalKhr => Ok(AcquireOutcome::Suboptimal(index)),
+            Result::NotReady => Ok(AcquireOutcome::NotReady),
+            Result::Timeout => Ok(AcquireOutcome::Timeout),
+            Result::ErrorOutOfDateKhr => Ok(AcquireOutcome::OutOfDate),
+            e => Err(e),
+        }
+    }
+
+    /// Presents `image_indices[i]` of `swapchains[i]` once `wait_semaphores` are signaled, and
+    /// returns each swapchain's own status. Errors that aren't about a particular swapchain being
+    /// stale (device loss, a lost surface) are returned as the error. `present_ids`, one per
+    /// swapchain, need `VK_KHR_present_id` enabled.
+    pub unsafe fn present(&self, queue: Queue, wait_semaphores: &[Semaphore], swapchains: &[SwapchainKHR], image_indices: &[u32], present_ids: Option<&[u64]>) -> VkResult<Vec<PresentStatus>> {
+        assert_eq!(swapchains.len(), image_indices.len());
+        let present_info = wait_semaphores.iter().fold(PresentInfoSafe::new(), |info, &semaphore| info.wait(semaphore));
+        let present_info = swapchains.iter().zip(image_indices.iter()).fold(present_info, |info, (&swapchain, &image_index)| info.swapchain(swapchain, image_index));
+        let mut id_info = present_ids.map(|ids| {
+            assert_eq!(ids.len(), swapchains.len());
+            PresentIdInfo::new(ids)
+        });
+        let mut present_info = match id_info {
+            Some(ref mut id_info) => present_info.push_next(id_info),
+            None => present_info,
+        };
+        match self.swapchain.queue_present_khr(queue, present_info.present_info()) {
+            // The overall result is only the worst of the per-swapchain ones here
+            Ok(()) | Err(Result::SuboptimalKhr) | Err(Result::ErrorOutOfDateKhr) => {},
+            Err(e) => return Err(e),
+        }
+        present_info.results().iter().map(|&result| PresentStatus::from_result(result)).collect()
+    }
 }
 
 impl<'device, I: InstanceV1_0, D: DeviceV1_0> Deref for SafeSwapchain<'device, I, D> {
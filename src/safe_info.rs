@@ -0,0 +1,260 @@
+//! Info structs that own the arrays they point into, like `safe_create::FramebufferCreateInfoSafe`,
+//! so a pointer into a temporary can't be handed to Vulkan. Each builder keeps its arrays in
+//! `Vec`s, whose heap storage doesn't move when the builder does, and re-points the raw struct
+//! whenever one of them changes. Extension structs go on a `pnext::PNextChain`, and the builder's
+//! lifetime keeps them alive for as long as the builder is.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std::marker::PhantomData;
+use std::ptr;
+use vk::types::*;
+use ::pnext::{ ExtensionStruct, PNextChain };
+
+fn ptr_or_null<T>(items: &[T]) -> *const T {
+    if items.is_empty() { ptr::null() } else { items.as_ptr() }
+}
+
+/// A `SubmitInfo` for `queue_submit_safe`.
+pub struct SubmitInfoSafe<'a> {
+    info: SubmitInfo,
+    wait_semaphores: Vec<Semaphore>,
+    wait_stages: Vec<PipelineStageFlags>,
+    command_buffers: Vec<CommandBuffer>,
+    signal_semaphores: Vec<Semaphore>,
+    next: PNextChain<'a>,
+}
+
+impl<'a> SubmitInfoSafe<'a> {
+    pub fn new() -> SubmitInfoSafe<'a> {
+        SubmitInfoSafe {
+            info: SubmitInfo {
+                s_type: StructureType::SubmitInfo,
+                p_next: ptr::null(),
+                wait_semaphore_count: 0,
+                p_wait_semaphores: ptr::null(),
+                p_wait_dst_stage_mask: ptr::null(),
+                command_buffer_count: 0,
+                p_command_buffers: ptr::null(),
+                signal_semaphore_count: 0,
+                p_signal_semaphores: ptr::null(),
+            },
+            wait_semaphores: Vec::new(),
+            wait_stages: Vec::new(),
+            command_buffers: Vec::new(),
+            signal_semaphores: Vec::new(),
+            next: PNextChain::new(),
+        }
+    }
+
+    /// Waits on `semaphore` before `stage`.
+    pub fn wait(mut self, semaphore: Semaphore, stage: PipelineStageFlags) -> SubmitInfoSafe<'a> {
+        self.wait_semaphores.push(semaphore);
+        self.wait_stages.push(stage);
+        self.repoint()
+    }
+
+    pub fn command_buffer(mut self, command_buffer: CommandBuffer) -> SubmitInfoSafe<'a> {
+        self.command_buffers.push(command_buffer);
+        self.repoint()
+    }
+
+    pub fn signal(mut self, semaphore: Semaphore) -> SubmitInfoSafe<'a> {
+        self.signal_semaphores.push(semaphore);
+        self.repoint()
+    }
+
+    /// Chains `next`, which has to be an extension of `SubmitInfo`.
+    pub fn push_next<T: ExtensionStruct>(mut self, next: &'a mut T) -> SubmitInfoSafe<'a> {
+        self.next = self.next.push(next);
+        self.info.p_next = self.next.head();
+        self
+    }
+
+    fn repoint(mut self) -> SubmitInfoSafe<'a> {
+        self.info.wait_semaphore_count = self.wait_semaphores.len() as u32;
+        self.info.p_wait_semaphores = ptr_or_null(&self.wait_semaphores);
+        self.info.p_wait_dst_stage_mask = ptr_or_null(&self.wait_stages);
+        self.info.command_buffer_count = self.command_buffers.len() as u32;
+        self.info.p_command_buffers = ptr_or_null(&self.command_buffers);
+        self.info.signal_semaphore_count = self.signal_semaphores.len() as u32;
+        self.info.p_signal_semaphores = ptr_or_null(&self.signal_semaphores);
+        self
+    }
+
+    pub fn info_ref(&self) -> &SubmitInfo {
+        &self.info
+    }
+}
+
+/// Submits `submits` to `queue`, signaling `fence` (which may be null) when they're done.
+pub unsafe fn queue_submit_safe<D: DeviceV1_0>(device: &D, queue: Queue, submits: &[SubmitInfoSafe], fence: Fence) -> VkResult<()> {
+    let infos: Vec<SubmitInfo> = submits.iter().map(|submit| submit.info_ref().clone()).collect();
+    device.queue_submit(queue, &infos, fence)
+}
+
+/// A `PresentInfoKHR` that keeps the per-swapchain results.
+pub struct PresentInfoSafe<'a> {
+    info: PresentInfoKHR,
+    wait_semaphores: Vec<Semaphore>,
+    swapchains: Vec<SwapchainKHR>,
+    image_indices: Vec<u32>,
+    results: Vec<Result>,
+    next: PNextChain<'a>,
+}
+
+impl<'a> PresentInfoSafe<'a> {
+    pub fn new() -> PresentInfoSafe<'a> {
+        PresentInfoSafe {
+            info: PresentInfoKHR {
+                s_type: StructureType::PresentInfoKhr,
+                p_next: ptr::null(),
+                wait_semaphore_count: 0,
+                p_wait_semaphores: ptr::null(),
+                swapchain_count: 0,
+                p_swapchains: ptr::null(),
+                p_image_indices: ptr::null(),
+                p_results: ptr::null_mut(),
+            },
+            wait_semaphores: Vec::new(),
+            swapchains: Vec::new(),
+            image_indices: Vec::new(),
+            results: Vec::new(),
+            next: PNextChain::new(),
+        }
+    }
+
+    pub fn wait(mut self, semaphore: Semaphore) -> PresentInfoSafe<'a> {
+        self.wait_semaphores.push(semaphore);
+        self.repoint()
+    }
+
+    /// Presents image `image_index` of `swapchain`.
+    pub fn swapchain(mut self, swapchain: SwapchainKHR, image_index: u32) -> PresentInfoSafe<'a> {
+        self.swapchains.push(swapchain);
+        self.image_indices.push(image_index);
+        self.results.push(Result::Success);
+        self.repoint()
+    }
+
+    /// Chains `next`, like `SubmitInfoSafe::push_next`.
+    pub fn push_next<T: ExtensionStruct>(mut self, next: &'a mut T) -> PresentInfoSafe<'a> {
+        self.next = self.next.push(next);
+        self.info.p_next = self.next.head();
+        self
+    }
+
+    fn repoint(mut self) -> PresentInfoSafe<'a> {
+        self.info.wait_semaphore_count = self.wait_semaphores.len() as u32;
+        self.info.p_wait_semaphores = ptr_or_null(&self.wait_semaphores);
+        self.info.swapchain_count = self.swapchains.len() as u32;
+        self.info.p_swapchains = ptr_or_null(&self.swapchains);
+        self.info.p_image_indices = ptr_or_null(&self.image_indices);
+        self.info.p_results = if self.results.is_empty() { ptr::null_mut() } else { self.results.as_mut_ptr() };
+        self
+    }
+
+    /// The info to present with. Presenting writes each swapchain's result into `self`, which
+    /// is why it takes `&mut`.
+    pub fn present_info(&mut self) -> &PresentInfoKHR {
+        &self.info
+    }
+
+    /// Each swapchain's result, in the order they were added, once presented.
+    pub fn results(&self) -> &[Result] {
+        &self.results
+    }
+}
+
+/// A `RenderPassBeginInfo` with its clear values.
+pub struct RenderPassBeginInfoSafe<'a> {
+    info: RenderPassBeginInfo,
+    clear_values: Vec<ClearValue>,
+    next: PNextChain<'a>,
+    phantom: PhantomData<&'a RenderPass>,
+}
+
+impl<'a> RenderPassBeginInfoSafe<'a> {
+    /// Begins `render_pass` in `framebuffer`, over `render_area`.
+    pub fn new(render_pass: &'a RenderPass, framebuffer: &'a Framebuffer, render_area: Rect2D) -> RenderPassBeginInfoSafe<'a> {
+        RenderPassBeginInfoSafe {
+            info: RenderPassBeginInfo {
+                s_type: StructureType::RenderPassBeginInfo,
+                p_next: ptr::null(),
+                render_pass: *render_pass,
+                framebuffer: *framebuffer,
+                render_area: render_area,
+                clear_value_count: 0,
+                p_clear_values: ptr::null(),
+            },
+            clear_values: Vec::new(),
+            next: PNextChain::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// One clear value per attachment, in attachment order.
+    pub fn clear_values(mut self, clear_values: &[ClearValue]) -> RenderPassBeginInfoSafe<'a> {
+        self.clear_values = clear_values.to_vec();
+        self.info.clear_value_count = self.clear_values.len() as u32;
+        self.info.p_clear_values = ptr_or_null(&self.clear_values);
+        self
+    }
+
+    /// Chains `next`, like `SubmitInfoSafe::push_next`.
+    pub fn push_next<T: ExtensionStruct>(mut self, next: &'a mut T) -> RenderPassBeginInfoSafe<'a> {
+        self.next = self.next.push(next);
+        self.info.p_next = self.next.head();
+        self
+    }
+
+    pub fn info_ref(&self) -> &RenderPassBeginInfo {
+        &self.info
+    }
+}
+
+/// A `SwapchainCreateInfoKHR` with its queue family indices.
+pub struct SwapchainCreateInfoSafe<'a> {
+    info: SwapchainCreateInfoKHR,
+    queue_family_indices: Vec<u32>,
+    next: PNextChain<'a>,
+}
+
+impl<'a> SwapchainCreateInfoSafe<'a> {
+    /// `create_info`'s queue family indices and `p_next` are replaced; use `queue_families` and
+    /// `push_next` for those.
+    pub fn new(mut create_info: SwapchainCreateInfoKHR) -> SwapchainCreateInfoSafe<'a> {
+        create_info.p_next = ptr::null();
+        create_info.queue_family_index_count = 0;
+        create_info.p_queue_family_indices = ptr::null();
+        SwapchainCreateInfoSafe {
+            info: create_info,
+            queue_family_indices: Vec::new(),
+            next: PNextChain::new(),
+        }
+    }
+
+    /// The families sharing the images, for `SharingMode::Concurrent`.
+    pub fn queue_families<I: Iterator<Item = u32>>(mut self, families: I) -> SwapchainCreateInfoSafe<'a> {
+        self.queue_family_indices = families.collect();
+        self.info.queue_family_index_count = self.queue_family_indices.len() as u32;
+        self.info.p_queue_family_indices = ptr_or_null(&self.queue_family_indices);
+        self
+    }
+
+    /// Chains `next`, like `SubmitInfoSafe::push_next`.
+    pub fn push_next<T: ExtensionStruct>(mut self, next: &'a mut T) -> SwapchainCreateInfoSafe<'a> {
+        self.next = self.next.push(next);
+        self.info.p_next = self.next.head();
+        self
+    }
+
+    pub fn info_ref(&self) -> &SwapchainCreateInfoKHR {
+        &self.info
+    }
+
+    /// For setting fields `ash` can't, like color spaces it doesn't know. The count and pointer
+    /// fields must be left alone.
+    pub unsafe fn info_mut(&mut self) -> &mut SwapchainCreateInfoKHR {
+        &mut self.info
+    }
+}
@@ -0,0 +1,214 @@
+//! Custom MSAA sample positions, from `VK_EXT_sample_locations`, for comparing the device's
+//! standard pattern against others with `--msaa-sample-locations=<ordered|center>`. The pattern
+//! is baked into the multisampled scene pipeline (see `GraphicsPipelineBuilder::sample_locations`),
+//! and the scene's render pass is begun with it too, so depth is laid out for the same positions
+//! it's tested at. `ash` predates the extension, so its structures are declared here.
+use std;
+use std::marker::PhantomData;
+use std::ptr;
+use vk::types::*;
+use ::pnext::ExtensionStruct;
+use ::rect;
+
+pub const EXTENSION_NAME: &'static str = "VK_EXT_sample_locations";
+pub const ARG_PREFIX: &'static str = "--msaa-sample-locations=";
+
+const STRUCTURE_TYPE_SAMPLE_LOCATIONS_INFO: u32 = 1000143000;
+const STRUCTURE_TYPE_RENDER_PASS_SAMPLE_LOCATIONS_BEGIN_INFO: u32 = 1000143001;
+const STRUCTURE_TYPE_PIPELINE_SAMPLE_LOCATIONS_STATE_CREATE_INFO: u32 = 1000143002;
+pub const STRUCTURE_TYPE_PHYSICAL_DEVICE_SAMPLE_LOCATIONS_PROPERTIES: u32 = 1000143003;
+
+/// `VK_IMAGE_CREATE_SAMPLE_LOCATIONS_COMPATIBLE_DEPTH_BIT_EXT`, for depth images rendered with
+/// custom locations.
+const IMAGE_CREATE_SAMPLE_LOCATIONS_COMPATIBLE_DEPTH_BIT: u32 = 0x1000;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SampleLocationRaw {
+    x: f32,
+    y: f32,
+}
+
+#[repr(C)]
+#[derive(Clone)]
+struct SampleLocationsInfoRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    sample_locations_per_pixel: SampleCountFlags,
+    sample_location_grid_size: Extent2D,
+    sample_locations_count: u32,
+    p_sample_locations: *const SampleLocationRaw,
+}
+
+#[repr(C)]
+struct AttachmentSampleLocationsRaw {
+    attachment_index: u32,
+    sample_locations_info: SampleLocationsInfoRaw,
+}
+
+#[repr(C)]
+struct SubpassSampleLocationsRaw {
+    subpass_index: u32,
+    sample_locations_info: SampleLocationsInfoRaw,
+}
+
+#[repr(C)]
+pub struct PipelineSampleLocationsState<'a> {
+    s_type: u32,
+    p_next: *const c_void,
+    sample_locations_enable: Bool32,
+    sample_locations_info: SampleLocationsInfoRaw,
+    phantom: PhantomData<&'a SampleLocations>,
+}
+
+unsafe impl<'a> ExtensionStruct for PipelineSampleLocationsState<'a> {}
+
+/// What `VkRenderPassSampleLocationsBeginInfoEXT` points into is kept after it, where Vulkan
+/// doesn't look.
+#[repr(C)]
+pub struct RenderPassSampleLocations<'a> {
+    s_type: u32,
+    p_next: *const c_void,
+    attachment_initial_sample_locations_count: u32,
+    p_attachment_initial_sample_locations: *const AttachmentSampleLocationsRaw,
+    post_subpass_sample_locations_count: u32,
+    p_post_subpass_sample_locations: *const SubpassSampleLocationsRaw,
+    _attachments: Vec<AttachmentSampleLocationsRaw>,
+    _subpasses: Vec<SubpassSampleLocationsRaw>,
+    phantom: PhantomData<&'a SampleLocations>,
+}
+
+unsafe impl<'a> ExtensionStruct for RenderPassSampleLocations<'a> {}
+
+#[repr(C)]
+pub struct PhysicalDeviceSampleLocationsPropertiesRaw {
+    pub s_type: u32,
+    pub p_next: *mut c_void,
+    pub sample_location_sample_counts: SampleCountFlags,
+    pub max_sample_location_grid_size: Extent2D,
+    pub sample_location_coordinate_range: [f32; 2],
+    pub sample_location_sub_pixel_bits: u32,
+    pub variable_sample_locations: Bool32,
+}
+
+unsafe impl ExtensionStruct for PhysicalDeviceSampleLocationsPropertiesRaw {}
+
+/// Flags for a depth image that's rendered with custom locations. `ImageCreateFlags` only
+/// accepts the bits `ash` knows about, so this goes in through the raw representation.
+pub fn depth_image_flags() -> ImageCreateFlags {
+    unsafe { std::mem::transmute::<u32, ImageCreateFlags>(IMAGE_CREATE_SAMPLE_LOCATIONS_COMPATIBLE_DEPTH_BIT) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplePattern {
+    /// An axis-aligned grid, which catches near-horizontal and near-vertical edges worse than the
+    /// rotated patterns devices use.
+    Ordered,
+    /// Every sample at the pixel center, so edges come out as aliased as without MSAA while
+    /// everything else still costs the same.
+    Center,
+}
+
+impl SamplePattern {
+    pub fn parse(text: &str) -> Option<SamplePattern> {
+        match text {
+            "ordered" => Some(SamplePattern::Ordered),
+            "center" => Some(SamplePattern::Center),
+            _ => None,
+        }
+    }
+}
+
+/// The positions of one pixel's samples, in `[0, 1)` from its top left corner.
+#[derive(Debug, Clone)]
+pub struct SampleLocations {
+    samples: SampleCountFlags,
+    locations: Vec<SampleLocationRaw>,
+}
+
+impl SampleLocations {
+    /// `pattern` with `samples` per pixel, which has to be a power of two.
+    pub fn new(pattern: SamplePattern, samples: u32) -> SampleLocations {
+        let locations = match pattern {
+            SamplePattern::Ordered => {
+                // Twice as many columns as rows when the count isn't a square
+                let rows = 1 << (samples.trailing_zeros() / 2);
+                let columns = samples / rows;
+                (0..samples).map(|i| SampleLocationRaw {
+                    x: ((i % columns) as f32 + 0.5) / columns as f32,
+                    y: ((i / columns) as f32 + 0.5) / rows as f32,
+                }).collect()
+            },
+            SamplePattern::Center => (0..samples).map(|_| SampleLocationRaw { x: 0.5, y: 0.5 }).collect(),
+        };
+        SampleLocations {
+            samples: SampleCountFlags::from_flags_truncate(samples),
+            locations: locations,
+        }
+    }
+
+    pub fn positions(&self) -> Vec<(f32, f32)> {
+        self.locations.iter().map(|location| (location.x, location.y)).collect()
+    }
+
+    /// Points into `self`, so it mustn't outlive it.
+    fn info(&self) -> SampleLocationsInfoRaw {
+        SampleLocationsInfoRaw {
+            s_type: STRUCTURE_TYPE_SAMPLE_LOCATIONS_INFO,
+            p_next: ptr::null(),
+            sample_locations_per_pixel: self.samples,
+            sample_location_grid_size: rect::extent(1, 1),
+            sample_locations_count: self.locations.len() as u32,
+            p_sample_locations: self.locations.as_ptr(),
+        }
+    }
+
+    /// For a pipeline's multisample state, rasterizing with these locations.
+    pub fn pipeline_state<'a>(&'a self) -> PipelineSampleLocationsState<'a> {
+        PipelineSampleLocationsState {
+            s_type: STRUCTURE_TYPE_PIPELINE_SAMPLE_LOCATIONS_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            sample_locations_enable: true as Bool32,
+            sample_locations_info: self.info(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// For beginning a single-subpass render pass whose `depth_attachment` is rendered with
+    /// these locations, so its layout transitions and the end of the subpass use them too.
+    pub fn render_pass_begin_info<'a>(&'a self, depth_attachment: u32) -> RenderPassSampleLocations<'a> {
+        let attachments = vec![AttachmentSampleLocationsRaw {
+            attachment_index: depth_attachment,
+            sample_locations_info: self.info(),
+        }];
+        let subpasses = vec![SubpassSampleLocationsRaw {
+            subpass_index: 0,
+            sample_locations_info: self.info(),
+        }];
+        RenderPassSampleLocations {
+            s_type: STRUCTURE_TYPE_RENDER_PASS_SAMPLE_LOCATIONS_BEGIN_INFO,
+            p_next: ptr::null(),
+            attachment_initial_sample_locations_count: attachments.len() as u32,
+            p_attachment_initial_sample_locations: attachments.as_ptr(),
+            post_subpass_sample_locations_count: subpasses.len() as u32,
+            p_post_subpass_sample_locations: subpasses.as_ptr(),
+            _attachments: attachments,
+            _subpasses: subpasses,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordered_grid_covers_the_pixel_evenly() {
+        assert_eq!(SampleLocations::new(SamplePattern::Ordered, 4).positions(), vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]);
+        let eight = SampleLocations::new(SamplePattern::Ordered, 8).positions();
+        assert_eq!(eight[..4].iter().map(|&(x, _)| x).collect::<Vec<f32>>(), vec![0.125, 0.375, 0.625, 0.875]);
+        assert!(eight.iter().all(|&(_, y)| y == 0.25 || y == 0.75));
+        assert_eq!(SampleLocations::new(SamplePattern::Ordered, 2).positions(), vec![(0.25, 0.5), (0.75, 0.5)]);
+    }
+}
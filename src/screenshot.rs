@@ -0,0 +1,58 @@
+//! Screenshots on F12. The frame's command buffer copies the swapchain image it just drew into a
+//! `Readback` after everything else, including the log console; once the frame has finished on
+//! the GPU the texels are converted to RGBA8 and written to `screenshots/<unix time>.png`.
+//!
+//! The PNG gets the texels as they are, so an sRGB swapchain gives an sRGB PNG and a UNORM one
+//! gives whatever the gamma pass encoded. Only 32-bit formats are supported; the HDR `R16g16b16a16`
+//! swapchain would need tone mapping to be worth looking at.
+use glfw;
+use std;
+use std::fs::{ self, File };
+use std::io::{ self, Write };
+use std::path::PathBuf;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use vk::types::*;
+use ::image::ImageData;
+use ::png;
+
+pub const KEY: glfw::Key = glfw::Key::F12;
+pub const OUTPUT_DIR: &'static str = "screenshots";
+
+pub fn supports_format(format: Format) -> bool {
+    matches!(format, Format::R8g8b8a8Unorm | Format::R8g8b8a8Srgb | Format::B8g8r8a8Unorm | Format::B8g8r8a8Srgb | Format::A2b10g10r10UnormPack32 | Format::A2r10g10b10UnormPack32)
+}
+
+/// `texels` of a `format` image of `extent`, in row-major order, as RGBA8. `format` must be one
+/// `supports_format` accepts.
+pub fn to_image_data(format: Format, extent: &Extent2D, texels: &[u32]) -> ImageData {
+    // 10-bit channels keep their top 8 bits, and 2-bit alpha spreads over all 8
+    let unpack_10 = |texel: u32| ((texel >> 2) as u8, (texel >> 12) as u8, (texel >> 22) as u8, ((texel >> 30) * 0x55) as u8);
+    let mut pixels = Vec::with_capacity(texels.len() * 4);
+    for &texel in texels.iter() {
+        let (r, g, b, a) = match format {
+            Format::B8g8r8a8Unorm | Format::B8g8r8a8Srgb => ((texel >> 16) as u8, (texel >> 8) as u8, texel as u8, (texel >> 24) as u8),
+            Format::A2b10g10r10UnormPack32 => unpack_10(texel),
+            Format::A2r10g10b10UnormPack32 => {
+                let (b, g, r, a) = unpack_10(texel);
+                (r, g, b, a)
+            },
+            _ => (texel as u8, (texel >> 8) as u8, (texel >> 16) as u8, (texel >> 24) as u8),
+        };
+        pixels.extend_from_slice(&[r, g, b, a]);
+    }
+    ImageData {
+        width: extent.width,
+        height: extent.height,
+        pixels: pixels,
+    }
+}
+
+/// Writes `image` to a new file named after the current time in `OUTPUT_DIR`, returning its path.
+pub fn write(image: &ImageData) -> io::Result<PathBuf> {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(std::time::Duration::from_secs(0));
+    fs::create_dir_all(OUTPUT_DIR)?;
+    let path = PathBuf::from(OUTPUT_DIR).join(format!("{}.{:03}.png", since_epoch.as_secs(), since_epoch.subsec_millis()));
+    let mut file = File::create(&path)?;
+    file.write_all(&png::encode(image))?;
+    Ok(path)
+}
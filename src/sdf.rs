@@ -0,0 +1,59 @@
+//! Ray-marched signed distance field scene, drawn entirely by `shaders/sdf_fragment.glsl` over a
+//! full-screen triangle: a few smoothly blended primitives over a checkered ground, with soft
+//! shadows and ambient occlusion. All of the scene lives in the fragment shader, which makes it a
+//! heavy, self-contained target for iterating on shaders without touching any Rust.
+use ash::version::DeviceV1_0;
+use std;
+use vk::types::*;
+use ::math::{ Mat4, Vec3 };
+use ::pipeline::GraphicsPipelineBuilder;
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/sdf_fragment.frag.spv";
+
+/// Push constant block read by `shaders/sdf_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdfPushConstants {
+    /// Unprojects the full-screen triangle into world-space rays.
+    pub inverse_view_projection: Mat4,
+    /// Camera position, and the animation time in `w`.
+    pub eye: [f32; 4],
+    /// Direction towards the light, and the shadow sharpness in `w`. Around 8 gives soft
+    /// penumbrae, and higher values approach hard shadows.
+    pub light: [f32; 4],
+}
+
+impl SdfPushConstants {
+    /// `None` if `view_projection` can't be inverted.
+    pub fn new(view_projection: &Mat4, eye: Vec3, light_direction: Vec3, time: f32) -> Option<SdfPushConstants> {
+        let light_direction = light_direction.normalize();
+        view_projection.inverse().map(|inverse| SdfPushConstants {
+            inverse_view_projection: inverse,
+            eye: [eye.x, eye.y, eye.z, time],
+            light: [light_direction.x, light_direction.y, light_direction.z, 8.0],
+        })
+    }
+
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<SdfPushConstants>() as u32,
+        }
+    }
+
+    /// Records pushing the constants and the full-screen draw, with the SDF pipeline bound.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let range = SdfPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, self as *const SdfPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl`, ray marched by `fragment_shader`.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
@@ -0,0 +1,154 @@
+//! GLSL to SPIR-V compilation at load time, so editing a shader doesn't need a `make` before the
+//! next run. Shaders are still loaded by their SPIR-V path, `shaders/<name>.<stage>.spv` like
+//! the Makefile's outputs, and `load_spirv` recompiles it first from `shaders/<name>.glsl` if the
+//! source is newer. Compile errors are logged line by line under `logging::SHADER` and returned,
+//! rather than showing up later as a missing or stale file.
+//!
+//! Compiling goes through shaderc, linked in with the `shader-compile` feature. Without it, the
+//! existing SPIR-V is used as is, and a shader with no SPIR-V yet fails to load with
+//! `ShaderError::Io`. The renderer only needs the scene's shaders, which are checked in
+//! pre-compiled; passes built on any other shader are skipped with a warning when it's missing.
+//! Only the source file's own time is checked, so after editing an included header like
+//! `debug_printf.h` it still takes a `make`.
+use std;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+#[cfg(feature = "shader-compile")]
+use shaderc;
+use ::logging;
+
+/// Shaders that need SPIR-V 1.3 for subgroup operations, like in the Makefile.
+#[cfg(feature = "shader-compile")]
+const VULKAN_1_1_SHADERS: [&'static str; 1] = ["reduce_subgroup"];
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(io::Error),
+    /// shaderc's diagnostics.
+    #[cfg(feature = "shader-compile")]
+    Compile(String),
+}
+
+impl From<io::Error> for ShaderError {
+    fn from(e: io::Error) -> ShaderError {
+        ShaderError::Io(e)
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderError::Io(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "shader-compile")]
+            ShaderError::Compile(ref output) => write!(f, "compilation failed: {}", output.trim()),
+        }
+    }
+}
+
+/// The GLSL source and stage for a SPIR-V path like `shaders/upscale_fragment.frag.spv`.
+pub fn source_for(spirv_path: &Path) -> Option<(PathBuf, &'static str)> {
+    let file_name = match spirv_path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.ends_with(".spv") => &name[..name.len() - ".spv".len()],
+        _ => return None,
+    };
+    let dot = file_name.rfind('.')?;
+    let stage = match &file_name[dot + 1..] {
+        "vert" => "vert",
+        "frag" => "frag",
+        "geom" => "geom",
+        "comp" => "comp",
+        _ => return None,
+    };
+    Some((spirv_path.with_file_name(format!("{}.glsl", &file_name[..dot])), stage))
+}
+
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(feature = "shader-compile")]
+fn shader_kind(stage: &str) -> shaderc::ShaderKind {
+    match stage {
+        "vert" => shaderc::ShaderKind::Vertex,
+        "frag" => shaderc::ShaderKind::Fragment,
+        "geom" => shaderc::ShaderKind::Geometry,
+        _ => shaderc::ShaderKind::Compute,
+    }
+}
+
+/// `#include`s are looked up next to the file including them, as glslangValidator does.
+#[cfg(feature = "shader-compile")]
+fn resolve_include(name: &str, including: &str) -> shaderc::IncludeCallbackResult {
+    let path = Path::new(including).with_file_name(name);
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(shaderc::ResolvedInclude {
+            resolved_name: path.to_string_lossy().into_owned(),
+            content: content,
+        }),
+        Err(e) => Err(format!("{:?}: {}", path, e)),
+    }
+}
+
+/// Compiles `source` as `stage` into `output`, logging shaderc's warnings and errors.
+#[cfg(feature = "shader-compile")]
+pub fn compile(source: &Path, stage: &str, output: &Path) -> std::result::Result<(), ShaderError> {
+    let name = source.file_stem().and_then(|name| name.to_str()).unwrap_or("");
+    let text = fs::read_to_string(source)?;
+    let compiler = shaderc::Compiler::new()
+        .ok_or_else(|| ShaderError::Compile("failed to create a shaderc compiler".to_string()))?;
+    let mut options = shaderc::CompileOptions::new()
+        .ok_or_else(|| ShaderError::Compile("failed to create shaderc options".to_string()))?;
+    if VULKAN_1_1_SHADERS.contains(&name) {
+        options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_1 as u32);
+    }
+    options.set_include_callback(|name, _, including, _| resolve_include(name, including));
+
+    let file_name = source.to_string_lossy();
+    match compiler.compile_into_spirv(&text, shader_kind(stage), &file_name, "main", Some(&options)) {
+        Ok(artifact) => {
+            if artifact.get_num_warnings() > 0 {
+                warn!(target: logging::SHADER, "Compiled {:?} with warnings:", source);
+                for line in artifact.get_warning_messages().lines().filter(|line| !line.trim().is_empty()) {
+                    warn!(target: logging::SHADER, "  {}", line);
+                }
+            } else {
+                info!(target: logging::SHADER, "Compiled {:?}", source);
+            }
+            fs::write(output, artifact.as_binary_u8())?;
+            Ok(())
+        },
+        Err(e) => {
+            let messages = e.to_string();
+            error!(target: logging::SHADER, "Failed to compile {:?}:", source);
+            for line in messages.lines().filter(|line| !line.trim().is_empty()) {
+                error!(target: logging::SHADER, "  {}", line);
+            }
+            // A stale output would otherwise look up to date next time
+            let _ = fs::remove_file(output);
+            Err(ShaderError::Compile(messages))
+        },
+    }
+}
+
+/// Reads the SPIR-V at `path`, compiling it first if its GLSL source is newer or it doesn't
+/// exist yet and shaderc is built in.
+pub fn load_spirv<P: AsRef<Path>>(path: P) -> std::result::Result<Vec<u8>, ShaderError> {
+    let path = path.as_ref();
+    if let Some((source, stage)) = source_for(path) {
+        let source_time = modified(&source);
+        let stale = match (source_time, modified(path)) {
+            (Some(source_time), Some(spirv_time)) => source_time > spirv_time,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if stale {
+            #[cfg(feature = "shader-compile")]
+            compile(&source, stage, path)?;
+            #[cfg(not(feature = "shader-compile"))]
+            warn!(target: logging::SHADER, "{:?} ({} shader) is newer than {:?}, but rebuilding it needs the shader-compile feature", source, stage, path);
+        }
+    }
+    Ok(::read_full_file(&path.to_string_lossy())?)
+}
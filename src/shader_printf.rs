@@ -0,0 +1,111 @@
+//! `debugPrintfEXT` output from shaders, for instrumenting them without a readback. Passing
+//! `--shader-printf` creates the instance with the Khronos validation layer's debug printf
+//! feature (through `VK_EXT_validation_features`, which `ash` doesn't wrap, so the structure is
+//! declared here) and the device with `VK_KHR_shader_non_semantic_info`. The layer reports each
+//! printf as a debug utils message, which the messenger in `debug_utils` hands to `log_message` so
+//! it's logged under the `shader_printf` target instead of as a validation message.
+//!
+//! Shaders include `debug_printf.h` and use its `PRINTF*` macros, which only emit anything when
+//! the shaders are built with `make PRINTF=1`.
+use ash::version::{ EntryV1_0, InstanceV1_0 };
+use std::ffi::{ CStr, CString };
+use std::ptr;
+use vk::types::*;
+use ::cvar::Flag;
+use ::pnext::ExtensionStruct;
+
+pub const ARG: &'static str = "--shader-printf";
+pub const CVAR: &'static str = "r.shader_printf";
+pub const FLAGS: &'static [Flag] = &[Flag::Switch { arg: ARG, name: CVAR }];
+
+/// Debug printf is implemented by the Khronos layer, not by the older LunarG meta layer.
+pub const VALIDATION_LAYER: &'static str = "VK_LAYER_KHRONOS_validation";
+pub const VALIDATION_FEATURES_EXTENSION: &'static str = "VK_EXT_validation_features";
+pub const NON_SEMANTIC_INFO_EXTENSION: &'static str = "VK_KHR_shader_non_semantic_info";
+
+const STRUCTURE_TYPE_VALIDATION_FEATURES_EXT: u32 = 1000247000;
+const VALIDATION_FEATURE_ENABLE_DEBUG_PRINTF_EXT: u32 = 3;
+
+static ENABLED_FEATURES: [u32; 1] = [VALIDATION_FEATURE_ENABLE_DEBUG_PRINTF_EXT];
+
+/// The layer tags every printf message with this message ID.
+const MESSAGE_ID: &'static str = "DEBUG-PRINTF";
+
+/// `VkValidationFeaturesEXT`, chained to `InstanceCreateInfo`.
+#[repr(C)]
+pub struct ValidationFeaturesRaw {
+    s_type: u32,
+    p_next: *const c_void,
+    enabled_validation_feature_count: u32,
+    p_enabled_validation_features: *const u32,
+    disabled_validation_feature_count: u32,
+    p_disabled_validation_features: *const u32,
+}
+
+unsafe impl ExtensionStruct for ValidationFeaturesRaw {}
+
+impl ValidationFeaturesRaw {
+    /// Enables debug printf. Must outlive the `create_instance` call it's chained to.
+    pub fn debug_printf() -> ValidationFeaturesRaw {
+        ValidationFeaturesRaw {
+            s_type: STRUCTURE_TYPE_VALIDATION_FEATURES_EXT,
+            p_next: ptr::null(),
+            enabled_validation_feature_count: ENABLED_FEATURES.len() as u32,
+            p_enabled_validation_features: ENABLED_FEATURES.as_ptr(),
+            disabled_validation_feature_count: 0,
+            p_disabled_validation_features: ptr::null(),
+        }
+    }
+}
+
+/// Whether `r.shader_printf`, which `ARG` sets, is on.
+pub fn requested() -> bool {
+    cvar!(CVAR, false).get()
+}
+
+/// Whether `VALIDATION_LAYER` is installed and provides `VALIDATION_FEATURES_EXTENSION`.
+pub fn instance_supported<E: EntryV1_0>(entry: &E) -> bool {
+    let layer = CString::new(VALIDATION_LAYER).unwrap();
+    let layer_installed = entry.enumerate_instance_layer_properties()
+        .map(|layers| layers.iter().any(|properties| unsafe { CStr::from_ptr(properties.layer_name.as_ptr()) } == layer.as_ref()))
+        .unwrap_or(false);
+    if !layer_installed {
+        return false;
+    }
+    // Layer extensions are only listed when asking the layer itself, which ash doesn't wrap
+    let extensions = unsafe {
+        let mut count = 0;
+        entry.fp_v1_0().enumerate_instance_extension_properties(layer.as_ptr(), &mut count, ptr::null_mut());
+        let mut extensions = Vec::with_capacity(count as usize);
+        match entry.fp_v1_0().enumerate_instance_extension_properties(layer.as_ptr(), &mut count, extensions.as_mut_ptr()) {
+            Result::Success => {
+                extensions.set_len(count as usize);
+                extensions
+            },
+            _ => return false,
+        }
+    };
+    has_extension(&extensions, VALIDATION_FEATURES_EXTENSION)
+}
+
+/// Whether `physical_device` supports `NON_SEMANTIC_INFO_EXTENSION`.
+pub fn device_supported<I: InstanceV1_0>(instance: &I, physical_device: PhysicalDevice) -> bool {
+    instance.enumerate_device_extension_properties(physical_device)
+        .map(|extensions| has_extension(&extensions, NON_SEMANTIC_INFO_EXTENSION))
+        .unwrap_or(false)
+}
+
+fn has_extension(extensions: &[ExtensionProperties], name: &str) -> bool {
+    extensions.iter().any(|properties| unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) }.to_bytes() == name.as_bytes())
+}
+
+/// Whether a debug message is printf output rather than a validation message.
+pub fn is_printf_message(message_id_name: &str, message: &str) -> bool {
+    [message_id_name, message].iter().any(|s| s.contains(MESSAGE_ID))
+}
+
+/// Logs printf output without the layer's preamble, which ends with the message ID's hash.
+pub fn log_message(message: &str) {
+    let text = message.rsplit("| ").next().unwrap_or(message).trim();
+    info!(target: "shader_printf", "{}", text);
+}
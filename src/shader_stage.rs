@@ -0,0 +1,123 @@
+//! A shader stage as one value: the module, which stage it's for, its entry point and any
+//! specialization constants. `PipelineShaderStageCreateInfo` points into all of those, so it's
+//! only ever built inside `with_create_info`, while they're still alive.
+use std;
+use std::ffi::CString;
+use std::ptr;
+use vk::types::*;
+
+pub const DEFAULT_ENTRY_POINT: &'static str = "main";
+
+/// Values for a stage's `constant_id`s, packed the way `SpecializationInfo` wants them.
+#[derive(Debug, Clone, Default)]
+pub struct Specialization {
+    entries: Vec<SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl Specialization {
+    pub fn new() -> Specialization {
+        Default::default()
+    }
+
+    /// Sets constant `constant_id` to `value`, which has to match the constant's type in the
+    /// shader (`u32` for `bool`s).
+    pub fn constant<T: Copy>(mut self, constant_id: u32, value: T) -> Specialization {
+        let size = std::mem::size_of::<T>();
+        let bytes = unsafe { std::slice::from_raw_parts(&value as *const T as *const u8, size) };
+        self.entries.push(SpecializationMapEntry {
+            constant_id: constant_id,
+            offset: self.data.len() as u32,
+            size: size,
+        });
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    fn with_info<R, F: FnOnce(&SpecializationInfo) -> R>(&self, f: F) -> R {
+        let info = SpecializationInfo {
+            map_entry_count: self.entries.len() as u32,
+            p_map_entries: self.entries.as_ptr(),
+            data_size: self.data.len(),
+            p_data: self.data.as_ptr() as *const c_void,
+        };
+        f(&info)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderStage {
+    stage: ShaderStageFlags,
+    module: ShaderModule,
+    entry_point: CString,
+    specialization: Option<Specialization>,
+}
+
+impl ShaderStage {
+    /// `module` as `stage`, entered at `DEFAULT_ENTRY_POINT`.
+    pub fn new(stage: ShaderStageFlags, module: ShaderModule) -> ShaderStage {
+        ShaderStage {
+            stage: stage,
+            module: module,
+            entry_point: CString::new(DEFAULT_ENTRY_POINT).unwrap(),
+            specialization: None,
+        }
+    }
+
+    pub fn geometry(module: ShaderModule) -> ShaderStage {
+        ShaderStage::new(SHADER_STAGE_GEOMETRY_BIT, module)
+    }
+
+    pub fn compute(module: ShaderModule) -> ShaderStage {
+        ShaderStage::new(SHADER_STAGE_COMPUTE_BIT, module)
+    }
+
+    pub fn specialize(mut self, specialization: Specialization) -> ShaderStage {
+        self.specialization = Some(specialization);
+        self
+    }
+
+    pub fn stage(&self) -> ShaderStageFlags {
+        self.stage
+    }
+
+    pub fn set_module(&mut self, module: ShaderModule) {
+        self.module = module;
+    }
+
+    /// Hands `f` the create info, which points into `self`. The pointers must not escape `f`.
+    pub fn with_create_info<R, F: FnOnce(PipelineShaderStageCreateInfo) -> R>(&self, f: F) -> R {
+        let create_info = |p_specialization_info: *const SpecializationInfo| PipelineShaderStageCreateInfo {
+            s_type: StructureType::PipelineShaderStageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            stage: self.stage,
+            module: self.module,
+            p_name: self.entry_point.as_ptr(),
+            p_specialization_info: p_specialization_info,
+        };
+        match self.specialization {
+            Some(ref specialization) => specialization.with_info(|info| f(create_info(info))),
+            None => f(create_info(ptr::null())),
+        }
+    }
+}
+
+/// Hands `f` the create infos of all of `stages`, in order. The pointers must not escape `f`.
+pub fn with_create_infos<R, F: FnOnce(&[PipelineShaderStageCreateInfo]) -> R>(stages: &[ShaderStage], f: F) -> R {
+    // Every stage's specialization info has to stay put until `f` is done, so they're built
+    // first and the create infos point into this
+    let specialization_infos: Vec<Option<SpecializationInfo>> = stages.iter()
+        .map(|stage| stage.specialization.as_ref().map(|specialization| specialization.with_info(|info| info.clone())))
+        .collect();
+    let create_infos: Vec<PipelineShaderStageCreateInfo> = stages.iter().zip(specialization_infos.iter()).map(|(stage, info)| PipelineShaderStageCreateInfo {
+        s_type: StructureType::PipelineShaderStageCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        stage: stage.stage,
+        module: stage.module,
+        p_name: stage.entry_point.as_ptr(),
+        p_specialization_info: info.as_ref().map(|info| info as *const SpecializationInfo).unwrap_or(ptr::null()),
+    }).collect();
+    f(&create_infos)
+}
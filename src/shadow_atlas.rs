@@ -0,0 +1,477 @@
+//! Packs the shadow maps of many lights into a single large depth texture. Each shadow map gets a
+//! square, power-of-two region of the atlas that is rendered to by setting the viewport and
+//! scissor to that region, so lights don't each need their own image and render pass.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::command::RenderTarget;
+use ::descriptor::DescriptorWrite;
+use ::math::prelude::next_pow2;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::rect::{ self, ExtentExt, RectExt };
+use ::safe_create::{ self, FramebufferCreateInfoSafe, Owned, OwnedFramebuffer };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const VIEW_FRAGMENT_SHADER: &'static str = "shaders/shadow_atlas_view_fragment.frag.spv";
+
+/// Binding of the atlas in `shaders/shadow_atlas_view_fragment.glsl`'s descriptor set.
+pub const VIEW_BINDING: u32 = 0;
+
+/// A square region of the shadow atlas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShadowRegion {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+}
+
+impl ShadowRegion {
+    pub fn viewport(&self) -> Viewport {
+        self.scissor().to_viewport()
+    }
+
+    pub fn scissor(&self) -> Rect2D {
+        Rect2D::from_tuple((self.x as i32, self.y as i32, self.size, self.size))
+    }
+
+    /// Scale and offset (`[scale, scale, offset_x, offset_y]`) that maps a light's `[0, 1]`
+    /// shadow UVs into this region of an atlas of `atlas_size` texels.
+    pub fn uv_transform(&self, atlas_size: u32) -> [f32; 4] {
+        let atlas_size = atlas_size as f32;
+        let scale = self.size as f32 / atlas_size;
+        [scale, scale, self.x as f32 / atlas_size, self.y as f32 / atlas_size]
+    }
+
+    /// Sets the dynamic viewport and scissor to this region.
+    pub unsafe fn cmd_set<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer) {
+        device.cmd_set_viewport(command_buffer, &[self.viewport()]);
+        device.cmd_set_scissor(command_buffer, &[self.scissor()]);
+    }
+}
+
+/// Shadow regions for the six faces of a point light, in the usual
+/// `+X, -X, +Y, -Y, +Z, -Z` cube face order.
+pub type CubeShadowRegions = [ShadowRegion; 6];
+
+/// Two-dimensional buddy allocator. Level `0` is the whole atlas, and each level down splits a
+/// tile into four quadrants of half the size. Freed tiles are merged with their buddies again
+/// when all four quadrants are free, so shadow maps can be pooled and reused between frames.
+pub struct ShadowAtlasAllocator {
+    size: u32,
+    min_tile_size: u32,
+    free_tiles: Vec<Vec<(u32, u32)>>,
+}
+
+impl ShadowAtlasAllocator {
+    pub fn new(size: u32, min_tile_size: u32) -> ShadowAtlasAllocator {
+        assert!(size.is_power_of_two() && min_tile_size.is_power_of_two());
+        assert!(min_tile_size <= size);
+        let level_count = (size / min_tile_size).trailing_zeros() as usize + 1;
+        let mut free_tiles = vec![Vec::new(); level_count];
+        free_tiles[0].push((0, 0));
+        ShadowAtlasAllocator {
+            size: size,
+            min_tile_size: min_tile_size,
+            free_tiles: free_tiles,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn tile_size(&self, level: usize) -> u32 {
+        self.size >> level
+    }
+
+    fn level_for_size(&self, size: u32) -> Option<usize> {
+        if size > self.size {
+            return None;
+        }
+        next_pow2(std::cmp::max(size, self.min_tile_size)).map(|size| (self.size / size).trailing_zeros() as usize)
+    }
+
+    fn allocate_level(&mut self, level: usize) -> Option<(u32, u32)> {
+        if let Some(tile) = self.free_tiles[level].pop() {
+            return Some(tile);
+        }
+        if level == 0 {
+            return None;
+        }
+        let tile_size = self.tile_size(level);
+        self.allocate_level(level - 1).map(|(x, y)| {
+            self.free_tiles[level].push((x + tile_size, y + tile_size));
+            self.free_tiles[level].push((x, y + tile_size));
+            self.free_tiles[level].push((x + tile_size, y));
+            (x, y)
+        })
+    }
+
+    /// Allocates a region at least `size` texels square, or `None` if the atlas is full.
+    pub fn allocate(&mut self, size: u32) -> Option<ShadowRegion> {
+        let level = self.level_for_size(size)?;
+        let tile_size = self.tile_size(level);
+        self.allocate_level(level).map(|(x, y)| ShadowRegion {
+            x: x,
+            y: y,
+            size: tile_size,
+        })
+    }
+
+    /// Allocates all six faces of a cube shadow map. Either every face is allocated or none are.
+    pub fn allocate_cube(&mut self, size: u32) -> Option<CubeShadowRegions> {
+        let mut faces: Vec<ShadowRegion> = Vec::with_capacity(6);
+        for _ in 0..6 {
+            match self.allocate(size) {
+                Some(region) => faces.push(region),
+                None => {
+                    for face in faces {
+                        self.free(face);
+                    }
+                    return None;
+                },
+            }
+        }
+        Some([faces[0], faces[1], faces[2], faces[3], faces[4], faces[5]])
+    }
+
+    pub fn free(&mut self, region: ShadowRegion) {
+        debug_assert!(rect::extent(self.size, self.size).to_rect().contains(region.x as i32, region.y as i32), "Freed shadow region {:?} is outside the atlas", region);
+        let level = self.level_for_size(region.size).expect("Freed shadow region is larger than the atlas");
+        assert_eq!(self.tile_size(level), region.size);
+        self.free_level(level, (region.x, region.y));
+    }
+
+    pub fn free_cube(&mut self, faces: CubeShadowRegions) {
+        for &face in faces.iter() {
+            self.free(face);
+        }
+    }
+
+    fn free_level(&mut self, level: usize, (x, y): (u32, u32)) {
+        if level == 0 {
+            self.free_tiles[0].push((x, y));
+            return;
+        }
+        let tile_size = self.tile_size(level);
+        let parent = (x & !(tile_size * 2 - 1), y & !(tile_size * 2 - 1));
+        let buddies = [
+            (parent.0, parent.1),
+            (parent.0 + tile_size, parent.1),
+            (parent.0, parent.1 + tile_size),
+            (parent.0 + tile_size, parent.1 + tile_size),
+        ];
+        let all_buddies_free = buddies.iter()
+            .filter(|&&tile| tile != (x, y))
+            .all(|tile| self.free_tiles[level].contains(tile));
+        if all_buddies_free {
+            self.free_tiles[level].retain(|tile| !buddies.contains(tile));
+            self.free_level(level - 1, parent);
+        } else {
+            self.free_tiles[level].push((x, y));
+        }
+    }
+}
+
+/// The atlas depth texture itself, along with the allocator for its regions and a render pass
+/// that clears the whole atlas and leaves it ready to sample.
+pub struct ShadowAtlas<'d, D: DeviceV1_0 + 'd> {
+    // In the order they're destroyed
+    framebuffer: OwnedFramebuffer<'d, D>,
+    render_pass: Owned<'d, D, RenderPass>,
+    sampler: Owned<'d, D, Sampler>,
+    view: Owned<'d, D, ImageView>,
+    _image: Owned<'d, D, Image>,
+    _memory: VkAllocation<'d, D>,
+    allocator: ShadowAtlasAllocator,
+}
+
+impl<'d, D: DeviceV1_0> ShadowAtlas<'d, D> {
+    /// `format` must be a depth format that can be rendered to and sampled, see
+    /// `format_support::depth_format`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, size: u32, min_tile_size: u32) -> VkResult<ShadowAtlas<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: format,
+            extent: Extent3D {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT | IMAGE_USAGE_SAMPLED_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = safe_create::create_image_safe(device, &image_create_info, None)?;
+        let memory = allocator.allocate_image_memory(*image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: *image,
+            view_type: ImageViewType::Type2d,
+            format: format,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: ImageSubresourceRange {
+                aspect_mask: IMAGE_ASPECT_DEPTH_BIT,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+        };
+        let view = safe_create::create_image_view_safe(device, &view_create_info, None)?;
+
+        let attachment = AttachmentDescription {
+            flags: Default::default(),
+            format: format,
+            samples: SAMPLE_COUNT_1_BIT,
+            load_op: AttachmentLoadOp::Clear,
+            store_op: AttachmentStoreOp::Store,
+            stencil_load_op: AttachmentLoadOp::DontCare,
+            stencil_store_op: AttachmentStoreOp::DontCare,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: ImageLayout::ShaderReadOnlyOptimal,
+        };
+        let depth_attachment_ref = AttachmentReference {
+            attachment: 0,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+        };
+        let subpass = SubpassDescription {
+            flags: Default::default(),
+            pipeline_bind_point: PipelineBindPoint::Graphics,
+            input_attachment_count: 0,
+            p_input_attachments: ptr::null(),
+            color_attachment_count: 0,
+            p_color_attachments: ptr::null(),
+            p_resolve_attachments: ptr::null(),
+            p_depth_stencil_attachment: &depth_attachment_ref,
+            preserve_attachment_count: 0,
+            p_preserve_attachments: ptr::null(),
+        };
+        let dependencies = [
+            // Last frame's lighting sampled the atlas
+            SubpassDependency {
+                src_subpass: VK_SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+                src_access_mask: ACCESS_SHADER_READ_BIT,
+                dst_stage_mask: PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT | PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+                dst_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT | ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dependency_flags: Default::default(),
+            },
+            SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: VK_SUBPASS_EXTERNAL,
+                src_stage_mask: PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+                src_access_mask: ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dst_stage_mask: PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+                dst_access_mask: ACCESS_SHADER_READ_BIT,
+                dependency_flags: Default::default(),
+            },
+        ];
+        let render_pass_create_info = RenderPassCreateInfo {
+            s_type: StructureType::RenderPassCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            attachment_count: 1,
+            p_attachments: &attachment,
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+        };
+        let render_pass = safe_create::create_render_pass_safe(device, &render_pass_create_info, None)?;
+
+        let framebuffer_create_info = FramebufferCreateInfo {
+            s_type: StructureType::FramebufferCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            render_pass: RenderPass::null(),
+            attachment_count: 0,
+            p_attachments: ptr::null(),
+            width: size,
+            height: size,
+            layers: 1,
+        };
+        let framebuffer_create_info = unsafe { FramebufferCreateInfoSafe::from_handles(framebuffer_create_info, *render_pass, &[*view]) };
+        let framebuffer = safe_create::create_framebuffer_safe(device, framebuffer_create_info, None)?;
+
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatOpaqueWhite,
+            unnormalized_coordinates: 0,
+        };
+        let sampler = safe_create::create_sampler_safe(device, &sampler_create_info, None)?;
+        debug!("Created {}x{} {:?} shadow atlas: {:?}", size, size, format, *image);
+        Ok(ShadowAtlas {
+            framebuffer: framebuffer,
+            render_pass: render_pass,
+            sampler: sampler,
+            view: view,
+            _image: image,
+            _memory: memory,
+            allocator: ShadowAtlasAllocator::new(size, min_tile_size),
+        })
+    }
+
+    pub fn allocator(&self) -> &ShadowAtlasAllocator {
+        &self.allocator
+    }
+
+    pub fn allocator_mut(&mut self) -> &mut ShadowAtlasAllocator {
+        &mut self.allocator
+    }
+
+    pub fn render_pass(&self) -> RenderPass {
+        *self.render_pass
+    }
+
+    /// The whole atlas. Begin its render pass with `clear_values`, then draw each region after
+    /// `ShadowRegion::cmd_set`.
+    pub fn target<'a>(&'a self) -> RenderTarget<'a> {
+        RenderTarget {
+            render_pass: &self.render_pass,
+            framebuffer: &self.framebuffer,
+            extent: rect::extent(self.allocator.size(), self.allocator.size()),
+        }
+    }
+
+    /// Clears to the far plane, so texels no light rendered into are never in shadow.
+    pub fn clear_values() -> [ClearValue; 1] {
+        [ClearValue::new_depth_stencil(ClearDepthStencilValue {
+            depth: 1.0,
+            stencil: 0,
+        })]
+    }
+
+    /// The atlas's depth after its render pass, for `binding`.
+    pub fn sampled_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: binding,
+            sampler: *self.sampler,
+            image_view: *self.view,
+            image_layout: ImageLayout::ShaderReadOnlyOptimal,
+        }
+    }
+}
+
+/// A builder for drawing depth into an atlas region with `vertex_shader` alone, since there's no
+/// color to shade. The viewport and scissor are dynamic, set per region with
+/// `ShadowRegion::cmd_set`. Add the vertex input to match the shader.
+pub fn depth_pipeline_builder(vertex_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(&rect::placeholder_extent())
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .depth_test(true, CompareOp::Less)
+        .blend_attachments(&[])
+        .dynamic_viewport_scissor()
+}
+
+/// A builder for showing the atlas with `shaders/fullscreen_vertex.glsl` and
+/// `VIEW_FRAGMENT_SHADER`, as a square of `size` in the top left corner.
+pub fn view_pipeline_builder(size: u32, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(&rect::extent(size, size))
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &ShadowRegion, b: &ShadowRegion) -> bool {
+        a.x < b.x + b.size && b.x < a.x + a.size && a.y < b.y + b.size && b.y < a.y + a.size
+    }
+
+    #[test]
+    fn sizes_round_up_to_a_power_of_two_tile() {
+        let mut allocator = ShadowAtlasAllocator::new(1024, 128);
+        assert_eq!(allocator.allocate(300).unwrap().size, 512);
+        assert_eq!(allocator.allocate(100).unwrap().size, 128);
+        assert_eq!(allocator.allocate(1).unwrap().size, 128);
+        assert_eq!(allocator.allocate(2048), None);
+    }
+
+    #[test]
+    fn regions_fill_the_atlas_without_overlapping() {
+        let mut allocator = ShadowAtlasAllocator::new(256, 64);
+        let mut regions: Vec<ShadowRegion> = Vec::new();
+        while let Some(region) = allocator.allocate(64) {
+            assert!(region.x + region.size <= 256 && region.y + region.size <= 256);
+            assert!(regions.iter().all(|other| !overlaps(&region, other)), "{:?} overlaps {:?}", region, regions);
+            regions.push(region);
+        }
+        assert_eq!(regions.len(), 16);
+        assert_eq!(allocator.allocate(1), None);
+    }
+
+    #[test]
+    fn freed_buddies_merge_back_into_the_whole_atlas() {
+        let mut allocator = ShadowAtlasAllocator::new(256, 64);
+        let regions: Vec<ShadowRegion> = (0..16).map(|_| allocator.allocate(64).unwrap()).collect();
+        assert_eq!(allocator.allocate(256), None);
+        for &region in regions.iter().rev() {
+            allocator.free(region);
+        }
+        assert_eq!(allocator.allocate(256), Some(ShadowRegion { x: 0, y: 0, size: 256 }));
+    }
+
+    #[test]
+    fn cubes_are_allocated_whole_or_not_at_all() {
+        let mut allocator = ShadowAtlasAllocator::new(256, 64);
+        let singles: Vec<ShadowRegion> = (0..12).map(|_| allocator.allocate(64).unwrap()).collect();
+        assert_eq!(allocator.allocate_cube(64), None);
+        // The faces allocated before running out were given back
+        let rest: Vec<ShadowRegion> = (0..4).map(|_| allocator.allocate(64).unwrap()).collect();
+        assert_eq!(allocator.allocate(64), None);
+
+        for region in singles.into_iter().chain(rest) {
+            allocator.free(region);
+        }
+        let faces = allocator.allocate_cube(64).unwrap();
+        for (i, a) in faces.iter().enumerate() {
+            assert!(faces[i + 1..].iter().all(|b| !overlaps(a, b)));
+        }
+        allocator.free_cube(faces);
+        assert!(allocator.allocate(256).is_some());
+    }
+
+    #[test]
+    fn uv_transform_maps_into_the_region() {
+        let region = ShadowRegion { x: 512, y: 256, size: 256 };
+        assert_eq!(region.uv_transform(1024), [0.25, 0.25, 0.5, 0.25]);
+    }
+}
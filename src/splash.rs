@@ -0,0 +1,227 @@
+//! A loading screen that needs nothing from disk: a progress bar drawn by a pipeline whose
+//! shaders are embedded below, for the renderer to show while the scene's pipelines are still
+//! compiling. The bar is two quads, the track and the filled part, each drawn from push constants
+//! alone, so the pass has no descriptors, vertex buffers or images to wait on either.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::math::prelude::clamp;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::rect::{ self, ExtentExt };
+use ::safe_create;
+
+pub const BACKGROUND: [f32; 4] = [0.02, 0.02, 0.03, 1.0];
+const TRACK_COLOR: [f32; 4] = [0.15, 0.15, 0.18, 1.0];
+const BAR_COLOR: [f32; 4] = [0.85, 0.55, 0.15, 1.0];
+/// In normalized device coordinates, centered on the screen.
+const BAR_HALF_WIDTH: f32 = 0.6;
+const BAR_HALF_HEIGHT: f32 = 0.015;
+
+/// Hand-assembled SPIR-V equivalent to:
+///
+/// ```glsl
+/// layout(push_constant) uniform Quad {
+///     vec4 bounds;
+///     vec4 color;
+/// } quad;
+///
+/// void main() {
+///     float x = (gl_VertexIndex & 1) != 0 ? quad.bounds.z : quad.bounds.x;
+///     float y = (gl_VertexIndex & 2) != 0 ? quad.bounds.w : quad.bounds.y;
+///     gl_Position = vec4(x, y, 0.0, 1.0);
+/// }
+/// ```
+///
+/// drawn as a 4 vertex triangle strip.
+const VERTEX_SPIRV: [u32; 208] = [
+    0x07230203, 0x00010000, 0x00000000, 0x00000027, 0x00000000, 0x00020011, 0x00000001, 0x0003000e,
+    0x00000000, 0x00000001, 0x0007000f, 0x00000000, 0x00000016, 0x6e69616d, 0x00000000, 0x0000000e,
+    0x00000010, 0x00040047, 0x00000010, 0x0000000b, 0x0000002a, 0x00050048, 0x0000000c, 0x00000000,
+    0x0000000b, 0x00000000, 0x00030047, 0x0000000c, 0x00000002, 0x00050048, 0x00000011, 0x00000000,
+    0x00000023, 0x00000000, 0x00050048, 0x00000011, 0x00000001, 0x00000023, 0x00000010, 0x00030047,
+    0x00000011, 0x00000002, 0x00020013, 0x00000001, 0x00030021, 0x00000002, 0x00000001, 0x00030016,
+    0x00000003, 0x00000020, 0x00040017, 0x00000004, 0x00000003, 0x00000004, 0x00040015, 0x00000005,
+    0x00000020, 0x00000001, 0x00020014, 0x00000006, 0x0004002b, 0x00000005, 0x00000007, 0x00000000,
+    0x0004002b, 0x00000005, 0x00000008, 0x00000001, 0x0004002b, 0x00000005, 0x00000009, 0x00000002,
+    0x0004002b, 0x00000003, 0x0000000a, 0x00000000, 0x0004002b, 0x00000003, 0x0000000b, 0x3f800000,
+    0x0003001e, 0x0000000c, 0x00000004, 0x00040020, 0x0000000d, 0x00000003, 0x0000000c, 0x0004003b,
+    0x0000000d, 0x0000000e, 0x00000003, 0x00040020, 0x0000000f, 0x00000001, 0x00000005, 0x0004003b,
+    0x0000000f, 0x00000010, 0x00000001, 0x0004001e, 0x00000011, 0x00000004, 0x00000004, 0x00040020,
+    0x00000012, 0x00000009, 0x00000011, 0x0004003b, 0x00000012, 0x00000013, 0x00000009, 0x00040020,
+    0x00000014, 0x00000009, 0x00000004, 0x00040020, 0x00000015, 0x00000003, 0x00000004, 0x00050036,
+    0x00000001, 0x00000016, 0x00000000, 0x00000002, 0x000200f8, 0x00000017, 0x0004003d, 0x00000005,
+    0x00000018, 0x00000010, 0x00050041, 0x00000014, 0x00000019, 0x00000013, 0x00000007, 0x0004003d,
+    0x00000004, 0x0000001a, 0x00000019, 0x00050051, 0x00000003, 0x0000001b, 0x0000001a, 0x00000000,
+    0x00050051, 0x00000003, 0x0000001c, 0x0000001a, 0x00000001, 0x00050051, 0x00000003, 0x0000001d,
+    0x0000001a, 0x00000002, 0x00050051, 0x00000003, 0x0000001e, 0x0000001a, 0x00000003, 0x000500c7,
+    0x00000005, 0x0000001f, 0x00000018, 0x00000008, 0x000500ab, 0x00000006, 0x00000020, 0x0000001f,
+    0x00000007, 0x000600a9, 0x00000003, 0x00000021, 0x00000020, 0x0000001d, 0x0000001b, 0x000500c7,
+    0x00000005, 0x00000022, 0x00000018, 0x00000009, 0x000500ab, 0x00000006, 0x00000023, 0x00000022,
+    0x00000007, 0x000600a9, 0x00000003, 0x00000024, 0x00000023, 0x0000001e, 0x0000001c, 0x00070050,
+    0x00000004, 0x00000025, 0x00000021, 0x00000024, 0x0000000a, 0x0000000b, 0x00050041, 0x00000015,
+    0x00000026, 0x0000000e, 0x00000007, 0x0003003e, 0x00000026, 0x00000025, 0x000100fd, 0x00010038,
+];
+
+/// Hand-assembled SPIR-V equivalent to:
+///
+/// ```glsl
+/// layout(push_constant) uniform Quad {
+///     vec4 bounds;
+///     vec4 color;
+/// } quad;
+///
+/// layout(location = 0) out vec4 outColor;
+///
+/// void main() {
+///     outColor = quad.color;
+/// }
+/// ```
+const FRAGMENT_SPIRV: [u32; 101] = [
+    0x07230203, 0x00010000, 0x00000000, 0x00000011, 0x00000000, 0x00020011, 0x00000001, 0x0003000e,
+    0x00000000, 0x00000001, 0x0006000f, 0x00000004, 0x0000000d, 0x6e69616d, 0x00000000, 0x0000000c,
+    0x00030010, 0x0000000d, 0x00000007, 0x00040047, 0x0000000c, 0x0000001e, 0x00000000, 0x00050048,
+    0x00000005, 0x00000000, 0x00000023, 0x00000000, 0x00050048, 0x00000005, 0x00000001, 0x00000023,
+    0x00000010, 0x00030047, 0x00000005, 0x00000002, 0x00020013, 0x00000001, 0x00030021, 0x00000002,
+    0x00000001, 0x00030016, 0x00000003, 0x00000020, 0x00040017, 0x00000004, 0x00000003, 0x00000004,
+    0x0004001e, 0x00000005, 0x00000004, 0x00000004, 0x00040020, 0x00000006, 0x00000009, 0x00000005,
+    0x0004003b, 0x00000006, 0x00000007, 0x00000009, 0x00040015, 0x00000008, 0x00000020, 0x00000001,
+    0x0004002b, 0x00000008, 0x00000009, 0x00000001, 0x00040020, 0x0000000a, 0x00000009, 0x00000004,
+    0x00040020, 0x0000000b, 0x00000003, 0x00000004, 0x0004003b, 0x0000000b, 0x0000000c, 0x00000003,
+    0x00050036, 0x00000001, 0x0000000d, 0x00000000, 0x00000002, 0x000200f8, 0x0000000e, 0x00050041,
+    0x0000000a, 0x0000000f, 0x00000007, 0x00000009, 0x0004003d, 0x00000004, 0x00000010, 0x0000000f,
+    0x0003003e, 0x0000000c, 0x00000010, 0x000100fd, 0x00010038,
+];
+
+/// Push constant block read by both embedded shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct QuadPushConstants {
+    /// Left, top, right and bottom, in normalized device coordinates.
+    bounds: [f32; 4],
+    color: [f32; 4],
+}
+
+impl QuadPushConstants {
+    fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_VERTEX_BIT | SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<QuadPushConstants>() as u32,
+        }
+    }
+}
+
+/// How much of what the renderer is waiting on has finished.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadingProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl LoadingProgress {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+fn create_embedded_shader_module<'d, D: DeviceV1_0>(device: &'d D, code: &[u32]) -> VkResult<safe_create::Owned<'d, D, ShaderModule>> {
+    let create_info = ShaderModuleCreateInfo {
+        s_type: StructureType::ShaderModuleCreateInfo,
+        p_next: ptr::null(),
+        flags: Default::default(),
+        code_size: std::mem::size_of_val(code),
+        p_code: code.as_ptr(),
+    };
+    safe_create::create_shader_module_safe(device, &create_info, None)
+}
+
+pub struct SplashPass<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    layout: PipelineLayout,
+    pipeline: Pipeline,
+}
+
+impl<'d, D: DeviceV1_0> SplashPass<'d, D> {
+    /// Draws into subpass 0 of `render_pass`. The viewport is dynamic, so the pass doesn't have
+    /// to be recreated with the swapchain.
+    pub fn new(device: &'d D, render_pass: RenderPass) -> VkResult<SplashPass<'d, D>> {
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = SplashPass {
+            device: device,
+            layout: PipelineLayout::null(),
+            pipeline: Pipeline::null(),
+        };
+        let push_constant_range = QuadPushConstants::range();
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 0,
+            p_set_layouts: ptr::null(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+        let vertex_shader = create_embedded_shader_module(device, &VERTEX_SPIRV)?;
+        let fragment_shader = create_embedded_shader_module(device, &FRAGMENT_SPIRV)?;
+        // The extent only matters for the static viewport, which the dynamic one replaces
+        let extent = rect::placeholder_extent();
+        ret.pipeline = unsafe {
+            GraphicsPipelineBuilder::new(&extent)
+                .stage(SHADER_STAGE_VERTEX_BIT, *vertex_shader)
+                .stage(SHADER_STAGE_FRAGMENT_BIT, *fragment_shader)
+                .topology(PrimitiveTopology::TriangleStrip)
+                .cull_mode(CULL_MODE_NONE)
+                .dynamic_viewport_scissor()
+                .build_raw(device, PipelineCache::null(), ret.layout, render_pass, 0)
+        }?;
+        Ok(ret)
+    }
+
+    /// Records the progress bar inside the render pass, over the whole of `extent`.
+    pub unsafe fn cmd_draw(&self, command_buffer: CommandBuffer, extent: &Extent2D, progress: &LoadingProgress) {
+        let viewport = extent.to_viewport();
+        let scissor = extent.to_rect();
+        self.device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::Graphics, self.pipeline);
+        self.device.cmd_set_viewport(command_buffer, &[viewport]);
+        self.device.cmd_set_scissor(command_buffer, &[scissor]);
+        let left = -BAR_HALF_WIDTH;
+        let filled = left + 2.0 * BAR_HALF_WIDTH * clamp(progress.fraction(), 0.0, 1.0);
+        let quads = [
+            QuadPushConstants {
+                bounds: [left, -BAR_HALF_HEIGHT, BAR_HALF_WIDTH, BAR_HALF_HEIGHT],
+                color: TRACK_COLOR,
+            },
+            QuadPushConstants {
+                bounds: [left, -BAR_HALF_HEIGHT, filled, BAR_HALF_HEIGHT],
+                color: BAR_COLOR,
+            },
+        ];
+        let range = QuadPushConstants::range();
+        for quad in quads.iter() {
+            self.device.fp_v1_0().cmd_push_constants(command_buffer, self.layout, range.stage_flags, range.offset, range.size, quad as *const QuadPushConstants as *const c_void);
+            self.device.cmd_draw(command_buffer, 4, 1, 0, 0);
+        }
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for SplashPass<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying splash pass: {:?}", self.pipeline);
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Screen-space ambient occlusion, run on an `EffectTarget` at the resolution `--effect-scale`
+//! gives `ssao`. `shaders/ssao.glsl` reads the target's reduced depth and writes how occluded
+//! each reduced texel is into its output, which the bilateral upsample then darkens the lit image
+//! with.
+use ash::version::DeviceV1_0;
+use std::ptr;
+use vk::types::*;
+use ::descriptor::DescriptorWrite;
+use ::half_res::{ EffectTarget, REDUCED_DEPTH_BINDING };
+use ::math::Mat4;
+use ::rect::ExtentExt;
+
+pub const SHADER: &'static str = "shaders/ssao.comp.spv";
+
+/// Binding of the occlusion the kernel writes, next to the target's reduced depth.
+const OCCLUSION_BINDING: u32 = 2;
+
+/// Invocations per axis of a `shaders/ssao.glsl` workgroup.
+const LOCAL_SIZE: u32 = 8;
+
+/// Push constant block read by `shaders/ssao.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SsaoPushConstants {
+    /// Near and far planes, the sampling radius in world units, and how strongly occluded
+    /// samples darken.
+    pub params: [f32; 4],
+    /// Reduced texels per world unit at a distance of one, so the radius shrinks on screen with
+    /// distance.
+    pub focal: f32,
+}
+
+impl SsaoPushConstants {
+    /// For `projection`, with planes at `near` and `far`, sampling `radius` around each texel of
+    /// `target`.
+    pub fn new<D: DeviceV1_0>(target: &EffectTarget<D>, projection: &Mat4, near: f32, far: f32, radius: f32, intensity: f32) -> SsaoPushConstants {
+        SsaoPushConstants {
+            params: [near, far, radius, intensity],
+            focal: projection.cols[1][1].abs() * target.extent().height as f32 * 0.5,
+        }
+    }
+}
+
+/// Descriptor set layout bindings for the kernel.
+pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+    vec![
+        DescriptorSetLayoutBinding {
+            binding: REDUCED_DEPTH_BINDING,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            p_immutable_samplers: ptr::null(),
+        },
+        DescriptorSetLayoutBinding {
+            binding: OCCLUSION_BINDING,
+            descriptor_type: DescriptorType::StorageImage,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            p_immutable_samplers: ptr::null(),
+        },
+    ]
+}
+
+/// Writes for the kernel's set, reading and writing `target`.
+pub fn writes<D: DeviceV1_0>(target: &EffectTarget<D>) -> [DescriptorWrite; 2] {
+    [target.reduced_depth_write(REDUCED_DEPTH_BINDING), target.effect_write(OCCLUSION_BINDING)]
+}
+
+/// Workgroups covering `target`.
+pub fn group_count<D: DeviceV1_0>(target: &EffectTarget<D>) -> (u32, u32, u32) {
+    let groups = target.extent().div_ceil(LOCAL_SIZE);
+    (groups.width, groups.height, 1)
+}
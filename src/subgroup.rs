@@ -0,0 +1,392 @@
+//! Subgroup capabilities and the reductions built on them. Subgroup properties are a Vulkan 1.1
+//! query that `ash` doesn't wrap, so the entry point is loaded by hand; on 1.0 instances or
+//! devices there's nothing to query, and the scalar shared-memory reduction is used instead.
+//!
+//! The "Subgroup reduction" demo, which `capabilities` only offers with `SubgroupArithmetic`,
+//! reduces a frame's worth of values through `ReductionBuffers` and draws the result as bars.
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, EntryV1_0, InstanceV1_0 };
+use std;
+use std::ffi::CString;
+use std::ptr;
+use vk::types::*;
+use ::compute::{ self, ComputeKernel, StorageBuffer };
+use ::descriptor::{ self, DescriptorAllocator };
+use ::math::prelude::div_ceil;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::pnext::{ ExtensionStruct, PNextChain };
+use ::vk_mem::MemoryAllocator;
+
+pub const BARS_SHADER: &'static str = "shaders/reduction_bars_fragment.frag.spv";
+
+/// Bindings of the reduction shaders' set.
+pub const VALUES_BINDING: u32 = 0;
+pub const PARTIALS_BINDING: u32 = 1;
+
+pub const STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2: u32 = 1000059001;
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_PROPERTIES: u32 = 1000094000;
+
+#[repr(C)]
+struct PhysicalDeviceSubgroupPropertiesRaw {
+    s_type: u32,
+    p_next: *mut c_void,
+    subgroup_size: u32,
+    supported_stages: ShaderStageFlags,
+    supported_operations: u32,
+    quad_operations_in_all_stages: Bool32,
+}
+
+unsafe impl ExtensionStruct for PhysicalDeviceSubgroupPropertiesRaw {}
+
+#[repr(C)]
+pub struct PhysicalDeviceProperties2Raw {
+    pub s_type: u32,
+    pub p_next: *mut c_void,
+    pub properties: PhysicalDeviceProperties,
+}
+
+pub type GetPhysicalDeviceProperties2Fn = unsafe extern "system" fn(PhysicalDevice, *mut PhysicalDeviceProperties2Raw);
+type EnumerateInstanceVersionFn = unsafe extern "system" fn(*mut u32) -> Result;
+
+/// The API version to create instances with: 1.1 when the loader supports it, so subgroup
+/// properties can be queried, and 1.0 otherwise.
+pub fn instance_api_version<E: EntryV1_0>(entry: &E) -> u32 {
+    let name = CString::new("vkEnumerateInstanceVersion").unwrap();
+    let pfn = entry.get_instance_proc_addr(unsafe { Instance::null() }, name.as_ptr());
+    // Only 1.1 loaders export this
+    if pfn as usize == 0 {
+        return vk_make_version!(1, 0, 0);
+    }
+    let enumerate_instance_version = unsafe { std::mem::transmute::<PFN_vkVoidFunction, EnumerateInstanceVersionFn>(pfn) };
+    let mut version = 0;
+    match unsafe { enumerate_instance_version(&mut version) } {
+        Result::Success if version >= vk_make_version!(1, 1, 0) => vk_make_version!(1, 1, 0),
+        _ => vk_make_version!(1, 0, 0),
+    }
+}
+
+/// `vkGetPhysicalDeviceProperties2`, for chaining extension properties onto. `None` unless both
+/// the instance (see `instance_api_version`) and `physical_device` are Vulkan 1.1.
+pub fn load_get_physical_device_properties2<E: EntryV1_0, I: InstanceV1_0>(entry: &E, instance: &I, physical_device: PhysicalDevice) -> Option<GetPhysicalDeviceProperties2Fn> {
+    if instance.get_physical_device_properties(physical_device).api_version < vk_make_version!(1, 1, 0) {
+        return None;
+    }
+    let name = CString::new("vkGetPhysicalDeviceProperties2").unwrap();
+    let pfn = entry.get_instance_proc_addr(instance.handle(), name.as_ptr());
+    if pfn as usize == 0 {
+        return None;
+    }
+    Some(unsafe { std::mem::transmute::<PFN_vkVoidFunction, GetPhysicalDeviceProperties2Fn>(pfn) })
+}
+
+/// Bits of `VkSubgroupFeatureFlags`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubgroupOperations(pub u32);
+
+impl SubgroupOperations {
+    pub const BASIC: u32 = 0x1;
+    pub const VOTE: u32 = 0x2;
+    pub const ARITHMETIC: u32 = 0x4;
+    pub const BALLOT: u32 = 0x8;
+    pub const SHUFFLE: u32 = 0x10;
+    pub const SHUFFLE_RELATIVE: u32 = 0x20;
+    pub const CLUSTERED: u32 = 0x40;
+    pub const QUAD: u32 = 0x80;
+
+    pub fn contains(&self, bits: u32) -> bool {
+        self.0 & bits == bits
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        let names = [
+            (SubgroupOperations::BASIC, "basic"),
+            (SubgroupOperations::VOTE, "vote"),
+            (SubgroupOperations::ARITHMETIC, "arithmetic"),
+            (SubgroupOperations::BALLOT, "ballot"),
+            (SubgroupOperations::SHUFFLE, "shuffle"),
+            (SubgroupOperations::SHUFFLE_RELATIVE, "shuffle_relative"),
+            (SubgroupOperations::CLUSTERED, "clustered"),
+            (SubgroupOperations::QUAD, "quad"),
+        ];
+        names.iter().filter(|&&(bit, _)| self.contains(bit)).map(|&(_, name)| name).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SubgroupProperties {
+    pub subgroup_size: u32,
+    pub supported_stages: ShaderStageFlags,
+    pub supported_operations: SubgroupOperations,
+    pub quad_operations_in_all_stages: bool,
+}
+
+impl SubgroupProperties {
+    /// Queries `physical_device`. `None` when the instance wasn't created with API version 1.1
+    /// (see `instance_api_version`) or the device doesn't support it.
+    pub fn query<E: EntryV1_0, I: InstanceV1_0>(entry: &E, instance: &I, physical_device: PhysicalDevice) -> Option<SubgroupProperties> {
+        let get_physical_device_properties2 = load_get_physical_device_properties2(entry, instance, physical_device)?;
+        let mut subgroup = PhysicalDeviceSubgroupPropertiesRaw {
+            s_type: STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_PROPERTIES,
+            p_next: ptr::null_mut(),
+            subgroup_size: 0,
+            supported_stages: ShaderStageFlags::empty(),
+            supported_operations: 0,
+            quad_operations_in_all_stages: 0,
+        };
+        let mut next = PNextChain::new().push(&mut subgroup);
+        let mut properties = PhysicalDeviceProperties2Raw {
+            s_type: STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2,
+            p_next: next.head_mut(),
+            properties: instance.get_physical_device_properties(physical_device),
+        };
+        unsafe { get_physical_device_properties2(physical_device, &mut properties) };
+        Some(SubgroupProperties {
+            subgroup_size: subgroup.subgroup_size,
+            supported_stages: subgroup.supported_stages,
+            supported_operations: SubgroupOperations(subgroup.supported_operations),
+            quad_operations_in_all_stages: subgroup.quad_operations_in_all_stages != 0,
+        })
+    }
+
+    /// Whether compute shaders can use `subgroupAdd`/`subgroupMin`/`subgroupMax`.
+    pub fn supports_compute_arithmetic(&self) -> bool {
+        self.supported_stages.subset(SHADER_STAGE_COMPUTE_BIT) && self.supported_operations.contains(SubgroupOperations::ARITHMETIC)
+    }
+
+    pub fn log(&self) {
+        info!("Subgroup size {}, operations {:?} in stages {:?}, quad operations in all stages: {}",
+              self.subgroup_size, self.supported_operations.names(), self.supported_stages, self.quad_operations_in_all_stages);
+    }
+}
+
+/// How the reduction shaders combine values within a workgroup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReductionPath {
+    /// Subgroup arithmetic, then one shared-memory step across subgroups.
+    Subgroup,
+    /// A shared-memory tree, for devices without subgroup arithmetic.
+    Scalar,
+}
+
+impl ReductionPath {
+    pub fn choose(properties: Option<&SubgroupProperties>) -> ReductionPath {
+        match properties {
+            Some(properties) if properties.supports_compute_arithmetic() => ReductionPath::Subgroup,
+            _ => ReductionPath::Scalar,
+        }
+    }
+
+    pub fn shader_path(&self) -> &'static str {
+        match *self {
+            ReductionPath::Subgroup => "shaders/reduce_subgroup.comp.spv",
+            ReductionPath::Scalar => "shaders/reduce_scalar.comp.spv",
+        }
+    }
+}
+
+/// Workgroup size of the reduction shaders.
+pub const REDUCTION_LOCAL_SIZE: u32 = 64;
+
+/// One workgroup's partial result, as written by the reduction shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reduction {
+    pub sum: u32,
+    pub min: u32,
+    pub max: u32,
+    _padding: u32,
+}
+
+impl Reduction {
+    pub fn identity() -> Reduction {
+        Reduction {
+            sum: 0,
+            min: u32::MAX,
+            max: 0,
+            _padding: 0,
+        }
+    }
+
+    pub fn combine(&self, other: &Reduction) -> Reduction {
+        Reduction {
+            sum: self.sum.wrapping_add(other.sum),
+            min: std::cmp::min(self.min, other.min),
+            max: std::cmp::max(self.max, other.max),
+            _padding: 0,
+        }
+    }
+
+    /// Folds per-workgroup partials into the final result.
+    pub fn combine_all(partials: &[Reduction]) -> Reduction {
+        partials.iter().fold(Reduction::identity(), |acc, r| acc.combine(r))
+    }
+
+    /// The same reduction on the CPU, for checking the shaders.
+    pub fn of(values: &[u32]) -> Reduction {
+        values.iter().fold(Reduction::identity(), |acc, &v| acc.combine(&Reduction { sum: v, min: v, max: v, _padding: 0 }))
+    }
+
+    pub fn group_count(value_count: usize) -> u32 {
+        div_ceil(value_count as u32, REDUCTION_LOCAL_SIZE)
+    }
+}
+
+/// One frame slot's values and the partials reduced from them.
+struct SlotBuffers<'d, D: DeviceV1_0 + 'd> {
+    values: StorageBuffer<'d, D>,
+    partials: StorageBuffer<'d, D>,
+    set: DescriptorSet,
+    count: u32,
+}
+
+/// Inputs and workgroup partials of a reduction for each frame in flight, so a slot's result can
+/// be read once the GPU is done with that slot's frame rather than waited for.
+pub struct ReductionBuffers<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    slots: Vec<SlotBuffers<'d, D>>,
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+}
+
+impl<'d, D: DeviceV1_0> ReductionBuffers<'d, D> {
+    /// Buffers for up to `capacity` values in each of `slot_count` frames. `set_layout` must be
+    /// made from `bindings`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, set_layout: DescriptorSetLayout, capacity: u32, slot_count: usize) -> VkResult<ReductionBuffers<'d, D>> {
+        let capacity = std::cmp::max(capacity, 1);
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::StorageBuffer,
+            descriptor_count: 2 * slot_count as u32,
+        }];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, slot_count as u32);
+        let mut slots = Vec::with_capacity(slot_count);
+        for _ in 0..slot_count {
+            let values = StorageBuffer::new(device, allocator, capacity as DeviceSize * std::mem::size_of::<u32>() as DeviceSize)?;
+            let partials = StorageBuffer::new(device, allocator, Reduction::group_count(capacity as usize) as DeviceSize * std::mem::size_of::<Reduction>() as DeviceSize)?;
+            let set = descriptor_allocator.allocate(set_layout)?;
+            descriptor::update_descriptor_set(device, set, &[
+                values.descriptor_write(VALUES_BINDING),
+                partials.descriptor_write(PARTIALS_BINDING),
+            ]);
+            slots.push(SlotBuffers {
+                values: values,
+                partials: partials,
+                set: set,
+                count: 0,
+            });
+        }
+        Ok(ReductionBuffers {
+            device: device,
+            slots: slots,
+            _descriptor_allocator: descriptor_allocator,
+        })
+    }
+
+    pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+        compute::storage_buffer_bindings(2)
+    }
+
+    pub fn capacity(&self) -> u32 {
+        (self.slots[0].values.size() / std::mem::size_of::<u32>() as DeviceSize) as u32
+    }
+
+    /// Writes `values` into `slot`'s buffer, which the GPU must be done with. Values past the
+    /// capacity are left out.
+    pub fn write(&mut self, slot: usize, values: &[u32]) -> VkResult<()> {
+        let count = std::cmp::min(values.len(), self.capacity() as usize);
+        let buffers = &mut self.slots[slot];
+        buffers.values.write(&values[..count])?;
+        buffers.count = count as u32;
+        Ok(())
+    }
+
+    /// Records reducing `slot`'s values with `kernel`, one of the reduction shaders made with
+    /// `bindings`, and a barrier so `result` can read the partials once the frame is done.
+    pub unsafe fn cmd_reduce(&self, command_buffer: CommandBuffer, kernel: &ComputeKernel<'d, D>, slot: usize) {
+        let buffers = &self.slots[slot];
+        if buffers.count == 0 {
+            return;
+        }
+        kernel.cmd_dispatch(command_buffer, buffers.set, &buffers.count, (Reduction::group_count(buffers.count as usize), 1, 1));
+        let to_host = MemoryBarrier {
+            s_type: StructureType::MemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_SHADER_WRITE_BIT,
+            dst_access_mask: ACCESS_HOST_READ_BIT,
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_HOST_BIT, Default::default(), &[to_host], &[], &[]);
+    }
+
+    /// What the last `cmd_reduce` of `slot` came to. The frame it was recorded in must be done.
+    pub fn result(&self, slot: usize) -> Reduction {
+        let buffers = &self.slots[slot];
+        Reduction::combine_all(&buffers.partials.read::<Reduction>(Reduction::group_count(buffers.count as usize) as usize))
+    }
+}
+
+/// Push constant block read by `shaders/reduction_bars_fragment.glsl`: a reduction's minimum,
+/// mean and maximum as fractions of the values' range, drawn as bars top to bottom.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReductionBarsPushConstants {
+    pub min: f32,
+    pub mean: f32,
+    pub max: f32,
+    /// 1 if the GPU's reduction differed from the CPU's, which turns the bars red.
+    pub mismatch: u32,
+}
+
+impl ReductionBarsPushConstants {
+    /// For `reduction` of `count` values below `range`.
+    pub fn new(reduction: &Reduction, count: u32, range: u32, mismatch: bool) -> ReductionBarsPushConstants {
+        let fraction = |value: f64| (value / range as f64) as f32;
+        ReductionBarsPushConstants {
+            min: if count > 0 { fraction(reduction.min as f64) } else { 0.0 },
+            mean: if count > 0 { fraction(reduction.sum as f64 / count as f64) } else { 0.0 },
+            max: fraction(reduction.max as f64),
+            mismatch: mismatch as u32,
+        }
+    }
+
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<ReductionBarsPushConstants>() as u32,
+        }
+    }
+
+    /// Pushes the bars and draws them over the screen, with the bars pipeline bound.
+    pub unsafe fn cmd_draw<D: DeviceV1_0>(&self, device: &D, command_buffer: CommandBuffer, layout: PipelineLayout) {
+        let range = ReductionBarsPushConstants::range();
+        device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, self as *const ReductionBarsPushConstants as *const c_void);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// The bars from `shaders/reduction_bars_fragment.glsl` over a fullscreen triangle.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partials_combine_to_the_whole_reduction() {
+        let values: Vec<u32> = (0..200u32).map(|i| (i * 37) % 101).collect();
+        let partials: Vec<Reduction> = values.chunks(REDUCTION_LOCAL_SIZE as usize).map(Reduction::of).collect();
+        assert_eq!(partials.len() as u32, Reduction::group_count(values.len()));
+        assert_eq!(Reduction::combine_all(&partials), Reduction::of(&values));
+        assert_eq!(Reduction::of(&[]), Reduction::identity());
+    }
+
+    #[test]
+    fn bars_are_fractions_of_the_range() {
+        let bars = ReductionBarsPushConstants::new(&Reduction::of(&[10, 20, 30, 40]), 4, 100, false);
+        assert_eq!(bars, ReductionBarsPushConstants { min: 0.1, mean: 0.25, max: 0.4, mismatch: 0 });
+        assert_eq!(ReductionBarsPushConstants::new(&Reduction::identity(), 0, 100, true).min, 0.0);
+    }
+}
@@ -0,0 +1,192 @@
+//! Requested swapchain image count, usage, and composite alpha, checked against what the surface
+//! actually supports before the swapchain is created. `--swapchain-images=<min|min+1|count>` picks
+//! the image count.
+use std;
+use std::fmt;
+use vk::types::*;
+use ::cvar::Flag;
+
+const ARG_PREFIX: &'static str = "--swapchain-images=";
+pub const CVAR: &'static str = "r.swapchain_images";
+pub const FLAGS: &'static [Flag] = &[Flag::Value { prefix: ARG_PREFIX, name: CVAR }];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageCount {
+    /// The surface's minimum image count.
+    Minimum,
+    /// One more than the minimum, so the application can always be drawing into an image while
+    /// the presentation engine holds the rest. Clamped to the surface's maximum.
+    MinimumPlusOne,
+    /// Exactly this many images, or an error if the surface can't provide them.
+    Exact(u32),
+}
+
+impl ImageCount {
+    pub fn parse(text: &str) -> Option<ImageCount> {
+        match text {
+            "min" => Some(ImageCount::Minimum),
+            "min+1" => Some(ImageCount::MinimumPlusOne),
+            count => count.parse().ok().filter(|&count| count > 0).map(ImageCount::Exact),
+        }
+    }
+
+    /// The count in `r.swapchain_images`, or `MinimumPlusOne` if it isn't set.
+    pub fn from_cvars() -> ImageCount {
+        let text: String = cvar!(CVAR, String::new()).get();
+        if text.is_empty() {
+            return ImageCount::MinimumPlusOne;
+        }
+        ImageCount::parse(&text).unwrap_or_else(|| {
+            warn!("Unknown swapchain image count {:?}, expected \"min\", \"min+1\" or a number", text);
+            ImageCount::MinimumPlusOne
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SwapchainConfig {
+    pub image_count: ImageCount,
+    pub usage: ImageUsageFlags,
+    pub composite_alpha: CompositeAlphaFlagsKHR,
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> SwapchainConfig {
+        SwapchainConfig {
+            image_count: ImageCount::MinimumPlusOne,
+            usage: IMAGE_USAGE_COLOR_ATTACHMENT_BIT,
+            composite_alpha: COMPOSITE_ALPHA_OPAQUE_BIT_KHR,
+        }
+    }
+}
+
+impl SwapchainConfig {
+    pub fn with_image_count(mut self, image_count: ImageCount) -> SwapchainConfig {
+        self.image_count = image_count;
+        self
+    }
+
+    /// Allows copying out of swapchain images, e.g. for screenshots.
+    pub fn with_transfer_src(mut self) -> SwapchainConfig {
+        self.usage |= IMAGE_USAGE_TRANSFER_SRC_BIT;
+        self
+    }
+
+    /// Allows copying into swapchain images, e.g. from an offscreen target.
+    pub fn with_transfer_dst(mut self) -> SwapchainConfig {
+        self.usage |= IMAGE_USAGE_TRANSFER_DST_BIT;
+        self
+    }
+
+    pub fn resolve(&self, capabilities: &SurfaceCapabilitiesKHR) -> std::result::Result<SwapchainSettings, SwapchainConfigError> {
+        // A maximum of 0 means there's no limit
+        let max_image_count = if capabilities.max_image_count > 0 { capabilities.max_image_count } else { u32::MAX };
+        let image_count = match self.image_count {
+            ImageCount::Minimum => capabilities.min_image_count,
+            ImageCount::MinimumPlusOne => std::cmp::min(capabilities.min_image_count + 1, max_image_count),
+            ImageCount::Exact(count) => {
+                if count < capabilities.min_image_count || count > max_image_count {
+                    return Err(SwapchainConfigError::ImageCount {
+                        requested: count,
+                        min: capabilities.min_image_count,
+                        max: capabilities.max_image_count,
+                    });
+                }
+                count
+            },
+        };
+        if !self.usage.subset(capabilities.supported_usage_flags) {
+            return Err(SwapchainConfigError::Usage {
+                requested: self.usage,
+                supported: capabilities.supported_usage_flags,
+            });
+        }
+        if !self.composite_alpha.subset(capabilities.supported_composite_alpha) {
+            return Err(SwapchainConfigError::CompositeAlpha {
+                requested: self.composite_alpha,
+                supported: capabilities.supported_composite_alpha,
+            });
+        }
+        Ok(SwapchainSettings {
+            image_count: image_count,
+            usage: self.usage,
+            composite_alpha: self.composite_alpha,
+        })
+    }
+}
+
+/// A `SwapchainConfig` that the surface is known to support.
+#[derive(Debug, Clone)]
+pub struct SwapchainSettings {
+    pub image_count: u32,
+    pub usage: ImageUsageFlags,
+    pub composite_alpha: CompositeAlphaFlagsKHR,
+}
+
+#[derive(Debug, Clone)]
+pub enum SwapchainConfigError {
+    /// `max` is 0 when the surface has no maximum.
+    ImageCount { requested: u32, min: u32, max: u32 },
+    Usage { requested: ImageUsageFlags, supported: ImageUsageFlags },
+    CompositeAlpha { requested: CompositeAlphaFlagsKHR, supported: CompositeAlphaFlagsKHR },
+}
+
+impl fmt::Display for SwapchainConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SwapchainConfigError::ImageCount { requested, min, max: 0 } =>
+                write!(f, "requested {} swapchain images, but the surface needs at least {}", requested, min),
+            SwapchainConfigError::ImageCount { requested, min, max } =>
+                write!(f, "requested {} swapchain images, but the surface supports {} to {}", requested, min, max),
+            SwapchainConfigError::Usage { requested, supported } =>
+                write!(f, "requested swapchain image usage {:?}, but the surface only supports {:?}", requested, supported),
+            SwapchainConfigError::CompositeAlpha { requested, supported } =>
+                write!(f, "requested composite alpha {:?}, but the surface only supports {:?}", requested, supported),
+        }
+    }
+}
+
+impl std::error::Error for SwapchainConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            SwapchainConfigError::ImageCount { .. } => "unsupported swapchain image count",
+            SwapchainConfigError::Usage { .. } => "unsupported swapchain image usage",
+            SwapchainConfigError::CompositeAlpha { .. } => "unsupported swapchain composite alpha",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_count_parses() {
+        assert_eq!(ImageCount::parse("min"), Some(ImageCount::Minimum));
+        assert_eq!(ImageCount::parse("min+1"), Some(ImageCount::MinimumPlusOne));
+        assert_eq!(ImageCount::parse("3"), Some(ImageCount::Exact(3)));
+        assert_eq!(ImageCount::parse("0"), None);
+        assert_eq!(ImageCount::parse("max"), None);
+    }
+
+    fn resolve(image_count: ImageCount, min: u32, max: u32) -> std::result::Result<SwapchainSettings, SwapchainConfigError> {
+        let mut capabilities: SurfaceCapabilitiesKHR = unsafe { std::mem::zeroed() };
+        capabilities.min_image_count = min;
+        capabilities.max_image_count = max;
+        capabilities.supported_usage_flags = IMAGE_USAGE_COLOR_ATTACHMENT_BIT;
+        capabilities.supported_composite_alpha = COMPOSITE_ALPHA_OPAQUE_BIT_KHR;
+        SwapchainConfig::default().with_image_count(image_count).resolve(&capabilities)
+    }
+
+    #[test]
+    fn image_counts_resolve_within_the_surface_range() {
+        assert_eq!(resolve(ImageCount::Minimum, 2, 3).unwrap().image_count, 2);
+        assert_eq!(resolve(ImageCount::MinimumPlusOne, 2, 3).unwrap().image_count, 3);
+        assert_eq!(resolve(ImageCount::MinimumPlusOne, 2, 2).unwrap().image_count, 2);
+        assert_eq!(resolve(ImageCount::Exact(3), 2, 3).unwrap().image_count, 3);
+        assert!(resolve(ImageCount::Exact(4), 2, 3).is_err());
+        assert!(resolve(ImageCount::Exact(1), 2, 3).is_err());
+        // A maximum of 0 is no maximum
+        assert_eq!(resolve(ImageCount::Exact(8), 2, 0).unwrap().image_count, 8);
+    }
+}
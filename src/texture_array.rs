@@ -0,0 +1,287 @@
+//! 2D array textures, and a material atlas that packs same-sized textures into the layers of a
+//! shared array so that draws only have to change a layer index instead of a descriptor.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::descriptor::DescriptorWrite;
+use ::image_view_cache::ImageViewCache;
+use ::immediate::ImmediateContext;
+use ::safe_create::{ self, Owned };
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/texture_array_fragment.frag.spv";
+
+/// Binding of the array in `shaders/texture_array_fragment.glsl`'s descriptor set.
+pub const MATERIAL_BINDING: u32 = 0;
+
+/// A device-local `Type2dArray` image with a view over all of its layers, and a sampler for
+/// them. Layers are handed out in order by `allocate_layer`.
+pub struct TextureArray<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    sampler: Owned<'d, D, Sampler>,
+    // Dropped in this order: the views, the image, and then the memory bound to it
+    _views: ImageViewCache<'d, D>,
+    image: Owned<'d, D, Image>,
+    _memory: VkAllocation<'d, D>,
+    /// Of all the layers, from `views`.
+    view: ImageView,
+    format: Format,
+    extent: Extent2D,
+    layer_count: u32,
+    used_layers: u32,
+}
+
+impl<'d, D: DeviceV1_0> TextureArray<'d, D> {
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, extent: Extent2D, layer_count: u32) -> VkResult<TextureArray<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type2d,
+            format: format,
+            extent: Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: layer_count,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_TRANSFER_DST_BIT | IMAGE_USAGE_SAMPLED_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let image = safe_create::create_image_safe(device, &image_create_info, None)?;
+        let memory = allocator.allocate_image_memory(*image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+
+        let mut views = ImageViewCache::new(device);
+        let view = views.get(*image, ImageViewType::Type2dArray, format, &layer_range(0, layer_count))?;
+
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::Repeat,
+            address_mode_v: SamplerAddressMode::Repeat,
+            address_mode_w: SamplerAddressMode::Repeat,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        let sampler = safe_create::create_sampler_safe(device, &sampler_create_info, None)?;
+        debug!("Created {}x{} {:?} texture array with {} layers: {:?}", extent.width, extent.height, format, layer_count, *image);
+        Ok(TextureArray {
+            device: device,
+            sampler: sampler,
+            _views: views,
+            image: image,
+            _memory: memory,
+            view: view,
+            format: format,
+            extent: extent,
+            layer_count: layer_count,
+            used_layers: 0,
+        })
+    }
+
+    /// Reserves the next unused layer, if there are any left.
+    pub fn allocate_layer(&mut self) -> Option<u32> {
+        if self.used_layers < self.layer_count {
+            self.used_layers += 1;
+            Some(self.used_layers - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Checks whether a texture of the given format and size could live in this array.
+    pub fn is_compatible(&self, format: Format, extent: &Extent2D) -> bool {
+        self.format == format && self.extent.width == extent.width && self.extent.height == extent.height
+    }
+
+    /// Records a copy of tightly-packed texel data at `offset` in `src_buffer` into `layer`,
+    /// leaving the layer in `ShaderReadOnlyOptimal` for sampling from fragment shaders.
+    pub unsafe fn cmd_upload_layer(&self, command_buffer: CommandBuffer, src_buffer: Buffer, offset: DeviceSize, layer: u32) {
+        assert!(layer < self.layer_count);
+        let to_transfer_dst = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: Default::default(),
+            dst_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            old_layout: ImageLayout::Undefined,
+            new_layout: ImageLayout::TransferDstOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: *self.image,
+            subresource_range: layer_range(layer, 1),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, PIPELINE_STAGE_TRANSFER_BIT, Default::default(), &[], &[], &[to_transfer_dst]);
+        let region = BufferImageCopy {
+            buffer_offset: offset,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: ImageSubresourceLayers {
+                aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+                mip_level: 0,
+                base_array_layer: layer,
+                layer_count: 1,
+            },
+            image_offset: Offset3D {
+                x: 0,
+                y: 0,
+                z: 0,
+            },
+            image_extent: Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            },
+        };
+        self.device.cmd_copy_buffer_to_image(command_buffer, src_buffer, *self.image, ImageLayout::TransferDstOptimal, &[region]);
+        let to_shader_read = ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: ACCESS_TRANSFER_WRITE_BIT,
+            dst_access_mask: ACCESS_SHADER_READ_BIT,
+            old_layout: ImageLayout::TransferDstOptimal,
+            new_layout: ImageLayout::ShaderReadOnlyOptimal,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: *self.image,
+            subresource_range: layer_range(layer, 1),
+        };
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TRANSFER_BIT, PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &[to_shader_read]);
+    }
+
+    /// Same as `cmd_upload_layer`, but submits the upload and waits for it to finish.
+    pub fn upload_layer(&self, immediate: &ImmediateContext<D>, src_buffer: Buffer, offset: DeviceSize, layer: u32) -> VkResult<()> {
+        immediate.immediate_submit(|command_buffer| unsafe {
+            self.cmd_upload_layer(command_buffer, src_buffer, offset, layer);
+        })
+    }
+
+    /// Descriptor set layout bindings for `FRAGMENT_SHADER`.
+    pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![DescriptorSetLayoutBinding {
+            binding: MATERIAL_BINDING,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            p_immutable_samplers: ptr::null(),
+        }]
+    }
+
+    /// All of the layers, for `binding`, once they're uploaded.
+    pub fn sampled_write(&self, binding: u32) -> DescriptorWrite {
+        DescriptorWrite::CombinedImageSampler {
+            binding: binding,
+            sampler: *self.sampler,
+            image_view: self.view,
+            image_layout: ImageLayout::ShaderReadOnlyOptimal,
+        }
+    }
+}
+
+fn layer_range(base_array_layer: u32, layer_count: u32) -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: base_array_layer,
+        layer_count: layer_count,
+    }
+}
+
+/// Location of a material's texture within a `MaterialAtlas`. Sorting draws by slot groups
+/// together the draws that can share a descriptor set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialSlot {
+    pub array: usize,
+    pub layer: u32,
+}
+
+/// Push constant block read by `shaders/texture_array_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialPushConstants {
+    pub layer: u32,
+}
+
+impl MaterialPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<MaterialPushConstants>() as u32,
+        }
+    }
+}
+
+/// Packs same-sized, same-format textures into shared `TextureArray`s, creating a new array of
+/// `layers_per_array` layers whenever no existing one has room.
+pub struct MaterialAtlas<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    allocator: &'d MemoryAllocator<D>,
+    layers_per_array: u32,
+    arrays: Vec<TextureArray<'d, D>>,
+}
+
+impl<'d, D: DeviceV1_0> MaterialAtlas<'d, D> {
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, layers_per_array: u32) -> MaterialAtlas<'d, D> {
+        MaterialAtlas {
+            device: device,
+            allocator: allocator,
+            layers_per_array: layers_per_array,
+            arrays: Vec::new(),
+        }
+    }
+
+    pub fn allocate(&mut self, format: Format, extent: Extent2D) -> VkResult<MaterialSlot> {
+        let existing = self.arrays.iter_mut()
+            .enumerate()
+            .filter(|(_, array)| array.is_compatible(format, &extent))
+            .filter_map(|(idx, array)| array.allocate_layer().map(|layer| MaterialSlot { array: idx, layer: layer }))
+            .next();
+        if let Some(slot) = existing {
+            return Ok(slot);
+        }
+        let mut array = TextureArray::new(self.device, self.allocator, format, extent, self.layers_per_array)?;
+        let layer = array.allocate_layer().expect("A fresh texture array should always have a free layer");
+        self.arrays.push(array);
+        Ok(MaterialSlot {
+            array: self.arrays.len() - 1,
+            layer: layer,
+        })
+    }
+
+    pub fn array(&self, slot: MaterialSlot) -> &TextureArray<'d, D> {
+        &self.arrays[slot.array]
+    }
+
+    pub fn arrays(&self) -> &[TextureArray<'d, D>] {
+        self.arrays.as_slice()
+    }
+}
+
+/// Pushes the layer index for `slot` so that the following draws sample from the right layer.
+pub unsafe fn cmd_push_material_slot<D: DeviceV1_0>(device: &D, command_buffer: CommandBuffer, layout: PipelineLayout, slot: MaterialSlot) {
+    let constants = MaterialPushConstants {
+        layer: slot.layer,
+    };
+    let range = MaterialPushConstants::range();
+    device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const MaterialPushConstants as *const c_void);
+}
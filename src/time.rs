@@ -0,0 +1,129 @@
+//! Frame timing for animation. `Time` is ticked once per frame and tracks the raw frame delta, a
+//! smoothed delta that rides out present-mode jitter, and the scaled time animations should
+//! follow, which stops while paused and can run faster or slower than real time.
+use std::time::{ Duration, Instant };
+
+/// Weight of each new frame in the smoothed delta.
+const SMOOTHING: f32 = 0.1;
+
+/// Deltas are clamped to this, so a breakpoint or a dragged window doesn't make animations jump.
+const MAX_DELTA: f32 = 0.25;
+
+pub fn seconds(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1e-9
+}
+
+/// Push constant block for shaders that animate, e.g. `layout(push_constant) uniform Time { float
+/// time; float delta; }`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimePushConstants {
+    pub time: f32,
+    pub delta: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Time {
+    start: Instant,
+    last_tick: Instant,
+    frame: u64,
+    real_delta: f32,
+    smoothed_delta: f32,
+    delta: f32,
+    total: f64,
+    scale: f32,
+    paused: bool,
+}
+
+impl Time {
+    pub fn new() -> Time {
+        let now = Instant::now();
+        Time {
+            start: now,
+            last_tick: now,
+            frame: 0,
+            real_delta: 0.0,
+            smoothed_delta: 0.0,
+            delta: 0.0,
+            total: 0.0,
+            scale: 1.0,
+            paused: false,
+        }
+    }
+
+    /// Starts a new frame.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        self.real_delta = seconds(now.duration_since(self.last_tick)).min(MAX_DELTA);
+        self.last_tick = now;
+        self.smoothed_delta = if self.frame == 0 {
+            self.real_delta
+        } else {
+            self.smoothed_delta + (self.real_delta - self.smoothed_delta) * SMOOTHING
+        };
+        self.delta = if self.paused { 0.0 } else { self.real_delta * self.scale };
+        self.total += self.delta as f64;
+        self.frame += 1;
+    }
+
+    /// Frames ticked so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Scaled seconds since the last tick, zero while paused.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Unscaled seconds since the last tick, for things that keep going while paused.
+    pub fn real_delta(&self) -> f32 {
+        self.real_delta
+    }
+
+    /// Scaled seconds since the start, i.e. the sum of every `delta`.
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    /// Wall clock time since the start.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn fps(&self) -> f32 {
+        if self.smoothed_delta > 0.0 { 1.0 / self.smoothed_delta } else { 0.0 }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+        debug!("Time scale: {}", self.scale);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        debug!("Time {}", if paused { "paused" } else { "resumed" });
+    }
+
+    pub fn toggle_pause(&mut self) {
+        let paused = !self.paused;
+        self.set_paused(paused);
+    }
+
+    /// Animation time for shaders. Wraps every hour, since a large `f32` loses the precision
+    /// smooth animation needs.
+    pub fn push_constants(&self) -> TimePushConstants {
+        TimePushConstants {
+            time: (self.total % 3600.0) as f32,
+            delta: self.delta,
+        }
+    }
+}
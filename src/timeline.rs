@@ -0,0 +1,141 @@
+//! Timeline semaphores, from `VK_KHR_timeline_semaphore`: a 64-bit counter that submissions
+//! advance and the CPU can read at any time without waiting. `FrameLoop` signals every frame it
+//! submits on one when the extension is enabled, so the watchdog can report which frame the GPU
+//! actually got through, rather than only what was submitted.
+//!
+//! `ash` predates the extension, so its structures are declared here. The renderer enables
+//! `EXTENSION_NAME` along with `TimelineSemaphoreFeatures::enabled()` when the device has it.
+use ash::prelude::VkResult;
+use ash::version::{ DeviceV1_0, InstanceV1_0 };
+use std;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::ptr;
+use vk::types::*;
+use ::capabilities::DeviceCapabilities;
+use ::pnext::{ ExtensionStruct, PNextChain };
+use ::safe_create::{ self, Owned };
+
+pub const EXTENSION_NAME: &'static str = "VK_KHR_timeline_semaphore";
+
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_TIMELINE_SEMAPHORE_FEATURES: u32 = 1000207000;
+const STRUCTURE_TYPE_SEMAPHORE_TYPE_CREATE_INFO: u32 = 1000207002;
+const STRUCTURE_TYPE_TIMELINE_SEMAPHORE_SUBMIT_INFO: u32 = 1000207003;
+
+const SEMAPHORE_TYPE_TIMELINE: u32 = 1;
+
+#[repr(C)]
+pub struct TimelineSemaphoreFeatures {
+    s_type: u32,
+    pub p_next: *mut c_void,
+    pub timeline_semaphore: Bool32,
+}
+
+unsafe impl ExtensionStruct for TimelineSemaphoreFeatures {}
+
+impl TimelineSemaphoreFeatures {
+    pub fn enabled() -> TimelineSemaphoreFeatures {
+        TimelineSemaphoreFeatures {
+            s_type: STRUCTURE_TYPE_PHYSICAL_DEVICE_TIMELINE_SEMAPHORE_FEATURES,
+            p_next: ptr::null_mut(),
+            timeline_semaphore: true as Bool32,
+        }
+    }
+}
+
+#[repr(C)]
+struct SemaphoreTypeCreateInfo {
+    s_type: u32,
+    p_next: *const c_void,
+    semaphore_type: u32,
+    initial_value: u64,
+}
+
+unsafe impl ExtensionStruct for SemaphoreTypeCreateInfo {}
+
+/// The values a submission waits for and signals, one for each of its wait and signal
+/// semaphores in the same order. Binary semaphores' values are ignored, but they still need one.
+#[repr(C)]
+pub struct TimelineSemaphoreSubmitInfo<'a> {
+    s_type: u32,
+    p_next: *const c_void,
+    wait_semaphore_value_count: u32,
+    p_wait_semaphore_values: *const u64,
+    signal_semaphore_value_count: u32,
+    p_signal_semaphore_values: *const u64,
+    phantom: PhantomData<&'a [u64]>,
+}
+
+unsafe impl<'a> ExtensionStruct for TimelineSemaphoreSubmitInfo<'a> {}
+
+impl<'a> TimelineSemaphoreSubmitInfo<'a> {
+    pub fn new(wait_values: &'a [u64], signal_values: &'a [u64]) -> TimelineSemaphoreSubmitInfo<'a> {
+        TimelineSemaphoreSubmitInfo {
+            s_type: STRUCTURE_TYPE_TIMELINE_SEMAPHORE_SUBMIT_INFO,
+            p_next: ptr::null(),
+            wait_semaphore_value_count: wait_values.len() as u32,
+            p_wait_semaphore_values: wait_values.as_ptr(),
+            signal_semaphore_value_count: signal_values.len() as u32,
+            p_signal_semaphore_values: signal_values.as_ptr(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Whether the device can have the extension enabled. Its feature struct goes through
+/// `vkGetPhysicalDeviceFeatures2`, so this also needs a Vulkan 1.1 device.
+pub fn is_supported(capabilities: &DeviceCapabilities) -> bool {
+    capabilities.properties.api_version >= vk_make_version!(1, 1, 0)
+        && capabilities.extensions.iter().any(|name| name == EXTENSION_NAME)
+}
+
+type GetSemaphoreCounterValueFn = unsafe extern "system" fn(Device, Semaphore, *mut u64) -> Result;
+
+pub struct TimelineSemaphore<'d, D: DeviceV1_0 + 'd> {
+    semaphore: Owned<'d, D, Semaphore>,
+    device: Device,
+    get_counter_value: GetSemaphoreCounterValueFn,
+}
+
+impl<'d, D: DeviceV1_0> TimelineSemaphore<'d, D> {
+    /// A timeline starting at `initial_value`, on a device with `EXTENSION_NAME` enabled.
+    pub fn new<I: InstanceV1_0>(instance: &I, device: &'d D, initial_value: u64) -> VkResult<TimelineSemaphore<'d, D>> {
+        let name = CString::new("vkGetSemaphoreCounterValueKHR").unwrap();
+        let pfn = instance.get_device_proc_addr(device.handle(), name.as_ptr());
+        if pfn as usize == 0 {
+            return Err(Result::ErrorExtensionNotPresent);
+        }
+        let get_counter_value = unsafe { std::mem::transmute::<PFN_vkVoidFunction, GetSemaphoreCounterValueFn>(pfn) };
+        let mut type_info = SemaphoreTypeCreateInfo {
+            s_type: STRUCTURE_TYPE_SEMAPHORE_TYPE_CREATE_INFO,
+            p_next: ptr::null(),
+            semaphore_type: SEMAPHORE_TYPE_TIMELINE,
+            initial_value: initial_value,
+        };
+        let next = PNextChain::new()
+            .push(&mut type_info);
+        let create_info = SemaphoreCreateInfo {
+            s_type: StructureType::SemaphoreCreateInfo,
+            p_next: next.head(),
+            flags: Default::default(),
+        };
+        Ok(TimelineSemaphore {
+            semaphore: safe_create::create_semaphore_safe(device, &create_info, None)?,
+            device: device.handle(),
+            get_counter_value: get_counter_value,
+        })
+    }
+
+    pub fn handle(&self) -> Semaphore {
+        *self.semaphore
+    }
+
+    /// The highest value signaled so far.
+    pub fn value(&self) -> VkResult<u64> {
+        let mut value = 0;
+        match unsafe { (self.get_counter_value)(self.device, *self.semaphore, &mut value) } {
+            Result::Success => Ok(value),
+            e => Err(e),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-frame uniform buffers. Each frame in flight gets its own host-visible copy of the block
+//! and a descriptor set pointing at it, so the CPU can rewrite one frame's uniforms while the
+//! GPU still reads another's.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::marker::PhantomData;
+use std::ptr;
+use vk::types::*;
+use ::buffer::DeviceBuffer;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::math::Mat4;
+use ::vk_mem::MemoryAllocator;
+
+/// Binding of the uniform block in its set.
+pub const BINDING: u32 = 0;
+
+/// The scene's uniform block, `SceneUniforms` in `shaders/vertex.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SceneUniforms {
+    pub model_view_projection: Mat4,
+    /// For turning normals into world space, so only rotation and uniform scale.
+    pub model: Mat4,
+}
+
+pub struct UniformBuffers<'d, D: DeviceV1_0 + 'd, T: Copy> {
+    device: &'d D,
+    buffers: Vec<DeviceBuffer<'d, D>>,
+    set_layout: DescriptorSetLayout,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_sets: Vec<DescriptorSet>,
+    phantom_uniforms: PhantomData<T>,
+}
+
+impl<'d, D: DeviceV1_0, T: Copy> UniformBuffers<'d, D, T> {
+    /// One buffer and set for each of `frames_in_flight`, visible to `stages`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, frames_in_flight: usize, stages: ShaderStageFlags) -> VkResult<UniformBuffers<'d, D, T>> {
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::UniformBuffer,
+            descriptor_count: frames_in_flight as u32,
+        }];
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = UniformBuffers {
+            device: device,
+            buffers: Vec::with_capacity(frames_in_flight),
+            set_layout: DescriptorSetLayout::null(),
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, frames_in_flight as u32),
+            descriptor_sets: Vec::with_capacity(frames_in_flight),
+            phantom_uniforms: PhantomData,
+        };
+        let bindings = [DescriptorSetLayoutBinding {
+            binding: BINDING,
+            descriptor_type: DescriptorType::UniformBuffer,
+            descriptor_count: 1,
+            stage_flags: stages,
+            p_immutable_samplers: ptr::null(),
+        }];
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        ret.set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        let size = std::mem::size_of::<T>() as DeviceSize;
+        for _ in 0..frames_in_flight {
+            let buffer = DeviceBuffer::host_visible(device, allocator, size, BUFFER_USAGE_UNIFORM_BUFFER_BIT)?;
+            let descriptor_set = ret.descriptor_allocator.allocate(ret.set_layout)?;
+            descriptor::update_descriptor_set(device, descriptor_set, &[DescriptorWrite::UniformBuffer {
+                binding: BINDING,
+                buffer: buffer.buffer(),
+                offset: 0,
+                range: size,
+            }]);
+            ret.buffers.push(buffer);
+            ret.descriptor_sets.push(descriptor_set);
+        }
+        Ok(ret)
+    }
+
+    /// For the pipeline layouts that read the block.
+    pub fn set_layout(&self) -> DescriptorSetLayout {
+        self.set_layout
+    }
+
+    /// Writes frame-in-flight `slot`'s copy. The GPU must be done with the last frame that used
+    /// `slot`.
+    pub fn write(&self, slot: usize, uniforms: &T) -> VkResult<()> {
+        self.buffers[slot].write(0, &[*uniforms])
+    }
+
+    pub fn descriptor_set(&self, slot: usize) -> DescriptorSet {
+        self.descriptor_sets[slot]
+    }
+}
+
+impl<'d, D: DeviceV1_0, T: Copy> Drop for UniformBuffers<'d, D, T> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying uniform buffers: {:?}", self.set_layout);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Rendering the scene at a lower resolution than the swapchain and upscaling it in a final
+//! full-screen pass (`shaders/upscale_fragment.glsl`). The scene goes into a sampled
+//! `OffscreenTarget` at `Upscaler::render_extent`, and the pass samples it into the swapchain
+//! image with the selected `UpscaleFilter`. Pass `--render-scale=<fraction>` to start scaled, and
+//! change the scale and filter at runtime with `Upscaler::handle_key`.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use glfw;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::command::{ FullscreenTriangle, RenderPassRecorder };
+use ::cvar::Flag;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::math::prelude::clamp;
+use ::offscreen::{ OffscreenTarget, OffscreenUsage };
+use ::pipeline::GraphicsPipelineBuilder;
+use ::rect::ExtentExt;
+use ::vk_mem::MemoryAllocator;
+
+pub const ARG_PREFIX: &'static str = "--render-scale=";
+pub const CVAR: &'static str = "r.render_scale";
+pub const FLAGS: &'static [Flag] = &[Flag::Value { prefix: ARG_PREFIX, name: CVAR }];
+
+pub const FRAGMENT_SHADER: &'static str = "shaders/upscale_fragment.frag.spv";
+
+/// Binding of the low resolution scene color.
+pub const SOURCE_BINDING: u32 = 0;
+
+/// Below a quarter of the output the image is mostly blur, whatever the filter.
+const MIN_SCALE: f32 = 0.25;
+const SCALE_STEP: f32 = 0.125;
+/// Sharpness in stops, see `UpscalePushConstants::sharpness`.
+const DEFAULT_SHARPNESS: f32 = 0.2;
+const MAX_SHARPNESS: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Each output pixel takes the closest source texel, keeping the blockiness visible.
+    Nearest,
+    Bilinear,
+    /// Bilinear followed by contrast-adaptive sharpening, like the RCAS pass of FSR 1.
+    Sharpened,
+}
+
+impl UpscaleFilter {
+    pub fn next(&self) -> UpscaleFilter {
+        match *self {
+            UpscaleFilter::Nearest => UpscaleFilter::Bilinear,
+            UpscaleFilter::Bilinear => UpscaleFilter::Sharpened,
+            UpscaleFilter::Sharpened => UpscaleFilter::Nearest,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        match *self {
+            UpscaleFilter::Nearest => 0,
+            UpscaleFilter::Bilinear => 1,
+            UpscaleFilter::Sharpened => 2,
+        }
+    }
+}
+
+/// Push constant block read by `shaders/upscale_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UpscalePushConstants {
+    pub mode: u32,
+    /// In stops: 0 is the strongest, and each step of 1 halves it.
+    pub sharpness: f32,
+    /// Size of one output pixel in texture coordinates.
+    pub output_texel: [f32; 2],
+}
+
+impl UpscalePushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<UpscalePushConstants>() as u32,
+        }
+    }
+}
+
+/// The render resolution and upscale settings, kept across swapchain recreation.
+#[derive(Debug, Clone)]
+pub struct Upscaler {
+    scale: f32,
+    pub filter: UpscaleFilter,
+    pub sharpness: f32,
+}
+
+impl Upscaler {
+    pub fn new(scale: f32) -> Upscaler {
+        Upscaler {
+            scale: clamp_scale(scale),
+            filter: UpscaleFilter::Bilinear,
+            sharpness: DEFAULT_SHARPNESS,
+        }
+    }
+
+    /// Starts at the scale in `r.render_scale`, which `--render-scale=<fraction>` sets.
+    pub fn from_cvars() -> Upscaler {
+        Upscaler::new(cvar!(CVAR, 1.0).get())
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = clamp_scale(scale);
+        info!("Render scale: {:.3}", self.scale);
+    }
+
+    /// The resolution to render the scene at for an `output` sized swapchain, at least 1x1.
+    pub fn render_extent(&self, output: &Extent2D) -> Extent2D {
+        output.scaled(self.scale)
+    }
+
+    /// Whether the upscale pass has anything to do. At full resolution only sharpening does.
+    pub fn is_active(&self) -> bool {
+        self.scale < 1.0 || self.filter == UpscaleFilter::Sharpened
+    }
+
+    /// PageUp and PageDown change the scale, U cycles the filter, and `[` and `]` adjust the
+    /// sharpness. Returns whether the key was handled; callers compare `render_extent` and
+    /// `is_active` before and after to see whether the render target has to be recreated.
+    pub fn handle_key(&mut self, key: glfw::Key) -> bool {
+        match key {
+            glfw::Key::PageUp => {
+                let scale = self.scale + SCALE_STEP;
+                self.set_scale(scale);
+            },
+            glfw::Key::PageDown => {
+                let scale = self.scale - SCALE_STEP;
+                self.set_scale(scale);
+            },
+            glfw::Key::U => {
+                self.filter = self.filter.next();
+                info!("Upscale filter: {:?}", self.filter);
+            },
+            // Sharpness counts stops down from the strongest, so `]` sharpens by lowering it
+            glfw::Key::LeftBracket => {
+                self.sharpness = (self.sharpness + 0.1).min(MAX_SHARPNESS);
+                info!("Upscale sharpness: {:.1} stops", self.sharpness);
+            },
+            glfw::Key::RightBracket => {
+                self.sharpness = (self.sharpness - 0.1).max(0.0);
+                info!("Upscale sharpness: {:.1} stops", self.sharpness);
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn push_constants(&self, output: &Extent2D) -> UpscalePushConstants {
+        UpscalePushConstants {
+            mode: self.filter.mode(),
+            sharpness: self.sharpness,
+            output_texel: [1.0 / output.width as f32, 1.0 / output.height as f32],
+        }
+    }
+}
+
+fn clamp_scale(scale: f32) -> f32 {
+    clamp(scale, MIN_SCALE, 1.0)
+}
+
+pub fn bindings() -> Vec<DescriptorSetLayoutBinding> {
+    vec![DescriptorSetLayoutBinding {
+        binding: SOURCE_BINDING,
+        descriptor_type: DescriptorType::CombinedImageSampler,
+        descriptor_count: 1,
+        stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+        p_immutable_samplers: ptr::null(),
+    }]
+}
+
+/// The low resolution target and everything the pass needs to upscale it into one swapchain
+/// render pass. Sized to one swapchain, so it's recreated with it.
+pub struct UpscalePass<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    source: OffscreenTarget<'d, D>,
+    sampler: Sampler,
+    set_layout: DescriptorSetLayout,
+    layout: PipelineLayout,
+    pipeline: Pipeline,
+    descriptor_allocator: DescriptorAllocator<'d, D>,
+    descriptor_set: DescriptorSet,
+    output_extent: Extent2D,
+}
+
+impl<'d, D: DeviceV1_0> UpscalePass<'d, D> {
+    /// Renders at `render_extent`, with a depth buffer if there's a `depth_format`, and upscales
+    /// into subpass 0 of `output_render_pass`, whose framebuffers are `output_extent`.
+    /// `vertex_shader` must be `shaders/fullscreen_vertex.glsl` and `fragment_shader`
+    /// `FRAGMENT_SHADER`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, format: Format, depth_format: Option<Format>, render_extent: Extent2D, output_extent: Extent2D, output_render_pass: RenderPass, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> VkResult<UpscalePass<'d, D>> {
+        let pool_sizes = [DescriptorPoolSize {
+            typ: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+        }];
+        let source = OffscreenTarget::new(device, allocator, format, depth_format, render_extent, OffscreenUsage::Sampled)?;
+        // Destroying null handles is a no-op, so from here on out `Drop` will clean up after us
+        // if anything goes wrong.
+        let mut ret = UpscalePass {
+            device: device,
+            source: source,
+            sampler: Sampler::null(),
+            set_layout: DescriptorSetLayout::null(),
+            layout: PipelineLayout::null(),
+            pipeline: Pipeline::null(),
+            descriptor_allocator: DescriptorAllocator::new(device, &pool_sizes, 1),
+            descriptor_set: DescriptorSet::null(),
+            output_extent: output_extent,
+        };
+
+        // The nearest filter fetches texels directly, so the sampler only has to suit the others
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        ret.sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+
+        let bindings = bindings();
+        let set_layout_create_info = DescriptorSetLayoutCreateInfo {
+            s_type: StructureType::DescriptorSetLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        ret.set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?;
+        let push_constant_range = UpscalePushConstants::range();
+        let layout_create_info = PipelineLayoutCreateInfo {
+            s_type: StructureType::PipelineLayoutCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            set_layout_count: 1,
+            p_set_layouts: &ret.set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        ret.layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }?;
+        ret.pipeline = unsafe { pipeline_builder(&ret.output_extent, vertex_shader, fragment_shader).build_raw(device, PipelineCache::null(), ret.layout, output_render_pass, 0) }?;
+
+        ret.descriptor_set = ret.descriptor_allocator.allocate(ret.set_layout)?;
+        descriptor::update_descriptor_set(device, ret.descriptor_set, &[DescriptorWrite::CombinedImageSampler {
+            binding: SOURCE_BINDING,
+            sampler: ret.sampler,
+            image_view: ret.source.view(),
+            image_layout: ImageLayout::ShaderReadOnlyOptimal,
+        }]);
+        debug!("Created upscale pass from {}x{} to {}x{}", ret.source.extent().width, ret.source.extent().height, ret.output_extent.width, ret.output_extent.height);
+        Ok(ret)
+    }
+
+    /// The low resolution target to render the scene into.
+    pub fn source(&self) -> &OffscreenTarget<'d, D> {
+        &self.source
+    }
+
+    /// Records the upscale inside the output render pass.
+    pub fn cmd_draw<'r, 'a>(&'a self, pass: &mut RenderPassRecorder<'r, 'a, D>, upscaler: &Upscaler) {
+        pass.bind(&self.pipeline);
+        pass.bind_descriptor_sets(&self.layout, 0, &[self.descriptor_set]);
+        pass.push_constants(&self.layout, &UpscalePushConstants::range(), &upscaler.push_constants(&self.output_extent));
+        pass.draw(&FullscreenTriangle);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for UpscalePass<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying upscale pass: {:?}", self.pipeline);
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+            self.device.destroy_descriptor_set_layout(self.set_layout, None);
+            self.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl` into the swapchain image.
+pub fn pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+}
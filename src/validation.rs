@@ -0,0 +1,21 @@
+//! Picking a validation layer that's actually installed. The Khronos layer replaced the LunarG
+//! meta layer, and machines without the SDK have neither; creating the instance with a missing
+//! layer fails outright, so validation is skipped with a warning instead.
+use ash::version::EntryV1_0;
+use std::ffi::CStr;
+
+/// In order of preference.
+pub const LAYERS: [&'static str; 2] = ["VK_LAYER_KHRONOS_validation", "VK_LAYER_LUNARG_standard_validation"];
+
+/// The most preferred of `LAYERS` that the loader knows about.
+pub fn choose_layer<E: EntryV1_0>(entry: &E) -> Option<&'static str> {
+    let installed: Vec<String> = match entry.enumerate_instance_layer_properties() {
+        Ok(layers) => layers.iter().map(|properties| unsafe { CStr::from_ptr(properties.layer_name.as_ptr()) }.to_string_lossy().into_owned()).collect(),
+        Err(e) => {
+            warn!("Couldn't list instance layers: {:?}", e);
+            return None;
+        },
+    };
+    debug!("Installed instance layers: {:?}", installed);
+    LAYERS.iter().cloned().find(|&layer| installed.iter().any(|name| name == layer))
+}
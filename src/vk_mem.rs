@@ -1,13 +1,41 @@
+//! Ownership of Vulkan objects, and device memory. `MemoryAllocator` suballocates buffers and
+//! images out of large blocks, since drivers only allow a few thousand `vkAllocateMemory` calls
+//! and each one is slow. There's a pool of blocks per memory type and resource kind, so
+//! device-local and host-visible memory never share a pool, and buffers never share a block with
+//! images (which would otherwise have to be kept `bufferImageGranularity` apart). Host-visible
+//! blocks stay mapped for as long as they live, so allocations from them can be written to
+//! without mapping, which couldn't be done for two allocations in the same block at once anyway.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
 use std::borrow::Borrow;
 use std::ops::Deref;
+use std::ptr;
+use std::fmt;
+use std::sync::{ Arc, Mutex };
+use vk::types::*;
+use ::logging;
+use ::math::prelude::align_up;
+/// How a `VkOwned` gets rid of its value. Closures work, as do concrete types like
+/// `safe_create::Destroyer`, which can be named in signatures.
+pub trait Destroy<A> {
+    fn destroy(&self, a: A);
+}
+
+impl<A, F: Fn(A)> Destroy<A> for F {
+    fn destroy(&self, a: A) {
+        self(a)
+    }
+}
+
 /// Wrapper struct for representing ownership of values in vulkan that implement
 /// the `Copy` trait.
-pub struct VkOwned<A: Copy, F: Fn(A)> {
+pub struct VkOwned<A: Copy, F: Destroy<A>> {
     value: A,
     destroy_fn: F
 }
 
-impl<A: Copy, F: Fn(A)> VkOwned<A, F> {
+impl<A: Copy, F: Destroy<A>> VkOwned<A, F> {
     /// Takes ownership of the previously-unowned vulkan pointer. This operation is unsafe because
     /// the vulkan pointer may be owned by some other means, or another VkOwned instance.
     pub unsafe fn new(a: A, destroy_fn: F) -> VkOwned<A, F> {
@@ -24,24 +52,556 @@ impl<A: Copy, F: Fn(A)> VkOwned<A, F> {
     pub unsafe fn unsafe_get(&self) -> A {
         self.value
     }
+
+    /// Takes ownership of `a` in place of the current value, which is handed back for the caller
+    /// to destroy. Unsafe for the same reasons as `new`, and `a` must be something this value's
+    /// destroyer can destroy.
+    pub unsafe fn replace(&mut self, a: A) -> A {
+        std::mem::replace(&mut self.value, a)
+    }
+
+    /// Gives up ownership without destroying the value, which the caller is then responsible for.
+    pub fn into_raw(self) -> A {
+        let (value, destroy_fn) = self.into_parts();
+        drop(destroy_fn);
+        value
+    }
+
+    /// Re-wraps the value with a destroyer made from the current one, e.g. to add logging around
+    /// it or hand it off to something that frees it differently.
+    pub fn map_destroy<G: Destroy<A>, M: FnOnce(F) -> G>(self, f: M) -> VkOwned<A, G> {
+        let (value, destroy_fn) = self.into_parts();
+        VkOwned {
+            value: value,
+            destroy_fn: f(destroy_fn),
+        }
+    }
+
+    fn into_parts(self) -> (A, F) {
+        let value = self.value;
+        // `Drop` would destroy the value, so the destroyer is moved out and `self` forgotten
+        let destroy_fn = unsafe { ptr::read(&self.destroy_fn) };
+        std::mem::forget(self);
+        (value, destroy_fn)
+    }
+
+    /// Shares ownership, e.g. of a sampler or pipeline layout used by several materials. The
+    /// value is destroyed when the last clone is dropped.
+    pub fn into_shared(self) -> VkShared<A, F> {
+        VkShared {
+            owned: Arc::new(self),
+        }
+    }
 }
 
-impl<A: Copy, F: Fn(A)> Drop for VkOwned<A, F> {
+impl<A: Copy, F: Destroy<A>> Drop for VkOwned<A, F> {
     fn drop(&mut self) {
-        (self.destroy_fn)(self.value)
+        self.destroy_fn.destroy(self.value)
     }
 }
 
-impl<A: Copy, F: Fn(A)> Borrow<A> for VkOwned<A, F> {
+impl<A: Copy, F: Destroy<A>> Borrow<A> for VkOwned<A, F> {
     fn borrow(&self) -> &A {
         &self.value
     }
 }
 
-impl<A: Copy, F: Fn(A)> Deref for VkOwned<A, F> {
+impl<A: Copy, F: Destroy<A>> Deref for VkOwned<A, F> {
     type Target = A;
 
     fn deref(&self) -> &A {
         &self.value
     }
 }
+
+/// A `VkOwned` with shared ownership, from `VkOwned::into_shared`.
+pub struct VkShared<A: Copy, F: Destroy<A>> {
+    owned: Arc<VkOwned<A, F>>,
+}
+
+impl<A: Copy, F: Destroy<A>> Clone for VkShared<A, F> {
+    fn clone(&self) -> VkShared<A, F> {
+        VkShared {
+            owned: self.owned.clone(),
+        }
+    }
+}
+
+impl<A: Copy, F: Destroy<A>> Borrow<A> for VkShared<A, F> {
+    fn borrow(&self) -> &A {
+        &self.owned.value
+    }
+}
+
+impl<A: Copy, F: Destroy<A>> Deref for VkShared<A, F> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.owned.value
+    }
+}
+
+/// Finds the index of a memory type that is allowed by `type_bits` (as reported in
+/// `MemoryRequirements::memory_type_bits`) and has all of the requested property flags.
+pub fn find_memory_type_index(properties: &PhysicalDeviceMemoryProperties, type_bits: u32, flags: MemoryPropertyFlags) -> Option<u32> {
+    properties.memory_types[..properties.memory_type_count as usize].iter()
+        .zip(0..properties.memory_type_count)
+        .find(|&(memory_type, idx)| (type_bits & (1 << idx)) != 0 && memory_type.property_flags.subset(flags))
+        .map(|(_, idx)| idx)
+}
+
+/// Blocks are this big at most, or an eighth of their heap on small heaps (e.g. the 256 MiB of
+/// device-local, host-visible memory some discrete GPUs have).
+const MAX_BLOCK_SIZE: DeviceSize = 64 * 1024 * 1024;
+/// Allocations over this fraction of a block get a block of their own.
+const DEDICATED_FRACTION: DeviceSize = 2;
+
+/// What an allocation is for. Buffers and images are kept in separate pools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Buffer,
+    /// Only optimally tiled images; linear ones would go in with the buffers.
+    Image,
+}
+
+struct MemoryBlock {
+    memory: DeviceMemory,
+    size: DeviceSize,
+    /// The persistent mapping of the whole block, or null if it isn't host-visible.
+    mapped: *mut u8,
+    /// Unallocated ranges as `(offset, size)`, sorted by offset, with neighbours merged.
+    free: Vec<(DeviceSize, DeviceSize)>,
+    allocation_count: u32,
+    /// Made for a single allocation too big to share a block, and freed with it.
+    dedicated: bool,
+}
+
+// The mapping is only accessed through `VkAllocation`s, each of which owns its own range of it.
+unsafe impl Send for MemoryBlock {}
+
+impl MemoryBlock {
+    /// First fit. Returns the offset of the allocated range.
+    fn allocate(&mut self, size: DeviceSize, alignment: DeviceSize) -> Option<DeviceSize> {
+        let alignment = std::cmp::max(alignment, 1);
+        let found = self.free.iter()
+            .position(|&(offset, free_size)| {
+                let aligned = align_up(offset, alignment);
+                aligned + size <= offset + free_size
+            });
+        found.map(|idx| {
+            let (offset, free_size) = self.free.remove(idx);
+            let aligned = align_up(offset, alignment);
+            let end = offset + free_size;
+            if aligned + size < end {
+                self.free.insert(idx, (aligned + size, end - aligned - size));
+            }
+            if aligned > offset {
+                self.free.insert(idx, (offset, aligned - offset));
+            }
+            self.allocation_count += 1;
+            aligned
+        })
+    }
+
+    fn free(&mut self, offset: DeviceSize, size: DeviceSize) {
+        let idx = self.free.iter().position(|&(free_offset, _)| free_offset > offset).unwrap_or(self.free.len());
+        self.free.insert(idx, (offset, size));
+        // Merge with the next range, then the previous one
+        if idx + 1 < self.free.len() && offset + size == self.free[idx + 1].0 {
+            self.free[idx].1 += self.free[idx + 1].1;
+            self.free.remove(idx + 1);
+        }
+        if idx > 0 && self.free[idx - 1].0 + self.free[idx - 1].1 == offset {
+            self.free[idx - 1].1 += self.free[idx].1;
+            self.free.remove(idx);
+        }
+        self.allocation_count -= 1;
+    }
+}
+
+struct MemoryPool {
+    memory_type_index: u32,
+    kind: ResourceKind,
+    /// `None` where a block has been freed, so the indices in `VkAllocation`s stay valid.
+    blocks: Vec<Option<MemoryBlock>>,
+}
+
+impl MemoryPool {
+    fn live_block_count(&self) -> usize {
+        self.blocks.iter().filter(|block| block.is_some()).count()
+    }
+
+    fn stats(&self) -> PoolStats {
+        let mut ret = PoolStats {
+            memory_type_index: self.memory_type_index,
+            kind: self.kind,
+            blocks: 0,
+            dedicated_blocks: 0,
+            allocations: 0,
+            block_bytes: 0,
+            allocated_bytes: 0,
+        };
+        for block in self.blocks.iter().filter_map(|block| block.as_ref()) {
+            ret.blocks += 1;
+            if block.dedicated {
+                ret.dedicated_blocks += 1;
+            }
+            ret.allocations += block.allocation_count;
+            ret.block_bytes += block.size;
+            ret.allocated_bytes += block.size - block.free.iter().map(|&(_, size)| size).sum::<DeviceSize>();
+        }
+        ret
+    }
+}
+
+/// Usage of one pool. Allocated bytes include alignment padding.
+#[derive(Debug, Clone)]
+pub struct PoolStats {
+    pub memory_type_index: u32,
+    pub kind: ResourceKind,
+    pub blocks: u32,
+    pub dedicated_blocks: u32,
+    pub allocations: u32,
+    pub block_bytes: DeviceSize,
+    pub allocated_bytes: DeviceSize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AllocatorStats {
+    pub pools: Vec<PoolStats>,
+}
+
+impl fmt::Display for AllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for pool in self.pools.iter() {
+            writeln!(f, "type {} {:?}: {} allocations in {} blocks ({} dedicated), {} of {} KiB used",
+                pool.memory_type_index, pool.kind, pool.allocations, pool.blocks, pool.dedicated_blocks, pool.allocated_bytes / 1024, pool.block_bytes / 1024)?;
+        }
+        Ok(())
+    }
+}
+
+/// A handle to read an allocator's stats through without borrowing it, e.g. from a panic hook.
+#[derive(Clone)]
+pub struct AllocatorStatsSource {
+    pools: Arc<Mutex<Vec<MemoryPool>>>,
+}
+
+impl AllocatorStatsSource {
+    /// `None` if the allocator is busy, which is what a thread panicking in the middle of an
+    /// allocation would find rather than deadlocking.
+    pub fn try_stats(&self) -> Option<AllocatorStats> {
+        self.pools.try_lock().ok().map(|pools| AllocatorStats {
+            pools: pools.iter().map(|pool| pool.stats()).collect(),
+        })
+    }
+}
+
+/// Hands out `VkAllocation`s from blocks of device memory. It keeps its own handle to the device
+/// to allocate and free blocks with, so whatever owns the device must call `free_blocks` before
+/// destroying it.
+pub struct MemoryAllocator<D: DeviceV1_0> {
+    device: D,
+    memory_properties: PhysicalDeviceMemoryProperties,
+    pools: Arc<Mutex<Vec<MemoryPool>>>,
+}
+
+impl<D: DeviceV1_0> MemoryAllocator<D> {
+    pub fn new(device: D, memory_properties: PhysicalDeviceMemoryProperties) -> MemoryAllocator<D> {
+        MemoryAllocator {
+            device: device,
+            memory_properties: memory_properties,
+            pools: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn memory_properties(&self) -> &PhysicalDeviceMemoryProperties {
+        &self.memory_properties
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        AllocatorStats {
+            pools: self.pools.lock().unwrap().iter().map(|pool| pool.stats()).collect(),
+        }
+    }
+
+    pub fn stats_source(&self) -> AllocatorStatsSource {
+        AllocatorStatsSource {
+            pools: self.pools.clone(),
+        }
+    }
+
+    /// Allocates memory for `buffer` with all of `flags`, and binds it.
+    pub fn allocate_buffer_memory<'a>(&'a self, buffer: Buffer, flags: MemoryPropertyFlags) -> VkResult<VkAllocation<'a, D>> {
+        let requirements = self.device.get_buffer_memory_requirements(buffer);
+        let allocation = self.allocate(&requirements, flags, ResourceKind::Buffer)?;
+        (unsafe { self.device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) })?;
+        Ok(allocation)
+    }
+
+    /// Allocates memory for the optimally tiled `image` with all of `flags`, and binds it.
+    pub fn allocate_image_memory<'a>(&'a self, image: Image, flags: MemoryPropertyFlags) -> VkResult<VkAllocation<'a, D>> {
+        let requirements = self.device.get_image_memory_requirements(image);
+        let allocation = self.allocate(&requirements, flags, ResourceKind::Image)?;
+        (unsafe { self.device.bind_image_memory(image, allocation.memory, allocation.offset) })?;
+        Ok(allocation)
+    }
+
+    /// Suballocates memory meeting `requirements` from the first memory type with all of `flags`,
+    /// allocating a new block when none of the pool's blocks have room.
+    pub fn allocate<'a>(&'a self, requirements: &MemoryRequirements, flags: MemoryPropertyFlags, kind: ResourceKind) -> VkResult<VkAllocation<'a, D>> {
+        let memory_type_index = find_memory_type_index(&self.memory_properties, requirements.memory_type_bits, flags)
+            .ok_or(Result::ErrorOutOfDeviceMemory)?;
+        let mut pools = self.pools.lock().unwrap();
+        let pool_idx = match pools.iter().position(|pool| pool.memory_type_index == memory_type_index && pool.kind == kind) {
+            Some(idx) => idx,
+            None => {
+                pools.push(MemoryPool {
+                    memory_type_index: memory_type_index,
+                    kind: kind,
+                    blocks: Vec::new(),
+                });
+                pools.len() - 1
+            },
+        };
+        let pool = &mut pools[pool_idx];
+        let block_size = self.block_size(memory_type_index);
+        let dedicated = requirements.size > block_size / DEDICATED_FRACTION;
+        if !dedicated {
+            for (block_idx, block) in pool.blocks.iter_mut().enumerate() {
+                if let Some(ref mut block) = *block {
+                    if block.dedicated {
+                        continue;
+                    }
+                    if let Some(offset) = block.allocate(requirements.size, requirements.alignment) {
+                        return Ok(self.allocation(pool_idx, block_idx, block, offset, requirements.size));
+                    }
+                }
+            }
+        }
+        let mut block = self.allocate_block(memory_type_index, if dedicated { requirements.size } else { block_size }, dedicated)?;
+        let offset = block.allocate(requirements.size, requirements.alignment).unwrap();
+        let block_idx = match pool.blocks.iter().position(|block| block.is_none()) {
+            Some(idx) => idx,
+            None => {
+                pool.blocks.push(None);
+                pool.blocks.len() - 1
+            },
+        };
+        let ret = self.allocation(pool_idx, block_idx, &block, offset, requirements.size);
+        pool.blocks[block_idx] = Some(block);
+        Ok(ret)
+    }
+
+    fn block_size(&self, memory_type_index: u32) -> DeviceSize {
+        let heap_index = self.memory_properties.memory_types[memory_type_index as usize].heap_index;
+        std::cmp::min(MAX_BLOCK_SIZE, self.memory_properties.memory_heaps[heap_index as usize].size / 8)
+    }
+
+    fn allocate_block(&self, memory_type_index: u32, size: DeviceSize, dedicated: bool) -> VkResult<MemoryBlock> {
+        let allocate_info = MemoryAllocateInfo {
+            s_type: StructureType::MemoryAllocateInfo,
+            p_next: ptr::null(),
+            allocation_size: size,
+            memory_type_index: memory_type_index,
+        };
+        let memory = unsafe { self.device.allocate_memory(&allocate_info, None) }?;
+        let property_flags = self.memory_properties.memory_types[memory_type_index as usize].property_flags;
+        let mapped = if property_flags.subset(MEMORY_PROPERTY_HOST_VISIBLE_BIT) {
+            match unsafe { self.device.map_memory(memory, 0, size, Default::default()) } {
+                Ok(mapped) => mapped as *mut u8,
+                Err(e) => {
+                    unsafe { self.device.free_memory(memory, None) };
+                    return Err(e);
+                },
+            }
+        } else {
+            ptr::null_mut()
+        };
+        debug!(target: logging::ALLOCATOR, "Allocated {} {} KiB memory block of type {} ({:?})", if dedicated { "dedicated" } else { "shared" }, size / 1024, memory_type_index, memory);
+        Ok(MemoryBlock {
+            memory: memory,
+            size: size,
+            mapped: mapped,
+            free: vec![(0, size)],
+            allocation_count: 0,
+            dedicated: dedicated,
+        })
+    }
+
+    fn allocation<'a>(&'a self, pool: usize, block_idx: usize, block: &MemoryBlock, offset: DeviceSize, size: DeviceSize) -> VkAllocation<'a, D> {
+        VkAllocation {
+            allocator: Some(self),
+            pool: pool,
+            block: block_idx,
+            memory: block.memory,
+            offset: offset,
+            size: size,
+            mapped: if block.mapped.is_null() { ptr::null_mut() } else { unsafe { block.mapped.offset(offset as isize) } },
+        }
+    }
+
+    /// Returns an allocation's range to its block. Empty blocks are freed, except for the last
+    /// shared block of each pool, so recreating a resource doesn't allocate a new block each time.
+    fn free(&self, allocation: &VkAllocation<D>) {
+        let mut pools = self.pools.lock().unwrap();
+        let pool = &mut pools[allocation.pool];
+        let live_blocks = pool.live_block_count();
+        let empty = {
+            let block = pool.blocks[allocation.block].as_mut().expect("Freeing an allocation from a freed block");
+            block.free(allocation.offset, allocation.size);
+            block.allocation_count == 0 && (block.dedicated || live_blocks > 1)
+        };
+        if empty {
+            let block = pool.blocks[allocation.block].take().unwrap();
+            self.free_block(block);
+        }
+    }
+
+    fn free_block(&self, block: MemoryBlock) {
+        trace!(target: logging::ALLOCATOR, "Freeing {} KiB memory block: {:?}", block.size / 1024, block.memory);
+        unsafe {
+            if !block.mapped.is_null() {
+                self.device.unmap_memory(block.memory);
+            }
+            self.device.free_memory(block.memory, None);
+        }
+    }
+
+    /// Frees every block. Nothing allocated from them may be in use any more.
+    pub fn free_blocks(&self) {
+        let mut pools = self.pools.lock().unwrap();
+        for pool in pools.drain(..) {
+            for block in pool.blocks.into_iter().flatten() {
+                if block.allocation_count > 0 {
+                    warn!(target: logging::ALLOCATOR, "Freeing a memory block with {} allocations left: {:?}", block.allocation_count, block.memory);
+                }
+                self.free_block(block);
+            }
+        }
+    }
+}
+
+impl<D: DeviceV1_0> Drop for MemoryAllocator<D> {
+    fn drop(&mut self) {
+        self.free_blocks();
+    }
+}
+
+/// A range of a `MemoryAllocator` block, returned to it on drop.
+pub struct VkAllocation<'a, D: DeviceV1_0 + 'a> {
+    /// `None` for a null allocation.
+    allocator: Option<&'a MemoryAllocator<D>>,
+    pool: usize,
+    block: usize,
+    memory: DeviceMemory,
+    offset: DeviceSize,
+    size: DeviceSize,
+    mapped: *mut u8,
+}
+
+impl<'a, D: DeviceV1_0> VkAllocation<'a, D> {
+    /// An allocation of nothing, which like a null handle is fine to drop.
+    pub fn null() -> VkAllocation<'a, D> {
+        VkAllocation {
+            allocator: None,
+            pool: 0,
+            block: 0,
+            memory: DeviceMemory::null(),
+            offset: 0,
+            size: 0,
+            mapped: ptr::null_mut(),
+        }
+    }
+
+    /// The start of the allocation in the host's address space, if it's host-visible.
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        if self.mapped.is_null() { None } else { Some(self.mapped) }
+    }
+
+    /// Copies `data` into the allocation at `offset`. It must be host-visible and coherent, and
+    /// the GPU must not be using that part of it.
+    pub fn write<T: Copy>(&self, offset: DeviceSize, data: &[T]) {
+        let size = std::mem::size_of_val(data) as DeviceSize;
+        assert!(offset + size <= self.size);
+        let mapped = self.mapped_ptr().expect("Writing to memory that isn't host-visible");
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), mapped.offset(offset as isize) as *mut T, data.len()) };
+    }
+
+    /// Reads `count` values of `T` from the allocation at `offset`, with the same requirements as
+    /// `write`.
+    pub fn read<T: Copy>(&self, offset: DeviceSize, count: usize) -> Vec<T> {
+        assert!(offset + (count * std::mem::size_of::<T>()) as DeviceSize <= self.size);
+        let mapped = self.mapped_ptr().expect("Reading from memory that isn't host-visible");
+        let mut ret = Vec::with_capacity(count);
+        unsafe {
+            ptr::copy_nonoverlapping(mapped.offset(offset as isize) as *const T, ret.as_mut_ptr(), count);
+            ret.set_len(count);
+        }
+        ret
+    }
+}
+
+impl<'a, D: DeviceV1_0> Drop for VkAllocation<'a, D> {
+    fn drop(&mut self) {
+        if let Some(allocator) = self.allocator {
+            allocator.free(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(size: DeviceSize) -> MemoryBlock {
+        MemoryBlock {
+            memory: DeviceMemory::null(),
+            size: size,
+            mapped: ptr::null_mut(),
+            free: vec![(0, size)],
+            allocation_count: 0,
+            dedicated: false,
+        }
+    }
+
+    #[test]
+    fn first_fit_leaves_alignment_padding_free() {
+        let mut block = block(1024);
+        assert_eq!(block.allocate(100, 1), Some(0));
+        assert_eq!(block.allocate(100, 256), Some(256));
+        assert_eq!(block.free, vec![(100, 156), (356, 668)]);
+        // The padding is reused by anything small enough to fit
+        assert_eq!(block.allocate(64, 4), Some(100));
+        assert_eq!(block.allocation_count, 3);
+        assert_eq!(block.allocate(1024, 1), None);
+        assert_eq!(block.allocation_count, 3);
+    }
+
+    #[test]
+    fn whole_block_is_allocatable_exactly_once() {
+        let mut block = block(512);
+        assert_eq!(block.allocate(512, 512), Some(0));
+        assert!(block.free.is_empty());
+        assert_eq!(block.allocate(1, 1), None);
+        block.free(0, 512);
+        assert_eq!(block.free, vec![(0, 512)]);
+    }
+
+    #[test]
+    fn freed_ranges_merge_with_their_neighbours() {
+        let mut block = block(400);
+        let offsets: Vec<DeviceSize> = (0..4).map(|_| block.allocate(100, 1).unwrap()).collect();
+        assert_eq!(offsets, vec![0, 100, 200, 300]);
+        block.free(100, 100);
+        block.free(300, 100);
+        assert_eq!(block.free, vec![(100, 100), (300, 100)]);
+        // Merges with the next range, then the previous one
+        block.free(200, 100);
+        assert_eq!(block.free, vec![(100, 300)]);
+        block.free(0, 100);
+        assert_eq!(block.free, vec![(0, 400)]);
+        assert_eq!(block.allocation_count, 0);
+        assert_eq!(block.allocate(400, 1), Some(0));
+    }
+}
@@ -0,0 +1,471 @@
+//! Volumetric fog in a froxel grid, a 3D texture aligned with the view frustum whose slices are
+//! spaced exponentially in distance from the camera. Every frame, `shaders/fog_inject.glsl`
+//! evaluates density and in-scattered light for each froxel at a jittered depth and blends it
+//! with the previous frame's grid, `shaders/fog_integrate.glsl` accumulates scattering and
+//! transmittance front to back, and `shaders/fog_apply_fragment.glsl` applies the result over
+//! the lit image by looking up each pixel's depth in the integrated grid.
+//!
+//! The history isn't reprojected, so fast camera motion briefly smears the fog; lowering
+//! `history_weight` trades that for more visible jitter noise.
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use std;
+use std::ptr;
+use vk::types::*;
+use ::compute::ComputeKernel;
+use ::descriptor::{ self, DescriptorAllocator, DescriptorWrite };
+use ::math::{ Mat4, Vec3 };
+use ::math::prelude::div_ceil;
+use ::pipeline::GraphicsPipelineBuilder;
+use ::vk_mem::{ MemoryAllocator, VkAllocation };
+
+pub const INJECT_SHADER: &'static str = "shaders/fog_inject.comp.spv";
+pub const INTEGRATE_SHADER: &'static str = "shaders/fog_integrate.comp.spv";
+pub const APPLY_SHADER: &'static str = "shaders/fog_apply_fragment.frag.spv";
+
+/// Invocations per axis of the fog kernels' 8x8x1 workgroups.
+const LOCAL_SIZE: u32 = 8;
+
+const FROXEL_FORMAT: Format = Format::R16g16b16a16Sfloat;
+
+const HISTORY_BINDING: u32 = 0;
+const FROXELS_BINDING: u32 = 1;
+const INTEGRATE_INPUT_BINDING: u32 = 0;
+const INTEGRATE_OUTPUT_BINDING: u32 = 1;
+/// Bindings of the apply pass' descriptor set.
+pub const INTEGRATED_BINDING: u32 = 0;
+pub const DEPTH_BINDING: u32 = 1;
+
+/// Push constant block read by `shaders/fog_inject.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct FogInjectPushConstants {
+    inverse_view_projection: Mat4,
+    eye: [f32; 4],
+    light: [f32; 4],
+    params: [f32; 4],
+}
+
+/// Push constant block read by `shaders/fog_integrate.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct FogIntegratePushConstants {
+    near: f32,
+    far: f32,
+}
+
+/// Push constant block read by `shaders/fog_apply_fragment.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FogApplyPushConstants {
+    pub inverse_view_projection: Mat4,
+    pub eye: [f32; 4],
+    pub range: [f32; 4],
+}
+
+impl FogApplyPushConstants {
+    pub fn range() -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: std::mem::size_of::<FogApplyPushConstants>() as u32,
+        }
+    }
+}
+
+/// The camera the froxels are aligned to, for one frame.
+#[derive(Debug, Clone, Copy)]
+pub struct FogView {
+    pub inverse_view_projection: Mat4,
+    pub eye: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FogSettings {
+    /// Distances covered by the grid. Geometry beyond `far` gets the fog at `far`.
+    pub near: f32,
+    pub far: f32,
+    /// Extinction per unit distance at ground level.
+    pub density: f32,
+    /// Direction towards the light.
+    pub light_direction: Vec3,
+    /// Henyey-Greenstein anisotropy; positive values scatter forwards, towards the light.
+    pub anisotropy: f32,
+    /// How much of the previous frame each froxel keeps.
+    pub history_weight: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> FogSettings {
+        FogSettings {
+            near: 0.1,
+            far: 64.0,
+            density: 0.02,
+            light_direction: Vec3::new(0.3, 1.0, 0.2),
+            anisotropy: 0.3,
+            history_weight: 0.9,
+        }
+    }
+}
+
+fn subresource_range() -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: IMAGE_ASPECT_COLOR_BIT,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// A 3D image that's both a storage image and sampled, kept in `General` layout.
+struct FroxelVolume<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    image: Image,
+    memory: VkAllocation<'d, D>,
+    view: ImageView,
+}
+
+impl<'d, D: DeviceV1_0> FroxelVolume<'d, D> {
+    fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, extent: &Extent3D) -> VkResult<FroxelVolume<'d, D>> {
+        let image_create_info = ImageCreateInfo {
+            s_type: StructureType::ImageCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image_type: ImageType::Type3d,
+            format: FROXEL_FORMAT,
+            extent: extent.clone(),
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SAMPLE_COUNT_1_BIT,
+            tiling: ImageTiling::Optimal,
+            usage: IMAGE_USAGE_STORAGE_BIT | IMAGE_USAGE_SAMPLED_BIT,
+            sharing_mode: SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: ImageLayout::Undefined,
+        };
+        let mut ret = FroxelVolume {
+            device: device,
+            image: unsafe { device.create_image(&image_create_info, None) }?,
+            memory: VkAllocation::null(),
+            view: ImageView::null(),
+        };
+        ret.memory = allocator.allocate_image_memory(ret.image, MEMORY_PROPERTY_DEVICE_LOCAL_BIT)?;
+        let view_create_info = ImageViewCreateInfo {
+            s_type: StructureType::ImageViewCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            image: ret.image,
+            view_type: ImageViewType::Type3d,
+            format: FROXEL_FORMAT,
+            components: ComponentMapping {
+                r: ComponentSwizzle::Identity,
+                g: ComponentSwizzle::Identity,
+                b: ComponentSwizzle::Identity,
+                a: ComponentSwizzle::Identity,
+            },
+            subresource_range: subresource_range(),
+        };
+        ret.view = unsafe { device.create_image_view(&view_create_info, None) }?;
+        Ok(ret)
+    }
+
+    fn barrier(&self, old_layout: ImageLayout, src_access_mask: AccessFlags, dst_access_mask: AccessFlags) -> ImageMemoryBarrier {
+        ImageMemoryBarrier {
+            s_type: StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: src_access_mask,
+            dst_access_mask: dst_access_mask,
+            old_layout: old_layout,
+            new_layout: ImageLayout::General,
+            src_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: VK_QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range: subresource_range(),
+        }
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for FroxelVolume<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            trace!("Destroying froxel volume: {:?}", self.image);
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+    }
+}
+
+pub struct VolumetricFog<'d, D: DeviceV1_0 + 'd> {
+    device: &'d D,
+    /// In-scattering and extinction, alternating between this frame's and the history.
+    froxels: [FroxelVolume<'d, D>; 2],
+    integrated: FroxelVolume<'d, D>,
+    sampler: Sampler,
+    extent: Extent3D,
+    _descriptor_allocator: DescriptorAllocator<'d, D>,
+    /// `inject_sets[i]` writes `froxels[i]` from the history in the other.
+    inject_sets: [DescriptorSet; 2],
+    /// `integrate_sets[i]` integrates `froxels[i]`.
+    integrate_sets: [DescriptorSet; 2],
+    current: usize,
+    frame: u32,
+    initialized: bool,
+    pub settings: FogSettings,
+}
+
+impl<'d, D: DeviceV1_0> VolumetricFog<'d, D> {
+    /// Creates a grid of `extent` froxels; 160x90x64 is plenty for 1080p. The kernels must be
+    /// built from `INJECT_SHADER` with `inject_bindings` and `INTEGRATE_SHADER` with
+    /// `integrate_bindings`.
+    pub fn new(device: &'d D, allocator: &'d MemoryAllocator<D>, inject: &ComputeKernel<D>, integrate: &ComputeKernel<D>, extent: Extent3D) -> VkResult<VolumetricFog<'d, D>> {
+        let froxels = [
+            FroxelVolume::new(device, allocator, &extent)?,
+            FroxelVolume::new(device, allocator, &extent)?,
+        ];
+        let integrated = FroxelVolume::new(device, allocator, &extent)?;
+        let sampler_create_info = SamplerCreateInfo {
+            s_type: StructureType::SamplerCreateInfo,
+            p_next: ptr::null(),
+            flags: Default::default(),
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            address_mode_w: SamplerAddressMode::ClampToEdge,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: 0,
+            max_anisotropy: 1.0,
+            compare_enable: 0,
+            compare_op: CompareOp::Always,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: BorderColor::FloatTransparentBlack,
+            unnormalized_coordinates: 0,
+        };
+        let sampler = unsafe { device.create_sampler(&sampler_create_info, None) }?;
+
+        let pool_sizes = [
+            DescriptorPoolSize {
+                typ: DescriptorType::StorageImage,
+                descriptor_count: 6,
+            },
+            DescriptorPoolSize {
+                typ: DescriptorType::CombinedImageSampler,
+                descriptor_count: 2,
+            },
+        ];
+        let mut descriptor_allocator = DescriptorAllocator::new(device, &pool_sizes, 4);
+        let mut inject_sets = [DescriptorSet::null(); 2];
+        let mut integrate_sets = [DescriptorSet::null(); 2];
+        let storage = |binding: u32, view: ImageView| DescriptorWrite::StorageImage {
+            binding: binding,
+            image_view: view,
+            image_layout: ImageLayout::General,
+        };
+        for i in 0..2 {
+            inject_sets[i] = descriptor_allocator.allocate(inject.set_layout())?;
+            descriptor::update_descriptor_set(device, inject_sets[i], &[
+                DescriptorWrite::CombinedImageSampler {
+                    binding: HISTORY_BINDING,
+                    sampler: sampler,
+                    image_view: froxels[1 - i].view,
+                    image_layout: ImageLayout::General,
+                },
+                storage(FROXELS_BINDING, froxels[i].view),
+            ]);
+            integrate_sets[i] = descriptor_allocator.allocate(integrate.set_layout())?;
+            descriptor::update_descriptor_set(device, integrate_sets[i], &[
+                storage(INTEGRATE_INPUT_BINDING, froxels[i].view),
+                storage(INTEGRATE_OUTPUT_BINDING, integrated.view),
+            ]);
+        }
+        debug!("Created {}x{}x{} volumetric fog froxel grid", extent.width, extent.height, extent.depth);
+        Ok(VolumetricFog {
+            device: device,
+            froxels: froxels,
+            integrated: integrated,
+            sampler: sampler,
+            extent: extent,
+            _descriptor_allocator: descriptor_allocator,
+            inject_sets: inject_sets,
+            integrate_sets: integrate_sets,
+            current: 0,
+            frame: 0,
+            initialized: false,
+            settings: Default::default(),
+        })
+    }
+
+    /// Push constant size for a kernel built from `INJECT_SHADER`.
+    pub fn inject_push_constant_size() -> u32 {
+        std::mem::size_of::<FogInjectPushConstants>() as u32
+    }
+
+    /// Push constant size for a kernel built from `INTEGRATE_SHADER`.
+    pub fn integrate_push_constant_size() -> u32 {
+        std::mem::size_of::<FogIntegratePushConstants>() as u32
+    }
+
+    pub fn inject_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        vec![
+            DescriptorSetLayoutBinding {
+                binding: HISTORY_BINDING,
+                descriptor_type: DescriptorType::CombinedImageSampler,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+            DescriptorSetLayoutBinding {
+                binding: FROXELS_BINDING,
+                descriptor_type: DescriptorType::StorageImage,
+                descriptor_count: 1,
+                stage_flags: SHADER_STAGE_COMPUTE_BIT,
+                p_immutable_samplers: ptr::null(),
+            },
+        ]
+    }
+
+    pub fn integrate_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        [INTEGRATE_INPUT_BINDING, INTEGRATE_OUTPUT_BINDING].iter().map(|&binding| DescriptorSetLayoutBinding {
+            binding: binding,
+            descriptor_type: DescriptorType::StorageImage,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_COMPUTE_BIT,
+            p_immutable_samplers: ptr::null(),
+        }).collect()
+    }
+
+    pub fn apply_bindings() -> Vec<DescriptorSetLayoutBinding> {
+        [INTEGRATED_BINDING, DEPTH_BINDING].iter().map(|&binding| DescriptorSetLayoutBinding {
+            binding: binding,
+            descriptor_type: DescriptorType::CombinedImageSampler,
+            descriptor_count: 1,
+            stage_flags: SHADER_STAGE_FRAGMENT_BIT,
+            p_immutable_samplers: ptr::null(),
+        }).collect()
+    }
+
+    /// Writes for the apply pass' descriptor set. The integrated grid is always the same image,
+    /// so the set only needs updating when the depth target changes.
+    pub fn apply_writes(&self, depth_sampler: Sampler, depth_view: ImageView, depth_layout: ImageLayout) -> [DescriptorWrite; 2] {
+        [
+            DescriptorWrite::CombinedImageSampler {
+                binding: INTEGRATED_BINDING,
+                sampler: self.sampler,
+                image_view: self.integrated.view,
+                image_layout: ImageLayout::General,
+            },
+            DescriptorWrite::CombinedImageSampler {
+                binding: DEPTH_BINDING,
+                sampler: depth_sampler,
+                image_view: depth_view,
+                image_layout: depth_layout,
+            },
+        ]
+    }
+
+    /// Forgets the history, e.g. after a camera cut.
+    pub fn reset_history(&mut self) {
+        self.frame = 0;
+    }
+
+    /// Records filling and integrating the grid for `view`, with barriers against the previous
+    /// frame's apply pass before and for this frame's after. Must be outside a render pass.
+    pub unsafe fn cmd_update(&mut self, command_buffer: CommandBuffer, inject: &ComputeKernel<D>, integrate: &ComputeKernel<D>, view: &FogView) {
+        let current = self.current;
+        let history = 1 - current;
+        if !self.initialized {
+            let barriers = [
+                self.froxels[0].barrier(ImageLayout::Undefined, Default::default(), ACCESS_SHADER_READ_BIT | ACCESS_SHADER_WRITE_BIT),
+                self.froxels[1].barrier(ImageLayout::Undefined, Default::default(), ACCESS_SHADER_READ_BIT | ACCESS_SHADER_WRITE_BIT),
+                self.integrated.barrier(ImageLayout::Undefined, Default::default(), ACCESS_SHADER_WRITE_BIT),
+            ];
+            self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_TOP_OF_PIPE_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &barriers);
+            self.initialized = true;
+        } else {
+            // Last frame's integration read the grid about to be rewritten, and its apply pass
+            // sampled the integrated grid
+            let barriers = [
+                self.froxels[current].barrier(ImageLayout::General, ACCESS_SHADER_READ_BIT, ACCESS_SHADER_WRITE_BIT),
+                self.integrated.barrier(ImageLayout::General, ACCESS_SHADER_READ_BIT, ACCESS_SHADER_WRITE_BIT),
+            ];
+            self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT | PIPELINE_STAGE_FRAGMENT_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &barriers);
+        }
+
+        let settings = self.settings;
+        let light = settings.light_direction.normalize();
+        // The first frame has no history worth keeping
+        let history_weight = if self.frame == 0 { 0.0 } else { settings.history_weight };
+        let inject_constants = FogInjectPushConstants {
+            inverse_view_projection: view.inverse_view_projection,
+            eye: [view.eye.x, view.eye.y, view.eye.z, (self.frame % 1024) as f32],
+            light: [light.x, light.y, light.z, settings.anisotropy],
+            params: [settings.near, settings.far, settings.density, history_weight],
+        };
+        let groups_x = div_ceil(self.extent.width, LOCAL_SIZE);
+        let groups_y = div_ceil(self.extent.height, LOCAL_SIZE);
+        inject.cmd_dispatch(command_buffer, self.inject_sets[current], &inject_constants, (groups_x, groups_y, self.extent.depth));
+
+        let injected = [self.froxels[current].barrier(ImageLayout::General, ACCESS_SHADER_WRITE_BIT, ACCESS_SHADER_READ_BIT)];
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT, Default::default(), &[], &[], &injected);
+
+        let integrate_constants = FogIntegratePushConstants {
+            near: settings.near,
+            far: settings.far,
+        };
+        integrate.cmd_dispatch(command_buffer, self.integrate_sets[current], &integrate_constants, (groups_x, groups_y, 1));
+
+        // The grid just written is next frame's history, read by the next inject
+        let integrated = [
+            self.integrated.barrier(ImageLayout::General, ACCESS_SHADER_WRITE_BIT, ACCESS_SHADER_READ_BIT),
+            self.froxels[history].barrier(ImageLayout::General, ACCESS_SHADER_READ_BIT, ACCESS_SHADER_READ_BIT),
+        ];
+        self.device.cmd_pipeline_barrier(command_buffer, PIPELINE_STAGE_COMPUTE_SHADER_BIT, PIPELINE_STAGE_COMPUTE_SHADER_BIT | PIPELINE_STAGE_FRAGMENT_SHADER_BIT, Default::default(), &[], &[], &integrated);
+
+        self.current = history;
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// Records the apply pass over the lit image, with the apply pipeline and a descriptor set
+    /// holding `apply_writes` bound. `view` must be the one the grid was updated with.
+    pub unsafe fn cmd_apply(&self, command_buffer: CommandBuffer, layout: PipelineLayout, view: &FogView) {
+        let constants = FogApplyPushConstants {
+            inverse_view_projection: view.inverse_view_projection,
+            eye: [view.eye.x, view.eye.y, view.eye.z, 0.0],
+            range: [self.settings.near, self.settings.far, 0.0, 0.0],
+        };
+        let range = FogApplyPushConstants::range();
+        self.device.fp_v1_0().cmd_push_constants(command_buffer, layout, range.stage_flags, range.offset, range.size, &constants as *const FogApplyPushConstants as *const c_void);
+        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+impl<'d, D: DeviceV1_0> Drop for VolumetricFog<'d, D> {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+/// Full-screen triangle from `shaders/fullscreen_vertex.glsl` that attenuates the image by the
+/// fog's transmittance and adds its in-scattering: `color * transmittance + scattering`.
+pub fn apply_pipeline_builder(extent: &Extent2D, vertex_shader: ShaderModule, fragment_shader: ShaderModule) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(extent)
+        .stage(SHADER_STAGE_VERTEX_BIT, vertex_shader)
+        .stage(SHADER_STAGE_FRAGMENT_BIT, fragment_shader)
+        .cull_mode(CULL_MODE_NONE)
+        .blend_attachment(PipelineColorBlendAttachmentState {
+            blend_enable: true as Bool32,
+            src_color_blend_factor: BlendFactor::One,
+            dst_color_blend_factor: BlendFactor::SrcAlpha,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: BlendFactor::Zero,
+            dst_alpha_blend_factor: BlendFactor::One,
+            alpha_blend_op: BlendOp::Add,
+            color_write_mask: ColorComponentFlags::all(),
+        })
+}
@@ -0,0 +1,172 @@
+//! A watchdog for frames that never finish. The render loop tells it whenever it blocks on the
+//! GPU, e.g. for a frame slot's fences, and a thread of its own checks on that wait. If it takes
+//! longer than `r.watchdog.timeout` seconds, the watchdog logs what was in flight and the last
+//! checkpoints the renderer passed, so a hang at least says where it is.
+//!
+//! With `r.watchdog.abort` set it then writes a crash report and exits, rather than waiting
+//! forever. Nothing is cleaned up on the way out, since the device can't be idled while it's
+//! stuck. Both settings are read on every check, so they can be changed while running.
+use std;
+use std::sync::{ Arc, Condvar, Mutex };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+use ::crash_report::CrashReporter;
+
+/// How often the watchdog thread checks on the current wait.
+pub const POLL_INTERVAL_MS: u64 = 250;
+/// Checkpoints logged with a stall.
+pub const CHECKPOINTS: usize = 8;
+/// The exit code after aborting on a stall.
+pub const EXIT_CODE: i32 = 3;
+
+/// Seconds a wait may take before it counts as a stall, or 0 to never check.
+pub fn timeout() -> f32 {
+    cvar!("r.watchdog.timeout", 10.0).get()
+}
+
+/// Whether to exit after reporting a stall.
+pub fn aborts() -> bool {
+    cvar!("r.watchdog.abort", false).get()
+}
+
+struct Wait {
+    frame: u64,
+    what: &'static str,
+    started: Instant,
+    /// What was submitted when the wait started.
+    submissions: String,
+    reported: bool,
+}
+
+#[derive(Default)]
+struct WatchState {
+    wait: Option<Wait>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<WatchState>,
+    changed: Condvar,
+}
+
+/// Owns the watchdog thread, stopping it when dropped.
+pub struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// `crash` gets a report written if the watchdog aborts.
+    pub fn spawn(crash: CrashReporter) -> std::io::Result<Watchdog> {
+        let shared: Arc<Shared> = Arc::default();
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("watchdog".to_string())
+            .spawn(move || watch(&thread_shared, &crash))?;
+        Ok(Watchdog {
+            shared: shared,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn handle(&self) -> WatchdogHandle {
+        WatchdogHandle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// For telling the watchdog about waits. Clones watch the same thread.
+#[derive(Clone)]
+pub struct WatchdogHandle {
+    shared: Arc<Shared>,
+}
+
+impl WatchdogHandle {
+    /// Watches a wait for `frame` until the returned guard is dropped. `submissions` describes
+    /// what's in flight, for the log if it stalls. Only one wait is watched at a time.
+    pub fn watch<'a>(&'a self, frame: u64, what: &'static str, submissions: String) -> WaitGuard<'a> {
+        self.shared.state.lock().unwrap().wait = Some(Wait {
+            frame: frame,
+            what: what,
+            started: Instant::now(),
+            submissions: submissions,
+            reported: false,
+        });
+        WaitGuard {
+            shared: &self.shared,
+        }
+    }
+}
+
+/// Ends a wait started with `WatchdogHandle::watch` when dropped.
+pub struct WaitGuard<'a> {
+    shared: &'a Shared,
+}
+
+impl<'a> Drop for WaitGuard<'a> {
+    fn drop(&mut self) {
+        let wait = self.shared.state.lock().unwrap().wait.take();
+        if let Some(wait) = wait {
+            if wait.reported {
+                warn!("Frame {} recovered from its stall after {:.1} s", wait.frame, ::time::seconds(wait.started.elapsed()));
+            }
+        }
+    }
+}
+
+fn report(wait: &Wait, crash: &CrashReporter) {
+    let stalled = ::time::seconds(wait.started.elapsed());
+    error!("Frame {} stalled: {} for {:.1} s", wait.frame, wait.what, stalled);
+    error!("In flight: {}", wait.submissions);
+    match crash.checkpoints().try_describe(Instant::now()) {
+        Some(checkpoints) => {
+            let lines: Vec<&str> = checkpoints.lines().collect();
+            error!("Last checkpoints:");
+            for line in lines[lines.len().saturating_sub(CHECKPOINTS)..].iter() {
+                error!("  {}", line);
+            }
+        },
+        None => error!("Last checkpoints: (unavailable)"),
+    }
+}
+
+fn watch(shared: &Shared, crash: &CrashReporter) {
+    let mut state = shared.state.lock().unwrap();
+    while !state.shutdown {
+        let timeout = timeout();
+        let stalled = match state.wait {
+            Some(ref wait) if timeout > 0.0 && !wait.reported => ::time::seconds(wait.started.elapsed()) >= timeout,
+            _ => false,
+        };
+        if stalled {
+            let abort = aborts();
+            if let Some(ref mut wait) = state.wait {
+                report(wait, crash);
+                wait.reported = true;
+                if abort {
+                    let reason = format!("Frame {} stalled: {} for over {} s", wait.frame, wait.what, timeout);
+                    match crash.write(&reason) {
+                        Ok(Some(dir)) => error!("Wrote crash report to {:?}", dir),
+                        Ok(None) => {},
+                        Err(e) => error!("Failed to write crash report: {}", e),
+                    }
+                    error!("Aborting after a stall");
+                    std::process::exit(EXIT_CODE);
+                }
+            }
+        }
+        state = shared.changed.wait_timeout(state, Duration::from_millis(POLL_INTERVAL_MS)).unwrap().0;
+    }
+}
@@ -0,0 +1,97 @@
+//! The window title, kept up to date with the frame rate, the GPU and the selected demo, e.g.
+//! "Smolder — 59.9 fps · AMD Radeon RX 580 · Mesh · paused". Anything else can add to the end of
+//! it through a `StatusText`, from any thread; the title itself is only set from the main thread,
+//! since that's the only one GLFW allows it from.
+use glfw;
+use std::collections::BTreeMap;
+use std::sync::{ Arc, Mutex };
+use std::time::Instant;
+use ::time::{ self, Time };
+
+const SEPARATOR: &'static str = " · ";
+
+/// Seconds between title updates, or 0 to leave the title alone.
+pub fn update_interval() -> f32 {
+    cvar!("window.title_interval", 0.5).get()
+}
+
+/// Status text shown at the end of the title, one piece per key, in key order.
+#[derive(Debug, Clone, Default)]
+pub struct StatusText {
+    pieces: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl StatusText {
+    /// Shows `text` for `key`, replacing what was there.
+    pub fn set<K: Into<String>, T: Into<String>>(&self, key: K, text: T) {
+        self.pieces.lock().unwrap().insert(key.into(), text.into());
+    }
+
+    pub fn clear(&self, key: &str) {
+        self.pieces.lock().unwrap().remove(key);
+    }
+
+    fn describe(&self) -> Option<String> {
+        let pieces = self.pieces.lock().unwrap();
+        if pieces.is_empty() {
+            return None;
+        }
+        Some(pieces.values().cloned().collect::<Vec<String>>().join(SEPARATOR))
+    }
+}
+
+pub struct WindowTitle {
+    base: String,
+    gpu: String,
+    status: StatusText,
+    last_update: Option<Instant>,
+    title: String,
+}
+
+impl WindowTitle {
+    /// `base` is what the window was created with.
+    pub fn new<B: Into<String>, G: Into<String>>(base: B, gpu: G) -> WindowTitle {
+        let base = base.into();
+        WindowTitle {
+            title: base.clone(),
+            base: base,
+            gpu: gpu.into(),
+            status: StatusText::default(),
+            last_update: None,
+        }
+    }
+
+    /// A handle for adding to the end of the title.
+    pub fn status(&self) -> StatusText {
+        self.status.clone()
+    }
+
+    /// The title as of `time`, with `demo` selected.
+    pub fn describe(&self, time: &Time, demo: Option<&str>) -> String {
+        let mut ret = format!("{} — {:.1} fps{}{}", self.base, time.fps(), SEPARATOR, self.gpu);
+        if let Some(demo) = demo {
+            ret.push_str(SEPARATOR);
+            ret.push_str(demo);
+        }
+        if let Some(status) = self.status.describe() {
+            ret.push_str(SEPARATOR);
+            ret.push_str(&status);
+        }
+        ret
+    }
+
+    /// Sets the title on `window` if it's been long enough since the last time. Must be called
+    /// from the main thread.
+    pub fn update(&mut self, window: &mut glfw::Window, time: &Time, demo: Option<&str>) {
+        let interval = update_interval();
+        if interval <= 0.0 || self.last_update.map(|last| time::seconds(last.elapsed()) < interval).unwrap_or(false) {
+            return;
+        }
+        self.last_update = Some(Instant::now());
+        let title = self.describe(time, demo);
+        if title != self.title {
+            window.set_title(&title);
+            self.title = title;
+        }
+    }
+}